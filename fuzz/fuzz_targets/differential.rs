@@ -0,0 +1,124 @@
+//! Decodes the same input with our custom decoders and with the upstream `image` decoders they were copied from,
+//! and panics on any divergence in success/failure or in the decoded pixels, other than the ones our decoders document:
+//! palettes shorter than their indices reach, lossless and arithmetic coded JPEGs, CMYK and YCCK conversion, and
+//! truncated or resynced JPEG scans.
+#![no_main]
+
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageDecoder, Limits};
+use libfuzzer_sys::fuzz_target;
+
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const JPEG_SOI: &[u8] = b"\xFF\xD8";
+
+// Keep allocations bounded so the fuzzer explores decoders rather than the allocator.
+const MAX_DIMENSION: u32 = 4096;
+const MAX_ALLOC: u64 = 64 * 1024 * 1024;
+
+
+/// How far our decoder may knowingly diverge from upstream on an input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Allowed {
+	Nothing,
+	/// The same outcome, dimensions and color type, with different pixels
+	Pixels,
+	/// Any outcome, e.g. decoding what upstream rejects
+	Anything,
+}
+
+
+fuzz_target!(|data: &[u8]| {
+	if data.starts_with(PNG_SIGNATURE) {
+		let ours = imgest::PngDecoder::with_limits(Cursor::new(data), limits()).ok();
+		// Indices past the end of the palette are opaque black, as in libpng, and tRNS entries past it are ignored
+		let allowed = match &ours {
+			Some(decoder) if short_palette(data, decoder.palette(), decoder.transparency()) => Allowed::Anything,
+			_ => Allowed::Nothing,
+		};
+		let upstream = image::codecs::png::PngDecoder::with_limits(Cursor::new(data), limits()).ok().and_then(decode);
+		compare("PNG", ours.and_then(decode), upstream, allowed);
+	} else if data.starts_with(JPEG_SOI) {
+		let ours = imgest::JpegDecoder::new(Cursor::new(data)).ok();
+		let allowed = match (jpeg_frame(data), &ours) {
+			// Lossless and arithmetic coding, which upstream doesn't decode (SOF11 is rejected by both)
+			(Some((0xC3 | 0xC9..=0xCB, _)), _) => Allowed::Anything,
+			// Adobe inverted CMYK and YCCK are converted differently
+			(Some((_, 4)), _) => Allowed::Pixels,
+			// A progressive scan cut short is dropped, and damaged restart intervals are replaced
+			(_, Some(decoder)) if decoder.is_truncated() || decoder.repaired_intervals() > 0 => Allowed::Pixels,
+			_ => Allowed::Nothing,
+		};
+		let upstream = image::codecs::jpeg::JpegDecoder::new(Cursor::new(data)).ok().and_then(decode);
+		compare("JPEG", ours.and_then(decode), upstream, allowed);
+	}
+});
+
+
+fn limits() -> Limits {
+	let mut limits = Limits::no_limits();
+	limits.max_image_width = Some(MAX_DIMENSION);
+	limits.max_image_height = Some(MAX_DIMENSION);
+	limits.max_alloc = Some(MAX_ALLOC);
+	limits
+}
+
+
+/// Decodes to a `DynamicImage`, or `None` on failure.
+///
+/// `from_decoder` allocates the output buffer without consulting `max_alloc`, so check it here first.
+fn decode(mut decoder: impl ImageDecoder) -> Option<DynamicImage> {
+	decoder.set_limits(limits()).ok()?;
+	if decoder.total_bytes() > MAX_ALLOC {
+		return None;
+	}
+	DynamicImage::from_decoder(decoder).ok()
+}
+
+
+/// Whether a palette PNG has fewer palette entries than its bit depth can index, or more tRNS entries than palette
+/// entries.
+fn short_palette(data: &[u8], palette: Option<&[u8]>, trns: Option<&[u8]>) -> bool {
+	// IHDR's bit depth and color type, which is 3 for palette images
+	let (Some(&bit_depth), Some(3)) = (data.get(24), data.get(25)) else {
+		return false;
+	};
+	let entries = palette.map_or(0, |palette| palette.len() / 3);
+	entries < 1 << bit_depth.min(8) || trns.is_some_and(|trns| trns.len() > entries)
+}
+
+
+/// The first frame header's marker and component count.
+fn jpeg_frame(data: &[u8]) -> Option<(u8, u8)> {
+	let mut pos = 2;
+	loop {
+		let &[0xFF, marker, length_high, length_low] = data.get(pos..pos + 4)? else {
+			return None;
+		};
+		if matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+			return Some((marker, *data.get(pos + 9)?));
+		}
+		pos += 2 + usize::from(u16::from_be_bytes([length_high, length_low]));
+	}
+}
+
+
+fn compare(format: &str, ours: Option<DynamicImage>, upstream: Option<DynamicImage>, allowed: Allowed) {
+	if allowed == Allowed::Anything {
+		return;
+	}
+	match (ours, upstream) {
+		(None, None) => (),
+		(Some(_), None) => panic!("{format}: our decoder succeeded where upstream failed"),
+		(None, Some(_)) => panic!("{format}: our decoder failed where upstream succeeded"),
+		(Some(ours), Some(upstream)) => {
+			assert_eq!(
+				(ours.width(), ours.height(), ours.color()),
+				(upstream.width(), upstream.height(), upstream.color()),
+				"{format}: dimension or color type mismatch"
+			);
+			assert!(allowed == Allowed::Pixels || ours.as_bytes() == upstream.as_bytes(), "{format}: pixel data mismatch");
+		},
+	}
+}
@@ -0,0 +1,22 @@
+//! Feeds arbitrary bytes through the EXIF parser and every value accessor; none of it may panic.
+#![no_main]
+
+use imgest::exif::{DateTime, Exif, parse_ascii};
+use libfuzzer_sys::fuzz_target;
+
+
+fuzz_target!(|data: &[u8]| {
+	let _ = DateTime::parse(data);
+	let _ = parse_ascii(data);
+
+	let Some(exif) = Exif::parse(data) else {
+		return;
+	};
+	for entry in &exif.entries {
+		let value = &entry.value;
+		let _ = (value.as_u32(), value.as_ascii(), value.as_datetime(), value.as_rationals());
+		if let Some(f) = value.as_f64() {
+			assert!(f.is_finite());
+		}
+	}
+});
@@ -0,0 +1,143 @@
+//! Measured per-decode resource usage, for capacity planning from real numbers rather than width × height math.
+//!
+//! Bytes read are always counted. Allocation tracking needs `TrackingAllocator` installed as the global allocator,
+//! since a library can't hook allocations otherwise:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOC: imgest::accounting::TrackingAllocator = imgest::accounting::TrackingAllocator;
+//! ```
+//!
+//! Allocations are tracked per thread, so only those made on the decoding thread through Rust's allocator are seen;
+//! memory allocated inside C libraries (dav1d, libheif) or on worker threads is not.
+
+use std::{
+	alloc::{GlobalAlloc, Layout, System},
+	cell::Cell,
+	io::{self, BufRead, Read, Seek, SeekFrom},
+	sync::atomic::{AtomicBool, Ordering},
+};
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceReport {
+	/// Bytes consumed from the reader, including anything read twice after seeking back
+	pub bytes_read: u64,
+	/// Peak bytes allocated during the decode above what was allocated when it started, including the returned
+	/// image. `None` unless `TrackingAllocator` is the global allocator.
+	pub peak_bytes_allocated: Option<u64>,
+	/// Pixels of an EXR or Radiance HDR image that had NaN or infinite samples, whatever `HdrOptions::non_finite`
+	/// then did with them.
+	pub non_finite_pixels: u64,
+}
+
+
+/// Global allocator wrapping `System` that keeps per-thread allocation counters for `ResourceReport`.
+pub struct TrackingAllocator;
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+	static CURRENT: Cell<i64> = const { Cell::new(0) };
+	static PEAK: Cell<i64> = const { Cell::new(0) };
+}
+
+
+fn track(delta: i64) {
+	// `try_with` because the thread locals may already be gone while a thread is being torn down
+	let _ = CURRENT.try_with(|current| {
+		let now = current.get() + delta;
+		current.set(now);
+		let _ = PEAK.try_with(|peak| peak.set(peak.get().max(now)));
+	});
+}
+
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		INSTALLED.store(true, Ordering::Relaxed);
+		let ptr = unsafe { System.alloc(layout) };
+		if !ptr.is_null() {
+			track(layout.size() as i64);
+		}
+		ptr
+	}
+
+	unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+		INSTALLED.store(true, Ordering::Relaxed);
+		let ptr = unsafe { System.alloc_zeroed(layout) };
+		if !ptr.is_null() {
+			track(layout.size() as i64);
+		}
+		ptr
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		unsafe { System.dealloc(ptr, layout) };
+		track(-(layout.size() as i64));
+	}
+
+	unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+		let new_ptr = unsafe { System.realloc(ptr, layout, new_size) };
+		if !new_ptr.is_null() {
+			track(new_size as i64 - layout.size() as i64);
+		}
+		new_ptr
+	}
+}
+
+
+/// Tracks the allocation peak on this thread from creation until `finish`.
+pub(crate) struct PeakTracker {
+	start: i64,
+}
+
+impl PeakTracker {
+	pub(crate) fn start() -> Self {
+		let start = CURRENT.with(Cell::get);
+		PEAK.with(|peak| peak.set(start));
+		PeakTracker { start }
+	}
+
+	pub(crate) fn finish(self) -> Option<u64> {
+		INSTALLED.load(Ordering::Relaxed).then(|| PEAK.with(Cell::get).saturating_sub(self.start).max(0) as u64)
+	}
+}
+
+
+/// Reader wrapper counting the bytes that pass through it.
+pub(crate) struct CountingReader<R> {
+	inner: R,
+	pub(crate) count: u64,
+}
+
+impl<R> CountingReader<R> {
+	pub(crate) fn new(inner: R) -> Self {
+		CountingReader { inner, count: 0 }
+	}
+}
+
+impl<R: Read> Read for CountingReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let n = self.inner.read(buf)?;
+		self.count += n as u64;
+		Ok(n)
+	}
+}
+
+impl<R: BufRead> BufRead for CountingReader<R> {
+	fn fill_buf(&mut self) -> io::Result<&[u8]> {
+		self.inner.fill_buf()
+	}
+
+	fn consume(&mut self, amt: usize) {
+		self.count += amt as u64;
+		self.inner.consume(amt);
+	}
+}
+
+impl<R: Seek> Seek for CountingReader<R> {
+	fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+		self.inner.seek(pos)
+	}
+}
@@ -0,0 +1,45 @@
+//! Decoding into buffers the caller allocates, for pipelines that hand decoded images to hardware: CUDA pinned
+//! (page-locked) host memory, for one, can be copied to the GPU by DMA straight away, where pageable memory first goes
+//! through a staging buffer. `load_raw_from_reader_with_allocator` decodes into a buffer from an `OutputAllocator`.
+//!
+//! ```no_run
+//! use imgest::{Error, LoadOptions, allocator::OutputAllocator};
+//!
+//! struct Pinned;
+//!
+//! impl OutputAllocator for Pinned {
+//!     type Buffer = Vec<u8>; // A pinned buffer type from the CUDA bindings in use
+//!
+//!     fn allocate(&self, len: usize) -> Result<Vec<u8>, Error> {
+//!         Ok(vec![0; len])
+//!     }
+//! }
+//!
+//! let file = std::io::BufReader::new(std::fs::File::open("photo.jpg").unwrap());
+//! let (_, raw) = imgest::load_raw_from_reader_with_allocator(file, &LoadOptions::new(), &Pinned).unwrap();
+//! ```
+
+use crate::Error;
+
+
+/// Allocates the buffers images are decoded into.
+pub trait OutputAllocator {
+	type Buffer: AsMut<[u8]>;
+
+	/// A buffer of exactly `len` bytes, whose contents are overwritten. Called once per image, after the size limits
+	/// have been checked.
+	fn allocate(&self, len: usize) -> Result<Self::Buffer, Error>;
+}
+
+
+/// Allocates `Vec`s, as `load_raw` does.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VecAllocator;
+
+impl OutputAllocator for VecAllocator {
+	type Buffer = Vec<u8>;
+
+	fn allocate(&self, len: usize) -> Result<Vec<u8>, Error> {
+		Ok(vec![0; len])
+	}
+}
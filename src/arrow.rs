@@ -0,0 +1,286 @@
+//! Arrow columns for decoded images, with the `arrow` feature. `ImageBatchBuilder` converts each image to a tensor of
+//! one fixed shape (see `convert::to_u8` and `to_f32`) and appends it to contiguous buffers, which `finish` hands over
+//! as the columns of a `RecordBatch` without copying, ready for a Parquet writer or an Arrow Flight stream.
+//!
+//! The batch has four columns:
+//! - `key` (`Utf8`): whatever the caller identifies images by, such as their paths
+//! - `pixels`: the tensor, as `FixedSizeBinary` for `TensorDType::U8`, or for floats a `FixedSizeList` tagged as
+//!   Arrow's `arrow.fixed_shape_tensor` extension type with the shape in `layout` order; null for failed images
+//! - `metadata` (`Struct`): the format, decoded color type, orientation and metadata blocks of `DecodedImage`; null for
+//!   failed images
+//! - `error` (`Utf8`): why the image failed, null for the others
+//!
+//! ```no_run
+//! use imgest::{LoadOptions, OutputColor, arrow::ImageBatchBuilder, convert::TensorLayout};
+//!
+//! let options = LoadOptions::new().output(OutputColor::Rgb8);
+//! let mut builder = ImageBatchBuilder::new(224, 224, 3).layout(TensorLayout::Chw);
+//! for path in ["a.jpg", "b.png"] {
+//!     match imgest::load_image_with_metadata(path, &options) {
+//!         Ok(decoded) => builder.append(path, &decoded).unwrap(),
+//!         Err(e) => builder.append_error(path, &e),
+//!     }
+//! }
+//! let batch = builder.finish().unwrap();
+//! ```
+
+use std::{collections::HashMap, sync::Arc};
+
+use arrow_array::{ArrayRef, BinaryArray, FixedSizeBinaryArray, FixedSizeListArray, Float32Array, RecordBatch, StringArray, StructArray, UInt8Array};
+use arrow_buffer::{Buffer, NullBuffer, ScalarBuffer};
+use arrow_schema::{ArrowError, DataType, Field, FieldRef, Fields, Schema, SchemaRef};
+use image::error::{ParameterError, ParameterErrorKind};
+
+use crate::{
+	DecodedImage, Error,
+	convert::{self, TensorDType, TensorLayout},
+};
+
+
+/// Samples of every row appended so far, failed rows zero filled.
+#[derive(Debug)]
+enum Samples {
+	U8(Vec<u8>),
+	F32(Vec<f32>),
+	#[cfg(feature = "f16")]
+	F16(Vec<half::f16>),
+}
+
+impl Samples {
+	fn new(dtype: &TensorDType) -> Samples {
+		match dtype {
+			TensorDType::U8 => Samples::U8(Vec::new()),
+			TensorDType::F32(_) => Samples::F32(Vec::new()),
+			#[cfg(feature = "f16")]
+			TensorDType::F16 { .. } => Samples::F16(Vec::new()),
+		}
+	}
+}
+
+
+/// The `metadata` column's fields for one image.
+#[derive(Debug)]
+struct RowMetadata {
+	format: String,
+	color_type: String,
+	orientation: u8,
+	icc_profile: Option<Vec<u8>>,
+	exif: Option<Vec<u8>>,
+	xmp: Option<Vec<u8>>,
+	iptc: Option<Vec<u8>>,
+}
+
+
+/// Accumulates images into Arrow columns. See the module documentation.
+#[derive(Debug)]
+pub struct ImageBatchBuilder {
+	width: u32,
+	height: u32,
+	channels: u8,
+	layout: TensorLayout,
+	dtype: TensorDType,
+	keys: Vec<String>,
+	samples: Samples,
+	metadata: Vec<Option<RowMetadata>>,
+	errors: Vec<Option<String>>,
+}
+
+impl ImageBatchBuilder {
+	/// Takes images of `width` by `height` with `channels` channels (which `LoadOptions::output` fixes, while sizes
+	/// are up to the caller), as `f32` tensors scaled to 0..1 in HWC layout.
+	pub fn new(width: u32, height: u32, channels: u8) -> ImageBatchBuilder {
+		let dtype = TensorDType::default();
+		ImageBatchBuilder {
+			width,
+			height,
+			channels,
+			layout: TensorLayout::default(),
+			samples: Samples::new(&dtype),
+			dtype,
+			keys: Vec::new(),
+			metadata: Vec::new(),
+			errors: Vec::new(),
+		}
+	}
+
+	pub fn layout(mut self, layout: TensorLayout) -> Self {
+		self.layout = layout;
+		self
+	}
+
+	/// The element type of the `pixels` column.
+	///
+	/// # Panics
+	/// When images are appended, if a normalization has neither one value nor one per channel.
+	pub fn dtype(mut self, dtype: TensorDType) -> Self {
+		self.samples = Samples::new(&dtype);
+		self.dtype = dtype;
+		self
+	}
+
+	/// Number of rows appended since the last `finish`, for flushing batches of a fixed size.
+	pub fn len(&self) -> usize {
+		self.keys.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.keys.is_empty()
+	}
+
+	/// The schema of the batches `finish` returns.
+	pub fn schema(&self) -> SchemaRef {
+		Arc::new(Schema::new(vec![
+			Field::new("key", DataType::Utf8, false),
+			self.pixels_field(),
+			Field::new("metadata", DataType::Struct(metadata_fields()), true),
+			Field::new("error", DataType::Utf8, true),
+		]))
+	}
+
+	/// Appends an image. It's a parameter error (with nothing appended) for its dimensions or channel count to differ
+	/// from the builder's.
+	pub fn append(&mut self, key: impl Into<String>, decoded: &DecodedImage) -> Result<(), Error> {
+		let image = &decoded.image;
+		if (image.width(), image.height(), image.color().channel_count()) != (self.width, self.height, self.channels) {
+			return Err(Error::Parameter(ParameterError::from_kind(ParameterErrorKind::DimensionMismatch)));
+		}
+		match (&mut self.samples, &self.dtype) {
+			(Samples::U8(samples), _) => samples.extend_from_slice(&convert::to_u8(image, self.layout)),
+			(Samples::F32(samples), TensorDType::F32(normalization)) => samples.extend_from_slice(&convert::to_f32(image, self.layout, normalization)),
+			#[cfg(feature = "f16")]
+			(Samples::F16(samples), TensorDType::F16 { normalization, linear }) => {
+				samples.extend_from_slice(&convert::to_f16(image, self.layout, normalization, *linear));
+			},
+			_ => unreachable!("samples are created to match the dtype"),
+		}
+		self.keys.push(key.into());
+		self.metadata.push(Some(RowMetadata {
+			format: crate::versions::format_name(decoded.format),
+			color_type: format!("{:?}", image.color()),
+			orientation: decoded.orientation.to_exif(),
+			icc_profile: decoded.icc_profile.clone(),
+			exif: decoded.exif.clone(),
+			xmp: decoded.xmp.clone(),
+			iptc: decoded.iptc.clone(),
+		}));
+		self.errors.push(None);
+		Ok(())
+	}
+
+	/// Appends a row for an image that failed, keeping the batch aligned with its inputs.
+	pub fn append_error(&mut self, key: impl Into<String>, error: &Error) {
+		let len = self.row_len();
+		match &mut self.samples {
+			Samples::U8(samples) => samples.resize(samples.len() + len, 0),
+			Samples::F32(samples) => samples.resize(samples.len() + len, 0.0),
+			#[cfg(feature = "f16")]
+			Samples::F16(samples) => samples.resize(samples.len() + len, half::f16::ZERO),
+		}
+		self.keys.push(key.into());
+		self.metadata.push(None);
+		self.errors.push(Some(error.to_string()));
+	}
+
+	/// The rows appended since the last `finish` as a batch, leaving the builder empty for the next one.
+	pub fn finish(&mut self) -> Result<RecordBatch, ArrowError> {
+		let schema = self.schema();
+		let keys = std::mem::take(&mut self.keys);
+		let metadata = std::mem::take(&mut self.metadata);
+		let errors = std::mem::take(&mut self.errors);
+		let samples = std::mem::replace(&mut self.samples, Samples::new(&self.dtype));
+
+		let nulls = Some(NullBuffer::from(metadata.iter().map(Option::is_some).collect::<Vec<_>>()));
+		let Ok(row_len) = i32::try_from(self.row_len()) else {
+			return Err(ArrowError::InvalidArgumentError("images are too large for a fixed size column".to_string()));
+		};
+		let pixels: ArrayRef = match samples {
+			Samples::U8(samples) => Arc::new(FixedSizeBinaryArray::try_new(row_len, Buffer::from_vec(samples), nulls.clone())?),
+			Samples::F32(samples) => {
+				let values = Arc::new(Float32Array::new(ScalarBuffer::from(samples), None));
+				Arc::new(FixedSizeListArray::try_new(element_field(DataType::Float32), row_len, values, nulls.clone())?)
+			},
+			#[cfg(feature = "f16")]
+			Samples::F16(samples) => {
+				let values = Arc::new(arrow_array::Float16Array::new(ScalarBuffer::from(samples), None));
+				Arc::new(FixedSizeListArray::try_new(element_field(DataType::Float16), row_len, values, nulls.clone())?)
+			},
+		};
+
+		// Failed rows get placeholder values, which the struct's nulls mask
+		let empty = RowMetadata {
+			format: String::new(),
+			color_type: String::new(),
+			orientation: 1,
+			icc_profile: None,
+			exif: None,
+			xmp: None,
+			iptc: None,
+		};
+		let rows: Vec<&RowMetadata> = metadata.iter().map(|row| row.as_ref().unwrap_or(&empty)).collect();
+		let blob = |field: fn(&RowMetadata) -> &Option<Vec<u8>>| -> ArrayRef { Arc::new(BinaryArray::from_iter(rows.iter().map(|row| field(row).as_deref()))) };
+		let metadata = StructArray::try_new(
+			metadata_fields(),
+			vec![
+				Arc::new(StringArray::from_iter_values(rows.iter().map(|row| &row.format))),
+				Arc::new(StringArray::from_iter_values(rows.iter().map(|row| &row.color_type))),
+				Arc::new(UInt8Array::from_iter_values(rows.iter().map(|row| row.orientation))),
+				blob(|row| &row.icc_profile),
+				blob(|row| &row.exif),
+				blob(|row| &row.xmp),
+				blob(|row| &row.iptc),
+			],
+			nulls,
+		)?;
+
+		let columns: Vec<ArrayRef> = vec![
+			Arc::new(StringArray::from(keys)),
+			pixels,
+			Arc::new(metadata),
+			Arc::new(StringArray::from(errors)),
+		];
+		RecordBatch::try_new(schema, columns)
+	}
+
+	fn row_len(&self) -> usize {
+		self.width as usize * self.height as usize * usize::from(self.channels)
+	}
+
+	fn pixels_field(&self) -> Field {
+		let row_len = i32::try_from(self.row_len()).unwrap_or(i32::MAX);
+		let element = match self.dtype {
+			TensorDType::U8 => return Field::new("pixels", DataType::FixedSizeBinary(row_len), true),
+			TensorDType::F32(_) => DataType::Float32,
+			#[cfg(feature = "f16")]
+			TensorDType::F16 { .. } => DataType::Float16,
+		};
+		let (width, height, channels) = (self.width, self.height, self.channels);
+		let (shape, dim_names) = match self.layout {
+			TensorLayout::Hwc => (format!("[{height},{width},{channels}]"), r#"["H","W","C"]"#),
+			TensorLayout::Chw => (format!("[{channels},{height},{width}]"), r#"["C","H","W"]"#),
+		};
+		let extension = format!(r#"{{"shape":{shape},"dim_names":{dim_names}}}"#);
+		Field::new("pixels", DataType::FixedSizeList(element_field(element), row_len), true).with_metadata(HashMap::from([
+			("ARROW:extension:name".to_string(), "arrow.fixed_shape_tensor".to_string()),
+			("ARROW:extension:metadata".to_string(), extension),
+		]))
+	}
+}
+
+
+fn element_field(data_type: DataType) -> FieldRef {
+	Arc::new(Field::new_list_field(data_type, false))
+}
+
+
+fn metadata_fields() -> Fields {
+	Fields::from(vec![
+		Field::new("format", DataType::Utf8, false),
+		Field::new("color_type", DataType::Utf8, false),
+		// The EXIF orientation value, 1 to 8
+		Field::new("orientation", DataType::UInt8, false),
+		Field::new("icc_profile", DataType::Binary, true),
+		Field::new("exif", DataType::Binary, true),
+		Field::new("xmp", DataType::Binary, true),
+		Field::new("iptc", DataType::Binary, true),
+	])
+}
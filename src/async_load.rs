@@ -0,0 +1,42 @@
+//! Loading from async code on tokio, with the `async` feature: file I/O goes through `tokio::fs`, and decoding runs on
+//! the blocking thread pool through `spawn_blocking`, so it never stalls the runtime's worker threads.
+
+use std::path::Path;
+
+use image::DynamicImage;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::{Error, Format, LoadOptions};
+
+
+/// `load_image`, for async callers.
+pub async fn load_image_async<P: AsRef<Path>>(path: P) -> Result<(Format, DynamicImage), Error> {
+	load_image_async_with_options(path, &LoadOptions::default()).await
+}
+
+
+pub async fn load_image_async_with_options<P: AsRef<Path>>(path: P, options: &LoadOptions) -> Result<(Format, DynamicImage), Error> {
+	let data = tokio::fs::read(path).await?;
+	decode_blocking(data, options).await
+}
+
+
+/// Reads `reader` to the end, then decodes that like `load_image_from_vec_with_options`.
+pub async fn load_image_from_async_reader<R: AsyncRead + Unpin>(mut reader: R, options: &LoadOptions) -> Result<(Format, DynamicImage), Error> {
+	let mut data = Vec::new();
+	reader.read_to_end(&mut data).await?;
+	decode_blocking(data, options).await
+}
+
+
+async fn decode_blocking(data: Vec<u8>, options: &LoadOptions) -> Result<(Format, DynamicImage), Error> {
+	let options = options.clone();
+	match tokio::task::spawn_blocking(move || crate::load_image_from_vec_with_options(data, &options)).await {
+		Ok(result) => result,
+		// A panic in the decoder is the caller's panic, as it would be decoding synchronously
+		Err(e) => match e.try_into_panic() {
+			Ok(panic) => std::panic::resume_unwind(panic),
+			Err(e) => Err(Error::Io(std::io::Error::other(e))),
+		},
+	}
+}
@@ -0,0 +1,118 @@
+//! An append-only JSONL log of what batch ingest decided for each input, for dataset provenance audits.
+//!
+//! Each line is one JSON object:
+//!
+//! ```text
+//! {"timestamp_ms":1718000000123,"input":"a/b.png","decision":"ingested","config":"..."}
+//! {"timestamp_ms":1718000000125,"input":"a/c.png","decision":"duplicate","of":"9f86d08...","config":"..."}
+//! {"timestamp_ms":1718000000130,"input":"a/d.png","decision":"quarantined","reason":"decoding error: ...","config":"..."}
+//! {"timestamp_ms":1718000000131,"input":"a/e.png","decision":"rejected","reason":"image exceeds size limits","config":"..."}
+//! ```
+//!
+//! `timestamp_ms` is milliseconds since the Unix epoch, and `config` identifies the configuration the log was opened
+//! with. Lines are only ever appended, so a log can span many runs.
+
+use std::{
+	fs::{File, OpenOptions},
+	io::{self, BufWriter, Write},
+	path::Path,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{Error, seen::ContentHash};
+
+
+/// What happened to an input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+	Ingested,
+	/// Skipped as a byte-for-byte copy of an earlier input with this content hash.
+	Duplicate { of: ContentHash },
+	/// Failed to decode.
+	Quarantined { reason: String },
+	/// Refused by a size limit (`Error::TooBig` or `Error::Limits`).
+	Rejected { reason: String },
+}
+
+impl Decision {
+	/// The decision for an input that failed with `err`.
+	pub fn from_error(err: &Error) -> Decision {
+		match err {
+			Error::TooBig | Error::Limits(_) => Decision::Rejected { reason: err.to_string() },
+			_ => Decision::Quarantined { reason: err.to_string() },
+		}
+	}
+
+	fn name(&self) -> &'static str {
+		match self {
+			Decision::Ingested => "ingested",
+			Decision::Duplicate { .. } => "duplicate",
+			Decision::Quarantined { .. } => "quarantined",
+			Decision::Rejected { .. } => "rejected",
+		}
+	}
+}
+
+
+pub struct AuditLog<W: Write> {
+	out: W,
+	config: String,
+}
+
+impl AuditLog<BufWriter<File>> {
+	/// Opens the log at `path` for appending, creating it if needed. `config` is recorded on every line.
+	pub fn append<P: AsRef<Path>>(path: P, config: impl Into<String>) -> io::Result<Self> {
+		let file = OpenOptions::new().create(true).append(true).open(path)?;
+		Ok(AuditLog::new(BufWriter::new(file), config))
+	}
+}
+
+impl<W: Write> AuditLog<W> {
+	pub fn new(out: W, config: impl Into<String>) -> Self {
+		AuditLog { out, config: config.into() }
+	}
+
+	pub fn record(&mut self, input: &Path, decision: &Decision) -> io::Result<()> {
+		let timestamp_ms = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis());
+		let mut line = format!(
+			"{{\"timestamp_ms\":{},\"input\":{},\"decision\":\"{}\"",
+			timestamp_ms,
+			json_string(&input.to_string_lossy()),
+			decision.name()
+		);
+		match decision {
+			Decision::Ingested => (),
+			Decision::Duplicate { of } => line.push_str(&format!(",\"of\":\"{of}\"")),
+			Decision::Quarantined { reason } | Decision::Rejected { reason } => line.push_str(&format!(",\"reason\":{}", json_string(reason))),
+		}
+		line.push_str(&format!(",\"config\":{}}}\n", json_string(&self.config)));
+		self.out.write_all(line.as_bytes())
+	}
+
+	pub fn flush(&mut self) -> io::Result<()> {
+		self.out.flush()
+	}
+
+	pub fn into_inner(self) -> W {
+		self.out
+	}
+}
+
+
+pub(crate) fn json_string(s: &str) -> String {
+	let mut out = String::with_capacity(s.len() + 2);
+	out.push('"');
+	for c in s.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\r' => out.push_str("\\r"),
+			'\t' => out.push_str("\\t"),
+			c if u32::from(c) < 0x20 => out.push_str(&format!("\\u{:04x}", u32::from(c))),
+			c => out.push(c),
+		}
+	}
+	out.push('"');
+	out
+}
@@ -0,0 +1,343 @@
+use std::io::{BufRead, Seek};
+
+use dav1d::{
+	PixelLayout, PlanarImageComponent,
+	pixel::{MatrixCoefficients, YUVRange},
+};
+use image::{
+	ColorType, ImageDecoder, ImageError, ImageFormat, ImageResult, Limits,
+	error::DecodingError,
+};
+
+use crate::{
+	convert::{self, Cicp, YCbCrMatrix},
+	error::Error,
+	options::AvifOptions,
+};
+
+
+/// AVIF decoder built directly on mp4parse and dav1d (the same libraries behind `image`'s `avif-native` feature), so
+/// that we control the YCbCr to RGB conversion.
+///
+/// 8-bit images decode to 8-bit color types. 10 and 12-bit images decode to 16-bit color types, with samples scaled
+/// to the full 16-bit range; an alpha plane is scaled to the same depth as the color planes, whatever depth it was
+/// coded at. Subsampled chroma is upsampled by replication, where libavif defaults to bilinear, so expect small
+/// differences along chroma edges compared to Pillow.
+///
+/// The YCbCr matrix and range come from the nclx `colr` property when there is one, which per MIAF overrides the AV1
+/// sequence header, and from the sequence header otherwise.
+pub struct AvifDecoder {
+	picture: dav1d::Picture,
+	alpha: Option<dav1d::Picture>,
+	icc_profile: Option<Vec<u8>>,
+	nclx: Option<Cicp>,
+	limits: Limits,
+}
+
+
+impl AvifDecoder {
+	pub fn new<R: BufRead + Seek>(r: R) -> Result<AvifDecoder, Error> {
+		Self::with_limits(r, Limits::no_limits())
+	}
+
+	pub fn with_limits<R: BufRead + Seek>(r: R, limits: Limits) -> Result<AvifDecoder, Error> {
+		Self::with_options(r, &AvifOptions::default(), limits)
+	}
+
+	pub fn with_options<R: BufRead + Seek>(mut r: R, options: &AvifOptions, limits: Limits) -> Result<AvifDecoder, Error> {
+		let mut input = Vec::new();
+		r.read_to_end(&mut input)?;
+		let ctx = mp4parse::read_avif(&mut input.as_slice(), mp4parse::ParseStrictness::Normal).map_err(error_from_avif)?;
+		let Some(coded) = ctx.primary_item_coded_data() else {
+			return Err(Error::Decoding(DecodingError::new(ImageFormat::Avif.into(), "missing primary image item")));
+		};
+		let picture = decode_av1(coded, options)?;
+		let alpha = ctx.alpha_item_coded_data().map(|coded| decode_av1(coded, options)).transpose()?;
+		if let Some(alpha) = &alpha
+			&& (alpha.width(), alpha.height()) != (picture.width(), picture.height())
+		{
+			return Err(Error::Decoding(DecodingError::new(ImageFormat::Avif.into(), "alpha plane size doesn't match the image")));
+		}
+		let icc_profile = match ctx.icc_colour_information() {
+			Some(icc) => Some(icc.map_err(error_from_avif)?.to_vec()),
+			None => None,
+		};
+
+		let mut decoder = AvifDecoder {
+			picture,
+			alpha,
+			icc_profile,
+			nclx: find_nclx(&input),
+			limits: Limits::no_limits(),
+		};
+		decoder.set_limits(limits)?;
+		Ok(decoder)
+	}
+
+	/// Bit depth the color planes were coded at: 8, 10 or 12.
+	pub fn bit_depth(&self) -> u8 {
+		self.picture.bit_depth() as u8
+	}
+
+	/// The color description from the nclx `colr` property, if the file has one.
+	pub fn cicp(&self) -> Option<Cicp> {
+		self.nclx
+	}
+
+	/// The matrix the color planes are converted with, or `None` for identity coded (GBR) images.
+	pub fn ycbcr_matrix(&self) -> Option<YCbCrMatrix> {
+		if let Some(nclx) = self.nclx {
+			return nclx.ycbcr_matrix();
+		}
+		match self.picture.matrix_coefficients() {
+			MatrixCoefficients::Identity => None,
+			MatrixCoefficients::BT709 => Some(YCbCrMatrix::Bt709),
+			MatrixCoefficients::BT2020NonConstantLuminance | MatrixCoefficients::BT2020ConstantLuminance => Some(YCbCrMatrix::Bt2020),
+			_ => Some(YCbCrMatrix::Bt601),
+		}
+	}
+
+	fn is_full_range(&self) -> bool {
+		match self.nclx {
+			Some(nclx) => nclx.full_range,
+			None => self.picture.color_range() == YUVRange::Full,
+		}
+	}
+
+	pub fn has_alpha(&self) -> bool {
+		self.alpha.is_some()
+	}
+
+	fn is_16bit(&self) -> bool {
+		self.picture.bit_depth() > 8 || self.alpha.as_ref().is_some_and(|alpha| alpha.bit_depth() > 8)
+	}
+
+	fn is_monochrome(&self) -> bool {
+		self.picture.pixel_layout() == PixelLayout::I400
+	}
+}
+
+
+impl ImageDecoder for AvifDecoder {
+	fn dimensions(&self) -> (u32, u32) {
+		(self.picture.width(), self.picture.height())
+	}
+
+	fn color_type(&self) -> ColorType {
+		match (self.is_monochrome(), self.has_alpha(), self.is_16bit()) {
+			(true, false, false) => ColorType::L8,
+			(true, false, true) => ColorType::L16,
+			(true, true, false) => ColorType::La8,
+			(true, true, true) => ColorType::La16,
+			(false, false, false) => ColorType::Rgb8,
+			(false, false, true) => ColorType::Rgb16,
+			(false, true, false) => ColorType::Rgba8,
+			(false, true, true) => ColorType::Rgba16,
+		}
+	}
+
+	fn icc_profile(&mut self) -> ImageResult<Option<Vec<u8>>> {
+		Ok(self.icc_profile.clone())
+	}
+
+	fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+		crate::error::check_buffer(buf, self.total_bytes())?;
+
+		let (width, height) = self.dimensions();
+		let monochrome = self.is_monochrome();
+		let is_16bit = self.is_16bit();
+		let matrix = self.ycbcr_matrix();
+		let color = Planes::new(&self.picture, self.is_full_range());
+		let alpha = self.alpha.as_ref().map(|alpha| Planes::new(alpha, alpha.color_range() == YUVRange::Full));
+		let channels = usize::from(self.color_type().channel_count());
+		let bytes_per_sample = if is_16bit { 2 } else { 1 };
+
+		let mut pixels = buf.chunks_exact_mut(channels * bytes_per_sample);
+		for y in 0..height as usize {
+			for x in 0..width as usize {
+				let mut samples = [0.0; 4];
+				let mut n = if monochrome {
+					samples[0] = color.luma(x, y);
+					1
+				} else {
+					let (cb, cr) = (color.chroma(PlanarImageComponent::U, x, y), color.chroma(PlanarImageComponent::V, x, y));
+					let rgb = match matrix {
+						Some(matrix) => convert::ycbcr_to_rgb_f32(color.luma(x, y), cb, cr, matrix),
+						// Identity coding stores G, B, R in the Y, U, V planes, so undo the chroma offset
+						None => [cr + 0.5, color.luma(x, y), cb + 0.5],
+					};
+					samples[..3].copy_from_slice(&rgb);
+					3
+				};
+				if let Some(alpha) = &alpha {
+					samples[n] = alpha.luma(x, y);
+					n += 1;
+				}
+
+				let pixel = pixels.next().expect("buffer size was checked");
+				for (sample, out) in samples[..n].iter().zip(pixel.chunks_exact_mut(bytes_per_sample)) {
+					let sample = sample.clamp(0.0, 1.0);
+					if is_16bit {
+						out.copy_from_slice(&((sample * 65535.0).round() as u16).to_ne_bytes());
+					} else {
+						out[0] = (sample * 255.0).round() as u8;
+					}
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn read_image_boxed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+		(*self).read_image(buf)
+	}
+
+	fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+		limits.check_support(&image::LimitSupport::default())?;
+		let (width, height) = self.dimensions();
+		limits.check_dimensions(width, height)?;
+		self.limits = limits;
+		Ok(())
+	}
+}
+
+
+/// Sample access for one decoded picture, normalizing to Y in 0..1 and chroma in -0.5..0.5.
+struct Planes {
+	y: dav1d::Plane,
+	u: Option<dav1d::Plane>,
+	v: Option<dav1d::Plane>,
+	luma_stride: usize,
+	chroma_stride: usize,
+	high_depth: bool,
+	/// Chroma subsampling shifts (x, y)
+	subsampling: (usize, usize),
+	/// (offset, scale) that map luma and chroma codes to normalized values
+	luma_range: (f32, f32),
+	chroma_range: (f32, f32),
+}
+
+
+impl Planes {
+	fn new(picture: &dav1d::Picture, full_range: bool) -> Planes {
+		let depth = picture.bit_depth() as i32;
+		let layout = picture.pixel_layout();
+		let subsampling = match layout {
+			PixelLayout::I420 => (1, 1),
+			PixelLayout::I422 => (1, 0),
+			PixelLayout::I400 | PixelLayout::I444 => (0, 0),
+		};
+		let max = ((1 << depth) - 1) as f32;
+		let half = (1 << (depth - 1)) as f32;
+		let (luma_range, chroma_range) = if full_range {
+			((0.0, max), (half, max))
+		} else {
+			// Studio swing: 16..235 for luma and 16..240 for chroma at 8 bits, shifted up for higher depths
+			let unit = (1 << (depth - 8)) as f32;
+			((16.0 * unit, 219.0 * unit), (half, 224.0 * unit))
+		};
+		let has_chroma = layout != PixelLayout::I400;
+
+		Planes {
+			y: picture.plane(PlanarImageComponent::Y),
+			u: has_chroma.then(|| picture.plane(PlanarImageComponent::U)),
+			v: has_chroma.then(|| picture.plane(PlanarImageComponent::V)),
+			luma_stride: picture.stride(PlanarImageComponent::Y) as usize,
+			chroma_stride: if has_chroma { picture.stride(PlanarImageComponent::U) as usize } else { 0 },
+			high_depth: depth > 8,
+			subsampling,
+			luma_range,
+			chroma_range,
+		}
+	}
+
+	fn sample(&self, plane: &dav1d::Plane, stride: usize, x: usize, y: usize) -> f32 {
+		let data: &[u8] = plane.as_ref();
+		if self.high_depth {
+			let offset = y * stride + x * 2;
+			f32::from(u16::from_ne_bytes([data[offset], data[offset + 1]]))
+		} else {
+			f32::from(data[y * stride + x])
+		}
+	}
+
+	fn luma(&self, x: usize, y: usize) -> f32 {
+		let (offset, scale) = self.luma_range;
+		(self.sample(&self.y, self.luma_stride, x, y) - offset) / scale
+	}
+
+	fn chroma(&self, component: PlanarImageComponent, x: usize, y: usize) -> f32 {
+		let plane = match component {
+			PlanarImageComponent::U => &self.u,
+			_ => &self.v,
+		};
+		let plane = plane.as_ref().expect("only called for pictures with chroma");
+		let (offset, scale) = self.chroma_range;
+		(self.sample(plane, self.chroma_stride, x >> self.subsampling.0, y >> self.subsampling.1) - offset) / scale
+	}
+}
+
+
+fn decode_av1(coded: &[u8], options: &AvifOptions) -> Result<dav1d::Picture, Error> {
+	let mut settings = dav1d::Settings::new();
+	// 0 is dav1d's default, picking the thread count itself
+	settings.set_n_threads(options.threads.unwrap_or(0));
+	let mut decoder = dav1d::Decoder::with_settings(&settings).map_err(error_from_avif)?;
+	decoder.send_data(coded.to_vec(), None, None, None).map_err(error_from_avif)?;
+	loop {
+		match decoder.get_picture() {
+			Err(dav1d::Error::Again) => match decoder.send_pending_data() {
+				Ok(()) | Err(dav1d::Error::Again) => (),
+				Err(err) => return Err(error_from_avif(err).into()),
+			},
+			result => return result.map_err(|err| error_from_avif(err).into()),
+		}
+	}
+}
+
+
+/// The first nclx `colr` property, found by walking meta/iprp/ipco. mp4parse only hands it out as a raw pointer.
+///
+/// AVIF files have at most one nclx property in practice, shared by the primary item; alpha items don't carry one.
+fn find_nclx(input: &[u8]) -> Option<Cicp> {
+	let meta = child_boxes(input).find(|(kind, _)| kind == b"meta")?.1;
+	// meta is a full box, with a version and flags before its children
+	let iprp = child_boxes(meta.get(4..)?).find(|(kind, _)| kind == b"iprp")?.1;
+	let ipco = child_boxes(iprp).find(|(kind, _)| kind == b"ipco")?.1;
+	child_boxes(ipco).find_map(|(kind, data)| {
+		if &kind != b"colr" || !data.starts_with(b"nclx") || data.len() < 11 {
+			return None;
+		}
+		let code = |offset: usize| u8::try_from(u16::from_be_bytes([data[offset], data[offset + 1]])).unwrap_or(2);
+		Some(Cicp {
+			color_primaries: code(4),
+			transfer_characteristics: code(6),
+			matrix_coefficients: code(8),
+			full_range: data[10] & 0x80 != 0,
+		})
+	})
+}
+
+
+/// Iterates the ISOBMFF boxes directly inside `data` as (type, payload), stopping at the first malformed header.
+fn child_boxes(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+	let mut rest = data;
+	std::iter::from_fn(move || {
+		let size = u32::from_be_bytes(rest.get(0..4)?.try_into().ok()?) as u64;
+		let kind: [u8; 4] = rest.get(4..8)?.try_into().ok()?;
+		let (header, size) = match size {
+			0 => (8, rest.len() as u64),
+			1 => (16, u64::from_be_bytes(rest.get(8..16)?.try_into().ok()?)),
+			size => (8, size),
+		};
+		let size = usize::try_from(size).ok().filter(|&size| size >= header && size <= rest.len())?;
+		let payload = &rest[header..size];
+		rest = &rest[size..];
+		Some((kind, payload))
+	})
+}
+
+
+fn error_from_avif<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> ImageError {
+	ImageError::Decoding(DecodingError::new(ImageFormat::Avif.into(), err))
+}
@@ -0,0 +1,455 @@
+//! Decoding many images on a pool of threads.
+//!
+//! `BatchDecoder` takes any iterator of paths or in-memory files and decodes them with `load_image_with_options` on
+//! its own threads, handing back results as an iterator, either in input order or as each finishes. At most
+//! `max_in_flight` inputs are taken from the iterator and not yet handed back at any time, which bounds memory however
+//! long the input is and however slowly the results are consumed.
+//!
+//! Formats differ a lot in what decoding them costs, so each image also takes a share of a decoding budget `threads`
+//! wide, by format (see `BatchDecoder::format_weight`): a few AVIFs, whose decoder runs threads of its own and needs
+//! far more memory, decode at once, while the other threads carry on with lighter images.
+//!
+//! A batch stops early when its results are dropped, or when its `ShutdownHandle` (see `BatchDecoder::shutdown`) is
+//! requested, after which the results still returned are those of the inputs already taken.
+//!
+//! Latency sensitive work can share a batch's threads through its `PriorityLane` (see `BatchResults::priority_lane`):
+//! a service decoding thumbnails for users alongside a bulk backfill hands each thumbnail to the lane, and it's decoded
+//! on the next free thread, ahead of every backfill input that hasn't started, rather than queueing behind them.
+//!
+//! ```no_run
+//! use imgest::{LoadOptions, batch::BatchDecoder};
+//!
+//! let paths = std::fs::read_dir("images").unwrap().map(|entry| entry.unwrap().path());
+//! for (index, result) in BatchDecoder::new(LoadOptions::new()).threads(8).decode(paths) {
+//!     match result {
+//!         Ok((format, img)) => println!("{index}: {format:?} {}x{}", img.width(), img.height()),
+//!         Err(e) => println!("{index}: {e}"),
+//!     }
+//! }
+//! ```
+
+use std::{
+	collections::{BTreeMap, HashMap, VecDeque},
+	io::Read,
+	path::PathBuf,
+	sync::{Arc, Condvar, Mutex, MutexGuard, mpsc},
+	thread::JoinHandle,
+};
+
+use image::{DynamicImage, ImageFormat};
+
+use crate::{Error, Format, LoadOptions, shutdown::ShutdownHandle};
+
+
+/// An image to decode: a file, or the contents of one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchInput {
+	Path(PathBuf),
+	Bytes(Vec<u8>),
+}
+
+impl From<PathBuf> for BatchInput {
+	fn from(path: PathBuf) -> Self {
+		BatchInput::Path(path)
+	}
+}
+
+impl From<Vec<u8>> for BatchInput {
+	fn from(data: Vec<u8>) -> Self {
+		BatchInput::Bytes(data)
+	}
+}
+
+
+/// The result of decoding one input, with the input's position in the iterator.
+pub type BatchResult = (usize, Result<(Format, DynamicImage), Error>);
+
+
+#[derive(Debug, Clone)]
+pub struct BatchDecoder {
+	options: LoadOptions,
+	threads: usize,
+	max_in_flight: usize,
+	ordered: bool,
+	weights: HashMap<Format, usize>,
+	shutdown: Option<ShutdownHandle>,
+}
+
+impl BatchDecoder {
+	/// Decodes with `options`, on as many threads as `std::thread::available_parallelism` reports, with up to twice
+	/// that many inputs in flight, in input order, with the default format weights.
+	pub fn new(options: LoadOptions) -> BatchDecoder {
+		let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+		BatchDecoder {
+			options,
+			threads,
+			max_in_flight: threads * 2,
+			ordered: true,
+			weights: HashMap::from([
+				(Format::Image(ImageFormat::Avif), 4),
+				(Format::Heif, 4),
+				(Format::Jxl, 2),
+				(Format::Jpeg2000, 2),
+			]),
+			shutdown: None,
+		}
+	}
+
+	/// Number of decoding threads; 0 is treated as 1.
+	pub fn threads(mut self, threads: usize) -> Self {
+		self.threads = threads.max(1);
+		self
+	}
+
+	/// Most inputs taken from the iterator but not yet returned at once, counting those being decoded, those decoded
+	/// and waiting to be returned (in order, behind a slower one) and those waiting to be consumed. Set it to at least
+	/// `threads`, or some threads sit idle; 0 is treated as 1.
+	pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+		self.max_in_flight = max_in_flight.max(1);
+		self
+	}
+
+	/// Return results in input order (the default), or as soon as each is decoded.
+	pub fn ordered(mut self, ordered: bool) -> Self {
+		self.ordered = ordered;
+		self
+	}
+
+	/// How much of the decoding budget, which is `threads` wide, an image of `format` takes while it's decoded. By
+	/// default AVIF and HEIF weigh 4, JPEG XL and JPEG 2000 2, and everything else 1; raise PNG's if the corpus has
+	/// many large ones. Images start decoding in input order, each once its weight is free, so a heavy one waits for
+	/// lighter ones to finish rather than being passed over. Weights are capped at `threads`; 0 is treated as 1.
+	pub fn format_weight(mut self, format: Format, weight: usize) -> Self {
+		self.weights.insert(format, weight.max(1));
+		self
+	}
+
+	/// Stops batches once `handle` is requested: no more inputs are taken, and the iterator ends after returning the
+	/// results of those already taken. `ShutdownHandle::cancel` also cancels the decodes in progress, alongside any
+	/// `LoadOptions::cancel_token`, so they fail with `Error::Cancelled` rather than being finished. Pass
+	/// `ShutdownHandle::on_signals()` to stop on SIGTERM and SIGINT.
+	pub fn shutdown(mut self, handle: ShutdownHandle) -> Self {
+		self.shutdown = Some(handle);
+		self
+	}
+
+	/// Starts decoding `inputs` in the background. Inputs are taken from the iterator on the decoding threads, so a
+	/// slow iterator (e.g. walking a directory tree) doesn't hold up the caller.
+	pub fn decode<I>(&self, inputs: I) -> BatchResults
+	where
+		I: IntoIterator,
+		I::Item: Into<BatchInput> + 'static,
+		I::IntoIter: Send + 'static,
+	{
+		let mut options = self.options.clone();
+		if let Some(handle) = &self.shutdown {
+			let token = handle.cancel_token();
+			options.cancel_token = Some(options.cancel_token.map_or_else(|| token.clone(), |own| own.or(token)));
+		}
+		let shared = Arc::new(Shared {
+			inputs: Mutex::new(Box::new(inputs.into_iter().map(Into::<BatchInput>::into).enumerate())),
+			state: Mutex::new(State {
+				in_flight: 0,
+				stopped: false,
+				next_start: 0,
+				weight_in_use: 0,
+				workers: self.threads,
+				priority: VecDeque::new(),
+			}),
+			returned: Condvar::new(),
+			budget_freed: Condvar::new(),
+			max_in_flight: self.max_in_flight,
+			budget: self.threads,
+			weights: self.weights.clone(),
+			options,
+			shutdown: self.shutdown.clone(),
+		});
+		let (sender, receiver) = mpsc::channel();
+		let workers = (0..self.threads)
+			.map(|_| {
+				let (shared, sender) = (Arc::clone(&shared), sender.clone());
+				std::thread::spawn(move || shared.work(&sender))
+			})
+			.collect();
+
+		BatchResults {
+			shared,
+			receiver,
+			workers,
+			ordered: self.ordered,
+			next: 0,
+			pending: BTreeMap::new(),
+		}
+	}
+}
+
+
+struct Shared {
+	inputs: Mutex<Box<dyn Iterator<Item = (usize, BatchInput)> + Send>>,
+	state: Mutex<State>,
+	/// Signalled when a result is returned, the batch is dropped, or a priority input is queued
+	returned: Condvar,
+	/// Signalled when an image starts or finishes decoding, the batch is dropped, or a priority input is queued
+	budget_freed: Condvar,
+	max_in_flight: usize,
+	/// The decoding budget, `threads` wide
+	budget: usize,
+	weights: HashMap<Format, usize>,
+	/// The decoder's options, with the shutdown handle's cancel token
+	options: LoadOptions,
+	shutdown: Option<ShutdownHandle>,
+}
+
+struct State {
+	in_flight: usize,
+	stopped: bool,
+	/// Index of the next input to start decoding
+	next_start: usize,
+	/// Total weight of the images being decoded
+	weight_in_use: usize,
+	/// Workers that haven't exited. Once none are left, the priority lane decodes on the caller's thread.
+	workers: usize,
+	/// Priority lane inputs waiting for a thread, decoded before any input that hasn't started
+	priority: VecDeque<PriorityInput>,
+}
+
+
+struct PriorityInput {
+	input: BatchInput,
+	weight: usize,
+	reply: mpsc::Sender<Result<(Format, DynamicImage), Error>>,
+}
+
+impl Shared {
+	fn work(&self, sender: &mpsc::Sender<BatchResult>) {
+		self.work_inputs(sender);
+		self.leave();
+	}
+
+	fn work_inputs(&self, sender: &mpsc::Sender<BatchResult>) {
+		loop {
+			{
+				let mut state = self.state.lock().unwrap();
+				loop {
+					state = self.serve_priority(state);
+					if state.in_flight < self.max_in_flight || state.stopped || self.shutting_down() {
+						break;
+					}
+					state = self.returned.wait(state).unwrap();
+				}
+				if state.stopped {
+					return;
+				}
+				if self.shutting_down() {
+					drop(state);
+					// The other workers waiting for a slot won't get one from this worker, so wake them to see it too
+					self.returned.notify_all();
+					return;
+				}
+				state.in_flight += 1;
+			}
+
+			let Some((index, input)) = self.inputs.lock().unwrap().next() else {
+				self.release();
+				return;
+			};
+			let weight = self.weight(&input);
+			if !self.start(index, weight) {
+				return;
+			}
+			let result = self.load(input);
+			self.state.lock().unwrap().weight_in_use -= weight;
+			self.budget_freed.notify_all();
+			if sender.send((index, result)).is_err() {
+				return;
+			}
+		}
+	}
+
+	fn load(&self, input: BatchInput) -> Result<(Format, DynamicImage), Error> {
+		match input {
+			BatchInput::Path(path) => crate::load_image_with_options(path, &self.options),
+			BatchInput::Bytes(data) => crate::load_image_from_vec_with_options(data, &self.options),
+		}
+	}
+
+	/// Decodes priority lane inputs while there are any and the first one's weight is free, unlocking `state` for each.
+	fn serve_priority<'a>(&'a self, mut state: MutexGuard<'a, State>) -> MutexGuard<'a, State> {
+		while state.priority.front().is_some_and(|next| state.weight_in_use + next.weight <= self.budget) {
+			let PriorityInput { input, weight, reply } = state.priority.pop_front().unwrap();
+			state.weight_in_use += weight;
+			drop(state);
+			// The caller only goes away by panicking
+			let _ = reply.send(self.load(input));
+			state = self.state.lock().unwrap();
+			state.weight_in_use -= weight;
+			self.budget_freed.notify_all();
+		}
+		state
+	}
+
+	/// Serves the priority lane until it's empty, then exits. Inputs are only queued while a worker is left, so none
+	/// are stranded.
+	fn leave(&self) {
+		let mut state = self.state.lock().unwrap();
+		loop {
+			state = self.serve_priority(state);
+			if state.priority.is_empty() {
+				break;
+			}
+			state = self.budget_freed.wait(state).unwrap();
+		}
+		state.workers -= 1;
+	}
+
+	fn shutting_down(&self) -> bool {
+		self.shutdown.as_ref().is_some_and(ShutdownHandle::is_requested)
+	}
+
+	fn release(&self) {
+		self.state.lock().unwrap().in_flight -= 1;
+		self.returned.notify_one();
+	}
+
+	/// The input's share of the decoding budget, by the format its first bytes look like. Inputs that can't be read
+	/// weigh 1, and fail when they're decoded.
+	fn weight(&self, input: &BatchInput) -> usize {
+		if self.weights.values().all(|&weight| weight.min(self.budget) <= 1) {
+			return 1;
+		}
+		let mut header = [0; 16];
+		let format = match input {
+			BatchInput::Path(path) => {
+				let read = std::fs::File::open(path).and_then(|file| file.take(16).read(&mut header));
+				read.ok().and_then(|len| Format::guess(&header[..len]))
+			},
+			BatchInput::Bytes(data) => Format::guess(&data[..data.len().min(16)]),
+		};
+		format.and_then(|format| self.weights.get(&format)).map_or(1, |&weight| weight.min(self.budget))
+	}
+
+	/// Waits for the inputs before `index` to have started, for the priority lane to be empty and for `weight` of the
+	/// budget to be free, and takes it, serving the priority lane meanwhile. Returns false if the batch was stopped.
+	fn start(&self, index: usize, weight: usize) -> bool {
+		let mut state = self.state.lock().unwrap();
+		loop {
+			state = self.serve_priority(state);
+			let ready = state.next_start == index && state.priority.is_empty() && state.weight_in_use + weight <= self.budget;
+			if ready || state.stopped {
+				break;
+			}
+			state = self.budget_freed.wait(state).unwrap();
+		}
+		if state.stopped {
+			return false;
+		}
+		state.next_start += 1;
+		state.weight_in_use += weight;
+		drop(state);
+		self.budget_freed.notify_all();
+		true
+	}
+}
+
+
+/// The results of `BatchDecoder::decode`. Dropping it stops the batch: inputs being decoded are finished and
+/// discarded, and no more are taken.
+pub struct BatchResults {
+	shared: Arc<Shared>,
+	receiver: mpsc::Receiver<BatchResult>,
+	workers: Vec<JoinHandle<()>>,
+	ordered: bool,
+	/// Index of the next result to return when ordered
+	next: usize,
+	/// Results decoded ahead of `next`
+	pending: BTreeMap<usize, Result<(Format, DynamicImage), Error>>,
+}
+
+impl BatchResults {
+	/// A lane for decoding single inputs on this batch's threads, ahead of the batch's own inputs.
+	pub fn priority_lane(&self) -> PriorityLane {
+		PriorityLane {
+			shared: Arc::clone(&self.shared),
+		}
+	}
+
+	/// Joins the workers once they've all finished, re-raising a panic from any of them.
+	fn join(&mut self) {
+		for worker in self.workers.drain(..) {
+			if let Err(panic) = worker.join() {
+				std::panic::resume_unwind(panic);
+			}
+		}
+	}
+}
+
+impl Iterator for BatchResults {
+	type Item = BatchResult;
+
+	fn next(&mut self) -> Option<BatchResult> {
+		let result = loop {
+			if self.ordered
+				&& let Some(result) = self.pending.remove(&self.next)
+			{
+				self.next += 1;
+				break (self.next - 1, result);
+			}
+			match self.receiver.recv() {
+				Ok(result) if !self.ordered => break result,
+				Ok((index, result)) => {
+					self.pending.insert(index, result);
+				},
+				// Every worker has exited, so everything has been received
+				Err(mpsc::RecvError) => {
+					self.join();
+					return None;
+				},
+			}
+		};
+		self.shared.release();
+		Some(result)
+	}
+}
+
+/// Decodes inputs on a batch's threads ahead of the batch's own, for latency sensitive work sharing a pool with bulk
+/// work. Can be cloned and used from any thread.
+#[derive(Clone)]
+pub struct PriorityLane {
+	shared: Arc<Shared>,
+}
+
+impl PriorityLane {
+	/// Decodes `input` on the next thread of the batch to come free, before any of the batch's inputs that haven't
+	/// started, and waits for the result. Decodes in progress aren't interrupted, and the input still takes its share
+	/// of the decoding budget, but doesn't count against `max_in_flight`. Once the batch is over (every input decoded,
+	/// its results dropped, or shut down) `input` is decoded on the calling thread instead.
+	pub fn decode(&self, input: impl Into<BatchInput>) -> Result<(Format, DynamicImage), Error> {
+		let input = input.into();
+		let weight = self.shared.weight(&input);
+		let (reply, result) = mpsc::channel();
+		{
+			let mut state = self.shared.state.lock().unwrap();
+			if state.workers == 0 || state.stopped || self.shared.shutting_down() {
+				drop(state);
+				return self.shared.load(input);
+			}
+			state.priority.push_back(PriorityInput { input, weight, reply });
+		}
+		// Workers wait on either, for a slot or for their weight to be free
+		self.shared.returned.notify_all();
+		self.shared.budget_freed.notify_all();
+		result.recv().expect("workers serve the priority lane before exiting")
+	}
+}
+
+
+impl Drop for BatchResults {
+	fn drop(&mut self) {
+		self.shared.state.lock().unwrap().stopped = true;
+		self.shared.returned.notify_all();
+		self.shared.budget_freed.notify_all();
+		// Detached rather than joined, so dropping doesn't wait for decodes in progress: each worker finds the receiver
+		// gone when it sends its result, and exits
+		self.workers.clear();
+	}
+}
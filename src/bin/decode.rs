@@ -1,28 +1,32 @@
+use std::path::PathBuf;
+
+
 fn main() {
 	// Usage: decode <path to image file> <path to output raw file>
-	let args: Vec<String> = std::env::args().collect();
+	// Arguments are taken as OsStrings so paths that aren't valid UTF-8 still work.
+	let args: Vec<_> = std::env::args_os().collect();
 	if args.len() != 3 {
-		eprintln!("Usage: {} <input image path> <output raw path>", args[0]);
+		eprintln!("Usage: {} <input image path> <output raw path>", PathBuf::from(&args[0]).display());
 		std::process::exit(1);
 	}
 
-	let input_path = &args[1];
-	let output_path = &args[2];
+	let input_path = PathBuf::from(&args[1]);
+	let output_path = PathBuf::from(&args[2]);
 
-	let (format, img) = match imgest::load_image(input_path) {
+	let (format, img) = match imgest::load_image(&input_path) {
 		Ok(v) => v,
 		Err(e) => {
-			eprintln!("Failed to load image {}: {:?}", input_path, e);
+			eprintln!("Failed to load image {}: {:?}", input_path.display(), e);
 			std::process::exit(1);
 		},
 	};
 
 	let raw_data = img.to_rgba8().into_raw();
-	std::fs::write(output_path, &raw_data).expect("Failed to write output raw file");
+	std::fs::write(&output_path, &raw_data).expect("Failed to write output raw file");
 	println!(
 		"Successfully decoded image {:?} to raw RGBA8 format, wrote {} bytes to {}",
 		format,
 		raw_data.len(),
-		output_path
+		output_path.display()
 	);
 }
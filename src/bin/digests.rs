@@ -0,0 +1,173 @@
+use std::{
+	ffi::OsString,
+	io::{BufWriter, Write},
+	path::{Path, PathBuf},
+	sync::{
+		Mutex,
+		atomic::{AtomicUsize, Ordering},
+	},
+};
+
+use imgest::digest::{ManifestEntry, PixelDigest};
+
+
+fn main() {
+	// Usage: digests record <dir|manifest> [--out FILE] [--jobs N]
+	//        digests check <digest manifest> [--jobs N]
+	// `record` decodes every file under a directory, or every path listed in a manifest (one per line), with default
+	// options and writes the digest of its pixels to a digest manifest (see `imgest::digest`), by default digests.txt.
+	// `check` decodes every file in a digest manifest again and reports those whose pixels changed, including files
+	// that now fail to decode or now succeed, exiting with an error if there are any. Record with the deployed imgest
+	// and check with a new release to see what it changes before rolling it out. With the `office` feature, the images
+	// in docx, pptx, xlsx, EPUB and zip files are digested one by one, under paths like `a.docx!/word/media/image1.png`.
+	let args: Vec<OsString> = std::env::args_os().collect();
+	let usage = || -> ! {
+		let name = Path::new(&args[0]).display();
+		eprintln!("Usage: {name} record <dir|manifest> [--out FILE] [--jobs N]\n       {name} check <digest manifest> [--jobs N]");
+		std::process::exit(1);
+	};
+
+	let mode = args.get(1).and_then(|v| v.to_str()).unwrap_or_else(|| usage()).to_string();
+	let mut input = None;
+	let mut out_path = PathBuf::from("digests.txt");
+	let mut jobs = std::thread::available_parallelism().map_or(1, |n| n.get());
+	let mut iter = args.iter().skip(2);
+	while let Some(arg) = iter.next() {
+		match arg.to_str() {
+			Some("--out") if mode == "record" => out_path = iter.next().map(PathBuf::from).unwrap_or_else(|| usage()),
+			Some("--jobs") => jobs = iter.next().and_then(|v| v.to_str()?.parse().ok()).filter(|&n| n > 0).unwrap_or_else(|| usage()),
+			_ if input.is_none() => input = Some(PathBuf::from(arg)),
+			_ => usage(),
+		}
+	}
+	let Some(input) = input else { usage() };
+
+	match mode.as_str() {
+		"record" => record(&input, &out_path, jobs),
+		"check" => check(&input, jobs),
+		_ => usage(),
+	}
+}
+
+
+fn record(input: &Path, out_path: &Path, jobs: usize) {
+	let paths = match read_inputs(input) {
+		Ok(paths) => paths,
+		Err(e) => {
+			eprintln!("Failed to read {}: {}", input.display(), e);
+			std::process::exit(1);
+		},
+	};
+	let digests = digest_all(&paths, jobs);
+
+	let file = match std::fs::File::create(out_path) {
+		Ok(file) => file,
+		Err(e) => {
+			eprintln!("Failed to create {}: {}", out_path.display(), e);
+			std::process::exit(1);
+		},
+	};
+	let mut out = BufWriter::new(file);
+	let failures = digests.iter().filter(|digest| digest.is_err()).count();
+	let count = paths.len();
+	for (path, digest) in paths.into_iter().zip(digests) {
+		let entry = ManifestEntry { digest: digest.ok(), path };
+		writeln!(out, "{entry}").expect("Failed to write output");
+	}
+	out.flush().expect("Failed to write output");
+	println!("Recorded digests for {} files ({} failed to decode) to {}", count, failures, out_path.display());
+}
+
+
+fn check(input: &Path, jobs: usize) {
+	let manifest = match std::fs::read_to_string(input) {
+		Ok(manifest) => manifest,
+		Err(e) => {
+			eprintln!("Failed to read {}: {}", input.display(), e);
+			std::process::exit(1);
+		},
+	};
+	let mut entries = Vec::new();
+	for (n, line) in manifest.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+		match line.parse::<ManifestEntry>() {
+			Ok(entry) => entries.push(entry),
+			Err(e) => {
+				eprintln!("{}:{}: not a digest manifest entry ({})", input.display(), n + 1, e);
+				std::process::exit(1);
+			},
+		}
+	}
+
+	let paths: Vec<PathBuf> = entries.iter().map(|entry| entry.path.clone()).collect();
+	let digests = digest_all(&paths, jobs);
+	let mut changed = 0;
+	for (entry, digest) in entries.iter().zip(digests) {
+		let message = match (entry.digest, digest) {
+			(Some(expected), Ok(actual)) if expected == actual => continue,
+			(None, Err(_)) => continue,
+			(Some(expected), Ok(actual)) => format!("pixels changed: expected {expected}, got {actual}"),
+			(Some(_), Err(e)) => format!("now fails to decode: {e}"),
+			(None, Ok(actual)) => format!("now decodes, to {actual}"),
+		};
+		println!("{}: {}", entry.path.display(), message);
+		changed += 1;
+	}
+	println!("{} of {} files unchanged", entries.len() - changed, entries.len());
+	if changed > 0 {
+		std::process::exit(1);
+	}
+}
+
+
+/// Decodes and digests every path, in parallel.
+fn digest_all(paths: &[PathBuf], jobs: usize) -> Vec<Result<PixelDigest, imgest::Error>> {
+	let results = Mutex::new((0..paths.len()).map(|_| None).collect::<Vec<_>>());
+	let next = AtomicUsize::new(0);
+	std::thread::scope(|scope| {
+		for _ in 0..jobs.min(paths.len()) {
+			scope.spawn(|| {
+				loop {
+					let index = next.fetch_add(1, Ordering::Relaxed);
+					let Some(path) = paths.get(index) else { break };
+					#[cfg(feature = "office")]
+				let result = imgest::container::read(path).map_err(imgest::Error::from).and_then(imgest::load_image_from_vec);
+				#[cfg(not(feature = "office"))]
+				let result = imgest::load_image(path);
+				let result = result.map(|(_, img)| PixelDigest::of(&img));
+					results.lock().unwrap()[index] = Some(result);
+				}
+			});
+		}
+	});
+	results.into_inner().unwrap().into_iter().map(|result| result.expect("every path is digested")).collect()
+}
+
+
+/// Every file under `input` if it's a directory, otherwise the non-empty lines of `input` as paths. With the `office`
+/// feature, documents and zip archives are replaced by the images in them (see `imgest::container`).
+fn read_inputs(input: &Path) -> std::io::Result<Vec<PathBuf>> {
+	let mut paths = Vec::new();
+	if input.is_dir() {
+		collect_files(input, &mut paths)?;
+		paths.sort();
+	} else {
+		paths = std::fs::read_to_string(input)?.lines().map(str::trim).filter(|line| !line.is_empty()).map(PathBuf::from).collect();
+	}
+	#[cfg(feature = "office")]
+	let paths = imgest::container::expand(paths);
+	Ok(paths)
+}
+
+
+fn collect_files(dir: &Path, out: &mut Vec<PathBuf>) -> std::io::Result<()> {
+	for entry in std::fs::read_dir(dir)? {
+		let entry = entry?;
+		let file_type = entry.file_type()?;
+		if file_type.is_dir() {
+			collect_files(&entry.path(), out)?;
+		} else if file_type.is_file() {
+			out.push(entry.path());
+		}
+	}
+	Ok(())
+}
@@ -0,0 +1,291 @@
+use std::{
+	ffi::OsString,
+	io::{BufWriter, Write},
+	path::{Path, PathBuf},
+	sync::{
+		Mutex,
+		atomic::{AtomicUsize, Ordering},
+	},
+	time::Instant,
+};
+
+use imgest::{
+	LoadOptions, OutputColor,
+	audit::{AuditLog, Decision},
+	open_files::OpenFileLimit,
+	phash,
+	seen::{ContentHash, SeenSet, SortedFileSeenSet},
+	shutdown::{self, ShutdownHandle},
+};
+
+
+fn main() {
+	// Usage: hash <dir|manifest> [--algo phash] [--out FILE] [--jobs N] [--seen FILE] [--max-open-files N] [--audit FILE]
+	// Hashes every file under a directory, or every path listed in a manifest (one per line), in parallel, and writes
+	// `path,phash,error,duplicate` rows as CSV (not Parquet): the input artifact for near-duplicate detection. Paths
+	// are written, and manifests read, as raw bytes, so non-UTF8 paths survive. Files that fail to decode get an empty
+	// hash and the error. With --seen, the SHA-256 of each file is checked against (and added to)
+	// the seen-set stored in FILE, and exact duplicates of a file seen in this or an earlier run are marked with
+	// `duplicate` = 1 and not decoded. Files are read whole before decoding, and at most --max-open-files (by default
+	// derived from RLIMIT_NOFILE) are open at once whatever --jobs is. On SIGTERM or SIGINT no new files are started;
+	// those in progress are finished and the CSV and seen-set are written out as usual, then it exits with status 130.
+	// Otherwise it exits with an error only if nothing could be hashed. With --audit, the decision for every file is
+	// appended to the JSONL audit log in FILE (see `imgest::audit`). With the `office` feature, the images in docx,
+	// pptx, xlsx, EPUB and zip files are hashed one by one, in rows for paths like `a.docx!/word/media/image1.png`.
+	//
+	// `hash --version [--json]` prints what this build decodes and with which backends (see `imgest::capabilities`)
+	// and exits, so workers can be checked for an identical decode stack.
+	let args: Vec<OsString> = std::env::args_os().collect();
+	let usage = || -> ! {
+		eprintln!(
+			"Usage: {} <dir|manifest> [--algo phash] [--out FILE] [--jobs N] [--seen FILE] [--max-open-files N] [--audit FILE]",
+			Path::new(&args[0]).display()
+		);
+		std::process::exit(1);
+	};
+
+	let mut input = None;
+	let mut out_path = PathBuf::from("hashes.csv");
+	let mut seen_path = None;
+	let mut max_open_files = None;
+	let mut audit_path = None;
+	let mut jobs = std::thread::available_parallelism().map_or(1, |n| n.get());
+	let mut version = false;
+	let mut json = false;
+	let mut iter = args.iter().skip(1);
+	while let Some(arg) = iter.next() {
+		match arg.to_str() {
+			Some("--algo") => match iter.next().and_then(|v| v.to_str()) {
+				Some("phash") => (),
+				Some(algo) => {
+					eprintln!("Unknown hash algorithm {algo:?}; only phash is available");
+					std::process::exit(1);
+				},
+				None => usage(),
+			},
+			Some("--out") => out_path = iter.next().map(PathBuf::from).unwrap_or_else(|| usage()),
+			Some("--audit") => audit_path = Some(iter.next().map(PathBuf::from).unwrap_or_else(|| usage())),
+			Some("--seen") => seen_path = Some(iter.next().map(PathBuf::from).unwrap_or_else(|| usage())),
+			Some("--max-open-files") => max_open_files = Some(iter.next().and_then(|v| v.to_str()?.parse().ok()).filter(|&n| n > 0).unwrap_or_else(|| usage())),
+			Some("--version") => version = true,
+			Some("--json") => json = true,
+			Some("--jobs") => jobs = iter.next().and_then(|v| v.to_str()?.parse().ok()).filter(|&n| n > 0).unwrap_or_else(|| usage()),
+			_ if input.is_none() => input = Some(PathBuf::from(arg)),
+			_ => usage(),
+		}
+	}
+	if version {
+		let capabilities = imgest::capabilities();
+		if json {
+			println!("{}", capabilities.to_json());
+		} else {
+			println!("{capabilities}");
+		}
+		return;
+	}
+	let Some(input) = input else { usage() };
+	if out_path.extension().is_some_and(|ext| ext == "parquet") {
+		eprintln!("Parquet output isn't supported, since the parquet crate isn't a dependency; write CSV and convert it downstream");
+		std::process::exit(1);
+	}
+
+	let paths = match read_inputs(&input) {
+		Ok(paths) => paths,
+		Err(e) => {
+			eprintln!("Failed to read {}: {}", input.display(), e);
+			std::process::exit(1);
+		},
+	};
+	if paths.is_empty() {
+		eprintln!("No files found in {}", input.display());
+		std::process::exit(1);
+	}
+
+	let seen = match seen_path.as_deref().map(SortedFileSeenSet::open).transpose() {
+		Ok(seen) => seen.map(Mutex::new),
+		Err(e) => {
+			eprintln!("Failed to open the seen-set: {e}");
+			std::process::exit(1);
+		},
+	};
+
+	// The options `phash::phash_bytes` decodes with
+	let config = format!("phash {}", LoadOptions::new().output(OutputColor::Luma8).config_fingerprint());
+	let audit = match audit_path.as_deref().map(|path| AuditLog::append(path, config)).transpose() {
+		Ok(audit) => audit.map(Mutex::new),
+		Err(e) => {
+			eprintln!("Failed to open the audit log: {e}");
+			std::process::exit(1);
+		},
+	};
+
+	if let Err(e) = shutdown::install_handlers() {
+		eprintln!("Failed to install signal handlers: {e}");
+		std::process::exit(1);
+	}
+	let shutdown = ShutdownHandle::on_signals();
+	let open_files = max_open_files.map_or_else(OpenFileLimit::from_rlimit, OpenFileLimit::new);
+
+	let file = match std::fs::File::create(&out_path) {
+		Ok(file) => file,
+		Err(e) => {
+			eprintln!("Failed to create {}: {}", out_path.display(), e);
+			std::process::exit(1);
+		},
+	};
+	let out = Mutex::new(BufWriter::new(file));
+	writeln!(out.lock().unwrap(), "path,phash,error,duplicate").expect("Failed to write output");
+
+	// Workers pull the next path off a shared counter, so slow files don't hold up a whole chunk
+	let start = Instant::now();
+	let next = AtomicUsize::new(0);
+	let processed = AtomicUsize::new(0);
+	let failures = AtomicUsize::new(0);
+	let duplicates = AtomicUsize::new(0);
+	std::thread::scope(|scope| {
+		for _ in 0..jobs.min(paths.len()) {
+			scope.spawn(|| {
+				let duplicate_of = |data: &[u8]| {
+					let seen = seen.as_ref()?;
+					let hash = ContentHash::of(data);
+					let new = seen.lock().unwrap().insert(hash).expect("Failed to read the seen-set");
+					(!new).then_some(hash)
+				};
+				while !shutdown.is_requested()
+					&& let Some(path) = paths.get(next.fetch_add(1, Ordering::Relaxed))
+				{
+					// Ok(Err(hash)) for a duplicate of the file with that content hash
+					let result = read(&open_files, path).map_err(imgest::Error::from).and_then(|data| match duplicate_of(&data) {
+						Some(of) => Ok(Err(of)),
+						None => phash::phash_bytes(&data).map(Ok),
+					});
+					let (hash, error, duplicate, decision) = match result {
+						Ok(Ok(hash)) => (hash.to_string(), String::new(), "", Decision::Ingested),
+						Ok(Err(of)) => {
+							duplicates.fetch_add(1, Ordering::Relaxed);
+							(String::new(), String::new(), "1", Decision::Duplicate { of })
+						},
+						Err(e) => {
+							failures.fetch_add(1, Ordering::Relaxed);
+							(String::new(), e.to_string(), "", Decision::from_error(&e))
+						},
+					};
+					if let Some(audit) = &audit {
+						audit.lock().unwrap().record(path, &decision).expect("Failed to write the audit log");
+					}
+					// The raw path bytes, so non-UTF8 paths can be read back
+					let mut row = csv_field(path.as_os_str().as_encoded_bytes());
+					row.extend_from_slice(b",");
+					row.extend_from_slice(hash.as_bytes());
+					row.extend_from_slice(b",");
+					row.extend_from_slice(&csv_field(error.as_bytes()));
+					row.extend_from_slice(format!(",{duplicate}\n").as_bytes());
+					out.lock().unwrap().write_all(&row).expect("Failed to write output");
+					processed.fetch_add(1, Ordering::Relaxed);
+				}
+			});
+		}
+	});
+	out.into_inner().unwrap().flush().expect("Failed to write output");
+	if let Some(audit) = audit {
+		audit.into_inner().unwrap().flush().expect("Failed to write the audit log");
+	}
+	if let Some(seen) = seen
+		&& let Err(e) = seen.into_inner().unwrap().flush()
+	{
+		eprintln!("Failed to save the seen-set: {e}");
+		std::process::exit(1);
+	}
+
+	let processed = processed.into_inner();
+	let failures = failures.into_inner();
+	let duplicates = duplicates.into_inner();
+	println!(
+		"Hashed {} of {} files ({} exact duplicates skipped) in {:.1}s, wrote {}",
+		processed - failures - duplicates,
+		paths.len(),
+		duplicates,
+		start.elapsed().as_secs_f32(),
+		out_path.display()
+	);
+	if processed < paths.len() {
+		eprintln!("Stopped by a signal after {processed} files");
+		std::process::exit(130);
+	}
+	if failures > 0 && failures + duplicates == paths.len() {
+		std::process::exit(1);
+	}
+}
+
+
+/// Every file under `input` if it's a directory, otherwise the non-empty lines of `input` as paths. With the `office`
+/// feature, documents and zip archives are replaced by the images in them (see `imgest::container`).
+fn read_inputs(input: &Path) -> std::io::Result<Vec<PathBuf>> {
+	let mut paths = Vec::new();
+	if input.is_dir() {
+		collect_files(input, &mut paths)?;
+		paths.sort();
+	} else {
+		// Read as bytes, since the paths needn't be UTF-8
+		paths = std::fs::read(input)?.split(|&b| b == b'\n').map(<[u8]>::trim_ascii).filter(|line| !line.is_empty()).map(path_from_bytes).collect();
+	}
+	#[cfg(feature = "office")]
+	let paths = imgest::container::expand(paths);
+	Ok(paths)
+}
+
+
+fn collect_files(dir: &Path, out: &mut Vec<PathBuf>) -> std::io::Result<()> {
+	for entry in std::fs::read_dir(dir)? {
+		let entry = entry?;
+		let file_type = entry.file_type()?;
+		if file_type.is_dir() {
+			collect_files(&entry.path(), out)?;
+		} else if file_type.is_file() {
+			out.push(entry.path());
+		}
+	}
+	Ok(())
+}
+
+
+/// Reads a file under a permit, or with the `office` feature an image embedded in a document.
+fn read(open_files: &OpenFileLimit, path: &Path) -> std::io::Result<Vec<u8>> {
+	#[cfg(feature = "office")]
+	{
+		let _permit = open_files.acquire();
+		imgest::container::read(path)
+	}
+	#[cfg(not(feature = "office"))]
+	open_files.read(path)
+}
+
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+	use std::os::unix::ffi::OsStrExt as _;
+	PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+	PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+
+/// Quotes a CSV field if it needs it.
+fn csv_field(value: &[u8]) -> Vec<u8> {
+	if !value.iter().any(|b| matches!(b, b',' | b'"' | b'\n' | b'\r')) {
+		return value.to_vec();
+	}
+	let mut out = vec![b'"'];
+	for &b in value {
+		if b == b'"' {
+			out.push(b'"');
+		}
+		out.push(b);
+	}
+	out.push(b'"');
+	out
+}
@@ -0,0 +1,178 @@
+use std::{
+	ffi::OsString,
+	path::{Path, PathBuf},
+	time::Instant,
+};
+
+
+const DEFAULT_PASSES: usize = 10;
+const DEFAULT_RSS_SLACK_MB: u64 = 16;
+
+
+/// Resource usage after a pass; `None` where the platform gives us no cheap way to measure it.
+struct Sample {
+	rss_bytes: Option<u64>,
+	open_fds: Option<u64>,
+}
+
+
+fn main() {
+	// Usage: soak <corpus dir> [--passes N] [--rss-slack-mb N]
+	// Decodes every file in the corpus repeatedly, sampling resident memory and open file descriptors after each pass.
+	// Exits with an error if either grows on every pass (beyond a small slack for RSS), which indicates a leak.
+	let args: Vec<OsString> = std::env::args_os().collect();
+	let usage = || -> ! {
+		eprintln!("Usage: {} <corpus dir> [--passes N] [--rss-slack-mb N]", Path::new(&args[0]).display());
+		std::process::exit(1);
+	};
+
+	let mut corpus_dir = None;
+	let mut passes = DEFAULT_PASSES;
+	let mut rss_slack_mb = DEFAULT_RSS_SLACK_MB;
+	let mut iter = args.iter().skip(1);
+	while let Some(arg) = iter.next() {
+		match arg.to_str() {
+			Some("--passes") => passes = iter.next().and_then(|v| v.to_str()?.parse().ok()).unwrap_or_else(|| usage()),
+			Some("--rss-slack-mb") => rss_slack_mb = iter.next().and_then(|v| v.to_str()?.parse().ok()).unwrap_or_else(|| usage()),
+			_ if corpus_dir.is_none() => corpus_dir = Some(PathBuf::from(arg)),
+			_ => usage(),
+		}
+	}
+	let Some(corpus_dir) = corpus_dir else { usage() };
+	if passes < 2 {
+		eprintln!("--passes must be at least 2 to detect growth");
+		std::process::exit(1);
+	}
+
+	let mut paths = Vec::new();
+	if let Err(e) = collect_files(&corpus_dir, &mut paths) {
+		eprintln!("Failed to read corpus directory {}: {}", corpus_dir.display(), e);
+		std::process::exit(1);
+	}
+	paths.sort();
+	if paths.is_empty() {
+		eprintln!("No files found in {}", corpus_dir.display());
+		std::process::exit(1);
+	}
+	println!("Soaking {} files for {} passes (plus one warmup pass)", paths.len(), passes);
+
+	// The warmup pass lets the allocator and any lazily initialized state settle before we start measuring
+	run_pass(&paths);
+
+	let mut samples = Vec::with_capacity(passes);
+	for pass in 1..=passes {
+		let start = Instant::now();
+		let failures = run_pass(&paths);
+		let sample = sample();
+		println!(
+			"pass {}/{}: {:.1}s, {} failed decodes, rss={}, open fds={}",
+			pass,
+			passes,
+			start.elapsed().as_secs_f32(),
+			failures,
+			sample.rss_bytes.map_or("n/a".to_string(), |b| format!("{:.1} MB", b as f64 / (1024.0 * 1024.0))),
+			sample.open_fds.map_or("n/a".to_string(), |n| n.to_string())
+		);
+		samples.push(sample);
+	}
+
+	let rss: Option<Vec<u64>> = samples.iter().map(|s| s.rss_bytes).collect();
+	let fds: Option<Vec<u64>> = samples.iter().map(|s| s.open_fds).collect();
+	let mut leaked = false;
+
+	match rss {
+		Some(rss) if monotonic_growth(&rss, rss_slack_mb * 1024 * 1024) => {
+			eprintln!("LEAK: resident memory grew on every pass: {:?}", rss);
+			leaked = true;
+		},
+		Some(_) => (),
+		None => eprintln!("WARNING: resident memory can't be measured on this platform, skipping RSS leak check"),
+	}
+	match fds {
+		Some(fds) if monotonic_growth(&fds, 0) => {
+			eprintln!("LEAK: open file descriptors grew on every pass: {:?}", fds);
+			leaked = true;
+		},
+		Some(_) => (),
+		None => eprintln!("WARNING: open file descriptors can't be counted on this platform, skipping fd leak check"),
+	}
+
+	if leaked {
+		std::process::exit(1);
+	}
+	println!("No monotonic growth detected");
+}
+
+
+fn collect_files(dir: &Path, out: &mut Vec<PathBuf>) -> std::io::Result<()> {
+	for entry in std::fs::read_dir(dir)? {
+		let entry = entry?;
+		let file_type = entry.file_type()?;
+		if file_type.is_dir() {
+			collect_files(&entry.path(), out)?;
+		} else if file_type.is_file() {
+			out.push(entry.path());
+		}
+	}
+	Ok(())
+}
+
+
+/// Decodes every path once, returning the number of failures.
+fn run_pass(paths: &[PathBuf]) -> usize {
+	paths.iter().filter(|path| imgest::load_image(path).is_err()).count()
+}
+
+
+/// True if every sample is larger than the one before it and the total growth exceeds `slack`.
+fn monotonic_growth(samples: &[u64], slack: u64) -> bool {
+	let strictly_increasing = samples.windows(2).all(|w| w[1] > w[0]);
+	let growth = samples.last().unwrap_or(&0).saturating_sub(*samples.first().unwrap_or(&0));
+	strictly_increasing && growth > slack
+}
+
+
+fn sample() -> Sample {
+	Sample {
+		rss_bytes: resident_bytes(),
+		open_fds: open_fds(),
+	}
+}
+
+
+#[cfg(target_os = "linux")]
+fn resident_bytes() -> Option<u64> {
+	let status = std::fs::read_to_string("/proc/self/status").ok()?;
+	status
+		.lines()
+		.find_map(|line| line.strip_prefix("VmRSS:"))
+		.and_then(|rest| rest.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+		.map(|kb| kb * 1024)
+}
+
+#[cfg(target_os = "macos")]
+fn resident_bytes() -> Option<u64> {
+	// No procfs on macOS; ps reports RSS in KiB
+	let output = std::process::Command::new("ps")
+		.args(["-o", "rss=", "-p", &std::process::id().to_string()])
+		.output()
+		.ok()?;
+	String::from_utf8(output.stdout).ok()?.trim().parse::<u64>().ok().map(|kb| kb * 1024)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn resident_bytes() -> Option<u64> {
+	None
+}
+
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn open_fds() -> Option<u64> {
+	let fd_dir = if cfg!(target_os = "linux") { "/proc/self/fd" } else { "/dev/fd" };
+	Some(std::fs::read_dir(fd_dir).ok()?.count() as u64)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn open_fds() -> Option<u64> {
+	None
+}
@@ -0,0 +1,91 @@
+use std::{
+	ffi::OsString,
+	path::{Path, PathBuf},
+};
+
+use imgest::conformance::{self, PillowEnv, Profiles, Tolerances};
+
+
+fn main() {
+	// Usage: verify [--profile NAME] [--profiles FILE] <image path>...
+	// Decodes each image with both imgest and Pillow and checks they agree within the conformance tolerances: those of
+	// the named profile if given (from FILE, or the built-in tolerances.toml), otherwise the built-in profile for the
+	// Pillow build in use. Exits with an error if any image fails.
+	let args: Vec<OsString> = std::env::args_os().collect();
+	let usage = || -> ! {
+		eprintln!("Usage: {} [--profile NAME] [--profiles FILE] <image path>...", Path::new(&args[0]).display());
+		std::process::exit(1);
+	};
+
+	let mut profile = None;
+	let mut profiles_file = None;
+	let mut paths = Vec::new();
+	let mut iter = args.iter().skip(1);
+	while let Some(arg) = iter.next() {
+		match arg.to_str() {
+			Some("--profile") => profile = Some(iter.next().and_then(|v| v.to_str()).unwrap_or_else(|| usage()).to_string()),
+			Some("--profiles") => profiles_file = Some(PathBuf::from(iter.next().unwrap_or_else(|| usage()))),
+			_ => paths.push(PathBuf::from(arg)),
+		}
+	}
+	if paths.is_empty() {
+		usage();
+	}
+
+	conformance::disable_python_sigint_handler();
+
+	let pillow = match PillowEnv::probe() {
+		Ok(pillow) => pillow,
+		Err(e) => {
+			eprintln!("Failed to probe Pillow: {e}");
+			std::process::exit(1);
+		},
+	};
+	if let Err(e) = pillow.check_required() {
+		eprintln!("{e}");
+		std::process::exit(1);
+	}
+	if !pillow.is_tested_version() {
+		eprintln!("Warning: Pillow {} differs from the tested {}; tolerances may be off", pillow.pillow, conformance::TESTED_PILLOW_VERSION);
+	}
+	let tolerances = match profile {
+		Some(name) => {
+			let profiles = match profiles_file {
+				Some(file) => Profiles::load(&file).unwrap_or_else(|e| {
+					eprintln!("{}: {e}", file.display());
+					std::process::exit(1);
+				}),
+				None => Profiles::builtin().clone(),
+			};
+			let Some(tolerances) = profiles.get(&name) else {
+				eprintln!("No tolerance profile named {name:?}; available: {}", profiles.names().collect::<Vec<_>>().join(", "));
+				std::process::exit(1);
+			};
+			tolerances.clone()
+		},
+		None => Tolerances::for_pillow(&pillow),
+	};
+
+	let mut failures = 0;
+	for path in &paths {
+		match conformance::compare(path, &tolerances) {
+			Ok(comparison) => println!(
+				"OK   {} ({:?}, avg_diff={}, differing_pixels={}, histogram=[{}])",
+				path.display(),
+				comparison.format,
+				comparison.stats.mae,
+				comparison.stats.differing_pixels,
+				comparison.stats.histogram_summary(8)
+			),
+			Err(e) => {
+				println!("FAIL {}: {e}", path.display());
+				failures += 1;
+			},
+		}
+	}
+
+	if failures > 0 {
+		eprintln!("{failures} of {} images failed against {}", paths.len(), pillow.summary());
+		std::process::exit(1);
+	}
+}
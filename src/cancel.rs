@@ -0,0 +1,113 @@
+//! Cancelling decodes from another thread, or after a deadline.
+
+use std::{
+	io::{self, BufRead, Read, Seek, SeekFrom},
+	sync::{
+		Arc,
+		atomic::{AtomicBool, Ordering},
+	},
+	time::{Duration, Instant},
+};
+
+
+/// A flag, and optionally a deadline, that a decode checks as it goes; see `LoadOptions::cancel_token`.
+///
+/// The check happens whenever the decoder reads input, which PNG (and most other formats) do throughout decoding,
+/// and between decoding stages. JPEGs are read into memory up front and their entropy decoding is a single call into
+/// zune-jpeg, so a JPEG is only checked before and after that call. A cancelled decode fails with `Error::Cancelled`.
+/// Clones share the flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+	cancelled: Arc<AtomicBool>,
+	deadline: Option<Instant>,
+	/// Other tokens this one is cancelled along with; see `or`
+	linked: Vec<CancelToken>,
+}
+
+impl CancelToken {
+	pub fn new() -> CancelToken {
+		CancelToken::default()
+	}
+
+	/// A token that also cancels itself at `deadline`.
+	pub fn with_deadline(deadline: Instant) -> CancelToken {
+		CancelToken {
+			deadline: Some(deadline),
+			..CancelToken::default()
+		}
+	}
+
+	/// A token that also cancels itself `timeout` from now.
+	pub fn with_timeout(timeout: Duration) -> CancelToken {
+		CancelToken::with_deadline(Instant::now() + timeout)
+	}
+
+	pub fn cancel(&self) {
+		self.cancelled.store(true, Ordering::Relaxed);
+	}
+
+	pub fn is_cancelled(&self) -> bool {
+		self.cancelled.load(Ordering::Relaxed)
+			|| self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+			|| self.linked.iter().any(CancelToken::is_cancelled)
+	}
+
+	/// A token that's cancelled when either this one or `other` is. Cancelling it cancels this one, not `other`.
+	pub(crate) fn or(&self, other: &CancelToken) -> CancelToken {
+		let mut token = self.clone();
+		token.linked.push(other.clone());
+		token
+	}
+}
+
+impl PartialEq for CancelToken {
+	fn eq(&self, other: &Self) -> bool {
+		Arc::ptr_eq(&self.cancelled, &other.cancelled) && self.deadline == other.deadline && self.linked == other.linked
+	}
+}
+
+impl Eq for CancelToken {}
+
+
+/// Reader wrapper failing reads once `token` is cancelled.
+pub(crate) struct CancelReader<'a, R> {
+	inner: R,
+	token: Option<&'a CancelToken>,
+}
+
+impl<'a, R> CancelReader<'a, R> {
+	pub(crate) fn new(inner: R, token: Option<&'a CancelToken>) -> Self {
+		CancelReader { inner, token }
+	}
+
+	fn check(&self) -> io::Result<()> {
+		match self.token {
+			Some(token) if token.is_cancelled() => Err(io::Error::other("cancelled")),
+			_ => Ok(()),
+		}
+	}
+}
+
+impl<R: Read> Read for CancelReader<'_, R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		self.check()?;
+		self.inner.read(buf)
+	}
+}
+
+impl<R: BufRead> BufRead for CancelReader<'_, R> {
+	fn fill_buf(&mut self) -> io::Result<&[u8]> {
+		self.check()?;
+		self.inner.fill_buf()
+	}
+
+	fn consume(&mut self, amt: usize) {
+		self.inner.consume(amt);
+	}
+}
+
+impl<R: Seek> Seek for CancelReader<'_, R> {
+	fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+		self.inner.seek(pos)
+	}
+}
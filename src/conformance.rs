@@ -0,0 +1,543 @@
+//! Conformance checking against Pillow, the reference this crate is tested for parity with.
+//!
+//! Both decoders' output is converted to 8-bit RGBA and compared per sample, within limits from a tolerance profile.
+//! Lossless formats must generally match exactly, JPEGs get a tolerance since IDCT and upsampling implementations
+//! legitimately differ, and edge pixels get a wider one because chroma upsampling at the image border is where
+//! decoders diverge most. Profiles live in TOML so they can be tuned without recompiling. Pillow is driven in-process through pyo3, so the Python environment
+//! (a virtual env with Pillow installed) must be active.
+//!
+//! Only compiled with the `conformance` feature.
+
+use std::{collections::BTreeMap, path::Path, sync::LazyLock};
+
+use pyo3::{
+	Bound, Py, PyAny, PyErr, PyResult, Python,
+	sync::PyOnceLock,
+	types::{PyAnyMethods as _, PyBytes, PyBytesMethods as _, PyModule},
+};
+use serde::Deserialize;
+
+use crate::{Error, Format};
+
+
+static PIL_IMAGE_MODULE: PyOnceLock<Py<PyModule>> = PyOnceLock::new();
+// Test sets include images around 129M pixels, so lift the cap to a higher but still sane value.
+const PIL_MAX_IMAGE_PIXELS: usize = 200_000_000;
+
+const BUILTIN_PROFILES: &str = include_str!("../tolerances.toml");
+
+/// The Pillow release the default tolerances were tuned against.
+pub const TESTED_PILLOW_VERSION: &str = "11.3";
+
+// Pillow codecs and modules a comparison can't run without.
+const REQUIRED_CODECS: &[&str] = &["jpg", "zlib"];
+const REQUIRED_MODULES: &[&str] = &["webp"];
+
+
+/// Limits on how far our output may stray from Pillow's, in 8-bit sample values.
+///
+/// Usually one of the named profiles in `tolerances.toml` at the crate root, which is built in; see `Profiles`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tolerances {
+	/// Width in pixels of the border held to the edge limits instead of the per-format ones. Decoders pad partial
+	/// blocks and upsample chroma at the image border differently, so that's where legitimate differences pile up.
+	/// Zero holds every pixel to the per-format limits.
+	#[serde(default = "default_border")]
+	pub border: u32,
+	/// Largest difference allowed within the border, for any format
+	pub edge_max_diff: u64,
+	/// Largest mean absolute difference allowed within the border, if limited
+	#[serde(default)]
+	pub edge_avg_diff: Option<f64>,
+	/// Limits away from the border, the first matching one applying. Images no limit matches must match exactly.
+	#[serde(default)]
+	pub limits: Vec<Limit>,
+	/// Limits for images compared at 16-bit precision, in 16-bit sample values and applying to the border too.
+	/// Images no limit matches must match exactly.
+	#[serde(default)]
+	pub limits_16bit: Vec<Limit>,
+}
+
+
+fn default_border() -> u32 {
+	1
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limit {
+	/// A file extension (`png`, `jpg`, ...), or `heif`, `jxl`, `jp2`, `svg`, `raw`, `ktx2` or `mpo` for formats outside `image::ImageFormat`
+	pub format: String,
+	/// Bits per sample as decoded; any depth if unset
+	pub bit_depth: Option<u8>,
+	/// Largest mean absolute difference
+	pub avg_diff: f64,
+	/// Largest difference of any one sample
+	pub max_diff: u64,
+}
+
+impl Limit {
+	fn matches(&self, format: Format, bit_depth: u8) -> bool {
+		let format_matches = match format {
+			Format::Image(format) => format.extensions_str().iter().any(|ext| ext.eq_ignore_ascii_case(&self.format)),
+			Format::Heif => self.format == "heif",
+			Format::Jxl => self.format == "jxl",
+			Format::Svg => self.format == "svg",
+			Format::RawPreview => self.format == "raw",
+			Format::Ktx2 => self.format == "ktx2",
+			Format::Jpeg2000 => self.format == "jp2",
+			Format::Mpo => self.format == "mpo",
+			Format::Pdf => self.format == "pdf",
+		};
+		format_matches && self.bit_depth.is_none_or(|depth| depth == bit_depth)
+	}
+}
+
+impl Default for Tolerances {
+	/// The `default` profile, for Pillow built against libjpeg-turbo as in the PyPI wheels.
+	fn default() -> Self {
+		Profiles::builtin().get("default").expect("the built-in profiles include a default").clone()
+	}
+}
+
+impl Tolerances {
+	/// The built-in profile for the Pillow build in use, going by known behavior differences between its codecs.
+	pub fn for_pillow(pillow: &PillowEnv) -> Self {
+		let ijg_7_or_later = !pillow.libjpeg_turbo
+			&& pillow
+				.libjpeg
+				.as_deref()
+				.and_then(|v| v.split('.').next())
+				.and_then(|major| major.parse::<u32>().ok())
+				.is_some_and(|major| major >= 7);
+		let profile = if ijg_7_or_later { "ijg" } else { "default" };
+		Profiles::builtin().get(profile).expect("the built-in profiles include ijg and default").clone()
+	}
+
+	/// (average, inner maximum) limits for an image of `format` with `bit_depth` bits per sample.
+	fn limits(&self, format: Format, bit_depth: u8) -> (f64, u64) {
+		self.limits
+			.iter()
+			.find(|limit| limit.matches(format, bit_depth))
+			.map_or((0.0, 0), |limit| (limit.avg_diff, limit.max_diff))
+	}
+
+	/// (average, maximum) limits for a grayscale image of `format` compared at 16-bit precision, in 16-bit sample
+	/// values.
+	fn limits_16bit(&self, format: Format) -> (f64, u64) {
+		self.limits_16bit
+			.iter()
+			.find(|limit| limit.matches(format, 16))
+			.map_or((0.0, 0), |limit| (limit.avg_diff, limit.max_diff))
+	}
+
+	/// Whether `stats`, from comparing a grayscale image of `format` at 16-bit precision, are within the 16-bit
+	/// limits, which hold the border to the same limits as the rest.
+	pub fn accepts_16bit(&self, format: Format, stats: &DiffStats) -> bool {
+		let (avg_limit, max_limit) = self.limits_16bit(format);
+		stats.mae <= avg_limit && stats.max_inner.max(stats.max_edge) <= max_limit
+	}
+
+	/// Whether `stats`, from comparing an image of `format` with `bit_depth` bits per sample as RGBA8, are within the
+	/// per-format limits away from the border and the edge limits within it.
+	pub fn accepts(&self, format: Format, bit_depth: u8, stats: &DiffStats) -> bool {
+		let (avg_limit, max_limit) = self.limits(format, bit_depth);
+		stats.mae <= avg_limit
+			&& stats.max_inner <= max_limit
+			&& stats.max_edge <= self.edge_max_diff
+			&& self.edge_avg_diff.is_none_or(|limit| stats.edge_mae <= limit)
+	}
+}
+
+
+/// Named tolerance profiles, as read from a TOML file of `[name]` tables. See `tolerances.toml` for the format.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct Profiles(BTreeMap<String, Tolerances>);
+
+impl Profiles {
+	/// The profiles in `tolerances.toml`: `default`, `ijg`, `pillow-10`, `strict` and `lenient`.
+	pub fn builtin() -> &'static Profiles {
+		static BUILTIN: LazyLock<Profiles> = LazyLock::new(|| Profiles::from_toml(BUILTIN_PROFILES).expect("the built-in profiles parse"));
+		&BUILTIN
+	}
+
+	pub fn from_toml(s: &str) -> Result<Profiles, toml::de::Error> {
+		toml::from_str(s)
+	}
+
+	pub fn load(path: &Path) -> Result<Profiles, ProfileError> {
+		let s = std::fs::read_to_string(path).map_err(ProfileError::Io)?;
+		Profiles::from_toml(&s).map_err(ProfileError::Parse)
+	}
+
+	pub fn get(&self, name: &str) -> Option<&Tolerances> {
+		self.0.get(name)
+	}
+
+	pub fn names(&self) -> impl Iterator<Item = &str> {
+		self.0.keys().map(String::as_str)
+	}
+}
+
+
+#[derive(Debug)]
+pub enum ProfileError {
+	Io(std::io::Error),
+	Parse(toml::de::Error),
+}
+
+impl std::fmt::Display for ProfileError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ProfileError::Io(err) => write!(f, "failed to read tolerance profiles: {err}"),
+			ProfileError::Parse(err) => write!(f, "invalid tolerance profiles: {err}"),
+		}
+	}
+}
+
+impl std::error::Error for ProfileError {}
+
+
+/// Versions of Pillow and the codec libraries behind it, which decide what the reference output is.
+#[derive(Debug, Clone)]
+pub struct PillowEnv {
+	pub pillow: String,
+	pub libjpeg: Option<String>,
+	pub libjpeg_turbo: bool,
+	pub zlib: Option<String>,
+	/// Required codecs, modules and plugins that are missing
+	pub missing: Vec<String>,
+}
+
+impl PillowEnv {
+	/// Probes the active Python environment.
+	pub fn probe() -> PyResult<PillowEnv> {
+		Python::attach(|py| {
+			let pil = py.import("PIL")?;
+			let features = py.import("PIL.features")?;
+			let version = |name: &str| -> PyResult<Option<String>> { features.call_method1("version", (name,))?.extract() };
+
+			let mut missing = Vec::new();
+			for codec in REQUIRED_CODECS {
+				if !features.call_method1("check_codec", (*codec,))?.extract::<bool>()? {
+					missing.push(format!("the {codec} codec"));
+				}
+			}
+			for module in REQUIRED_MODULES {
+				if !features.call_method1("check_module", (*module,))?.extract::<bool>()? {
+					missing.push(format!("the {module} module"));
+				}
+			}
+			// Pillow can only open HEIF through the pillow-heif plugin
+			if cfg!(feature = "heif") && py.import("pillow_heif").and_then(|heif| heif.call_method0("register_heif_opener")).is_err() {
+				missing.push("the pillow-heif plugin".to_string());
+			}
+
+			Ok(PillowEnv {
+				pillow: pil.getattr("__version__")?.extract()?,
+				libjpeg: version("jpg")?,
+				libjpeg_turbo: features.call_method1("check_feature", ("libjpeg_turbo",))?.extract::<Option<bool>>()?.unwrap_or(false),
+				zlib: version("zlib")?,
+				missing,
+			})
+		})
+	}
+
+	pub fn summary(&self) -> String {
+		format!(
+			"pillow={} libjpeg={}{} zlib={}",
+			self.pillow,
+			self.libjpeg.as_deref().unwrap_or("none"),
+			if self.libjpeg_turbo { " (turbo)" } else { "" },
+			self.zlib.as_deref().unwrap_or("none")
+		)
+	}
+
+	/// A message naming what's missing, if anything is.
+	pub fn check_required(&self) -> Result<(), String> {
+		if self.missing.is_empty() {
+			return Ok(());
+		}
+		Err(format!(
+			"Pillow {} is missing {}; install a Pillow build with them (e.g. the PyPI wheels)",
+			self.pillow,
+			self.missing.join(", ")
+		))
+	}
+
+	pub fn is_tested_version(&self) -> bool {
+		self.pillow.starts_with(&format!("{TESTED_PILLOW_VERSION}."))
+	}
+}
+
+
+/// Per-sample differences between two images of the same size, split between the border and the rest.
+///
+/// The histogram and bias tell apart the usual causes of a mismatch: a rounding difference shows up as most
+/// differing samples being off by one in the same direction, while corruption is a few pixels off by a lot.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiffStats {
+	/// Mean absolute difference over all samples
+	pub mae: f64,
+	/// Mean absolute difference over the samples of border pixels, or zero if there are none
+	pub edge_mae: f64,
+	/// Largest difference away from the border
+	pub max_inner: u64,
+	/// Largest difference within the border
+	pub max_edge: u64,
+	/// Mean of our samples minus Pillow's over all samples; positive if ours are brighter on average
+	pub bias: f64,
+	/// Pixels with at least one differing sample
+	pub differing_pixels: u64,
+	/// Number of samples for each absolute difference, indexed by the difference and ending at the largest one
+	pub histogram: Vec<u64>,
+}
+
+impl DiffStats {
+	/// The non-zero buckets of the histogram as `difference:count` pairs, at most `max_buckets` of them.
+	pub fn histogram_summary(&self, max_buckets: usize) -> String {
+		let buckets: Vec<_> = self.histogram.iter().enumerate().skip(1).filter(|(_, count)| **count > 0).collect();
+		let mut summary = buckets.iter().take(max_buckets).map(|(diff, count)| format!("{diff}:{count}")).collect::<Vec<_>>().join(" ");
+		if buckets.len() > max_buckets {
+			summary.push_str(" ...");
+		}
+		summary
+	}
+}
+
+
+/// Compares two RGBA8 buffers, treating the outermost `border` pixels on each side as edge pixels.
+pub fn diff_rgba8(ours: &[u8], theirs: &[u8], width: usize, height: usize, border: usize) -> DiffStats {
+	diff_samples(ours, theirs, width, height, 4, border)
+}
+
+
+/// Compares two buffers of interleaved samples with `channels` per pixel, treating the outermost `border` pixels on
+/// each side as edge pixels.
+pub fn diff_samples<T: Copy + PartialEq + Into<u64>>(ours: &[T], theirs: &[T], width: usize, height: usize, channels: usize, border: usize) -> DiffStats {
+	let mut stats = DiffStats::default();
+	if ours == theirs {
+		return stats;
+	}
+
+	let is_edge = |x: usize, y: usize| x < border || y < border || x + border >= width || y + border >= height;
+	let inner_pixels = width.saturating_sub(2 * border) * height.saturating_sub(2 * border);
+	let edge_samples = (width * height - inner_pixels) * channels;
+
+	let (mut diff_sum, mut edge_diff_sum, mut signed_sum) = (0, 0, 0i128);
+	let mut last_differing_pixel = None;
+	for (i, (&a, &b)) in ours.iter().zip(theirs).enumerate() {
+		let (a, b) = (a.into(), b.into());
+		let diff = a.abs_diff(b);
+		if diff == 0 {
+			continue;
+		}
+		diff_sum += diff;
+		signed_sum += i128::from(a) - i128::from(b);
+		let bucket = diff as usize;
+		if stats.histogram.len() <= bucket {
+			stats.histogram.resize(bucket + 1, 0);
+		}
+		stats.histogram[bucket] += 1;
+
+		let pixel = i / channels;
+		if last_differing_pixel != Some(pixel) {
+			stats.differing_pixels += 1;
+			last_differing_pixel = Some(pixel);
+		}
+		if is_edge(pixel % width, pixel / width) {
+			edge_diff_sum += diff;
+			stats.max_edge = stats.max_edge.max(diff);
+		} else {
+			stats.max_inner = stats.max_inner.max(diff);
+		}
+	}
+	stats.mae = diff_sum as f64 / ours.len() as f64;
+	stats.bias = signed_sum as f64 / ours.len() as f64;
+	stats.histogram[0] = ours.len() as u64 - stats.histogram[1..].iter().sum::<u64>();
+	if edge_samples > 0 {
+		stats.edge_mae = edge_diff_sum as f64 / edge_samples as f64;
+	}
+	stats
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+	pub format: Format,
+	/// In 16-bit sample values if `sixteen_bit`, otherwise 8-bit
+	pub stats: DiffStats,
+	/// Whether the images were compared at 16-bit precision rather than as RGBA8
+	pub sixteen_bit: bool,
+}
+
+
+#[derive(Debug)]
+pub enum ConformanceError {
+	/// We failed to decode the image
+	Imgest(Error),
+	/// Pillow failed to decode the image
+	Pillow(PyErr),
+	DimensionMismatch { ours: (u32, u32), pillow: (u32, u32) },
+	/// Both decoded, but the output differs by more than the tolerances allow
+	Mismatch(Comparison),
+}
+
+impl std::fmt::Display for ConformanceError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ConformanceError::Imgest(err) => write!(f, "imgest failed: {err}"),
+			ConformanceError::Pillow(err) => write!(f, "Pillow failed: {err}"),
+			ConformanceError::DimensionMismatch { ours, pillow } => {
+				write!(f, "dimension mismatch: imgest gave {}x{}, Pillow gave {}x{}", ours.0, ours.1, pillow.0, pillow.1)
+			},
+			ConformanceError::Mismatch(Comparison { format, stats, sixteen_bit }) => write!(
+				f,
+				"({format:?}{}) mismatch, avg_diff={}, max_inner={}, max_edge={}, edge_avg_diff={}, differing_pixels={}, bias={}, histogram=[{}]",
+				if *sixteen_bit { ", 16-bit" } else { "" },
+				stats.mae,
+				stats.max_inner,
+				stats.max_edge,
+				stats.edge_mae,
+				stats.differing_pixels,
+				stats.bias,
+				stats.histogram_summary(8)
+			),
+		}
+	}
+}
+
+impl std::error::Error for ConformanceError {}
+
+
+/// Decodes `path` with both imgest and Pillow and compares the results within `tolerances`.
+///
+/// Grayscale images we decode at 16 bits are compared at 16-bit precision if Pillow decodes them to one of its
+/// 16-bit (or 32-bit integer) modes too, so differences that vanish when reducing to 8 bits still count. Pillow has
+/// no 16-bit color modes, so everything else is compared as RGBA8.
+///
+/// Blocks on both decodes; from async code, run it on a blocking thread.
+pub fn compare(path: &Path, tolerances: &Tolerances) -> Result<Comparison, ConformanceError> {
+	let (format, img) = crate::load_image(path).map_err(ConformanceError::Imgest)?;
+	let check_dimensions = |width: u32, height: u32| {
+		if (img.width(), img.height()) != (width, height) {
+			return Err(ConformanceError::DimensionMismatch {
+				ours: (img.width(), img.height()),
+				pillow: (width, height),
+			});
+		}
+		Ok(())
+	};
+
+	if img.color() == image::ColorType::L16
+		&& let Some((width, height, theirs)) = Python::attach(|py| decode_with_pillow_16bit(py, path)).map_err(ConformanceError::Pillow)?
+	{
+		check_dimensions(width, height)?;
+		let ours = img.as_luma16().expect("L16 images are Luma16").as_raw().as_slice();
+		let stats = diff_samples(ours, &theirs, width as usize, height as usize, 1, tolerances.border as usize);
+		let comparison = Comparison { format, stats, sixteen_bit: true };
+		if !tolerances.accepts_16bit(format, &comparison.stats) {
+			return Err(ConformanceError::Mismatch(comparison));
+		}
+		return Ok(comparison);
+	}
+
+	let (width, height, theirs) = Python::attach(|py| decode_with_pillow(py, path)).map_err(ConformanceError::Pillow)?;
+	check_dimensions(width, height)?;
+
+	let bit_depth = (img.color().bits_per_pixel() / u16::from(img.color().channel_count())) as u8;
+	let ours = img.into_rgba8().into_raw();
+	let stats = diff_rgba8(&ours, &theirs, width as usize, height as usize, tolerances.border as usize);
+	let comparison = Comparison { format, stats, sixteen_bit: false };
+	if !tolerances.accepts(format, bit_depth, &comparison.stats) {
+		return Err(ConformanceError::Mismatch(comparison));
+	}
+	Ok(comparison)
+}
+
+
+/// Opens `path` with Pillow, without decoding it yet.
+fn open_with_pillow<'py>(py: Python<'py>, path: &Path) -> PyResult<Bound<'py, PyAny>> {
+	let pil = PIL_IMAGE_MODULE
+		.get_or_try_init(py, || {
+			let module = py.import("PIL.Image")?;
+			module.setattr("MAX_IMAGE_PIXELS", PIL_MAX_IMAGE_PIXELS)?;
+			let file_module = py.import("PIL.ImageFile")?;
+			file_module.setattr("LOAD_TRUNCATED_IMAGES", true)?;
+			let module = module.unbind();
+			Ok::<Py<PyModule>, PyErr>(module)
+		})?
+		.bind(py);
+
+	// pyo3 converts the path with the filesystem encoding, so non-UTF8 paths aren't mangled
+	pil.call_method1("open", (path,))
+}
+
+
+/// Decodes `path` with Pillow to 8-bit RGBA, returning (width, height, pixels).
+pub fn decode_with_pillow(py: Python<'_>, path: &Path) -> PyResult<(u32, u32, Vec<u8>)> {
+	let image = open_with_pillow(py, path)?;
+
+	// Normalize 16-bit grayscale to 8-bit before RGBA conversion (avoids oddness in Pillow's direct I;16 -> RGBA conversion which saturates to white).
+	let mode: String = image.getattr("mode")?.extract()?;
+	let image = if mode.starts_with("I;16") {
+		// 65536-entry LUT: v -> v >> 8
+		let lut: Vec<u16> = (0..=65535).map(|v| (v >> 8) as u16).collect();
+
+		image.call_method1("convert", ("I",))?.call_method1("point", (lut, "L"))?
+	} else {
+		image
+	};
+
+	// Convert to RGBA8
+	let image = image.call_method1("convert", ("RGBA",))?;
+
+	// (width, height)
+	let (width, height): (u32, u32) = image.getattr("size")?.extract()?;
+
+	// Get raw bytes as Bound<PyBytes>
+	let bytes = image.call_method0("tobytes")?;
+	let bytes = bytes.cast::<PyBytes>()?;
+
+	let data = bytes.as_bytes().to_vec();
+
+	Ok((width, height, data))
+}
+
+
+/// Decodes `path` with Pillow to 16-bit grayscale, returning (width, height, samples), if Pillow opens it in one of
+/// its 16-bit modes or 32-bit integer mode; `None` otherwise. 32-bit values are clamped to 16 bits.
+pub fn decode_with_pillow_16bit(py: Python<'_>, path: &Path) -> PyResult<Option<(u32, u32, Vec<u16>)>> {
+	let image = open_with_pillow(py, path)?;
+	let mode: String = image.getattr("mode")?.extract()?;
+	if !mode.starts_with("I;16") && mode != "I" {
+		return Ok(None);
+	}
+
+	// Mode I is 32-bit signed integers in native byte order, whichever I;16 variant it came from
+	let image = image.call_method1("convert", ("I",))?;
+	let (width, height): (u32, u32) = image.getattr("size")?.extract()?;
+	let bytes = image.call_method0("tobytes")?;
+	let bytes = bytes.cast::<PyBytes>()?;
+	let data = bytes
+		.as_bytes()
+		.chunks_exact(4)
+		.map(|v| i32::from_ne_bytes(v.try_into().unwrap()).clamp(0, 65535) as u16)
+		.collect();
+
+	Ok(Some((width, height, data)))
+}
+
+
+/// Restores the default SIGINT handler, which Python replaces with one that swallows Ctrl-C while Rust code runs.
+pub fn disable_python_sigint_handler() {
+	let _ = Python::attach(|py| -> PyResult<()> {
+		let signal = py.import("signal")?;
+		let sigint = signal.getattr("SIGINT")?;
+		let sig_dfl = signal.getattr("SIG_DFL")?;
+		signal.getattr("signal")?.call1((sigint, sig_dfl))?;
+		Ok(())
+	});
+}
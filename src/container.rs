@@ -0,0 +1,297 @@
+//! Images embedded in zip based documents, with the `office` feature: Word, PowerPoint and Excel files (docx, pptx,
+//! xlsx), EPUB books, and plain zip archives.
+//!
+//! An embedded image is addressed by the container's path, `!/`, and its name in the archive, as in
+//! `report.docx!/word/media/image1.png`. `expand` replaces each container in a list of paths (such as the `hash` and
+//! `digests` binaries read from a directory or manifest) with its images, and `read` reads either kind of path, so
+//! manifests can mix plain files and embedded images and go through the usual decoding.
+//!
+//! Only stored and deflated entries are read; encrypted archives and Zip64 (archives or entries of 4 GiB and more)
+//! aren't supported.
+
+use std::{
+	ffi::OsString,
+	io,
+	path::{Path, PathBuf},
+};
+
+use image::DynamicImage;
+
+use crate::{Error, Format, LoadOptions};
+
+
+/// Separates the container's path from the entry name in the path of an embedded image.
+pub const ENTRY_SEPARATOR: &str = "!/";
+
+/// Extensions of the files `expand` treats as containers.
+const CONTAINER_EXTENSIONS: [&str; 5] = ["docx", "pptx", "xlsx", "epub", "zip"];
+
+/// Extensions of the entries listed as images, which leaves out the EMF and WMF vector graphics Office also keeps in
+/// its media folders.
+const IMAGE_EXTENSIONS: [&str; 23] = [
+	"png", "jpg", "jpeg", "jpe", "gif", "webp", "bmp", "tif", "tiff", "ico", "avif", "heic", "heif", "jxl", "jp2", "j2k", "svg", "qoi", "tga", "dds", "ktx2",
+	"exr", "hdr",
+];
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerKind {
+	Docx,
+	Pptx,
+	Xlsx,
+	Epub,
+	/// Any other zip archive
+	Zip,
+}
+
+impl ContainerKind {
+	/// The folder Office keeps embedded images in, for the Office kinds.
+	fn media_folder(self) -> Option<&'static str> {
+		match self {
+			ContainerKind::Docx => Some("word/media/"),
+			ContainerKind::Pptx => Some("ppt/media/"),
+			ContainerKind::Xlsx => Some("xl/media/"),
+			ContainerKind::Epub | ContainerKind::Zip => None,
+		}
+	}
+}
+
+
+/// A zip archive read into memory, with its central directory parsed.
+#[derive(Debug, Clone)]
+pub struct Container {
+	data: Vec<u8>,
+	entries: Vec<Entry>,
+	kind: ContainerKind,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+	name: String,
+	method: u16,
+	encrypted: bool,
+	compressed_size: u32,
+	uncompressed_size: u32,
+	/// Offset of the local file header
+	offset: u32,
+}
+
+impl Container {
+	pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Container> {
+		Container::from_vec(std::fs::read(path)?)
+	}
+
+	/// Parses the archive's central directory. Entries are only read, and checked, by `read`.
+	pub fn from_vec(data: Vec<u8>) -> io::Result<Container> {
+		let entries = central_directory(&data)?;
+		let mut container = Container {
+			data,
+			entries,
+			kind: ContainerKind::Zip,
+		};
+		container.kind = if container.read("mimetype").is_ok_and(|mimetype| mimetype.trim_ascii() == b"application/epub+zip") {
+			ContainerKind::Epub
+		} else if container.entry("word/document.xml").is_some() {
+			ContainerKind::Docx
+		} else if container.entry("ppt/presentation.xml").is_some() {
+			ContainerKind::Pptx
+		} else if container.entry("xl/workbook.xml").is_some() {
+			ContainerKind::Xlsx
+		} else {
+			ContainerKind::Zip
+		};
+		Ok(container)
+	}
+
+	pub fn kind(&self) -> ContainerKind {
+		self.kind
+	}
+
+	/// Names of every entry, in archive order.
+	pub fn names(&self) -> impl Iterator<Item = &str> {
+		self.entries.iter().map(|entry| entry.name.as_str())
+	}
+
+	/// Names of the images in the archive, in archive order: those in the media folder of Office documents, and any
+	/// with an image file extension in EPUB books and other archives.
+	pub fn images(&self) -> Vec<&str> {
+		let folder = self.kind.media_folder();
+		self.names()
+			.filter(|name| folder.is_none_or(|folder| name.starts_with(folder)))
+			.filter(|name| {
+				let extension = name.rsplit_once('.').map_or("", |(_, extension)| extension);
+				IMAGE_EXTENSIONS.iter().any(|image| image.eq_ignore_ascii_case(extension))
+			})
+			.collect()
+	}
+
+	/// The contents of the entry `name`.
+	pub fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+		let Some(entry) = self.entry(name) else {
+			return Err(io::Error::new(io::ErrorKind::NotFound, format!("no entry {name:?} in the archive")));
+		};
+		if entry.encrypted {
+			return Err(io::Error::new(io::ErrorKind::Unsupported, format!("entry {name:?} is encrypted")));
+		}
+
+		let header = self
+			.data
+			.get(entry.offset as usize..)
+			.filter(|header| u32_at(header, 0) == Some(LOCAL_HEADER))
+			.ok_or_else(|| corrupt("bad local header"))?;
+		let (Some(name_len), Some(extra_len)) = (u16_at(header, 26), u16_at(header, 28)) else {
+			return Err(corrupt("bad local header"));
+		};
+		let start = 30 + usize::from(name_len) + usize::from(extra_len);
+		let compressed = header.get(start..start + entry.compressed_size as usize).ok_or_else(|| corrupt("entry runs past the end of the archive"))?;
+		let size = entry.uncompressed_size as usize;
+		let data = match entry.method {
+			METHOD_STORED => compressed.to_vec(),
+			// Inflating no more than the declared size keeps a deflate bomb to what the directory admits to
+			METHOD_DEFLATED => match miniz_oxide::inflate::decompress_to_vec_with_limit(compressed, size) {
+				Ok(data) => data,
+				Err(e) if e.status == miniz_oxide::inflate::TINFLStatus::HasMoreOutput => return Err(corrupt("entry is larger than its declared size")),
+				Err(_) => return Err(corrupt("corrupt deflate stream")),
+			},
+			method => return Err(io::Error::new(io::ErrorKind::Unsupported, format!("entry {name:?} uses compression method {method}"))),
+		};
+		if data.len() != size {
+			return Err(corrupt("entry is smaller than its declared size"));
+		}
+		Ok(data)
+	}
+
+	/// Decodes every image in the archive, in the order of `images`. The iterator keeps its own copy of `options`.
+	pub fn load_images<'a>(&'a self, options: &LoadOptions) -> impl Iterator<Item = (&'a str, Result<(Format, DynamicImage), Error>)> + use<'a> {
+		let options = options.clone();
+		self.images().into_iter().map(move |name| {
+			let result = self.read(name).map_err(Error::from).and_then(|data| crate::load_image_from_vec_with_options(data, &options));
+			(name, result)
+		})
+	}
+
+	fn entry(&self, name: &str) -> Option<&Entry> {
+		self.entries.iter().find(|entry| entry.name == name)
+	}
+}
+
+
+/// Whether `path` has the extension of a container `expand` looks into.
+pub fn is_container(path: &Path) -> bool {
+	path.extension().and_then(|extension| extension.to_str()).is_some_and(|extension| CONTAINER_EXTENSIONS.iter().any(|c| c.eq_ignore_ascii_case(extension)))
+}
+
+
+/// The path addressing entry `name` of the container at `container`.
+pub fn entry_path(container: &Path, name: &str) -> PathBuf {
+	let mut path = OsString::from(container);
+	path.push(ENTRY_SEPARATOR);
+	path.push(name);
+	PathBuf::from(path)
+}
+
+
+/// Splits the path of an embedded image into the container's path and the entry name, at the first `!/` following a
+/// container extension. None for any other path, including containers whose path isn't UTF-8.
+pub fn split_entry_path(path: &Path) -> Option<(&Path, &str)> {
+	let path = path.to_str()?;
+	let mut search = 0;
+	while let Some(found) = path[search..].find(ENTRY_SEPARATOR) {
+		let (container, name) = (Path::new(&path[..search + found]), &path[search + found + ENTRY_SEPARATOR.len()..]);
+		if is_container(container) {
+			return Some((container, name));
+		}
+		search += found + ENTRY_SEPARATOR.len();
+	}
+	None
+}
+
+
+/// Replaces every container in `paths` with the paths of its images (see `entry_path`), keeping the other paths, in
+/// order. Containers that can't be opened or parsed are kept as they are, so the error turns up when they're read
+/// rather than them silently going missing; those without any images are dropped.
+pub fn expand(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+	let mut out = Vec::with_capacity(paths.len());
+	for path in paths {
+		if !is_container(&path) || path.to_str().is_none() {
+			out.push(path);
+			continue;
+		}
+		match Container::open(&path) {
+			Ok(container) => out.extend(container.images().into_iter().map(|name| entry_path(&path, name))),
+			Err(_) => out.push(path),
+		}
+	}
+	out
+}
+
+
+/// Reads a file, or an embedded image if `path` addresses one (see `split_entry_path`).
+pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+	match split_entry_path(path.as_ref()) {
+		Some((container, name)) => Container::open(container)?.read(name),
+		None => std::fs::read(path),
+	}
+}
+
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+
+fn central_directory(data: &[u8]) -> io::Result<Vec<Entry>> {
+	// The end record is 22 bytes, followed by a comment of up to 64 KiB
+	let search_from = data.len().saturating_sub(22 + usize::from(u16::MAX));
+	let end = (search_from..data.len().saturating_sub(21))
+		.rev()
+		.find(|&pos| u32_at(data, pos) == Some(END_OF_CENTRAL_DIRECTORY))
+		.ok_or_else(|| corrupt("no end of central directory record"))?;
+	let (Some(count), Some(offset)) = (u16_at(data, end + 10), u32_at(data, end + 16)) else {
+		return Err(corrupt("truncated end of central directory record"));
+	};
+	if count == u16::MAX || offset == u32::MAX {
+		return Err(io::Error::new(io::ErrorKind::Unsupported, "Zip64 archives aren't supported"));
+	}
+
+	let mut entries = Vec::with_capacity(usize::from(count));
+	let mut pos = offset as usize;
+	for _ in 0..count {
+		let header = data.get(pos..pos + 46).filter(|header| u32_at(header, 0) == Some(CENTRAL_HEADER)).ok_or_else(|| corrupt("bad central directory"))?;
+		let field16 = |at| u16_at(header, at).expect("header length was checked");
+		let field32 = |at| u32_at(header, at).expect("header length was checked");
+		let name_len = usize::from(field16(28));
+		let name = data.get(pos + 46..pos + 46 + name_len).ok_or_else(|| corrupt("bad central directory"))?;
+		let entry = Entry {
+			name: String::from_utf8_lossy(name).into_owned(),
+			method: field16(10),
+			encrypted: field16(8) & 1 != 0,
+			compressed_size: field32(20),
+			uncompressed_size: field32(24),
+			offset: field32(42),
+		};
+		if [entry.compressed_size, entry.uncompressed_size, entry.offset].contains(&u32::MAX) {
+			return Err(io::Error::new(io::ErrorKind::Unsupported, "Zip64 entries aren't supported"));
+		}
+		entries.push(entry);
+		pos += 46 + name_len + usize::from(field16(30)) + usize::from(field16(32));
+	}
+	Ok(entries)
+}
+
+
+fn u16_at(data: &[u8], at: usize) -> Option<u16> {
+	Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+
+fn u32_at(data: &[u8], at: usize) -> Option<u32> {
+	Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+
+fn corrupt(message: &str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, format!("corrupt zip archive: {message}"))
+}
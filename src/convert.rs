@@ -0,0 +1,535 @@
+//! Pixel conversions shared by the decoders and available to downstream users: bit depth, gray/RGB, YCbCr/RGB,
+//! CMYK/RGB, luma extraction, sRGB transfer functions and normalized float tensors.
+//!
+//! Conversions follow the references we test parity against: rounding (not truncating) bit depth reduction, and full
+//! range BT.601 YCbCr as used by JFIF.
+
+use std::sync::LazyLock;
+
+use image::{ColorType, DynamicImage, GrayImage, RgbaImage};
+
+
+/// Widens an 8-bit sample to 16 bits, mapping 255 to 65535.
+pub fn u8_to_u16(v: u8) -> u16 {
+	u16::from(v) * 257
+}
+
+
+/// Narrows a 16-bit sample to 8 bits with rounding, i.e. `round(v * 255 / 65535)`.
+pub fn u16_to_u8(v: u16) -> u8 {
+	((u32::from(v) * 255 + 32767) / 65535) as u8
+}
+
+
+/// Narrows a 16-bit sample to 8 bits by keeping the high byte, as libpng's `png_set_strip_16` does.
+pub fn u16_to_u8_truncate(v: u16) -> u8 {
+	(v >> 8) as u8
+}
+
+
+pub fn u8_slice_to_u16(src: &[u8]) -> Vec<u16> {
+	src.iter().map(|&v| u8_to_u16(v)).collect()
+}
+
+
+pub fn u16_slice_to_u8(src: &[u16]) -> Vec<u8> {
+	src.iter().map(|&v| u16_to_u8(v)).collect()
+}
+
+
+/// Reorders big endian 16-bit samples (as stored in PNG and TIFF "MM") to native endianness in place.
+pub fn be16_to_native_in_place(buf: &mut [u8]) {
+	for c in buf.chunks_exact_mut(2) {
+		let v = u16::from_be_bytes([c[0], c[1]]);
+		c.copy_from_slice(&v.to_ne_bytes());
+	}
+}
+
+
+/// Replicates each gray sample into R, G and B.
+pub fn gray_to_rgb<T: Copy>(src: &[T]) -> Vec<T> {
+	src.iter().flat_map(|&v| [v, v, v]).collect()
+}
+
+
+/// Replicates each gray sample into R, G and B, keeping alpha.
+pub fn gray_alpha_to_rgba<T: Copy>(src: &[T]) -> Vec<T> {
+	src.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect()
+}
+
+
+/// Expands 8-bit gray with alpha to RGBA into `dst`, which must be twice as long as `src`.
+///
+/// Fixed size chunk copies into a preallocated buffer vectorize well, unlike `gray_alpha_to_rgba` or `image`'s
+/// generic per-pixel conversion; grayscale-with-alpha PNGs (manga, line art) are common enough for this to matter.
+pub fn la8_to_rgba8(src: &[u8], dst: &mut [u8]) {
+	for (out, p) in dst.chunks_exact_mut(4).zip(src.chunks_exact(2)) {
+		out.copy_from_slice(&[p[0], p[0], p[0], p[1]]);
+	}
+}
+
+
+/// `la8_to_rgba8` for 16-bit samples, rounding to 8 bits like `u16_to_u8`.
+pub fn la16_to_rgba8(src: &[u16], dst: &mut [u8]) {
+	for (out, p) in dst.chunks_exact_mut(4).zip(src.chunks_exact(2)) {
+		let (l, a) = (u16_to_u8(p[0]), u16_to_u8(p[1]));
+		out.copy_from_slice(&[l, l, l, a]);
+	}
+}
+
+
+/// Converts any image to 8-bit RGBA, taking the fast paths above where they apply and `image`'s conversion otherwise.
+/// Both round 16-bit samples the same way, so the result doesn't depend on which path was taken.
+pub fn into_rgba8(img: DynamicImage) -> RgbaImage {
+	let (width, height) = (img.width(), img.height());
+	let out = match &img {
+		DynamicImage::ImageLumaA8(buf) => {
+			let mut out = vec![0; buf.len() * 2];
+			la8_to_rgba8(buf.as_raw(), &mut out);
+			out
+		},
+		DynamicImage::ImageLumaA16(buf) => {
+			let mut out = vec![0; buf.len() * 2];
+			la16_to_rgba8(buf.as_raw(), &mut out);
+			out
+		},
+		_ => return img.into_rgba8(),
+	};
+	RgbaImage::from_raw(width, height, out).expect("buffer size matches the dimensions")
+}
+
+
+/// Converts interleaved 8-bit RGB to gray using the luma coefficients of `matrix`, rounding to nearest.
+pub fn rgb_to_gray(src: &[u8], matrix: YCbCrMatrix) -> Vec<u8> {
+	let (kr, kg, kb) = matrix.luma_coefficients();
+	src.chunks_exact(3)
+		.map(|p| (kr * f32::from(p[0]) + kg * f32::from(p[1]) + kb * f32::from(p[2])).round().clamp(0.0, 255.0) as u8)
+		.collect()
+}
+
+
+/// Converts one row of any 8 or 16-bit color type (16-bit samples native endian, as our decoders produce them) to
+/// 8-bit gray into `dst`, dropping alpha. Color is weighted like `rgb_to_gray` with BT.601, and 16-bit samples are
+/// rounded like `u16_to_u8`.
+pub fn row_to_luma8(row: &[u8], color_type: ColorType, dst: &mut [u8]) {
+	let bytes_per_pixel = usize::from(color_type.bytes_per_pixel());
+	let is_16bit = color_type.bytes_per_pixel() / color_type.channel_count() == 2;
+	let sample = |p: &[u8], c: usize| if is_16bit { u16_to_u8(u16::from_ne_bytes([p[c * 2], p[c * 2 + 1]])) } else { p[c] };
+	let (kr, kg, kb) = YCbCrMatrix::Bt601.luma_coefficients();
+	for (out, p) in dst.iter_mut().zip(row.chunks_exact(bytes_per_pixel)) {
+		*out = if color_type.has_color() {
+			let (r, g, b) = (f32::from(sample(p, 0)), f32::from(sample(p, 1)), f32::from(sample(p, 2)));
+			(kr * r + kg * g + kb * b).round().clamp(0.0, 255.0) as u8
+		} else {
+			sample(p, 0)
+		};
+	}
+}
+
+
+/// Converts one row of any 8 or 16-bit color type (16-bit samples native endian) to 8-bit RGBA into `dst`, which must
+/// hold 4 bytes per pixel. Gray is replicated, missing alpha is opaque, and 16-bit samples are rounded like `u16_to_u8`,
+/// so the result matches `into_rgba8`.
+pub fn row_to_rgba8(row: &[u8], color_type: ColorType, dst: &mut [u8]) {
+	let bytes_per_pixel = usize::from(color_type.bytes_per_pixel());
+	let channels = usize::from(color_type.channel_count());
+	let is_16bit = bytes_per_pixel / channels == 2;
+	let sample = |p: &[u8], c: usize| if is_16bit { u16_to_u8(u16::from_ne_bytes([p[c * 2], p[c * 2 + 1]])) } else { p[c] };
+	for (out, p) in dst.chunks_exact_mut(4).zip(row.chunks_exact(bytes_per_pixel)) {
+		let rgb = if color_type.has_color() { [sample(p, 0), sample(p, 1), sample(p, 2)] } else { [sample(p, 0); 3] };
+		let alpha = if color_type.has_alpha() { sample(p, channels - 1) } else { u8::MAX };
+		out.copy_from_slice(&[rgb[0], rgb[1], rgb[2], alpha]);
+	}
+}
+
+
+/// Converts any image to 8-bit gray with `row_to_luma8`, going through 8-bit RGB for float images.
+pub fn into_luma8(img: DynamicImage) -> GrayImage {
+	let (width, height) = (img.width(), img.height());
+	match img {
+		DynamicImage::ImageLuma8(img) => img,
+		DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+			GrayImage::from_raw(width, height, rgb_to_gray(img.to_rgb8().as_raw(), YCbCrMatrix::Bt601)).expect("buffer size matches the dimensions")
+		},
+		img => {
+			let mut out = vec![0; width as usize * height as usize];
+			row_to_luma8(img.as_bytes(), img.color(), &mut out);
+			GrayImage::from_raw(width, height, out).expect("buffer size matches the dimensions")
+		},
+	}
+}
+
+
+/// Matrix coefficients for YCbCr <-> RGB.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum YCbCrMatrix {
+	/// ITU-R BT.601, used by JPEG/JFIF and most stills
+	#[default]
+	Bt601,
+	/// ITU-R BT.709, used by HD video
+	Bt709,
+	/// ITU-R BT.2020 (non-constant luminance), used by UHD/HDR video and therefore by many HEIF and AVIF stills
+	Bt2020,
+}
+
+impl YCbCrMatrix {
+	/// (Kr, Kg, Kb)
+	pub fn luma_coefficients(self) -> (f32, f32, f32) {
+		let (kr, kb) = match self {
+			YCbCrMatrix::Bt601 => (0.299, 0.114),
+			YCbCrMatrix::Bt709 => (0.2126, 0.0722),
+			YCbCrMatrix::Bt2020 => (0.2627, 0.0593),
+		};
+		(kr, 1.0 - kr - kb, kb)
+	}
+}
+
+
+/// Coding-independent code points (ITU-T H.273) describing how a video-derived still is encoded, as carried by nclx
+/// `colr` boxes and AV1/HEVC sequence headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cicp {
+	pub color_primaries: u8,
+	pub transfer_characteristics: u8,
+	pub matrix_coefficients: u8,
+	pub full_range: bool,
+}
+
+impl Cicp {
+	/// The matrix to convert with, or `None` for the identity matrix (code 0), where the planes hold G, B and R as-is.
+	///
+	/// Unspecified and unsupported codes (YCgCo, ICtCp, chromaticity derived) fall back to BT.601, as libavif and
+	/// libheif do. Constant luminance BT.2020 is approximated by the non-constant luminance matrix.
+	pub fn ycbcr_matrix(&self) -> Option<YCbCrMatrix> {
+		match self.matrix_coefficients {
+			0 => None,
+			1 => Some(YCbCrMatrix::Bt709),
+			9 | 10 => Some(YCbCrMatrix::Bt2020),
+			_ => Some(YCbCrMatrix::Bt601),
+		}
+	}
+}
+
+
+/// Converts one full range 8-bit YCbCr pixel to RGB.
+pub fn ycbcr_to_rgb(y: u8, cb: u8, cr: u8, matrix: YCbCrMatrix) -> [u8; 3] {
+	let (kr, kg, kb) = matrix.luma_coefficients();
+	let y = f32::from(y);
+	let cb = f32::from(cb) - 128.0;
+	let cr = f32::from(cr) - 128.0;
+
+	let r = y + 2.0 * (1.0 - kr) * cr;
+	let b = y + 2.0 * (1.0 - kb) * cb;
+	let g = (y - kr * r - kb * b) / kg;
+	[r, g, b].map(|v| v.round().clamp(0.0, 255.0) as u8)
+}
+
+
+/// Converts one YCbCr pixel with normalized samples (Y in 0..1, Cb and Cr in -0.5..0.5) to RGB in 0..1, without
+/// clamping. For sources above 8 bits, where range and depth are normalized away by the caller.
+pub fn ycbcr_to_rgb_f32(y: f32, cb: f32, cr: f32, matrix: YCbCrMatrix) -> [f32; 3] {
+	let (kr, kg, kb) = matrix.luma_coefficients();
+	let r = y + 2.0 * (1.0 - kr) * cr;
+	let b = y + 2.0 * (1.0 - kb) * cb;
+	let g = (y - kr * r - kb * b) / kg;
+	[r, g, b]
+}
+
+
+/// Converts one 8-bit RGB pixel to full range YCbCr.
+pub fn rgb_to_ycbcr(r: u8, g: u8, b: u8, matrix: YCbCrMatrix) -> [u8; 3] {
+	let (kr, kg, kb) = matrix.luma_coefficients();
+	let (r, g, b) = (f32::from(r), f32::from(g), f32::from(b));
+
+	let y = kr * r + kg * g + kb * b;
+	let cb = 128.0 + (b - y) / (2.0 * (1.0 - kb));
+	let cr = 128.0 + (r - y) / (2.0 * (1.0 - kr));
+	[y, cb, cr].map(|v| v.round().clamp(0.0, 255.0) as u8)
+}
+
+
+/// Converts interleaved 8-bit CMYK to RGB with the naive formula Pillow uses, R = (1 - C)(1 - K) and so on, rounding
+/// to nearest. `inverted` is for Adobe style CMYK, where 0 means full ink: Photoshop writes JPEGs that way.
+pub fn cmyk_to_rgb(src: &[u8], inverted: bool, dst: &mut [u8]) {
+	let ink = |v: u8| u32::from(if inverted { v } else { 255 - v });
+	for (cmyk, rgb) in src.chunks_exact(4).zip(dst.chunks_exact_mut(3)) {
+		let k = ink(cmyk[3]);
+		for (out, &c) in rgb.iter_mut().zip(cmyk) {
+			*out = ((ink(c) * k + 127) / 255) as u8;
+		}
+	}
+}
+
+
+/// Converts interleaved YCbCr to RGB in place.
+pub fn ycbcr_to_rgb_in_place(buf: &mut [u8], matrix: YCbCrMatrix) {
+	for p in buf.chunks_exact_mut(3) {
+		let rgb = ycbcr_to_rgb(p[0], p[1], p[2], matrix);
+		p.copy_from_slice(&rgb);
+	}
+}
+
+
+/// The sRGB electro-optical transfer function: encoded value in 0..1 to linear light in 0..1.
+pub fn srgb_to_linear(v: f32) -> f32 {
+	if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+
+/// Inverse of `srgb_to_linear`.
+pub fn linear_to_srgb(v: f32) -> f32 {
+	if v <= 0.0031308 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 }
+}
+
+
+static SRGB8_TO_LINEAR: LazyLock<[f32; 256]> = LazyLock::new(|| std::array::from_fn(|i| srgb_to_linear(i as f32 / 255.0)));
+
+
+/// `srgb_to_linear` for 8-bit samples, via a lookup table.
+pub fn srgb8_to_linear(v: u8) -> f32 {
+	SRGB8_TO_LINEAR[usize::from(v)]
+}
+
+
+/// `linear_to_srgb` to an 8-bit sample, rounding and clamping out of range input.
+pub fn linear_to_srgb8(v: f32) -> u8 {
+	(linear_to_srgb(v.clamp(0.0, 1.0)) * 255.0).round() as u8
+}
+
+
+/// Maps the color samples (not alpha) of an image through `f`, which takes and returns values in 0..1. Integer
+/// samples go through a lookup table built from `f`, with results rounded and clamped; float samples are mapped
+/// directly.
+pub fn map_color_samples(img: &mut DynamicImage, f: impl Fn(f32) -> f32) {
+	fn map<T: Copy>(samples: &mut [T], channels: usize, alpha: bool, f: impl Fn(T) -> T) {
+		let color = channels - usize::from(alpha);
+		for pixel in samples.chunks_exact_mut(channels) {
+			for v in &mut pixel[..color] {
+				*v = f(*v);
+			}
+		}
+	}
+
+	let color_type = img.color();
+	let (channels, alpha) = (usize::from(color_type.channel_count()), color_type.has_alpha());
+	let map8 = |samples: &mut [u8]| {
+		let lut: Vec<u8> = (0..=u8::MAX).map(|v| (f(f32::from(v) / 255.0).clamp(0.0, 1.0) * 255.0).round() as u8).collect();
+		map(samples, channels, alpha, |v| lut[usize::from(v)]);
+	};
+	let map16 = |samples: &mut [u16]| {
+		let lut: Vec<u16> = (0..=u16::MAX).map(|v| (f(f32::from(v) / 65535.0).clamp(0.0, 1.0) * 65535.0).round() as u16).collect();
+		map(samples, channels, alpha, |v| lut[usize::from(v)]);
+	};
+	match img {
+		DynamicImage::ImageLuma8(img) => map8(img),
+		DynamicImage::ImageLumaA8(img) => map8(img),
+		DynamicImage::ImageRgb8(img) => map8(img),
+		DynamicImage::ImageRgba8(img) => map8(img),
+		DynamicImage::ImageLuma16(img) => map16(img),
+		DynamicImage::ImageLumaA16(img) => map16(img),
+		DynamicImage::ImageRgb16(img) => map16(img),
+		DynamicImage::ImageRgba16(img) => map16(img),
+		DynamicImage::ImageRgb32F(img) => map(img, channels, alpha, &f),
+		DynamicImage::ImageRgba32F(img) => map(img, channels, alpha, &f),
+		_ => (),
+	}
+}
+
+
+/// Reinhard's global tone mapping operator, `v / (1 + v)`, compressing linear light in 0..inf into 0..1.
+pub fn reinhard(v: f32) -> f32 {
+	let v = v.max(0.0);
+	v / (1.0 + v)
+}
+
+
+/// Replaces the NaN and infinite samples of interleaved float pixels with `channels` samples each: NaN becomes `nan`
+/// and the infinities `pos_inf` and `neg_inf`. Returns the number of pixels that had any.
+pub fn replace_non_finite(samples: &mut [f32], channels: usize, nan: f32, pos_inf: f32, neg_inf: f32) -> usize {
+	let mut pixels = 0;
+	for pixel in samples.chunks_exact_mut(channels) {
+		let mut affected = false;
+		for v in pixel.iter_mut().filter(|v| !v.is_finite()) {
+			*v = if v.is_nan() {
+				nan
+			} else if v.is_sign_positive() {
+				pos_inf
+			} else {
+				neg_inf
+			};
+			affected = true;
+		}
+		pixels += usize::from(affected);
+	}
+	pixels
+}
+
+
+/// Tone maps interleaved linear float RGB or RGBA (`channels` 3 or 4) to 8-bit sRGB RGBA. Color goes through `map`
+/// and `linear_to_srgb8`; alpha is linear already and is only clamped and scaled.
+pub fn tone_map_to_rgba8(src: &[f32], channels: usize, map: impl Fn(f32) -> f32, dst: &mut [u8]) {
+	for (pixel, out) in src.chunks_exact(channels).zip(dst.chunks_exact_mut(4)) {
+		for (o, &v) in out[..3].iter_mut().zip(pixel) {
+			*o = linear_to_srgb8(map(v));
+		}
+		out[3] = pixel.get(3).map_or(255, |&a| (a.clamp(0.0, 1.0) * 255.0).round() as u8);
+	}
+}
+
+
+/// Memory order of the tensors `to_f32` and `to_u8` produce.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TensorLayout {
+	/// Channels interleaved per pixel, as images are stored (TensorFlow's default)
+	#[default]
+	Hwc,
+	/// One plane per channel (PyTorch's default)
+	Chw,
+}
+
+
+/// How `to_f32` normalizes samples, after scaling them to 0..=1 (8-bit samples divided by 255, 16-bit by 65535, and
+/// floats as they are).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Normalization {
+	/// Left in 0..=1
+	#[default]
+	Unit,
+	/// `(v - mean[c]) / std[c]` for channel `c`. Either one value for every channel or one per channel, alpha included,
+	/// so drop alpha before normalizing RGBA images with RGB statistics.
+	MeanStd { mean: Vec<f32>, std: Vec<f32> },
+}
+
+impl Normalization {
+	/// The ImageNet RGB statistics most pretrained vision models expect.
+	pub fn imagenet() -> Normalization {
+		Normalization::MeanStd {
+			mean: vec![0.485, 0.456, 0.406],
+			std: vec![0.229, 0.224, 0.225],
+		}
+	}
+
+	/// Per channel mean and reciprocal std.
+	fn per_channel(&self, channels: usize) -> (Vec<f32>, Vec<f32>) {
+		let expand = |values: &[f32]| match values.len() {
+			1 => vec![values[0]; channels],
+			n if n == channels => values.to_vec(),
+			n => panic!("normalization has {n} values for an image with {channels} channels"),
+		};
+		match self {
+			Normalization::Unit => (vec![0.0; channels], vec![1.0; channels]),
+			Normalization::MeanStd { mean, std } => (expand(mean), expand(std).iter().map(|s| 1.0 / s).collect()),
+		}
+	}
+}
+
+
+/// Converts an image to a normalized `f32` tensor in `layout`, of `width * height * channels` values with the
+/// image's own channels. 16-bit samples keep their precision.
+///
+/// # Panics
+/// If `normalization` has neither one value nor one per channel.
+pub fn to_f32(img: &DynamicImage, layout: TensorLayout, normalization: &Normalization) -> Vec<f32> {
+	let channels = usize::from(img.color().channel_count());
+	let (mean, inv_std) = normalization.per_channel(channels);
+	if let Some(flat) = img.as_flat_samples_u8() {
+		normalize(flat.samples, channels, layout, &mean, &inv_std, |v| f32::from(v) / 255.0)
+	} else if let Some(flat) = img.as_flat_samples_u16() {
+		normalize(flat.samples, channels, layout, &mean, &inv_std, |v| f32::from(v) / 65535.0)
+	} else if let Some(flat) = img.as_flat_samples_f32() {
+		normalize(flat.samples, channels, layout, &mean, &inv_std, |v| v)
+	} else {
+		// `DynamicImage` is non-exhaustive; anything added later goes through RGBA
+		let (mean, inv_std) = normalization.per_channel(4);
+		normalize(img.to_rgba32f().as_raw(), 4, layout, &mean, &inv_std, |v| v)
+	}
+}
+
+
+/// Element type of the framework tensors `DecodedImage::to_candle_tensor` and `to_tch_tensor` produce, and of the
+/// `pixels` column of `arrow::ImageBatchBuilder`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TensorDType {
+	/// 8-bit samples, as from `to_u8`
+	U8,
+	/// Normalized floats, as from `to_f32`
+	F32(Normalization),
+	/// Normalized half floats, as from `to_f16`
+	#[cfg(feature = "f16")]
+	F16 { normalization: Normalization, linear: bool },
+}
+
+impl Default for TensorDType {
+	fn default() -> Self {
+		TensorDType::F32(Normalization::Unit)
+	}
+}
+
+
+/// Converts an image to an 8-bit tensor in `layout`, of `width * height * channels` values with the image's own
+/// channels. 16-bit samples are rounded to 8 bits and floats are clamped to 0..=1 and scaled.
+pub fn to_u8(img: &DynamicImage, layout: TensorLayout) -> Vec<u8> {
+	let channels = usize::from(img.color().channel_count());
+	if let Some(flat) = img.as_flat_samples_u8() {
+		reorder(flat.samples, channels, layout, |_, v| v)
+	} else if let Some(flat) = img.as_flat_samples_u16() {
+		reorder(flat.samples, channels, layout, |_, v| u16_to_u8(v))
+	} else if let Some(flat) = img.as_flat_samples_f32() {
+		reorder(flat.samples, channels, layout, |_, v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+	} else {
+		// `DynamicImage` is non-exhaustive; anything added later goes through RGBA
+		reorder(img.to_rgba8().as_raw(), 4, layout, |_, v| v)
+	}
+}
+
+
+/// Converts an image to a normalized half float tensor, as `to_f32` does, for pipelines that hand fp16 to the GPU.
+/// With `linear`, 8 and 16-bit color samples are converted from sRGB to linear light before normalizing, in full
+/// precision; alpha, and float images (which are linear already), are left as they are.
+///
+/// # Panics
+/// If `normalization` has neither one value nor one per channel.
+#[cfg(feature = "f16")]
+pub fn to_f16(img: &DynamicImage, layout: TensorLayout, normalization: &Normalization, linear: bool) -> Vec<half::f16> {
+	let channels = usize::from(img.color().channel_count());
+	let color_channels = if img.color().has_alpha() { channels - 1 } else { channels };
+	let (mean, inv_std) = normalization.per_channel(channels);
+	let linearize = |c: usize| linear && c < color_channels;
+	let half = |c: usize, v: f32| half::f16::from_f32((v - mean[c]) * inv_std[c]);
+	if let Some(flat) = img.as_flat_samples_u8() {
+		reorder(flat.samples, channels, layout, |c, v| half(c, if linearize(c) { srgb8_to_linear(v) } else { f32::from(v) / 255.0 }))
+	} else if let Some(flat) = img.as_flat_samples_u16() {
+		reorder(flat.samples, channels, layout, |c, v| {
+			let v = f32::from(v) / 65535.0;
+			half(c, if linearize(c) { srgb_to_linear(v) } else { v })
+		})
+	} else if let Some(flat) = img.as_flat_samples_f32() {
+		reorder(flat.samples, channels, layout, half)
+	} else {
+		// `DynamicImage` is non-exhaustive; anything added later goes through RGBA
+		let (mean, inv_std) = normalization.per_channel(4);
+		reorder(img.to_rgba32f().as_raw(), 4, layout, |c, v| half::f16::from_f32((v - mean[c]) * inv_std[c]))
+	}
+}
+
+
+fn normalize<T: Copy>(samples: &[T], channels: usize, layout: TensorLayout, mean: &[f32], inv_std: &[f32], to_unit: impl Fn(T) -> f32) -> Vec<f32> {
+	reorder(samples, channels, layout, |c, v| (to_unit(v) - mean[c]) * inv_std[c])
+}
+
+
+/// Maps interleaved samples, with their channel index, into `layout`.
+fn reorder<T: Copy, U: Copy + Default>(samples: &[T], channels: usize, layout: TensorLayout, f: impl Fn(usize, T) -> U) -> Vec<U> {
+	let pixels = samples.len() / channels;
+	let mut out = vec![U::default(); pixels * channels];
+	for (i, pixel) in samples.chunks_exact(channels).enumerate() {
+		for (c, &v) in pixel.iter().enumerate() {
+			let index = match layout {
+				TensorLayout::Hwc => i * channels + c,
+				TensorLayout::Chw => c * pixels + i,
+			};
+			out[index] = f(c, v);
+		}
+	}
+	out
+}
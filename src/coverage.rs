@@ -0,0 +1,65 @@
+//! Decoder branch introspection for tests.
+//!
+//! Decoders record which of their code paths an input took (progressive vs baseline, interlaced, 16-bit, ICC present, ...)
+//! so fixture suites can assert that their corpus actually exercises every path rather than guessing.
+//! Only compiled with the `testing` feature; without it the recording calls compile to nothing.
+
+use std::{cell::RefCell, collections::HashSet};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Branch {
+	PngInterlaced,
+	PngNonInterlaced,
+	Png8Bit,
+	Png16Bit,
+	PngSubByte,
+	PngPalette,
+	PngIccProfile,
+	PngAnimated,
+	JpegBaseline,
+	JpegProgressive,
+	JpegGrayscale,
+	JpegColor,
+	JpegIccProfile,
+	WebP,
+	Fallback,
+}
+
+impl Branch {
+	pub const ALL: &[Branch] = &[
+		Branch::PngInterlaced,
+		Branch::PngNonInterlaced,
+		Branch::Png8Bit,
+		Branch::Png16Bit,
+		Branch::PngSubByte,
+		Branch::PngPalette,
+		Branch::PngIccProfile,
+		Branch::PngAnimated,
+		Branch::JpegBaseline,
+		Branch::JpegProgressive,
+		Branch::JpegGrayscale,
+		Branch::JpegColor,
+		Branch::JpegIccProfile,
+		Branch::WebP,
+		Branch::Fallback,
+	];
+}
+
+
+thread_local! {
+	static HITS: RefCell<HashSet<Branch>> = RefCell::new(HashSet::new());
+}
+
+
+/// Returns the branches recorded on this thread since the last call, and clears them.
+pub fn take() -> HashSet<Branch> {
+	HITS.with(|hits| std::mem::take(&mut *hits.borrow_mut()))
+}
+
+
+pub(crate) fn record(branch: Branch) {
+	HITS.with(|hits| {
+		hits.borrow_mut().insert(branch);
+	});
+}
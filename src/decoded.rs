@@ -0,0 +1,316 @@
+//! Decoded images along with the metadata their decoders read, for callers that need more than the pixels.
+
+use image::{ColorType, DynamicImage, ImageDecoder, metadata::Orientation};
+#[cfg(feature = "ndarray")]
+use ndarray::{Array3, ArrayView3};
+
+#[cfg(any(feature = "candle", feature = "tch"))]
+use crate::convert::TensorDType;
+use crate::{
+	Error, Format,
+	convert::{self, Normalization, TensorLayout},
+	exif::Exif,
+	iptc::Iptc,
+};
+
+
+/// An image with its format and metadata, as returned by `load_image_with_metadata`.
+///
+/// Metadata fields are `None` when the format can't carry them or the file doesn't, and are passed through as stored:
+/// nothing is applied to the pixels (the ICC profile isn't converted from, and the orientation is only undone with
+/// `LoadOptions::apply_orientation`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedImage {
+	pub image: DynamicImage,
+	pub format: Format,
+	pub icc_profile: Option<Vec<u8>>,
+	/// Raw EXIF, starting with the TIFF header. See `exif::Exif::parse`.
+	pub exif: Option<Vec<u8>>,
+	/// The XMP packet
+	pub xmp: Option<Vec<u8>>,
+	/// IPTC-IIM data, in whatever wrapping the format stores it in. See `iptc::Iptc::parse`.
+	pub iptc: Option<Vec<u8>>,
+	/// Gamma from a PNG gAMA chunk (or 1/2.2 for sRGB, see `PngDecoder::gamma_value`)
+	pub gamma: Option<f64>,
+	/// Significant bits per channel from a PNG sBIT chunk, whether or not `PngOptions::significant_bits` rescaled them
+	pub significant_bits: Option<Vec<u8>>,
+	/// Whether the decoder produced 16-bit samples, before `LoadOptions::output` and `output_depth` converted them
+	pub is_16bit: bool,
+	/// The EXIF (or format specific) orientation, which the image still needs to be displayed upright. Always
+	/// `NoTransforms` with `LoadOptions::apply_orientation`, which has already applied it.
+	pub orientation: Orientation,
+}
+
+
+impl DecodedImage {
+	/// The EXIF, parsed: `Exif::fields` has the commonly used fields typed, and `Exif::get` any tag. `None` without
+	/// EXIF, or if its TIFF header is unusable.
+	pub fn parsed_exif(&self) -> Option<Exif> {
+		Exif::parse(self.exif.as_deref()?)
+	}
+
+	/// The IPTC records, parsed whatever the format wrapped them in: `Iptc::fields` has the caption, keywords, credit
+	/// and other common fields typed, and `Iptc::get` any dataset. `None` without IPTC, or if it has no records.
+	pub fn parsed_iptc(&self) -> Option<Iptc> {
+		Iptc::parse(self.iptc.as_deref()?)
+	}
+
+	/// The image as a normalized float tensor, e.g. for a training pipeline. See `convert::to_f32`.
+	pub fn to_f32(&self, layout: TensorLayout, normalization: &Normalization) -> Vec<f32> {
+		convert::to_f32(&self.image, layout, normalization)
+	}
+
+	/// The image as a normalized half float tensor. See `convert::to_f16`.
+	#[cfg(feature = "f16")]
+	pub fn to_f16(&self, layout: TensorLayout, normalization: &Normalization, linear: bool) -> Vec<half::f16> {
+		convert::to_f16(&self.image, layout, normalization, linear)
+	}
+
+	/// The image's samples. See `DecodedPixels`.
+	pub fn into_pixels(self) -> DecodedPixels {
+		DecodedPixels::from(self.image)
+	}
+
+	/// The image as a height by width by channels (HWC) array of its samples, taking the image's buffer without
+	/// copying it.
+	#[cfg(feature = "ndarray")]
+	pub fn into_array3(self) -> DecodedArray {
+		let (width, height) = (self.image.width() as usize, self.image.height() as usize);
+		let pixels = self.into_pixels();
+		// From the buffer rather than the color type, which `DecodedPixels` may have widened to RGBA
+		let len = match &pixels {
+			DecodedPixels::U8(samples) => samples.len(),
+			DecodedPixels::U16(samples) => samples.len(),
+			DecodedPixels::F32(samples) => samples.len(),
+		};
+		let shape = (height, width, len / (width * height).max(1));
+		match pixels {
+			DecodedPixels::U8(samples) => DecodedArray::U8(Array3::from_shape_vec(shape, samples).expect("buffer size matches the dimensions")),
+			DecodedPixels::U16(samples) => DecodedArray::U16(Array3::from_shape_vec(shape, samples).expect("buffer size matches the dimensions")),
+			DecodedPixels::F32(samples) => DecodedArray::F32(Array3::from_shape_vec(shape, samples).expect("buffer size matches the dimensions")),
+		}
+	}
+
+	/// The image as a candle tensor on `device`, of shape (height, width, channels) or (channels, height, width) by
+	/// `layout`. The samples are converted straight into the buffer the tensor takes over.
+	#[cfg(feature = "candle")]
+	pub fn to_candle_tensor(&self, device: &candle_core::Device, layout: TensorLayout, dtype: &TensorDType) -> candle_core::Result<candle_core::Tensor> {
+		let shape = self.tensor_shape(layout);
+		match dtype {
+			TensorDType::U8 => candle_core::Tensor::from_vec(convert::to_u8(&self.image, layout), shape, device),
+			TensorDType::F32(normalization) => candle_core::Tensor::from_vec(self.to_f32(layout, normalization), shape, device),
+			#[cfg(feature = "f16")]
+			TensorDType::F16 { normalization, linear } => candle_core::Tensor::from_vec(self.to_f16(layout, normalization, *linear), shape, device),
+		}
+	}
+
+	/// The image as a tch tensor on `device`, shaped as for `to_candle_tensor`.
+	#[cfg(feature = "tch")]
+	pub fn to_tch_tensor(&self, device: tch::Device, layout: TensorLayout, dtype: &TensorDType) -> tch::Tensor {
+		let (a, b, c) = self.tensor_shape(layout);
+		let tensor = match dtype {
+			TensorDType::U8 => tch::Tensor::from_slice(&convert::to_u8(&self.image, layout)),
+			TensorDType::F32(normalization) => tch::Tensor::from_slice(&self.to_f32(layout, normalization)),
+			#[cfg(feature = "f16")]
+			TensorDType::F16 { normalization, linear } => tch::Tensor::from_slice(&self.to_f16(layout, normalization, *linear)),
+		};
+		tensor.view([a as i64, b as i64, c as i64]).to_device(device)
+	}
+
+	#[cfg(any(feature = "candle", feature = "tch"))]
+	fn tensor_shape(&self, layout: TensorLayout) -> (usize, usize, usize) {
+		let (width, height) = (self.image.width() as usize, self.image.height() as usize);
+		let channels = usize::from(self.image.color().channel_count());
+		match layout {
+			TensorLayout::Hwc => (height, width, channels),
+			TensorLayout::Chw => (channels, height, width),
+		}
+	}
+
+	/// The image as a normalized float HWC array. See `convert::to_f32`.
+	#[cfg(feature = "ndarray")]
+	pub fn to_array3_f32(&self, normalization: &Normalization) -> Array3<f32> {
+		let shape = (
+			self.image.height() as usize,
+			self.image.width() as usize,
+			usize::from(self.image.color().channel_count()),
+		);
+		Array3::from_shape_vec(shape, self.to_f32(TensorLayout::Hwc, normalization)).expect("buffer size matches the dimensions")
+	}
+}
+
+
+/// The samples of an image as an ndarray, in height by width by channels (HWC) layout, as returned by
+/// `DecodedImage::into_array3`.
+#[cfg(feature = "ndarray")]
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodedArray {
+	U8(Array3<u8>),
+	U16(Array3<u16>),
+	F32(Array3<f32>),
+}
+
+#[cfg(feature = "ndarray")]
+impl DecodedArray {
+	pub fn as_u8(&self) -> Option<ArrayView3<'_, u8>> {
+		match self {
+			DecodedArray::U8(array) => Some(array.view()),
+			_ => None,
+		}
+	}
+
+	pub fn as_u16(&self) -> Option<ArrayView3<'_, u16>> {
+		match self {
+			DecodedArray::U16(array) => Some(array.view()),
+			_ => None,
+		}
+	}
+
+	pub fn as_f32(&self) -> Option<ArrayView3<'_, f32>> {
+		match self {
+			DecodedArray::F32(array) => Some(array.view()),
+			_ => None,
+		}
+	}
+
+	/// The 8-bit array, or the array back if it's of another type.
+	pub fn into_u8(self) -> Result<Array3<u8>, DecodedArray> {
+		match self {
+			DecodedArray::U8(array) => Ok(array),
+			other => Err(other),
+		}
+	}
+
+	/// The float array, or the array back if it's of another type.
+	pub fn into_f32(self) -> Result<Array3<f32>, DecodedArray> {
+		match self {
+			DecodedArray::F32(array) => Ok(array),
+			other => Err(other),
+		}
+	}
+}
+
+
+/// The samples of an image in the type they were decoded to, interleaved and row by row, for code that handles every
+/// depth rather than converting to one. Ask for a depth with `LoadOptions::output_depth` to know which to expect.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodedPixels {
+	U8(Vec<u8>),
+	U16(Vec<u16>),
+	F32(Vec<f32>),
+}
+
+impl DecodedPixels {
+	pub fn as_u8(&self) -> Option<&[u8]> {
+		match self {
+			DecodedPixels::U8(samples) => Some(samples),
+			_ => None,
+		}
+	}
+
+	pub fn as_u16(&self) -> Option<&[u16]> {
+		match self {
+			DecodedPixels::U16(samples) => Some(samples),
+			_ => None,
+		}
+	}
+
+	pub fn as_f32(&self) -> Option<&[f32]> {
+		match self {
+			DecodedPixels::F32(samples) => Some(samples),
+			_ => None,
+		}
+	}
+
+	/// The 16-bit samples, or the image back if they're of another type.
+	pub fn into_u16(self) -> Result<Vec<u16>, DecodedPixels> {
+		match self {
+			DecodedPixels::U16(samples) => Ok(samples),
+			other => Err(other),
+		}
+	}
+
+	/// Bits per sample: 8, 16 or 32.
+	pub fn bit_depth(&self) -> u8 {
+		match self {
+			DecodedPixels::U8(_) => 8,
+			DecodedPixels::U16(_) => 16,
+			DecodedPixels::F32(_) => 32,
+		}
+	}
+}
+
+impl From<DynamicImage> for DecodedPixels {
+	/// Takes the image's buffer without copying it.
+	fn from(img: DynamicImage) -> Self {
+		match img {
+			DynamicImage::ImageLuma8(buf) => DecodedPixels::U8(buf.into_raw()),
+			DynamicImage::ImageLumaA8(buf) => DecodedPixels::U8(buf.into_raw()),
+			DynamicImage::ImageRgb8(buf) => DecodedPixels::U8(buf.into_raw()),
+			DynamicImage::ImageRgba8(buf) => DecodedPixels::U8(buf.into_raw()),
+			DynamicImage::ImageLuma16(buf) => DecodedPixels::U16(buf.into_raw()),
+			DynamicImage::ImageLumaA16(buf) => DecodedPixels::U16(buf.into_raw()),
+			DynamicImage::ImageRgb16(buf) => DecodedPixels::U16(buf.into_raw()),
+			DynamicImage::ImageRgba16(buf) => DecodedPixels::U16(buf.into_raw()),
+			DynamicImage::ImageRgb32F(buf) => DecodedPixels::F32(buf.into_raw()),
+			DynamicImage::ImageRgba32F(buf) => DecodedPixels::F32(buf.into_raw()),
+			// `DynamicImage` is non-exhaustive; anything added later is converted to the widest type
+			img => DecodedPixels::F32(img.into_rgba32f().into_raw()),
+		}
+	}
+}
+
+
+/// An image as its decoder produced it, as returned by `load_raw`, in a `Vec` or a buffer from an
+/// `allocator::OutputAllocator`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawImage<B = Vec<u8>> {
+	pub width: u32,
+	pub height: u32,
+	/// The decoder's color type, which for formats going through the `image` crate is already one `DynamicImage` has
+	pub color_type: ColorType,
+	/// Samples interleaved and row by row, without padding; 16-bit and float samples are in native byte order
+	pub data: B,
+}
+
+
+/// Metadata collected from a decoder before it's consumed.
+#[derive(Debug, Default)]
+pub(crate) struct Metadata {
+	pub icc_profile: Option<Vec<u8>>,
+	pub exif: Option<Vec<u8>>,
+	pub xmp: Option<Vec<u8>>,
+	pub iptc: Option<Vec<u8>>,
+	pub gamma: Option<f64>,
+	pub significant_bits: Option<Vec<u8>>,
+	pub is_16bit: bool,
+	pub orientation: Option<Orientation>,
+}
+
+impl Metadata {
+	pub(crate) fn read(&mut self, decoder: &mut impl ImageDecoder) -> Result<(), Error> {
+		self.icc_profile = decoder.icc_profile()?;
+		self.exif = decoder.exif_metadata()?;
+		self.xmp = decoder.xmp_metadata()?;
+		self.iptc = decoder.iptc_metadata()?;
+		self.orientation = Some(decoder.orientation()?);
+		let color_type = decoder.color_type();
+		self.is_16bit = color_type.bytes_per_pixel() / color_type.channel_count() == 2;
+		Ok(())
+	}
+
+	pub(crate) fn into_decoded(self, format: Format, image: DynamicImage) -> DecodedImage {
+		DecodedImage {
+			image,
+			format,
+			icc_profile: self.icc_profile,
+			exif: self.exif,
+			xmp: self.xmp,
+			iptc: self.iptc,
+			gamma: self.gamma,
+			significant_bits: self.significant_bits,
+			is_16bit: self.is_16bit,
+			orientation: self.orientation.unwrap_or(Orientation::NoTransforms),
+		}
+	}
+}
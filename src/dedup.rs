@@ -0,0 +1,166 @@
+//! Near-duplicate grouping over perceptual hashes.
+//!
+//! `DedupIndex` finds pairs of images whose `PHash`es are within a Hamming distance, then groups them into connected
+//! components (so chains of near duplicates end up together) and picks one image per group to keep. Candidate pairs
+//! come from multi-index hashing: with the 64 bits split into `max_distance + 1` chunks, any two hashes within the
+//! distance agree exactly on at least one chunk, so only images sharing a chunk are compared.
+
+use std::{
+	collections::{HashMap, HashSet},
+	path::PathBuf,
+};
+
+use crate::phash::PHash;
+
+
+/// An image in the index, with what's needed to choose between duplicates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DedupEntry {
+	pub path: PathBuf,
+	pub hash: PHash,
+	pub width: u32,
+	pub height: u32,
+	/// Encoded size in bytes
+	pub file_size: u64,
+}
+
+impl DedupEntry {
+	fn pixels(&self) -> u64 {
+		u64::from(self.width) * u64::from(self.height)
+	}
+}
+
+
+/// A group of near duplicates, as indices into `DedupIndex::entries`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cluster {
+	/// The entry to keep: the highest resolution, then the least compressed (most bytes per pixel), then the first
+	/// inserted.
+	pub representative: usize,
+	/// Every entry in the group, including the representative, in insertion order.
+	pub members: Vec<usize>,
+}
+
+impl Cluster {
+	/// The members other than the representative, i.e. the ones to prune.
+	pub fn redundant(&self) -> impl Iterator<Item = usize> + '_ {
+		self.members.iter().copied().filter(|&i| i != self.representative)
+	}
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct DedupIndex {
+	entries: Vec<DedupEntry>,
+}
+
+impl DedupIndex {
+	pub fn new() -> DedupIndex {
+		DedupIndex::default()
+	}
+
+	/// Adds an entry, returning its index.
+	pub fn insert(&mut self, entry: DedupEntry) -> usize {
+		self.entries.push(entry);
+		self.entries.len() - 1
+	}
+
+	pub fn entries(&self) -> &[DedupEntry] {
+		&self.entries
+	}
+
+	/// Every pair of entries (lower index first) whose hashes are at most `max_distance` bits apart, sorted.
+	pub fn near_duplicates(&self, max_distance: u32) -> Vec<(usize, usize)> {
+		let chunks = (max_distance + 1).min(64) as usize;
+		let mut pairs = HashSet::new();
+		for chunk in 0..chunks {
+			let (start, end) = (chunk * 64 / chunks, (chunk + 1) * 64 / chunks);
+			let mask = if end - start == 64 { u64::MAX } else { ((1u64 << (end - start)) - 1) << start };
+			let mut buckets: HashMap<u64, Vec<usize>> = HashMap::new();
+			for (i, entry) in self.entries.iter().enumerate() {
+				buckets.entry(entry.hash.0 & mask).or_default().push(i);
+			}
+			for bucket in buckets.values() {
+				for (n, &a) in bucket.iter().enumerate() {
+					for &b in &bucket[n + 1..] {
+						if self.entries[a].hash.distance(self.entries[b].hash) <= max_distance {
+							pairs.insert((a, b));
+						}
+					}
+				}
+			}
+		}
+		let mut pairs: Vec<_> = pairs.into_iter().collect();
+		pairs.sort_unstable();
+		pairs
+	}
+
+	/// Groups near duplicates into connected components, returning only groups of two or more, ordered by their
+	/// first member. Entries not in any cluster have no near duplicates.
+	pub fn clusters(&self, max_distance: u32) -> Vec<Cluster> {
+		let mut sets = UnionFind::new(self.entries.len());
+		for (a, b) in self.near_duplicates(max_distance) {
+			sets.union(a, b);
+		}
+
+		let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+		for i in 0..self.entries.len() {
+			groups.entry(sets.find(i)).or_default().push(i);
+		}
+		let mut clusters: Vec<Cluster> = groups
+			.into_values()
+			.filter(|members| members.len() > 1)
+			.map(|members| Cluster {
+				representative: self.representative(&members),
+				members,
+			})
+			.collect();
+		clusters.sort_unstable_by_key(|cluster| cluster.members[0]);
+		clusters
+	}
+
+	fn representative(&self, members: &[usize]) -> usize {
+		// Bytes per pixel compared by cross multiplication to stay in integers
+		let better = |a: usize, b: usize| {
+			let (a, b) = (&self.entries[a], &self.entries[b]);
+			a.pixels().cmp(&b.pixels()).then((u128::from(a.file_size) * u128::from(b.pixels())).cmp(&(u128::from(b.file_size) * u128::from(a.pixels()))))
+		};
+		members.iter().copied().reduce(|best, i| if better(i, best).is_gt() { i } else { best }).expect("clusters aren't empty")
+	}
+}
+
+
+/// Disjoint sets with path halving and union by size.
+struct UnionFind {
+	parent: Vec<usize>,
+	size: Vec<usize>,
+}
+
+impl UnionFind {
+	fn new(n: usize) -> UnionFind {
+		UnionFind {
+			parent: (0..n).collect(),
+			size: vec![1; n],
+		}
+	}
+
+	fn find(&mut self, mut i: usize) -> usize {
+		while self.parent[i] != i {
+			self.parent[i] = self.parent[self.parent[i]];
+			i = self.parent[i];
+		}
+		i
+	}
+
+	fn union(&mut self, a: usize, b: usize) {
+		let (mut a, mut b) = (self.find(a), self.find(b));
+		if a == b {
+			return;
+		}
+		if self.size[a] < self.size[b] {
+			std::mem::swap(&mut a, &mut b);
+		}
+		self.parent[b] = a;
+		self.size[a] += self.size[b];
+	}
+}
@@ -0,0 +1,88 @@
+//! Digests of decoded pixels, for catching silent changes in decoder output between imgest versions.
+//!
+//! A `PixelDigest` is the SHA-256 of an image's color type, dimensions and samples, so it changes whenever decoding a
+//! file gives a different result, however small. Recording digests for a corpus with one release and checking them
+//! with the next (the `digests` binary does both) shows exactly which files decode differently before it goes into
+//! production.
+//!
+//! Manifests have one `ManifestEntry` per line, in the layout of `sha256sum`:
+//!
+//! ```text
+//! 9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08  a/b.png
+//! failed  a/c.png
+//! ```
+
+use std::{fmt, path::PathBuf, str::FromStr};
+
+use image::DynamicImage;
+use sha2::{Digest, Sha256};
+
+use crate::seen::{ContentHash, ParseContentHashError};
+
+
+/// SHA-256 of decoded pixels. Formats as (and parses from) 64 hex digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PixelDigest(pub [u8; 32]);
+
+impl PixelDigest {
+	/// Hashes the color type, width, height and samples, with samples wider than a byte little-endian whatever the
+	/// platform.
+	pub fn of(img: &DynamicImage) -> PixelDigest {
+		let color = img.color();
+		let mut hasher = Sha256::new();
+		hasher.update(format!("{color:?} {} {}\n", img.width(), img.height()));
+		let sample = usize::from(color.bytes_per_pixel() / color.channel_count());
+		if cfg!(target_endian = "big") && sample > 1 {
+			for sample in img.as_bytes().chunks_exact(sample) {
+				hasher.update(sample.iter().rev().copied().collect::<Vec<_>>());
+			}
+		} else {
+			hasher.update(img.as_bytes());
+		}
+		PixelDigest(hasher.finalize().into())
+	}
+}
+
+impl fmt::Display for PixelDigest {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		fmt::Display::fmt(&ContentHash(self.0), f)
+	}
+}
+
+impl FromStr for PixelDigest {
+	type Err = ParseContentHashError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		s.parse().map(|ContentHash(hash)| PixelDigest(hash))
+	}
+}
+
+
+/// A line of a digest manifest: the digest of a file decoded with default options, or `None` if it failed to decode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+	pub digest: Option<PixelDigest>,
+	pub path: PathBuf,
+}
+
+impl fmt::Display for ManifestEntry {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.digest {
+			Some(digest) => write!(f, "{digest}  {}", self.path.display()),
+			None => write!(f, "failed  {}", self.path.display()),
+		}
+	}
+}
+
+impl FromStr for ManifestEntry {
+	type Err = ParseContentHashError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (digest, path) = s.split_once("  ").ok_or(ParseContentHashError)?;
+		let digest = if digest == "failed" { None } else { Some(digest.parse()?) };
+		Ok(ManifestEntry {
+			digest,
+			path: PathBuf::from(path),
+		})
+	}
+}
@@ -0,0 +1,297 @@
+//! DLPack export for the Python bindings, with the `dlpack` feature: `imgest.load_tensor` decodes an image into a
+//! preprocessed tensor (see `convert::to_f32` and `convert::to_u8`) that PyTorch, JAX, CuPy or NumPy take over without
+//! a copy through the DLPack protocol:
+//!
+//! ```python
+//! tensor = imgest.load_tensor("photo.jpg", layout="chw", mean=[0.485, 0.456, 0.406], std=[0.229, 0.224, 0.225])
+//! batch_item = torch.from_dlpack(tensor)
+//! ```
+//!
+//! The tensor's buffer is handed over the first time it's exported; exporting it again raises `BufferError`.
+
+use std::{ffi::c_void, path::PathBuf};
+
+use image::DynamicImage;
+use pyo3::{
+	exceptions::{PyBufferError, PyValueError},
+	ffi,
+	prelude::*,
+};
+
+use crate::{
+	convert::{self, Normalization, TensorLayout},
+	python::{PyLoadOptions, to_py_err},
+};
+
+
+/// The name of a capsule holding a `DLManagedTensor` nobody has consumed yet; consumers rename it `used_dltensor`.
+const CAPSULE_NAME: &std::ffi::CStr = c"dltensor";
+
+/// `DLDeviceType::kDLCPU`
+const DL_CPU: i32 = 1;
+/// `DLDataTypeCode::kDLUInt`
+const DL_UINT: u8 = 1;
+/// `DLDataTypeCode::kDLFloat`
+const DL_FLOAT: u8 = 2;
+
+
+/// `DLDevice`
+#[repr(C)]
+struct DlDevice {
+	device_type: i32,
+	device_id: i32,
+}
+
+/// `DLDataType`
+#[repr(C)]
+struct DlDataType {
+	code: u8,
+	bits: u8,
+	lanes: u16,
+}
+
+/// `DLTensor`
+#[repr(C)]
+struct DlTensor {
+	data: *mut c_void,
+	device: DlDevice,
+	ndim: i32,
+	dtype: DlDataType,
+	shape: *mut i64,
+	/// Null for a compact row-major tensor
+	strides: *mut i64,
+	byte_offset: u64,
+}
+
+/// `DLManagedTensor`
+#[repr(C)]
+struct DlManagedTensor {
+	dl_tensor: DlTensor,
+	manager_ctx: *mut c_void,
+	deleter: Option<unsafe extern "C" fn(*mut DlManagedTensor)>,
+}
+
+
+enum TensorData {
+	U8(Vec<u8>),
+	F32(Vec<f32>),
+}
+
+impl TensorData {
+	fn dtype(&self) -> &'static str {
+		match self {
+			TensorData::U8(_) => "uint8",
+			TensorData::F32(_) => "float32",
+		}
+	}
+
+	fn dl_dtype(&self) -> DlDataType {
+		let (code, bits) = match self {
+			TensorData::U8(_) => (DL_UINT, 8),
+			TensorData::F32(_) => (DL_FLOAT, 32),
+		};
+		DlDataType { code, bits, lanes: 1 }
+	}
+
+	fn as_ptr(&self) -> *mut c_void {
+		match self {
+			TensorData::U8(samples) => samples.as_ptr() as *mut c_void,
+			TensorData::F32(samples) => samples.as_ptr() as *mut c_void,
+		}
+	}
+}
+
+
+/// A `DlManagedTensor` along with what it points at, boxed so the pointers stay put. The managed tensor comes first,
+/// so a pointer to it is a pointer to the whole.
+#[repr(C)]
+struct Managed {
+	tensor: DlManagedTensor,
+	shape: [i64; 3],
+	data: TensorData,
+}
+
+impl Managed {
+	fn into_raw(data: TensorData, shape: [i64; 3]) -> *mut DlManagedTensor {
+		let mut managed = Box::new(Managed {
+			tensor: DlManagedTensor {
+				dl_tensor: DlTensor {
+					data: data.as_ptr(),
+					device: DlDevice {
+						device_type: DL_CPU,
+						device_id: 0,
+					},
+					ndim: 3,
+					dtype: data.dl_dtype(),
+					shape: std::ptr::null_mut(),
+					strides: std::ptr::null_mut(),
+					byte_offset: 0,
+				},
+				manager_ctx: std::ptr::null_mut(),
+				deleter: Some(delete_managed),
+			},
+			shape,
+			data,
+		});
+		managed.tensor.dl_tensor.shape = managed.shape.as_mut_ptr();
+		Box::into_raw(managed).cast()
+	}
+}
+
+
+unsafe extern "C" fn delete_managed(tensor: *mut DlManagedTensor) {
+	// SAFETY: every `DlManagedTensor` with this deleter is the start of a `Managed` from `Managed::new`, and the
+	// consumer calls the deleter once
+	drop(unsafe { Box::from_raw(tensor.cast::<Managed>()) });
+}
+
+
+/// Frees the tensor of a capsule that was never consumed.
+unsafe extern "C" fn capsule_destructor(capsule: *mut ffi::PyObject) {
+	// SAFETY: called by Python with the capsule being destroyed; a consumed capsule has been renamed, and is left alone
+	unsafe {
+		if ffi::PyCapsule_IsValid(capsule, CAPSULE_NAME.as_ptr()) == 1 {
+			let tensor = ffi::PyCapsule_GetPointer(capsule, CAPSULE_NAME.as_ptr()).cast::<DlManagedTensor>();
+			if let Some(deleter) = (*tensor).deleter {
+				deleter(tensor);
+			}
+		}
+	}
+}
+
+
+/// A decoded, preprocessed image, exported through `__dlpack__`.
+#[pyclass(name = "Tensor", module = "imgest")]
+struct PyTensor {
+	data: Option<TensorData>,
+	shape: [i64; 3],
+	dtype: &'static str,
+}
+
+#[pymethods]
+impl PyTensor {
+	#[getter]
+	fn shape(&self) -> (i64, i64, i64) {
+		(self.shape[0], self.shape[1], self.shape[2])
+	}
+
+	#[getter]
+	fn dtype(&self) -> &'static str {
+		self.dtype
+	}
+
+	/// Hands the buffer over in a DLPack capsule. The data is on the CPU, so `stream` is ignored, and it's exported
+	/// as a legacy (unversioned) capsule, which every consumer accepts, whatever `max_version` asks for.
+	#[pyo3(signature = (*, stream = None, max_version = None, dl_device = None, copy = None))]
+	fn __dlpack__<'py>(
+		&mut self,
+		py: Python<'py>,
+		stream: Option<Bound<'py, PyAny>>,
+		max_version: Option<Bound<'py, PyAny>>,
+		dl_device: Option<(i32, i32)>,
+		copy: Option<bool>,
+	) -> PyResult<Bound<'py, PyAny>> {
+		let _ = (stream, max_version);
+		if dl_device.is_some_and(|device| device != (DL_CPU, 0)) {
+			return Err(PyBufferError::new_err("the tensor can only be exported to the CPU"));
+		}
+		if copy == Some(true) {
+			return Err(PyBufferError::new_err("the tensor is exported without copying"));
+		}
+		let Some(data) = self.data.take() else {
+			return Err(PyBufferError::new_err("the tensor was already exported"));
+		};
+		let tensor = Managed::into_raw(data, self.shape);
+		// SAFETY: `tensor` stays valid until the consumer calls its deleter, or `capsule_destructor` does
+		unsafe {
+			let capsule = ffi::PyCapsule_New(tensor.cast(), CAPSULE_NAME.as_ptr(), Some(capsule_destructor));
+			if capsule.is_null() {
+				delete_managed(tensor);
+			}
+			Bound::from_owned_ptr_or_err(py, capsule)
+		}
+	}
+
+	fn __dlpack_device__(&self) -> (i32, i32) {
+		(DL_CPU, 0)
+	}
+}
+
+
+fn to_tensor(img: &DynamicImage, layout: &str, dtype: &str, mean: Option<Vec<f32>>, std: Option<Vec<f32>>) -> PyResult<PyTensor> {
+	let layout = match layout {
+		"hwc" => TensorLayout::Hwc,
+		"chw" => TensorLayout::Chw,
+		value => return Err(PyValueError::new_err(format!("layout must be \"hwc\" or \"chw\", not {value:?}"))),
+	};
+	let channels = usize::from(img.color().channel_count());
+	let normalization = match (mean, std) {
+		(None, None) => Normalization::Unit,
+		(Some(mean), Some(std)) if [mean.len(), std.len()].iter().all(|&n| n == 1 || n == channels) => Normalization::MeanStd { mean, std },
+		(Some(_), Some(_)) => return Err(PyValueError::new_err(format!("mean and std need one value, or one per channel ({channels})"))),
+		_ => return Err(PyValueError::new_err("mean and std go together")),
+	};
+	let data = match dtype {
+		"float32" => TensorData::F32(convert::to_f32(img, layout, &normalization)),
+		"uint8" if normalization == Normalization::Unit => TensorData::U8(convert::to_u8(img, layout)),
+		"uint8" => return Err(PyValueError::new_err("uint8 tensors can't be normalized")),
+		value => return Err(PyValueError::new_err(format!("dtype must be \"float32\" or \"uint8\", not {value:?}"))),
+	};
+	let (width, height) = (i64::from(img.width()), i64::from(img.height()));
+	let channels = channels as i64;
+	let shape = match layout {
+		TensorLayout::Hwc => [height, width, channels],
+		TensorLayout::Chw => [channels, height, width],
+	};
+	Ok(PyTensor {
+		dtype: data.dtype(),
+		data: Some(data),
+		shape,
+	})
+}
+
+
+/// Decodes an image with `options` and converts it to a tensor in `layout` ("hwc" or "chw") of `dtype` ("float32",
+/// scaled to 0..1 and normalized with `mean` and `std` if given, or "uint8").
+#[pyfunction]
+#[pyo3(signature = (path, options = None, *, layout = "chw", dtype = "float32", mean = None, std = None))]
+#[allow(clippy::too_many_arguments)]
+fn load_tensor(
+	py: Python<'_>,
+	path: PathBuf,
+	options: Option<PyRef<'_, PyLoadOptions>>,
+	layout: &str,
+	dtype: &str,
+	mean: Option<Vec<f32>>,
+	std: Option<Vec<f32>>,
+) -> PyResult<PyTensor> {
+	let options = options.map(|options| options.options.clone()).unwrap_or_default();
+	let (_, img) = py.detach(|| crate::load_image_with_options(path, &options)).map_err(to_py_err)?;
+	to_tensor(&img, layout, dtype, mean, std)
+}
+
+
+#[pyfunction]
+#[pyo3(signature = (data, options = None, *, layout = "chw", dtype = "float32", mean = None, std = None))]
+#[allow(clippy::too_many_arguments)]
+fn load_tensor_from_bytes(
+	py: Python<'_>,
+	data: &[u8],
+	options: Option<PyRef<'_, PyLoadOptions>>,
+	layout: &str,
+	dtype: &str,
+	mean: Option<Vec<f32>>,
+	std: Option<Vec<f32>>,
+) -> PyResult<PyTensor> {
+	let options = options.map(|options| options.options.clone()).unwrap_or_default();
+	let (_, img) = py.detach(|| crate::load_image_from_bytes_with_options(data, &options)).map_err(to_py_err)?;
+	to_tensor(&img, layout, dtype, mean, std)
+}
+
+
+pub(crate) fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
+	m.add_class::<PyTensor>()?;
+	m.add_function(wrap_pyfunction!(load_tensor, m)?)?;
+	m.add_function(wrap_pyfunction!(load_tensor_from_bytes, m)?)?;
+	Ok(())
+}
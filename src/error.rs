@@ -1,11 +1,19 @@
 use std::error::Error as StdError;
 
+use image::{
+	ImageError, ImageResult,
+	error::{LimitError, LimitErrorKind, ParameterError, ParameterErrorKind},
+};
+
 pub enum Error {
 	UnsupportedFormat,
 	Io(std::io::Error),
 	Animated,
+	MultiPage,
 	PngDecoding(png::DecodingError),
-	TooBig, // Image exceeds hard limits
+	TooBig, // Image exceeds `LoadOptions::max_pixels`, `max_file_bytes`, `max_alloc_bytes` or `max_expansion_ratio`
+	NonFinite(u64), // Float pixels with NaN or infinite samples, under `NonFinite::Reject`
+	Cancelled, // `LoadOptions::cancel_token` was cancelled, or its deadline passed, during decoding
 	Decoding(image::error::DecodingError),
 	Parameter(image::error::ParameterError),
 	Limits(image::error::LimitError),
@@ -22,6 +30,7 @@ impl From<png::DecodingError> for Error {
 	fn from(err: png::DecodingError) -> Self {
 		match err {
 			png::DecodingError::IoError(io_err) => Error::Io(io_err),
+			png::DecodingError::LimitsExceeded => Error::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory)),
 			_ => Error::PngDecoding(err),
 		}
 	}
@@ -48,8 +57,11 @@ impl std::fmt::Display for Error {
 			Error::UnsupportedFormat => write!(f, "unsupported image format"),
 			Error::Io(err) => write!(f, "I/O error: {}", err),
 			Error::Animated => write!(f, "animated images are not supported"),
+			Error::MultiPage => write!(f, "multi-page images are not supported"),
 			Error::PngDecoding(err) => write!(f, "PNG decoding error: {}", err),
 			Error::TooBig => write!(f, "image exceeds size limits"),
+			Error::NonFinite(pixels) => write!(f, "{} pixels have NaN or infinite samples", pixels),
+			Error::Cancelled => write!(f, "decoding was cancelled"),
 			Error::Decoding(err) => write!(f, "decoding error: {}", err),
 			Error::Parameter(err) => write!(f, "parameter error: {}", err),
 			Error::Limits(err) => write!(f, "limits error: {}", err),
@@ -63,3 +75,29 @@ impl std::fmt::Debug for Error {
 		std::fmt::Display::fmt(self, f)
 	}
 }
+
+
+/// `bytes` as a buffer length, or a limits error if no buffer that big can be allocated on this target (anything past
+/// `isize::MAX`, which on 32-bit targets is 2 GiB).
+pub(crate) fn buffer_len(bytes: u64) -> ImageResult<usize> {
+	usize::try_from(bytes)
+		.ok()
+		.filter(|&len| isize::try_from(len).is_ok())
+		.ok_or_else(|| ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory)))
+}
+
+
+/// The length of a `width` by `height` buffer of `bytes_per_pixel`, computed without overflowing.
+pub(crate) fn pixel_buffer_len(width: u32, height: u32, bytes_per_pixel: u8) -> ImageResult<usize> {
+	buffer_len((u64::from(width) * u64::from(height)).saturating_mul(u64::from(bytes_per_pixel)))
+}
+
+
+/// Checks the buffer passed to `ImageDecoder::read_image` is `total_bytes` long, failing with a dimension mismatch
+/// rather than panicking.
+pub(crate) fn check_buffer(buf: &[u8], total_bytes: u64) -> ImageResult<()> {
+	if u64::try_from(buf.len()) != Ok(total_bytes) {
+		return Err(ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::DimensionMismatch)));
+	}
+	Ok(())
+}
@@ -0,0 +1,605 @@
+//! Hardened EXIF (TIFF IFD) parsing.
+//!
+//! EXIF blobs come straight from untrusted files, and MakerNotes in particular are full of garbage, so every read is
+//! bounds checked, IFD chains are cycle checked, and the value parsers never panic. Nothing here depends on locale:
+//! numbers are only ever produced from the binary rationals or parsed with fixed ASCII rules.
+
+use std::{collections::HashSet, path::Path};
+
+use image::metadata::Orientation;
+
+use crate::audit::json_string;
+
+
+/// Hard cap on the number of entries we accept in a single IFD; real files have a few dozen.
+const MAX_ENTRIES_PER_IFD: u16 = 1024;
+
+/// Hard cap on the number of IFDs we follow, guarding against long (or looping) chains.
+const MAX_IFDS: usize = 32;
+
+
+pub mod tags {
+	pub const IMAGE_WIDTH: u16 = 0x0100;
+	pub const IMAGE_HEIGHT: u16 = 0x0101;
+	pub const MAKE: u16 = 0x010F;
+	pub const MODEL: u16 = 0x0110;
+	pub const ORIENTATION: u16 = 0x0112;
+	pub const X_RESOLUTION: u16 = 0x011A;
+	pub const Y_RESOLUTION: u16 = 0x011B;
+	pub const RESOLUTION_UNIT: u16 = 0x0128;
+	pub const SOFTWARE: u16 = 0x0131;
+	pub const DATE_TIME: u16 = 0x0132;
+	pub const EXIF_IFD_POINTER: u16 = 0x8769;
+	pub const GPS_IFD_POINTER: u16 = 0x8825;
+	pub const INTEROP_IFD_POINTER: u16 = 0xA005;
+	pub const EXPOSURE_TIME: u16 = 0x829A;
+	pub const F_NUMBER: u16 = 0x829D;
+	pub const ISO_SPEED: u16 = 0x8827;
+	pub const DATE_TIME_ORIGINAL: u16 = 0x9003;
+	pub const DATE_TIME_DIGITIZED: u16 = 0x9004;
+	pub const FOCAL_LENGTH: u16 = 0x920A;
+	pub const MAKER_NOTE: u16 = 0x927C;
+	pub const PIXEL_X_DIMENSION: u16 = 0xA002;
+	pub const PIXEL_Y_DIMENSION: u16 = 0xA003;
+	pub const LENS_MAKE: u16 = 0xA433;
+	pub const LENS_MODEL: u16 = 0xA434;
+	pub const GPS_LATITUDE_REF: u16 = 0x0001;
+	pub const GPS_LATITUDE: u16 = 0x0002;
+	pub const GPS_LONGITUDE_REF: u16 = 0x0003;
+	pub const GPS_LONGITUDE: u16 = 0x0004;
+	pub const GPS_ALTITUDE_REF: u16 = 0x0005;
+	pub const GPS_ALTITUDE: u16 = 0x0006;
+}
+
+
+/// ExifTool's names for the tags we know, in the order `to_exiftool_json` writes them.
+const EXIFTOOL_NAMES: &[(Ifd, u16, &str)] = &[
+	(Ifd::Primary, tags::IMAGE_WIDTH, "ImageWidth"),
+	(Ifd::Primary, tags::IMAGE_HEIGHT, "ImageHeight"),
+	(Ifd::Primary, tags::MAKE, "Make"),
+	(Ifd::Primary, tags::MODEL, "Model"),
+	(Ifd::Primary, tags::ORIENTATION, "Orientation"),
+	(Ifd::Primary, tags::X_RESOLUTION, "XResolution"),
+	(Ifd::Primary, tags::Y_RESOLUTION, "YResolution"),
+	(Ifd::Primary, tags::RESOLUTION_UNIT, "ResolutionUnit"),
+	(Ifd::Primary, tags::SOFTWARE, "Software"),
+	(Ifd::Primary, tags::DATE_TIME, "ModifyDate"),
+	(Ifd::Exif, tags::EXPOSURE_TIME, "ExposureTime"),
+	(Ifd::Exif, tags::F_NUMBER, "FNumber"),
+	(Ifd::Exif, tags::ISO_SPEED, "ISO"),
+	(Ifd::Exif, tags::DATE_TIME_ORIGINAL, "DateTimeOriginal"),
+	(Ifd::Exif, tags::DATE_TIME_DIGITIZED, "CreateDate"),
+	(Ifd::Exif, tags::FOCAL_LENGTH, "FocalLength"),
+	(Ifd::Exif, tags::PIXEL_X_DIMENSION, "ExifImageWidth"),
+	(Ifd::Exif, tags::PIXEL_Y_DIMENSION, "ExifImageHeight"),
+	(Ifd::Exif, tags::LENS_MAKE, "LensMake"),
+	(Ifd::Exif, tags::LENS_MODEL, "LensModel"),
+	(Ifd::Gps, tags::GPS_LATITUDE_REF, "GPSLatitudeRef"),
+	(Ifd::Gps, tags::GPS_LATITUDE, "GPSLatitude"),
+	(Ifd::Gps, tags::GPS_LONGITUDE_REF, "GPSLongitudeRef"),
+	(Ifd::Gps, tags::GPS_LONGITUDE, "GPSLongitude"),
+	(Ifd::Gps, tags::GPS_ALTITUDE_REF, "GPSAltitudeRef"),
+	(Ifd::Gps, tags::GPS_ALTITUDE, "GPSAltitude"),
+];
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+	LittleEndian,
+	BigEndian,
+}
+
+
+/// Which IFD an entry was found in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Ifd {
+	/// The primary image's IFD
+	Primary,
+	/// IFD1, which describes the embedded thumbnail
+	Thumbnail,
+	Exif,
+	Gps,
+	Interop,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rational {
+	pub numerator: u32,
+	pub denominator: u32,
+}
+
+
+impl Rational {
+	/// Returns `None` for a zero denominator rather than producing infinities or NaN.
+	pub fn to_f64(self) -> Option<f64> {
+		(self.denominator != 0).then(|| f64::from(self.numerator) / f64::from(self.denominator))
+	}
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SRational {
+	pub numerator: i32,
+	pub denominator: i32,
+}
+
+
+impl SRational {
+	/// Returns `None` for a zero denominator rather than producing infinities or NaN.
+	pub fn to_f64(self) -> Option<f64> {
+		(self.denominator != 0).then(|| f64::from(self.numerator) / f64::from(self.denominator))
+	}
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+	Byte(Vec<u8>),
+	Ascii(Vec<u8>),
+	Short(Vec<u16>),
+	Long(Vec<u32>),
+	Rational(Vec<Rational>),
+	SByte(Vec<i8>),
+	Undefined(Vec<u8>),
+	SShort(Vec<i16>),
+	SLong(Vec<i32>),
+	SRational(Vec<SRational>),
+	Float(Vec<f32>),
+	Double(Vec<f64>),
+}
+
+
+impl Value {
+	/// The first component as an unsigned integer, for the integer types.
+	pub fn as_u32(&self) -> Option<u32> {
+		match self {
+			Value::Byte(v) => v.first().map(|&x| u32::from(x)),
+			Value::Short(v) => v.first().map(|&x| u32::from(x)),
+			Value::Long(v) => v.first().copied(),
+			Value::SByte(v) => v.first().and_then(|&x| u32::try_from(x).ok()),
+			Value::SShort(v) => v.first().and_then(|&x| u32::try_from(x).ok()),
+			Value::SLong(v) => v.first().and_then(|&x| u32::try_from(x).ok()),
+			_ => None,
+		}
+	}
+
+	/// The first component as a finite float, for the numeric types.
+	pub fn as_f64(&self) -> Option<f64> {
+		let value = match self {
+			Value::Rational(v) => v.first()?.to_f64()?,
+			Value::SRational(v) => v.first()?.to_f64()?,
+			Value::Float(v) => f64::from(*v.first()?),
+			Value::Double(v) => *v.first()?,
+			_ => f64::from(self.as_u32()?),
+		};
+		value.is_finite().then_some(value)
+	}
+
+	pub fn as_rationals(&self) -> Option<&[Rational]> {
+		match self {
+			Value::Rational(v) => Some(v),
+			_ => None,
+		}
+	}
+
+	/// ASCII values as a string. See `parse_ascii`.
+	pub fn as_ascii(&self) -> Option<&str> {
+		match self {
+			Value::Ascii(v) => parse_ascii(v),
+			_ => None,
+		}
+	}
+
+	/// ASCII values as an EXIF date-time. See `DateTime::parse`.
+	pub fn as_datetime(&self) -> Option<DateTime> {
+		match self {
+			Value::Ascii(v) => DateTime::parse(v),
+			_ => None,
+		}
+	}
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+	pub ifd: Ifd,
+	pub tag: u16,
+	pub value: Value,
+}
+
+
+/// A parsed EXIF blob: every well-formed entry of the primary, thumbnail, Exif, GPS and Interop IFDs.
+///
+/// Malformed entries are skipped rather than failing the whole blob.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exif {
+	pub byte_order: ByteOrder,
+	pub entries: Vec<Entry>,
+}
+
+
+impl Exif {
+	/// Parses a TIFF-structured EXIF blob, with or without the `Exif\0\0` prefix used in JPEG APP1 segments.
+	///
+	/// Returns `None` only if the TIFF header itself is unusable.
+	pub fn parse(data: &[u8]) -> Option<Exif> {
+		let data = data.strip_prefix(b"Exif\0\0").unwrap_or(data);
+		let reader = TiffReader::new(data)?;
+		let first_ifd = reader.u32(4)?;
+
+		let mut entries = Vec::new();
+		let mut visited = HashSet::new();
+		let mut pending = vec![(Ifd::Primary, first_ifd)];
+
+		while let Some((ifd, offset)) = pending.pop() {
+			if visited.len() >= MAX_IFDS || !visited.insert(offset) {
+				continue;
+			}
+			let Some((ifd_entries, next)) = reader.read_ifd(offset) else {
+				continue;
+			};
+
+			// Only IFD0 links to the thumbnail IFD; later links in the chain are ignored.
+			if ifd == Ifd::Primary && next != 0 {
+				pending.push((Ifd::Thumbnail, next));
+			}
+
+			for (tag, value) in ifd_entries {
+				let sub_ifd = match (ifd, tag) {
+					(Ifd::Primary, tags::EXIF_IFD_POINTER) => Some(Ifd::Exif),
+					(Ifd::Primary, tags::GPS_IFD_POINTER) => Some(Ifd::Gps),
+					(Ifd::Exif, tags::INTEROP_IFD_POINTER) => Some(Ifd::Interop),
+					_ => None,
+				};
+				if let Some(sub_ifd) = sub_ifd {
+					if let Some(offset) = value.as_u32() {
+						pending.push((sub_ifd, offset));
+					}
+					continue;
+				}
+				entries.push(Entry { ifd, tag, value });
+			}
+		}
+
+		Some(Exif {
+			byte_order: reader.byte_order,
+			entries,
+		})
+	}
+
+	pub fn get(&self, ifd: Ifd, tag: u16) -> Option<&Value> {
+		self.entries.iter().find(|e| e.ifd == ifd && e.tag == tag).map(|e| &e.value)
+	}
+
+	/// The commonly used fields, typed. Fields whose tags are missing or unreadable are `None`.
+	pub fn fields(&self) -> ExifFields {
+		let ascii = |ifd, tag| self.get(ifd, tag).and_then(Value::as_ascii).map(str::to_string);
+		let number = |tag| self.get(Ifd::Exif, tag).and_then(Value::as_f64);
+		ExifFields {
+			orientation: self
+				.get(Ifd::Primary, tags::ORIENTATION)
+				.and_then(Value::as_u32)
+				.and_then(|orientation| Orientation::from_exif(u8::try_from(orientation).ok()?)),
+			date_time_original: self.get(Ifd::Exif, tags::DATE_TIME_ORIGINAL).and_then(Value::as_datetime),
+			make: ascii(Ifd::Primary, tags::MAKE),
+			model: ascii(Ifd::Primary, tags::MODEL),
+			lens_make: ascii(Ifd::Exif, tags::LENS_MAKE),
+			lens_model: ascii(Ifd::Exif, tags::LENS_MODEL),
+			exposure_time: number(tags::EXPOSURE_TIME),
+			f_number: number(tags::F_NUMBER),
+			iso: self.get(Ifd::Exif, tags::ISO_SPEED).and_then(Value::as_u32),
+			focal_length: number(tags::FOCAL_LENGTH),
+			gps: self.gps_position(),
+		}
+	}
+
+	fn gps_position(&self) -> Option<GpsPosition> {
+		let signed = |tag, ref_tag, negative: &str| {
+			let value = degrees(self.get(Ifd::Gps, tag)?.as_rationals()?)?;
+			let reference = self.get(Ifd::Gps, ref_tag).and_then(Value::as_ascii);
+			Some(if reference == Some(negative) { -value } else { value })
+		};
+		let altitude = self.get(Ifd::Gps, tags::GPS_ALTITUDE).and_then(Value::as_f64).map(|altitude| {
+			// A reference of 1 means below sea level
+			let below = self.get(Ifd::Gps, tags::GPS_ALTITUDE_REF).and_then(Value::as_u32) == Some(1);
+			if below { -altitude } else { altitude }
+		});
+		Some(GpsPosition {
+			latitude: signed(tags::GPS_LATITUDE, tags::GPS_LATITUDE_REF, "S")?,
+			longitude: signed(tags::GPS_LONGITUDE, tags::GPS_LONGITUDE_REF, "W")?,
+			altitude,
+		})
+	}
+
+	/// The tags in `tags` as one object of `exiftool -j -n` output, keyed by ExifTool's tag names, for tooling built
+	/// around ExifTool dumps. Like `-n`, values aren't print converted: enumerations are numbers, rationals are
+	/// decimals (to ExifTool's 15 significant digits), and GPS coordinates are unsigned decimal degrees with the
+	/// hemisphere in the `Ref` tags. ExifTool writes a JSON array of these objects, one per file. Tags we don't know
+	/// and values we can't read are left out, as are the thumbnail's tags, which ExifTool only shows with `-a`.
+	pub fn to_exiftool_json(&self, source_file: &Path) -> String {
+		let mut out = format!("{{\"SourceFile\":{}", json_string(&source_file.to_string_lossy()));
+		for &(ifd, tag, name) in EXIFTOOL_NAMES {
+			let Some(value) = self.get(ifd, tag) else {
+				continue;
+			};
+			let value = match (ifd, tag) {
+				(Ifd::Gps, tags::GPS_LATITUDE | tags::GPS_LONGITUDE) => value.as_rationals().and_then(degrees).map(exiftool_number),
+				_ if matches!(value, Value::Ascii(_)) => value.as_ascii().map(json_string),
+				_ => value.as_f64().map(exiftool_number),
+			};
+			if let Some(value) = value {
+				out.push_str(&format!(",{}:{}", json_string(name), value));
+			}
+		}
+		out.push('}');
+		out
+	}
+}
+
+
+/// The commonly used EXIF fields, as read by `Exif::fields`. Everything else is in `Exif::entries`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExifFields {
+	pub orientation: Option<Orientation>,
+	pub date_time_original: Option<DateTime>,
+	pub make: Option<String>,
+	pub model: Option<String>,
+	pub lens_make: Option<String>,
+	pub lens_model: Option<String>,
+	/// In seconds
+	pub exposure_time: Option<f64>,
+	pub f_number: Option<f64>,
+	pub iso: Option<u32>,
+	/// In millimeters
+	pub focal_length: Option<f64>,
+	/// Only when both the latitude and the longitude are readable
+	pub gps: Option<GpsPosition>,
+}
+
+
+/// A GPS position in signed decimal degrees: south latitudes and west longitudes are negative.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpsPosition {
+	pub latitude: f64,
+	pub longitude: f64,
+	/// In meters, negative below sea level
+	pub altitude: Option<f64>,
+}
+
+
+/// Degrees, minutes and seconds as decimal degrees.
+fn degrees(dms: &[Rational]) -> Option<f64> {
+	let [d, m, s] = dms else {
+		return None;
+	};
+	let degrees = d.to_f64()? + m.to_f64()? / 60.0 + s.to_f64()? / 3600.0;
+	degrees.is_finite().then_some(degrees)
+}
+
+
+/// Formats a number like Perl, which ExifTool is written in: to 15 significant digits, without trailing zeros.
+fn exiftool_number(value: f64) -> String {
+	let rounded: f64 = format!("{value:.14e}").parse().unwrap_or(value);
+	format!("{rounded}")
+}
+
+
+/// Parses an EXIF ASCII value: trailing NULs and whitespace are dropped, and the result must be printable ASCII.
+///
+/// Returns `None` for empty strings and for anything containing non-ASCII or control bytes, which in practice is
+/// always binary garbage mislabeled as ASCII.
+pub fn parse_ascii(bytes: &[u8]) -> Option<&str> {
+	let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+	let bytes = &bytes[..end];
+	if !bytes.iter().all(|&b| (0x20..0x7F).contains(&b)) {
+		return None;
+	}
+	let s = std::str::from_utf8(bytes).ok()?.trim();
+	(!s.is_empty()).then_some(s)
+}
+
+
+/// An EXIF date-time ("YYYY:MM:DD HH:MM:SS"). Time zone information is stored in separate tags and isn't applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+	pub year: u16,
+	pub month: u8,
+	pub day: u8,
+	pub hour: u8,
+	pub minute: u8,
+	pub second: u8,
+}
+
+
+impl DateTime {
+	/// Parses an EXIF date-time.
+	///
+	/// Tolerates the common deviations seen in the wild: `-` or `/` as the date separator, `T` between date and time,
+	/// a missing seconds field, and trailing junk after the seconds. Returns `None` for the all-blank/all-zero
+	/// "unknown" placeholders and for out of range fields.
+	pub fn parse(bytes: &[u8]) -> Option<DateTime> {
+		let s = parse_ascii(bytes)?.as_bytes();
+		let mut pos = 0;
+
+		let year = digits(s, &mut pos, 4)?;
+		separator(s, &mut pos, b":-/")?;
+		let month = digits(s, &mut pos, 2)?;
+		separator(s, &mut pos, b":-/")?;
+		let day = digits(s, &mut pos, 2)?;
+		separator(s, &mut pos, b" T")?;
+		let hour = digits(s, &mut pos, 2)?;
+		separator(s, &mut pos, b":")?;
+		let minute = digits(s, &mut pos, 2)?;
+		let second = if separator(s, &mut pos, b":").is_some() { digits(s, &mut pos, 2)? } else { 0 };
+
+		let valid = (1..=12).contains(&month) && (1..=31).contains(&day) && hour <= 23 && minute <= 59 && second <= 60 && year > 0;
+		valid.then_some(DateTime {
+			year,
+			month: month as u8,
+			day: day as u8,
+			hour: hour as u8,
+			minute: minute as u8,
+			second: second as u8,
+		})
+	}
+}
+
+
+impl std::fmt::Display for DateTime {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"{:04}:{:02}:{:02} {:02}:{:02}:{:02}",
+			self.year, self.month, self.day, self.hour, self.minute, self.second
+		)
+	}
+}
+
+
+fn digits(s: &[u8], pos: &mut usize, count: usize) -> Option<u16> {
+	let field = s.get(*pos..*pos + count)?;
+	let mut value = 0u16;
+	for &b in field {
+		if !b.is_ascii_digit() {
+			return None;
+		}
+		value = value * 10 + u16::from(b - b'0');
+	}
+	*pos += count;
+	Some(value)
+}
+
+
+fn separator(s: &[u8], pos: &mut usize, allowed: &[u8]) -> Option<()> {
+	let b = s.get(*pos)?;
+	allowed.contains(b).then(|| *pos += 1)
+}
+
+
+/// Bounds-checked reads over a TIFF structure.
+pub(crate) struct TiffReader<'a> {
+	data: &'a [u8],
+	byte_order: ByteOrder,
+}
+
+
+impl<'a> TiffReader<'a> {
+	pub(crate) fn new(data: &'a [u8]) -> Option<Self> {
+		let byte_order = match data.get(0..4)? {
+			b"II*\0" => ByteOrder::LittleEndian,
+			b"MM\0*" => ByteOrder::BigEndian,
+			_ => return None,
+		};
+		Some(TiffReader { data, byte_order })
+	}
+
+	fn bytes<const N: usize>(&self, offset: u32) -> Option<[u8; N]> {
+		let start = usize::try_from(offset).ok()?;
+		self.data.get(start..start.checked_add(N)?)?.try_into().ok()
+	}
+
+	pub(crate) fn u16(&self, offset: u32) -> Option<u16> {
+		let b = self.bytes::<2>(offset)?;
+		Some(match self.byte_order {
+			ByteOrder::LittleEndian => u16::from_le_bytes(b),
+			ByteOrder::BigEndian => u16::from_be_bytes(b),
+		})
+	}
+
+	pub(crate) fn u32(&self, offset: u32) -> Option<u32> {
+		let b = self.bytes::<4>(offset)?;
+		Some(match self.byte_order {
+			ByteOrder::LittleEndian => u32::from_le_bytes(b),
+			ByteOrder::BigEndian => u32::from_be_bytes(b),
+		})
+	}
+
+	fn u64(&self, offset: u32) -> Option<u64> {
+		let b = self.bytes::<8>(offset)?;
+		Some(match self.byte_order {
+			ByteOrder::LittleEndian => u64::from_le_bytes(b),
+			ByteOrder::BigEndian => u64::from_be_bytes(b),
+		})
+	}
+
+	/// Reads the entries of the IFD at `offset`, skipping malformed ones, and returns them with the next IFD offset.
+	pub(crate) fn read_ifd(&self, offset: u32) -> Option<(Vec<(u16, Value)>, u32)> {
+		let count = self.u16(offset)?;
+		if count > MAX_ENTRIES_PER_IFD {
+			return None;
+		}
+
+		let mut entries = Vec::with_capacity(usize::from(count));
+		for i in 0..u32::from(count) {
+			let entry_offset = offset.checked_add(2 + i * 12)?;
+			if let Some(entry) = self.read_entry(entry_offset) {
+				entries.push(entry);
+			}
+		}
+		let next = self.u32(offset.checked_add(2 + u32::from(count) * 12)?).unwrap_or(0);
+		Some((entries, next))
+	}
+
+	fn read_entry(&self, offset: u32) -> Option<(u16, Value)> {
+		let tag = self.u16(offset)?;
+		let kind = self.u16(offset.checked_add(2)?)?;
+		let count = self.u32(offset.checked_add(4)?)?;
+		let size = match kind {
+			1 | 2 | 6 | 7 => 1,
+			3 | 8 => 2,
+			4 | 9 | 11 => 4,
+			5 | 10 | 12 => 8,
+			_ => return None,
+		};
+
+		// Values of 4 bytes or less are stored inline, larger ones at an offset
+		let len = count.checked_mul(size)?;
+		let value_offset = if len <= 4 {
+			offset.checked_add(8)?
+		} else {
+			self.u32(offset.checked_add(8)?)?
+		};
+		// Fitting in u32 means the per-component offsets below can't overflow
+		let end = value_offset.checked_add(len)? as usize;
+		if end > self.data.len() {
+			return None;
+		}
+
+		let at = |i: u32| value_offset + i * size;
+		let value = match kind {
+			1 => Value::Byte(self.data[value_offset as usize..end].to_vec()),
+			2 => Value::Ascii(self.data[value_offset as usize..end].to_vec()),
+			7 => Value::Undefined(self.data[value_offset as usize..end].to_vec()),
+			6 => Value::SByte(self.data[value_offset as usize..end].iter().map(|&b| b as i8).collect()),
+			3 => Value::Short((0..count).map(|i| self.u16(at(i))).collect::<Option<_>>()?),
+			8 => Value::SShort((0..count).map(|i| self.u16(at(i)).map(|v| v as i16)).collect::<Option<_>>()?),
+			4 => Value::Long((0..count).map(|i| self.u32(at(i))).collect::<Option<_>>()?),
+			9 => Value::SLong((0..count).map(|i| self.u32(at(i)).map(|v| v as i32)).collect::<Option<_>>()?),
+			11 => Value::Float((0..count).map(|i| self.u32(at(i)).map(f32::from_bits)).collect::<Option<_>>()?),
+			12 => Value::Double((0..count).map(|i| self.u64(at(i)).map(f64::from_bits)).collect::<Option<_>>()?),
+			5 => Value::Rational(
+				(0..count)
+					.map(|i| {
+						Some(Rational {
+							numerator: self.u32(at(i))?,
+							denominator: self.u32(at(i) + 4)?,
+						})
+					})
+					.collect::<Option<_>>()?,
+			),
+			10 => Value::SRational(
+				(0..count)
+					.map(|i| {
+						Some(SRational {
+							numerator: self.u32(at(i))? as i32,
+							denominator: self.u32(at(i) + 4)? as i32,
+						})
+					})
+					.collect::<Option<_>>()?,
+			),
+			_ => unreachable!(),
+		};
+		Some((tag, value))
+	}
+}
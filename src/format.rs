@@ -0,0 +1,96 @@
+use image::ImageFormat;
+
+
+/// HEIF major brands (ISO/IEC 23008-12): HEVC coded stills and sequences, and the generic MIAF brands.
+const HEIF_BRANDS: &[&[u8; 4]] = &[b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx", b"mif1", b"msf1"];
+
+/// A bare JPEG XL codestream, and the ISOBMFF based container.
+const JXL_CODESTREAM: &[u8] = b"\xFF\x0A";
+const JXL_CONTAINER: &[u8] = b"\0\0\0\x0CJXL \r\n\x87\n";
+
+/// The JP2 signature box, and the start of a bare JPEG 2000 codestream (SOC then SIZ markers).
+const JP2_SIGNATURE: &[u8] = b"\0\0\0\x0CjP  \r\n\x87\n";
+const J2K_CODESTREAM: &[u8] = b"\xFF\x4F\xFF\x51";
+
+/// The KTX 2.0 file identifier.
+pub(crate) const KTX2_IDENTIFIER: &[u8; 12] = b"\xABKTX 20\xBB\r\n\x1A\n";
+
+/// The PDF header, which some writers precede with junk; we don't look past the start.
+const PDF_HEADER: &[u8] = b"%PDF-";
+
+/// How SVG documents start, after an optional BOM and whitespace. An XML declaration doesn't guarantee SVG, but no
+/// other format we decode is XML, so anything else fails to parse as SVG instead of as an unknown format.
+const SVG_STARTS: &[&[u8]] = &[b"<svg", b"<?xml", b"<!DOCTYPE svg", b"<!--"];
+
+
+/// The format of a decoded image.
+///
+/// `image::ImageFormat` only has variants for formats the `image` crate itself knows about, so formats we decode
+/// through other libraries get their own variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Format {
+	Image(ImageFormat),
+	Heif,
+	Jxl,
+	Svg,
+	/// A camera RAW file, decoded from its embedded JPEG preview rather than the sensor data. Only produced with the
+	/// `raw` feature; `guess` reports these as TIFF, since telling them apart takes more than the first few bytes.
+	RawPreview,
+	/// A KTX2 texture. DDS textures are `Image(ImageFormat::Dds)`.
+	Ktx2,
+	/// JPEG 2000, either a JP2 file or a bare codestream
+	Jpeg2000,
+	/// A multi-picture JPEG (CIPA DC-007), as written by stereo and some burst mode cameras, decoded to its primary
+	/// image. `guess` reports these as JPEG, since the MP index can come after kilobytes of EXIF.
+	Mpo,
+	/// A PDF, decoded to its largest embedded image, with the `pdf` feature
+	Pdf,
+}
+
+impl Format {
+	/// Guesses the format from the first bytes of a file; 16 bytes are enough for every format we recognize.
+	pub fn guess(buf: &[u8]) -> Option<Format> {
+		if buf.get(4..8) == Some(b"ftyp") && buf.get(8..12).is_some_and(|brand| HEIF_BRANDS.iter().any(|b| &b[..] == brand)) {
+			return Some(Format::Heif);
+		}
+		if buf.starts_with(JXL_CODESTREAM) || buf.starts_with(JXL_CONTAINER) {
+			return Some(Format::Jxl);
+		}
+		if buf.starts_with(JP2_SIGNATURE) || buf.starts_with(J2K_CODESTREAM) {
+			return Some(Format::Jpeg2000);
+		}
+		if buf.starts_with(KTX2_IDENTIFIER) {
+			return Some(Format::Ktx2);
+		}
+		if buf.starts_with(PDF_HEADER) {
+			return Some(Format::Pdf);
+		}
+		let text = buf.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(buf);
+		let text = &text[text.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(text.len())..];
+		if SVG_STARTS.iter().any(|start| text.starts_with(start)) {
+			return Some(Format::Svg);
+		}
+		image::guess_format(buf).ok().map(Format::Image)
+	}
+
+	/// The equivalent `image::ImageFormat`, if there is one.
+	pub fn image_format(self) -> Option<ImageFormat> {
+		match self {
+			Format::Image(format) => Some(format),
+			Format::Heif | Format::Jxl | Format::Svg | Format::RawPreview | Format::Ktx2 | Format::Jpeg2000 | Format::Mpo | Format::Pdf => None,
+		}
+	}
+}
+
+impl From<ImageFormat> for Format {
+	fn from(format: ImageFormat) -> Self {
+		Format::Image(format)
+	}
+}
+
+impl PartialEq<ImageFormat> for Format {
+	fn eq(&self, other: &ImageFormat) -> bool {
+		self.image_format() == Some(*other)
+	}
+}
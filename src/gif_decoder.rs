@@ -0,0 +1,113 @@
+use std::io::{BufRead, Seek};
+
+use image::{
+	ColorType, ImageDecoder, ImageError, ImageFormat, ImageResult, Limits,
+	error::DecodingError,
+};
+
+use crate::error::Error;
+
+
+/// Decoder for static GIFs.
+///
+/// Decoding the first frame happens up front, since the only way to know whether a GIF is animated is to get past
+/// its first frame and look for a second one. The first frame is composited onto a transparent canvas the size of
+/// the logical screen, matching `image`'s GIF decoder; transparent palette entries come out with zero alpha.
+pub struct GifDecoder {
+	width: u32,
+	height: u32,
+	/// The first frame composited onto the canvas, as RGBA
+	canvas: Vec<u8>,
+	animated: bool,
+	limits: Limits,
+}
+
+
+impl GifDecoder {
+	pub fn new<R: BufRead + Seek>(r: R) -> Result<GifDecoder, Error> {
+		Self::with_limits(r, Limits::no_limits())
+	}
+
+	pub fn with_limits<R: BufRead + Seek>(r: R, limits: Limits) -> Result<GifDecoder, Error> {
+		limits.check_support(&image::LimitSupport::default())?;
+
+		let mut options = gif::DecodeOptions::new();
+		options.set_color_output(gif::ColorOutput::RGBA);
+		let mut decoder = options.read_info(r).map_err(error_from_gif)?;
+		let (width, height) = (u32::from(decoder.width()), u32::from(decoder.height()));
+		limits.check_dimensions(width, height)?;
+
+		let mut canvas = vec![0; crate::error::pixel_buffer_len(width, height, 4)?];
+		let Some(frame) = decoder.read_next_frame().map_err(error_from_gif)? else {
+			return Err(Error::Decoding(DecodingError::new(ImageFormat::Gif.into(), "no image data")));
+		};
+		composite(frame, width, height, &mut canvas);
+		let animated = decoder.next_frame_info().map_err(error_from_gif)?.is_some();
+
+		let mut decoder = GifDecoder {
+			width,
+			height,
+			canvas,
+			animated,
+			limits: Limits::no_limits(),
+		};
+		decoder.set_limits(limits)?;
+		Ok(decoder)
+	}
+
+	/// Returns true if the GIF has more than one frame.
+	pub fn is_animated(&self) -> bool {
+		self.animated
+	}
+}
+
+
+impl ImageDecoder for GifDecoder {
+	fn dimensions(&self) -> (u32, u32) {
+		(self.width, self.height)
+	}
+
+	fn color_type(&self) -> ColorType {
+		ColorType::Rgba8
+	}
+
+	fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+		crate::error::check_buffer(buf, self.total_bytes())?;
+		buf.copy_from_slice(&self.canvas);
+		Ok(())
+	}
+
+	fn read_image_boxed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+		(*self).read_image(buf)
+	}
+
+	fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+		limits.check_support(&image::LimitSupport::default())?;
+		limits.check_dimensions(self.width, self.height)?;
+		self.limits = limits;
+		Ok(())
+	}
+}
+
+
+/// Copies an RGBA frame onto the canvas at its offset, clipping anything outside the logical screen.
+fn composite(frame: &gif::Frame, width: u32, height: u32, canvas: &mut [u8]) {
+	let (left, top) = (u32::from(frame.left), u32::from(frame.top));
+	let frame_width = usize::from(frame.width);
+	let visible_width = (width.saturating_sub(left) as usize).min(frame_width);
+	if visible_width == 0 || frame_width == 0 {
+		return;
+	}
+	for (y, row) in (top..height).zip(frame.buffer.chunks_exact(frame_width * 4)) {
+		let start = (y as usize * width as usize + left as usize) * 4;
+		canvas[start..start + visible_width * 4].copy_from_slice(&row[..visible_width * 4]);
+	}
+}
+
+
+fn error_from_gif(err: gif::DecodingError) -> ImageError {
+	match err {
+		gif::DecodingError::Io(err) => ImageError::IoError(err),
+		err => ImageError::Decoding(DecodingError::new(ImageFormat::Gif.into(), err)),
+	}
+}
@@ -0,0 +1,170 @@
+use std::io::{BufRead, Seek};
+
+use image::{
+	ColorType, ImageDecoder, ImageError, ImageResult, Limits,
+	error::{DecodingError, ImageFormatHint},
+	metadata::Orientation,
+};
+use libheif_rs::{ColorSpace, HeifContext, ImageHandle, ItemId, LibHeif, RgbChroma};
+
+use crate::error::Error;
+
+
+/// HEIF/HEIC decoder built on libheif.
+///
+/// Only the primary image item is decoded; auxiliary images (depth maps, HDR gain maps) and thumbnails are ignored,
+/// and alpha comes from the primary item's alpha auxiliary image. Images above 8 bits decode to 16-bit color types.
+///
+/// libheif applies the `irot`/`imir`/`clap` transformations while decoding, which take precedence over EXIF
+/// orientation in HEIF, so `orientation` always reports `NoTransforms` even when the EXIF says otherwise.
+pub struct HeifDecoder {
+	width: u32,
+	height: u32,
+	color_type: ColorType,
+	/// Decoded pixels, already in `color_type` layout and native endianness
+	pixels: Vec<u8>,
+	exif: Option<Vec<u8>>,
+	icc_profile: Option<Vec<u8>>,
+	limits: Limits,
+}
+
+
+impl HeifDecoder {
+	pub fn new<R: BufRead + Seek>(r: R) -> Result<HeifDecoder, Error> {
+		Self::with_limits(r, Limits::no_limits())
+	}
+
+	pub fn with_limits<R: BufRead + Seek>(mut r: R, limits: Limits) -> Result<HeifDecoder, Error> {
+		limits.check_support(&image::LimitSupport::default())?;
+
+		let mut input = Vec::new();
+		r.read_to_end(&mut input)?;
+		let ctx = HeifContext::read_from_bytes(&input).map_err(error_from_heif)?;
+		let handle = ctx.primary_image_handle().map_err(error_from_heif)?;
+		// Check before decoding, since decoding allocates the full image
+		limits.check_dimensions(handle.width(), handle.height())?;
+
+		let has_alpha = handle.has_alpha_channel();
+		let bits = handle.luma_bits_per_pixel();
+		let (chroma, color_type) = match (bits > 8, has_alpha) {
+			(false, false) => (RgbChroma::Rgb, ColorType::Rgb8),
+			(false, true) => (RgbChroma::Rgba, ColorType::Rgba8),
+			(true, false) => (RgbChroma::HdrRgbLe, ColorType::Rgb16),
+			(true, true) => (RgbChroma::HdrRgbaLe, ColorType::Rgba16),
+		};
+		let image = LibHeif::new().decode(&handle, ColorSpace::Rgb(chroma), None).map_err(error_from_heif)?;
+		let planes = image.planes();
+		let Some(plane) = planes.interleaved else {
+			return Err(Error::Decoding(DecodingError::new(heif_hint(), "libheif returned no interleaved plane")));
+		};
+
+		let row_bytes = plane.width as usize * usize::from(color_type.bytes_per_pixel());
+		let mut pixels = Vec::with_capacity(row_bytes * plane.height as usize);
+		for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+			pixels.extend_from_slice(&row[..row_bytes]);
+		}
+		if bits > 8 {
+			// libheif leaves high bit depth samples at their coded depth, so scale them up to the full 16-bit range
+			for sample in pixels.chunks_exact_mut(2) {
+				let v = u16::from_le_bytes([sample[0], sample[1]]);
+				let v = if bits < 16 { (v << (16 - bits)) | (v >> (2 * bits - 16)) } else { v };
+				sample.copy_from_slice(&v.to_ne_bytes());
+			}
+		}
+
+		let mut decoder = HeifDecoder {
+			width: plane.width,
+			height: plane.height,
+			color_type,
+			pixels,
+			exif: exif(&handle),
+			icc_profile: handle.color_profile_raw().map(|profile| profile.data),
+			limits: Limits::no_limits(),
+		};
+		decoder.set_limits(limits)?;
+		Ok(decoder)
+	}
+}
+
+
+impl ImageDecoder for HeifDecoder {
+	fn dimensions(&self) -> (u32, u32) {
+		(self.width, self.height)
+	}
+
+	fn color_type(&self) -> ColorType {
+		self.color_type
+	}
+
+	fn icc_profile(&mut self) -> ImageResult<Option<Vec<u8>>> {
+		Ok(self.icc_profile.clone())
+	}
+
+	fn exif_metadata(&mut self) -> ImageResult<Option<Vec<u8>>> {
+		Ok(self.exif.clone())
+	}
+
+	fn orientation(&mut self) -> ImageResult<Orientation> {
+		Ok(Orientation::NoTransforms)
+	}
+
+	fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+		crate::error::check_buffer(buf, self.total_bytes())?;
+		buf.copy_from_slice(&self.pixels);
+		Ok(())
+	}
+
+	fn read_image_boxed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+		(*self).read_image(buf)
+	}
+
+	fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+		limits.check_support(&image::LimitSupport::default())?;
+		limits.check_dimensions(self.width, self.height)?;
+		self.limits = limits;
+		Ok(())
+	}
+}
+
+
+/// Dimensions of every top level image, in file order, and whether each is the primary image. Thumbnails and
+/// auxiliary images aren't top level, so they're left out.
+pub(crate) fn top_level_images<R: BufRead + Seek>(mut r: R) -> Result<Vec<((u32, u32), bool)>, Error> {
+	let mut input = Vec::new();
+	r.read_to_end(&mut input)?;
+	let ctx = HeifContext::read_from_bytes(&input).map_err(error_from_heif)?;
+	let mut ids = vec![0; ctx.number_of_top_level_images()];
+	let count = ctx.top_level_image_ids(&mut ids);
+	ids.truncate(count);
+	ids.into_iter()
+		.map(|id| {
+			let handle = ctx.image_handle(id).map_err(error_from_heif)?;
+			Ok(((handle.width(), handle.height()), handle.is_primary()))
+		})
+		.collect()
+}
+
+
+/// The primary image's EXIF, as a plain TIFF structure like the other decoders return.
+///
+/// HEIF Exif items start with a big endian offset to the TIFF header, which is usually zero but can skip an
+/// `Exif\0\0` prefix.
+fn exif(handle: &ImageHandle) -> Option<Vec<u8>> {
+	let mut ids: [ItemId; 1] = [0];
+	if handle.metadata_block_ids(&mut ids, b"Exif") == 0 {
+		return None;
+	}
+	let block = handle.metadata(ids[0]).ok()?;
+	let offset = usize::try_from(u32::from_be_bytes(block.get(..4)?.try_into().ok()?)).ok()?;
+	block.get(offset.checked_add(4)?..).map(<[u8]>::to_vec)
+}
+
+
+fn heif_hint() -> ImageFormatHint {
+	ImageFormatHint::Name("HEIF".to_string())
+}
+
+
+fn error_from_heif(err: libheif_rs::HeifError) -> ImageError {
+	ImageError::Decoding(DecodingError::new(heif_hint(), err))
+}
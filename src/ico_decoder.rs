@@ -0,0 +1,190 @@
+use std::io::{BufRead, Cursor, Seek};
+
+use image::{
+	ColorType, ImageDecoder, ImageFormat, ImageResult, Limits,
+	codecs::ico,
+	error::DecodingError,
+};
+
+use crate::error::Error;
+
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+
+/// One image in an ICO (or CUR) directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IcoEntry {
+	/// Position in the directory
+	pub index: usize,
+	/// Size according to the directory; the stored image is authoritative if they disagree
+	pub width: u32,
+	pub height: u32,
+	pub bits_per_pixel: u16,
+	/// Whether the image is stored as a PNG rather than a headerless BMP
+	pub png: bool,
+}
+
+
+/// ICO decoder that picks which of the embedded images to decode.
+///
+/// By default that's the largest one, preferring more bits per pixel between equally sized entries. With a requested
+/// size it's an exact match if there is one, otherwise the smallest entry larger than requested, otherwise the largest.
+/// `image` would pick the largest too, but can't be told otherwise and doesn't say which entry it chose.
+///
+/// The chosen entry is decoded by `image`'s ICO decoder from a copy of the file trimmed down to that one entry, which
+/// keeps its handling of AND masks and PNG entries.
+pub struct IcoDecoder {
+	entries: Vec<IcoEntry>,
+	chosen: IcoEntry,
+	inner: ico::IcoDecoder<Cursor<Vec<u8>>>,
+	limits: Limits,
+}
+
+
+impl IcoDecoder {
+	pub fn new<R: BufRead + Seek>(r: R) -> Result<IcoDecoder, Error> {
+		Self::with_size(r, None, Limits::no_limits())
+	}
+
+	pub fn with_limits<R: BufRead + Seek>(r: R, limits: Limits) -> Result<IcoDecoder, Error> {
+		Self::with_size(r, None, limits)
+	}
+
+	/// `size` is the preferred width, see the type docs for how entries are chosen.
+	pub fn with_size<R: BufRead + Seek>(mut r: R, size: Option<u32>, limits: Limits) -> Result<IcoDecoder, Error> {
+		limits.check_support(&image::LimitSupport::default())?;
+
+		let mut input = Vec::new();
+		r.read_to_end(&mut input)?;
+		let (entries, ranges) = parse_directory(&input)?;
+		let chosen = choose(&entries, size).ok_or_else(|| decoding_error("no images in the directory"))?;
+		limits.check_dimensions(chosen.width, chosen.height)?;
+		let (offset, len) = ranges[chosen.index];
+		let data = offset
+			.checked_add(len)
+			.and_then(|end| input.get(offset..end))
+			.ok_or_else(|| decoding_error("image data out of bounds"))?;
+		let chosen = IcoEntry {
+			png: data.starts_with(PNG_SIGNATURE),
+			..chosen
+		};
+
+		// Rebuild as a single entry file: header, one directory entry, then the image data
+		let mut single = Vec::with_capacity(22 + data.len());
+		single.extend_from_slice(&input[..4]);
+		single.extend_from_slice(&1u16.to_le_bytes());
+		single.extend_from_slice(&input[6 + chosen.index * 16..6 + chosen.index * 16 + 12]);
+		single.extend_from_slice(&22u32.to_le_bytes());
+		single.extend_from_slice(data);
+		let inner = ico::IcoDecoder::new(Cursor::new(single))?;
+
+		let mut decoder = IcoDecoder {
+			entries,
+			chosen,
+			inner,
+			limits: Limits::no_limits(),
+		};
+		decoder.set_limits(limits)?;
+		Ok(decoder)
+	}
+
+	/// The entry that will be decoded.
+	pub fn chosen(&self) -> IcoEntry {
+		self.chosen
+	}
+
+	/// All entries in the directory. `png` is only filled in for the chosen one.
+	pub fn entries(&self) -> &[IcoEntry] {
+		&self.entries
+	}
+}
+
+
+impl ImageDecoder for IcoDecoder {
+	fn dimensions(&self) -> (u32, u32) {
+		self.inner.dimensions()
+	}
+
+	fn color_type(&self) -> ColorType {
+		self.inner.color_type()
+	}
+
+	fn icc_profile(&mut self) -> ImageResult<Option<Vec<u8>>> {
+		self.inner.icc_profile()
+	}
+
+	fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+		self.inner.read_image(buf)
+	}
+
+	fn read_image_boxed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+		(*self).read_image(buf)
+	}
+
+	fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+		limits.check_support(&image::LimitSupport::default())?;
+		let (width, height) = self.dimensions();
+		limits.check_dimensions(width, height)?;
+		self.inner.set_limits(limits.clone())?;
+		self.limits = limits;
+		Ok(())
+	}
+}
+
+
+/// (offset, length) of an entry's data
+type DataRange = (usize, usize);
+
+
+/// Parses the ICONDIR, returning the entries and where each one's data is.
+fn parse_directory(input: &[u8]) -> Result<(Vec<IcoEntry>, Vec<DataRange>), Error> {
+	let u16_at = |i: usize| u16::from_le_bytes([input[i], input[i + 1]]);
+	let u32_at = |i: usize| u32::from_le_bytes([input[i], input[i + 1], input[i + 2], input[i + 3]]);
+
+	if input.len() < 6 || u16_at(0) != 0 || !matches!(u16_at(2), 1 | 2) {
+		return Err(decoding_error("not an ICO or CUR file"));
+	}
+	let count = usize::from(u16_at(4));
+	if input.len() < 6 + count * 16 {
+		return Err(decoding_error("directory truncated"));
+	}
+
+	let mut entries = Vec::with_capacity(count);
+	let mut ranges = Vec::with_capacity(count);
+	for index in 0..count {
+		let at = 6 + index * 16;
+		// A stored size of 0 means 256
+		let size = |b: u8| if b == 0 { 256 } else { u32::from(b) };
+		entries.push(IcoEntry {
+			index,
+			width: size(input[at]),
+			height: size(input[at + 1]),
+			bits_per_pixel: u16_at(at + 6),
+			png: false,
+		});
+		ranges.push((u32_at(at + 12) as usize, u32_at(at + 8) as usize));
+	}
+	Ok((entries, ranges))
+}
+
+
+fn choose(entries: &[IcoEntry], size: Option<u32>) -> Option<IcoEntry> {
+	let largest = entries.iter().max_by_key(|e| (e.width * e.height, e.bits_per_pixel, std::cmp::Reverse(e.index)));
+	let Some(size) = size else {
+		return largest.copied();
+	};
+	let best_of = |filter: &dyn Fn(&IcoEntry) -> bool| {
+		entries
+			.iter()
+			.filter(|e| filter(e))
+			.min_by_key(|e| (e.width, std::cmp::Reverse(e.bits_per_pixel), e.index))
+			.copied()
+	};
+	best_of(&|e| e.width == size).or_else(|| best_of(&|e| e.width > size)).or(largest.copied())
+}
+
+
+fn decoding_error(message: &'static str) -> Error {
+	Error::Decoding(DecodingError::new(ImageFormat::Ico.into(), message))
+}
@@ -0,0 +1,136 @@
+use std::io::{BufRead, Seek};
+
+use image::{
+	ColorType, ImageDecoder, ImageError, ImageResult, Limits,
+	error::{DecodingError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind},
+};
+
+use crate::error::Error;
+
+
+/// JPEG 2000 decoder built on OpenJPEG, for both JP2 files and bare J2K codestreams.
+///
+/// Up to four components are interleaved as gray, gray with alpha, RGB or RGBA. Components with more than 8 bits of
+/// precision decode to 16-bit color types, scaled to the full 16-bit range like a 16-bit PNG, so 12-bit medical
+/// imagery keeps its precision. Signed components are offset to unsigned. Subsampled components (as in YCbCr coded
+/// files) are unsupported.
+pub struct Jpeg2000Decoder {
+	width: u32,
+	height: u32,
+	color_type: ColorType,
+	/// Interleaved samples, native endian if 16-bit
+	data: Vec<u8>,
+	limits: Limits,
+}
+
+
+impl Jpeg2000Decoder {
+	pub fn new<R: BufRead + Seek>(r: R) -> Result<Jpeg2000Decoder, Error> {
+		Self::with_limits(r, Limits::no_limits())
+	}
+
+	pub fn with_limits<R: BufRead + Seek>(mut r: R, limits: Limits) -> Result<Jpeg2000Decoder, Error> {
+		limits.check_support(&image::LimitSupport::default())?;
+
+		let mut input = Vec::new();
+		r.read_to_end(&mut input)?;
+		let image = jpeg2k::Image::from_bytes(&input).map_err(error_from_jpeg2k)?;
+		let (width, height) = (image.width(), image.height());
+		limits.check_dimensions(width, height)?;
+
+		let components = image.components();
+		if components.iter().any(|c| c.width() != width || c.height() != height) {
+			return Err(unsupported("subsampled components"));
+		}
+		let precision = components.iter().map(|c| c.precision()).max().unwrap_or(0);
+		let is_16bit = precision > 8;
+		let color_type = match (components.len(), is_16bit) {
+			(1, false) => ColorType::L8,
+			(1, true) => ColorType::L16,
+			(2, false) => ColorType::La8,
+			(2, true) => ColorType::La16,
+			(3, false) => ColorType::Rgb8,
+			(3, true) => ColorType::Rgb16,
+			(4, false) => ColorType::Rgba8,
+			(4, true) => ColorType::Rgba16,
+			(count, _) => return Err(unsupported(&format!("{count} components"))),
+		};
+
+		let pixels = crate::error::pixel_buffer_len(width, height, 1)?;
+		let channels = components.len();
+		let mut data = vec![0; crate::error::pixel_buffer_len(width, height, color_type.bytes_per_pixel())?];
+		for (c, component) in components.iter().enumerate() {
+			let samples = component.data();
+			if samples.len() < pixels {
+				return Err(Error::Decoding(DecodingError::new(jpeg2000_hint(), "component data is short")));
+			}
+			let precision = component.precision().clamp(1, 16);
+			let offset = if component.is_signed() { 1i64 << (precision - 1) } else { 0 };
+			let max = (1i64 << precision) - 1;
+			let target_max = if is_16bit { 65535 } else { 255 };
+			for (i, &sample) in samples[..pixels].iter().enumerate() {
+				let v = (i64::from(sample) + offset).clamp(0, max);
+				let v = (v * target_max + max / 2) / max;
+				if is_16bit {
+					let at = (i * channels + c) * 2;
+					data[at..at + 2].copy_from_slice(&(v as u16).to_ne_bytes());
+				} else {
+					data[i * channels + c] = v as u8;
+				}
+			}
+		}
+
+		let mut decoder = Jpeg2000Decoder {
+			width,
+			height,
+			color_type,
+			data,
+			limits: Limits::no_limits(),
+		};
+		decoder.set_limits(limits)?;
+		Ok(decoder)
+	}
+}
+
+
+impl ImageDecoder for Jpeg2000Decoder {
+	fn dimensions(&self) -> (u32, u32) {
+		(self.width, self.height)
+	}
+
+	fn color_type(&self) -> ColorType {
+		self.color_type
+	}
+
+	fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+		crate::error::check_buffer(buf, self.total_bytes())?;
+		buf.copy_from_slice(&self.data);
+		Ok(())
+	}
+
+	fn read_image_boxed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+		(*self).read_image(buf)
+	}
+
+	fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+		limits.check_support(&image::LimitSupport::default())?;
+		limits.check_dimensions(self.width, self.height)?;
+		self.limits = limits;
+		Ok(())
+	}
+}
+
+
+fn jpeg2000_hint() -> ImageFormatHint {
+	ImageFormatHint::Name("JPEG 2000".to_string())
+}
+
+
+fn unsupported(what: &str) -> Error {
+	Error::Unsupported(UnsupportedError::from_format_and_kind(jpeg2000_hint(), UnsupportedErrorKind::GenericFeature(what.to_string())))
+}
+
+
+fn error_from_jpeg2k(err: jpeg2k::error::Error) -> ImageError {
+	ImageError::Decoding(DecodingError::new(jpeg2000_hint(), err))
+}
@@ -0,0 +1,392 @@
+//! 1/8 scale JPEG previews from the DC coefficients alone.
+//!
+//! A block's DC coefficient is its mean sample value up to the quantizer, so a preview needs no IDCT, no chroma
+//! upsampling beyond block granularity, and for progressive files only the DC scans: the AC scans, which are most of
+//! the file, are skipped without being entropy decoded. Baseline files still need their AC codes Huffman decoded to
+//! find where each block ends, but nothing more is done with them.
+//!
+//! Only 8-bit Huffman coded files with one or three components are covered; `decode` returns `None` for the rest.
+
+use image::{DynamicImage, GrayImage, ImageFormat, RgbImage, error::DecodingError};
+
+use crate::{
+	convert::{self, YCbCrMatrix},
+	error::Error,
+};
+
+
+const SOF_BASELINE: u8 = 0xC0;
+const SOF_EXTENDED: u8 = 0xC1;
+const SOF_PROGRESSIVE: u8 = 0xC2;
+
+
+struct Component {
+	id: u8,
+	h: usize,
+	v: usize,
+	quant_table: usize,
+	/// Quantized DC coefficients, one per block of the padded MCU grid
+	dc: Vec<i32>,
+}
+
+
+/// Canonical Huffman table in the form of JPEG spec F.2.2.3.
+pub(crate) struct Huffman {
+	max_code: [i32; 17],
+	val_ptr: [i32; 17],
+	min_code: [i32; 17],
+	values: Vec<u8>,
+}
+
+impl Huffman {
+	fn new(counts: &[u8], values: &[u8]) -> Huffman {
+		let mut table = Huffman {
+			max_code: [-1; 17],
+			val_ptr: [0; 17],
+			min_code: [0; 17],
+			values: values.to_vec(),
+		};
+		let (mut code, mut k) = (0i32, 0i32);
+		for len in 1..=16 {
+			let count = i32::from(counts[len - 1]);
+			if count > 0 {
+				table.val_ptr[len] = k;
+				table.min_code[len] = code;
+				code += count;
+				k += count;
+				table.max_code[len] = code - 1;
+			}
+			code <<= 1;
+		}
+		table
+	}
+
+	pub(crate) fn decode(&self, bits: &mut BitReader) -> Result<u8, Error> {
+		let mut code = 0i32;
+		for len in 1..=16 {
+			code = (code << 1) | bits.bit() as i32;
+			if code <= self.max_code[len] {
+				let index = self.val_ptr[len] + code - self.min_code[len];
+				return self.values.get(index as usize).copied().ok_or_else(|| corrupt("bad Huffman code"));
+			}
+		}
+		Err(corrupt("bad Huffman code"))
+	}
+}
+
+
+/// Reads entropy coded data, removing stuffed zero bytes. At a marker it yields zero bits without advancing, like
+/// libjpeg, so a truncated scan decodes as flat blocks instead of failing.
+pub(crate) struct BitReader<'a> {
+	data: &'a [u8],
+	pos: usize,
+	acc: u32,
+	count: u32,
+	/// Set once bits have been made up past the end of the data
+	pub exhausted: bool,
+}
+
+impl<'a> BitReader<'a> {
+	pub(crate) fn new(data: &'a [u8]) -> BitReader<'a> {
+		BitReader {
+			data,
+			pos: 0,
+			acc: 0,
+			count: 0,
+			exhausted: false,
+		}
+	}
+
+	pub(crate) fn bit(&mut self) -> u32 {
+		if self.count == 0 {
+			let byte = match (self.data.get(self.pos), self.data.get(self.pos + 1)) {
+				(Some(0xFF), Some(0x00)) => {
+					self.pos += 2;
+					0xFF
+				},
+				(Some(0xFF), _) | (None, _) => {
+					self.exhausted = true;
+					0
+				},
+				(Some(&byte), _) => {
+					self.pos += 1;
+					byte
+				},
+			};
+			self.acc = u32::from(byte);
+			self.count = 8;
+		}
+		self.count -= 1;
+		(self.acc >> self.count) & 1
+	}
+
+	pub(crate) fn bits(&mut self, n: u8) -> u32 {
+		(0..n).fold(0, |v, _| (v << 1) | self.bit())
+	}
+
+	/// Value of an `n` bit magnitude category, per JPEG spec F.2.2.1 (RECEIVE and EXTEND).
+	pub(crate) fn receive_extend(&mut self, n: u8) -> i32 {
+		if n == 0 {
+			return 0;
+		}
+		let v = self.bits(n) as i32;
+		if v < 1 << (n - 1) { v - (1 << n) + 1 } else { v }
+	}
+
+	/// Drops any partial byte and skips past the next restart marker.
+	pub(crate) fn restart(&mut self) {
+		self.count = 0;
+		match self.data.get(self.pos..).and_then(|rest| rest.windows(2).position(|w| w[0] == 0xFF && (0xD0..=0xD7).contains(&w[1]))) {
+			Some(offset) => self.pos += offset + 2,
+			None => self.pos = self.data.len(),
+		}
+	}
+}
+
+
+/// Decodes the DC preview of `input`, or returns `None` if it uses a coding process or layout not covered here.
+pub(crate) fn decode(input: &[u8]) -> Result<Option<DynamicImage>, Error> {
+	if !input.starts_with(&[0xFF, 0xD8]) {
+		return Ok(None);
+	}
+
+	let mut quant = [[0u16; 64]; 4];
+	let mut dc_tables: [Option<Huffman>; 4] = Default::default();
+	let mut ac_tables: [Option<Huffman>; 4] = Default::default();
+	let mut components: Vec<Component> = Vec::new();
+	let (mut width, mut height, mut progressive) = (0, 0, false);
+	let mut restart_interval = 0;
+	let mut adobe_transform = None;
+	let (mut h_max, mut v_max, mut mcus_x, mut mcus_y) = (1, 1, 0, 0);
+
+	let segment_length = |pos: usize| input.get(pos..pos + 2).map(|b| usize::from(u16::from_be_bytes([b[0], b[1]])));
+	let mut pos = 2;
+	loop {
+		while input.get(pos).is_some_and(|&b| b != 0xFF) {
+			pos += 1;
+		}
+		while input.get(pos) == Some(&0xFF) {
+			pos += 1;
+		}
+		let Some(&marker) = input.get(pos) else {
+			break;
+		};
+		pos += 1;
+		if matches!(marker, 0x01 | 0xD0..=0xD7) {
+			continue;
+		}
+		if marker == 0xD9 {
+			break;
+		}
+		let Some(len) = segment_length(pos) else {
+			break;
+		};
+		let payload = input.get(pos + 2..pos + len.max(2)).ok_or_else(|| corrupt("truncated segment"))?;
+		pos += len.max(2);
+
+		match marker {
+			0xDB => {
+				let mut rest = payload;
+				while let Some(&pq_tq) = rest.first() {
+					let (wide, id) = (pq_tq >> 4 != 0, usize::from(pq_tq & 3));
+					let size = if wide { 128 } else { 64 };
+					let table = rest.get(1..1 + size).ok_or_else(|| corrupt("truncated quantization table"))?;
+					for (i, q) in quant[id].iter_mut().enumerate() {
+						*q = if wide { u16::from_be_bytes([table[i * 2], table[i * 2 + 1]]) } else { u16::from(table[i]) };
+					}
+					rest = &rest[1 + size..];
+				}
+			},
+			0xC4 => read_huffman_tables(payload, &mut dc_tables, &mut ac_tables)?,
+			0xDD => restart_interval = payload.get(0..2).map_or(0, |b| usize::from(u16::from_be_bytes([b[0], b[1]]))),
+			0xEE if payload.starts_with(b"Adobe") => adobe_transform = payload.get(11).copied(),
+			SOF_BASELINE | SOF_EXTENDED | SOF_PROGRESSIVE => {
+				if payload.len() < 6 || payload[0] != 8 {
+					return Ok(None);
+				}
+				progressive = marker == SOF_PROGRESSIVE;
+				height = usize::from(u16::from_be_bytes([payload[1], payload[2]]));
+				width = usize::from(u16::from_be_bytes([payload[3], payload[4]]));
+				let count = usize::from(payload[5]);
+				// No DNL support: height must come from the frame header
+				if !matches!(count, 1 | 3) || width == 0 || height == 0 {
+					return Ok(None);
+				}
+				let specs = payload.get(6..6 + count * 3).ok_or_else(|| corrupt("truncated frame header"))?;
+				for spec in specs.chunks_exact(3) {
+					let (h, v) = (usize::from(spec[1] >> 4), usize::from(spec[1] & 15));
+					if !(1..=4).contains(&h) || !(1..=4).contains(&v) {
+						return Err(corrupt("bad sampling factors"));
+					}
+					components.push(Component {
+						id: spec[0],
+						h,
+						v,
+						quant_table: usize::from(spec[2] & 3),
+						dc: Vec::new(),
+					});
+				}
+				h_max = components.iter().map(|c| c.h).max().unwrap_or(1);
+				v_max = components.iter().map(|c| c.v).max().unwrap_or(1);
+				mcus_x = width.div_ceil(8 * h_max);
+				mcus_y = height.div_ceil(8 * v_max);
+				for component in &mut components {
+					component.dc = vec![0; mcus_x * component.h * mcus_y * component.v];
+				}
+			},
+			// Lossless, arithmetic coding and the hierarchical processes
+			0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return Ok(None),
+			0xDA => {
+				if components.is_empty() {
+					return Err(corrupt("scan before frame header"));
+				}
+				let count = usize::from(*payload.first().ok_or_else(|| corrupt("truncated scan header"))?);
+				let specs = payload.get(1..1 + count * 2).ok_or_else(|| corrupt("truncated scan header"))?;
+				let &[ss, _, ah_al] = payload.get(1 + count * 2..4 + count * 2).ok_or_else(|| corrupt("truncated scan header"))? else {
+					unreachable!();
+				};
+				let mut scan = Vec::new();
+				for spec in specs.chunks_exact(2) {
+					let index = components.iter().position(|c| c.id == spec[0]).ok_or_else(|| corrupt("scan references an unknown component"))?;
+					scan.push((index, usize::from(spec[1] >> 4), usize::from(spec[1] & 15)));
+				}
+
+				// Entropy coded data runs until a marker other than a stuffed zero or a restart marker, or the end of the file
+				let start = pos;
+				while let Some(offset) = input.get(pos..).map(|rest| rest.iter().position(|&b| b == 0xFF).unwrap_or(rest.len())) {
+					pos += offset;
+					match input.get(pos + 1) {
+						Some(0x00 | 0xD0..=0xD7) => pos += 2,
+						_ => break,
+					}
+				}
+				pos = pos.min(input.len());
+
+				// Progressive AC scans carry nothing the preview needs
+				if progressive && ss != 0 {
+					continue;
+				}
+				let mut bits = BitReader::new(&input[start..pos]);
+				let (refine, al) = (progressive && ah_al >> 4 != 0, u32::from(ah_al & 15));
+				let tables: Vec<(Option<&Huffman>, Option<&Huffman>)> = scan.iter().map(|&(_, dc, ac)| (dc_tables[dc].as_ref(), ac_tables[ac].as_ref())).collect();
+				let mut predictors = vec![0i32; scan.len()];
+
+				// (component index in the scan, block x, block y) in decoding order
+				let blocks: Box<dyn Iterator<Item = (usize, usize, usize)>> = if let [(index, _, _)] = scan[..] {
+					let component = &components[index];
+					let blocks_x = (width * component.h).div_ceil(h_max).div_ceil(8);
+					let blocks_y = (height * component.v).div_ceil(v_max).div_ceil(8);
+					Box::new((0..blocks_y).flat_map(move |y| (0..blocks_x).map(move |x| (0, x, y))))
+				} else {
+					let layout: Vec<(usize, usize, usize)> = scan.iter().enumerate().map(|(i, &(index, _, _))| (i, components[index].h, components[index].v)).collect();
+					Box::new((0..mcus_y).flat_map(move |my| {
+						let layout = layout.clone();
+						(0..mcus_x).flat_map(move |mx| {
+							layout
+								.clone()
+								.into_iter()
+								.flat_map(move |(i, h, v)| (0..v).flat_map(move |by| (0..h).map(move |bx| (i, mx * h + bx, my * v + by))))
+						})
+					}))
+				};
+				let blocks_per_mcu = if scan.len() == 1 { 1 } else { scan.iter().map(|&(index, _, _)| components[index].h * components[index].v).sum() };
+
+				for (n, (i, x, y)) in blocks.enumerate() {
+					if restart_interval > 0 && n > 0 && n % (restart_interval * blocks_per_mcu) == 0 {
+						bits.restart();
+						predictors.fill(0);
+					}
+					let component = &mut components[scan[i].0];
+					let stride = mcus_x * component.h;
+					let dc = &mut component.dc[y * stride + x];
+					if refine {
+						*dc |= (bits.bit() as i32) << al;
+						continue;
+					}
+					let dc_table = tables[i].0.ok_or_else(|| corrupt("missing Huffman table"))?;
+					let category = dc_table.decode(&mut bits)?;
+					if category > 11 {
+						return Err(corrupt("bad DC difference"));
+					}
+					predictors[i] = predictors[i].wrapping_add(bits.receive_extend(category));
+					*dc = predictors[i].wrapping_shl(al);
+					if !progressive {
+						skip_ac(tables[i].1.ok_or_else(|| corrupt("missing Huffman table"))?, &mut bits)?;
+					}
+				}
+			},
+			_ => (),
+		}
+	}
+
+	if components.is_empty() {
+		return Err(corrupt("no frame header"));
+	}
+
+	// Each block's mean sample is its dequantized DC coefficient over 8, plus the level shift
+	let (out_width, out_height) = (width.div_ceil(8), height.div_ceil(8));
+	let sample = |component: &Component, x: usize, y: usize| {
+		let (bx, by) = (x * component.h / h_max, y * component.v / v_max);
+		let dc = i64::from(component.dc[by * mcus_x * component.h + bx]) * i64::from(quant[component.quant_table][0]);
+		((dc as f64 / 8.0).round() as i64 + 128).clamp(0, 255) as u8
+	};
+	if let [gray] = &components[..] {
+		let data = (0..out_height).flat_map(|y| (0..out_width).map(move |x| (x, y))).map(|(x, y)| sample(gray, x, y)).collect();
+		return Ok(Some(DynamicImage::ImageLuma8(GrayImage::from_raw(out_width as u32, out_height as u32, data).expect("buffer size matches the dimensions"))));
+	}
+	let is_rgb = adobe_transform == Some(0) || components.iter().map(|c| c.id).eq(*b"RGB");
+	let mut data = Vec::with_capacity(out_width * out_height * 3);
+	for y in 0..out_height {
+		for x in 0..out_width {
+			let [a, b, c] = [0, 1, 2].map(|i| sample(&components[i], x, y));
+			data.extend_from_slice(&if is_rgb { [a, b, c] } else { convert::ycbcr_to_rgb(a, b, c, YCbCrMatrix::Bt601) });
+		}
+	}
+	Ok(Some(DynamicImage::ImageRgb8(RgbImage::from_raw(out_width as u32, out_height as u32, data).expect("buffer size matches the dimensions"))))
+}
+
+
+/// Reads the tables of a DHT segment into the DC and AC table slots.
+pub(crate) fn read_huffman_tables(payload: &[u8], dc_tables: &mut [Option<Huffman>; 4], ac_tables: &mut [Option<Huffman>; 4]) -> Result<(), Error> {
+	let mut rest = payload;
+	while let Some(&tc_th) = rest.first() {
+		let counts = rest.get(1..17).ok_or_else(|| corrupt("truncated Huffman table"))?;
+		let total = counts.iter().map(|&c| usize::from(c)).sum::<usize>();
+		let values = rest.get(17..17 + total).ok_or_else(|| corrupt("truncated Huffman table"))?;
+		let table = Some(Huffman::new(counts, values));
+		match tc_th >> 4 {
+			0 => dc_tables[usize::from(tc_th & 3)] = table,
+			_ => ac_tables[usize::from(tc_th & 3)] = table,
+		}
+		rest = &rest[17 + total..];
+	}
+	Ok(())
+}
+
+
+/// Huffman decodes a block's AC coefficients only to find where the block ends.
+pub(crate) fn skip_ac(table: &Huffman, bits: &mut BitReader) -> Result<(), Error> {
+	let mut k = 1;
+	while k < 64 {
+		let rs = table.decode(bits)?;
+		let (run, size) = (rs >> 4, rs & 15);
+		if size == 0 {
+			if run != 15 {
+				break;
+			}
+			k += 16;
+			continue;
+		}
+		bits.bits(size);
+		k += usize::from(run) + 1;
+	}
+	if k > 64 {
+		return Err(corrupt("coefficients past the end of a block"));
+	}
+	Ok(())
+}
+
+
+pub(crate) fn corrupt(what: &str) -> Error {
+	Error::Decoding(DecodingError::new(ImageFormat::Jpeg.into(), what.to_string()))
+}
@@ -1,13 +1,23 @@
-use std::io::{BufRead, Seek};
+use std::{
+	io::{BufRead, Read, Seek},
+	ops::Range,
+};
 
 use image::{
-	ColorType, ImageDecoder, ImageError, ImageFormat, ImageResult, Limits,
+	ColorType, DynamicImage, GrayImage, ImageDecoder, ImageError, ImageFormat, ImageResult, Limits, RgbaImage,
 	error::{DecodingError, LimitError, UnsupportedError, UnsupportedErrorKind},
 	metadata::Orientation,
 };
 use zune_core::bytestream::ZCursor;
 
-use crate::error::Error;
+use crate::{
+	convert::{self, YCbCrMatrix},
+	error::Error,
+	jpeg_dc,
+	jpeg_fallback::{self, Fallback},
+	jpeg_markers, jpeg_resync, jpeg_scans,
+	options::{DctScale, JpegOptions},
+};
 
 
 type ZuneColorSpace = zune_core::colorspace::ColorSpace;
@@ -19,15 +29,71 @@ pub struct JpegDecoder {
 	height: u16,
 	limits: Limits,
 	orientation: Option<Orientation>,
+	scale: DctScale,
+	/// CMYK and YCCK files with an Adobe APP14 marker store inverted CMYK, as Photoshop writes it
+	inverted_cmyk: bool,
+	/// A truncated progressive file whose incomplete last scan was dropped from `input`
+	truncated: bool,
+	/// Restart intervals replaced by `JpegOptions::resync`
+	repaired_intervals: usize,
+	/// Number of images in an MPO file, whose `input` is cut down to the primary image
+	mpo_images: Option<usize>,
+	/// The frame header of lossless and arithmetic coded JPEGs, which zune-jpeg can't decode, and are decoded in
+	/// `read_image` instead. See `jpeg_fallback`.
+	fallback: Option<Fallback>,
 }
 
 // COPIED from: https://github.com/image-rs/image/blob/256dc9dd5501fa63cbf081a795a936c74c01abd9/src/codecs/jpeg/decoder.rs
 impl JpegDecoder {
 	/// Create a new decoder that decodes from the stream ```r```
 	pub fn new<R: BufRead + Seek>(r: R) -> Result<JpegDecoder, Error> {
+		Self::with_options(r, &JpegOptions::default())
+	}
+
+	pub fn with_options<R: BufRead + Seek>(r: R, jpeg_options: &JpegOptions) -> Result<JpegDecoder, Error> {
 		let mut input = Vec::new();
 		let mut r = r;
 		r.read_to_end(&mut input)?;
+
+		// Only the primary image of an MPO file is decoded, so the decoders never see the others
+		let mpo_images = mp_entries(&input).map(|images| {
+			if let Some(primary) = images.first().filter(|primary| primary.start == 0 && primary.end > 2) {
+				input.truncate(primary.end);
+			}
+			images.len()
+		});
+
+		if let Some(fallback) = jpeg_fallback::probe(&input)? {
+			if fallback.color_type == ColorType::L16 && jpeg_options.dct_scale != DctScale::Full {
+				return Err(Error::Unsupported(UnsupportedError::from_format_and_kind(
+					ImageFormat::Jpeg.into(),
+					UnsupportedErrorKind::GenericFeature("DCT scaling of 16-bit lossless JPEGs".to_string()),
+				)));
+			}
+			return Ok(JpegDecoder {
+				input,
+				orig_color_space: if fallback.color_type.has_color() { ZuneColorSpace::RGB } else { ZuneColorSpace::Luma },
+				width: fallback.width,
+				height: fallback.height,
+				limits: Limits::no_limits(),
+				orientation: None,
+				scale: jpeg_options.dct_scale,
+				inverted_cmyk: false,
+				truncated: false,
+				repaired_intervals: 0,
+				mpo_images,
+				fallback: Some(fallback),
+			});
+		}
+
+		let mut repaired_intervals = 0;
+		if jpeg_options.resync
+			&& let Some((repaired, count)) = jpeg_resync::repair(&input)?
+		{
+			input = repaired;
+			repaired_intervals = count;
+		}
+
 		let options = zune_core::options::DecoderOptions::default()
 			.set_strict_mode(false)
 			.set_max_width(usize::MAX)
@@ -41,17 +107,24 @@ impl JpegDecoder {
 		let width: u16 = width.try_into().unwrap();
 		let height: u16 = height.try_into().unwrap();
 		let orig_color_space = decoder.input_colorspace().expect("headers were decoded");
+		let is_progressive = decoder.info().is_some_and(|info| info.sof.is_progressive());
+		#[cfg(feature = "testing")]
+		record_coverage(&decoder, orig_color_space);
 
 		// Now configure the decoder color output.
-		decoder.set_options({
-			let requested_color = match orig_color_space {
-				ZuneColorSpace::RGB | ZuneColorSpace::RGBA | ZuneColorSpace::Luma | ZuneColorSpace::LumaA => orig_color_space,
-				// Late failure
-				_ => ZuneColorSpace::RGB,
-			};
-
-			decoder.options().jpeg_set_out_colorspace(requested_color)
-		});
+		decoder.set_options(decoder.options().jpeg_set_out_colorspace(to_supported_color_space(orig_color_space)));
+		let inverted_cmyk = has_adobe_marker(&input);
+		drop(decoder);
+
+		// A progressive scan cut short would refine part of the image and leave the rest at the previous scan's
+		// quality, so stop at the last complete scan instead. A last scan that runs to the end of the file without an
+		// EOI may still have all of its data, so it's only dropped if it runs out before its last block.
+		let ends = scan_ends(&input);
+		let truncated = is_progressive && ends.len() >= 2 && ends.last() == Some(&input.len()) && jpeg_scans::last_scan_is_cut(&input);
+		if truncated {
+			input.truncate(ends[ends.len() - 2]);
+			input.extend_from_slice(&[0xFF, 0xD9]);
+		}
 
 		// Limits are disabled by default in the constructor for all decoders
 		let limits = Limits::no_limits();
@@ -62,20 +135,309 @@ impl JpegDecoder {
 			height,
 			limits,
 			orientation: None,
+			scale: jpeg_options.dct_scale,
+			inverted_cmyk,
+			truncated,
+			repaired_intervals,
+			mpo_images,
+			fallback: None,
 		})
 	}
+
+	/// Reads just the marker segments up to the frame header, without buffering the file or touching scan data, for
+	/// cheap probing. The reader is left just past the frame header.
+	pub fn read_header<R: Read>(mut r: R) -> Result<JpegHeader, Error> {
+		let mut marker = [0; 2];
+		r.read_exact(&mut marker)?;
+		if marker != [0xFF, 0xD8] {
+			return Err(Error::Decoding(DecodingError::new(ImageFormat::Jpeg.into(), "missing start of image marker")));
+		}
+		loop {
+			// Skip fill bytes
+			let mut byte = [0xFF];
+			while byte[0] == 0xFF {
+				r.read_exact(&mut byte)?;
+			}
+			let marker = byte[0];
+			if matches!(marker, 0x01 | 0xD0..=0xD7) {
+				continue;
+			}
+			if matches!(marker, 0xD9 | 0xDA) {
+				return Err(Error::Decoding(DecodingError::new(ImageFormat::Jpeg.into(), "no frame header before the image data")));
+			}
+			let mut len = [0; 2];
+			r.read_exact(&mut len)?;
+			let len = u64::from(u16::from_be_bytes(len).saturating_sub(2));
+			if !matches!(marker, 0xC0..=0xCF) || matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+				std::io::copy(&mut (&mut r).take(len), &mut std::io::sink())?;
+				continue;
+			}
+
+			let mut frame = [0; 6];
+			r.read_exact(&mut frame)?;
+			let precision = frame[0];
+			let components = frame[5];
+			let color_type = match components {
+				1 if marker == 0xC3 && precision > 8 => ColorType::L16,
+				1 => ColorType::L8,
+				_ => ColorType::Rgb8,
+			};
+			return Ok(JpegHeader {
+				width: u32::from(u16::from_be_bytes([frame[3], frame[4]])),
+				height: u32::from(u16::from_be_bytes([frame[1], frame[2]])),
+				precision,
+				components,
+				progressive: matches!(marker, 0xC2 | 0xC6 | 0xCA | 0xCE),
+				color_type,
+			});
+		}
+	}
+
+	/// Returns true if the file ended partway through a progressive scan. That scan is ignored, so the image is
+	/// decoded as of the last complete scan, like a browser showing a partially loaded file.
+	pub fn is_truncated(&self) -> bool {
+		self.truncated
+	}
+
+	/// Number of corrupt restart intervals that `JpegOptions::resync` replaced with intact neighbors. Always 0 without
+	/// that option.
+	pub fn repaired_intervals(&self) -> usize {
+		self.repaired_intervals
+	}
+
+	/// Number of images in a multi-picture (MPO) file, counting the primary image that this decoder decodes, or `None`
+	/// for plain JPEGs.
+	pub fn mpo_image_count(&self) -> Option<usize> {
+		self.mpo_images
+	}
+
+	/// The ExtendedXMP packet, holding what didn't fit in the 64 KB segment of the standard packet (`xmp_metadata`),
+	/// reassembled from its segments. It's a packet of its own, to be merged with the standard one, and is `None`
+	/// unless the standard packet points to it and every chunk is there.
+	pub fn extended_xmp(&self) -> Option<Vec<u8>> {
+		let segments = jpeg_markers::header_segments(&self.input);
+		jpeg_markers::extended_xmp(&segments, &jpeg_markers::xmp(&segments)?)
+	}
+
+	/// Returns true for progressive JPEGs, which can be previewed after only some of their scans.
+	pub fn is_progressive(&self) -> bool {
+		let mut decoder = zune_jpeg::JpegDecoder::new_with_options(ZCursor::new(&self.input), header_options());
+		decoder.decode_headers().is_ok() && decoder.info().is_some_and(|info| info.sof.is_progressive())
+	}
+
+	/// Number of scans (SOS segments) in the file.
+	pub fn scan_count(&self) -> usize {
+		scan_ends(&self.input).len()
+	}
+
+	/// Decodes only the first `scans` scans, giving a coarse but full size image for progressive JPEGs (for baseline
+	/// JPEGs, components in later scans come out empty). Asking for more scans than exist decodes the whole image.
+	///
+	/// zune-jpeg can't suspend and resume decoding, so each preview decodes from the start of the file.
+	pub fn preview(&self, scans: usize) -> Result<DynamicImage, Error> {
+		let ends = scan_ends(&self.input);
+		let input = match ends.get(scans.max(1) - 1) {
+			Some(&end) if scans < ends.len() => {
+				let mut input = self.input[..end].to_vec();
+				input.extend_from_slice(&[0xFF, 0xD9]);
+				input
+			},
+			_ => self.input.clone(),
+		};
+		Ok(DynamicImage::from_decoder(self.with_input(input, self.scale))?)
+	}
+
+	/// A 1/8 scale preview built from each block's DC coefficient alone, without any IDCT: every pixel is the mean of
+	/// an 8x8 block (chroma is taken at block granularity too). Progressive files only need their DC scans read. This
+	/// is an order of magnitude faster than a full decode, and plenty for perceptual hashing or duplicate screening.
+	///
+	/// Files the DC path doesn't cover (12-bit, CMYK, lossless, arithmetic coded) fall back to a full decode at
+	/// `DctScale::Eighth`, which gives the same dimensions. `DctScale` options are ignored.
+	pub fn dc_preview(&self) -> Result<DynamicImage, Error> {
+		if self.fallback.as_ref().is_some_and(|fallback| fallback.color_type == ColorType::L16) {
+			return Err(Error::Unsupported(UnsupportedError::from_format_and_kind(
+				ImageFormat::Jpeg.into(),
+				UnsupportedErrorKind::GenericFeature("DCT scaling of 16-bit lossless JPEGs".to_string()),
+			)));
+		}
+		if self.fallback.is_none()
+			&& let Some(img) = jpeg_dc::decode(&self.input)?
+		{
+			return Ok(img);
+		}
+		Ok(DynamicImage::from_decoder(self.with_input(self.input.clone(), DctScale::Eighth))?)
+	}
+
+	/// A copy of this decoder reading `input`, which must have the same frame header, at `scale`.
+	fn with_input(&self, input: Vec<u8>, scale: DctScale) -> JpegDecoder {
+		JpegDecoder {
+			input,
+			orig_color_space: self.orig_color_space,
+			width: self.width,
+			height: self.height,
+			limits: self.limits.clone(),
+			orientation: self.orientation,
+			scale,
+			inverted_cmyk: self.inverted_cmyk,
+			truncated: self.truncated,
+			repaired_intervals: self.repaired_intervals,
+			mpo_images: self.mpo_images,
+			fallback: self.fallback.clone(),
+		}
+	}
+
+	/// Decodes at `self.scale` with jpeg-decoder's scaled IDCT, so the full size image is never allocated, to the
+	/// `channels` interleaved samples `transform` gives. jpeg-decoder gives up on damaged files zune-jpeg decodes what it
+	/// can of, so any failure returns `None`, leaving the caller to decode at full size with zune-jpeg and box filter.
+	fn decode_scaled(&self, transform: jpeg_decoder::ColorTransform, channels: usize) -> Option<Vec<u8>> {
+		let (width, height) = self.dimensions();
+		let mut decoder = jpeg_decoder::Decoder::new(self.input.as_slice());
+		decoder.set_color_transform(transform);
+		// jpeg-decoder picks the smallest scale reaching the requested size on either side, so only the longer side is
+		// asked for: a smaller scale reaches it only when it comes out at one pixel, and then so does the shorter side
+		let requested = if self.width >= self.height { (width as u16, u16::MAX) } else { (u16::MAX, height as u16) };
+		let scaled = decoder.scale(requested.0, requested.1).ok()?;
+		let data = decoder.decode().ok()?;
+		let expected_len = width as usize * height as usize * channels;
+		((u32::from(scaled.0), u32::from(scaled.1)) == (width, height) && data.len() == expected_len).then_some(data)
+	}
+
+	/// The dimensions before DCT scaling.
+	pub fn full_dimensions(&self) -> (u32, u32) {
+		(u32::from(self.width), u32::from(self.height))
+	}
+
+	/// Changes the scale set by `JpegOptions::dct_scale`. 16-bit lossless JPEGs can't be scaled and are left as they
+	/// are, returning false.
+	pub fn set_dct_scale(&mut self, scale: DctScale) -> bool {
+		if self.fallback.as_ref().is_some_and(|fallback| fallback.color_type == ColorType::L16) {
+			return false;
+		}
+		self.scale = scale;
+		true
+	}
+
+	/// Decodes to 8-bit gray. For YCbCr and grayscale files only the Y plane is decoded, skipping chroma entirely, which
+	/// is close to half the work (DCT scaled decodes skip just the color conversion); other files (RGB, CMYK, lossless)
+	/// are decoded in full and converted with `convert::into_luma8`. DCT scaling applies as for `read_image`.
+	pub fn decode_luma(self) -> Result<GrayImage, Error> {
+		if self.fallback.is_some() || !matches!(self.orig_color_space, ZuneColorSpace::YCbCr | ZuneColorSpace::Luma) {
+			return Ok(convert::into_luma8(DynamicImage::from_decoder(self)?));
+		}
+
+		let (width, height) = self.dimensions();
+		// Without color conversion (which jpeg-decoder's RGB transform amounts to for a YCbCr file) the first channel is Y
+		let scaled = match self.orig_color_space {
+			_ if self.scale == DctScale::Full => None,
+			ZuneColorSpace::Luma => self.decode_scaled(jpeg_decoder::ColorTransform::Grayscale, 1),
+			_ => self.decode_scaled(jpeg_decoder::ColorTransform::RGB, 3).map(|data| data.into_iter().step_by(3).collect()),
+		};
+		if let Some(data) = scaled {
+			return Ok(GrayImage::from_raw(width, height, data).expect("buffer size matches the dimensions"));
+		}
+
+		let mut decoder = new_zune_decoder(&self.input, ZuneColorSpace::Luma, self.limits);
+		let full = decoder.decode().map_err(err_from_jpeg)?;
+		let data = match self.scale {
+			DctScale::Full => full,
+			scale => {
+				let mut out = vec![0; crate::error::pixel_buffer_len(width, height, 1)?];
+				box_downscale(&full, usize::from(self.width), usize::from(self.height), 1, scale.denominator() as usize, &mut out);
+				out
+			},
+		};
+		Ok(GrayImage::from_raw(width, height, data).expect("buffer size matches the dimensions"))
+	}
+
+	/// Decodes to 8-bit RGBA. At full size, YCbCr and RGB files are color converted straight into the RGBA output by
+	/// zune-jpeg, so no RGB image is held alongside it; DCT scaled decodes and other files (grayscale, CMYK, lossless)
+	/// go through `read_image` and are converted with `convert::into_rgba8`.
+	pub fn decode_rgba8(self) -> Result<RgbaImage, Error> {
+		if self.fallback.is_some() || self.scale != DctScale::Full || !matches!(self.orig_color_space, ZuneColorSpace::YCbCr | ZuneColorSpace::RGB) {
+			return Ok(convert::into_rgba8(DynamicImage::from_decoder(self)?));
+		}
+
+		let (width, height) = self.dimensions();
+		let mut decoder = new_zune_decoder(&self.input, ZuneColorSpace::RGBA, self.limits);
+		let mut data = vec![0; crate::error::pixel_buffer_len(width, height, 4)?];
+		decoder.decode_into(&mut data).map_err(err_from_jpeg)?;
+		Ok(RgbaImage::from_raw(width, height, data).expect("buffer size matches the dimensions"))
+	}
+
+	/// Yields a preview after each scan, ending with the fully decoded image.
+	pub fn refinements(&self) -> Refinements<'_> {
+		Refinements {
+			decoder: self,
+			scans: 0,
+			total: self.scan_count().max(1),
+		}
+	}
+}
+
+
+/// The frame header of a JPEG, as read by `JpegDecoder::read_header`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JpegHeader {
+	pub width: u32,
+	pub height: u32,
+	/// Bits per sample
+	pub precision: u8,
+	/// 1 for grayscale, 3 for YCbCr or RGB, 4 for CMYK or YCCK
+	pub components: u8,
+	pub progressive: bool,
+	/// What `JpegDecoder` will decode the image to
+	pub color_type: ColorType,
+}
+
+
+pub struct Refinements<'a> {
+	decoder: &'a JpegDecoder,
+	scans: usize,
+	total: usize,
+}
+
+
+pub struct Refinement {
+	/// Number of scans that went into `image`
+	pub scans: usize,
+	/// True for the last refinement, which is the fully decoded image
+	pub is_final: bool,
+	pub image: DynamicImage,
+}
+
+impl Iterator for Refinements<'_> {
+	type Item = Result<Refinement, Error>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.scans >= self.total {
+			return None;
+		}
+		self.scans += 1;
+		let is_final = self.scans == self.total;
+		Some(self.decoder.preview(self.scans).map(|image| Refinement {
+			scans: self.scans,
+			is_final,
+			image,
+		}))
+	}
 }
 
 impl ImageDecoder for JpegDecoder {
 	fn dimensions(&self) -> (u32, u32) {
-		(u32::from(self.width), u32::from(self.height))
+		self.scale.scale_dimensions(u32::from(self.width), u32::from(self.height))
 	}
 
 	fn color_type(&self) -> ColorType {
-		colortype_from_jpeg(self.orig_color_space)
+		match &self.fallback {
+			Some(fallback) => fallback.color_type,
+			None => colortype_from_jpeg(self.orig_color_space),
+		}
 	}
 
 	fn icc_profile(&mut self) -> ImageResult<Option<Vec<u8>>> {
+		if let Some(fallback) = &self.fallback {
+			return Ok(fallback.icc_profile.clone());
+		}
 		let options = zune_core::options::DecoderOptions::default()
 			.set_strict_mode(false)
 			.set_max_width(usize::MAX)
@@ -86,13 +448,18 @@ impl ImageDecoder for JpegDecoder {
 	}
 
 	fn exif_metadata(&mut self) -> ImageResult<Option<Vec<u8>>> {
-		let options = zune_core::options::DecoderOptions::default()
-			.set_strict_mode(false)
-			.set_max_width(usize::MAX)
-			.set_max_height(usize::MAX);
-		let mut decoder = zune_jpeg::JpegDecoder::new_with_options(ZCursor::new(&self.input), options);
-		decoder.decode_headers().map_err(err_from_jpeg)?;
-		let exif = decoder.exif().cloned();
+		let exif = match &self.fallback {
+			Some(fallback) => fallback.exif.clone(),
+			None => {
+				let options = zune_core::options::DecoderOptions::default()
+					.set_strict_mode(false)
+					.set_max_width(usize::MAX)
+					.set_max_height(usize::MAX);
+				let mut decoder = zune_jpeg::JpegDecoder::new_with_options(ZCursor::new(&self.input), options);
+				decoder.decode_headers().map_err(err_from_jpeg)?;
+				decoder.exif().cloned()
+			},
+		};
 
 		self.orientation = Some(
 			exif.as_ref()
@@ -103,26 +470,14 @@ impl ImageDecoder for JpegDecoder {
 		Ok(exif)
 	}
 
+	/// The standard XMP packet. See `extended_xmp` for the rest of a packet too large for one segment.
 	fn xmp_metadata(&mut self) -> ImageResult<Option<Vec<u8>>> {
-		let options = zune_core::options::DecoderOptions::default()
-			.set_strict_mode(false)
-			.set_max_width(usize::MAX)
-			.set_max_height(usize::MAX);
-		let mut decoder = zune_jpeg::JpegDecoder::new_with_options(ZCursor::new(&self.input), options);
-		decoder.decode_headers().map_err(err_from_jpeg)?;
-
-		Ok(decoder.xmp().cloned())
+		Ok(jpeg_markers::xmp(&jpeg_markers::header_segments(&self.input)))
 	}
 
+	/// The IPTC-IIM records of the Photoshop image resources in the APP13 segments, unwrapped. See `iptc::Iptc`.
 	fn iptc_metadata(&mut self) -> ImageResult<Option<Vec<u8>>> {
-		let options = zune_core::options::DecoderOptions::default()
-			.set_strict_mode(false)
-			.set_max_width(usize::MAX)
-			.set_max_height(usize::MAX);
-		let mut decoder = zune_jpeg::JpegDecoder::new_with_options(ZCursor::new(&self.input), options);
-		decoder.decode_headers().map_err(err_from_jpeg)?;
-
-		Ok(decoder.iptc().cloned())
+		Ok(jpeg_markers::iptc(&jpeg_markers::header_segments(&self.input)))
 	}
 
 	fn orientation(&mut self) -> ImageResult<Orientation> {
@@ -134,28 +489,62 @@ impl ImageDecoder for JpegDecoder {
 	}
 
 	fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
-		let advertised_len = self.total_bytes();
-		let actual_len = buf.len() as u64;
+		crate::error::check_buffer(buf, self.total_bytes())?;
+
+		let channels = usize::from(self.color_type().channel_count());
+		if let Some(fallback) = &self.fallback {
+			let data = fallback.decode(&self.input)?;
+			match self.scale {
+				DctScale::Full => buf.copy_from_slice(&data),
+				scale => box_downscale(&data, usize::from(self.width), usize::from(self.height), channels, scale.denominator() as usize, buf),
+			}
+			return Ok(());
+		}
+		let is_cmyk = matches!(to_supported_color_space(self.orig_color_space), ZuneColorSpace::CMYK | ZuneColorSpace::YCCK);
+		if self.scale != DctScale::Full
+			&& let Some(data) = self.decode_scaled(jpeg_decoder_transform(self.orig_color_space), if is_cmyk { 4 } else { channels })
+		{
+			if is_cmyk {
+				// jpeg-decoder complements both CMYK and YCCK samples, which undoes the Adobe inversion
+				convert::cmyk_to_rgb(&data, !self.inverted_cmyk, buf);
+			} else {
+				buf.copy_from_slice(&data);
+			}
+			return Ok(());
+		}
 
-		if actual_len != advertised_len {
-			return Err(ImageError::Decoding(DecodingError::new(
-				ImageFormat::Jpeg.into(),
-				format!(
-					"Length of the decoded data {actual_len} \
-                    doesn't match the advertised dimensions of the image \
-                    that imply length {advertised_len}"
-				),
-			)));
+		let mut decoder = new_zune_decoder(&self.input, to_supported_color_space(self.orig_color_space), self.limits);
+		if self.scale == DctScale::Full && !is_cmyk {
+			decoder.decode_into(buf).map_err(err_from_jpeg)?;
+			return Ok(());
 		}
 
-		let mut decoder = new_zune_decoder(&self.input, self.orig_color_space, self.limits);
-		decoder.decode_into(buf).map_err(err_from_jpeg)?;
+		let mut full = decoder.decode().map_err(err_from_jpeg)?;
+		if is_cmyk {
+			if self.orig_color_space == ZuneColorSpace::YCCK {
+				// The YCC samples are RGB, the complement of the stored CMY, as in libjpeg
+				for pixel in full.chunks_exact_mut(4) {
+					let rgb = convert::ycbcr_to_rgb(pixel[0], pixel[1], pixel[2], YCbCrMatrix::Bt601);
+					for (sample, value) in pixel.iter_mut().zip(rgb) {
+						*sample = 255 - value;
+					}
+				}
+			}
+			let mut rgb = vec![0; full.len() / 4 * 3];
+			convert::cmyk_to_rgb(&full, self.inverted_cmyk, &mut rgb);
+			full = rgb;
+		}
+		match self.scale {
+			DctScale::Full => buf.copy_from_slice(&full),
+			scale => box_downscale(&full, usize::from(self.width), usize::from(self.height), channels, scale.denominator() as usize, buf),
+		}
 		Ok(())
 	}
 
 	fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
 		limits.check_support(&image::LimitSupport::default())?;
-		let (width, height) = self.dimensions();
+		// Check the full size image, since that's what zune-jpeg allocates
+		let (width, height) = (u32::from(self.width), u32::from(self.height));
 		limits.check_dimensions(width, height)?;
 		self.limits = limits;
 		Ok(())
@@ -167,8 +556,85 @@ impl ImageDecoder for JpegDecoder {
 }
 
 
-fn new_zune_decoder(input: &[u8], orig_color_space: ZuneColorSpace, limits: Limits) -> zune_jpeg::JpegDecoder<ZCursor<&[u8]>> {
-	let target_color_space = to_supported_color_space(orig_color_space);
+#[cfg(feature = "testing")]
+fn record_coverage(decoder: &zune_jpeg::JpegDecoder<ZCursor<&[u8]>>, orig_color_space: ZuneColorSpace) {
+	use crate::coverage::{Branch, record};
+
+	let is_progressive = decoder.info().is_some_and(|info| info.sof.is_progressive());
+	record(if is_progressive { Branch::JpegProgressive } else { Branch::JpegBaseline });
+	record(match orig_color_space {
+		ZuneColorSpace::Luma | ZuneColorSpace::LumaA => Branch::JpegGrayscale,
+		_ => Branch::JpegColor,
+	});
+	if decoder.icc_profile().is_some() {
+		record(Branch::JpegIccProfile);
+	}
+}
+
+
+fn header_options() -> zune_core::options::DecoderOptions {
+	zune_core::options::DecoderOptions::default()
+		.set_strict_mode(false)
+		.set_max_width(usize::MAX)
+		.set_max_height(usize::MAX)
+}
+
+
+/// Byte offsets just past the entropy coded data of each scan, found by walking the marker segments.
+fn scan_ends(input: &[u8]) -> Vec<usize> {
+	let mut ends = Vec::new();
+	if !input.starts_with(&[0xFF, 0xD8]) {
+		return ends;
+	}
+
+	let segment_length = |pos: usize| input.get(pos..pos + 2).map(|b| usize::from(u16::from_be_bytes([b[0], b[1]])));
+	let mut pos = 2;
+	loop {
+		// Skip to the next marker, including any fill bytes
+		while input.get(pos).is_some_and(|&b| b != 0xFF) {
+			pos += 1;
+		}
+		while input.get(pos) == Some(&0xFF) {
+			pos += 1;
+		}
+		let Some(&marker) = input.get(pos) else {
+			break;
+		};
+		pos += 1;
+
+		match marker {
+			0xD9 => break,
+			0x01 | 0xD0..=0xD7 => (),
+			0xDA => {
+				let Some(len) = segment_length(pos) else { break };
+				pos += len;
+				// Entropy coded data runs until a marker other than a stuffed zero or a restart marker, or the end of the file
+				while let Some(offset) = input.get(pos..).map(|rest| rest.iter().position(|&b| b == 0xFF).unwrap_or(rest.len())) {
+					pos += offset;
+					match input.get(pos + 1) {
+						Some(0x00 | 0xD0..=0xD7) => pos += 2,
+						// The end of the file, possibly halfway through a stuffed byte
+						None => {
+							pos = input.len();
+							break;
+						},
+						_ => break,
+					}
+				}
+				pos = pos.min(input.len());
+				ends.push(pos);
+			},
+			_ => {
+				let Some(len) = segment_length(pos) else { break };
+				pos += len;
+			},
+		}
+	}
+	ends
+}
+
+
+fn new_zune_decoder(input: &[u8], target_color_space: ZuneColorSpace, limits: Limits) -> zune_jpeg::JpegDecoder<ZCursor<&[u8]>> {
 	let mut options = zune_core::options::DecoderOptions::default()
 		.jpeg_set_out_colorspace(target_color_space)
 		.set_strict_mode(false);
@@ -184,10 +650,106 @@ fn new_zune_decoder(input: &[u8], orig_color_space: ZuneColorSpace, limits: Limi
 }
 
 
+/// Whether the file has an Adobe APP14 marker. Its transform flag (YCCK or not) is read by zune-jpeg; its presence
+/// alone means CMYK is stored inverted.
+fn has_adobe_marker(input: &[u8]) -> bool {
+	jpeg_markers::header_segments(input).iter().any(|(marker, payload)| *marker == 0xEE && payload.starts_with(b"Adobe"))
+}
+
+
+/// Byte ranges of the images listed in the MP index (CIPA DC-007) of a multi-picture file, the primary image first, or
+/// `None` if there is no MP index.
+pub(crate) fn mp_entries(input: &[u8]) -> Option<Vec<Range<usize>>> {
+	const NUMBER_OF_IMAGES: u16 = 0xB001;
+	const MP_ENTRY: u16 = 0xB002;
+
+	let segments = jpeg_markers::header_segments(input);
+	let payload = segments.iter().find(|(marker, payload)| *marker == 0xE2 && payload.starts_with(b"MPF\0"))?.1;
+	// Offsets in the index are relative to its TIFF style header, just past the "MPF\0" identifier
+	let header_start = payload.as_ptr() as usize - input.as_ptr() as usize + 4;
+	let tiff = &payload[4..];
+	let big_endian = match tiff.get(0..4)? {
+		b"MM\0*" => true,
+		b"II*\0" => false,
+		_ => return None,
+	};
+	let u16_at = |at: usize| tiff.get(at..at.checked_add(2)?).map(|b| if big_endian { u16::from_be_bytes([b[0], b[1]]) } else { u16::from_le_bytes([b[0], b[1]]) });
+	let u32_at = |at: usize| {
+		tiff.get(at..at.checked_add(4)?)
+			.map(|b| if big_endian { u32::from_be_bytes([b[0], b[1], b[2], b[3]]) } else { u32::from_le_bytes([b[0], b[1], b[2], b[3]]) })
+	};
+
+	let ifd = u32_at(4)? as usize;
+	let (mut count, mut entries) = (None, None);
+	for i in 0..usize::from(u16_at(ifd)?) {
+		let entry = ifd + 2 + i * 12;
+		match u16_at(entry)? {
+			NUMBER_OF_IMAGES => count = u32_at(entry + 8),
+			MP_ENTRY => entries = u32_at(entry + 8),
+			_ => (),
+		}
+	}
+
+	let count = count? as usize;
+	let entries = entries? as usize;
+	let mut images = Vec::new();
+	for i in 0..count {
+		let entry = entries.checked_add(i * 16)?;
+		let size = u32_at(entry + 4)? as usize;
+		// The primary image's offset is 0, meaning the start of the file rather than of the header
+		let start = match u32_at(entry + 8)? as usize {
+			0 => Some(0),
+			offset => header_start.checked_add(offset),
+		};
+		// Sizes and offsets near 4 GiB don't fit a 32-bit usize, and can't be in the file anyway
+		let Some((start, end)) = start.and_then(|start| Some((start, start.checked_add(size)?))) else {
+			continue;
+		};
+		images.push(start.min(input.len())..end.min(input.len()));
+	}
+	Some(images)
+}
+
+
+/// Averages each `factor` x `factor` block of `src` (8-bit, interleaved) into one pixel of `dst`, clipping blocks at
+/// the right and bottom edges.
+fn box_downscale(src: &[u8], width: usize, height: usize, channels: usize, factor: usize, dst: &mut [u8]) {
+	let dst_width = width.div_ceil(factor);
+	for (dy, dst_row) in dst.chunks_exact_mut(dst_width * channels).enumerate() {
+		let ys = dy * factor..((dy + 1) * factor).min(height);
+		for (dx, dst_pixel) in dst_row.chunks_exact_mut(channels).enumerate() {
+			let xs = dx * factor..((dx + 1) * factor).min(width);
+			let count = (ys.len() * xs.len()) as u32;
+			for (c, out) in dst_pixel.iter_mut().enumerate() {
+				let sum: u32 = ys.clone().flat_map(|y| xs.clone().map(move |x| u32::from(src[(y * width + x) * channels + c]))).sum();
+				*out = ((sum + count / 2) / count) as u8;
+			}
+		}
+	}
+}
+
+
+/// The jpeg-decoder color transform matching zune-jpeg's reading of the file's color space. CMYK and YCCK come out
+/// complemented, and YCCK converted to CMYK.
+fn jpeg_decoder_transform(orig: ZuneColorSpace) -> jpeg_decoder::ColorTransform {
+	use jpeg_decoder::ColorTransform;
+	match orig {
+		ZuneColorSpace::Luma => ColorTransform::Grayscale,
+		ZuneColorSpace::RGB => ColorTransform::RGB,
+		ZuneColorSpace::CMYK => ColorTransform::CMYK,
+		ZuneColorSpace::YCCK => ColorTransform::YCCK,
+		_ => ColorTransform::YCbCr,
+	}
+}
+
+
 fn to_supported_color_space(orig: ZuneColorSpace) -> ZuneColorSpace {
 	use zune_core::colorspace::ColorSpace::*;
 	match orig {
 		RGB | RGBA | Luma | LumaA => orig,
+		// Converted to RGB by us, to get the Adobe inversion right. zune-jpeg can't undo the YCCK transform without
+		// converting to RGB as well, so YCCK samples come out as stored
+		CMYK | YCCK => orig,
 		// the rest is not supported by `image` so it will be converted to RGB during decoding
 		_ => RGB,
 	}
@@ -217,6 +779,8 @@ fn colortype_from_jpeg(colorspace: ZuneColorSpace) -> ColorType {
 		RGBA => ColorType::Rgba8,
 		Luma => ColorType::L8,
 		LumaA => ColorType::La8,
+		// Converted after decoding
+		CMYK | YCCK => ColorType::Rgb8,
 		// to_supported_color_space() doesn't return any of the other variants
 		_ => unreachable!(),
 	}
@@ -0,0 +1,169 @@
+//! Decoding of the JPEG processes zune-jpeg doesn't implement: lossless (SOF3) through jpeg-decoder, and arithmetic
+//! coding (SOF9, SOF10) through libjpeg-turbo as bundled by mozjpeg, with the `jpeg-arithmetic` feature.
+//!
+//! These files are rare enough (DICOM exports, old archives) that they're decoded in one go, once their frame header has
+//! been checked against the limits, and their metadata is read from the marker segments (see `jpeg_markers`) since
+//! zune-jpeg rejects them before getting that far.
+
+use image::{
+	ColorType, ImageError, ImageFormat, ImageResult,
+	error::{DecodingError, UnsupportedError, UnsupportedErrorKind},
+};
+
+use crate::{error::Error, jpeg_markers};
+
+
+const SOF_LOSSLESS: u8 = 0xC3;
+const SOF_ARITHMETIC_SEQUENTIAL: u8 = 0xC9;
+const SOF_ARITHMETIC_PROGRESSIVE: u8 = 0xCA;
+const SOF_ARITHMETIC_LOSSLESS: u8 = 0xCB;
+
+
+/// The frame header and metadata of a file zune-jpeg can't decode, with the metadata zune-jpeg would otherwise have
+/// provided. The image itself isn't decoded until `decode`, so size limits can be checked first.
+#[derive(Clone)]
+pub(crate) struct Fallback {
+	pub width: u16,
+	pub height: u16,
+	pub color_type: ColorType,
+	pub icc_profile: Option<Vec<u8>>,
+	pub exif: Option<Vec<u8>>,
+	marker: u8,
+	precision: u8,
+}
+
+
+/// Reads the frame header of `input` if its frame type is one zune-jpeg can't handle, returning `None` otherwise.
+pub(crate) fn probe(input: &[u8]) -> Result<Option<Fallback>, Error> {
+	let segments = jpeg_markers::header_segments(input);
+	let Some(&(marker, frame)) = segments.iter().find(|(marker, _)| matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC)) else {
+		return Ok(None);
+	};
+	match marker {
+		SOF_LOSSLESS | SOF_ARITHMETIC_SEQUENTIAL | SOF_ARITHMETIC_PROGRESSIVE => (),
+		SOF_ARITHMETIC_LOSSLESS => return Err(unsupported("lossless arithmetic coding").into()),
+		_ => return Ok(None),
+	}
+	#[cfg(not(feature = "jpeg-arithmetic"))]
+	if marker != SOF_LOSSLESS {
+		return Err(unsupported("arithmetic coding without the jpeg-arithmetic feature").into());
+	}
+	let &[precision, h1, h0, w1, w0, components, ..] = frame else {
+		return Err(Error::Decoding(DecodingError::new(ImageFormat::Jpeg.into(), "truncated frame header")));
+	};
+
+	// What jpeg-decoder and libjpeg-turbo decode each component count to
+	let color_type = match components {
+		1 if marker == SOF_LOSSLESS && precision > 8 => ColorType::L16,
+		1 => ColorType::L8,
+		3 => ColorType::Rgb8,
+		4 if marker == SOF_LOSSLESS => return Err(unsupported("lossless CMYK").into()),
+		4 => return Err(unsupported("arithmetic coded CMYK").into()),
+		_ => return Err(unsupported(&format!("{components} component images")).into()),
+	};
+
+	let app_payload = |app: u8, prefix: &[u8]| {
+		segments
+			.iter()
+			.find(|(marker, payload)| *marker == app && payload.starts_with(prefix))
+			.map(|(_, payload)| payload[prefix.len()..].to_vec())
+	};
+	Ok(Some(Fallback {
+		width: u16::from_be_bytes([w1, w0]),
+		height: u16::from_be_bytes([h1, h0]),
+		color_type,
+		icc_profile: jpeg_markers::icc_profile(&segments),
+		exif: app_payload(0xE1, b"Exif\0\0"),
+		marker,
+		precision,
+	}))
+}
+
+
+impl Fallback {
+	/// Decodes `input`, the file `probe` read this frame header from, to interleaved samples, native endian if 16-bit.
+	pub(crate) fn decode(&self, input: &[u8]) -> ImageResult<Vec<u8>> {
+		let (width, height, color_type, data) = match self.marker {
+			SOF_LOSSLESS => decode_lossless(input, self.precision)?,
+			#[cfg(feature = "jpeg-arithmetic")]
+			_ => decode_arithmetic(input)?,
+			#[cfg(not(feature = "jpeg-arithmetic"))]
+			_ => unreachable!("probe rejects arithmetic coding without the jpeg-arithmetic feature"),
+		};
+		if (width, height, color_type) != (self.width, self.height, self.color_type) {
+			return Err(ImageError::Decoding(DecodingError::new(ImageFormat::Jpeg.into(), "decoded image doesn't match the frame header")));
+		}
+		Ok(data)
+	}
+}
+
+
+fn decode_lossless(input: &[u8], precision: u8) -> ImageResult<(u16, u16, ColorType, Vec<u8>)> {
+	let mut decoder = jpeg_decoder::Decoder::new(input);
+	let data = decoder.decode().map_err(error_from_jpeg_decoder)?;
+	let info = decoder.info().expect("decode read the headers");
+	let color_type = match info.pixel_format {
+		jpeg_decoder::PixelFormat::L8 => ColorType::L8,
+		jpeg_decoder::PixelFormat::L16 => ColorType::L16,
+		jpeg_decoder::PixelFormat::RGB24 => ColorType::Rgb8,
+		jpeg_decoder::PixelFormat::CMYK32 => return Err(unsupported("lossless CMYK")),
+	};
+
+	// jpeg-decoder returns 16-bit samples native endian and unscaled, so 12-bit samples only reach 4095. Scale them to the
+	// full range like every other 16-bit output.
+	let data = if color_type == ColorType::L16 {
+		let max = (1u32 << precision.clamp(9, 16)) - 1;
+		data.chunks_exact(2)
+			.flat_map(|v| {
+				let v = u32::from(u16::from_ne_bytes([v[0], v[1]])).min(max);
+				(((v * 65535 + max / 2) / max) as u16).to_ne_bytes()
+			})
+			.collect()
+	} else {
+		data
+	};
+	Ok((info.width, info.height, color_type, data))
+}
+
+
+#[cfg(feature = "jpeg-arithmetic")]
+fn decode_arithmetic(input: &[u8]) -> ImageResult<(u16, u16, ColorType, Vec<u8>)> {
+	let decompress = mozjpeg::Decompress::new_mem(input).map_err(error_from_mozjpeg)?;
+	let is_gray = decompress.color_space() == mozjpeg::ColorSpace::JCS_GRAYSCALE;
+	if matches!(decompress.color_space(), mozjpeg::ColorSpace::JCS_CMYK | mozjpeg::ColorSpace::JCS_YCCK) {
+		return Err(unsupported("arithmetic coded CMYK"));
+	}
+	let (width, height) = decompress.size();
+	let (width, height) = (width as u16, height as u16);
+	if is_gray {
+		let mut started = decompress.grayscale().map_err(error_from_mozjpeg)?;
+		let data: Vec<u8> = started.read_scanlines().map_err(error_from_mozjpeg)?;
+		started.finish().map_err(error_from_mozjpeg)?;
+		Ok((width, height, ColorType::L8, data))
+	} else {
+		let mut started = decompress.rgb().map_err(error_from_mozjpeg)?;
+		let pixels: Vec<[u8; 3]> = started.read_scanlines().map_err(error_from_mozjpeg)?;
+		started.finish().map_err(error_from_mozjpeg)?;
+		Ok((width, height, ColorType::Rgb8, pixels.concat()))
+	}
+}
+
+
+fn unsupported(what: &str) -> ImageError {
+	ImageError::Unsupported(UnsupportedError::from_format_and_kind(ImageFormat::Jpeg.into(), UnsupportedErrorKind::GenericFeature(what.to_string())))
+}
+
+
+fn error_from_jpeg_decoder(err: jpeg_decoder::Error) -> ImageError {
+	match err {
+		jpeg_decoder::Error::Io(err) => ImageError::IoError(err),
+		jpeg_decoder::Error::Unsupported(feature) => unsupported(&format!("{feature:?}")),
+		err => ImageError::Decoding(DecodingError::new(ImageFormat::Jpeg.into(), err)),
+	}
+}
+
+
+#[cfg(feature = "jpeg-arithmetic")]
+fn error_from_mozjpeg(err: std::io::Error) -> ImageError {
+	ImageError::Decoding(DecodingError::new(ImageFormat::Jpeg.into(), err))
+}
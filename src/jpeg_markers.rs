@@ -0,0 +1,125 @@
+//! The marker segments of a JPEG's header, and the metadata carried in them: ICC profiles in APP2, XMP, standard and
+//! extended, in APP1, and IPTC in APP13.
+
+
+/// The marker segments up to the first scan, as (marker, payload).
+pub(crate) fn header_segments(input: &[u8]) -> Vec<(u8, &[u8])> {
+	let mut segments = Vec::new();
+	if !input.starts_with(&[0xFF, 0xD8]) {
+		return segments;
+	}
+
+	let mut pos = 2;
+	loop {
+		while input.get(pos) == Some(&0xFF) {
+			pos += 1;
+		}
+		let Some(&marker) = input.get(pos) else {
+			break;
+		};
+		pos += 1;
+		if matches!(marker, 0x01 | 0xD0..=0xD7) {
+			continue;
+		}
+		let Some(len) = input.get(pos..pos + 2).map(|b| usize::from(u16::from_be_bytes([b[0], b[1]]))) else {
+			break;
+		};
+		let Some(payload) = input.get(pos + 2..pos + len.max(2)) else {
+			break;
+		};
+		segments.push((marker, payload));
+		if marker == 0xDA || marker == 0xD9 {
+			break;
+		}
+		pos += len.max(2);
+	}
+	segments
+}
+
+
+/// Reassembles an ICC profile split across APP2 segments, which carry their sequence numbers.
+pub(crate) fn icc_profile(segments: &[(u8, &[u8])]) -> Option<Vec<u8>> {
+	const PREFIX: &[u8] = b"ICC_PROFILE\0";
+	let mut chunks: Vec<(u8, &[u8])> = segments
+		.iter()
+		.filter(|(marker, payload)| *marker == 0xE2 && payload.starts_with(PREFIX) && payload.len() >= PREFIX.len() + 2)
+		.map(|(_, payload)| (payload[PREFIX.len()], &payload[PREFIX.len() + 2..]))
+		.collect();
+	if chunks.is_empty() {
+		return None;
+	}
+	chunks.sort_by_key(|(sequence, _)| *sequence);
+	Some(chunks.into_iter().flat_map(|(_, chunk)| chunk.iter().copied()).collect())
+}
+
+
+const XMP_PREFIX: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const XMP_EXTENSION_PREFIX: &[u8] = b"http://ns.adobe.com/xmp/extension/\0";
+const PHOTOSHOP_PREFIX: &[u8] = b"Photoshop 3.0\0";
+
+
+/// The standard XMP packet, from the first APP1 segment carrying one.
+pub(crate) fn xmp(segments: &[(u8, &[u8])]) -> Option<Vec<u8>> {
+	segments
+		.iter()
+		.find(|(marker, payload)| *marker == 0xE1 && payload.starts_with(XMP_PREFIX))
+		.map(|(_, payload)| payload[XMP_PREFIX.len()..].to_vec())
+}
+
+
+/// Reassembles the ExtendedXMP packet the standard packet `xmp` points to (with `xmpNote:HasExtendedXMP`), which
+/// writers split across as many APP1 segments as it takes, each with the packet's GUID, full length and the offset of
+/// its chunk. `None` if there's none, or its chunks don't exactly cover the packet.
+pub(crate) fn extended_xmp(segments: &[(u8, &[u8])], xmp: &[u8]) -> Option<Vec<u8>> {
+	let guid = extended_xmp_guid(xmp)?;
+	// (full length, offset, chunk)
+	let chunks: Vec<(usize, usize, &[u8])> = segments
+		.iter()
+		.filter(|(marker, _)| *marker == 0xE1)
+		.filter_map(|(_, payload)| payload.strip_prefix(XMP_EXTENSION_PREFIX))
+		.filter(|rest| rest.len() >= 40 && &rest[..32] == guid)
+		.map(|rest| {
+			let full = u32::from_be_bytes([rest[32], rest[33], rest[34], rest[35]]) as usize;
+			let offset = u32::from_be_bytes([rest[36], rest[37], rest[38], rest[39]]) as usize;
+			(full, offset, &rest[40..])
+		})
+		.collect();
+	let full = chunks.first()?.0;
+	// The chunks have to add up to the full length before it's trusted for the allocation
+	if chunks.iter().any(|&(length, ..)| length != full) || chunks.iter().map(|(_, _, chunk)| chunk.len()).sum::<usize>() != full {
+		return None;
+	}
+
+	let mut packet = vec![0; full];
+	let mut covered = vec![false; full];
+	for (_, offset, chunk) in chunks {
+		let range = offset..offset.checked_add(chunk.len()).filter(|&end| end <= full)?;
+		packet[range.clone()].copy_from_slice(chunk);
+		covered[range].fill(true);
+	}
+	covered.iter().all(|&covered| covered).then_some(packet)
+}
+
+
+/// The GUID of `xmpNote:HasExtendedXMP`, as an attribute or an element: 32 hex digits, the MD5 of the extended packet.
+fn extended_xmp_guid(xmp: &[u8]) -> Option<&[u8]> {
+	const KEY: &[u8] = b"xmpNote:HasExtendedXMP";
+	let at = xmp.windows(KEY.len()).position(|window| window == KEY)? + KEY.len();
+	let start = at + xmp[at..].iter().position(|b| !matches!(b, b'=' | b'"' | b'\'' | b'>' | b' ' | b'\t' | b'\r' | b'\n'))?;
+	let guid = xmp.get(start..start + 32)?;
+	guid.iter().all(u8::is_ascii_hexdigit).then_some(guid)
+}
+
+
+/// The IPTC-IIM records of the Photoshop image resources in APP13 segments, which writers continue across segments
+/// when they don't fit in one.
+pub(crate) fn iptc(segments: &[(u8, &[u8])]) -> Option<Vec<u8>> {
+	let resources: Vec<u8> = segments
+		.iter()
+		.filter(|(marker, _)| *marker == 0xED)
+		.filter_map(|(_, payload)| payload.strip_prefix(PHOTOSHOP_PREFIX))
+		.flatten()
+		.copied()
+		.collect();
+	crate::iptc::from_photoshop_resources(&resources).map(<[u8]>::to_vec)
+}
@@ -0,0 +1,221 @@
+//! Salvaging of sequential JPEGs with corrupt restart intervals, for `JpegOptions::resync`.
+//!
+//! Restart markers reset the entropy decoder, so the interval between two of them decodes independently of the rest.
+//! Each interval is checked by Huffman decoding it: one that hits an invalid code, overruns a block, or runs out of
+//! data before its last MCU is damaged, and its entropy coded data is replaced by that of the nearest intact interval.
+//! The damaged MCUs then decode as a copy of their neighbors' instead of as garbage, or failing the whole image.
+
+use std::ops::Range;
+
+use crate::{
+	error::Error,
+	jpeg_dc::{self, BitReader, Huffman},
+};
+
+
+struct Component {
+	id: u8,
+	h: usize,
+	v: usize,
+}
+
+
+/// Returns `input` with its damaged restart intervals replaced and how many were replaced, or `None` if there's
+/// nothing to repair: no damage, no restart markers, or a progressive or arithmetic coded file.
+pub(crate) fn repair(input: &[u8]) -> Result<Option<(Vec<u8>, usize)>, Error> {
+	if !input.starts_with(&[0xFF, 0xD8]) {
+		return Ok(None);
+	}
+
+	let mut dc_tables: [Option<Huffman>; 4] = Default::default();
+	let mut ac_tables: [Option<Huffman>; 4] = Default::default();
+	let mut components = Vec::new();
+	let (mut width, mut height, mut precision) = (0, 0, 8);
+	let mut restart_interval = 0;
+	let mut replacements: Vec<(Range<usize>, Range<usize>)> = Vec::new();
+
+	let segment_length = |pos: usize| input.get(pos..pos + 2).map(|b| usize::from(u16::from_be_bytes([b[0], b[1]])));
+	let mut pos = 2;
+	loop {
+		while input.get(pos).is_some_and(|&b| b != 0xFF) {
+			pos += 1;
+		}
+		while input.get(pos) == Some(&0xFF) {
+			pos += 1;
+		}
+		let Some(&marker) = input.get(pos) else {
+			break;
+		};
+		pos += 1;
+		if matches!(marker, 0x01 | 0xD0..=0xD7) {
+			continue;
+		}
+		if marker == 0xD9 {
+			break;
+		}
+		let Some(len) = segment_length(pos) else {
+			break;
+		};
+		let Some(payload) = input.get(pos + 2..pos + len.max(2)) else {
+			break;
+		};
+		pos += len.max(2);
+
+		match marker {
+			0xC4 => jpeg_dc::read_huffman_tables(payload, &mut dc_tables, &mut ac_tables)?,
+			0xDD => restart_interval = payload.get(0..2).map_or(0, |b| usize::from(u16::from_be_bytes([b[0], b[1]]))),
+			0xC0 | 0xC1 => {
+				let Some(specs) = payload.get(6..6 + usize::from(*payload.get(5).unwrap_or(&0)) * 3) else {
+					return Ok(None);
+				};
+				precision = payload[0];
+				height = usize::from(u16::from_be_bytes([payload[1], payload[2]]));
+				width = usize::from(u16::from_be_bytes([payload[3], payload[4]]));
+				components = specs
+					.chunks_exact(3)
+					.map(|spec| Component {
+						id: spec[0],
+						h: usize::from(spec[1] >> 4).max(1),
+						v: usize::from(spec[1] & 15).max(1),
+					})
+					.collect();
+			},
+			// Progressive, lossless, arithmetic coding and the hierarchical processes
+			0xC2 | 0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return Ok(None),
+			0xDA => {
+				let start = pos;
+				// Entropy coded data runs until a marker other than a stuffed zero or a restart marker, or the end of the file
+				let mut intervals = Vec::new();
+				let mut interval_start = pos;
+				while let Some(offset) = input.get(pos..).map(|rest| rest.iter().position(|&b| b == 0xFF).unwrap_or(rest.len())) {
+					pos += offset;
+					match input.get(pos + 1) {
+						Some(0x00) => pos += 2,
+						Some(0xD0..=0xD7) => {
+							intervals.push(interval_start..pos);
+							pos += 2;
+							interval_start = pos;
+						},
+						_ => break,
+					}
+				}
+				pos = pos.min(input.len());
+				intervals.push(interval_start..pos);
+
+				if restart_interval == 0 || components.is_empty() || height == 0 || start == pos {
+					continue;
+				}
+				let Some(layout) = scan_layout(payload, &components, width, height, &dc_tables, &ac_tables) else {
+					continue;
+				};
+				replacements.extend(repair_scan(input, &intervals, &layout, restart_interval, precision));
+			},
+			_ => (),
+		}
+	}
+
+	if replacements.is_empty() {
+		return Ok(None);
+	}
+	let repaired = replacements.len();
+	let mut out = Vec::with_capacity(input.len());
+	let mut copied = 0;
+	for (damaged, intact) in replacements {
+		out.extend_from_slice(&input[copied..damaged.start]);
+		out.extend_from_slice(&input[intact]);
+		copied = damaged.end;
+	}
+	out.extend_from_slice(&input[copied..]);
+	Ok(Some((out, repaired)))
+}
+
+
+/// The (DC table, AC table) of each block of an MCU, and the number of MCUs in the scan.
+struct ScanLayout<'a> {
+	blocks: Vec<(&'a Huffman, &'a Huffman)>,
+	mcus: usize,
+}
+
+
+fn scan_layout<'a>(
+	header: &[u8],
+	components: &[Component],
+	width: usize,
+	height: usize,
+	dc_tables: &'a [Option<Huffman>; 4],
+	ac_tables: &'a [Option<Huffman>; 4],
+) -> Option<ScanLayout<'a>> {
+	let count = usize::from(*header.first()?);
+	let specs = header.get(1..1 + count * 2)?;
+	let h_max = components.iter().map(|c| c.h).max()?;
+	let v_max = components.iter().map(|c| c.v).max()?;
+
+	let mut blocks = Vec::new();
+	for spec in specs.chunks_exact(2) {
+		let component = components.iter().find(|c| c.id == spec[0])?;
+		let dc = dc_tables[usize::from((spec[1] >> 4) & 3)].as_ref()?;
+		let ac = ac_tables[usize::from(spec[1] & 3)].as_ref()?;
+		// A single component scan isn't interleaved: each of its blocks is an MCU
+		let blocks_per_mcu = if count == 1 { 1 } else { component.h * component.v };
+		blocks.extend(std::iter::repeat_n((dc, ac), blocks_per_mcu));
+	}
+
+	let mcus = match components.iter().find(|c| count == 1 && c.id == specs[0]) {
+		Some(component) => (width * component.h).div_ceil(h_max).div_ceil(8) * (height * component.v).div_ceil(v_max).div_ceil(8),
+		None => width.div_ceil(8 * h_max) * height.div_ceil(8 * v_max),
+	};
+	Some(ScanLayout { blocks, mcus })
+}
+
+
+/// (damaged interval, intact interval to copy into it) pairs for one scan.
+fn repair_scan(input: &[u8], intervals: &[Range<usize>], layout: &ScanLayout, restart_interval: usize, precision: u8) -> Vec<(Range<usize>, Range<usize>)> {
+	let expected = layout.mcus.div_ceil(restart_interval);
+	let mcus = |i: usize| restart_interval.min(layout.mcus.saturating_sub(i * restart_interval));
+	let intact: Vec<bool> = intervals
+		.iter()
+		.take(expected)
+		.enumerate()
+		.map(|(i, range)| decodes(&input[range.clone()], layout, mcus(i), precision))
+		.collect();
+	if intact.iter().all(|&ok| ok) {
+		return Vec::new();
+	}
+
+	let mut replacements = Vec::new();
+	for (i, _) in intact.iter().enumerate().filter(|(_, ok)| !**ok) {
+		// The nearest intact interval with at least as many MCUs, looking back first
+		let donor = (1..intact.len())
+			.flat_map(|d| [i.checked_sub(d), Some(i + d)])
+			.flatten()
+			.find(|&j| intact.get(j) == Some(&true) && mcus(j) >= mcus(i));
+		if let Some(j) = donor {
+			replacements.push((intervals[i].clone(), intervals[j].clone()));
+		}
+	}
+	replacements
+}
+
+
+/// Whether `data` Huffman decodes as exactly `mcus` MCUs.
+fn decodes(data: &[u8], layout: &ScanLayout, mcus: usize, precision: u8) -> bool {
+	let mut bits = BitReader::new(data);
+	for _ in 0..mcus {
+		for &(dc, ac) in &layout.blocks {
+			let Ok(category) = dc.decode(&mut bits) else {
+				return false;
+			};
+			if category > precision.saturating_add(3) {
+				return false;
+			}
+			bits.bits(category);
+			if jpeg_dc::skip_ac(ac, &mut bits).is_err() {
+				return false;
+			}
+		}
+		if bits.exhausted {
+			return false;
+		}
+	}
+	true
+}
@@ -0,0 +1,336 @@
+//! Whether the last scan of a progressive JPEG was cut short, for telling a truncated file from a complete one that
+//! merely lacks its EOI marker.
+//!
+//! The last scan is Huffman decoded block by block, and was cut short if its entropy coded data runs out before its
+//! last block. Successive approximation refinements of AC coefficients code a correction bit for each coefficient
+//! that's already nonzero, so how much data one takes depends on every earlier AC scan of its component. Those are
+//! decoded too, keeping only which coefficients are nonzero.
+
+use crate::{
+	error::Error,
+	jpeg_dc::{self, BitReader, Huffman},
+};
+
+
+struct Component {
+	id: u8,
+	h: usize,
+	v: usize,
+	/// Which of the 64 coefficients are nonzero so far, one mask per block of the padded MCU grid
+	nonzero: Vec<u64>,
+}
+
+
+/// A scan's parameters from its SOS header.
+struct Scan {
+	/// (component index, DC table, AC table) for each component of the scan
+	components: Vec<(usize, usize, usize)>,
+	ss: usize,
+	se: usize,
+	ah: u8,
+}
+
+
+/// Returns true unless the last scan of the progressive JPEG `input` has all of its blocks' data. Files that can't be
+/// walked (not progressive, or corrupt before the last scan) count as cut short, since that's the usual reason for
+/// the last scan to run to the end of the file.
+pub(crate) fn last_scan_is_cut(input: &[u8]) -> bool {
+	walk(input).unwrap_or(None).unwrap_or(true)
+}
+
+
+/// Walks every scan, returning whether the last one ran out of data, or `None` for files that aren't progressive.
+fn walk(input: &[u8]) -> Result<Option<bool>, Error> {
+	if !input.starts_with(&[0xFF, 0xD8]) {
+		return Ok(None);
+	}
+
+	let mut dc_tables: [Option<Huffman>; 4] = Default::default();
+	let mut ac_tables: [Option<Huffman>; 4] = Default::default();
+	let mut components: Vec<Component> = Vec::new();
+	let (mut width, mut height) = (0, 0);
+	let (mut h_max, mut v_max, mut mcus_x, mut mcus_y) = (1, 1, 0, 0);
+	let mut restart_interval = 0;
+	let mut last_cut = None;
+
+	let segment_length = |pos: usize| input.get(pos..pos + 2).map(|b| usize::from(u16::from_be_bytes([b[0], b[1]])));
+	let mut pos = 2;
+	loop {
+		while input.get(pos).is_some_and(|&b| b != 0xFF) {
+			pos += 1;
+		}
+		while input.get(pos) == Some(&0xFF) {
+			pos += 1;
+		}
+		let Some(&marker) = input.get(pos) else {
+			break;
+		};
+		pos += 1;
+		if matches!(marker, 0x01 | 0xD0..=0xD7) {
+			continue;
+		}
+		if marker == 0xD9 {
+			break;
+		}
+		let Some(len) = segment_length(pos) else {
+			break;
+		};
+		let Some(payload) = input.get(pos + 2..pos + len.max(2)) else {
+			break;
+		};
+		pos += len.max(2);
+
+		match marker {
+			0xC4 => jpeg_dc::read_huffman_tables(payload, &mut dc_tables, &mut ac_tables)?,
+			0xDD => restart_interval = payload.get(0..2).map_or(0, |b| usize::from(u16::from_be_bytes([b[0], b[1]]))),
+			0xC2 => {
+				let Some(specs) = payload.get(6..6 + usize::from(*payload.get(5).unwrap_or(&0)) * 3) else {
+					return Ok(None);
+				};
+				height = usize::from(u16::from_be_bytes([payload[1], payload[2]]));
+				width = usize::from(u16::from_be_bytes([payload[3], payload[4]]));
+				components = specs
+					.chunks_exact(3)
+					.map(|spec| Component {
+						id: spec[0],
+						h: usize::from(spec[1] >> 4).clamp(1, 4),
+						v: usize::from(spec[1] & 15).clamp(1, 4),
+						nonzero: Vec::new(),
+					})
+					.collect();
+				if components.is_empty() || width == 0 || height == 0 {
+					return Ok(None);
+				}
+				h_max = components.iter().map(|c| c.h).max().unwrap_or(1);
+				v_max = components.iter().map(|c| c.v).max().unwrap_or(1);
+				mcus_x = width.div_ceil(8 * h_max);
+				mcus_y = height.div_ceil(8 * v_max);
+				for component in &mut components {
+					component.nonzero = vec![0; mcus_x * component.h * mcus_y * component.v];
+				}
+			},
+			// Every other coding process
+			0xC0 | 0xC1 | 0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return Ok(None),
+			0xDA => {
+				if components.is_empty() {
+					return Ok(None);
+				}
+				let scan = read_scan(payload, &components)?;
+
+				// Entropy coded data runs until a marker other than a stuffed zero or a restart marker, or the end of the file
+				let start = pos;
+				while let Some(offset) = input.get(pos..).map(|rest| rest.iter().position(|&b| b == 0xFF).unwrap_or(rest.len())) {
+					pos += offset;
+					match input.get(pos + 1) {
+						Some(0x00 | 0xD0..=0xD7) => pos += 2,
+						// The end of the file, possibly halfway through a stuffed byte
+						None => {
+							pos = input.len();
+							break;
+						},
+						_ => break,
+					}
+				}
+				pos = pos.min(input.len());
+
+				let mut bits = BitReader::new(&input[start..pos]);
+				// The first error is where the data ran out if it did, and corruption otherwise
+				let result = decode_scan(
+					&scan,
+					&mut components,
+					&mut bits,
+					&dc_tables,
+					&ac_tables,
+					restart_interval,
+					(width, height, h_max, v_max),
+					(mcus_x, mcus_y),
+				);
+				if result.is_err() && !bits.exhausted {
+					return Ok(None);
+				}
+				last_cut = Some(bits.exhausted);
+			},
+			_ => (),
+		}
+	}
+	Ok(last_cut)
+}
+
+
+fn read_scan(payload: &[u8], components: &[Component]) -> Result<Scan, Error> {
+	let count = usize::from(*payload.first().ok_or_else(|| jpeg_dc::corrupt("truncated scan header"))?);
+	let specs = payload.get(1..1 + count * 2).ok_or_else(|| jpeg_dc::corrupt("truncated scan header"))?;
+	let &[ss, se, ah_al] = payload
+		.get(1 + count * 2..4 + count * 2)
+		.ok_or_else(|| jpeg_dc::corrupt("truncated scan header"))?
+	else {
+		unreachable!();
+	};
+	let scan_components = specs
+		.chunks_exact(2)
+		.map(|spec| {
+			let index = components
+				.iter()
+				.position(|c| c.id == spec[0])
+				.ok_or_else(|| jpeg_dc::corrupt("scan references an unknown component"))?;
+			Ok((index, usize::from(spec[1] >> 4 & 3), usize::from(spec[1] & 3)))
+		})
+		.collect::<Result<Vec<_>, Error>>()?;
+	if scan_components.is_empty() || ss > se || se > 63 || (ss == 0 && se != 0) || (ss != 0 && scan_components.len() != 1) {
+		return Err(jpeg_dc::corrupt("bad progressive scan parameters"));
+	}
+	Ok(Scan {
+		components: scan_components,
+		ss: usize::from(ss),
+		se: usize::from(se),
+		ah: ah_al >> 4,
+	})
+}
+
+
+#[allow(clippy::too_many_arguments)]
+fn decode_scan(
+	scan: &Scan,
+	components: &mut [Component],
+	bits: &mut BitReader,
+	dc_tables: &[Option<Huffman>; 4],
+	ac_tables: &[Option<Huffman>; 4],
+	restart_interval: usize,
+	(width, height, h_max, v_max): (usize, usize, usize, usize),
+	(mcus_x, mcus_y): (usize, usize),
+) -> Result<(), Error> {
+	// (component index in the scan, block x, block y) in decoding order
+	let blocks: Vec<(usize, usize, usize)> = if let [(index, _, _)] = scan.components[..] {
+		let component = &components[index];
+		let blocks_x = (width * component.h).div_ceil(h_max).div_ceil(8);
+		let blocks_y = (height * component.v).div_ceil(v_max).div_ceil(8);
+		(0..blocks_y).flat_map(|y| (0..blocks_x).map(move |x| (0, x, y))).collect()
+	} else {
+		let mut blocks = Vec::new();
+		for my in 0..mcus_y {
+			for mx in 0..mcus_x {
+				for (i, &(index, _, _)) in scan.components.iter().enumerate() {
+					let (h, v) = (components[index].h, components[index].v);
+					blocks.extend((0..v).flat_map(|by| (0..h).map(move |bx| (i, mx * h + bx, my * v + by))));
+				}
+			}
+		}
+		blocks
+	};
+	let blocks_per_mcu = if scan.components.len() == 1 {
+		1
+	} else {
+		scan.components.iter().map(|&(index, _, _)| components[index].h * components[index].v).sum()
+	};
+
+	let mut eob_run = 0u32;
+	for (n, (i, x, y)) in blocks.into_iter().enumerate() {
+		if restart_interval > 0 && n > 0 && n % (restart_interval * blocks_per_mcu) == 0 {
+			bits.restart();
+			eob_run = 0;
+		}
+		let (index, dc, ac) = scan.components[i];
+		let component = &mut components[index];
+		let stride = mcus_x * component.h;
+		let nonzero = &mut component.nonzero[y * stride + x];
+
+		if scan.ss == 0 {
+			if scan.ah == 0 {
+				let category = table(dc_tables, dc)?.decode(bits)?;
+				bits.bits(category.min(16));
+			} else {
+				bits.bit();
+			}
+		} else if scan.ah == 0 {
+			ac_first(table(ac_tables, ac)?, bits, scan, nonzero, &mut eob_run)?;
+		} else {
+			ac_refine(table(ac_tables, ac)?, bits, scan, nonzero, &mut eob_run)?;
+		}
+		if bits.exhausted {
+			return Ok(());
+		}
+	}
+	Ok(())
+}
+
+
+fn table(tables: &[Option<Huffman>; 4], id: usize) -> Result<&Huffman, Error> {
+	tables[id].as_ref().ok_or_else(|| jpeg_dc::corrupt("missing Huffman table"))
+}
+
+
+/// The first scan of a spectral band (JPEG spec G.1.2.2), marking which coefficients it makes nonzero.
+fn ac_first(table: &Huffman, bits: &mut BitReader, scan: &Scan, nonzero: &mut u64, eob_run: &mut u32) -> Result<(), Error> {
+	if *eob_run > 0 {
+		*eob_run -= 1;
+		return Ok(());
+	}
+	let mut k = scan.ss;
+	while k <= scan.se {
+		let rs = table.decode(bits)?;
+		let (run, size) = (rs >> 4, rs & 15);
+		if size == 0 {
+			if run < 15 {
+				*eob_run = (1 << run) - 1 + bits.bits(run);
+				break;
+			}
+			k += 16;
+			continue;
+		}
+		k += usize::from(run);
+		bits.bits(size);
+		if k > scan.se {
+			return Err(jpeg_dc::corrupt("coefficients past the end of a band"));
+		}
+		*nonzero |= 1 << k;
+		k += 1;
+	}
+	Ok(())
+}
+
+
+/// A successive approximation refinement of a spectral band (JPEG spec G.1.2.3): a correction bit for each
+/// coefficient that was already nonzero, and new coefficients of magnitude 1 among the zero ones.
+fn ac_refine(table: &Huffman, bits: &mut BitReader, scan: &Scan, nonzero: &mut u64, eob_run: &mut u32) -> Result<(), Error> {
+	let mut k = scan.ss;
+	if *eob_run == 0 {
+		while k <= scan.se {
+			let rs = table.decode(bits)?;
+			let (mut run, size) = (i32::from(rs >> 4), rs & 15);
+			if size != 0 {
+				bits.bit();
+			} else if run != 15 {
+				*eob_run = (1 << run) + bits.bits(run as u8);
+				break;
+			}
+			// Skip `run` zero coefficients, reading the correction bits of the nonzero ones passed on the way
+			while k <= scan.se {
+				if *nonzero & 1 << k != 0 {
+					bits.bit();
+				} else {
+					run -= 1;
+					if run < 0 {
+						break;
+					}
+				}
+				k += 1;
+			}
+			if size != 0 {
+				if k > scan.se {
+					return Err(jpeg_dc::corrupt("coefficients past the end of a band"));
+				}
+				*nonzero |= 1 << k;
+			}
+			k += 1;
+		}
+	}
+	if *eob_run > 0 {
+		for k in k..=scan.se {
+			if *nonzero & 1 << k != 0 {
+				bits.bit();
+			}
+		}
+		*eob_run -= 1;
+	}
+	Ok(())
+}
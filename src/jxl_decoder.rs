@@ -0,0 +1,125 @@
+use std::io::{BufRead, Seek};
+
+use image::{
+	ColorType, ExtendedColorType, ImageDecoder, ImageError, ImageResult, Limits,
+	error::{DecodingError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind},
+	metadata::Orientation,
+};
+use jxl_oxide::{JxlImage, PixelFormat};
+
+use crate::error::Error;
+
+
+/// JPEG XL decoder built on jxl-oxide, covering both VarDCT (lossy) and modular (lossless) images.
+///
+/// Images with more than 8 bits per sample decode to 16-bit color types, like 16-bit PNGs; float samples are
+/// quantized to 16 bits as well. jxl-oxide renders with the codestream's orientation already applied, so
+/// `orientation` always reports `NoTransforms`.
+pub struct JxlDecoder {
+	image: JxlImage,
+	color_type: ColorType,
+	limits: Limits,
+}
+
+
+impl JxlDecoder {
+	pub fn new<R: BufRead + Seek>(r: R) -> Result<JxlDecoder, Error> {
+		Self::with_limits(r, Limits::no_limits())
+	}
+
+	pub fn with_limits<R: BufRead + Seek>(r: R, limits: Limits) -> Result<JxlDecoder, Error> {
+		let image = JxlImage::builder().read(r).map_err(error_from_jxl)?;
+		let is_16bit = image.image_header().metadata.bit_depth.bits_per_sample() > 8;
+		let color_type = match (image.pixel_format(), is_16bit) {
+			(PixelFormat::Gray, false) => ColorType::L8,
+			(PixelFormat::Gray, true) => ColorType::L16,
+			(PixelFormat::Graya, false) => ColorType::La8,
+			(PixelFormat::Graya, true) => ColorType::La16,
+			(PixelFormat::Rgb, false) => ColorType::Rgb8,
+			(PixelFormat::Rgb, true) => ColorType::Rgb16,
+			(PixelFormat::Rgba, false) => ColorType::Rgba8,
+			(PixelFormat::Rgba, true) => ColorType::Rgba16,
+			(PixelFormat::Cmyk | PixelFormat::Cmyka, _) => {
+				return Err(Error::Unsupported(UnsupportedError::from_format_and_kind(
+					jxl_hint(),
+					UnsupportedErrorKind::Color(ExtendedColorType::Cmyk8),
+				)));
+			},
+		};
+
+		let mut decoder = JxlDecoder {
+			image,
+			color_type,
+			limits: Limits::no_limits(),
+		};
+		decoder.set_limits(limits)?;
+		Ok(decoder)
+	}
+
+	/// Returns true if the image has an animation header, even if it only has one frame.
+	pub fn is_animated(&self) -> bool {
+		self.image.image_header().metadata.animation.is_some()
+	}
+}
+
+
+impl ImageDecoder for JxlDecoder {
+	fn dimensions(&self) -> (u32, u32) {
+		(self.image.width(), self.image.height())
+	}
+
+	fn color_type(&self) -> ColorType {
+		self.color_type
+	}
+
+	fn icc_profile(&mut self) -> ImageResult<Option<Vec<u8>>> {
+		Ok(self.image.original_icc().map(<[u8]>::to_vec))
+	}
+
+	fn orientation(&mut self) -> ImageResult<Orientation> {
+		Ok(Orientation::NoTransforms)
+	}
+
+	fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+		crate::error::check_buffer(buf, self.total_bytes())?;
+
+		let render = self.image.render_frame(0).map_err(error_from_jxl)?;
+		let mut stream = render.stream();
+		if self.color_type.bytes_per_pixel() / self.color_type.channel_count() == 2 {
+			let mut samples = vec![0u16; buf.len() / 2];
+			stream.write_to_buffer(&mut samples);
+			for (out, sample) in buf.chunks_exact_mut(2).zip(samples) {
+				out.copy_from_slice(&sample.to_ne_bytes());
+			}
+		} else {
+			stream.write_to_buffer(buf);
+		}
+		Ok(())
+	}
+
+	fn read_image_boxed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+		(*self).read_image(buf)
+	}
+
+	fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+		limits.check_support(&image::LimitSupport::default())?;
+		let (width, height) = self.dimensions();
+		limits.check_dimensions(width, height)?;
+		self.limits = limits;
+		Ok(())
+	}
+}
+
+
+fn jxl_hint() -> ImageFormatHint {
+	ImageFormatHint::Name("JPEG XL".to_string())
+}
+
+
+/// jxl-oxide returns its errors boxed: I/O errors from the reader, and bitstream and rendering errors otherwise.
+fn error_from_jxl(err: Box<dyn std::error::Error + Send + Sync>) -> ImageError {
+	match err.downcast::<std::io::Error>() {
+		Ok(err) => ImageError::IoError(*err),
+		Err(err) => ImageError::Decoding(DecodingError::new(jxl_hint(), err)),
+	}
+}
@@ -1,65 +1,722 @@
+/// Records a decoder branch for `coverage`; compiles to nothing without the `testing` feature.
+macro_rules! record_branch {
+	($branch:ident) => {
+		#[cfg(feature = "testing")]
+		$crate::coverage::record($crate::coverage::Branch::$branch);
+	};
+}
+
+pub mod accounting;
+pub mod allocator;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "async")]
+mod async_load;
+pub mod audit;
+#[cfg(feature = "avif")]
+mod avif_decoder;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub mod batch;
+mod cancel;
+#[cfg(feature = "office")]
+pub mod container;
+#[cfg(feature = "conformance")]
+pub mod conformance;
+pub mod convert;
+#[cfg(feature = "testing")]
+pub mod coverage;
+mod decoded;
+pub mod dedup;
+pub mod digest;
+#[cfg(feature = "dlpack")]
+mod dlpack;
 mod error;
+pub mod exif;
+mod format;
+mod gif_decoder;
+#[cfg(feature = "heif")]
+mod heif_decoder;
+mod ico_decoder;
+pub mod iptc;
+#[cfg(feature = "jp2")]
+mod jpeg2000_decoder;
+mod jpeg_dc;
 mod jpeg_decoder;
+mod jpeg_fallback;
+mod jpeg_markers;
+mod jpeg_resync;
+mod jpeg_scans;
+mod jxl_decoder;
+pub mod metadata;
+#[cfg(feature = "nvjpeg")]
+pub mod nvjpeg;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub mod open_files;
+mod options;
+pub mod orientation;
+pub mod phash;
+#[cfg(feature = "pdf")]
+mod pdf;
 mod png_decoder;
+mod probe;
+#[cfg(feature = "python")]
+mod python;
+mod qoi_decoder;
+#[cfg(feature = "raw")]
+mod raw;
+pub mod seen;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub mod shutdown;
+#[cfg(feature = "svg")]
+mod svg_decoder;
+#[cfg(feature = "texture")]
+mod texture_decoder;
+mod tiff_decoder;
+mod versions;
+mod webp_decoder;
+mod window;
+
+use std::io::{BufRead, Cursor, Seek, SeekFrom};
+// Without a filesystem (`wasm32-unknown-unknown`), only the functions decoding from readers and memory are available
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::{fs::File, io::BufReader, path::Path};
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use image::RgbaImage;
+use image::{DynamicImage, ImageDecoder, ImageFormat, metadata::Orientation};
 
-use std::{
-	fs::File,
-	io::{BufRead, BufReader, Seek},
-	path::Path,
+use crate::{
+	accounting::{CountingReader, PeakTracker, ResourceReport},
+	allocator::{OutputAllocator, VecAllocator},
+	cancel::CancelReader,
+	decoded::Metadata,
+	window::Window,
 };
 
-use image::{DynamicImage, ImageFormat, guess_format};
+#[cfg(feature = "async")]
+pub use crate::async_load::{load_image_async, load_image_async_with_options, load_image_from_async_reader};
+#[cfg(feature = "avif")]
+pub use crate::avif_decoder::AvifDecoder;
+#[cfg(feature = "ndarray")]
+pub use crate::decoded::DecodedArray;
+#[cfg(feature = "heif")]
+pub use crate::heif_decoder::HeifDecoder;
+#[cfg(feature = "jp2")]
+pub use crate::jpeg2000_decoder::Jpeg2000Decoder;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub use crate::probe::{probe_image, probe_images};
+#[cfg(feature = "svg")]
+pub use crate::svg_decoder::SvgDecoder;
+#[cfg(feature = "texture")]
+pub use crate::texture_decoder::{TextureDecoder, TextureEncoding};
+pub use crate::{
+	cancel::CancelToken,
+	decoded::{DecodedImage, DecodedPixels, RawImage},
+	error::Error,
+	format::Format,
+	gif_decoder::GifDecoder,
+	ico_decoder::{IcoDecoder, IcoEntry},
+	jpeg_decoder::{JpegDecoder, JpegHeader, Refinement, Refinements},
+	jxl_decoder::JxlDecoder,
+	options::{
+		AnimatedPolicy, AvifOptions, ChromaUpsampling, DctScale, HdrOptions, IcoOptions, JpegOptions, LoadOptions, MultiPage, NonFinite, OutputColor, OutputDepth,
+		Placeholder, PlaceholderFill, PngGamma, PngOptions, SignificantBits, SixteenBit, SvgOptions, TiffOptions, ToneMap,
+	},
+	png_decoder::{PngDecoder, PngRow, PngRows, RowPosition},
+	probe::{ImageInfo, SubImage, probe_image_from_reader, probe_images_from_reader},
+	qoi_decoder::QoiDecoder,
+	tiff_decoder::{GeoTiffTags, TiffBands, TiffDecoder, TiffSamples},
+	versions::{Capabilities, capabilities},
+	webp_decoder::WebPDecoder,
+};
+
+
+pub fn load_image_from_reader<R: BufRead + Seek>(reader: R) -> Result<(Format, DynamicImage), Error> {
+	load_image_from_reader_with_options(reader, &LoadOptions::default())
+}
+
+
+pub fn load_image_from_reader_with_options<R: BufRead + Seek>(reader: R, options: &LoadOptions) -> Result<(Format, DynamicImage), Error> {
+	let (format, img, _) = load(reader, options, None)?;
+	Ok((format, img))
+}
+
+
+/// Decodes and applies the options that work on the decoded image, also returning the number of non-finite float
+/// pixels found. Fills in `metadata` if given.
+fn load<R: BufRead + Seek>(reader: R, options: &LoadOptions, metadata: Option<&mut Metadata>) -> Result<(Format, DynamicImage, usize), Error> {
+	// The orientation is read with the rest of the metadata, so that's collected even if the caller doesn't want it
+	let mut collected = Metadata::default();
+	let mut metadata = metadata.or(options.apply_orientation.then_some(&mut collected));
+	let cancel_token = options.cancel_token.as_ref();
+	let (format, mut img) = decode(CancelReader::new(reader, cancel_token), options, metadata.as_deref_mut())
+		.map_err(|e| if cancel_token.is_some_and(CancelToken::is_cancelled) { Error::Cancelled } else { e })?;
+	if options.apply_orientation
+		&& let Some(metadata) = metadata
+		&& let Some(orientation) = metadata.orientation.replace(Orientation::NoTransforms)
+	{
+		img.apply_orientation(orientation);
+	}
+	let (img, non_finite) = match format {
+		Format::Image(ImageFormat::OpenExr | ImageFormat::Hdr) => options.hdr.apply(img)?,
+		_ => (img, 0),
+	};
+	Ok((format, options.apply_output(img), non_finite))
+}
+
 
-use crate::png_decoder::PngDecoder;
+/// Guesses the format from the first 16 bytes. They're peeked from the read buffer when it holds them, so what was
+/// read into it is decoded from rather than dropped by seeking back to the start.
+fn guess_format<R: BufRead + Seek>(reader: &mut R) -> Result<Format, Error> {
+	let peeked = reader.fill_buf()?;
+	let format = if peeked.len() >= 16 {
+		Format::guess(&peeked[..16])
+	} else {
+		let mut buf = [0; 16];
+		reader.read_exact(&mut buf)?;
+		reader.rewind()?;
+		Format::guess(&buf)
+	};
+	format.ok_or(Error::UnsupportedFormat)
+}
 
 
-pub fn load_image_from_reader<R: BufRead + Seek>(mut reader: R) -> Result<(ImageFormat, DynamicImage), error::Error> {
-	// Guess format
-	let mut buf = [0; 16];
-	reader.read_exact(&mut buf)?;
+fn decode<R: BufRead + Seek>(mut reader: R, options: &LoadOptions, mut metadata: Option<&mut Metadata>) -> Result<(Format, DynamicImage), Error> {
+	let input_len = reader.seek(SeekFrom::End(0))?;
 	reader.rewind()?;
-	let Ok(format) = guess_format(&buf) else {
-		return Err(error::Error::UnsupportedFormat);
+	if options.max_file_bytes.is_some_and(|max| input_len > max) {
+		return Err(Error::TooBig);
+	}
+
+	let format = guess_format(&mut reader)?;
+
+	let format = match format {
+		#[cfg(feature = "heif")]
+		Format::Heif => {
+			let decoder = HeifDecoder::new(reader)?;
+			let img = decode_limited(decoder, options, input_len, metadata)?;
+			return Ok((Format::Heif, img));
+		},
+		#[cfg(not(feature = "heif"))]
+		Format::Heif => return Err(Error::UnsupportedFormat),
+		#[cfg(feature = "svg")]
+		Format::Svg => {
+			let decoder = SvgDecoder::with_options(reader, &options.svg, options.decoder_limits())?;
+			let img = decode_limited(decoder, options, input_len, metadata)?;
+			return Ok((Format::Svg, img));
+		},
+		#[cfg(not(feature = "svg"))]
+		Format::Svg => return Err(Error::UnsupportedFormat),
+		Format::Jxl => {
+			let decoder = JxlDecoder::new(reader)?;
+			options.animated_policy.check(decoder.is_animated())?;
+			let img = decode_limited(decoder, options, input_len, metadata)?;
+			return Ok((Format::Jxl, img));
+		},
+		#[cfg(feature = "jp2")]
+		Format::Jpeg2000 => {
+			let decoder = Jpeg2000Decoder::new(reader)?;
+			let img = decode_limited(decoder, options, input_len, metadata)?;
+			return Ok((Format::Jpeg2000, img));
+		},
+		#[cfg(not(feature = "jp2"))]
+		Format::Jpeg2000 => return Err(Error::UnsupportedFormat),
+		#[cfg(feature = "texture")]
+		Format::Ktx2 => {
+			let decoder = TextureDecoder::new(reader)?;
+			let img = decode_limited(decoder, options, input_len, metadata)?;
+			return Ok((Format::Ktx2, img));
+		},
+		#[cfg(not(feature = "texture"))]
+		Format::Ktx2 => return Err(Error::UnsupportedFormat),
+		#[cfg(feature = "pdf")]
+		Format::Pdf => {
+			let mut input = Vec::new();
+			reader.read_to_end(&mut input)?;
+			let img = pdf::decode(&input, options, input_len, metadata)?;
+			return Ok((Format::Pdf, img));
+		},
+		#[cfg(not(feature = "pdf"))]
+		Format::Pdf => return Err(Error::UnsupportedFormat),
+		// Only produced by inspecting a TIFF or JPEG further down, never by `guess`
+		Format::RawPreview | Format::Mpo => return Err(Error::UnsupportedFormat),
+		Format::Image(format) => format,
 	};
 
 	match format {
 		ImageFormat::Png => {
-			let decoder = PngDecoder::new(reader)?;
-			if decoder.is_animated() {
-				return Err(error::Error::Animated);
+			let mut decoder = PngDecoder::with_options(reader, options.decoder_limits(), &options.png)?;
+			options.animated_policy.check(decoder.is_animated())?;
+			if let Some(metadata) = metadata.as_deref_mut() {
+				metadata.gamma = decoder.gamma_value()?;
+				metadata.significant_bits = decoder.significant_bits().map(<[u8]>::to_vec);
 			}
-			let img = DynamicImage::from_decoder(decoder)?;
-			return Ok((ImageFormat::Png, img));
+			let gamma = decoder.gamma_only();
+			let (width, height) = decoder.dimensions();
+			let factor = options.target_max_dimension.map_or(1, |target| width.max(height) / target.max(1));
+			let img = match options.output {
+				_ if factor > 1 => {
+					apply_limits(&mut decoder, options, input_len, metadata)?;
+					decoder.decode_downscaled(factor)?
+				},
+				OutputColor::Luma8 => {
+					apply_limits(&mut decoder, options, input_len, metadata)?;
+					DynamicImage::ImageLuma8(decoder.decode_luma()?)
+				},
+				OutputColor::Rgba8 => {
+					apply_limits(&mut decoder, options, input_len, metadata)?;
+					DynamicImage::ImageRgba8(decoder.decode_rgba8()?)
+				},
+				_ => decode_limited(decoder, options, input_len, metadata)?,
+			};
+			let img = match gamma {
+				Some(gamma) => options.png.gamma.apply(img, gamma),
+				None => img,
+			};
+			Ok((ImageFormat::Png.into(), img))
 		},
 		ImageFormat::Jpeg => {
-			let decoder = jpeg_decoder::JpegDecoder::new(reader)?;
-			let img = DynamicImage::from_decoder(decoder)?;
-			return Ok((ImageFormat::Jpeg, img));
+			let mut decoder = JpegDecoder::with_options(reader, &options.jpeg)?;
+			let format = if decoder.mpo_image_count().is_some() { Format::Mpo } else { ImageFormat::Jpeg.into() };
+			let (width, height) = decoder.full_dimensions();
+			let scale = options.target_max_dimension.map_or(DctScale::Full, |target| DctScale::for_target(width, height, target));
+			let dc_only = scale.denominator() > options.jpeg.dct_scale.denominator() && decoder.set_dct_scale(scale) && scale == DctScale::Eighth;
+			let img = match options.output {
+				_ if dc_only => {
+					apply_limits(&mut decoder, options, input_len, metadata)?;
+					decoder.dc_preview()?
+				},
+				OutputColor::Luma8 => {
+					apply_limits(&mut decoder, options, input_len, metadata)?;
+					DynamicImage::ImageLuma8(decoder.decode_luma()?)
+				},
+				OutputColor::Rgba8 => {
+					apply_limits(&mut decoder, options, input_len, metadata)?;
+					DynamicImage::ImageRgba8(decoder.decode_rgba8()?)
+				},
+				_ => decode_limited(decoder, options, input_len, metadata)?,
+			};
+			Ok((format, img))
 		},
 		ImageFormat::WebP => {
-			let dec = image::codecs::webp::WebPDecoder::new(reader)?;
-			if dec.has_animation() {
-				return Err(error::Error::Animated);
-			}
-			let img = DynamicImage::from_decoder(dec)?;
-			return Ok((ImageFormat::WebP, img));
+			let decoder = WebPDecoder::new(reader)?;
+			options.animated_policy.check(decoder.is_animated())?;
+			record_branch!(WebP);
+			let img = decode_limited(decoder, options, input_len, metadata)?;
+			Ok((ImageFormat::WebP.into(), img))
+		},
+		#[cfg(feature = "avif")]
+		ImageFormat::Avif => {
+			let decoder = AvifDecoder::with_options(reader, &options.avif, options.decoder_limits())?;
+			let img = decode_limited(decoder, options, input_len, metadata)?;
+			Ok((ImageFormat::Avif.into(), img))
 		},
 		ImageFormat::Gif => {
-			// TODO: Technically a GIF could be static, but we'll just treat all GIFs as animated for simplicity for now
-			return Err(error::Error::Animated);
+			let decoder = GifDecoder::new(reader)?;
+			options.animated_policy.check(decoder.is_animated())?;
+			let img = decode_limited(decoder, options, input_len, metadata)?;
+			Ok((ImageFormat::Gif.into(), img))
+		},
+		ImageFormat::Ico => {
+			let decoder = IcoDecoder::with_size(reader, options.ico.size, options.decoder_limits())?;
+			let img = decode_limited(decoder, options, input_len, metadata)?;
+			Ok((ImageFormat::Ico.into(), img))
+		},
+		#[cfg(feature = "texture")]
+		ImageFormat::Dds => {
+			let decoder = TextureDecoder::new(reader)?;
+			let img = decode_limited(decoder, options, input_len, metadata)?;
+			Ok((ImageFormat::Dds.into(), img))
+		},
+		ImageFormat::Qoi => {
+			let decoder = QoiDecoder::new(reader)?;
+			let img = decode_limited(decoder, options, input_len, metadata)?;
+			Ok((ImageFormat::Qoi.into(), img))
+		},
+		ImageFormat::OpenExr | ImageFormat::Hdr => {
+			// `options.hdr` is applied by `load`
+			let img = match format {
+				ImageFormat::OpenExr => decode_limited(image::codecs::openexr::OpenExrDecoder::new(reader)?, options, input_len, metadata)?,
+				_ => decode_limited(image::codecs::hdr::HdrDecoder::new(reader)?, options, input_len, metadata)?,
+			};
+			Ok((format.into(), img))
+		},
+		ImageFormat::Tiff => {
+			#[cfg(feature = "raw")]
+			let reader = {
+				let mut input = Vec::new();
+				reader.read_to_end(&mut input)?;
+				match raw::inspect(&input) {
+					raw::Raw::NotRecognized => Cursor::new(input),
+					raw::Raw::Preview(range) => {
+						let decoder = JpegDecoder::with_options(Cursor::new(&input[range]), &options.jpeg)?;
+						let img = decode_limited(decoder, options, input_len, metadata)?;
+						return Ok((Format::RawPreview, img));
+					},
+					raw::Raw::NoPreview => {
+						return Err(Error::Unsupported(image::error::UnsupportedError::from_format_and_kind(
+							ImageFormat::Tiff.into(),
+							image::error::UnsupportedErrorKind::GenericFeature("camera RAW without an embedded JPEG preview".to_string()),
+						)));
+					},
+				}
+			};
+			let decoder = TiffDecoder::new(reader)?;
+			if decoder.is_multi_page() && options.tiff.multi_page == MultiPage::Reject {
+				return Err(Error::MultiPage);
+			}
+			let img = decode_limited(decoder, options, input_len, metadata)?;
+			Ok((ImageFormat::Tiff.into(), img))
 		},
 		_ => {
 			// Use the image crate directly for other formats
-			let img = image::load(reader, format)?;
-			return Ok((format, img));
+			record_branch!(Fallback);
+			let decoder = image::ImageReader::with_format(reader, format).into_decoder()?;
+			let img = decode_limited(decoder, options, input_len, metadata)?;
+			Ok((format.into(), img))
 		},
 	}
 }
 
 
-pub fn load_image<P: AsRef<Path>>(path: P) -> Result<(ImageFormat, DynamicImage), error::Error> {
+/// `DynamicImage::from_decoder` under the options' limits, reading `metadata` first if given.
+fn decode_limited<D: ImageDecoder>(mut decoder: D, options: &LoadOptions, input_len: u64, metadata: Option<&mut Metadata>) -> Result<DynamicImage, Error> {
+	apply_limits(&mut decoder, options, input_len, metadata)?;
+	Ok(DynamicImage::from_decoder(decoder)?)
+}
+
+
+/// Checks the image against `max_pixels`, `max_alloc_bytes` and `max_expansion_ratio` (for an input of `input_len`
+/// bytes), then counts its output buffer against the decoder limits' `max_alloc` and sets those limits on the decoder,
+/// which checks its dimensions. Also reads `metadata` if given, since this is the last thing done with a decoder
+/// before it's consumed.
+fn apply_limits<D: ImageDecoder>(decoder: &mut D, options: &LoadOptions, input_len: u64, metadata: Option<&mut Metadata>) -> Result<(), Error> {
+	if options.cancel_token.as_ref().is_some_and(CancelToken::is_cancelled) {
+		return Err(Error::Cancelled);
+	}
+	let (width, height) = decoder.dimensions();
+	options.check_size(u64::from(width) * u64::from(height), decoder.total_bytes(), input_len)?;
+	// Fails on 32-bit targets for buffers past 2 GiB, which `u64` sizes would otherwise let through to the decoder
+	error::buffer_len(decoder.total_bytes())?;
+	let mut limits = options.decoder_limits();
+	limits.reserve(decoder.total_bytes())?;
+	decoder.set_limits(limits)?;
+	if let Some(metadata) = metadata {
+		metadata.read(decoder)?;
+	}
+	Ok(())
+}
+
+
+/// `BufReader`'s default capacity, the smallest `buffered` uses
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+const MIN_READ_BUFFER: u64 = 8 * 1024;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+const MAX_READ_BUFFER: u64 = 256 * 1024;
+
+
+/// Wraps `file` in a read buffer sized to it: 1/16 of the file, from `BufReader`'s default 8 KiB up to 256 KiB. The
+/// streaming decoders (PNG, GIF, WebP, TIFF and most others) ask for a few KB at a time, so a multi-MB file takes tens
+/// of reads rather than hundreds. Decoders reading the whole file up front, like JPEG's, are served from the buffer
+/// and then read the rest in one call, so the size makes no difference to them.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+fn buffered(file: File) -> BufReader<File> {
+	let len = file.metadata().map_or(0, |metadata| metadata.len());
+	BufReader::with_capacity((len / 16).clamp(MIN_READ_BUFFER, MAX_READ_BUFFER) as usize, file)
+}
+
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn load_image<P: AsRef<Path>>(path: P) -> Result<(Format, DynamicImage), Error> {
+	load_image_with_options(path, &LoadOptions::default())
+}
+
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn load_image_with_options<P: AsRef<Path>>(path: P, options: &LoadOptions) -> Result<(Format, DynamicImage), Error> {
+	load_image_from_reader_with_options(buffered(File::open(path)?), options)
+}
+
+
+/// Decodes to 8-bit RGBA, whatever `options.output` says. PNGs and JPEGs are converted as they're decoded, so the
+/// returned buffer is the only full size allocation rather than a second one next to the native color image; the
+/// other formats are decoded as usual and converted afterwards, which for those decoding to RGBA already is a move.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn load_image_rgba8<P: AsRef<Path>>(path: P) -> Result<(Format, RgbaImage), Error> {
+	load_image_rgba8_with_options(path, &LoadOptions::default())
+}
+
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn load_image_rgba8_with_options<P: AsRef<Path>>(path: P, options: &LoadOptions) -> Result<(Format, RgbaImage), Error> {
+	let options = options.clone().output(OutputColor::Rgba8).output_depth(OutputDepth::Preserve);
+	let (format, img) = load_image_with_options(path, &options)?;
+	Ok((format, img.into_rgba8()))
+}
+
+
+/// `load_image`, decoding from a memory mapping of the file rather than reading it, which saves the read calls and
+/// the copy through a read buffer for very large files. Falls back to reading where the file can't be mapped (some
+/// network and virtual filesystems, or platforms without mmap).
+///
+/// The file mustn't be truncated while it's being decoded: reading a page that no longer exists kills the process with
+/// SIGBUS on Unix. Only use this on files nothing else is writing to.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn load_image_mmap<P: AsRef<Path>>(path: P) -> Result<(Format, DynamicImage), Error> {
+	load_image_mmap_with_options(path, &LoadOptions::default())
+}
+
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn load_image_mmap_with_options<P: AsRef<Path>>(path: P, options: &LoadOptions) -> Result<(Format, DynamicImage), Error> {
 	let file = File::open(path)?;
-	let reader = BufReader::new(file);
+	// SAFETY: the mapping is only read, and only until this returns; the file staying intact meanwhile is the caller's
+	// side of the contract, as documented on `load_image_mmap`
+	match unsafe { memmap2::Mmap::map(&file) } {
+		Ok(map) => load_image_from_bytes_with_options(&map, options),
+		Err(_) => load_image_from_reader_with_options(buffered(file), options),
+	}
+}
+
+
+/// Decodes an image already in memory, e.g. a download or a database BLOB.
+pub fn load_image_from_bytes(data: &[u8]) -> Result<(Format, DynamicImage), Error> {
+	load_image_from_reader(Cursor::new(data))
+}
+
+
+pub fn load_image_from_bytes_with_options(data: &[u8], options: &LoadOptions) -> Result<(Format, DynamicImage), Error> {
+	load_image_from_reader_with_options(Cursor::new(data), options)
+}
+
+
+/// Decodes the image stored in the `len` bytes at `offset` of `reader`, e.g. one embedded in a PDF, an archive or a
+/// database page, without copying it out first. Decoding sees only that range, as if it were the whole file, so the
+/// format is guessed from its first bytes and the size limits apply to `len`.
+pub fn load_image_from_reader_at<R: BufRead + Seek>(reader: R, offset: u64, len: u64) -> Result<(Format, DynamicImage), Error> {
+	load_image_from_reader_at_with_options(reader, offset, len, &LoadOptions::default())
+}
+
+
+pub fn load_image_from_reader_at_with_options<R: BufRead + Seek>(
+	reader: R,
+	offset: u64,
+	len: u64,
+	options: &LoadOptions,
+) -> Result<(Format, DynamicImage), Error> {
+	load_image_from_reader_with_options(Window::new(reader, offset, len)?, options)
+}
+
+
+/// `load_image_from_bytes` taking ownership of the buffer, which is dropped as soon as decoding finishes.
+pub fn load_image_from_vec(data: Vec<u8>) -> Result<(Format, DynamicImage), Error> {
+	load_image_from_vec_with_options(data, &LoadOptions::default())
+}
+
+
+pub fn load_image_from_vec_with_options(data: Vec<u8>, options: &LoadOptions) -> Result<(Format, DynamicImage), Error> {
+	load_image_from_reader_with_options(Cursor::new(data), options)
+}
+
+
+/// Decodes to exactly what the decoder produces (see `RawImage`), without `DynamicImage` picking a buffer type for
+/// it, for callers that do their own conversion. The options that pick or configure a decoder, and the limits, apply
+/// as for `load_image`; those that work on the decoded image (`output`, `output_depth`, `apply_orientation` and
+/// `hdr`) don't. PDFs aren't supported.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn load_raw<P: AsRef<Path>>(path: P) -> Result<(Format, RawImage), Error> {
+	load_raw_with_options(path, &LoadOptions::default())
+}
+
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn load_raw_with_options<P: AsRef<Path>>(path: P, options: &LoadOptions) -> Result<(Format, RawImage), Error> {
+	load_raw_from_reader_with_options(buffered(File::open(path)?), options)
+}
+
+
+pub fn load_raw_from_reader<R: BufRead + Seek>(reader: R) -> Result<(Format, RawImage), Error> {
+	load_raw_from_reader_with_options(reader, &LoadOptions::default())
+}
+
+
+pub fn load_raw_from_reader_with_options<R: BufRead + Seek>(reader: R, options: &LoadOptions) -> Result<(Format, RawImage), Error> {
+	load_raw_from_reader_with_allocator(reader, options, &VecAllocator)
+}
+
+
+/// `load_raw`, decoding straight into a buffer from `allocator`, e.g. pinned memory for a DMA to the GPU. See
+/// `allocator::OutputAllocator`.
+pub fn load_raw_from_reader_with_allocator<R: BufRead + Seek, A: OutputAllocator>(
+	reader: R,
+	options: &LoadOptions,
+	allocator: &A,
+) -> Result<(Format, RawImage<A::Buffer>), Error> {
+	let cancel_token = options.cancel_token.as_ref();
+	decode_raw(CancelReader::new(reader, cancel_token), options, allocator)
+		.map_err(|e| if cancel_token.is_some_and(CancelToken::is_cancelled) { Error::Cancelled } else { e })
+}
+
+
+fn decode_raw<R: BufRead + Seek, A: OutputAllocator>(mut reader: R, options: &LoadOptions, allocator: &A) -> Result<(Format, RawImage<A::Buffer>), Error> {
+	let input_len = reader.seek(SeekFrom::End(0))?;
+	reader.rewind()?;
+	if options.max_file_bytes.is_some_and(|max| input_len > max) {
+		return Err(Error::TooBig);
+	}
+	let format = guess_format(&mut reader)?;
+
+	let (format, mut decoder) = native_decoder(reader, format, options)?;
+	apply_limits(&mut decoder, options, input_len, None)?;
+	let (width, height) = decoder.dimensions();
+	let color_type = decoder.color_type();
+	let mut data = allocator.allocate(error::buffer_len(decoder.total_bytes())?)?;
+	decoder.read_image(data.as_mut())?;
+	Ok((
+		format,
+		RawImage {
+			width,
+			height,
+			color_type,
+			data,
+		},
+	))
+}
+
 
-	load_image_from_reader(reader)
+/// The decoder `decode` uses for `format`, boxed, after the checks `decode` makes on it before decoding.
+fn native_decoder<'a, R: BufRead + Seek + 'a>(reader: R, format: Format, options: &LoadOptions) -> Result<(Format, Box<dyn ImageDecoder + 'a>), Error> {
+	let format = match format {
+		#[cfg(feature = "heif")]
+		Format::Heif => return Ok((format, Box::new(HeifDecoder::new(reader)?))),
+		#[cfg(feature = "svg")]
+		Format::Svg => return Ok((format, Box::new(SvgDecoder::with_options(reader, &options.svg, options.decoder_limits())?))),
+		Format::Jxl => {
+			let decoder = JxlDecoder::new(reader)?;
+			options.animated_policy.check(decoder.is_animated())?;
+			return Ok((format, Box::new(decoder)));
+		},
+		#[cfg(feature = "jp2")]
+		Format::Jpeg2000 => return Ok((format, Box::new(Jpeg2000Decoder::new(reader)?))),
+		#[cfg(feature = "texture")]
+		Format::Ktx2 => return Ok((format, Box::new(TextureDecoder::new(reader)?))),
+		Format::Image(format) => format,
+		_ => return Err(Error::UnsupportedFormat),
+	};
+
+	let decoder: Box<dyn ImageDecoder + 'a> = match format {
+		ImageFormat::Png => {
+			let decoder = PngDecoder::with_options(reader, options.decoder_limits(), &options.png)?;
+			options.animated_policy.check(decoder.is_animated())?;
+			Box::new(decoder)
+		},
+		ImageFormat::Jpeg => {
+			let decoder = JpegDecoder::with_options(reader, &options.jpeg)?;
+			if decoder.mpo_image_count().is_some() {
+				return Ok((Format::Mpo, Box::new(decoder)));
+			}
+			Box::new(decoder)
+		},
+		ImageFormat::WebP => {
+			let decoder = WebPDecoder::new(reader)?;
+			options.animated_policy.check(decoder.is_animated())?;
+			Box::new(decoder)
+		},
+		#[cfg(feature = "avif")]
+		ImageFormat::Avif => Box::new(AvifDecoder::with_options(reader, &options.avif, options.decoder_limits())?),
+		ImageFormat::Gif => {
+			let decoder = GifDecoder::new(reader)?;
+			options.animated_policy.check(decoder.is_animated())?;
+			Box::new(decoder)
+		},
+		ImageFormat::Ico => Box::new(IcoDecoder::with_size(reader, options.ico.size, options.decoder_limits())?),
+		#[cfg(feature = "texture")]
+		ImageFormat::Dds => Box::new(TextureDecoder::new(reader)?),
+		ImageFormat::Qoi => Box::new(QoiDecoder::new(reader)?),
+		ImageFormat::Tiff => {
+			#[cfg(feature = "raw")]
+			let reader = {
+				let (mut reader, mut input) = (reader, Vec::new());
+				reader.read_to_end(&mut input)?;
+				match raw::inspect(&input) {
+					raw::Raw::NotRecognized => Cursor::new(input),
+					raw::Raw::Preview(range) => {
+						let decoder = JpegDecoder::with_options(Cursor::new(&input[range]), &options.jpeg)?;
+						return Ok((Format::RawPreview, Box::new(decoder)));
+					},
+					raw::Raw::NoPreview => {
+						return Err(Error::Unsupported(image::error::UnsupportedError::from_format_and_kind(
+							ImageFormat::Tiff.into(),
+							image::error::UnsupportedErrorKind::GenericFeature("camera RAW without an embedded JPEG preview".to_string()),
+						)));
+					},
+				}
+			};
+			let decoder = TiffDecoder::new(reader)?;
+			if decoder.is_multi_page() && options.tiff.multi_page == MultiPage::Reject {
+				return Err(Error::MultiPage);
+			}
+			Box::new(decoder)
+		},
+		_ => Box::new(image::ImageReader::with_format(reader, format).into_decoder()?),
+	};
+	Ok((format.into(), decoder))
+}
+
+
+/// Like `load_image_with_options`, also returning the metadata the decoder read. See `DecodedImage`.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn load_image_with_metadata<P: AsRef<Path>>(path: P, options: &LoadOptions) -> Result<DecodedImage, Error> {
+	load_image_from_reader_with_metadata(buffered(File::open(path)?), options)
+}
+
+
+pub fn load_image_from_reader_with_metadata<R: BufRead + Seek>(reader: R, options: &LoadOptions) -> Result<DecodedImage, Error> {
+	let mut metadata = Metadata::default();
+	let (format, img, _) = load(reader, options, Some(&mut metadata))?;
+	Ok(metadata.into_decoded(format, img))
+}
+
+
+/// Like `load_image_with_options`, but never fails: an image that can't be loaded is replaced by
+/// `LoadOptions::placeholder`, returned with the error as a warning. The placeholder is the size `probe_image` reports
+/// for the file if it gets that far, and has the format it reports, otherwise no format. It's converted to
+/// `LoadOptions::output` like a decoded image would be.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn load_image_or_placeholder<P: AsRef<Path>>(path: P, options: &LoadOptions) -> (Option<Format>, DynamicImage, Option<Error>) {
+	match File::open(path) {
+		Ok(file) => load_image_from_reader_or_placeholder(buffered(file), options),
+		Err(e) => (None, options.apply_output(options.placeholder.render(None, options)), Some(e.into())),
+	}
+}
+
+
+pub fn load_image_from_reader_or_placeholder<R: BufRead + Seek>(mut reader: R, options: &LoadOptions) -> (Option<Format>, DynamicImage, Option<Error>) {
+	let err = match load(&mut reader, options, None) {
+		Ok((format, img, _)) => return (Some(format), img, None),
+		Err(e) => e,
+	};
+	let info = reader.rewind().ok().and_then(|()| probe_image_from_reader(reader).ok());
+	let img = options.placeholder.render(info.map(|info| (info.width, info.height)), options);
+	(info.map(|info| info.format), options.apply_output(img), Some(err))
+}
+
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn load_image_with_report<P: AsRef<Path>>(path: P, options: &LoadOptions) -> Result<(Format, DynamicImage, ResourceReport), Error> {
+	load_image_from_reader_with_report(buffered(File::open(path)?), options)
+}
+
+
+/// Like `load_image_from_reader_with_options`, also measuring what the decode cost. See `accounting`.
+pub fn load_image_from_reader_with_report<R: BufRead + Seek>(reader: R, options: &LoadOptions) -> Result<(Format, DynamicImage, ResourceReport), Error> {
+	let mut reader = CountingReader::new(reader);
+	let tracker = PeakTracker::start();
+	let (format, img, non_finite) = load(&mut reader, options, None)?;
+	let report = ResourceReport {
+		bytes_read: reader.count,
+		peak_bytes_allocated: tracker.finish(),
+		non_finite_pixels: non_finite as u64,
+	};
+	Ok((format, img, report))
 }
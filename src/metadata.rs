@@ -0,0 +1,153 @@
+//! Metadata without the pixels: `extract_metadata` reads the EXIF, XMP, IPTC and ICC blocks of JPEG, PNG, WebP and
+//! TIFF files along with what `probe_image` reports, touching no more of the file than it takes to find them.
+//!
+//! JPEG marker segments are read here, up to the first scan, rather than through `JpegDecoder`, which would decode
+//! lossless and arithmetic coded files up front. PNG and WebP go through their decoders, whose constructors only read
+//! the headers. TIFF tags are read straight from the first directory.
+
+use std::io::{BufRead, Seek};
+
+use image::{ImageDecoder, ImageFormat, metadata::Orientation};
+
+use crate::{
+	Error, Format, ImageInfo, PngDecoder, WebPDecoder,
+	exif::{ByteOrder, Exif, Ifd, Value, tags},
+	iptc::Iptc,
+	jpeg_markers,
+};
+
+
+const TIFF_XMP_TAG: u16 = 700;
+const TIFF_IPTC_TAG: u16 = 33723;
+const TIFF_ICC_PROFILE_TAG: u16 = 34675;
+
+
+/// What `extract_metadata` found. Blocks are `None` when the format can't carry them or the file doesn't, and are
+/// passed through as stored, like the fields of `DecodedImage`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageMetadata {
+	/// As `probe_image_from_reader` reports it
+	pub info: ImageInfo,
+	pub icc_profile: Option<Vec<u8>>,
+	/// Raw EXIF, starting with the TIFF header. See `exif::Exif::parse`. Always `None` for TIFF files, which are TIFF
+	/// structured themselves: parse the file instead.
+	pub exif: Option<Vec<u8>>,
+	/// The XMP packet
+	pub xmp: Option<Vec<u8>>,
+	/// The ExtendedXMP packet of a JPEG whose XMP didn't fit in one segment. See `JpegDecoder::extended_xmp`.
+	pub extended_xmp: Option<Vec<u8>>,
+	/// IPTC-IIM data, in whatever wrapping the format stores it in: the bare records of a JPEG's APP13 segments or a
+	/// TIFF's IPTC tag, or the hex dump of a PNG's raw profile text chunk. See `iptc::Iptc::parse`.
+	pub iptc: Option<Vec<u8>>,
+	/// The EXIF (or TIFF) orientation, which the pixels still need to be displayed upright
+	pub orientation: Orientation,
+}
+
+
+impl ImageMetadata {
+	/// The EXIF, parsed. See `DecodedImage::parsed_exif`.
+	pub fn parsed_exif(&self) -> Option<Exif> {
+		Exif::parse(self.exif.as_deref()?)
+	}
+
+	/// The IPTC records, parsed. See `DecodedImage::parsed_iptc`.
+	pub fn parsed_iptc(&self) -> Option<Iptc> {
+		Iptc::parse(self.iptc.as_deref()?)
+	}
+}
+
+
+/// Reads the image's metadata without decoding it. JPEG, PNG, WebP and TIFF files have their metadata blocks read;
+/// other formats `probe_image_from_reader` supports report the basic info alone.
+pub fn extract_metadata<R: BufRead + Seek>(mut reader: R) -> Result<ImageMetadata, Error> {
+	let info = crate::probe_image_from_reader(&mut reader)?;
+	reader.rewind()?;
+	let mut metadata = ImageMetadata {
+		info,
+		icc_profile: None,
+		exif: None,
+		xmp: None,
+		extended_xmp: None,
+		iptc: None,
+		orientation: Orientation::NoTransforms,
+	};
+
+	match info.format {
+		Format::Image(ImageFormat::Jpeg) => {
+			let mut input = Vec::new();
+			reader.read_to_end(&mut input)?;
+			read_jpeg(&input, &mut metadata);
+		},
+		Format::Image(ImageFormat::Png) => read_decoder(&mut PngDecoder::new(reader)?, &mut metadata)?,
+		Format::Image(ImageFormat::WebP) => read_decoder(&mut WebPDecoder::new(reader)?, &mut metadata)?,
+		Format::Image(ImageFormat::Tiff) => {
+			let mut input = Vec::new();
+			reader.read_to_end(&mut input)?;
+			read_tiff(&input, &mut metadata);
+		},
+		_ => (),
+	}
+	Ok(metadata)
+}
+
+
+fn read_decoder(decoder: &mut impl ImageDecoder, metadata: &mut ImageMetadata) -> Result<(), Error> {
+	metadata.icc_profile = decoder.icc_profile()?;
+	metadata.exif = decoder.exif_metadata()?;
+	metadata.xmp = decoder.xmp_metadata()?;
+	metadata.iptc = decoder.iptc_metadata()?;
+	metadata.orientation = decoder.orientation()?;
+	Ok(())
+}
+
+
+/// The metadata in a JPEG's marker segments, up to the first scan.
+fn read_jpeg(input: &[u8], metadata: &mut ImageMetadata) {
+	let segments = jpeg_markers::header_segments(input);
+	let app_payload = |app: u8, prefix: &[u8]| {
+		segments
+			.iter()
+			.find(|(marker, payload)| *marker == app && payload.starts_with(prefix))
+			.map(|(_, payload)| payload[prefix.len()..].to_vec())
+	};
+	metadata.icc_profile = jpeg_markers::icc_profile(&segments);
+	metadata.exif = app_payload(0xE1, b"Exif\0\0");
+	metadata.xmp = jpeg_markers::xmp(&segments);
+	metadata.extended_xmp = metadata.xmp.as_deref().and_then(|xmp| jpeg_markers::extended_xmp(&segments, xmp));
+	metadata.iptc = jpeg_markers::iptc(&segments);
+	metadata.orientation = metadata
+		.exif
+		.as_deref()
+		.and_then(Orientation::from_exif_chunk)
+		.unwrap_or(Orientation::NoTransforms);
+}
+
+
+/// The metadata tags of a TIFF's first directory.
+fn read_tiff(input: &[u8], metadata: &mut ImageMetadata) {
+	let Some(exif) = Exif::parse(input) else {
+		return;
+	};
+	let bytes = |tag| match exif.get(Ifd::Primary, tag)? {
+		Value::Byte(bytes) | Value::Undefined(bytes) | Value::Ascii(bytes) => Some(bytes.clone()),
+		// Photoshop declares its IPTC tag as LONG, so its bytes come back in the file's byte order
+		Value::Long(longs) => Some(
+			longs
+				.iter()
+				.flat_map(|&long| match exif.byte_order {
+					ByteOrder::LittleEndian => long.to_le_bytes(),
+					ByteOrder::BigEndian => long.to_be_bytes(),
+				})
+				.collect(),
+		),
+		_ => None,
+	};
+	metadata.icc_profile = bytes(TIFF_ICC_PROFILE_TAG);
+	metadata.xmp = bytes(TIFF_XMP_TAG);
+	metadata.iptc = bytes(TIFF_IPTC_TAG);
+	metadata.orientation = exif
+		.get(Ifd::Primary, tags::ORIENTATION)
+		.and_then(Value::as_u32)
+		.and_then(|orientation| Orientation::from_exif(u8::try_from(orientation).ok()?))
+		.unwrap_or(Orientation::NoTransforms);
+}
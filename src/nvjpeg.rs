@@ -0,0 +1,316 @@
+//! JPEG decoding on NVIDIA GPUs through nvJPEG, with the `nvjpeg` feature, which links against the CUDA runtime and
+//! nvJPEG libraries (`libcudart` and `libnvjpeg`, from the CUDA toolkit; point `RUSTFLAGS=-L` at them if they aren't on
+//! the linker path).
+//!
+//! `NvJpeg` decodes 8-bit baseline and extended sequential grayscale and YCbCr JPEGs on the GPU, returning the pixels
+//! in host memory or leaving them in device memory for a GPU pipeline to pick up. Everything else (progressive,
+//! lossless, arithmetic coded, 12-bit, CMYK) and anything nvJPEG fails on goes through `load_image` on the CPU, so
+//! `NvJpeg::load_image` accepts any image. nvJPEG's IDCT and chroma upsampling differ from zune-jpeg's, so GPU decodes
+//! differ from CPU ones by a few levels.
+//!
+//! `PinnedAllocator` has other formats, and anything else decoded on the CPU, decoded into pinned host memory (see
+//! `allocator`).
+//!
+//! ```no_run
+//! use imgest::nvjpeg::{NvJpeg, NvJpegOutput};
+//!
+//! let mut gpu = NvJpeg::new().unwrap();
+//! let data = std::fs::read("photo.jpg").unwrap();
+//! let decoded = gpu.decode(&data, NvJpegOutput::Device).unwrap();
+//! ```
+
+use std::{ffi::c_void, ptr};
+
+use image::{DynamicImage, GrayImage, ImageFormat, RgbImage};
+
+use crate::{Error, Format, JpegDecoder, LoadOptions, allocator::OutputAllocator};
+
+
+/// Where `NvJpeg::decode` leaves the pixels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NvJpegOutput {
+	/// Copied back to an image in host memory
+	#[default]
+	Host,
+	/// Left in device memory, see `DeviceImage`
+	Device,
+}
+
+
+/// The result of `NvJpeg::decode`.
+#[derive(Debug)]
+pub enum NvJpegImage {
+	Host(DynamicImage),
+	Device(DeviceImage),
+}
+
+
+/// An 8-bit image in CUDA device memory, freed when dropped: interleaved RGB, or gray for grayscale JPEGs, with rows
+/// `pitch` bytes apart.
+#[derive(Debug)]
+pub struct DeviceImage {
+	ptr: *mut u8,
+	pub width: u32,
+	pub height: u32,
+	pub channels: u8,
+	pub pitch: usize,
+}
+
+// Device memory belongs to the CUDA context rather than to a thread
+unsafe impl Send for DeviceImage {}
+
+impl DeviceImage {
+	/// The device pointer, valid until the image is dropped.
+	pub fn as_ptr(&self) -> *const u8 {
+		self.ptr
+	}
+
+	/// Copies the image back to host memory.
+	pub fn to_host(&self) -> Result<DynamicImage, Error> {
+		let row = self.width as usize * usize::from(self.channels);
+		let mut data = vec![0; crate::error::pixel_buffer_len(self.width, self.height, self.channels)?];
+		// SAFETY: `ptr` holds `height` rows of `pitch` bytes, each starting with `row` bytes of pixels, and `data` is
+		// `height` rows of `row` bytes
+		let status = unsafe { cudaMemcpy2D(data.as_mut_ptr().cast(), row, self.ptr.cast(), self.pitch, row, self.height as usize, CUDA_MEMCPY_DEVICE_TO_HOST) };
+		cuda_result(status, "cudaMemcpy2D")?;
+		let img = match self.channels {
+			1 => GrayImage::from_raw(self.width, self.height, data).map(DynamicImage::ImageLuma8),
+			_ => RgbImage::from_raw(self.width, self.height, data).map(DynamicImage::ImageRgb8),
+		};
+		Ok(img.expect("buffer size matches the dimensions"))
+	}
+}
+
+impl Drop for DeviceImage {
+	fn drop(&mut self) {
+		// SAFETY: `ptr` came from `cudaMalloc` and is freed only here
+		unsafe { cudaFree(self.ptr.cast()) };
+	}
+}
+
+
+/// Allocates CUDA pinned host memory, for `load_raw_from_reader_with_allocator`: images decoded on the CPU into it can
+/// be copied to the GPU without a staging copy.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PinnedAllocator;
+
+impl OutputAllocator for PinnedAllocator {
+	type Buffer = PinnedBuffer;
+
+	fn allocate(&self, len: usize) -> Result<PinnedBuffer, Error> {
+		let mut ptr = ptr::null_mut();
+		// SAFETY: plain out parameter
+		cuda_result(unsafe { cudaHostAlloc(&mut ptr, len.max(1), CUDA_HOST_ALLOC_DEFAULT) }, "cudaHostAlloc")?;
+		Ok(PinnedBuffer { ptr: ptr.cast(), len })
+	}
+}
+
+
+/// `len` bytes of CUDA pinned host memory, freed when dropped.
+#[derive(Debug)]
+pub struct PinnedBuffer {
+	ptr: *mut u8,
+	len: usize,
+}
+
+// Pinned memory is ordinary host memory as far as threads are concerned
+unsafe impl Send for PinnedBuffer {}
+
+impl AsRef<[u8]> for PinnedBuffer {
+	fn as_ref(&self) -> &[u8] {
+		// SAFETY: `ptr` holds `len` bytes for as long as `self` lives
+		unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+	}
+}
+
+impl AsMut<[u8]> for PinnedBuffer {
+	fn as_mut(&mut self) -> &mut [u8] {
+		// SAFETY: as for `as_ref`, and borrowed mutably through `self`
+		unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+	}
+}
+
+impl Drop for PinnedBuffer {
+	fn drop(&mut self) {
+		// SAFETY: `ptr` came from `cudaHostAlloc` and is freed only here
+		unsafe { cudaFreeHost(self.ptr.cast()) };
+	}
+}
+
+
+/// An nvJPEG handle and decode state. Decodes one image at a time; use one per thread.
+pub struct NvJpeg {
+	handle: NvjpegHandle,
+	state: NvjpegJpegState,
+}
+
+impl NvJpeg {
+	/// Sets up nvJPEG on the current CUDA device, failing with `Error::Unsupported` if there's no CUDA device.
+	pub fn new() -> Result<NvJpeg, Error> {
+		let mut devices = 0;
+		// SAFETY: plain out parameter
+		if unsafe { cudaGetDeviceCount(&mut devices) } != 0 || devices == 0 {
+			return Err(unsupported("no CUDA device"));
+		}
+		let mut handle = ptr::null_mut();
+		// SAFETY: plain out parameter
+		nvjpeg_result(unsafe { nvjpegCreateSimple(&mut handle) }, "nvjpegCreateSimple")?;
+		let mut state = ptr::null_mut();
+		// SAFETY: `handle` was just created
+		if let Err(e) = nvjpeg_result(unsafe { nvjpegJpegStateCreate(handle, &mut state) }, "nvjpegJpegStateCreate") {
+			// SAFETY: as above, and not used again
+			unsafe { nvjpegDestroy(handle) };
+			return Err(e);
+		}
+		Ok(NvJpeg { handle, state })
+	}
+
+	/// Whether `decode` takes a JPEG with this header: 8-bit, sequential, grayscale or three components.
+	pub fn supports(header: &crate::JpegHeader) -> bool {
+		header.precision == 8 && !header.progressive && matches!(header.components, 1 | 3)
+	}
+
+	/// Decodes a JPEG on the GPU, failing with `Error::Unsupported` for those `supports` rejects. EXIF orientation isn't
+	/// applied, and MPO files decode to their primary image.
+	pub fn decode(&mut self, data: &[u8], output: NvJpegOutput) -> Result<NvJpegImage, Error> {
+		let header = JpegDecoder::read_header(data)?;
+		if !NvJpeg::supports(&header) {
+			return Err(unsupported("a progressive, lossless, 12-bit or CMYK JPEG"));
+		}
+		let channels: u8 = if header.components == 1 { 1 } else { 3 };
+		let pitch = header.width as usize * usize::from(channels);
+		let len = crate::error::pixel_buffer_len(header.width, header.height, channels)?;
+
+		let mut device = ptr::null_mut();
+		// SAFETY: plain out parameter
+		cuda_result(unsafe { cudaMalloc(&mut device, len) }, "cudaMalloc")?;
+		let image = DeviceImage {
+			ptr: device.cast(),
+			width: header.width,
+			height: header.height,
+			channels,
+			pitch,
+		};
+		let mut destination = NvjpegImage {
+			channel: [image.ptr, ptr::null_mut(), ptr::null_mut(), ptr::null_mut()],
+			pitch: [pitch, 0, 0, 0],
+		};
+		let format = if channels == 1 { NVJPEG_OUTPUT_Y } else { NVJPEG_OUTPUT_RGBI };
+		// SAFETY: `destination` points at `height` rows of `pitch` bytes of device memory, which nvJPEG fills in for
+		// a `width` by `height` image in `format`; the default stream is synchronized before the memory is read or freed
+		unsafe {
+			nvjpeg_result(nvjpegDecode(self.handle, self.state, data.as_ptr(), data.len(), format, &mut destination, ptr::null_mut()), "nvjpegDecode")?;
+			cuda_result(cudaStreamSynchronize(ptr::null_mut()), "cudaStreamSynchronize")?;
+		}
+
+		match output {
+			NvJpegOutput::Host => Ok(NvJpegImage::Host(image.to_host()?)),
+			NvJpegOutput::Device => Ok(NvJpegImage::Device(image)),
+		}
+	}
+
+	/// `load_image_from_bytes_with_options` decoding the JPEGs `supports` takes on the GPU when `options` needs
+	/// nothing the GPU path doesn't do (any `output` or `output_depth` is applied afterwards, but JPEG options,
+	/// downscaling and orientation send it to the CPU), and falling back to the CPU for everything else, including
+	/// images nvJPEG fails on.
+	pub fn load_image(&mut self, data: &[u8], options: &LoadOptions) -> Result<(Format, DynamicImage), Error> {
+		let plain = options.jpeg == crate::JpegOptions::default() && options.target_max_dimension.is_none() && !options.apply_orientation;
+		let is_jpeg = Format::guess(data) == Some(ImageFormat::Jpeg.into());
+		if plain
+			&& is_jpeg
+			&& let Ok(header) = JpegDecoder::read_header(data)
+			&& NvJpeg::supports(&header)
+		{
+			let pixels = u64::from(header.width) * u64::from(header.height);
+			options.check_size(pixels, pixels * u64::from(header.components), data.len() as u64)?;
+			if let Ok(NvJpegImage::Host(img)) = self.decode(data, NvJpegOutput::Host) {
+				let format = if crate::jpeg_decoder::mp_entries(data).is_some() { Format::Mpo } else { ImageFormat::Jpeg.into() };
+				return Ok((format, options.apply_output(img)));
+			}
+		}
+		crate::load_image_from_bytes_with_options(data, options)
+	}
+}
+
+impl Drop for NvJpeg {
+	fn drop(&mut self) {
+		// SAFETY: both were created in `new` and are destroyed only here, state first
+		unsafe {
+			nvjpegJpegStateDestroy(self.state);
+			nvjpegDestroy(self.handle);
+		}
+	}
+}
+
+
+fn unsupported(what: &str) -> Error {
+	Error::Unsupported(image::error::UnsupportedError::from_format_and_kind(
+		ImageFormat::Jpeg.into(),
+		image::error::UnsupportedErrorKind::GenericFeature(format!("nvJPEG decoding of {what}")),
+	))
+}
+
+
+fn nvjpeg_result(status: i32, call: &str) -> Result<(), Error> {
+	match status {
+		0 => Ok(()),
+		status => Err(Error::Decoding(image::error::DecodingError::new(ImageFormat::Jpeg.into(), format!("{call} failed with nvJPEG status {status}")))),
+	}
+}
+
+
+fn cuda_result(status: i32, call: &str) -> Result<(), Error> {
+	match status {
+		0 => Ok(()),
+		// cudaErrorMemoryAllocation
+		2 => Err(Error::Limits(image::error::LimitError::from_kind(image::error::LimitErrorKind::InsufficientMemory))),
+		status => Err(Error::Io(std::io::Error::other(format!("{call} failed with CUDA error {status}")))),
+	}
+}
+
+
+type NvjpegHandle = *mut c_void;
+type NvjpegJpegState = *mut c_void;
+type CudaStream = *mut c_void;
+
+/// `nvjpegImage_t`
+#[repr(C)]
+struct NvjpegImage {
+	channel: [*mut u8; 4],
+	pitch: [usize; 4],
+}
+
+// `nvjpegOutputFormat_t`
+const NVJPEG_OUTPUT_Y: i32 = 2;
+const NVJPEG_OUTPUT_RGBI: i32 = 5;
+// `cudaMemcpyKind`
+const CUDA_MEMCPY_DEVICE_TO_HOST: i32 = 2;
+const CUDA_HOST_ALLOC_DEFAULT: u32 = 0;
+
+#[link(name = "nvjpeg")]
+unsafe extern "C" {
+	fn nvjpegCreateSimple(handle: *mut NvjpegHandle) -> i32;
+	fn nvjpegDestroy(handle: NvjpegHandle) -> i32;
+	fn nvjpegJpegStateCreate(handle: NvjpegHandle, state: *mut NvjpegJpegState) -> i32;
+	fn nvjpegJpegStateDestroy(state: NvjpegJpegState) -> i32;
+	fn nvjpegDecode(
+		handle: NvjpegHandle,
+		state: NvjpegJpegState,
+		data: *const u8,
+		length: usize,
+		output_format: i32,
+		destination: *mut NvjpegImage,
+		stream: CudaStream,
+	) -> i32;
+}
+
+#[link(name = "cudart")]
+unsafe extern "C" {
+	fn cudaGetDeviceCount(count: *mut i32) -> i32;
+	fn cudaMalloc(ptr: *mut *mut c_void, size: usize) -> i32;
+	fn cudaFree(ptr: *mut c_void) -> i32;
+	fn cudaHostAlloc(ptr: *mut *mut c_void, size: usize, flags: u32) -> i32;
+	fn cudaFreeHost(ptr: *mut c_void) -> i32;
+	fn cudaMemcpy2D(dst: *mut c_void, dpitch: usize, src: *const c_void, spitch: usize, width: usize, height: usize, kind: i32) -> i32;
+	fn cudaStreamSynchronize(stream: CudaStream) -> i32;
+}
@@ -0,0 +1,103 @@
+//! Bounding the number of files open at once during batch ingest.
+//!
+//! Decode concurrency and open files are separate budgets: a batch can run more decodes than the process may hold
+//! file descriptors for (EMFILE), especially when other code in the process holds descriptors of its own.
+//! `OpenFileLimit` is a semaphore that file reads take a permit from, sized by default from `RLIMIT_NOFILE`.
+
+use std::{
+	io,
+	path::Path,
+	sync::{Condvar, Mutex},
+};
+
+
+/// Descriptors left over for stdio, outputs and whatever else the process has open when deriving the default limit.
+const RESERVED: u64 = 32;
+
+/// Used where the descriptor limit can't be read.
+const FALLBACK: usize = 256;
+
+
+/// A counting semaphore over open file handles.
+#[derive(Debug)]
+pub struct OpenFileLimit {
+	max: usize,
+	available: Mutex<usize>,
+	released: Condvar,
+}
+
+impl OpenFileLimit {
+	/// Allows `max` files open at once; a `max` of 0 is treated as 1.
+	pub fn new(max: usize) -> OpenFileLimit {
+		let max = max.max(1);
+		OpenFileLimit {
+			max,
+			available: Mutex::new(max),
+			released: Condvar::new(),
+		}
+	}
+
+	/// Derived from the process's soft `RLIMIT_NOFILE`, less a reserve for descriptors opened elsewhere.
+	pub fn from_rlimit() -> OpenFileLimit {
+		let max = match descriptor_limit() {
+			Some(limit) => usize::try_from(limit.saturating_sub(RESERVED)).unwrap_or(usize::MAX),
+			None => FALLBACK,
+		};
+		OpenFileLimit::new(max)
+	}
+
+	pub fn max(&self) -> usize {
+		self.max
+	}
+
+	/// Waits for a permit, which is returned when the `OpenFilePermit` is dropped.
+	pub fn acquire(&self) -> OpenFilePermit<'_> {
+		let mut available = self.available.lock().unwrap();
+		while *available == 0 {
+			available = self.released.wait(available).unwrap();
+		}
+		*available -= 1;
+		OpenFilePermit { limit: self }
+	}
+
+	/// `std::fs::read` under a permit, which is held only while the file is open.
+	pub fn read<P: AsRef<Path>>(&self, path: P) -> io::Result<Vec<u8>> {
+		let _permit = self.acquire();
+		std::fs::read(path)
+	}
+}
+
+
+/// A permit to hold one file open.
+#[derive(Debug)]
+pub struct OpenFilePermit<'a> {
+	limit: &'a OpenFileLimit,
+}
+
+impl Drop for OpenFilePermit<'_> {
+	fn drop(&mut self) {
+		*self.limit.available.lock().unwrap() += 1;
+		self.limit.released.notify_one();
+	}
+}
+
+
+/// The soft limit on open file descriptors, if there is one and it can be read.
+pub fn descriptor_limit() -> Option<u64> {
+	#[cfg(unix)]
+	{
+		let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+		// SAFETY: `limit` is a valid rlimit for getrlimit to write to
+		if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 || limit.rlim_cur == libc::RLIM_INFINITY {
+			return None;
+		}
+		// rlim_t is u64 on Linux and macOS but not on every Unix
+		#[allow(clippy::unnecessary_cast)]
+		let limit = limit.rlim_cur as u64;
+		Some(limit)
+	}
+	#[cfg(not(unix))]
+	{
+		None
+	}
+}
@@ -0,0 +1,818 @@
+use image::{DynamicImage, Limits};
+use sha2::{Digest, Sha256};
+
+use crate::{cancel::CancelToken, convert, error::Error, versions};
+
+
+/// Options for `load_image_with_options`.
+///
+/// Format specific knobs live in per-format sub-structs so the option surface stays navigable as it grows; options
+/// for a format are ignored when decoding any other format. Either fill in the fields with `..Default::default()` or
+/// chain the builder methods:
+///
+/// ```
+/// use imgest::{AnimatedPolicy, LoadOptions, OutputColor};
+///
+/// let options = LoadOptions::new().output(OutputColor::Rgba8).animated_policy(AnimatedPolicy::FirstFrame);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadOptions {
+	pub png: PngOptions,
+	pub jpeg: JpegOptions,
+	pub tiff: TiffOptions,
+	pub ico: IcoOptions,
+	pub hdr: HdrOptions,
+	pub svg: SvgOptions,
+	pub avif: AvifOptions,
+	pub output: OutputColor,
+	/// Bit depth of the returned image, applied after `output`.
+	pub output_depth: OutputDepth,
+	pub animated_policy: AnimatedPolicy,
+	/// Dimension and allocation limits, passed to every decoder. The output buffer counts against `max_alloc`, so
+	/// images bigger than it fail before anything is decoded. Defaults to `Limits::no_limits()`.
+	pub limits: Limits,
+	/// Fail with `Error::TooBig` for images of more than this many pixels, before decoding them.
+	pub max_pixels: Option<u64>,
+	/// Fail with `Error::TooBig` for files bigger than this, before reading past the format signature.
+	pub max_file_bytes: Option<u64>,
+	/// Fail with `Error::TooBig` for images whose decoded buffer is bigger than this, before decoding them. Also caps
+	/// `limits.max_alloc` for the decoders, so those that account for their own working memory fail with
+	/// `Error::Limits` partway through if they go over it.
+	pub max_alloc_bytes: Option<u64>,
+	/// Fail with `Error::TooBig` for images whose decoded buffer is more than this many times the size of the file,
+	/// before decoding them: a guard against decompression bombs, which declare huge dimensions in a small file.
+	/// Legitimate images can compress very well (deflate reaches about 1000:1 on flat color), so set it generously.
+	pub max_expansion_ratio: Option<u64>,
+	/// Rotate and flip the image upright according to its EXIF (or format specific) orientation, as browsers and
+	/// Pillow's `ImageOps.exif_transpose` do. Off by default, returning pixels as stored.
+	pub apply_orientation: bool,
+	/// Decode JPEGs and PNGs already reduced, to no smaller than this on their longer side, for callers that resize
+	/// down anyway. This isn't an exact resize: JPEGs are reduced by 1/2, 1/4 or 1/8 (see `DctScale::for_target`),
+	/// PNGs by the largest integer factor. At 1/8, JPEGs take the DC-only path (see `JpegDecoder::dc_preview`), which
+	/// skips the IDCT; at 1/2 and 1/4 they're decoded with a scaled IDCT (see `DctScale`). PNGs are box filtered as rows
+	/// stream in (see `PngDecoder::decode_downscaled`). Either way the full size image is never held in memory. Takes
+	/// precedence over a smaller `JpegOptions::dct_scale`.
+	/// Other formats are decoded at full size. Size limits apply to the reduced size for JPEGs, and the full size for
+	/// everything else.
+	pub target_max_dimension: Option<u32>,
+	/// Checked throughout decoding, failing with `Error::Cancelled` once it's cancelled. See `CancelToken` for how
+	/// often each format checks.
+	pub cancel_token: Option<CancelToken>,
+	/// What `load_image_or_placeholder` returns for inputs that fail to decode. Ignored by the other functions.
+	pub placeholder: Placeholder,
+}
+
+impl Default for LoadOptions {
+	fn default() -> Self {
+		LoadOptions {
+			png: PngOptions::default(),
+			jpeg: JpegOptions::default(),
+			tiff: TiffOptions::default(),
+			ico: IcoOptions::default(),
+			hdr: HdrOptions::default(),
+			svg: SvgOptions::default(),
+			avif: AvifOptions::default(),
+			output: OutputColor::default(),
+			output_depth: OutputDepth::default(),
+			animated_policy: AnimatedPolicy::default(),
+			limits: Limits::no_limits(),
+			max_pixels: None,
+			max_file_bytes: None,
+			max_alloc_bytes: None,
+			max_expansion_ratio: None,
+			apply_orientation: false,
+			target_max_dimension: None,
+			cancel_token: None,
+			placeholder: Placeholder::default(),
+		}
+	}
+}
+
+impl LoadOptions {
+	pub fn new() -> LoadOptions {
+		LoadOptions::default()
+	}
+
+	pub fn png(mut self, png: PngOptions) -> Self {
+		self.png = png;
+		self
+	}
+
+	pub fn jpeg(mut self, jpeg: JpegOptions) -> Self {
+		self.jpeg = jpeg;
+		self
+	}
+
+	pub fn tiff(mut self, tiff: TiffOptions) -> Self {
+		self.tiff = tiff;
+		self
+	}
+
+	pub fn ico(mut self, ico: IcoOptions) -> Self {
+		self.ico = ico;
+		self
+	}
+
+	pub fn hdr(mut self, hdr: HdrOptions) -> Self {
+		self.hdr = hdr;
+		self
+	}
+
+	pub fn svg(mut self, svg: SvgOptions) -> Self {
+		self.svg = svg;
+		self
+	}
+
+	pub fn avif(mut self, avif: AvifOptions) -> Self {
+		self.avif = avif;
+		self
+	}
+
+	pub fn output(mut self, output: OutputColor) -> Self {
+		self.output = output;
+		self
+	}
+
+	pub fn output_depth(mut self, output_depth: OutputDepth) -> Self {
+		self.output_depth = output_depth;
+		self
+	}
+
+	pub fn animated_policy(mut self, animated_policy: AnimatedPolicy) -> Self {
+		self.animated_policy = animated_policy;
+		self
+	}
+
+	pub fn limits(mut self, limits: Limits) -> Self {
+		self.limits = limits;
+		self
+	}
+
+	pub fn max_pixels(mut self, max_pixels: u64) -> Self {
+		self.max_pixels = Some(max_pixels);
+		self
+	}
+
+	pub fn max_file_bytes(mut self, max_file_bytes: u64) -> Self {
+		self.max_file_bytes = Some(max_file_bytes);
+		self
+	}
+
+	pub fn max_alloc_bytes(mut self, max_alloc_bytes: u64) -> Self {
+		self.max_alloc_bytes = Some(max_alloc_bytes);
+		self
+	}
+
+	pub fn max_expansion_ratio(mut self, max_expansion_ratio: u64) -> Self {
+		self.max_expansion_ratio = Some(max_expansion_ratio);
+		self
+	}
+
+	pub fn apply_orientation(mut self, apply_orientation: bool) -> Self {
+		self.apply_orientation = apply_orientation;
+		self
+	}
+
+	pub fn target_max_dimension(mut self, target_max_dimension: u32) -> Self {
+		self.target_max_dimension = Some(target_max_dimension);
+		self
+	}
+
+	pub fn cancel_token(mut self, cancel_token: CancelToken) -> Self {
+		self.cancel_token = Some(cancel_token);
+		self
+	}
+
+	pub fn placeholder(mut self, placeholder: Placeholder) -> Self {
+		self.placeholder = placeholder;
+		self
+	}
+
+	/// A stable hash (64 hex digits) of everything that determines what loading with these options produces: the
+	/// fields hashed by `options_fingerprint`, the imgest version and enabled features, and the backend crate
+	/// versions. Stored alongside decoded outputs, it identifies the configuration that produced them. It changes with
+	/// every imgest release, whether or not the output does.
+	pub fn config_fingerprint(&self) -> String {
+		let mut hasher = Sha256::new();
+		hasher.update(format!("imgest {}\n", env!("CARGO_PKG_VERSION")));
+		for (name, version, enabled) in versions::BACKENDS {
+			hasher.update(format!("{name} {version} {enabled}\n"));
+		}
+		for (feature, enabled) in versions::FEATURES {
+			hasher.update(format!("{feature} {enabled}\n"));
+		}
+		hasher.update(self.fingerprint_fields());
+		hasher.finalize().iter().map(|b| format!("{b:02x}")).collect()
+	}
+
+	/// A stable hash (64 hex digits) of the options alone, leaving out `cancel_token` and `avif.threads`, which don't
+	/// change the output. It doesn't depend on the imgest or backend versions, so it stays the same across releases
+	/// unless the field list's version tag (`FINGERPRINT_VERSION`) is bumped.
+	pub fn options_fingerprint(&self) -> String {
+		Sha256::digest(self.fingerprint_fields()).iter().map(|b| format!("{b:02x}")).collect()
+	}
+
+	/// Version tag of `fingerprint_fields`, bumped whenever a field is added, removed or encoded differently.
+	const FINGERPRINT_VERSION: u32 = 1;
+
+	/// The options hashed by the fingerprints, one `name=value` line per field. Fields are listed explicitly, rather
+	/// than taken from the `Debug` output, so the encoding only changes along with `FINGERPRINT_VERSION`.
+	fn fingerprint_fields(&self) -> String {
+		fn opt<T: std::fmt::Display>(value: Option<T>) -> String {
+			value.map_or_else(|| "none".to_string(), |value| value.to_string())
+		}
+		let rgba = |color: [u8; 4]| color.map(|b| format!("{b:02x}")).concat();
+		let (png, jpeg, hdr, svg, limits) = (&self.png, &self.jpeg, &self.hdr, &self.svg, &self.limits);
+
+		let fields = [
+			("png.keep_indexed", png.keep_indexed.to_string()),
+			(
+				"png.sixteen_bit",
+				match png.sixteen_bit {
+					SixteenBit::Keep => "keep",
+					SixteenBit::Truncate => "truncate",
+					SixteenBit::Round => "round",
+				}
+				.to_string(),
+			),
+			("png.skip_metadata", png.skip_metadata.to_string()),
+			("png.max_metadata_bytes", opt(png.max_metadata_bytes)),
+			(
+				"png.significant_bits",
+				match png.significant_bits {
+					SignificantBits::Ignore => "ignore",
+					SignificantBits::HighBits => "high_bits",
+					SignificantBits::LowBits => "low_bits",
+				}
+				.to_string(),
+			),
+			(
+				"png.gamma",
+				match png.gamma {
+					PngGamma::Ignore => "ignore",
+					PngGamma::Linear => "linear",
+					PngGamma::Srgb => "srgb",
+				}
+				.to_string(),
+			),
+			("jpeg.dct_scale", jpeg.dct_scale.denominator().to_string()),
+			(
+				"jpeg.upsampling",
+				match jpeg.upsampling {
+					ChromaUpsampling::Fancy => "fancy",
+				}
+				.to_string(),
+			),
+			("jpeg.resync", jpeg.resync.to_string()),
+			(
+				"tiff.multi_page",
+				match self.tiff.multi_page {
+					MultiPage::FirstPage => "first_page",
+					MultiPage::Reject => "reject",
+				}
+				.to_string(),
+			),
+			("ico.size", opt(self.ico.size)),
+			(
+				"hdr.tone_map",
+				opt(hdr.tone_map.map(|tone_map| match tone_map {
+					ToneMap::Clamp => "clamp",
+					ToneMap::Reinhard => "reinhard",
+				})),
+			),
+			(
+				"hdr.non_finite",
+				match hdr.non_finite {
+					NonFinite::Keep => "keep",
+					NonFinite::Zero => "zero",
+					NonFinite::Clamp => "clamp",
+					NonFinite::Reject => "reject",
+				}
+				.to_string(),
+			),
+			("svg.dpi", svg.dpi.to_string()),
+			("svg.size", opt(svg.size.map(|(width, height)| format!("{width}x{height}")))),
+			(
+				"output",
+				match self.output {
+					OutputColor::Native => "native",
+					OutputColor::Rgba8 => "rgba8",
+					OutputColor::Rgba8KeepGrayAlpha => "rgba8_keep_gray_alpha",
+					OutputColor::Luma8 => "luma8",
+				}
+				.to_string(),
+			),
+			(
+				"output_depth",
+				match self.output_depth {
+					OutputDepth::Preserve => "preserve",
+					OutputDepth::Force8 => "force8",
+					OutputDepth::Force16 => "force16",
+				}
+				.to_string(),
+			),
+			(
+				"animated_policy",
+				match self.animated_policy {
+					AnimatedPolicy::Reject => "reject",
+					AnimatedPolicy::FirstFrame => "first_frame",
+				}
+				.to_string(),
+			),
+			("limits.max_image_width", opt(limits.max_image_width)),
+			("limits.max_image_height", opt(limits.max_image_height)),
+			("limits.max_alloc", opt(limits.max_alloc)),
+			("max_pixels", opt(self.max_pixels)),
+			("max_file_bytes", opt(self.max_file_bytes)),
+			("max_alloc_bytes", opt(self.max_alloc_bytes)),
+			("max_expansion_ratio", opt(self.max_expansion_ratio)),
+			("apply_orientation", self.apply_orientation.to_string()),
+			("target_max_dimension", opt(self.target_max_dimension)),
+			(
+				"placeholder.fill",
+				match self.placeholder.fill {
+					PlaceholderFill::Solid(color) => format!("solid {}", rgba(color)),
+					PlaceholderFill::Checkerboard { cell, colors } => format!("checkerboard {cell} {} {}", rgba(colors[0]), rgba(colors[1])),
+				},
+			),
+			("placeholder.size", format!("{}x{}", self.placeholder.width, self.placeholder.height)),
+		];
+
+		let mut out = format!("imgest-options v{}\n", LoadOptions::FINGERPRINT_VERSION);
+		for (name, value) in fields {
+			out.push_str(&format!("{name}={value}\n"));
+		}
+		out
+	}
+
+	/// Converts a decoded image to `output`, then `output_depth`.
+	pub(crate) fn apply_output(&self, img: DynamicImage) -> DynamicImage {
+		self.output_depth.apply(self.output.apply(img))
+	}
+
+	/// `limits`, with `max_alloc` lowered to `max_alloc_bytes`.
+	pub(crate) fn decoder_limits(&self) -> Limits {
+		let mut limits = self.limits.clone();
+		if let Some(max) = self.max_alloc_bytes {
+			limits.max_alloc = Some(limits.max_alloc.map_or(max, |alloc| alloc.min(max)));
+		}
+		limits
+	}
+
+	/// Checks an image's pixel count and decoded size against `max_pixels`, `max_alloc_bytes` and, for an input of
+	/// `input_len` bytes, `max_expansion_ratio`.
+	pub(crate) fn check_size(&self, pixels: u64, bytes: u64, input_len: u64) -> Result<(), Error> {
+		if self.max_pixels.is_some_and(|max| pixels > max)
+			|| self.max_alloc_bytes.is_some_and(|max| bytes > max)
+			|| self.max_expansion_ratio.is_some_and(|ratio| bytes > input_len.max(1).saturating_mul(ratio))
+		{
+			return Err(Error::TooBig);
+		}
+		Ok(())
+	}
+}
+
+
+/// What to do with animated images (APNG, GIF, WebP, JPEG XL).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AnimatedPolicy {
+	/// Fail with `Error::Animated`.
+	#[default]
+	Reject,
+	/// Decode the first frame as if the image were static. For APNG this is the default image, which an encoder may
+	/// have supplied as a fallback separate from the animation.
+	FirstFrame,
+}
+
+impl AnimatedPolicy {
+	pub(crate) fn check(self, animated: bool) -> Result<(), Error> {
+		match (self, animated) {
+			(AnimatedPolicy::Reject, true) => Err(Error::Animated),
+			_ => Ok(()),
+		}
+	}
+}
+
+
+/// A generated stand-in for an image that failed to decode, for preview and serving paths that would rather show
+/// something than handle the failure. See `load_image_or_placeholder`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placeholder {
+	pub fill: PlaceholderFill,
+	/// Size of the placeholder when the failed image's own size can't be probed, or is too big to reproduce (over
+	/// `max_pixels`, or 16 megapixels, so a decompression bomb doesn't get an equally big placeholder).
+	pub width: u32,
+	pub height: u32,
+}
+
+impl Default for Placeholder {
+	fn default() -> Self {
+		Placeholder {
+			fill: PlaceholderFill::Checkerboard {
+				cell: 16,
+				colors: [[0xCC, 0xCC, 0xCC, 0xFF], [0xFF, 0xFF, 0xFF, 0xFF]],
+			},
+			width: 256,
+			height: 256,
+		}
+	}
+}
+
+impl Placeholder {
+	/// The largest probed size taken for a placeholder, in pixels.
+	const MAX_PIXELS: u64 = 1 << 24;
+
+	/// The placeholder for an image probed at `probed`, as Rgba8 before `LoadOptions::output`. The probed size is
+	/// reduced like `target_max_dimension` would have reduced the image.
+	pub(crate) fn render(&self, probed: Option<(u32, u32)>, options: &LoadOptions) -> DynamicImage {
+		let probed = probed.map(|(width, height)| match options.target_max_dimension {
+			Some(target) if width.max(height) > target => {
+				let scale = |v: u32| (u64::from(v) * u64::from(target) / u64::from(width.max(height))).max(1) as u32;
+				(scale(width), scale(height))
+			},
+			_ => (width, height),
+		});
+		let (width, height) = probed
+			.filter(|&(width, height)| {
+				let pixels = u64::from(width) * u64::from(height);
+				pixels > 0 && pixels <= Placeholder::MAX_PIXELS && options.max_pixels.is_none_or(|max| pixels <= max)
+			})
+			.unwrap_or((self.width, self.height));
+		let img = match self.fill {
+			PlaceholderFill::Solid(color) => image::RgbaImage::from_pixel(width, height, image::Rgba(color)),
+			PlaceholderFill::Checkerboard { cell, colors } => {
+				let cell = cell.max(1);
+				image::RgbaImage::from_fn(width, height, |x, y| image::Rgba(colors[((x / cell + y / cell) % 2) as usize]))
+			},
+		};
+		DynamicImage::ImageRgba8(img)
+	}
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaceholderFill {
+	/// A single RGBA color
+	Solid([u8; 4]),
+	/// Alternating squares of two RGBA colors, `cell` pixels on a side, starting with the first in the top left
+	Checkerboard { cell: u32, colors: [[u8; 4]; 2] },
+}
+
+
+/// Color type of the returned image.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputColor {
+	/// Whatever the decoder produced, e.g. La16 for a 16-bit grayscale PNG with alpha.
+	#[default]
+	Native,
+	/// 8-bit RGBA regardless of the source. See `convert::into_rgba8`.
+	Rgba8,
+	/// 8-bit RGBA, except that grayscale images with alpha become La8 rather than being expanded, at half the memory.
+	Rgba8KeepGrayAlpha,
+	/// 8-bit gray (BT.601 luma, alpha dropped), for analysis that ignores color. JPEGs and PNGs go through their
+	/// decoders' `decode_luma`, which skips chroma decoding for JPEG and converts row by row for PNG; other formats are
+	/// converted after decoding with `convert::into_luma8`.
+	Luma8,
+}
+
+impl OutputColor {
+	pub(crate) fn apply(self, img: DynamicImage) -> DynamicImage {
+		match (self, img) {
+			(OutputColor::Native, img) => img,
+			(OutputColor::Rgba8KeepGrayAlpha, img @ DynamicImage::ImageLumaA8(_)) => img,
+			(OutputColor::Rgba8KeepGrayAlpha, img @ DynamicImage::ImageLumaA16(_)) => DynamicImage::ImageLumaA8(img.to_luma_alpha8()),
+			(OutputColor::Rgba8 | OutputColor::Rgba8KeepGrayAlpha, img) => DynamicImage::ImageRgba8(convert::into_rgba8(img)),
+			(OutputColor::Luma8, img) => DynamicImage::ImageLuma8(convert::into_luma8(img)),
+		}
+	}
+}
+
+
+/// Bit depth of the returned image, keeping its channels. 16-bit PNGs and TIFFs decode to 16-bit color types, which
+/// `Preserve` returns as they are; code that then calls `DynamicImage::into_rgba8` or the like drops the low byte
+/// without saying so, so callers that need the precision should ask for `Force16` and take the samples with
+/// `DecodedPixels`, which fails loudly on the wrong sample type rather than converting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputDepth {
+	/// Whatever `output` produced: 8 or 16-bit integers, or 32-bit floats for HDR formats.
+	#[default]
+	Preserve,
+	/// 8-bit samples, rounding 16-bit ones like `convert::u16_to_u8` and clamping floats to 0..=1.
+	Force8,
+	/// 16-bit samples, widening 8-bit ones like `convert::u8_to_u16` and clamping floats to 0..=1.
+	Force16,
+}
+
+impl OutputDepth {
+	pub(crate) fn apply(self, img: DynamicImage) -> DynamicImage {
+		let color = img.color();
+		match (self, color.has_color(), color.has_alpha()) {
+			(OutputDepth::Preserve, ..) => img,
+			(OutputDepth::Force8, false, false) => DynamicImage::ImageLuma8(img.into_luma8()),
+			(OutputDepth::Force8, false, true) => DynamicImage::ImageLumaA8(img.into_luma_alpha8()),
+			(OutputDepth::Force8, true, false) => DynamicImage::ImageRgb8(img.into_rgb8()),
+			(OutputDepth::Force8, true, true) => DynamicImage::ImageRgba8(img.into_rgba8()),
+			(OutputDepth::Force16, false, false) => DynamicImage::ImageLuma16(img.into_luma16()),
+			(OutputDepth::Force16, false, true) => DynamicImage::ImageLumaA16(img.into_luma_alpha16()),
+			(OutputDepth::Force16, true, false) => DynamicImage::ImageRgb16(img.into_rgb16()),
+			(OutputDepth::Force16, true, true) => DynamicImage::ImageRgba16(img.into_rgba16()),
+		}
+	}
+}
+
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PngOptions {
+	/// Return palette images as an 8-bit grayscale image of palette indices instead of expanding them to RGB(A).
+	/// The palette itself is available from `PngDecoder::palette`.
+	pub keep_indexed: bool,
+	pub sixteen_bit: SixteenBit,
+	/// Don't retain text chunks (tEXt/zTXt/iTXt, and so XMP and IPTC) or the ICC profile, and report no EXIF.
+	/// Text handling is a measurable share of decode time for PNGs carrying large embedded generation workflows.
+	/// The other decoders only read metadata when asked for it, so they have no equivalent.
+	pub skip_metadata: bool,
+	/// Cap on the metadata retained per image, in bytes of chunk payload. Over the cap, text chunks are dropped
+	/// first, then EXIF, then the ICC profile, and `PngDecoder::metadata_truncated` reports it. `None` retains everything.
+	pub max_metadata_bytes: Option<u64>,
+	pub significant_bits: SignificantBits,
+	pub gamma: PngGamma,
+}
+
+
+/// Correction for PNGs whose only color information is a gAMA chunk (see `PngDecoder::gamma_only`), such as those
+/// written by older tools that encode with a gamma other than sRGB's. Alpha is left alone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PngGamma {
+	/// Return samples as stored.
+	#[default]
+	Ignore,
+	/// Decode to linear light, raising samples to 1/gamma.
+	Linear,
+	/// Decode to linear light, then encode with the sRGB transfer function, so the image matches sRGB images.
+	Srgb,
+}
+
+impl PngGamma {
+	pub(crate) fn apply(self, img: DynamicImage, gamma: f64) -> DynamicImage {
+		let exponent = (1.0 / gamma) as f32;
+		let mut img = img;
+		match self {
+			PngGamma::Ignore => (),
+			PngGamma::Linear => convert::map_color_samples(&mut img, |v| v.powf(exponent)),
+			PngGamma::Srgb => convert::map_color_samples(&mut img, |v| convert::linear_to_srgb(v.powf(exponent))),
+		}
+		img
+	}
+}
+
+
+/// What to do with samples that an sBIT chunk says have fewer significant bits than the bit depth, e.g. 10-bit
+/// scanner data in a 16-bit PNG. Palette images are never rescaled, and neither are rows from `PngDecoder::into_rows`
+/// (so `OutputColor::Luma8` ignores this). `PngDecoder::significant_bits` reports the chunk either way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SignificantBits {
+	/// Return samples as stored.
+	#[default]
+	Ignore,
+	/// The significant bits are the high bits, as the PNG spec requires; keep those and rescale them to the full
+	/// range, replacing whatever the encoder put in the low bits.
+	HighBits,
+	/// The significant bits are the low bits, unscaled (0..=1023 for 10 bits), as some scanners write them; rescale
+	/// them to the full range, clamping anything above.
+	LowBits,
+}
+
+
+/// What to do with 16-bit PNG samples.
+///
+/// The references we compare against disagree here: libpng's `png_set_strip_16` (and our sweep's Pillow path, which
+/// goes through `I;16 >> 8`) keep the high byte, while `image`'s `to_rgba8` rounds. The two differ by one for about
+/// half of all 16-bit values, so strict parity users should pick the one matching their reference.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SixteenBit {
+	/// Return 16-bit color types and leave any reduction to the caller.
+	#[default]
+	Keep,
+	/// Reduce to 8 bits by keeping the high byte, as libpng does. See `convert::u16_to_u8_truncate`.
+	Truncate,
+	/// Reduce to 8 bits with rounding, i.e. `round(v * 255 / 65535)`. See `convert::u16_to_u8`.
+	Round,
+}
+
+impl SixteenBit {
+	/// The per-sample reduction, or `None` for `Keep`.
+	pub(crate) fn reduction(self) -> Option<fn(u16) -> u8> {
+		match self {
+			SixteenBit::Keep => None,
+			SixteenBit::Truncate => Some(convert::u16_to_u8_truncate),
+			SixteenBit::Round => Some(convert::u16_to_u8),
+		}
+	}
+}
+
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JpegOptions {
+	pub dct_scale: DctScale,
+	pub upsampling: ChromaUpsampling,
+	/// Salvage files with corrupt entropy coded data by replacing each damaged restart interval with the nearest
+	/// intact one, so the damage shows as repeated MCUs rather than garbage or a failed decode. Only sequential files
+	/// with restart markers can be salvaged this way; see `JpegDecoder::repaired_intervals`. Validating the intervals
+	/// costs a Huffman decoding pass.
+	pub resync: bool,
+}
+
+
+/// Output scale for JPEG decoding, as in libjpeg's `scale_num / scale_denom`.
+///
+/// Dimensions round up, matching libjpeg. zune-jpeg has no scaled IDCT, so reduced scales are decoded by jpeg-decoder's,
+/// which never holds the full size image. Files jpeg-decoder can't decode (damaged, lossless or arithmetic coded) are
+/// decoded at full size and box filtered down instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DctScale {
+	#[default]
+	Full,
+	Half,
+	Quarter,
+	Eighth,
+}
+
+impl DctScale {
+	pub fn denominator(self) -> u32 {
+		match self {
+			DctScale::Full => 1,
+			DctScale::Half => 2,
+			DctScale::Quarter => 4,
+			DctScale::Eighth => 8,
+		}
+	}
+
+	pub fn scale_dimensions(self, width: u32, height: u32) -> (u32, u32) {
+		let d = self.denominator();
+		(width.div_ceil(d), height.div_ceil(d))
+	}
+
+	/// The largest reduction that keeps the longer side of a `width` x `height` image at least `target`.
+	pub fn for_target(width: u32, height: u32, target: u32) -> DctScale {
+		[DctScale::Eighth, DctScale::Quarter, DctScale::Half]
+			.into_iter()
+			.find(|scale| width.max(height).div_ceil(scale.denominator()) >= target)
+			.unwrap_or(DctScale::Full)
+	}
+}
+
+
+/// How subsampled chroma planes are upsampled.
+///
+/// zune-jpeg only implements fancy upsampling, so that's the only choice for now; pixel replication can be added here
+/// once a backend offers it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ChromaUpsampling {
+	/// Triangle filtered ("fancy") upsampling, as done by libjpeg and therefore Pillow.
+	#[default]
+	Fancy,
+}
+
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TiffOptions {
+	pub multi_page: MultiPage,
+}
+
+
+/// What to do with TIFFs that have more than one page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MultiPage {
+	/// Decode the first page and ignore the rest.
+	#[default]
+	FirstPage,
+	/// Fail with `Error::MultiPage`.
+	Reject,
+}
+
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IcoOptions {
+	/// Preferred width of the embedded image to decode; `None` picks the largest. See `IcoDecoder`.
+	pub size: Option<u32>,
+}
+
+
+/// Options for float formats (OpenEXR, Radiance HDR), which decode to `Rgb32F`, or `Rgba32F` for EXR with alpha.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HdrOptions {
+	/// Tone map to 8-bit sRGB RGBA instead of returning linear float samples.
+	pub tone_map: Option<ToneMap>,
+	/// What to do with NaN and infinite samples, which EXR can store. Applied before tone mapping.
+	pub non_finite: NonFinite,
+}
+
+impl HdrOptions {
+	/// Applies `non_finite` then `tone_map`, returning the image and the number of pixels that had non-finite samples.
+	pub(crate) fn apply(&self, img: DynamicImage) -> Result<(DynamicImage, usize), Error> {
+		let (img, non_finite) = self.non_finite.apply(img)?;
+		let img = match self.tone_map {
+			Some(tone_map) => tone_map.apply(img),
+			None => img,
+		};
+		Ok((img, non_finite))
+	}
+}
+
+
+/// Policy for NaN and infinite float samples, so they don't propagate into whatever consumes the pixels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NonFinite {
+	/// Return them as decoded.
+	#[default]
+	Keep,
+	/// Replace them with 0.
+	Zero,
+	/// Replace NaN with 0, and infinities with the largest (or for negative infinity, smallest) finite sample in the
+	/// image, so the range of the image is that of its finite samples.
+	Clamp,
+	/// Fail with `Error::NonFinite`.
+	Reject,
+}
+
+impl NonFinite {
+	pub(crate) fn apply(self, img: DynamicImage) -> Result<(DynamicImage, usize), Error> {
+		let (width, height) = (img.width(), img.height());
+		let (mut samples, channels) = match img {
+			DynamicImage::ImageRgb32F(img) => (img.into_raw(), 3),
+			DynamicImage::ImageRgba32F(img) => (img.into_raw(), 4),
+			img => return Ok((img, 0)),
+		};
+		let finite = || samples.iter().copied().filter(|v| v.is_finite());
+		let (nan, pos_inf, neg_inf) = match self {
+			NonFinite::Keep => (f32::NAN, f32::INFINITY, f32::NEG_INFINITY),
+			NonFinite::Zero | NonFinite::Reject => (0.0, 0.0, 0.0),
+			NonFinite::Clamp => (0.0, finite().reduce(f32::max).unwrap_or(0.0), finite().reduce(f32::min).unwrap_or(0.0)),
+		};
+		// NaN replaced with NaN still counts the pixel, so Keep reports the same count as the others
+		let pixels = convert::replace_non_finite(&mut samples, channels, nan, pos_inf, neg_inf);
+		if self == NonFinite::Reject && pixels > 0 {
+			return Err(Error::NonFinite(pixels as u64));
+		}
+
+		let img = match channels {
+			3 => DynamicImage::ImageRgb32F(image::Rgb32FImage::from_raw(width, height, samples).expect("buffer came from an image of this size")),
+			_ => DynamicImage::ImageRgba32F(image::Rgba32FImage::from_raw(width, height, samples).expect("buffer came from an image of this size")),
+		};
+		Ok((img, pixels))
+	}
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMap {
+	/// Clip at 1.0, losing highlights. Right for images that are display referred but happen to be stored as float.
+	Clamp,
+	/// `convert::reinhard`, which keeps highlight detail at the cost of contrast.
+	Reinhard,
+}
+
+impl ToneMap {
+	pub(crate) fn apply(self, img: DynamicImage) -> DynamicImage {
+		let (width, height) = (img.width(), img.height());
+		let (samples, channels) = match img {
+			DynamicImage::ImageRgb32F(img) => (img.into_raw(), 3),
+			DynamicImage::ImageRgba32F(img) => (img.into_raw(), 4),
+			img => (img.into_rgba32f().into_raw(), 4),
+		};
+		let map: fn(f32) -> f32 = match self {
+			ToneMap::Clamp => |v: f32| v,
+			ToneMap::Reinhard => convert::reinhard,
+		};
+		let mut out = image::RgbaImage::new(width, height);
+		convert::tone_map_to_rgba8(&samples, channels, map, &mut out);
+		DynamicImage::ImageRgba8(out)
+	}
+}
+
+
+/// Rasterization size for SVG (with the `svg` feature), which always decodes to `Rgba8`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SvgOptions {
+	/// Resolution to render the document's own size at. SVG user units are CSS pixels, i.e. 96 per inch, so 96
+	/// renders at the document's nominal pixel size and 192 at twice that.
+	pub dpi: u32,
+	/// Fit the rendering inside this width and height, keeping the aspect ratio, instead of going by `dpi`.
+	pub size: Option<(u32, u32)>,
+}
+
+impl Default for SvgOptions {
+	fn default() -> Self {
+		SvgOptions { dpi: 96, size: None }
+	}
+}
+
+
+/// AV1 decoding options for AVIF (with the `avif` feature).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AvifOptions {
+	/// Worker threads for dav1d; `None` lets dav1d pick one per logical CPU. Callers already decoding many images in
+	/// parallel, like `BatchDecoder`, usually want `Some(1)`.
+	pub threads: Option<u32>,
+}
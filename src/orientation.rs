@@ -0,0 +1,166 @@
+//! Cheap heuristics for spotting images that are probably sideways even though their EXIF orientation says they're
+//! upright (or they have no EXIF at all), e.g. scans fed into the scanner the wrong way or photos "fixed" by software
+//! that rotated the pixels but kept stale metadata.
+//!
+//! Nothing here rotates anything; the output is meant for flagging images for review.
+
+use image::{DynamicImage, GenericImageView};
+
+use crate::exif::{Exif, Ifd, tags};
+
+
+/// Images are reduced to at most this many pixels on their long side before looking at content.
+const ANALYSIS_SIZE: u32 = 64;
+
+/// Portrait paper sizes (height / width): ISO 216 (A4 etc.) and US Letter.
+const PAPER_RATIOS: &[f64] = &[std::f64::consts::SQRT_2, 11.0 / 8.5];
+const PAPER_RATIO_TOLERANCE: f64 = 0.02;
+
+
+/// Rotation that should be applied to make the image upright.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuspectedRotation {
+	Clockwise90,
+	CounterClockwise90,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RotationSignal {
+	/// EXIF PixelXDimension/PixelYDimension are swapped relative to the actual pixels.
+	ExifDimensionsSwapped,
+	/// The thumbnail IFD claims a different orientation than the primary image.
+	ThumbnailOrientationMismatch,
+	/// Landscape image with a paper aspect ratio and no camera make, which is what sideways document scans look like.
+	PaperAspectLandscape,
+	/// One of the side edges looks like sky (bright and bluish) while the top doesn't. Carries the strength in 0..1.
+	SkyOnSide(f32),
+	/// Vertical edges dominate horizontal ones; natural scenes usually have the opposite (horizon, ground, shelves).
+	VerticalEdgeDominance,
+}
+
+impl RotationSignal {
+	fn weight(self) -> f32 {
+		match self {
+			RotationSignal::ExifDimensionsSwapped => 0.5,
+			RotationSignal::ThumbnailOrientationMismatch => 0.4,
+			RotationSignal::PaperAspectLandscape => 0.25,
+			RotationSignal::SkyOnSide(strength) => 0.8 * strength,
+			RotationSignal::VerticalEdgeDominance => 0.15,
+		}
+	}
+}
+
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RotationHint {
+	/// Rough likelihood in 0..1 that the image as stored is rotated by 90 degrees. Not a calibrated probability.
+	pub confidence: f32,
+	/// Direction of the fix, when a content signal could tell.
+	pub suspected: Option<SuspectedRotation>,
+	pub signals: Vec<RotationSignal>,
+}
+
+
+/// Estimates whether `img` is sideways. `img` should be the decoded pixels as stored, without any orientation applied;
+/// this is meant for images whose EXIF orientation is 1 or missing.
+pub fn rotation_hint(img: &DynamicImage, exif: Option<&Exif>) -> RotationHint {
+	let (width, height) = img.dimensions();
+	let mut hint = RotationHint::default();
+	if width == 0 || height == 0 {
+		return hint;
+	}
+
+	if let Some(exif) = exif {
+		let pixel_x = exif.get(Ifd::Exif, tags::PIXEL_X_DIMENSION).and_then(|v| v.as_u32());
+		let pixel_y = exif.get(Ifd::Exif, tags::PIXEL_Y_DIMENSION).and_then(|v| v.as_u32());
+		if let (Some(x), Some(y)) = (pixel_x, pixel_y)
+			&& x != y && (x, y) == (height, width)
+		{
+			hint.signals.push(RotationSignal::ExifDimensionsSwapped);
+		}
+
+		let primary = exif.get(Ifd::Primary, tags::ORIENTATION).and_then(|v| v.as_u32()).unwrap_or(1);
+		if let Some(thumbnail) = exif.get(Ifd::Thumbnail, tags::ORIENTATION).and_then(|v| v.as_u32())
+			&& thumbnail != primary
+		{
+			hint.signals.push(RotationSignal::ThumbnailOrientationMismatch);
+		}
+	}
+
+	let has_camera_make = exif.is_some_and(|exif| exif.get(Ifd::Primary, tags::MAKE).and_then(|v| v.as_ascii()).is_some());
+	let ratio = f64::from(width) / f64::from(height);
+	if !has_camera_make && PAPER_RATIOS.iter().any(|r| (ratio - r).abs() / r < PAPER_RATIO_TOLERANCE) {
+		hint.signals.push(RotationSignal::PaperAspectLandscape);
+	}
+
+	let small = img.thumbnail(ANALYSIS_SIZE, ANALYSIS_SIZE).to_rgb8();
+	if let Some((strength, rotation)) = sky_on_side(&small) {
+		hint.signals.push(RotationSignal::SkyOnSide(strength));
+		hint.suspected = Some(rotation);
+	}
+	if vertical_edge_dominance(&small) {
+		hint.signals.push(RotationSignal::VerticalEdgeDominance);
+	}
+
+	// Treat signals as independent evidence
+	hint.confidence = 1.0 - hint.signals.iter().map(|s| 1.0 - s.weight()).product::<f32>();
+	hint
+}
+
+
+/// Compares the "skyness" (brightness plus blue excess, roughly 0..1) of the quarter-size border bands.
+fn sky_on_side(img: &image::RgbImage) -> Option<(f32, SuspectedRotation)> {
+	let (width, height) = img.dimensions();
+	if width < 4 || height < 4 {
+		return None;
+	}
+
+	let band = |x0: u32, y0: u32, x1: u32, y1: u32| -> f32 {
+		let mut sum = 0.0;
+		for y in y0..y1 {
+			for x in x0..x1 {
+				let [r, g, b] = img.get_pixel(x, y).0.map(f32::from);
+				let luma = (0.299 * r + 0.587 * g + 0.114 * b) / 255.0;
+				let blue = ((b - r.max(g)) / 255.0).max(0.0);
+				sum += luma + blue;
+			}
+		}
+		sum / ((x1 - x0) * (y1 - y0)) as f32
+	};
+	let top = band(0, 0, width, height / 4);
+	let bottom = band(0, height - height / 4, width, height);
+	let left = band(0, 0, width / 4, height);
+	let right = band(width - width / 4, 0, width, height);
+
+	// The sky must sit opposite a darker side, and stand out more than top vs bottom does
+	let (side, opposite, rotation) = if left > right {
+		(left, right, SuspectedRotation::Clockwise90)
+	} else {
+		(right, left, SuspectedRotation::CounterClockwise90)
+	};
+	let side_contrast = side - opposite;
+	let vertical_contrast = top - bottom;
+	if side_contrast < 0.15 || side_contrast < vertical_contrast + 0.1 {
+		return None;
+	}
+	Some(((side_contrast - vertical_contrast.max(0.0)).min(1.0), rotation))
+}
+
+
+fn vertical_edge_dominance(img: &image::RgbImage) -> bool {
+	let luma = |x: u32, y: u32| {
+		let [r, g, b] = img.get_pixel(x, y).0.map(f32::from);
+		0.299 * r + 0.587 * g + 0.114 * b
+	};
+	let (width, height) = img.dimensions();
+	let (mut horizontal, mut vertical) = (0.0, 0.0);
+	for y in 0..height.saturating_sub(1) {
+		for x in 0..width.saturating_sub(1) {
+			// A large change along x is a vertical edge
+			vertical += (luma(x + 1, y) - luma(x, y)).abs();
+			horizontal += (luma(x, y + 1) - luma(x, y)).abs();
+		}
+	}
+	vertical > 2.0 * horizontal && vertical > 0.0
+}
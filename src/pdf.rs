@@ -0,0 +1,471 @@
+//! Single-image PDFs (scans, exported photos) through their embedded image XObjects, with the `pdf` feature.
+//!
+//! Rather than rendering pages, we find the image XObjects in the file and decode the largest: DCTDecode streams are
+//! JPEGs, JPXDecode streams JPEG 2000 (which also needs the `jp2` feature), and FlateDecode streams are raw samples,
+//! optionally PNG predicted, in a gray, RGB or CMYK color space. Objects are found by scanning for their `obj`
+//! headers rather than through the cross-reference table, so damaged and incrementally updated files work too. Object
+//! streams (PDF 1.5) aren't looked in, but they can't hold streams, so no image is ever inside one.
+
+use std::{collections::HashMap, io::Cursor};
+
+use image::{DynamicImage, GrayImage, ImageBuffer, Luma, Rgb, RgbImage};
+
+#[cfg(feature = "jp2")]
+use crate::Jpeg2000Decoder;
+use crate::{Error, JpegDecoder, LoadOptions, decoded::Metadata};
+
+
+/// Dictionaries and arrays nested deeper than this are treated as malformed.
+const MAX_DEPTH: usize = 32;
+
+
+#[derive(Debug, Clone, PartialEq)]
+enum Object<'a> {
+	Number(f64),
+	Name(&'a [u8]),
+	Array(Vec<Object<'a>>),
+	Dict(Vec<(&'a [u8], Object<'a>)>),
+	/// An indirect reference, by object number
+	Ref(u32),
+	/// Strings, booleans and null, which images don't need
+	Other,
+}
+
+impl<'a> Object<'a> {
+	fn get(&self, key: &[u8]) -> Option<&Object<'a>> {
+		match self {
+			Object::Dict(entries) => entries.iter().find(|(k, _)| *k == key).map(|(_, v)| v),
+			_ => None,
+		}
+	}
+
+	fn as_u32(&self) -> Option<u32> {
+		match *self {
+			Object::Number(n) if n >= 0.0 && n <= f64::from(u32::MAX) && n.fract() == 0.0 => Some(n as u32),
+			_ => None,
+		}
+	}
+
+	fn as_name(&self) -> Option<&'a [u8]> {
+		match *self {
+			Object::Name(name) => Some(name),
+			_ => None,
+		}
+	}
+}
+
+
+fn is_whitespace(b: u8) -> bool {
+	matches!(b, b'\0' | b'\t' | b'\n' | b'\x0C' | b'\r' | b' ')
+}
+
+fn is_delimiter(b: u8) -> bool {
+	matches!(b, b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%')
+}
+
+
+struct Lexer<'a> {
+	data: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+	fn skip_whitespace(&mut self) {
+		while let Some(&b) = self.data.get(self.pos) {
+			if b == b'%' {
+				while self.data.get(self.pos).is_some_and(|&b| b != b'\n' && b != b'\r') {
+					self.pos += 1;
+				}
+			} else if is_whitespace(b) {
+				self.pos += 1;
+			} else {
+				break;
+			}
+		}
+	}
+
+	/// Regular characters up to the next whitespace or delimiter.
+	fn token(&mut self) -> &'a [u8] {
+		let start = self.pos;
+		while self.data.get(self.pos).is_some_and(|&b| !is_whitespace(b) && !is_delimiter(b)) {
+			self.pos += 1;
+		}
+		&self.data[start..self.pos]
+	}
+
+	fn object(&mut self, depth: usize) -> Option<Object<'a>> {
+		if depth > MAX_DEPTH {
+			return None;
+		}
+		self.skip_whitespace();
+		match *self.data.get(self.pos)? {
+			b'/' => {
+				self.pos += 1;
+				Some(Object::Name(self.token()))
+			},
+			b'<' if self.data.get(self.pos + 1) == Some(&b'<') => {
+				self.pos += 2;
+				let mut entries = Vec::new();
+				loop {
+					self.skip_whitespace();
+					if self.data.get(self.pos..self.pos + 2)? == b">>" {
+						self.pos += 2;
+						return Some(Object::Dict(entries));
+					}
+					let key = self.object(depth + 1)?.as_name()?;
+					entries.push((key, self.object(depth + 1)?));
+				}
+			},
+			b'<' => {
+				self.pos += self.data[self.pos..].iter().position(|&b| b == b'>')? + 1;
+				Some(Object::Other)
+			},
+			b'[' => {
+				self.pos += 1;
+				let mut items = Vec::new();
+				loop {
+					self.skip_whitespace();
+					if *self.data.get(self.pos)? == b']' {
+						self.pos += 1;
+						return Some(Object::Array(items));
+					}
+					items.push(self.object(depth + 1)?);
+				}
+			},
+			b'(' => {
+				let mut nesting = 0;
+				loop {
+					match *self.data.get(self.pos)? {
+						b'\\' => self.pos += 1,
+						b'(' => nesting += 1,
+						b')' => nesting -= 1,
+						_ => (),
+					}
+					self.pos += 1;
+					if nesting == 0 {
+						return Some(Object::Other);
+					}
+				}
+			},
+			b'0'..=b'9' | b'+' | b'-' | b'.' => {
+				let number: f64 = std::str::from_utf8(self.token()).ok()?.parse().ok()?;
+				// `number generation R` is a reference
+				let after = self.pos;
+				if let Some(object) = (number.fract() == 0.0 && number >= 0.0).then_some(number as u32) {
+					self.skip_whitespace();
+					let generation = self.token();
+					self.skip_whitespace();
+					if !generation.is_empty() && generation.iter().all(u8::is_ascii_digit) && self.token() == b"R" {
+						return Some(Object::Ref(object));
+					}
+				}
+				self.pos = after;
+				Some(Object::Number(number))
+			},
+			_ => {
+				// true, false and null; anything else ends the object
+				(!self.token().is_empty()).then_some(Object::Other)
+			},
+		}
+	}
+}
+
+
+struct Document<'a> {
+	data: &'a [u8],
+	/// The offset just past `N G obj` of every object, by object number
+	objects: HashMap<u32, usize>,
+}
+
+impl<'a> Document<'a> {
+	fn new(data: &'a [u8]) -> Document<'a> {
+		Document { data, objects: find_objects(data) }
+	}
+
+	/// The object a reference points to, or any other object as it is.
+	fn resolve(&self, object: &Object<'a>) -> Option<Object<'a>> {
+		match *object {
+			Object::Ref(number) => Lexer {
+				data: self.data,
+				pos: *self.objects.get(&number)?,
+			}
+			.object(0),
+			_ => Some(object.clone()),
+		}
+	}
+
+	/// Color components of a color space, for those we can convert.
+	fn components(&self, space: &Object<'a>) -> Option<u8> {
+		match self.resolve(space)? {
+			Object::Name(b"DeviceGray" | b"CalGray") => Some(1),
+			Object::Name(b"DeviceRGB" | b"CalRGB") => Some(3),
+			Object::Name(b"DeviceCMYK") => Some(4),
+			Object::Array(items) if items.first().and_then(Object::as_name) == Some(b"ICCBased") => {
+				match self.resolve(items.get(1)?)?.get(b"N").and_then(Object::as_u32)? {
+					n @ (1 | 3 | 4) => Some(n as u8),
+					_ => None,
+				}
+			},
+			_ => None,
+		}
+	}
+}
+
+
+/// Finds every `N G obj` header, later definitions (from incremental updates) replacing earlier ones.
+fn find_objects(data: &[u8]) -> HashMap<u32, usize> {
+	let mut objects = HashMap::new();
+	let mut search = 0;
+	while let Some(found) = data[search..].windows(3).position(|w| w == b"obj") {
+		let at = search + found;
+		search = at + 3;
+		if data.get(at + 3).is_some_and(|&b| !is_whitespace(b) && !is_delimiter(b)) {
+			continue;
+		}
+		// Walk back over the generation and object numbers
+		let mut pos = at;
+		let mut numbers = [0u32; 2];
+		let mut valid = true;
+		for number in &mut numbers {
+			let end = pos - data[..pos].iter().rev().take_while(|&&b| is_whitespace(b)).count();
+			let start = end - data[..end].iter().rev().take_while(|b| b.is_ascii_digit()).count();
+			match std::str::from_utf8(&data[start..end]).ok().and_then(|s| s.parse().ok()) {
+				Some(n) if end < pos => *number = n,
+				_ => valid = false,
+			}
+			pos = start;
+		}
+		if valid {
+			objects.insert(numbers[1], at + 3);
+		}
+	}
+	objects
+}
+
+
+/// An image XObject we can decode.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PdfImage<'a> {
+	pub width: u32,
+	pub height: u32,
+	pub kind: ImageKind,
+	pub data: &'a [u8],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ImageKind {
+	Jpeg,
+	Jpeg2000,
+	/// Deflated samples, with the number of color components (1, 3 or 4), bits per component (1, 8 or 16), and whether
+	/// rows are PNG predicted
+	Flate { components: u8, bits: u8, png_predicted: bool },
+}
+
+
+/// Every image XObject in the file that we can decode.
+pub(crate) fn images(data: &[u8]) -> Vec<PdfImage<'_>> {
+	let document = Document::new(data);
+	let mut images = Vec::new();
+	for &pos in document.objects.values() {
+		let mut lexer = Lexer { data, pos };
+		let Some(dict) = lexer.object(0) else {
+			continue;
+		};
+		if dict.get(b"Subtype").and_then(Object::as_name) != Some(b"Image") {
+			continue;
+		}
+		lexer.skip_whitespace();
+		if lexer.token() != b"stream" {
+			continue;
+		}
+		// The keyword is followed by CRLF or LF, or in broken files just CR
+		let start = match data.get(lexer.pos..lexer.pos + 2) {
+			Some(b"\r\n") => lexer.pos + 2,
+			_ => lexer.pos + 1,
+		};
+		let length = dict.get(b"Length").and_then(|length| document.resolve(length)?.as_u32());
+		let stream = match length.and_then(|length| data.get(start..start.checked_add(length as usize)?)) {
+			Some(stream) => stream,
+			None => {
+				let Some(end) = data.get(start..).and_then(|rest| rest.windows(9).position(|w| w == b"endstream")) else {
+					continue;
+				};
+				let stream = &data[start..start + end];
+				stream.strip_suffix(b"\n").map_or(stream, |s| s.strip_suffix(b"\r").unwrap_or(s))
+			},
+		};
+
+		let (Some(width), Some(height)) = (dict.get(b"Width").and_then(Object::as_u32), dict.get(b"Height").and_then(Object::as_u32)) else {
+			continue;
+		};
+		let filter = match dict.get(b"Filter") {
+			Some(Object::Name(name)) => *name,
+			Some(Object::Array(filters)) if filters.len() == 1 => match filters[0].as_name() {
+				Some(name) => name,
+				None => continue,
+			},
+			_ => continue,
+		};
+		let kind = match filter {
+			b"DCTDecode" => ImageKind::Jpeg,
+			b"JPXDecode" if cfg!(feature = "jp2") => ImageKind::Jpeg2000,
+			b"FlateDecode" => {
+				let components = match dict.get(b"ColorSpace").and_then(|space| document.components(space)) {
+					Some(components) => components,
+					None => continue,
+				};
+				let bits = match dict.get(b"BitsPerComponent").and_then(Object::as_u32) {
+					Some(bits @ (8 | 16)) => bits as u8,
+					Some(1) if components == 1 => 1,
+					_ => continue,
+				};
+				let predictor = dict.get(b"DecodeParms").and_then(|parms| parms.get(b"Predictor")).and_then(Object::as_u32).unwrap_or(1);
+				if !matches!(predictor, 1 | 10..=15) || (components == 4 && bits != 8) {
+					continue;
+				}
+				ImageKind::Flate {
+					components,
+					bits,
+					png_predicted: predictor >= 10,
+				}
+			},
+			_ => continue,
+		};
+		images.push(PdfImage {
+			width,
+			height,
+			kind,
+			data: stream,
+		});
+	}
+	// Largest first, ties broken by position in the file so the pick doesn't depend on hash order
+	images.sort_by_key(|image| (std::cmp::Reverse(u64::from(image.width) * u64::from(image.height)), image.data.as_ptr() as usize));
+	images
+}
+
+
+/// The error for a PDF without any image in it that we can decode.
+pub(crate) fn no_image() -> Error {
+	Error::Unsupported(image::error::UnsupportedError::from_format_and_kind(
+		image::error::ImageFormatHint::Name("PDF".to_string()),
+		image::error::UnsupportedErrorKind::GenericFeature("PDF without an embedded image we can decode".to_string()),
+	))
+}
+
+
+/// Decodes the largest image in the PDF `data`.
+pub(crate) fn decode(data: &[u8], options: &LoadOptions, input_len: u64, metadata: Option<&mut Metadata>) -> Result<DynamicImage, Error> {
+	let Some(image) = images(data).into_iter().next() else {
+		return Err(no_image());
+	};
+	match image.kind {
+		ImageKind::Jpeg => crate::decode_limited(JpegDecoder::with_options(Cursor::new(image.data), &options.jpeg)?, options, input_len, metadata),
+		#[cfg(feature = "jp2")]
+		ImageKind::Jpeg2000 => crate::decode_limited(Jpeg2000Decoder::new(Cursor::new(image.data))?, options, input_len, metadata),
+		#[cfg(not(feature = "jp2"))]
+		ImageKind::Jpeg2000 => unreachable!("JPEG 2000 images are only listed with the jp2 feature"),
+		ImageKind::Flate { components, bits, png_predicted } => {
+			let img = decode_flate(&image, components, bits, png_predicted, options, input_len)?;
+			if let Some(metadata) = metadata {
+				metadata.is_16bit = bits == 16;
+			}
+			Ok(img)
+		},
+	}
+}
+
+
+fn decode_flate(image: &PdfImage<'_>, components: u8, bits: u8, png_predicted: bool, options: &LoadOptions, input_len: u64) -> Result<DynamicImage, Error> {
+	let (width, height) = (image.width, image.height);
+	let pixels = u64::from(width) * u64::from(height);
+	let row_bytes = (u64::from(width) * u64::from(components) * u64::from(bits)).div_ceil(8);
+	let out_bytes = pixels * u64::from(components.min(3)) * if bits == 16 { 2 } else { 1 };
+	options.check_size(pixels, out_bytes, input_len)?;
+
+	let stored_row = row_bytes + u64::from(png_predicted);
+	let expected = usize::try_from(stored_row * u64::from(height)).map_err(|_| Error::TooBig)?;
+	let decoding_error = |message: &str| {
+		Error::Decoding(image::error::DecodingError::new(image::error::ImageFormatHint::Name("PDF".to_string()), message.to_string()))
+	};
+	// Inflating no more than the image needs keeps a deflate bomb from getting further than the size checks above
+	let mut samples = match miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(image.data, expected) {
+		Ok(samples) => samples,
+		// Hitting the limit only means there's data past the image, which is harmless
+		Err(e) if e.status == miniz_oxide::inflate::TINFLStatus::HasMoreOutput => e.output,
+		Err(_) => return Err(decoding_error("corrupt deflate stream")),
+	};
+	if samples.len() < expected {
+		return Err(decoding_error("image stream is truncated"));
+	}
+	samples.truncate(expected);
+
+	if png_predicted {
+		let bpp = usize::from(components) * usize::from(bits).div_ceil(8);
+		samples = unpredict(&samples, stored_row as usize, bpp);
+	}
+
+	let width_usize = width as usize;
+	let img = match (components, bits) {
+		(1, 1) => DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| {
+			let byte = samples[y as usize * row_bytes as usize + x as usize / 8];
+			Luma([if byte & (0x80 >> (x % 8)) != 0 { 255 } else { 0 }])
+		})),
+		(1, 8) => DynamicImage::ImageLuma8(GrayImage::from_raw(width, height, samples).expect("sample count was checked")),
+		(3, 8) => DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, samples).expect("sample count was checked")),
+		(1 | 3, 16) => {
+			let samples: Vec<u16> = samples.chunks_exact(2).map(|s| u16::from_be_bytes([s[0], s[1]])).collect();
+			match components {
+				1 => DynamicImage::ImageLuma16(ImageBuffer::<Luma<u16>, _>::from_raw(width, height, samples).expect("sample count was checked")),
+				_ => DynamicImage::ImageRgb16(ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, samples).expect("sample count was checked")),
+			}
+		},
+		// Naive CMYK to RGB, which is what PDF viewers do without a color managed output intent
+		_ => DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+			let at = y as usize * width_usize * 4 + x as usize * 4;
+			let k = 255 - u16::from(samples[at + 3]);
+			let channel = |c: u8| ((255 - u16::from(c)) * k / 255) as u8;
+			Rgb([channel(samples[at]), channel(samples[at + 1]), channel(samples[at + 2])])
+		})),
+	};
+	Ok(img)
+}
+
+
+/// Undoes PNG row filters, where each row starts with its filter type byte.
+fn unpredict(data: &[u8], stored_row: usize, bpp: usize) -> Vec<u8> {
+	let row_bytes = stored_row - 1;
+	let mut out = vec![0u8; row_bytes * (data.len() / stored_row)];
+	for (y, row) in data.chunks_exact(stored_row).enumerate() {
+		let (filter, row) = (row[0], &row[1..]);
+		let (before, current) = out.split_at_mut(y * row_bytes);
+		let previous = (y > 0).then(|| &before[(y - 1) * row_bytes..]);
+		let current = &mut current[..row_bytes];
+		for x in 0..row_bytes {
+			let left = if x >= bpp { current[x - bpp] } else { 0 };
+			let up = previous.map_or(0, |p| p[x]);
+			let up_left = if x >= bpp { previous.map_or(0, |p| p[x - bpp]) } else { 0 };
+			let predicted = match filter {
+				1 => left,
+				2 => up,
+				3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+				4 => paeth(left, up, up_left),
+				_ => 0,
+			};
+			current[x] = row[x].wrapping_add(predicted);
+		}
+	}
+	out
+}
+
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+	let p = i16::from(a) + i16::from(b) - i16::from(c);
+	let (pa, pb, pc) = ((p - i16::from(a)).abs(), (p - i16::from(b)).abs(), (p - i16::from(c)).abs());
+	if pa <= pb && pa <= pc {
+		a
+	} else if pb <= pc {
+		b
+	} else {
+		c
+	}
+}
@@ -0,0 +1,100 @@
+//! Perceptual hashing (pHash) for near-duplicate detection.
+//!
+//! The hash is the classic DCT pHash: the image is reduced to 32x32 gray, and each of the 64 lowest frequency DCT
+//! coefficients contributes one bit, set when it's above their median (the DC term takes part in the bits but not the
+//! median). Visually similar images, including rescaled and recompressed copies, land a few bits apart; unrelated
+//! images land around 32 apart.
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::path::Path;
+use std::{fmt, io::Cursor, str::FromStr};
+
+use image::{GrayImage, ImageFormat, imageops::FilterType};
+
+use crate::{Format, JpegDecoder, LoadOptions, OutputColor, convert, error::Error};
+
+
+const SIZE: u32 = 32;
+const BITS: usize = 8;
+
+
+/// A 64-bit perceptual hash. Formats as (and parses from) 16 hex digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PHash(pub u64);
+
+impl PHash {
+	/// Number of differing bits: 0 for identical hashes, up to 64.
+	pub fn distance(self, other: PHash) -> u32 {
+		(self.0 ^ other.0).count_ones()
+	}
+}
+
+impl fmt::Display for PHash {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{:016x}", self.0)
+	}
+}
+
+impl FromStr for PHash {
+	type Err = std::num::ParseIntError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		u64::from_str_radix(s, 16).map(PHash)
+	}
+}
+
+
+/// Hashes a grayscale image.
+pub fn phash(img: &GrayImage) -> PHash {
+	if img.width() == 0 || img.height() == 0 {
+		return PHash(0);
+	}
+	let small = image::imageops::resize(img, SIZE, SIZE, FilterType::Triangle);
+	let size = SIZE as usize;
+
+	// Separable DCT-II, keeping only the lowest `BITS` frequencies in each direction
+	let cos: Vec<f32> = (0..BITS)
+		.flat_map(|k| (0..size).map(move |n| (std::f32::consts::PI / size as f32 * (n as f32 + 0.5) * k as f32).cos()))
+		.collect();
+	let rows: Vec<f32> = small
+		.as_raw()
+		.chunks_exact(size)
+		.flat_map(|row| (0..BITS).map(|u| row.iter().zip(&cos[u * size..]).map(|(&p, &c)| f32::from(p) * c).sum::<f32>()).collect::<Vec<_>>())
+		.collect();
+	let coefficients: Vec<f32> = (0..BITS)
+		.flat_map(|v| (0..BITS).map(move |u| (v, u)))
+		.map(|(v, u)| (0..size).map(|y| rows[y * BITS + u] * cos[v * size + y]).sum())
+		.collect();
+
+	let mut sorted = coefficients[1..].to_vec();
+	sorted.sort_by(f32::total_cmp);
+	let median = sorted[sorted.len() / 2];
+	PHash(coefficients.iter().fold(0, |hash, &c| (hash << 1) | u64::from(c > median)))
+}
+
+
+/// Decodes and hashes an image file, taking the cheapest decode that still gives pHash enough to work with: the DC
+/// preview for JPEGs of at least 256x256 (see `JpegDecoder::dc_preview`), and a luma-only decode otherwise.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn phash_file<P: AsRef<Path>>(path: P) -> Result<PHash, Error> {
+	phash_bytes(&std::fs::read(path)?)
+}
+
+
+/// `phash_file` for an image already in memory.
+pub fn phash_bytes(data: &[u8]) -> Result<PHash, Error> {
+	if Format::guess(data) == Some(Format::Image(ImageFormat::Jpeg)) {
+		let decoder = JpegDecoder::new(Cursor::new(data))?;
+		let preview = decoder.dc_preview()?;
+		if preview.width() >= SIZE && preview.height() >= SIZE {
+			return Ok(phash(&convert::into_luma8(preview)));
+		}
+	}
+
+	let options = LoadOptions {
+		output: OutputColor::Luma8,
+		..Default::default()
+	};
+	let (_, img) = crate::load_image_from_reader_with_options(Cursor::new(data), &options)?;
+	Ok(phash(&convert::into_luma8(img)))
+}
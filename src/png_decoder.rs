@@ -1,11 +1,15 @@
-use std::io::{BufRead, Seek};
+use std::io::{BufRead, Seek, SeekFrom};
 
 use image::{
-	ColorType, ExtendedColorType, ImageDecoder, ImageError, ImageFormat, ImageResult, Limits,
+	ColorType, DynamicImage, ExtendedColorType, GrayImage, ImageBuffer, ImageDecoder, ImageError, ImageFormat, ImageResult, Limits, RgbaImage,
 	error::{DecodingError, LimitError, LimitErrorKind, ParameterError, ParameterErrorKind, UnsupportedError, UnsupportedErrorKind},
 };
 
-use crate::error::Error;
+use crate::{
+	convert,
+	error::Error,
+	options::{PngOptions, SignificantBits},
+};
 
 
 const XMP_KEY: &str = "XML:com.adobe.xmp";
@@ -17,6 +21,18 @@ pub struct PngDecoder<R: BufRead + Seek> {
 	is_16bit: bool,
 	reader: png::Reader<R>,
 	limits: Limits,
+	/// Bit depth of the palette indices for palette images, which we unpack ourselves rather than through the png crate
+	indexed_bits: Option<u8>,
+	/// RGBA for every possible index when expanding palette images; `None` when returning the indices as-is, see
+	/// `PngOptions::keep_indexed`
+	palette_lut: Option<Box<[[u8; 4]; 256]>>,
+	/// Reduces 16-bit samples to 8 bits, see `PngOptions::sixteen_bit`
+	reduce_16: Option<fn(u16) -> u8>,
+	/// See `PngOptions::significant_bits`
+	significant_bits: SignificantBits,
+	/// See `PngOptions::skip_metadata` and `PngOptions::max_metadata_bytes`; the png crate can't be told to skip eXIf
+	drop_exif: bool,
+	metadata_truncated: bool,
 }
 
 
@@ -27,20 +43,42 @@ impl<R: BufRead + Seek> PngDecoder<R> {
 	}
 
 	pub fn with_limits(r: R, limits: Limits) -> Result<PngDecoder<R>, Error> {
+		Self::with_options(r, limits, &PngOptions::default())
+	}
+
+	pub fn with_options(mut r: R, limits: Limits, png_options: &PngOptions) -> Result<PngDecoder<R>, Error> {
 		limits.check_support(&image::LimitSupport::default())?;
 
+		let mut dropped = MetadataDrop {
+			text: png_options.skip_metadata,
+			icc_profile: png_options.skip_metadata,
+			exif: png_options.skip_metadata,
+		};
+		let mut metadata_truncated = false;
+		if let Some(max) = png_options.max_metadata_bytes.filter(|_| !png_options.skip_metadata) {
+			let sizes = metadata_sizes(&mut r)?;
+			dropped = sizes.fit(max);
+			metadata_truncated = dropped != MetadataDrop::default();
+		}
+
 		let max_bytes = usize::try_from(limits.max_alloc.unwrap_or(u64::MAX)).unwrap_or(usize::MAX);
 		let mut decoder = png::Decoder::new_with_limits(r, png::Limits { bytes: max_bytes });
-		decoder.set_ignore_text_chunk(false);
+		decoder.set_ignore_text_chunk(dropped.text);
+		decoder.set_ignore_iccp_chunk(dropped.icc_profile);
 
 		let info = decoder.read_header_info()?;
 		limits.check_dimensions(info.width, info.height)?;
+		let indexed = info.color_type == png::ColorType::Indexed;
+		let keep_indexed = png_options.keep_indexed && indexed;
 
 		// By default the PNG decoder will scale 16 bpc to 8 bpc, so custom
 		// transformations must be set. EXPAND preserves the default behavior
 		// expanding bpc < 8 to 8 bpc.
-		decoder.set_transformations(png::Transformations::EXPAND);
+		// Palette indices are left packed and unpacked (and looked up in `palette_lut`) in `read_image` instead, so that
+		// malformed palettes are handled the same way as libpng.
+		decoder.set_transformations(if indexed { png::Transformations::IDENTITY } else { png::Transformations::EXPAND });
 		let reader = decoder.read_info()?;
+		let has_trns = reader.info().trns.is_some();
 		let (color_type, bits) = reader.output_color_type();
 		let color_type = match (color_type, bits) {
 			(png::ColorType::Grayscale, png::BitDepth::Eight) => ColorType::L8,
@@ -67,15 +105,38 @@ impl<R: BufRead + Seek> PngDecoder<R> {
 			(png::ColorType::Rgb, png::BitDepth::Four) => return Err(unsupported_color(ExtendedColorType::Rgb4)),
 			(png::ColorType::Rgba, png::BitDepth::Four) => return Err(unsupported_color(ExtendedColorType::Rgba4)),
 
-			(png::ColorType::Indexed, bits) => return Err(unsupported_color(ExtendedColorType::Unknown(bits as u8))),
+			(png::ColorType::Indexed, _) if keep_indexed => ColorType::L8,
+			(png::ColorType::Indexed, _) if has_trns => ColorType::Rgba8,
+			(png::ColorType::Indexed, _) => ColorType::Rgb8,
 		};
+		let palette_lut = (indexed && !keep_indexed).then(|| {
+			let info = reader.info();
+			palette_lut(info.palette.as_deref().unwrap_or_default(), info.trns.as_deref())
+		});
 		let is_16bit = matches!(bits, png::BitDepth::Sixteen);
+		let reduce_16 = png_options.sixteen_bit.reduction().filter(|_| is_16bit);
+		let color_type = match color_type {
+			_ if reduce_16.is_none() => color_type,
+			ColorType::L16 => ColorType::L8,
+			ColorType::La16 => ColorType::La8,
+			ColorType::Rgb16 => ColorType::Rgb8,
+			ColorType::Rgba16 => ColorType::Rgba8,
+			_ => unreachable!("16-bit PNGs have 16-bit color types"),
+		};
+		#[cfg(feature = "testing")]
+		record_coverage(reader.info());
 
 		Ok(PngDecoder {
 			color_type,
 			reader,
 			limits,
 			is_16bit,
+			indexed_bits: indexed.then_some(bits as u8),
+			palette_lut,
+			reduce_16,
+			significant_bits: png_options.significant_bits,
+			drop_exif: dropped.exif,
+			metadata_truncated,
 		})
 	}
 
@@ -88,7 +149,19 @@ impl<R: BufRead + Seek> PngDecoder<R> {
 	/// > capable of colour management are recommended to ignore the gAMA and cHRM chunks, and use
 	/// > the values given above as if they had appeared in gAMA and cHRM chunks.
 	pub fn gamma_value(&self) -> Result<Option<f64>, Error> {
-		Ok(self.reader.info().source_gamma.map(|x| f64::from(x.into_scaled()) / 100_000.0))
+		Ok(self.reader.info().gamma().map(|x| f64::from(x.into_scaled()) / 100_000.0))
+	}
+
+	/// Returns the gAMA value if it's all there is to say how the samples are encoded, i.e. there's no sRGB or iCCP
+	/// chunk, and the image isn't returned as palette indices. This is the gamma `PngOptions::gamma` corrects for.
+	/// An iCCP chunk dropped by `PngOptions::skip_metadata` isn't seen, and so doesn't count.
+	pub fn gamma_only(&self) -> Option<f64> {
+		let info = self.reader.info();
+		let keep_indexed = self.indexed_bits.is_some() && self.palette_lut.is_none();
+		if info.srgb.is_some() || info.icc_profile.is_some() || keep_indexed {
+			return None;
+		}
+		info.gamma().map(|x| f64::from(x.into_scaled()) / 100_000.0).filter(|&gamma| gamma > 0.0)
 	}
 
 	/// Returns if the image contains an animation.
@@ -101,10 +174,140 @@ impl<R: BufRead + Seek> PngDecoder<R> {
 		self.reader.info().is_animated()
 	}
 
-	/// Returns true if the image is 16 bits per channel.
+	/// Returns true if the image is stored at 16 bits per channel, even if `PngOptions::sixteen_bit` reduces it to 8.
 	pub fn is_16bit(&self) -> bool {
 		self.is_16bit
 	}
+
+	/// Returns the sBIT chunk: the number of significant bits of each channel as stored (for palette images, of the
+	/// palette's RGB). See `PngOptions::significant_bits`.
+	pub fn significant_bits(&self) -> Option<&[u8]> {
+		self.reader.info().sbit.as_deref()
+	}
+
+	/// Returns the PLTE chunk as RGB triples, if the image has one.
+	pub fn palette(&self) -> Option<&[u8]> {
+		self.reader.info().palette.as_deref()
+	}
+
+	/// Streams the image row by row instead of decoding it into one buffer.
+	///
+	/// Rows come out in the same color type and native endianness as `read_image`. Adam7 interlaced images can't be
+	/// streamed as finished rows without buffering most of the image (no row is complete before pass 6), so their
+	/// passes are exposed as-is; see `RowPosition::scatter` for placing them.
+	pub fn into_rows(self) -> PngRows<R> {
+		let (width, height) = self.reader.info().size();
+		let interlaced = self.reader.info().interlaced;
+		PngRows {
+			reader: self.reader,
+			color_type: self.color_type,
+			indexed_bits: self.indexed_bits,
+			palette_lut: self.palette_lut,
+			reduce_16: self.reduce_16,
+			width,
+			height,
+			next: if interlaced { next_adam7_position(width, height, 0, 0) } else { (height > 0).then(|| RowPosition::full_row(0, width)) },
+			unpacked: Vec::new(),
+		}
+	}
+
+	/// Returns true if metadata was dropped to stay within `PngOptions::max_metadata_bytes`, in which case some or all
+	/// of the text chunks, EXIF and ICC profile are missing even though the file has them.
+	pub fn metadata_truncated(&self) -> bool {
+		self.metadata_truncated
+	}
+
+	/// Returns the tRNS chunk: per palette entry alpha for palette images, a single transparent color otherwise.
+	pub fn transparency(&self) -> Option<&[u8]> {
+		self.reader.info().trns.as_deref()
+	}
+
+	/// Decodes at 1/`factor` of the size in each dimension (rounding up), averaging each `factor` x `factor` block as
+	/// rows stream in, so only the output and its running sums are held in memory. As with `into_rows`,
+	/// `PngOptions::significant_bits` isn't applied.
+	pub fn decode_downscaled(self, factor: u32) -> Result<DynamicImage, Error> {
+		let factor = factor.max(1);
+		let mut rows = self.into_rows();
+		let (width, height) = rows.dimensions();
+		let color_type = rows.color_type();
+		let channels = usize::from(color_type.channel_count());
+		let wide = color_type.bytes_per_pixel() / color_type.channel_count() == 2;
+		let (out_width, out_height) = (width.div_ceil(factor), height.div_ceil(factor));
+		let out_row = out_width as usize * channels;
+
+		// 16-bit samples overflow a u32 sum past 256x256 blocks
+		let mut sums = vec![0u64; out_row * out_height as usize];
+		while let Some(row) = rows.next_row()? {
+			let position = row.position;
+			let sums_row = &mut sums[(position.y / factor) as usize * out_row..][..out_row];
+			for i in 0..position.width as usize {
+				let x = (position.x_offset as usize + i * position.x_step as usize) / factor as usize;
+				for c in 0..channels {
+					let k = i * channels + c;
+					let sample = if wide { u16::from_ne_bytes([row.data[2 * k], row.data[2 * k + 1]]).into() } else { u64::from(row.data[k]) };
+					sums_row[x * channels + c] += sample;
+				}
+			}
+		}
+
+		// Blocks are clipped at the right and bottom edges
+		let block = |n: u32, size: u32| u64::from(factor.min(size - n * factor));
+		let averages = sums.iter().enumerate().map(|(i, &sum)| {
+			let (x, y) = ((i % out_row / channels) as u32, (i / out_row) as u32);
+			let count = block(x, width) * block(y, height);
+			(sum + count / 2) / count
+		});
+		let img = if wide {
+			let data: Vec<u16> = averages.map(|v| v as u16).collect();
+			match color_type {
+				ColorType::L16 => ImageBuffer::from_raw(out_width, out_height, data).map(DynamicImage::ImageLuma16),
+				ColorType::La16 => ImageBuffer::from_raw(out_width, out_height, data).map(DynamicImage::ImageLumaA16),
+				ColorType::Rgb16 => ImageBuffer::from_raw(out_width, out_height, data).map(DynamicImage::ImageRgb16),
+				_ => ImageBuffer::from_raw(out_width, out_height, data).map(DynamicImage::ImageRgba16),
+			}
+		} else {
+			let data: Vec<u8> = averages.map(|v| v as u8).collect();
+			match color_type {
+				ColorType::L8 => ImageBuffer::from_raw(out_width, out_height, data).map(DynamicImage::ImageLuma8),
+				ColorType::La8 => ImageBuffer::from_raw(out_width, out_height, data).map(DynamicImage::ImageLumaA8),
+				ColorType::Rgb8 => ImageBuffer::from_raw(out_width, out_height, data).map(DynamicImage::ImageRgb8),
+				_ => ImageBuffer::from_raw(out_width, out_height, data).map(DynamicImage::ImageRgba8),
+			}
+		};
+		Ok(img.expect("buffer size matches the dimensions"))
+	}
+
+	/// Decodes to 8-bit gray, converting each row as it's streamed (see `convert::row_to_luma8`) so the full color
+	/// image is never held in memory.
+	pub fn decode_luma(self) -> Result<GrayImage, Error> {
+		let mut rows = self.into_rows();
+		let (width, height) = rows.dimensions();
+		let color_type = rows.color_type();
+		let mut out = vec![0; crate::error::pixel_buffer_len(width, height, 1)?];
+		let mut luma = Vec::new();
+		while let Some(row) = rows.next_row()? {
+			luma.resize(row.position.width as usize, 0);
+			convert::row_to_luma8(row.data, color_type, &mut luma);
+			row.position.scatter(&luma, &mut out, width, 1);
+		}
+		Ok(GrayImage::from_raw(width, height, out).expect("buffer size matches the dimensions"))
+	}
+
+	/// Decodes to 8-bit RGBA, converting each row as it's streamed (see `convert::row_to_rgba8`) so the output is the
+	/// only full size buffer.
+	pub fn decode_rgba8(self) -> Result<RgbaImage, Error> {
+		let mut rows = self.into_rows();
+		let (width, height) = rows.dimensions();
+		let color_type = rows.color_type();
+		let mut out = vec![0; crate::error::pixel_buffer_len(width, height, 4)?];
+		let mut rgba = Vec::new();
+		while let Some(row) = rows.next_row()? {
+			rgba.resize(row.position.width as usize * 4, 0);
+			convert::row_to_rgba8(row.data, color_type, &mut rgba);
+			row.position.scatter(&rgba, &mut out, width, 4);
+		}
+		Ok(RgbaImage::from_raw(width, height, out).expect("buffer size matches the dimensions"))
+	}
 }
 
 
@@ -122,6 +325,10 @@ impl<R: BufRead + Seek> ImageDecoder for PngDecoder<R> {
 	}
 
 	fn exif_metadata(&mut self) -> ImageResult<Option<Vec<u8>>> {
+		// The png crate has no switch for eXIf, so it is still parsed but never copied out
+		if self.drop_exif {
+			return Ok(None);
+		}
 		Ok(self.reader.info().exif_metadata.as_ref().map(|x| x.to_vec()))
 	}
 
@@ -160,22 +367,51 @@ impl<R: BufRead + Seek> ImageDecoder for PngDecoder<R> {
 	}
 
 	fn read_image(mut self, buf: &mut [u8]) -> ImageResult<()> {
-		use byteorder_lite::{BigEndian, ByteOrder, NativeEndian};
-
-		assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+		crate::error::check_buffer(buf, self.total_bytes())?;
+		if let Some(bits) = self.indexed_bits {
+			let size = self.reader.output_buffer_size().ok_or(ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory)))?;
+			let mut packed = vec![0; size];
+			let frame = self.reader.next_frame(&mut packed).map_err(error_from_png)?;
+			let width = frame.width as usize;
+			let channels = usize::from(self.color_type.channel_count());
+			for (row, out_row) in packed.chunks_exact(frame.line_size).zip(buf.chunks_exact_mut(width * channels)) {
+				match &self.palette_lut {
+					Some(lut) => expand_palette_row(row, bits, lut, channels, out_row),
+					None => unpack_indices(row, bits, out_row),
+				}
+			}
+			return Ok(());
+		}
+		// Images stored below 8 bits were already widened by the png crate, which replicates the high bits
+		let info = self.reader.info();
+		let sbit = info
+			.sbit
+			.as_deref()
+			.filter(|_| self.significant_bits != SignificantBits::Ignore && matches!(info.bit_depth, png::BitDepth::Eight | png::BitDepth::Sixteen))
+			.map(<[u8]>::to_vec);
+		let channels = usize::from(self.color_type.channel_count());
+		if let Some(reduce) = self.reduce_16 {
+			let mut wide = vec![0; crate::error::buffer_len(self.total_bytes().saturating_mul(2))?];
+			self.reader.next_frame(&mut wide).map_err(error_from_png)?;
+			if let Some(sbit) = &sbit {
+				rescale_significant_bits(&mut wide, 2, channels, sbit, self.significant_bits);
+			}
+			reduce_16_to_8(&wide, reduce, buf);
+			return Ok(());
+		}
 		self.reader.next_frame(buf).map_err(error_from_png)?;
 		// PNG images are big endian. For 16 bit per channel and larger types,
 		// the buffer may need to be reordered to native endianness per the
 		// contract of `read_image`.
 		// TODO: assumes equal channel bit depth.
 		let bpc = self.color_type().bytes_per_pixel() / self.color_type().channel_count();
+		if let Some(sbit) = &sbit {
+			rescale_significant_bits(buf, usize::from(bpc), channels, sbit, self.significant_bits);
+		}
 
 		match bpc {
 			1 => (), // No reodering necessary for u8
-			2 => buf.chunks_exact_mut(2).for_each(|c| {
-				let v = BigEndian::read_u16(c);
-				NativeEndian::write_u16(c, v);
-			}),
+			2 => convert::be16_to_native_in_place(buf),
 			_ => unreachable!(),
 		}
 		Ok(())
@@ -197,6 +433,324 @@ impl<R: BufRead + Seek> ImageDecoder for PngDecoder<R> {
 }
 
 
+pub struct PngRows<R: BufRead + Seek> {
+	reader: png::Reader<R>,
+	color_type: ColorType,
+	indexed_bits: Option<u8>,
+	palette_lut: Option<Box<[[u8; 4]; 256]>>,
+	reduce_16: Option<fn(u16) -> u8>,
+	width: u32,
+	height: u32,
+	next: Option<RowPosition>,
+	unpacked: Vec<u8>,
+}
+
+
+/// A decoded row, or for interlaced images one row of an Adam7 pass.
+pub struct PngRow<'a> {
+	pub data: &'a [u8],
+	pub position: RowPosition,
+}
+
+
+/// Where a streamed row's pixels go in the full image: pixel `i` of the row lands at (`x_offset + i * x_step`, `y`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RowPosition {
+	/// The Adam7 pass (1 through 7), or `None` for non-interlaced images
+	pub pass: Option<u8>,
+	/// The row index within the pass; equal to `y` for non-interlaced images
+	pub line: u32,
+	pub y: u32,
+	pub x_offset: u32,
+	pub x_step: u32,
+	/// Number of pixels in the row
+	pub width: u32,
+}
+
+/// (x offset, y offset, x step, y step) for each Adam7 pass
+const ADAM7_PASSES: [(u32, u32, u32, u32); 7] = [(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)];
+
+impl RowPosition {
+	fn full_row(y: u32, width: u32) -> RowPosition {
+		RowPosition {
+			pass: None,
+			line: y,
+			y,
+			x_offset: 0,
+			x_step: 1,
+			width,
+		}
+	}
+
+	/// Copies `row` into `image`, a full size buffer of the same color type with `width * bytes_per_pixel` bytes per row.
+	pub fn scatter(&self, row: &[u8], image: &mut [u8], image_width: u32, bytes_per_pixel: usize) {
+		let stride = image_width as usize * bytes_per_pixel;
+		let image_row = &mut image[self.y as usize * stride..][..stride];
+		for (i, pixel) in row.chunks_exact(bytes_per_pixel).enumerate() {
+			let x = (self.x_offset + i as u32 * self.x_step) as usize;
+			image_row[x * bytes_per_pixel..][..bytes_per_pixel].copy_from_slice(pixel);
+		}
+	}
+}
+
+
+/// The first non-empty Adam7 pass line at or after (`pass`, `line`), with `pass` counted from 0.
+fn next_adam7_position(width: u32, height: u32, mut pass: usize, mut line: u32) -> Option<RowPosition> {
+	while let Some(&(x_offset, y_offset, x_step, y_step)) = ADAM7_PASSES.get(pass) {
+		let pass_width = width.saturating_sub(x_offset).div_ceil(x_step);
+		let pass_height = height.saturating_sub(y_offset).div_ceil(y_step);
+		if pass_width > 0 && line < pass_height {
+			return Some(RowPosition {
+				pass: Some(pass as u8 + 1),
+				line,
+				y: y_offset + line * y_step,
+				x_offset,
+				x_step,
+				width: pass_width,
+			});
+		}
+		pass += 1;
+		line = 0;
+	}
+	None
+}
+
+
+impl<R: BufRead + Seek> PngRows<R> {
+	pub fn dimensions(&self) -> (u32, u32) {
+		(self.width, self.height)
+	}
+
+	pub fn color_type(&self) -> ColorType {
+		self.color_type
+	}
+
+	pub fn is_interlaced(&self) -> bool {
+		self.reader.info().interlaced
+	}
+
+	pub fn next_row(&mut self) -> Result<Option<PngRow<'_>>, Error> {
+		let Some(position) = self.next else {
+			return Ok(None);
+		};
+		self.next = match position.pass {
+			Some(pass) => next_adam7_position(self.width, self.height, usize::from(pass) - 1, position.line + 1),
+			None => (position.y + 1 < self.height).then(|| RowPosition::full_row(position.y + 1, self.width)),
+		};
+
+		let bytes_per_pixel = usize::from(self.color_type.bytes_per_pixel());
+		let bpc = self.color_type.bytes_per_pixel() / self.color_type.channel_count();
+		let indexed_bits = self.indexed_bits;
+		let palette_lut = self.palette_lut.as_deref();
+		let reduce_16 = self.reduce_16;
+		let unpacked = &mut self.unpacked;
+		let Some(row) = self.reader.next_interlaced_row()? else {
+			return Err(Error::Decoding(DecodingError::new(ImageFormat::Png.into(), "image data ended early")));
+		};
+		if let png::InterlaceInfo::Adam7(info) = row.interlace() {
+			debug_assert_eq!(Some(*info), position.pass.map(|pass| png::Adam7Info::new(pass, position.line, self.width)));
+		}
+		let data = match (indexed_bits, palette_lut, reduce_16) {
+			(Some(bits), Some(lut), _) => {
+				unpacked.resize(position.width as usize * bytes_per_pixel, 0);
+				expand_palette_row(row.data(), bits, lut, bytes_per_pixel, unpacked);
+				&unpacked[..]
+			},
+			(Some(bits @ (1 | 2 | 4)), None, _) => {
+				unpacked.resize(position.width as usize, 0);
+				unpack_indices(row.data(), bits, unpacked);
+				&unpacked[..]
+			},
+			(_, _, Some(reduce)) => {
+				unpacked.resize(row.data().len() / 2, 0);
+				reduce_16_to_8(row.data(), reduce, unpacked);
+				&unpacked[..]
+			},
+			_ if bpc == 2 => {
+				unpacked.clear();
+				unpacked.extend_from_slice(row.data());
+				convert::be16_to_native_in_place(unpacked);
+				&unpacked[..]
+			},
+			_ => row.data(),
+		};
+		Ok(Some(PngRow { data, position }))
+	}
+}
+
+
+#[cfg(feature = "testing")]
+fn record_coverage(info: &png::Info) {
+	use crate::coverage::{Branch, record};
+
+	record(if info.interlaced { Branch::PngInterlaced } else { Branch::PngNonInterlaced });
+	match info.bit_depth {
+		png::BitDepth::Sixteen => record(Branch::Png16Bit),
+		png::BitDepth::Eight => record(Branch::Png8Bit),
+		_ => record(Branch::PngSubByte),
+	}
+	if info.color_type == png::ColorType::Indexed {
+		record(Branch::PngPalette);
+	}
+	if info.icc_profile.is_some() {
+		record(Branch::PngIccProfile);
+	}
+	if info.is_animated() {
+		record(Branch::PngAnimated);
+	}
+}
+
+
+/// Reduces big endian 16-bit samples to 8 bits with `reduce`.
+fn reduce_16_to_8(wide: &[u8], reduce: fn(u16) -> u8, out: &mut [u8]) {
+	for (out, sample) in out.iter_mut().zip(wide.chunks_exact(2)) {
+		*out = reduce(u16::from_be_bytes([sample[0], sample[1]]));
+	}
+}
+
+
+/// Rescales big endian samples of `bytes` (1 or 2) bytes so the significant bits given by an sBIT chunk span the full
+/// range. sBIT lists one entry per stored channel, so a trailing alpha channel expanded from tRNS is left alone.
+fn rescale_significant_bits(buf: &mut [u8], bytes: usize, channels: usize, sbit: &[u8], mode: SignificantBits) {
+	let depth = 8 * bytes as u32;
+	let full = (1u32 << depth) - 1;
+	if sbit.len() > channels {
+		return;
+	}
+	let rescale: Vec<Option<(u32, u32)>> = (0..channels)
+		.map(|c| sbit.get(c).map(|&s| u32::from(s)).filter(|&s| s > 0 && s < depth).map(|s| (s, (1 << s) - 1)))
+		.collect();
+	if rescale.iter().all(Option::is_none) {
+		return;
+	}
+
+	for pixel in buf.chunks_exact_mut(bytes * channels) {
+		for (sample, rescale) in pixel.chunks_exact_mut(bytes).zip(&rescale) {
+			let Some((bits, max)) = *rescale else { continue };
+			let v = if bytes == 2 { u32::from(u16::from_be_bytes([sample[0], sample[1]])) } else { u32::from(sample[0]) };
+			let v = match mode {
+				SignificantBits::Ignore => continue,
+				SignificantBits::HighBits => v >> (depth - bits),
+				SignificantBits::LowBits => v.min(max),
+			};
+			let v = (v * full + max / 2) / max;
+			if bytes == 2 {
+				sample.copy_from_slice(&(v as u16).to_be_bytes());
+			} else {
+				sample[0] = v as u8;
+			}
+		}
+	}
+}
+
+
+/// The `x`th palette index of a row packed at 1, 2, 4 or 8 bits per pixel, most significant bits first.
+fn index_at(row: &[u8], x: usize, bits: u8) -> u8 {
+	if bits == 8 {
+		return row[x];
+	}
+	let per_byte = usize::from(8 / bits);
+	let shift = 8 - bits * (1 + (x % per_byte) as u8);
+	(row[x / per_byte] >> shift) & ((1 << bits) - 1)
+}
+
+
+/// Unpacks one row of palette indices into one byte per pixel; `out` holds exactly one row.
+fn unpack_indices(row: &[u8], bits: u8, out: &mut [u8]) {
+	for (x, out) in out.iter_mut().enumerate() {
+		*out = index_at(row, x, bits);
+	}
+}
+
+
+/// Looks up one row of palette indices in `lut`, writing RGB (`channels` = 3) or RGBA (`channels` = 4).
+fn expand_palette_row(row: &[u8], bits: u8, lut: &[[u8; 4]; 256], channels: usize, out: &mut [u8]) {
+	for (x, pixel) in out.chunks_exact_mut(channels).enumerate() {
+		pixel.copy_from_slice(&lut[usize::from(index_at(row, x, bits))][..channels]);
+	}
+}
+
+
+/// RGBA for each of the 256 possible indices.
+///
+/// Files in the wild have palettes shorter than `2^bit_depth` with pixels indexing past their end, and tRNS chunks
+/// shorter (or longer) than the palette. Rather than failing those images we follow libpng, and so Pillow and
+/// Firefox: indices past the end of the palette are opaque black, palette entries past the end of tRNS are opaque,
+/// and tRNS entries past the end of the palette are ignored.
+fn palette_lut(palette: &[u8], trns: Option<&[u8]>) -> Box<[[u8; 4]; 256]> {
+	let mut lut = Box::new([[0, 0, 0, 255]; 256]);
+	let entries = palette.len() / 3;
+	for (entry, rgb) in lut.iter_mut().zip(palette.chunks_exact(3)) {
+		entry[..3].copy_from_slice(rgb);
+	}
+	for (entry, &alpha) in lut.iter_mut().take(entries).zip(trns.unwrap_or_default()) {
+		entry[3] = alpha;
+	}
+	lut
+}
+
+
+/// Which kinds of metadata chunk the png crate should drop (or, for eXIf, we should withhold).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct MetadataDrop {
+	text: bool,
+	icc_profile: bool,
+	exif: bool,
+}
+
+
+/// Total payload bytes of each kind of metadata chunk ahead of the image data.
+#[derive(Debug, Default)]
+struct MetadataSizes {
+	text: u64,
+	icc_profile: u64,
+	exif: u64,
+}
+
+impl MetadataSizes {
+	/// Drops whole kinds of metadata until the rest fits in `max` bytes: text first, since that is where the bulk
+	/// usually is (embedded workflows, base64 blobs), then EXIF, and the ICC profile last since it affects color.
+	fn fit(&self, max: u64) -> MetadataDrop {
+		let mut drop = MetadataDrop::default();
+		let mut total = self.text + self.icc_profile + self.exif;
+		for (size, dropped) in [(self.text, &mut drop.text), (self.exif, &mut drop.exif), (self.icc_profile, &mut drop.icc_profile)] {
+			if total <= max {
+				break;
+			}
+			if size > 0 {
+				*dropped = true;
+				total -= size;
+			}
+		}
+		drop
+	}
+}
+
+
+/// Walks the chunk headers up to the first IDAT and rewinds, so the budget can be settled before the png crate
+/// allocates anything. A malformed or truncated chunk stream just ends the walk; the png crate reports the error.
+fn metadata_sizes<R: BufRead + Seek>(r: &mut R) -> std::io::Result<MetadataSizes> {
+	let start = r.stream_position()?;
+	let mut sizes = MetadataSizes::default();
+	let mut header = [0; 8];
+	if r.read_exact(&mut header).is_ok() {
+		while r.read_exact(&mut header).is_ok() {
+			let length = u64::from(u32::from_be_bytes([header[0], header[1], header[2], header[3]]));
+			match &header[4..] {
+				b"IDAT" | b"IEND" => break,
+				b"tEXt" | b"zTXt" | b"iTXt" => sizes.text += length,
+				b"iCCP" => sizes.icc_profile += length,
+				b"eXIf" => sizes.exif += length,
+				_ => (),
+			}
+			r.seek(SeekFrom::Current(length as i64 + 4))?;
+		}
+	}
+	r.seek(SeekFrom::Start(start))?;
+	Ok(sizes)
+}
+
+
 fn unsupported_color(ect: ExtendedColorType) -> Error {
 	Error::Unsupported(UnsupportedError::from_format_and_kind(
 		ImageFormat::Png.into(),
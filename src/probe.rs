@@ -0,0 +1,244 @@
+//! Reading an image's format, dimensions and color type without decoding its pixels.
+
+use std::io::{BufRead, Seek};
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::{fs::File, io::BufReader, path::Path};
+
+use image::{ColorType, ImageDecoder, ImageFormat};
+
+#[cfg(feature = "avif")]
+use crate::AvifDecoder;
+#[cfg(feature = "heif")]
+use crate::HeifDecoder;
+#[cfg(feature = "jp2")]
+use crate::Jpeg2000Decoder;
+#[cfg(feature = "svg")]
+use crate::SvgDecoder;
+#[cfg(feature = "texture")]
+use crate::TextureDecoder;
+use crate::{Error, Format, GifDecoder, IcoDecoder, JpegDecoder, JxlDecoder, PngDecoder, QoiDecoder, TiffDecoder, WebPDecoder};
+
+
+/// What `load_image` would return, minus the pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageInfo {
+	/// The format `load_image` would report, except that MPO files are reported as JPEG, since spotting them takes
+	/// more than the header.
+	pub format: Format,
+	pub width: u32,
+	pub height: u32,
+	/// Bits per sample of `color_type`
+	pub bit_depth: u8,
+	/// The color type `load_image` decodes to with default options
+	pub color_type: ColorType,
+	/// Whether the image is animated, which `load_image` rejects by default
+	pub animated: bool,
+}
+
+impl ImageInfo {
+	fn new(format: impl Into<Format>, (width, height): (u32, u32), color_type: ColorType, animated: bool) -> ImageInfo {
+		ImageInfo {
+			format: format.into(),
+			width,
+			height,
+			bit_depth: (color_type.bits_per_pixel() / u16::from(color_type.channel_count())) as u8,
+			color_type,
+			animated,
+		}
+	}
+
+	fn from_decoder(format: impl Into<Format>, decoder: &impl ImageDecoder, animated: bool) -> ImageInfo {
+		ImageInfo::new(format, decoder.dimensions(), decoder.color_type(), animated)
+	}
+}
+
+
+/// The largest image in a PDF, which is the one `load_image` decodes.
+#[cfg(feature = "pdf")]
+fn probe_pdf(input: &[u8]) -> Result<ImageInfo, Error> {
+	use crate::pdf::ImageKind;
+
+	let Some(image) = crate::pdf::images(input).into_iter().next() else {
+		return Err(crate::pdf::no_image());
+	};
+	let color_type = match image.kind {
+		ImageKind::Jpeg => JpegDecoder::read_header(image.data)?.color_type,
+		#[cfg(feature = "jp2")]
+		ImageKind::Jpeg2000 => Jpeg2000Decoder::new(std::io::Cursor::new(image.data))?.color_type(),
+		#[cfg(not(feature = "jp2"))]
+		ImageKind::Jpeg2000 => unreachable!("JPEG 2000 images are only listed with the jp2 feature"),
+		ImageKind::Flate { components: 1, bits: 16, .. } => ColorType::L16,
+		ImageKind::Flate { components: 1, .. } => ColorType::L8,
+		ImageKind::Flate { bits: 16, .. } => ColorType::Rgb16,
+		ImageKind::Flate { .. } => ColorType::Rgb8,
+	};
+	Ok(ImageInfo::new(Format::Pdf, (image.width, image.height), color_type, false))
+}
+
+
+/// One of the images in a file, as listed by `probe_images`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubImage {
+	/// Position in the file, from 0: the page of a TIFF, entry in an ICO directory, picture in an MPO file, top level
+	/// image in a HEIF file, or image in a PDF, in file order
+	pub index: usize,
+	/// As stored, or for ICO entries as the directory says
+	pub width: u32,
+	pub height: u32,
+	/// Whether this is the image `load_image` decodes with default options
+	pub primary: bool,
+}
+
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn probe_image<P: AsRef<Path>>(path: P) -> Result<ImageInfo, Error> {
+	probe_image_from_reader(BufReader::new(File::open(path)?))
+}
+
+
+/// Reads as little of the image as it takes to describe it. For most formats that's the header, but JPEG XL, SVG and
+/// some of the container formats parse more, and GIF and WebP read far enough to tell whether they're animated.
+pub fn probe_image_from_reader<R: BufRead + Seek>(mut reader: R) -> Result<ImageInfo, Error> {
+	let mut buf = [0; 16];
+	reader.read_exact(&mut buf)?;
+	reader.rewind()?;
+	let Some(format) = Format::guess(&buf) else {
+		return Err(Error::UnsupportedFormat);
+	};
+
+	let format = match format {
+		#[cfg(feature = "heif")]
+		Format::Heif => return Ok(ImageInfo::from_decoder(Format::Heif, &HeifDecoder::new(reader)?, false)),
+		#[cfg(feature = "svg")]
+		Format::Svg => return Ok(ImageInfo::from_decoder(Format::Svg, &SvgDecoder::new(reader)?, false)),
+		Format::Jxl => {
+			let decoder = JxlDecoder::new(reader)?;
+			return Ok(ImageInfo::from_decoder(Format::Jxl, &decoder, decoder.is_animated()));
+		},
+		#[cfg(feature = "jp2")]
+		Format::Jpeg2000 => return Ok(ImageInfo::from_decoder(Format::Jpeg2000, &Jpeg2000Decoder::new(reader)?, false)),
+		#[cfg(feature = "texture")]
+		Format::Ktx2 => return Ok(ImageInfo::from_decoder(Format::Ktx2, &TextureDecoder::new(reader)?, false)),
+		#[cfg(feature = "pdf")]
+		Format::Pdf => {
+			let mut input = Vec::new();
+			reader.read_to_end(&mut input)?;
+			return probe_pdf(&input);
+		},
+		Format::Image(format) => format,
+		_ => return Err(Error::UnsupportedFormat),
+	};
+
+	let info = match format {
+		ImageFormat::Png => {
+			let decoder = PngDecoder::new(reader)?;
+			ImageInfo::from_decoder(format, &decoder, decoder.is_animated())
+		},
+		ImageFormat::Jpeg => {
+			let header = JpegDecoder::read_header(reader)?;
+			ImageInfo::new(format, (header.width, header.height), header.color_type, false)
+		},
+		ImageFormat::WebP => {
+			let decoder = WebPDecoder::new(reader)?;
+			ImageInfo::from_decoder(format, &decoder, decoder.is_animated())
+		},
+		#[cfg(feature = "avif")]
+		ImageFormat::Avif => ImageInfo::from_decoder(format, &AvifDecoder::new(reader)?, false),
+		ImageFormat::Gif => {
+			let decoder = GifDecoder::new(reader)?;
+			ImageInfo::from_decoder(format, &decoder, decoder.is_animated())
+		},
+		ImageFormat::Ico => ImageInfo::from_decoder(format, &IcoDecoder::new(reader)?, false),
+		#[cfg(feature = "texture")]
+		ImageFormat::Dds => ImageInfo::from_decoder(format, &TextureDecoder::new(reader)?, false),
+		ImageFormat::Qoi => ImageInfo::from_decoder(format, &QoiDecoder::new(reader)?, false),
+		ImageFormat::Tiff => {
+			#[cfg(feature = "raw")]
+			let reader = {
+				let mut input = Vec::new();
+				reader.read_to_end(&mut input)?;
+				match crate::raw::inspect(&input) {
+					crate::raw::Raw::NotRecognized => std::io::Cursor::new(input),
+					crate::raw::Raw::Preview(range) => {
+						let header = JpegDecoder::read_header(&input[range])?;
+						return Ok(ImageInfo::new(Format::RawPreview, (header.width, header.height), header.color_type, false));
+					},
+					crate::raw::Raw::NoPreview => {
+						return Err(Error::Unsupported(image::error::UnsupportedError::from_format_and_kind(
+							ImageFormat::Tiff.into(),
+							image::error::UnsupportedErrorKind::GenericFeature("camera RAW without an embedded JPEG preview".to_string()),
+						)));
+					},
+				}
+			};
+			ImageInfo::from_decoder(format, &TiffDecoder::new(reader)?, false)
+		},
+		_ => ImageInfo::from_decoder(format, &image::ImageReader::with_format(reader, format).into_decoder()?, false),
+	};
+	Ok(info)
+}
+
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn probe_images<P: AsRef<Path>>(path: P) -> Result<Vec<SubImage>, Error> {
+	probe_images_from_reader(BufReader::new(File::open(path)?))
+}
+
+
+/// Lists every image in formats that can hold several (multi-page TIFF, ICO, MPO, HEIF and PDF) without decoding any
+/// of them, so callers can decide what to ingest first. Images in other formats, and animations, list as one.
+pub fn probe_images_from_reader<R: BufRead + Seek>(mut reader: R) -> Result<Vec<SubImage>, Error> {
+	let info = probe_image_from_reader(&mut reader)?;
+	reader.rewind()?;
+	// (index, dimensions, primary)
+	let images: Vec<(usize, (u32, u32), bool)> = match info.format {
+		Format::Image(ImageFormat::Tiff) => {
+			let pages = crate::tiff_decoder::page_dimensions(reader)?;
+			pages.into_iter().enumerate().map(|(i, size)| (i, size, i == 0)).collect()
+		},
+		Format::Image(ImageFormat::Ico) => {
+			let decoder = IcoDecoder::new(reader)?;
+			let chosen = decoder.chosen().index;
+			decoder.entries().iter().map(|entry| (entry.index, (entry.width, entry.height), entry.index == chosen)).collect()
+		},
+		Format::Image(ImageFormat::Jpeg) => {
+			let mut input = Vec::new();
+			reader.read_to_end(&mut input)?;
+			match crate::jpeg_decoder::mp_entries(&input) {
+				// Pictures other than the primary one whose header can't be read are left out, since `load_image`
+				// doesn't read them either
+				Some(entries) => entries
+					.into_iter()
+					.enumerate()
+					.filter_map(|(i, range)| match JpegDecoder::read_header(&input[range]) {
+						Ok(header) => Some(Ok((i, (header.width, header.height), i == 0))),
+						Err(e) if i == 0 => Some(Err(e)),
+						Err(_) => None,
+					})
+					.collect::<Result<_, _>>()?,
+				None => vec![(0, (info.width, info.height), true)],
+			}
+		},
+		#[cfg(feature = "heif")]
+		Format::Heif => crate::heif_decoder::top_level_images(reader)?.into_iter().enumerate().map(|(i, (size, primary))| (i, size, primary)).collect(),
+		#[cfg(feature = "pdf")]
+		Format::Pdf => {
+			let mut input = Vec::new();
+			reader.read_to_end(&mut input)?;
+			let mut images = crate::pdf::images(&input);
+			let primary = images.first().map(|image| image.data.as_ptr());
+			images.sort_by_key(|image| image.data.as_ptr() as usize);
+			images.iter().enumerate().map(|(i, image)| (i, (image.width, image.height), Some(image.data.as_ptr()) == primary)).collect()
+		},
+		_ => vec![(0, (info.width, info.height), true)],
+	};
+	Ok(images
+		.into_iter()
+		.map(|(index, (width, height), primary)| SubImage {
+			index,
+			width,
+			height,
+			primary,
+		})
+		.collect())
+}
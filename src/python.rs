@@ -0,0 +1,223 @@
+//! Python bindings, with the `python` feature. Built as an extension module (`maturin build --features python`, see
+//! `pyproject.toml`), `import imgest` gives Pillow based pipelines the same decoding as the Rust API:
+//!
+//! ```python
+//! import imgest
+//!
+//! options = imgest.LoadOptions(output="rgba8", max_pixels=100_000_000, apply_orientation=True)
+//! format, width, height, data = imgest.load_image("photo.jpg", options)
+//! img = PIL.Image.frombytes("RGBA", (width, height), data)
+//! ```
+//!
+//! Pixels come back as `bytes`, interleaved and row by row in the image's own color type unless `output` asks for
+//! one; 16-bit samples are in native byte order. Decoding releases the GIL. `OSError` is raised for I/O errors and
+//! `imgest.ImgestError` for everything else.
+
+use std::path::PathBuf;
+
+use pyo3::{create_exception, exceptions::PyException, prelude::*, types::PyBytes};
+
+use crate::{AnimatedPolicy, Error, Format, LoadOptions, OutputColor, OutputDepth};
+
+
+create_exception!(imgest, ImgestError, PyException);
+
+
+pub(crate) fn to_py_err(err: Error) -> PyErr {
+	match err {
+		Error::Io(err) => err.into(),
+		err => ImgestError::new_err(err.to_string()),
+	}
+}
+
+
+fn bad_value(name: &str, value: &str, allowed: &str) -> PyErr {
+	pyo3::exceptions::PyValueError::new_err(format!("{name} must be one of {allowed}, not {value:?}"))
+}
+
+
+/// `imgest::LoadOptions`, with the options that make sense from Python. Immutable; build a new one to change it.
+#[pyclass(name = "LoadOptions", module = "imgest", frozen)]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PyLoadOptions {
+	pub(crate) options: LoadOptions,
+}
+
+#[pymethods]
+impl PyLoadOptions {
+	#[new]
+	#[pyo3(signature = (
+		*,
+		output = "native",
+		output_depth = "preserve",
+		animated = "reject",
+		max_pixels = None,
+		max_file_bytes = None,
+		max_alloc_bytes = None,
+		max_expansion_ratio = None,
+		apply_orientation = false,
+		target_max_dimension = None,
+	))]
+	#[allow(clippy::too_many_arguments)]
+	fn new(
+		output: &str,
+		output_depth: &str,
+		animated: &str,
+		max_pixels: Option<u64>,
+		max_file_bytes: Option<u64>,
+		max_alloc_bytes: Option<u64>,
+		max_expansion_ratio: Option<u64>,
+		apply_orientation: bool,
+		target_max_dimension: Option<u32>,
+	) -> PyResult<Self> {
+		let output = match output {
+			"native" => OutputColor::Native,
+			"rgba8" => OutputColor::Rgba8,
+			"rgba8_keep_gray_alpha" => OutputColor::Rgba8KeepGrayAlpha,
+			"luma8" => OutputColor::Luma8,
+			value => return Err(bad_value("output", value, "\"native\", \"rgba8\", \"rgba8_keep_gray_alpha\" or \"luma8\"")),
+		};
+		let output_depth = match output_depth {
+			"preserve" => OutputDepth::Preserve,
+			"8" => OutputDepth::Force8,
+			"16" => OutputDepth::Force16,
+			value => return Err(bad_value("output_depth", value, "\"preserve\", \"8\" or \"16\"")),
+		};
+		let animated_policy = match animated {
+			"reject" => AnimatedPolicy::Reject,
+			"first_frame" => AnimatedPolicy::FirstFrame,
+			value => return Err(bad_value("animated", value, "\"reject\" or \"first_frame\"")),
+		};
+		let options = LoadOptions {
+			output,
+			output_depth,
+			animated_policy,
+			max_pixels,
+			max_file_bytes,
+			max_alloc_bytes,
+			max_expansion_ratio,
+			apply_orientation,
+			target_max_dimension,
+			..LoadOptions::default()
+		};
+		Ok(PyLoadOptions { options })
+	}
+
+	#[getter]
+	fn output(&self) -> &'static str {
+		match self.options.output {
+			OutputColor::Native => "native",
+			OutputColor::Rgba8 => "rgba8",
+			OutputColor::Rgba8KeepGrayAlpha => "rgba8_keep_gray_alpha",
+			OutputColor::Luma8 => "luma8",
+		}
+	}
+
+	#[getter]
+	fn output_depth(&self) -> &'static str {
+		match self.options.output_depth {
+			OutputDepth::Preserve => "preserve",
+			OutputDepth::Force8 => "8",
+			OutputDepth::Force16 => "16",
+		}
+	}
+
+	#[getter]
+	fn animated(&self) -> &'static str {
+		match self.options.animated_policy {
+			AnimatedPolicy::Reject => "reject",
+			AnimatedPolicy::FirstFrame => "first_frame",
+		}
+	}
+
+	#[getter]
+	fn max_pixels(&self) -> Option<u64> {
+		self.options.max_pixels
+	}
+
+	#[getter]
+	fn max_file_bytes(&self) -> Option<u64> {
+		self.options.max_file_bytes
+	}
+
+	#[getter]
+	fn max_alloc_bytes(&self) -> Option<u64> {
+		self.options.max_alloc_bytes
+	}
+
+	#[getter]
+	fn max_expansion_ratio(&self) -> Option<u64> {
+		self.options.max_expansion_ratio
+	}
+
+	#[getter]
+	fn apply_orientation(&self) -> bool {
+		self.options.apply_orientation
+	}
+
+	#[getter]
+	fn target_max_dimension(&self) -> Option<u32> {
+		self.options.target_max_dimension
+	}
+
+	fn __repr__(&self) -> String {
+		format!(
+			"LoadOptions(output={:?}, output_depth={:?}, animated={:?}, max_pixels={}, max_file_bytes={}, max_alloc_bytes={}, max_expansion_ratio={}, \
+			 apply_orientation={}, target_max_dimension={})",
+			self.output(),
+			self.output_depth(),
+			self.animated(),
+			py_option(self.options.max_pixels),
+			py_option(self.options.max_file_bytes),
+			py_option(self.options.max_alloc_bytes),
+			py_option(self.options.max_expansion_ratio),
+			if self.options.apply_orientation { "True" } else { "False" },
+			py_option(self.options.target_max_dimension),
+		)
+	}
+}
+
+
+fn py_option<T: ToString>(value: Option<T>) -> String {
+	value.map_or_else(|| "None".to_string(), |value| value.to_string())
+}
+
+
+/// (format, width, height, pixels) for a decoded image, with the format's lowercase name as in `capabilities`.
+type PyImage<'py> = (String, u32, u32, Bound<'py, PyBytes>);
+
+
+fn to_py_image(py: Python<'_>, (format, img): (Format, image::DynamicImage)) -> PyImage<'_> {
+	(crate::versions::format_name(format), img.width(), img.height(), PyBytes::new(py, img.as_bytes()))
+}
+
+
+#[pyfunction]
+#[pyo3(signature = (path, options = None))]
+fn load_image<'py>(py: Python<'py>, path: PathBuf, options: Option<PyRef<'_, PyLoadOptions>>) -> PyResult<PyImage<'py>> {
+	let options = options.map(|options| options.options.clone()).unwrap_or_default();
+	let decoded = py.detach(|| crate::load_image_with_options(path, &options)).map_err(to_py_err)?;
+	Ok(to_py_image(py, decoded))
+}
+
+
+#[pyfunction]
+#[pyo3(signature = (data, options = None))]
+fn load_image_from_bytes<'py>(py: Python<'py>, data: &[u8], options: Option<PyRef<'_, PyLoadOptions>>) -> PyResult<PyImage<'py>> {
+	let options = options.map(|options| options.options.clone()).unwrap_or_default();
+	let decoded = py.detach(|| crate::load_image_from_bytes_with_options(data, &options)).map_err(to_py_err)?;
+	Ok(to_py_image(py, decoded))
+}
+
+
+#[pymodule]
+fn imgest(m: &Bound<'_, PyModule>) -> PyResult<()> {
+	m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+	m.add("ImgestError", m.py().get_type::<ImgestError>())?;
+	m.add_class::<PyLoadOptions>()?;
+	m.add_function(wrap_pyfunction!(load_image, m)?)?;
+	m.add_function(wrap_pyfunction!(load_image_from_bytes, m)?)?;
+	#[cfg(feature = "dlpack")]
+	crate::dlpack::register(m)?;
+	Ok(())
+}
@@ -0,0 +1,177 @@
+use std::io::{BufRead, Read};
+
+use image::{
+	ColorType, ImageDecoder, ImageError, ImageFormat, ImageResult, Limits,
+	error::DecodingError,
+};
+
+use crate::error::Error;
+
+
+const MAGIC: &[u8; 4] = b"qoif";
+const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+const OP_RGB: u8 = 0xFE;
+const OP_RGBA: u8 = 0xFF;
+const OP_INDEX: u8 = 0b00;
+const OP_DIFF: u8 = 0b01;
+const OP_LUMA: u8 = 0b10;
+
+
+/// Decoder for QOI ("Quite OK Image") files.
+///
+/// The format is simple enough that it's decoded here directly, one pixel at a time as the reader is consumed.
+pub struct QoiDecoder<R> {
+	reader: R,
+	width: u32,
+	height: u32,
+	channels: u8,
+	linear: bool,
+	limits: Limits,
+}
+
+
+impl<R: BufRead> QoiDecoder<R> {
+	pub fn new(r: R) -> Result<QoiDecoder<R>, Error> {
+		Self::with_limits(r, Limits::no_limits())
+	}
+
+	pub fn with_limits(mut r: R, limits: Limits) -> Result<QoiDecoder<R>, Error> {
+		limits.check_support(&image::LimitSupport::default())?;
+
+		let mut header = [0; 14];
+		r.read_exact(&mut header)?;
+		if &header[..4] != MAGIC {
+			return Err(Error::Decoding(decoding_error("not a QOI file")));
+		}
+		let width = u32::from_be_bytes(header[4..8].try_into().unwrap());
+		let height = u32::from_be_bytes(header[8..12].try_into().unwrap());
+		let channels = header[12];
+		if channels != 3 && channels != 4 {
+			return Err(Error::Decoding(decoding_error(format!("invalid channel count {channels}"))));
+		}
+		if header[13] > 1 {
+			return Err(Error::Decoding(decoding_error(format!("invalid colorspace {}", header[13]))));
+		}
+		if width == 0 || height == 0 {
+			return Err(Error::Decoding(decoding_error("zero width or height")));
+		}
+
+		let mut decoder = QoiDecoder {
+			reader: r,
+			width,
+			height,
+			channels,
+			linear: header[13] == 1,
+			limits: Limits::no_limits(),
+		};
+		decoder.set_limits(limits)?;
+		Ok(decoder)
+	}
+
+	/// Returns true if the header marks all channels as linear, rather than sRGB color with linear alpha. The samples
+	/// are returned as stored either way.
+	pub fn is_linear(&self) -> bool {
+		self.linear
+	}
+}
+
+
+impl<R: BufRead> ImageDecoder for QoiDecoder<R> {
+	fn dimensions(&self) -> (u32, u32) {
+		(self.width, self.height)
+	}
+
+	fn color_type(&self) -> ColorType {
+		match self.channels {
+			3 => ColorType::Rgb8,
+			_ => ColorType::Rgba8,
+		}
+	}
+
+	fn read_image(mut self, buf: &mut [u8]) -> ImageResult<()> {
+		crate::error::check_buffer(buf, self.total_bytes())?;
+		let channels = usize::from(self.channels);
+		let mut index = [[0u8; 4]; 64];
+		let mut px = [0, 0, 0, 255u8];
+		let mut run = 0;
+		let mut byte = || -> ImageResult<u8> {
+			let mut b = [0];
+			self.reader.read_exact(&mut b)?;
+			Ok(b[0])
+		};
+
+		for out in buf.chunks_exact_mut(channels) {
+			if run > 0 {
+				run -= 1;
+			} else {
+				let op = byte()?;
+				match (op, op >> 6) {
+					(OP_RGB, _) => {
+						px[0] = byte()?;
+						px[1] = byte()?;
+						px[2] = byte()?;
+					},
+					(OP_RGBA, _) => {
+						px = [byte()?, byte()?, byte()?, byte()?];
+					},
+					(_, OP_INDEX) => px = index[usize::from(op & 0x3F)],
+					(_, OP_DIFF) => {
+						px[0] = px[0].wrapping_add((op >> 4) & 0x03).wrapping_sub(2);
+						px[1] = px[1].wrapping_add((op >> 2) & 0x03).wrapping_sub(2);
+						px[2] = px[2].wrapping_add(op & 0x03).wrapping_sub(2);
+					},
+					(_, OP_LUMA) => {
+						let dg = (op & 0x3F).wrapping_sub(32);
+						let next = byte()?;
+						px[0] = px[0].wrapping_add(dg).wrapping_add(next >> 4).wrapping_sub(8);
+						px[1] = px[1].wrapping_add(dg);
+						px[2] = px[2].wrapping_add(dg).wrapping_add(next & 0x0F).wrapping_sub(8);
+					},
+					// OP_RUN, stored with a bias of -1
+					_ => run = op & 0x3F,
+				}
+			}
+
+			let hash = (px[0] as usize * 3 + px[1] as usize * 5 + px[2] as usize * 7 + px[3] as usize * 11) % 64;
+			index[hash] = px;
+			out.copy_from_slice(&px[..channels]);
+		}
+
+		// The end marker is part of the format, but a missing one costs no pixels, so only a wrong one is an error
+		let mut end = [0; 8];
+		let read = read_up_to(&mut self.reader, &mut end)?;
+		if end[..read] != END_MARKER[..read] {
+			return Err(ImageError::Decoding(decoding_error("missing end marker")));
+		}
+		Ok(())
+	}
+
+	fn read_image_boxed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+		(*self).read_image(buf)
+	}
+
+	fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+		limits.check_support(&image::LimitSupport::default())?;
+		limits.check_dimensions(self.width, self.height)?;
+		self.limits = limits;
+		Ok(())
+	}
+}
+
+
+fn read_up_to<R: Read>(r: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+	let mut filled = 0;
+	while filled < buf.len() {
+		match r.read(&mut buf[filled..])? {
+			0 => break,
+			n => filled += n,
+		}
+	}
+	Ok(filled)
+}
+
+
+fn decoding_error(message: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> DecodingError {
+	DecodingError::new(ImageFormat::Qoi.into(), message)
+}
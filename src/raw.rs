@@ -0,0 +1,128 @@
+//! Camera RAW (CR2, NEF, ARW, DNG) support through the JPEG previews embedded in them.
+//!
+//! All four are TIFF containers. Rather than demosaicing the sensor data we find the largest embedded baseline or
+//! progressive JPEG, which cameras write at (or near) full resolution for their own playback. Lossless JPEG, which
+//! CR2 and DNG use for the sensor data itself, is never picked.
+
+use std::{collections::HashSet, ops::Range};
+
+use crate::exif::{TiffReader, Value};
+
+
+const MAX_IFDS: usize = 32;
+
+const CR2_SIGNATURE: &[u8] = b"CR\x02\0";
+
+const COMPRESSION: u16 = 0x0103;
+const PHOTOMETRIC_INTERPRETATION: u16 = 0x0106;
+const STRIP_OFFSETS: u16 = 0x0111;
+const STRIP_BYTE_COUNTS: u16 = 0x0117;
+const SUB_IFDS: u16 = 0x014A;
+const JPEG_INTERCHANGE_FORMAT: u16 = 0x0201;
+const JPEG_INTERCHANGE_FORMAT_LENGTH: u16 = 0x0202;
+const DNG_VERSION: u16 = 0xC612;
+
+const COMPRESSION_OLD_JPEG: u32 = 6;
+const COMPRESSION_JPEG: u32 = 7;
+const COMPRESSION_NEF: u32 = 34713;
+const PHOTOMETRIC_CFA: u32 = 32803;
+const PHOTOMETRIC_LINEAR_RAW: u32 = 34892;
+
+
+pub(crate) enum Raw {
+	/// An ordinary TIFF
+	NotRecognized,
+	/// Byte range of the largest usable embedded JPEG
+	Preview(Range<usize>),
+	/// A RAW file without any JPEG we can decode
+	NoPreview,
+}
+
+
+/// Decides whether a TIFF is a camera RAW, from a CR2 signature, a DNGVersion tag, or an IFD holding CFA or linear
+/// raw sensor data, and if so finds its preview.
+pub(crate) fn inspect(data: &[u8]) -> Raw {
+	let Some(reader) = TiffReader::new(data) else {
+		return Raw::NotRecognized;
+	};
+	let mut is_raw = data.get(8..12) == Some(CR2_SIGNATURE);
+	let mut best: Option<(u64, Range<usize>)> = None;
+
+	let mut visited = HashSet::new();
+	let mut pending = reader.u32(4).into_iter().collect::<Vec<_>>();
+	while let Some(offset) = pending.pop() {
+		if visited.len() >= MAX_IFDS || !visited.insert(offset) {
+			continue;
+		}
+		let Some((entries, next)) = reader.read_ifd(offset) else {
+			continue;
+		};
+		if next != 0 {
+			pending.push(next);
+		}
+		let get = |tag| entries.iter().find(|(t, _)| *t == tag).map(|(_, value)| value);
+		let get_u32 = |tag| get(tag).and_then(Value::as_u32);
+
+		if let Some(Value::Long(offsets)) = get(SUB_IFDS) {
+			pending.extend(offsets);
+		} else if let Some(offset) = get_u32(SUB_IFDS) {
+			pending.push(offset);
+		}
+		is_raw |= get(DNG_VERSION).is_some()
+			|| matches!(get_u32(PHOTOMETRIC_INTERPRETATION), Some(PHOTOMETRIC_CFA | PHOTOMETRIC_LINEAR_RAW))
+			|| get_u32(COMPRESSION) == Some(COMPRESSION_NEF);
+
+		let mut candidates = Vec::new();
+		if let (Some(start), Some(len)) = (get_u32(JPEG_INTERCHANGE_FORMAT), get_u32(JPEG_INTERCHANGE_FORMAT_LENGTH)) {
+			candidates.push((start, len));
+		}
+		// A JPEG stored as the IFD's only strip
+		if matches!(get_u32(COMPRESSION), Some(COMPRESSION_OLD_JPEG | COMPRESSION_JPEG))
+			&& let (Some(start), Some(len)) = (get_u32(STRIP_OFFSETS), get_u32(STRIP_BYTE_COUNTS))
+		{
+			candidates.push((start, len));
+		}
+		for (start, len) in candidates {
+			let range = start as usize..(start as usize).saturating_add(len as usize);
+			let Some((width, height)) = data.get(range.clone()).and_then(jpeg_dimensions) else {
+				continue;
+			};
+			let pixels = u64::from(width) * u64::from(height);
+			if best.as_ref().is_none_or(|(best, _)| pixels > *best) {
+				best = Some((pixels, range));
+			}
+		}
+	}
+
+	match (is_raw, best) {
+		(false, _) => Raw::NotRecognized,
+		(true, Some((_, range))) => Raw::Preview(range),
+		(true, None) => Raw::NoPreview,
+	}
+}
+
+
+/// Dimensions of a baseline, extended or progressive JPEG; `None` for anything else, including lossless JPEG.
+fn jpeg_dimensions(jpeg: &[u8]) -> Option<(u16, u16)> {
+	if !jpeg.starts_with(&[0xFF, 0xD8]) {
+		return None;
+	}
+	let mut pos = 2;
+	loop {
+		// Markers may be preceded by any number of fill bytes
+		while *jpeg.get(pos)? == 0xFF && *jpeg.get(pos + 1)? == 0xFF {
+			pos += 1;
+		}
+		if *jpeg.get(pos)? != 0xFF {
+			return None;
+		}
+		let marker = *jpeg.get(pos + 1)?;
+		let segment = jpeg.get(pos + 2..)?;
+		let be16 = |i: usize| Some(u16::from_be_bytes([*segment.get(i)?, *segment.get(i + 1)?]));
+		match marker {
+			0xC0..=0xC2 => return Some((be16(5)?, be16(3)?)),
+			0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF | 0xD9 | 0xDA => return None,
+			_ => pos += 2 + usize::from(be16(0)?),
+		}
+	}
+}
@@ -0,0 +1,219 @@
+//! Exact-duplicate detection by content hash, for skipping repeated files during batch ingest.
+//!
+//! A `SeenSet` records the SHA-256 of every file's bytes as it's ingested; a file whose hash is already in the set is
+//! a byte-for-byte copy of one ingested earlier and doesn't need decoding again. `SortedFileSeenSet` keeps the set on
+//! disk across runs, so repeated ingests of overlapping corpora skip what they've already seen without a separate
+//! dedup pass over everything.
+
+use std::{
+	collections::HashSet,
+	fmt,
+	fs::File,
+	io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+	path::{Path, PathBuf},
+	str::FromStr,
+};
+
+use sha2::{Digest, Sha256};
+
+
+/// SHA-256 of a file's bytes. Formats as (and parses from) 64 hex digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ContentHash(pub [u8; 32]);
+
+impl ContentHash {
+	pub fn of(data: &[u8]) -> ContentHash {
+		ContentHash(Sha256::digest(data).into())
+	}
+}
+
+impl fmt::Display for ContentHash {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		self.0.iter().try_for_each(|b| write!(f, "{b:02x}"))
+	}
+}
+
+impl FromStr for ContentHash {
+	type Err = ParseContentHashError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		if s.len() != 64 || !s.is_ascii() {
+			return Err(ParseContentHashError);
+		}
+		let mut hash = [0; 32];
+		for (byte, pair) in hash.iter_mut().zip(s.as_bytes().chunks_exact(2)) {
+			*byte = u8::from_str_radix(std::str::from_utf8(pair).map_err(|_| ParseContentHashError)?, 16).map_err(|_| ParseContentHashError)?;
+		}
+		Ok(ContentHash(hash))
+	}
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseContentHashError;
+
+impl fmt::Display for ParseContentHashError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "expected 64 hex digits")
+	}
+}
+
+impl std::error::Error for ParseContentHashError {}
+
+
+/// A set of content hashes already ingested.
+pub trait SeenSet {
+	/// Adds `hash`, returning true if it wasn't in the set yet (the file is new) and false for a duplicate.
+	fn insert(&mut self, hash: ContentHash) -> io::Result<bool>;
+
+	/// Persists anything inserted so far, for sets with a backing store.
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+
+/// A seen-set for a single run.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySeenSet {
+	hashes: HashSet<ContentHash>,
+}
+
+impl MemorySeenSet {
+	pub fn new() -> MemorySeenSet {
+		MemorySeenSet::default()
+	}
+
+	pub fn len(&self) -> usize {
+		self.hashes.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.hashes.is_empty()
+	}
+}
+
+impl SeenSet for MemorySeenSet {
+	fn insert(&mut self, hash: ContentHash) -> io::Result<bool> {
+		Ok(self.hashes.insert(hash))
+	}
+}
+
+
+/// A seen-set stored on disk as sorted raw 32 byte hashes.
+///
+/// Lookups binary search the file, so only the hashes inserted since the last `flush` are held in memory. `flush`
+/// merges those into the file through a temporary file next to it, leaving the old file intact if it fails.
+#[derive(Debug)]
+pub struct SortedFileSeenSet {
+	path: PathBuf,
+	file: Option<File>,
+	/// Hashes in `file`
+	stored: u64,
+	pending: HashSet<ContentHash>,
+}
+
+impl SortedFileSeenSet {
+	/// Opens the set stored at `path`, or starts an empty one if the file doesn't exist yet.
+	pub fn open<P: AsRef<Path>>(path: P) -> io::Result<SortedFileSeenSet> {
+		let path = path.as_ref().to_path_buf();
+		let (file, stored) = match File::open(&path) {
+			Ok(file) => {
+				let len = file.metadata()?.len();
+				if len % 32 != 0 {
+					return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} isn't a seen-set: its size isn't a multiple of 32", path.display())));
+				}
+				(Some(file), len / 32)
+			},
+			Err(e) if e.kind() == io::ErrorKind::NotFound => (None, 0),
+			Err(e) => return Err(e),
+		};
+
+		Ok(SortedFileSeenSet {
+			path,
+			file,
+			stored,
+			pending: HashSet::new(),
+		})
+	}
+
+	/// Number of hashes in the set, flushed or not.
+	pub fn len(&self) -> u64 {
+		self.stored + self.pending.len() as u64
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	pub fn contains(&mut self, hash: &ContentHash) -> io::Result<bool> {
+		Ok(self.pending.contains(hash) || self.stored_contains(hash)?)
+	}
+
+	fn stored_contains(&mut self, hash: &ContentHash) -> io::Result<bool> {
+		let Some(file) = &mut self.file else {
+			return Ok(false);
+		};
+		let (mut low, mut high) = (0, self.stored);
+		let mut entry = [0; 32];
+		while low < high {
+			let mid = low + (high - low) / 2;
+			file.seek(SeekFrom::Start(mid * 32))?;
+			file.read_exact(&mut entry)?;
+			match entry.cmp(&hash.0) {
+				std::cmp::Ordering::Less => low = mid + 1,
+				std::cmp::Ordering::Greater => high = mid,
+				std::cmp::Ordering::Equal => return Ok(true),
+			}
+		}
+		Ok(false)
+	}
+}
+
+impl SeenSet for SortedFileSeenSet {
+	fn insert(&mut self, hash: ContentHash) -> io::Result<bool> {
+		if self.contains(&hash)? {
+			return Ok(false);
+		}
+		self.pending.insert(hash);
+		Ok(true)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		if self.pending.is_empty() {
+			return Ok(());
+		}
+		let mut pending: Vec<ContentHash> = self.pending.iter().copied().collect();
+		pending.sort_unstable();
+
+		let mut tmp_name = self.path.file_name().unwrap_or_default().to_os_string();
+		tmp_name.push(".tmp");
+		let tmp_path = self.path.with_file_name(tmp_name);
+		let mut out = BufWriter::new(File::create(&tmp_path)?);
+		let mut pending = pending.into_iter().peekable();
+		if let Some(file) = &self.file {
+			let mut stored = BufReader::new(file);
+			stored.seek(SeekFrom::Start(0))?;
+			let mut entry = [0; 32];
+			for _ in 0..self.stored {
+				stored.read_exact(&mut entry)?;
+				while let Some(hash) = pending.next_if(|hash| hash.0 < entry) {
+					out.write_all(&hash.0)?;
+				}
+				out.write_all(&entry)?;
+			}
+		}
+		for hash in pending {
+			out.write_all(&hash.0)?;
+		}
+		out.into_inner().map_err(io::IntoInnerError::into_error)?.sync_all()?;
+		// Closed first, since Windows can't replace a file that's open
+		self.file = None;
+		std::fs::rename(&tmp_path, &self.path)?;
+
+		self.file = Some(File::open(&self.path)?);
+		self.stored += self.pending.len() as u64;
+		self.pending.clear();
+		Ok(())
+	}
+}
@@ -0,0 +1,102 @@
+//! Stopping batch work cleanly on SIGTERM and SIGINT, as sent by systemd, Kubernetes or Ctrl-C.
+//!
+//! The handlers only set a flag, which `ShutdownHandle::on_signals` handles see. Batch loops, and `BatchDecoder`s
+//! given such a handle, check it before taking new work, finish what's in flight, then flush their outputs as they
+//! would at the end of a run. A second signal exits immediately.
+
+use std::{
+	io,
+	sync::{
+		Arc,
+		atomic::{AtomicBool, Ordering},
+	},
+};
+
+use crate::cancel::CancelToken;
+
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+
+/// Whether shutdown has been requested, by a signal or `request`.
+pub fn requested() -> bool {
+	REQUESTED.load(Ordering::Relaxed)
+}
+
+
+pub fn request() {
+	REQUESTED.store(true, Ordering::Relaxed);
+}
+
+
+/// A request to shut down one pipeline, such as a `BatchDecoder` (see `BatchDecoder::shutdown`) or a loop of one's
+/// own. Once it's requested, the pipeline takes no new work, and either finishes what's in flight (`drain`) or cancels
+/// it through its `CancelToken` (`cancel`). Clones share the request.
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownHandle {
+	draining: Arc<AtomicBool>,
+	cancel: CancelToken,
+	/// Whether SIGTERM and SIGINT request a drain too
+	signals: bool,
+}
+
+impl ShutdownHandle {
+	pub fn new() -> ShutdownHandle {
+		ShutdownHandle::default()
+	}
+
+	/// A handle that SIGTERM and SIGINT also drain, once `install_handlers` has installed the handlers.
+	pub fn on_signals() -> ShutdownHandle {
+		ShutdownHandle {
+			signals: true,
+			..ShutdownHandle::default()
+		}
+	}
+
+	/// Stops taking new work, and lets what's in flight finish.
+	pub fn drain(&self) {
+		self.draining.store(true, Ordering::Relaxed);
+	}
+
+	/// Stops taking new work, and cancels what's in flight, which fails with `Error::Cancelled`.
+	pub fn cancel(&self) {
+		self.cancel.cancel();
+	}
+
+	/// Whether a drain or cancel has been requested, directly or by a signal.
+	pub fn is_requested(&self) -> bool {
+		self.draining.load(Ordering::Relaxed) || self.cancel.is_cancelled() || (self.signals && requested())
+	}
+
+	/// The token `cancel` cancels, for work in flight to check.
+	pub fn cancel_token(&self) -> &CancelToken {
+		&self.cancel
+	}
+}
+
+
+/// Installs SIGTERM and SIGINT handlers that `request` shutdown. Does nothing on platforms without signals.
+pub fn install_handlers() -> io::Result<()> {
+	#[cfg(unix)]
+	for signal in [libc::SIGTERM, libc::SIGINT] {
+		// SAFETY: `handle` only touches an atomic and calls `_exit`, both async-signal-safe
+		unsafe {
+			let mut action: libc::sigaction = std::mem::zeroed();
+			action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+			libc::sigemptyset(&mut action.sa_mask);
+			if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+				return Err(io::Error::last_os_error());
+			}
+		}
+	}
+	Ok(())
+}
+
+
+#[cfg(unix)]
+extern "C" fn handle(signal: libc::c_int) {
+	if REQUESTED.swap(true, Ordering::Relaxed) {
+		// SAFETY: `_exit` is async-signal-safe
+		unsafe { libc::_exit(128 + signal) };
+	}
+}
@@ -0,0 +1,114 @@
+use std::{
+	io::{BufRead, Seek},
+	sync::{Arc, LazyLock},
+};
+
+use image::{
+	ColorType, ImageDecoder, ImageResult, Limits,
+	error::{DecodingError, ImageFormatHint, LimitError, LimitErrorKind},
+};
+use resvg::{tiny_skia, usvg};
+
+use crate::{error::Error, options::SvgOptions};
+
+
+/// System fonts for `<text>`, loaded once since scanning the font directories takes far longer than most renders.
+static FONTS: LazyLock<Arc<usvg::fontdb::Database>> = LazyLock::new(|| {
+	let mut fonts = usvg::fontdb::Database::new();
+	fonts.load_system_fonts();
+	Arc::new(fonts)
+});
+
+
+/// SVG rasterizer built on resvg, producing 8-bit RGBA (not premultiplied).
+///
+/// The size comes from `SvgOptions`: the document's own size scaled to the requested DPI, or fit inside a target
+/// size keeping the aspect ratio. External resources (linked images, web fonts) are never fetched.
+pub struct SvgDecoder {
+	width: u32,
+	height: u32,
+	pixels: Vec<u8>,
+	limits: Limits,
+}
+
+
+impl SvgDecoder {
+	pub fn new<R: BufRead + Seek>(r: R) -> Result<SvgDecoder, Error> {
+		Self::with_options(r, &SvgOptions::default(), Limits::no_limits())
+	}
+
+	pub fn with_options<R: BufRead + Seek>(mut r: R, svg_options: &SvgOptions, limits: Limits) -> Result<SvgDecoder, Error> {
+		limits.check_support(&image::LimitSupport::default())?;
+
+		let mut input = Vec::new();
+		r.read_to_end(&mut input)?;
+		let options = usvg::Options {
+			fontdb: FONTS.clone(),
+			..Default::default()
+		};
+		let tree = usvg::Tree::from_data(&input, &options).map_err(|err| Error::Decoding(DecodingError::new(svg_hint(), err)))?;
+
+		let size = tree.size();
+		let scale = match svg_options.size {
+			Some((width, height)) => (width as f32 / size.width()).min(height as f32 / size.height()),
+			None => svg_options.dpi as f32 / 96.0,
+		};
+		let width = (size.width() * scale).round().max(1.0) as u32;
+		let height = (size.height() * scale).round().max(1.0) as u32;
+		// Check before rendering, since documents can declare any size they like
+		limits.check_dimensions(width, height)?;
+
+		let mut pixmap = tiny_skia::Pixmap::new(width, height).ok_or(Error::Limits(LimitError::from_kind(LimitErrorKind::DimensionError)))?;
+		resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+		let pixels = pixmap
+			.pixels()
+			.iter()
+			.flat_map(|pixel| {
+				let color = pixel.demultiply();
+				[color.red(), color.green(), color.blue(), color.alpha()]
+			})
+			.collect();
+
+		let mut decoder = SvgDecoder {
+			width,
+			height,
+			pixels,
+			limits: Limits::no_limits(),
+		};
+		decoder.set_limits(limits)?;
+		Ok(decoder)
+	}
+}
+
+
+impl ImageDecoder for SvgDecoder {
+	fn dimensions(&self) -> (u32, u32) {
+		(self.width, self.height)
+	}
+
+	fn color_type(&self) -> ColorType {
+		ColorType::Rgba8
+	}
+
+	fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+		crate::error::check_buffer(buf, self.total_bytes())?;
+		buf.copy_from_slice(&self.pixels);
+		Ok(())
+	}
+
+	fn read_image_boxed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+		(*self).read_image(buf)
+	}
+
+	fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+		limits.check_support(&image::LimitSupport::default())?;
+		limits.check_dimensions(self.width, self.height)?;
+		self.limits = limits;
+		Ok(())
+	}
+}
+
+
+fn svg_hint() -> ImageFormatHint {
+	ImageFormatHint::Name("SVG".to_string())
+}
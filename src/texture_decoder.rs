@@ -0,0 +1,316 @@
+use std::io::{BufRead, Seek, SeekFrom};
+
+use image::{
+	ColorType, ImageDecoder, ImageError, ImageFormat, ImageResult, Limits,
+	error::{DecodingError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind},
+};
+
+use crate::{
+	Format,
+	error::Error,
+	format::KTX2_IDENTIFIER,
+};
+
+
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+const DDS_FOURCC_DX10: &[u8; 4] = b"DX10";
+/// DDS_PIXELFORMAT flags
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+
+
+/// texture2ddecoder's block decoders, taking the data, width and height
+type BlockDecodeFn = fn(&[u8], usize, usize, &mut [u32]) -> Result<(), &'static str>;
+
+
+/// How the texels of a texture are stored. Only the encodings common in game assets are covered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TextureEncoding {
+	/// Uncompressed 8-bit RGBA, with the byte offset of each of R, G and B and optionally A within a texel of
+	/// `bytes_per_texel` bytes.
+	Uncompressed { bytes_per_texel: u8, offsets: [u8; 3], alpha: Option<u8> },
+	Bc1,
+	Bc2,
+	Bc3,
+	/// Single channel, returned as gray
+	Bc4,
+	/// Two channels, returned as red and green
+	Bc5,
+	Bc7,
+	Etc2Rgb,
+	Etc2Rgba1,
+	Etc2Rgba8,
+}
+
+impl TextureEncoding {
+	/// Bytes per 4x4 block, or per texel if uncompressed, and the block size in texels.
+	fn block(self) -> (usize, usize) {
+		match self {
+			TextureEncoding::Uncompressed { bytes_per_texel, .. } => (usize::from(bytes_per_texel), 1),
+			TextureEncoding::Bc1 | TextureEncoding::Bc4 | TextureEncoding::Etc2Rgb | TextureEncoding::Etc2Rgba1 => (8, 4),
+			TextureEncoding::Bc2 | TextureEncoding::Bc3 | TextureEncoding::Bc5 | TextureEncoding::Bc7 | TextureEncoding::Etc2Rgba8 => (16, 4),
+		}
+	}
+}
+
+
+/// Decoder for DDS and KTX2 textures, block compressed (BC1-5, BC7, ETC2) or uncompressed 8-bit RGBA.
+///
+/// Only the first (largest) mip level of the first layer or face is decoded; textures are always returned as RGBA8.
+/// Volume textures, supercompressed KTX2 (Basis Universal, Zstandard) and the remaining encodings are unsupported.
+pub struct TextureDecoder {
+	container: Format,
+	width: u32,
+	height: u32,
+	encoding: TextureEncoding,
+	data: Vec<u8>,
+	limits: Limits,
+}
+
+
+impl TextureDecoder {
+	pub fn new<R: BufRead + Seek>(r: R) -> Result<TextureDecoder, Error> {
+		Self::with_limits(r, Limits::no_limits())
+	}
+
+	pub fn with_limits<R: BufRead + Seek>(mut r: R, limits: Limits) -> Result<TextureDecoder, Error> {
+		limits.check_support(&image::LimitSupport::default())?;
+
+		let mut magic = [0; 12];
+		r.read_exact(&mut magic)?;
+		r.rewind()?;
+		let (container, width, height, encoding, offset) = if &magic == KTX2_IDENTIFIER {
+			let (width, height, encoding, offset) = read_ktx2_header(&mut r)?;
+			(Format::Ktx2, width, height, encoding, offset)
+		} else if magic.starts_with(DDS_MAGIC) {
+			let (width, height, encoding, offset) = read_dds_header(&mut r)?;
+			(Format::Image(ImageFormat::Dds), width, height, encoding, offset)
+		} else {
+			return Err(Error::UnsupportedFormat);
+		};
+		limits.check_dimensions(width, height)?;
+
+		let (block_bytes, block_size) = encoding.block();
+		let blocks = (width as usize).div_ceil(block_size) * (height as usize).div_ceil(block_size);
+		let mut data = vec![0; blocks * block_bytes];
+		r.seek(SeekFrom::Start(offset))?;
+		r.read_exact(&mut data)?;
+
+		let mut decoder = TextureDecoder {
+			container,
+			width,
+			height,
+			encoding,
+			data,
+			limits: Limits::no_limits(),
+		};
+		decoder.set_limits(limits)?;
+		Ok(decoder)
+	}
+
+	/// `Format::Image(ImageFormat::Dds)` or `Format::Ktx2`.
+	pub fn container(&self) -> Format {
+		self.container
+	}
+
+	pub fn encoding(&self) -> TextureEncoding {
+		self.encoding
+	}
+}
+
+
+impl ImageDecoder for TextureDecoder {
+	fn dimensions(&self) -> (u32, u32) {
+		(self.width, self.height)
+	}
+
+	fn color_type(&self) -> ColorType {
+		ColorType::Rgba8
+	}
+
+	fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+		crate::error::check_buffer(buf, self.total_bytes())?;
+		let (width, height) = (self.width as usize, self.height as usize);
+
+		if let TextureEncoding::Uncompressed { bytes_per_texel, offsets, alpha } = self.encoding {
+			for (texel, out) in self.data.chunks_exact(usize::from(bytes_per_texel)).zip(buf.chunks_exact_mut(4)) {
+				out[0] = texel[usize::from(offsets[0])];
+				out[1] = texel[usize::from(offsets[1])];
+				out[2] = texel[usize::from(offsets[2])];
+				out[3] = alpha.map_or(255, |a| texel[usize::from(a)]);
+			}
+			return Ok(());
+		}
+
+		// texture2ddecoder writes each texel as a u32 of 0xAARRGGBB
+		let mut texels = vec![0u32; width * height];
+		let decode: BlockDecodeFn = match self.encoding {
+			TextureEncoding::Bc1 => texture2ddecoder::decode_bc1,
+			TextureEncoding::Bc2 => texture2ddecoder::decode_bc2,
+			TextureEncoding::Bc3 => texture2ddecoder::decode_bc3,
+			TextureEncoding::Bc4 => texture2ddecoder::decode_bc4,
+			TextureEncoding::Bc5 => texture2ddecoder::decode_bc5,
+			TextureEncoding::Bc7 => texture2ddecoder::decode_bc7,
+			TextureEncoding::Etc2Rgb => texture2ddecoder::decode_etc2_rgb,
+			TextureEncoding::Etc2Rgba1 => texture2ddecoder::decode_etc2_rgba1,
+			TextureEncoding::Etc2Rgba8 => texture2ddecoder::decode_etc2_rgba8,
+			TextureEncoding::Uncompressed { .. } => unreachable!(),
+		};
+		decode(&self.data, width, height, &mut texels).map_err(|err| ImageError::Decoding(DecodingError::new(container_hint(self.container), err)))?;
+
+		for (&texel, out) in texels.iter().zip(buf.chunks_exact_mut(4)) {
+			let [b, g, r, a] = texel.to_le_bytes();
+			let rgba = match self.encoding {
+				TextureEncoding::Bc4 => [r, r, r, 255],
+				TextureEncoding::Bc5 => [r, g, 0, 255],
+				_ => [r, g, b, a],
+			};
+			out.copy_from_slice(&rgba);
+		}
+		Ok(())
+	}
+
+	fn read_image_boxed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+		(*self).read_image(buf)
+	}
+
+	fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+		limits.check_support(&image::LimitSupport::default())?;
+		limits.check_dimensions(self.width, self.height)?;
+		self.limits = limits;
+		Ok(())
+	}
+}
+
+
+/// Returns (width, height, encoding, offset of the first texel) for a DDS file, from its header and DX10 extension.
+fn read_dds_header<R: BufRead>(r: &mut R) -> Result<(u32, u32, TextureEncoding, u64), Error> {
+	let hint = container_hint(Format::Image(ImageFormat::Dds));
+	let mut header = [0; 128];
+	r.read_exact(&mut header)?;
+	let u32_at = |buf: &[u8], offset: usize| u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap());
+
+	let (height, width, depth) = (u32_at(&header, 12), u32_at(&header, 16), u32_at(&header, 24));
+	if depth > 1 {
+		return Err(unsupported(hint, "volume textures"));
+	}
+	let pf_flags = u32_at(&header, 80);
+	let fourcc = &header[84..88];
+
+	if pf_flags & DDPF_FOURCC != 0 && fourcc == DDS_FOURCC_DX10 {
+		let mut dx10 = [0; 20];
+		r.read_exact(&mut dx10)?;
+		let dxgi_format = u32_at(&dx10, 0);
+		let encoding = match dxgi_format {
+			// R8G8B8A8_UNORM(_SRGB)
+			28 | 29 => uncompressed(4, [0, 1, 2], Some(3)),
+			// B8G8R8A8_UNORM(_SRGB), B8G8R8X8_UNORM(_SRGB)
+			87 | 91 => uncompressed(4, [2, 1, 0], Some(3)),
+			88 | 93 => uncompressed(4, [2, 1, 0], None),
+			71 | 72 => TextureEncoding::Bc1,
+			74 | 75 => TextureEncoding::Bc2,
+			77 | 78 => TextureEncoding::Bc3,
+			80 => TextureEncoding::Bc4,
+			83 => TextureEncoding::Bc5,
+			98 | 99 => TextureEncoding::Bc7,
+			_ => return Err(unsupported(hint, &format!("DXGI format {dxgi_format}"))),
+		};
+		return Ok((width, height, encoding, 148));
+	}
+
+	let encoding = if pf_flags & DDPF_FOURCC != 0 {
+		match fourcc {
+			b"DXT1" => TextureEncoding::Bc1,
+			b"DXT2" | b"DXT3" => TextureEncoding::Bc2,
+			b"DXT4" | b"DXT5" => TextureEncoding::Bc3,
+			b"ATI1" | b"BC4U" => TextureEncoding::Bc4,
+			b"ATI2" | b"BC5U" => TextureEncoding::Bc5,
+			_ => return Err(unsupported(hint, &format!("FourCC {:?}", String::from_utf8_lossy(fourcc)))),
+		}
+	} else if pf_flags & DDPF_RGB != 0 {
+		// Only byte aligned 8-bit channels; 16-bit packed formats like R5G6B5 aren't covered
+		let bit_count = u32_at(&header, 88);
+		let bytes_per_texel = (bit_count / 8) as u8;
+		let byte_offset = |offset: usize| {
+			let mask = u32_at(&header, offset);
+			let shift = mask.trailing_zeros();
+			(mask != 0 && shift % 8 == 0 && mask >> shift == 0xFF && shift / 8 < u32::from(bytes_per_texel)).then_some((shift / 8) as u8)
+		};
+		let offsets = [byte_offset(92), byte_offset(96), byte_offset(100)];
+		let alpha = if pf_flags & DDPF_ALPHAPIXELS != 0 { byte_offset(104) } else { None };
+		match (bit_count, offsets) {
+			(24 | 32, [Some(r), Some(g), Some(b)]) if pf_flags & DDPF_ALPHAPIXELS == 0 || alpha.is_some() => uncompressed(bytes_per_texel, [r, g, b], alpha),
+			_ => return Err(unsupported(hint, &format!("{bit_count}-bit uncompressed pixel format"))),
+		}
+	} else {
+		return Err(unsupported(hint, "pixel format"));
+	};
+	Ok((width, height, encoding, 128))
+}
+
+
+/// Returns (width, height, encoding, offset of the first texel) for a KTX2 file, from its header and level index.
+fn read_ktx2_header<R: BufRead>(r: &mut R) -> Result<(u32, u32, TextureEncoding, u64), Error> {
+	let hint = container_hint(Format::Ktx2);
+	// Identifier, header and the first level index entry
+	let mut header = [0; 104];
+	r.read_exact(&mut header)?;
+	let u32_at = |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+
+	let vk_format = u32_at(12);
+	let (width, height, depth) = (u32_at(20), u32_at(24), u32_at(28));
+	let supercompression = u32_at(44);
+	if depth > 1 {
+		return Err(unsupported(hint, "volume textures"));
+	}
+	if supercompression != 0 {
+		return Err(unsupported(hint, &format!("supercompression scheme {supercompression}")));
+	}
+	// A height of zero is a 1D texture
+	let height = height.max(1);
+
+	let encoding = match vk_format {
+		// VK_FORMAT_R8G8B8A8_UNORM/_SRGB, B8G8R8A8_UNORM/_SRGB
+		37 | 43 => uncompressed(4, [0, 1, 2], Some(3)),
+		44 | 50 => uncompressed(4, [2, 1, 0], Some(3)),
+		// VK_FORMAT_BC1_RGB_* and BC1_RGBA_*, which decode the same
+		131..=134 => TextureEncoding::Bc1,
+		135 | 136 => TextureEncoding::Bc2,
+		137 | 138 => TextureEncoding::Bc3,
+		139 => TextureEncoding::Bc4,
+		141 => TextureEncoding::Bc5,
+		145 | 146 => TextureEncoding::Bc7,
+		147 | 148 => TextureEncoding::Etc2Rgb,
+		149 | 150 => TextureEncoding::Etc2Rgba1,
+		151 | 152 => TextureEncoding::Etc2Rgba8,
+		0 => return Err(unsupported(hint, "VK_FORMAT_UNDEFINED without supercompression")),
+		_ => return Err(unsupported(hint, &format!("VkFormat {vk_format}"))),
+	};
+
+	// The level index starts with the largest level, whose first image is the first layer or face
+	let level_offset = u64::from_le_bytes(header[80..88].try_into().unwrap());
+	if level_offset == 0 {
+		return Err(Error::Decoding(DecodingError::new(hint, "missing level data")));
+	}
+	Ok((width, height, encoding, level_offset))
+}
+
+
+fn uncompressed(bytes_per_texel: u8, offsets: [u8; 3], alpha: Option<u8>) -> TextureEncoding {
+	TextureEncoding::Uncompressed { bytes_per_texel, offsets, alpha }
+}
+
+
+fn container_hint(container: Format) -> ImageFormatHint {
+	match container {
+		Format::Image(format) => format.into(),
+		_ => ImageFormatHint::Name("KTX2".to_string()),
+	}
+}
+
+
+fn unsupported(hint: ImageFormatHint, what: &str) -> Error {
+	Error::Unsupported(UnsupportedError::from_format_and_kind(hint, UnsupportedErrorKind::GenericFeature(what.to_string())))
+}
@@ -0,0 +1,353 @@
+use std::io::{BufRead, Read, Seek};
+
+use image::{
+	ColorType, ImageDecoder, ImageError, ImageFormat, ImageResult, Limits,
+	error::{DecodingError, LimitError, LimitErrorKind, UnsupportedError, UnsupportedErrorKind},
+};
+use tiff::decoder::DecodingResult;
+
+use crate::{convert, error::Error};
+
+
+const ICC_PROFILE_TAG: u16 = 34675;
+const SAMPLE_FORMAT_IEEEFP: u16 = 3;
+
+const MODEL_PIXEL_SCALE_TAG: u16 = 33550;
+const MODEL_TIEPOINT_TAG: u16 = 33922;
+const MODEL_TRANSFORMATION_TAG: u16 = 34264;
+const GEO_KEY_DIRECTORY_TAG: u16 = 34735;
+const GEO_DOUBLE_PARAMS_TAG: u16 = 34736;
+const GEO_ASCII_PARAMS_TAG: u16 = 34737;
+
+
+/// TIFF decoder for the first page (IFD) of a file.
+///
+/// LZW, Deflate and PackBits compression are handled by the tiff crate. 16-bit samples stay 16-bit, bilevel and other
+/// sub-byte grayscale images (as produced by document scanners) are unpacked to L8, and 8-bit CMYK is converted to
+/// RGB the same naive way as `image`. 32 and 64-bit float samples decode to `Rgb32F` (gray is expanded) or
+/// `Rgba32F`.
+///
+/// Multi-band images, such as satellite imagery with near infrared or other bands beyond RGB, decode to their first
+/// three bands (or first band, if there are fewer than three); `dropped_bands` says how many were left out and
+/// `read_bands` returns all of them.
+pub struct TiffDecoder<R: BufRead + Seek> {
+	inner: tiff::decoder::Decoder<R>,
+	width: u32,
+	height: u32,
+	color_type: ColorType,
+	source: tiff::ColorType,
+	samples: TiffSamples,
+	geotiff: Option<GeoTiffTags>,
+	multi_page: bool,
+	limits: Limits,
+}
+
+
+/// How a TIFF's samples are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TiffSamples {
+	/// Samples per pixel
+	pub bands: u16,
+	pub bits_per_sample: u8,
+	/// IEEE floating point samples, rather than unsigned integers
+	pub float: bool,
+}
+
+
+/// The GeoTIFF tags of an image, passed through as stored so georeferencing survives decoding. Interpreting them
+/// (the GeoKey directory especially) is left to a geospatial library.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GeoTiffTags {
+	/// ModelPixelScaleTag: the (x, y, z) size of a pixel in model space
+	pub model_pixel_scale: Option<Vec<f64>>,
+	/// ModelTiepointTag: (i, j, k, x, y, z) tuples tying raster points to model space
+	pub model_tiepoint: Option<Vec<f64>>,
+	/// ModelTransformationTag: a 4x4 row-major raster to model space matrix, instead of scale and tiepoints
+	pub model_transformation: Option<Vec<f64>>,
+	/// GeoKeyDirectoryTag: the header and key entries, as shorts
+	pub geo_key_directory: Option<Vec<u16>>,
+	/// GeoDoubleParamsTag: double valued keys, which the directory points into
+	pub geo_double_params: Option<Vec<f64>>,
+	/// GeoAsciiParamsTag: `|` terminated string valued keys, which the directory points into
+	pub geo_ascii_params: Option<String>,
+}
+
+impl GeoTiffTags {
+	fn read<R: Read + Seek>(inner: &mut tiff::decoder::Decoder<R>) -> Result<Option<GeoTiffTags>, Error> {
+		let mut find = |tag: u16| inner.find_tag(tiff::tags::Tag::from_u16_exhaustive(tag)).map_err(error_from_tiff);
+		let doubles = |value: Option<tiff::decoder::ifd::Value>| value.map(|v| v.into_f64_vec()).transpose().map_err(error_from_tiff);
+		let tags = GeoTiffTags {
+			model_pixel_scale: doubles(find(MODEL_PIXEL_SCALE_TAG)?)?,
+			model_tiepoint: doubles(find(MODEL_TIEPOINT_TAG)?)?,
+			model_transformation: doubles(find(MODEL_TRANSFORMATION_TAG)?)?,
+			geo_key_directory: find(GEO_KEY_DIRECTORY_TAG)?.map(|v| v.into_u16_vec()).transpose().map_err(error_from_tiff)?,
+			geo_double_params: doubles(find(GEO_DOUBLE_PARAMS_TAG)?)?,
+			geo_ascii_params: find(GEO_ASCII_PARAMS_TAG)?.map(|v| v.into_string()).transpose().map_err(error_from_tiff)?,
+		};
+		Ok(Some(tags).filter(|tags| *tags != GeoTiffTags::default()))
+	}
+}
+
+
+/// Every band of an image, interleaved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TiffBands {
+	pub width: u32,
+	pub height: u32,
+	pub bands: u16,
+	/// `width * height * bands` samples, converted to f32. Integer samples of up to 16 bits and 32-bit floats are
+	/// exact; wider samples are rounded.
+	pub data: Vec<f32>,
+}
+
+
+impl<R: BufRead + Seek> TiffDecoder<R> {
+	pub fn new(r: R) -> Result<TiffDecoder<R>, Error> {
+		Self::with_limits(r, Limits::no_limits())
+	}
+
+	pub fn with_limits(r: R, limits: Limits) -> Result<TiffDecoder<R>, Error> {
+		limits.check_support(&image::LimitSupport::default())?;
+
+		let mut tiff_limits = tiff::decoder::Limits::default();
+		if let Some(max_alloc) = limits.max_alloc {
+			tiff_limits.decoding_buffer_size = usize::try_from(max_alloc).unwrap_or(usize::MAX);
+		}
+		let mut inner = tiff::decoder::Decoder::new(r).map_err(error_from_tiff)?.with_limits(tiff_limits);
+		let (width, height) = inner.dimensions().map_err(error_from_tiff)?;
+		let source = inner.colortype().map_err(error_from_tiff)?;
+		let float = inner
+			.find_tag_unsigned_vec::<u16>(tiff::tags::Tag::SampleFormat)
+			.map_err(error_from_tiff)?
+			.is_some_and(|formats| formats.contains(&SAMPLE_FORMAT_IEEEFP));
+		let (bands, bits_per_sample) = match source {
+			tiff::ColorType::Gray(bits) => (1, bits),
+			tiff::ColorType::GrayA(bits) => (2, bits),
+			tiff::ColorType::RGB(bits) | tiff::ColorType::YCbCr(bits) => (3, bits),
+			tiff::ColorType::RGBA(bits) | tiff::ColorType::CMYK(bits) => (4, bits),
+			tiff::ColorType::CMYKA(bits) => (5, bits),
+			tiff::ColorType::Multiband { bit_depth, num_samples } => (num_samples, bit_depth),
+			tiff::ColorType::Palette(bits) => (1, bits),
+			other => return Err(unsupported(format!("{other:?}"))),
+		};
+		let samples = TiffSamples {
+			bands,
+			bits_per_sample,
+			float,
+		};
+
+		let color_type = match source {
+			_ if float => match (source, bits_per_sample) {
+				(tiff::ColorType::GrayA(_) | tiff::ColorType::RGBA(_), 32 | 64) => ColorType::Rgba32F,
+				(tiff::ColorType::Gray(_) | tiff::ColorType::RGB(_) | tiff::ColorType::Multiband { .. }, 32 | 64) => ColorType::Rgb32F,
+				_ => return Err(unsupported(format!("{bits_per_sample}-bit float {source:?}"))),
+			},
+			tiff::ColorType::Multiband { bit_depth: 8, num_samples } => if num_samples >= 3 { ColorType::Rgb8 } else { ColorType::L8 },
+			tiff::ColorType::Multiband { bit_depth: 16, num_samples } => if num_samples >= 3 { ColorType::Rgb16 } else { ColorType::L16 },
+			tiff::ColorType::Gray(1 | 2 | 4 | 8) => ColorType::L8,
+			tiff::ColorType::Gray(16) => ColorType::L16,
+			tiff::ColorType::GrayA(8) => ColorType::La8,
+			tiff::ColorType::GrayA(16) => ColorType::La16,
+			tiff::ColorType::RGB(8) | tiff::ColorType::CMYK(8) => ColorType::Rgb8,
+			tiff::ColorType::RGB(16) => ColorType::Rgb16,
+			tiff::ColorType::RGBA(8) => ColorType::Rgba8,
+			tiff::ColorType::RGBA(16) => ColorType::Rgba16,
+			other => return Err(unsupported(format!("{other:?}"))),
+		};
+		let geotiff = GeoTiffTags::read(&mut inner)?;
+		let multi_page = inner.more_images();
+
+		let mut decoder = TiffDecoder {
+			inner,
+			width,
+			height,
+			color_type,
+			source,
+			samples,
+			geotiff,
+			multi_page,
+			limits: Limits::no_limits(),
+		};
+		decoder.set_limits(limits)?;
+		Ok(decoder)
+	}
+
+	/// Returns true if the file has more pages after the first one.
+	pub fn is_multi_page(&self) -> bool {
+		self.multi_page
+	}
+
+	/// The GeoTIFF tags of the first page, or `None` if it has none.
+	pub fn geotiff(&self) -> Option<&GeoTiffTags> {
+		self.geotiff.as_ref()
+	}
+
+	/// The bands and sample format stored in the file, which the decoded color type may not reflect.
+	pub fn samples(&self) -> TiffSamples {
+		self.samples
+	}
+
+	/// Number of bands left out of the decoded image, i.e. beyond the first three of a multi-band image. Anything
+	/// but 0 means the image returned is a partial view of the data; use `read_bands` to get all of it.
+	pub fn dropped_bands(&self) -> u16 {
+		match self.source {
+			tiff::ColorType::Multiband { .. } => self.samples.bands.saturating_sub(u16::from(self.color_type.channel_count())),
+			_ => 0,
+		}
+	}
+
+	/// Decodes every band instead of an image of the first few.
+	pub fn read_bands(mut self) -> Result<TiffBands, Error> {
+		let data: Vec<f32> = match self.inner.read_image().map_err(error_from_tiff)? {
+			DecodingResult::U8(data) => data.into_iter().map(f32::from).collect(),
+			DecodingResult::U16(data) => data.into_iter().map(f32::from).collect(),
+			DecodingResult::U32(data) => data.into_iter().map(|v| v as f32).collect(),
+			DecodingResult::U64(data) => data.into_iter().map(|v| v as f32).collect(),
+			DecodingResult::I8(data) => data.into_iter().map(f32::from).collect(),
+			DecodingResult::I16(data) => data.into_iter().map(f32::from).collect(),
+			DecodingResult::I32(data) => data.into_iter().map(|v| v as f32).collect(),
+			DecodingResult::I64(data) => data.into_iter().map(|v| v as f32).collect(),
+			DecodingResult::F32(data) => data,
+			DecodingResult::F64(data) => data.into_iter().map(|v| v as f32).collect(),
+			#[allow(unreachable_patterns)]
+			_ => return Err(unsupported("sample format".to_string())),
+		};
+		let bands = self.samples.bands;
+		if data.len() < self.width as usize * self.height as usize * usize::from(bands) {
+			return Err(Error::Decoding(DecodingError::new(ImageFormat::Tiff.into(), "fewer samples than the image dimensions need")));
+		}
+
+		Ok(TiffBands {
+			width: self.width,
+			height: self.height,
+			bands,
+			data,
+		})
+	}
+}
+
+
+impl<R: BufRead + Seek> ImageDecoder for TiffDecoder<R> {
+	fn dimensions(&self) -> (u32, u32) {
+		(self.width, self.height)
+	}
+
+	fn color_type(&self) -> ColorType {
+		self.color_type
+	}
+
+	fn icc_profile(&mut self) -> ImageResult<Option<Vec<u8>>> {
+		Ok(self.inner.get_tag_u8_vec(tiff::tags::Tag::Unknown(ICC_PROFILE_TAG)).ok())
+	}
+
+	fn read_image(mut self, buf: &mut [u8]) -> ImageResult<()> {
+		crate::error::check_buffer(buf, self.total_bytes())?;
+
+		let (bands, channels) = (usize::from(self.samples.bands), usize::from(self.color_type.channel_count()));
+		match (self.inner.read_image().map_err(error_from_tiff)?, self.source) {
+			(DecodingResult::F32(data), _) => {
+				for (out, sample) in buf.chunks_exact_mut(4).zip(select_bands(&data, bands, channels)) {
+					out.copy_from_slice(&sample.to_ne_bytes());
+				}
+			},
+			(DecodingResult::F64(data), _) => {
+				for (out, sample) in buf.chunks_exact_mut(4).zip(select_bands(&data, bands, channels)) {
+					out.copy_from_slice(&(sample as f32).to_ne_bytes());
+				}
+			},
+			(DecodingResult::U8(data), tiff::ColorType::Multiband { .. }) => {
+				for (out, sample) in buf.iter_mut().zip(select_bands(&data, bands, channels)) {
+					*out = sample;
+				}
+			},
+			(DecodingResult::U16(data), tiff::ColorType::Multiband { .. }) => {
+				for (out, sample) in buf.chunks_exact_mut(2).zip(select_bands(&data, bands, channels)) {
+					out.copy_from_slice(&sample.to_ne_bytes());
+				}
+			},
+			(DecodingResult::U8(data), tiff::ColorType::Gray(bits @ (1 | 2 | 4))) => unpack_gray(&data, bits, self.width as usize, buf),
+			(DecodingResult::U8(data), tiff::ColorType::CMYK(_)) => convert::cmyk_to_rgb(&data, false, buf),
+			(DecodingResult::U8(data), _) => buf.copy_from_slice(&data[..buf.len()]),
+			(DecodingResult::U16(data), _) => {
+				for (out, sample) in buf.chunks_exact_mut(2).zip(data) {
+					out.copy_from_slice(&sample.to_ne_bytes());
+				}
+			},
+			_ => {
+				return Err(ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+					ImageFormat::Tiff.into(),
+					UnsupportedErrorKind::GenericFeature("sample format".to_string()),
+				)));
+			},
+		}
+		Ok(())
+	}
+
+	fn read_image_boxed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+		(*self).read_image(buf)
+	}
+
+	fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+		limits.check_support(&image::LimitSupport::default())?;
+		limits.check_dimensions(self.width, self.height)?;
+		self.limits = limits;
+		Ok(())
+	}
+}
+
+
+/// Dimensions of every page (top level IFD) of a TIFF, in order, reading only the directories.
+pub(crate) fn page_dimensions<R: Read + Seek>(r: R) -> Result<Vec<(u32, u32)>, Error> {
+	let mut inner = tiff::decoder::Decoder::new(r).map_err(error_from_tiff)?;
+	let mut pages = vec![inner.dimensions().map_err(error_from_tiff)?];
+	while inner.more_images() {
+		inner.next_image().map_err(error_from_tiff)?;
+		pages.push(inner.dimensions().map_err(error_from_tiff)?);
+	}
+	Ok(pages)
+}
+
+
+/// The samples of the output `channels` taken from pixels of `bands` samples: the first `channels` bands if there are
+/// enough, otherwise the first band as gray, expanded to RGB, followed by the second as alpha for RGBA output.
+fn select_bands<T: Copy>(data: &[T], bands: usize, channels: usize) -> impl Iterator<Item = T> + '_ {
+	data.chunks_exact(bands).flat_map(move |pixel| {
+		(0..channels).map(move |c| match c {
+			_ if bands >= channels => pixel[c],
+			3 => pixel[1],
+			_ => pixel[0],
+		})
+	})
+}
+
+
+fn unsupported(feature: String) -> Error {
+	Error::Unsupported(UnsupportedError::from_format_and_kind(ImageFormat::Tiff.into(), UnsupportedErrorKind::GenericFeature(feature)))
+}
+
+
+/// Unpacks sub-byte grayscale rows (each padded to a whole byte) into 8-bit samples scaled to the full range.
+fn unpack_gray(data: &[u8], bits: u8, width: usize, out: &mut [u8]) {
+	let row_bytes = (width * usize::from(bits)).div_ceil(8);
+	let max = (1u16 << bits) - 1;
+	for (row, out_row) in data.chunks(row_bytes).zip(out.chunks_exact_mut(width)) {
+		for (x, out) in out_row.iter_mut().enumerate() {
+			let bit = x * usize::from(bits);
+			let v = u16::from(row[bit / 8] >> (8 - usize::from(bits) - bit % 8)) & max;
+			*out = (v * 255 / max) as u8;
+		}
+	}
+}
+
+
+fn error_from_tiff(err: tiff::TiffError) -> ImageError {
+	match err {
+		tiff::TiffError::IoError(err) => ImageError::IoError(err),
+		tiff::TiffError::LimitsExceeded => ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory)),
+		tiff::TiffError::UnsupportedError(err) => ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+			ImageFormat::Tiff.into(),
+			UnsupportedErrorKind::GenericFeature(err.to_string()),
+		)),
+		err => ImageError::Decoding(DecodingError::new(ImageFormat::Tiff.into(), err)),
+	}
+}
@@ -0,0 +1,179 @@
+//! Versions of the decoding backends and the features this build was compiled with, and `capabilities` reporting them.
+
+use std::fmt;
+
+use image::ImageFormat;
+
+use crate::{Format, audit::json_string};
+
+
+/// The backend crates, with the versions pinned in Cargo.toml (keep in sync), and for optional ones whether they're
+/// compiled in. System libraries linked by some of them (dav1d, libheif) aren't covered.
+pub(crate) const BACKENDS: &[(&str, &str, bool)] = &[
+	("image", "0.25.9", true),
+	("zune-jpeg", "0.5.12", true),
+	("zune-core", "0.5.1", true),
+	("jpeg-decoder", "0.3.1", true),
+	("png", "0.18.0", true),
+	("gif", "0.14.1", true),
+	("tiff", "0.10.3", true),
+	("image-webp", "0.2.4", true),
+	("jxl-oxide", "0.12.2", true),
+	("dav1d", "0.10.3", cfg!(feature = "avif")),
+	("mp4parse", "0.17.0", cfg!(feature = "avif")),
+	("libheif-rs", "1.0.2", cfg!(feature = "heif")),
+	("resvg", "0.45.1", cfg!(feature = "svg")),
+	("mozjpeg", "0.10.13", cfg!(feature = "jpeg-arithmetic")),
+	("jpeg2k", "0.9.1", cfg!(feature = "jp2")),
+	("texture2ddecoder", "0.1.2", cfg!(feature = "texture")),
+	("miniz_oxide", "0.8.9", cfg!(any(feature = "pdf", feature = "office"))),
+];
+
+
+/// The Cargo features of this crate, and whether each is enabled.
+pub(crate) const FEATURES: &[(&str, bool)] = &[
+	("testing", cfg!(feature = "testing")),
+	("avif", cfg!(feature = "avif")),
+	("heif", cfg!(feature = "heif")),
+	("svg", cfg!(feature = "svg")),
+	("raw", cfg!(feature = "raw")),
+	("jpeg-arithmetic", cfg!(feature = "jpeg-arithmetic")),
+	("jp2", cfg!(feature = "jp2")),
+	("texture", cfg!(feature = "texture")),
+	("conformance", cfg!(feature = "conformance")),
+	("pdf", cfg!(feature = "pdf")),
+	("async", cfg!(feature = "async")),
+	("office", cfg!(feature = "office")),
+	("ndarray", cfg!(feature = "ndarray")),
+	("f16", cfg!(feature = "f16")),
+	("python", cfg!(feature = "python")),
+	("nvjpeg", cfg!(feature = "nvjpeg")),
+	("dlpack", cfg!(feature = "dlpack")),
+	("candle", cfg!(feature = "candle")),
+	("tch", cfg!(feature = "tch")),
+	("arrow", cfg!(feature = "arrow")),
+];
+
+
+/// The formats `load_image` decodes, and whether each is compiled in. The plain `image::ImageFormat` ones without a
+/// decoder of our own go through the `image` crate.
+const FORMATS: &[(Format, bool)] = &[
+	(Format::Image(ImageFormat::Png), true),
+	(Format::Image(ImageFormat::Jpeg), true),
+	(Format::Mpo, true),
+	(Format::Image(ImageFormat::Gif), true),
+	(Format::Image(ImageFormat::Bmp), true),
+	(Format::Image(ImageFormat::Ico), true),
+	(Format::Image(ImageFormat::WebP), true),
+	(Format::Image(ImageFormat::Tiff), true),
+	(Format::Jxl, true),
+	(Format::Image(ImageFormat::Qoi), true),
+	(Format::Image(ImageFormat::OpenExr), true),
+	(Format::Image(ImageFormat::Hdr), true),
+	(Format::Image(ImageFormat::Pnm), true),
+	(Format::Image(ImageFormat::Tga), true),
+	(Format::Image(ImageFormat::Farbfeld), true),
+	(Format::Image(ImageFormat::Avif), cfg!(feature = "avif")),
+	(Format::Heif, cfg!(feature = "heif")),
+	(Format::Svg, cfg!(feature = "svg")),
+	(Format::Jpeg2000, cfg!(feature = "jp2")),
+	(Format::Image(ImageFormat::Dds), cfg!(feature = "texture")),
+	(Format::Ktx2, cfg!(feature = "texture")),
+	(Format::RawPreview, cfg!(feature = "raw")),
+	(Format::Pdf, cfg!(feature = "pdf")),
+];
+
+
+/// What this build of imgest can decode and with what, for checking that every worker in a fleet runs an identical
+/// decode stack. Two workers whose `Capabilities` compare equal decode the same inputs with the same code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+	/// The imgest crate version
+	pub version: &'static str,
+	/// The formats `load_image` decodes in this build
+	pub formats: Vec<Format>,
+	/// The backend crates compiled in, as (name, version)
+	pub backends: Vec<(&'static str, &'static str)>,
+	/// The Cargo features enabled
+	pub features: Vec<&'static str>,
+	/// The SIMD instruction sets detected on this CPU that the backends have code paths for (zune-jpeg's AVX2 IDCT and
+	/// color conversion, jxl-oxide's AVX2/FMA and NEON kernels). Unlike the rest, this depends on the machine.
+	pub simd: Vec<&'static str>,
+}
+
+impl Capabilities {
+	/// One JSON object with the fields above, formats as lowercase names and backends as a name to version map.
+	pub fn to_json(&self) -> String {
+		format!(
+			"{{\"version\":{},\"formats\":[{}],\"backends\":{{{}}},\"features\":[{}],\"simd\":[{}]}}",
+			json_string(self.version),
+			join(self.formats.iter().map(|&format| json_string(&format_name(format)))),
+			join(self.backends.iter().map(|(name, version)| format!("{}:{}", json_string(name), json_string(version)))),
+			join(self.features.iter().map(|feature| json_string(feature))),
+			join(self.simd.iter().map(|simd| json_string(simd))),
+		)
+	}
+}
+
+
+impl fmt::Display for Capabilities {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		writeln!(f, "imgest {}", self.version)?;
+		writeln!(f, "formats: {}", self.formats.iter().map(|&format| format_name(format)).collect::<Vec<_>>().join(", "))?;
+		writeln!(f, "backends: {}", self.backends.iter().map(|(name, version)| format!("{name} {version}")).collect::<Vec<_>>().join(", "))?;
+		writeln!(f, "features: {}", self.features.join(", "))?;
+		write!(f, "simd: {}", self.simd.join(", "))
+	}
+}
+
+
+pub fn capabilities() -> Capabilities {
+	Capabilities {
+		version: env!("CARGO_PKG_VERSION"),
+		formats: FORMATS.iter().filter(|(_, enabled)| *enabled).map(|&(format, _)| format).collect(),
+		backends: BACKENDS.iter().filter(|(_, _, enabled)| *enabled).map(|&(name, version, _)| (name, version)).collect(),
+		features: FEATURES.iter().filter(|(_, enabled)| *enabled).map(|&(name, _)| name).collect(),
+		simd: detected_simd(),
+	}
+}
+
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn detected_simd() -> Vec<&'static str> {
+	let mut simd = Vec::new();
+	if std::arch::is_x86_feature_detected!("sse4.1") {
+		simd.push("sse4.1");
+	}
+	if std::arch::is_x86_feature_detected!("avx2") {
+		simd.push("avx2");
+	}
+	if std::arch::is_x86_feature_detected!("fma") {
+		simd.push("fma");
+	}
+	simd
+}
+
+#[cfg(target_arch = "aarch64")]
+fn detected_simd() -> Vec<&'static str> {
+	if std::arch::is_aarch64_feature_detected!("neon") { vec!["neon"] } else { Vec::new() }
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+fn detected_simd() -> Vec<&'static str> {
+	Vec::new()
+}
+
+
+/// A lowercase name for a format: the `image` crate's variant name for its formats, ours otherwise.
+pub(crate) fn format_name(format: Format) -> String {
+	match format {
+		Format::Image(format) => format!("{format:?}").to_lowercase(),
+		Format::RawPreview => "raw-preview".to_string(),
+		format => format!("{format:?}").to_lowercase(),
+	}
+}
+
+
+fn join(items: impl Iterator<Item = String>) -> String {
+	items.collect::<Vec<_>>().join(",")
+}
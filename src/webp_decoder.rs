@@ -0,0 +1,119 @@
+use std::io::{BufRead, Seek};
+
+use image::{
+	ColorType, ImageDecoder, ImageError, ImageFormat, ImageResult, Limits,
+	error::{DecodingError, LimitError, LimitErrorKind},
+	metadata::Orientation,
+};
+
+use crate::error::Error;
+
+
+pub struct WebPDecoder<R> {
+	inner: image_webp::WebPDecoder<R>,
+	orientation: Option<Orientation>,
+	limits: Limits,
+}
+
+
+// Copied from: https://github.com/image-rs/image/blob/256dc9dd5501fa63cbf081a795a936c74c01abd9/src/codecs/webp/decoder.rs
+impl<R: BufRead + Seek> WebPDecoder<R> {
+	pub fn new(r: R) -> Result<WebPDecoder<R>, Error> {
+		Self::with_limits(r, Limits::no_limits())
+	}
+
+	pub fn with_limits(r: R, limits: Limits) -> Result<WebPDecoder<R>, Error> {
+		let inner = image_webp::WebPDecoder::new(r).map_err(error_from_webp)?;
+		let mut decoder = WebPDecoder {
+			inner,
+			orientation: None,
+			limits: Limits::no_limits(),
+		};
+		decoder.set_limits(limits)?;
+		Ok(decoder)
+	}
+
+	/// Returns true if the image as described by the bitstream is animated.
+	pub fn is_animated(&self) -> bool {
+		self.inner.is_animated()
+	}
+
+	/// Returns true if the image uses VP8 (lossy) rather than VP8L (lossless) compression. Alpha in lossy images is
+	/// stored separately and may itself be lossless.
+	pub fn is_lossy(&mut self) -> bool {
+		self.inner.is_lossy()
+	}
+
+	pub fn has_alpha(&self) -> bool {
+		self.inner.has_alpha()
+	}
+}
+
+
+impl<R: BufRead + Seek> ImageDecoder for WebPDecoder<R> {
+	fn dimensions(&self) -> (u32, u32) {
+		self.inner.dimensions()
+	}
+
+	fn color_type(&self) -> ColorType {
+		if self.inner.has_alpha() { ColorType::Rgba8 } else { ColorType::Rgb8 }
+	}
+
+	fn read_image(mut self, buf: &mut [u8]) -> ImageResult<()> {
+		crate::error::check_buffer(buf, self.total_bytes())?;
+
+		self.inner.read_image(buf).map_err(error_from_webp)
+	}
+
+	fn read_image_boxed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+		(*self).read_image(buf)
+	}
+
+	fn icc_profile(&mut self) -> ImageResult<Option<Vec<u8>>> {
+		self.inner.icc_profile().map_err(error_from_webp)
+	}
+
+	fn exif_metadata(&mut self) -> ImageResult<Option<Vec<u8>>> {
+		let exif = self.inner.exif_metadata().map_err(error_from_webp)?;
+
+		self.orientation = Some(
+			exif.as_ref()
+				.and_then(|exif| Orientation::from_exif_chunk(exif))
+				.unwrap_or(Orientation::NoTransforms),
+		);
+
+		Ok(exif)
+	}
+
+	fn xmp_metadata(&mut self) -> ImageResult<Option<Vec<u8>>> {
+		self.inner.xmp_metadata().map_err(error_from_webp)
+	}
+
+	fn orientation(&mut self) -> ImageResult<Orientation> {
+		// `exif_metadata` caches the orientation, so call it if `orientation` hasn't been set yet.
+		if self.orientation.is_none() {
+			let _ = self.exif_metadata()?;
+		}
+		Ok(self.orientation.unwrap())
+	}
+
+	fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+		limits.check_support(&image::LimitSupport::default())?;
+		let (width, height) = self.dimensions();
+		limits.check_dimensions(width, height)?;
+		if let Some(max_alloc) = limits.max_alloc {
+			self.inner.set_memory_limit(usize::try_from(max_alloc).unwrap_or(usize::MAX));
+		}
+		self.limits = limits;
+		Ok(())
+	}
+}
+
+
+fn error_from_webp(err: image_webp::DecodingError) -> ImageError {
+	match err {
+		image_webp::DecodingError::IoError(err) => ImageError::IoError(err),
+		image_webp::DecodingError::MemoryLimitExceeded => ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory)),
+		err => ImageError::Decoding(DecodingError::new(ImageFormat::WebP.into(), err)),
+	}
+}
@@ -0,0 +1,65 @@
+//! Reading a byte range of a larger file as if it were the whole file.
+
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+
+
+/// The `len` bytes at `start` of `inner`. Positions are relative to `start`, and reads stop at the end of the range.
+pub(crate) struct Window<R> {
+	inner: R,
+	start: u64,
+	len: u64,
+	pos: u64,
+}
+
+impl<R: Seek> Window<R> {
+	/// Fails with `UnexpectedEof` if the range extends past the end of `inner`.
+	pub(crate) fn new(mut inner: R, start: u64, len: u64) -> io::Result<Self> {
+		let end = start.checked_add(len).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "byte range overflows"))?;
+		if end > inner.seek(SeekFrom::End(0))? {
+			return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "byte range extends past the end of the input"));
+		}
+		inner.seek(SeekFrom::Start(start))?;
+		Ok(Window { inner, start, len, pos: 0 })
+	}
+
+	fn remaining(&self) -> usize {
+		usize::try_from(self.len.saturating_sub(self.pos)).unwrap_or(usize::MAX)
+	}
+}
+
+impl<R: Read + Seek> Read for Window<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let max = buf.len().min(self.remaining());
+		let n = self.inner.read(&mut buf[..max])?;
+		self.pos += n as u64;
+		Ok(n)
+	}
+}
+
+impl<R: BufRead + Seek> BufRead for Window<R> {
+	fn fill_buf(&mut self) -> io::Result<&[u8]> {
+		let remaining = self.remaining();
+		let buf = self.inner.fill_buf()?;
+		Ok(&buf[..buf.len().min(remaining)])
+	}
+
+	fn consume(&mut self, amt: usize) {
+		self.inner.consume(amt);
+		self.pos += amt as u64;
+	}
+}
+
+impl<R: Seek> Seek for Window<R> {
+	fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+		let pos = match pos {
+			SeekFrom::Start(pos) => Some(pos),
+			SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+			SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+		};
+		let pos = pos.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position"))?;
+		// Past the end is allowed, as for files; reads there return nothing
+		self.inner.seek(SeekFrom::Start(self.start.saturating_add(pos)))?;
+		self.pos = pos;
+		Ok(pos)
+	}
+}
@@ -0,0 +1,45 @@
+use std::io::Cursor;
+
+use image::{DynamicImage, RgbaImage};
+use imgest::{LoadOptions, accounting::TrackingAllocator};
+
+
+#[global_allocator]
+static ALLOC: TrackingAllocator = TrackingAllocator;
+
+
+#[test]
+fn report_measures_decode() {
+	let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(64, 48, |x, y| image::Rgba([x as u8, y as u8, 7, 255])));
+	let mut data = Vec::new();
+	img.write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png).unwrap();
+
+	let (format, decoded, report) = imgest::load_image_from_reader_with_report(Cursor::new(&data), &LoadOptions::default()).unwrap();
+	assert_eq!(format, image::ImageFormat::Png);
+	assert_eq!(decoded, img);
+	// The format is guessed from the read buffer, so the file is read once, and the decoder may stop short of IEND
+	assert!(report.bytes_read >= data.len() as u64 - 12, "{report:?}");
+	assert!(report.bytes_read <= data.len() as u64, "{report:?}");
+	// At least the returned pixels were allocated
+	let peak = report.peak_bytes_allocated.expect("the tracking allocator is installed");
+	assert!(peak >= 64 * 48 * 4, "{report:?}");
+}
+
+
+#[test]
+fn report_is_per_decode() {
+	let small = DynamicImage::ImageRgba8(RgbaImage::new(4, 4));
+	let large = DynamicImage::ImageRgba8(RgbaImage::new(256, 256));
+	let encode = |img: &DynamicImage| {
+		let mut data = Vec::new();
+		img.write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png).unwrap();
+		data
+	};
+	let (small, large) = (encode(&small), encode(&large));
+
+	// Keep the large image alive so it would inflate the small decode's peak if the baseline weren't reset
+	let (_, _large_img, large_report) = imgest::load_image_from_reader_with_report(Cursor::new(&large), &LoadOptions::default()).unwrap();
+	let (_, _, small_report) = imgest::load_image_from_reader_with_report(Cursor::new(&small), &LoadOptions::default()).unwrap();
+	assert!(small_report.peak_bytes_allocated.unwrap() < large_report.peak_bytes_allocated.unwrap());
+	assert!(small_report.peak_bytes_allocated.unwrap() < 256 * 256 * 4);
+}
@@ -0,0 +1,80 @@
+#![cfg(feature = "arrow")]
+
+mod common;
+
+use std::io::Cursor;
+
+use arrow_array::{Array, BinaryArray, FixedSizeBinaryArray, FixedSizeListArray, Float32Array, StringArray, StructArray, UInt8Array};
+use common::encode_png;
+use image::{DynamicImage, RgbImage};
+use imgest::{
+	Error, LoadOptions,
+	arrow::ImageBatchBuilder,
+	convert::{Normalization, TensorDType, TensorLayout},
+};
+
+
+fn decoded(seed: u8) -> imgest::DecodedImage {
+	let img = DynamicImage::ImageRgb8(RgbImage::from_fn(4, 3, |x, y| image::Rgb([x as u8 * 60, y as u8 * 100, seed])));
+	imgest::load_image_from_reader_with_metadata(Cursor::new(encode_png(&img)), &LoadOptions::new()).unwrap()
+}
+
+
+#[test]
+fn u8_batches() {
+	let mut builder = ImageBatchBuilder::new(4, 3, 3).dtype(TensorDType::U8);
+	builder.append("a.png", &decoded(1)).unwrap();
+	builder.append_error("b.png", &Error::UnsupportedFormat);
+	builder.append("c.png", &decoded(3)).unwrap();
+	let small = imgest::load_image_from_reader_with_metadata(Cursor::new(encode_png(&DynamicImage::new_rgb8(2, 2))), &LoadOptions::new()).unwrap();
+	assert!(builder.append("d.png", &small).is_err());
+	assert_eq!(builder.len(), 3);
+
+	let batch = builder.finish().unwrap();
+	assert!(builder.is_empty());
+	assert_eq!(batch.schema(), builder.schema());
+	assert_eq!(batch.num_rows(), 3);
+	let keys = batch.column(0).as_any().downcast_ref::<StringArray>().unwrap();
+	assert_eq!(keys.iter().collect::<Vec<_>>(), [Some("a.png"), Some("b.png"), Some("c.png")]);
+
+	let pixels = batch.column(1).as_any().downcast_ref::<FixedSizeBinaryArray>().unwrap();
+	assert_eq!(pixels.value_length(), 36);
+	assert_eq!(pixels.value(0), imgest::convert::to_u8(&decoded(1).image, TensorLayout::Hwc));
+	assert_eq!(pixels.value(2), imgest::convert::to_u8(&decoded(3).image, TensorLayout::Hwc));
+	assert!(pixels.is_null(1));
+
+	let metadata = batch.column(2).as_any().downcast_ref::<StructArray>().unwrap();
+	assert!(metadata.is_valid(0) && metadata.is_null(1));
+	let formats = metadata.column_by_name("format").unwrap().as_any().downcast_ref::<StringArray>().unwrap();
+	assert_eq!(formats.value(0), "png");
+	let orientation = metadata.column_by_name("orientation").unwrap().as_any().downcast_ref::<UInt8Array>().unwrap();
+	assert_eq!(orientation.value(2), 1);
+	let exif = metadata.column_by_name("exif").unwrap().as_any().downcast_ref::<BinaryArray>().unwrap();
+	assert!(exif.is_null(0));
+
+	let errors = batch.column(3).as_any().downcast_ref::<StringArray>().unwrap();
+	assert_eq!(errors.iter().map(|e| e.is_some()).collect::<Vec<_>>(), [false, true, false]);
+}
+
+
+#[test]
+fn float_tensor_batches() {
+	let normalization = Normalization::MeanStd {
+		mean: vec![0.5],
+		std: vec![0.25],
+	};
+	let mut builder = ImageBatchBuilder::new(4, 3, 3)
+		.layout(TensorLayout::Chw)
+		.dtype(TensorDType::F32(normalization.clone()));
+	builder.append("a.png", &decoded(9)).unwrap();
+	let schema = builder.schema();
+	let field = schema.field_with_name("pixels").unwrap();
+	assert_eq!(field.metadata()["ARROW:extension:name"], "arrow.fixed_shape_tensor");
+	assert_eq!(field.metadata()["ARROW:extension:metadata"], r#"{"shape":[3,3,4],"dim_names":["C","H","W"]}"#);
+
+	let batch = builder.finish().unwrap();
+	let pixels = batch.column(1).as_any().downcast_ref::<FixedSizeListArray>().unwrap();
+	let values = pixels.value(0);
+	let values = values.as_any().downcast_ref::<Float32Array>().unwrap();
+	assert_eq!(values.values().to_vec(), decoded(9).to_f32(TensorLayout::Chw, &normalization));
+}
@@ -0,0 +1,26 @@
+#![cfg(feature = "async")]
+
+mod common;
+
+use common::RawPng;
+use image::ImageFormat;
+use imgest::{Error, Format, LoadOptions, OutputColor};
+
+
+#[tokio::test]
+async fn load_async() {
+	let png = RawPng::new(2, 1, 8, 0).encode(&[vec![7, 9]]);
+	let dir = tempfile::tempdir().unwrap();
+	let path = dir.path().join("a.png");
+	std::fs::write(&path, &png).unwrap();
+
+	let (format, img) = imgest::load_image_async(&path).await.unwrap();
+	assert_eq!(format, Format::Image(ImageFormat::Png));
+	assert_eq!(img.as_luma8().unwrap().as_raw(), &[7, 9]);
+	assert!(matches!(imgest::load_image_async(dir.path().join("missing.png")).await, Err(Error::Io(_))));
+
+	let options = LoadOptions::new().output(OutputColor::Rgba8);
+	let (_, img) = imgest::load_image_from_async_reader(png.as_slice(), &options).await.unwrap();
+	assert_eq!(img.as_rgba8().unwrap().as_raw(), &[7, 7, 7, 255, 9, 9, 9, 255]);
+	assert!(matches!(imgest::load_image_from_async_reader(&b"not an image at all"[..], &options).await, Err(Error::UnsupportedFormat)));
+}
@@ -0,0 +1,46 @@
+use std::path::Path;
+
+use imgest::{
+	Error,
+	audit::{AuditLog, Decision},
+	seen::ContentHash,
+};
+
+
+#[test]
+fn records_one_line_per_decision() {
+	let mut log = AuditLog::new(Vec::new(), "test \"config\"");
+	let hash = ContentHash::of(b"abc");
+	log.record(Path::new("a.png"), &Decision::Ingested).unwrap();
+	log.record(Path::new("b\n.png"), &Decision::Duplicate { of: hash }).unwrap();
+	log.record(Path::new("c.png"), &Decision::from_error(&Error::UnsupportedFormat)).unwrap();
+	log.record(Path::new("d.png"), &Decision::from_error(&Error::TooBig)).unwrap();
+
+	let out = String::from_utf8(log.into_inner()).unwrap();
+	let lines: Vec<&str> = out.lines().collect();
+	assert_eq!(lines.len(), 4);
+	for line in &lines {
+		assert!(line.starts_with("{\"timestamp_ms\":") && line.ends_with(",\"config\":\"test \\\"config\\\"\"}"), "{line}");
+	}
+	assert!(lines[0].contains("\"input\":\"a.png\",\"decision\":\"ingested\","));
+	assert!(lines[1].contains(&format!("\"input\":\"b\\n.png\",\"decision\":\"duplicate\",\"of\":\"{hash}\",")));
+	assert!(lines[2].contains("\"decision\":\"quarantined\",\"reason\":\"unsupported image format\","));
+	assert!(lines[3].contains("\"decision\":\"rejected\",\"reason\":\"image exceeds size limits\","));
+}
+
+
+#[test]
+fn append_keeps_earlier_runs() {
+	let dir = tempfile::tempdir().unwrap();
+	let path = dir.path().join("audit.jsonl");
+	for run in 0..2 {
+		let mut log = AuditLog::append(&path, format!("run {run}")).unwrap();
+		log.record(Path::new("a.png"), &Decision::Ingested).unwrap();
+		log.flush().unwrap();
+	}
+
+	let out = std::fs::read_to_string(&path).unwrap();
+	assert_eq!(out.lines().count(), 2);
+	assert!(out.lines().next().unwrap().contains("\"config\":\"run 0\""));
+	assert!(out.lines().nth(1).unwrap().contains("\"config\":\"run 1\""));
+}
@@ -0,0 +1,111 @@
+#![cfg(feature = "avif")]
+
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageDecoder, ImageEncoder, RgbImage, RgbaImage, codecs::avif::AvifEncoder};
+use imgest::{AvifDecoder, AvifOptions, LoadOptions, convert::YCbCrMatrix};
+
+
+fn encode(img: &DynamicImage) -> Vec<u8> {
+	let mut out = Vec::new();
+	AvifEncoder::new_with_speed_quality(&mut out, 10, 100)
+		.write_image(img.as_bytes(), img.width(), img.height(), img.color().into())
+		.unwrap();
+	out
+}
+
+
+fn gradient() -> RgbaImage {
+	RgbaImage::from_fn(32, 24, |x, y| image::Rgba([(x * 8) as u8, (y * 10) as u8, 128, (255 - x * 4) as u8]))
+}
+
+
+/// Mean absolute difference over 8-bit RGBA samples.
+fn mean_abs_diff(a: &RgbaImage, b: &RgbaImage) -> f64 {
+	a.as_raw().iter().zip(b.as_raw()).map(|(&a, &b)| f64::from(a.abs_diff(b))).sum::<f64>() / a.as_raw().len() as f64
+}
+
+
+#[test]
+fn decodes_rgba() {
+	let img = DynamicImage::ImageRgba8(gradient());
+	let data = encode(&img);
+
+	let decoder = AvifDecoder::new(Cursor::new(&data)).unwrap();
+	assert!(decoder.has_alpha());
+	assert_eq!(decoder.dimensions(), (32, 24));
+	// High bit depth sources come out as 16-bit, alpha included
+	let expected_color = if decoder.bit_depth() > 8 { image::ColorType::Rgba16 } else { image::ColorType::Rgba8 };
+	assert_eq!(decoder.color_type(), expected_color);
+
+	let (format, decoded) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!(format, image::ImageFormat::Avif);
+	let decoded = decoded.to_rgba8();
+	assert!(mean_abs_diff(&decoded, &gradient()) < 2.0);
+	// Alpha is coded losslessly enough at this quality to come back nearly exact
+	for (a, b) in decoded.pixels().zip(gradient().pixels()) {
+		assert!(a.0[3].abs_diff(b.0[3]) <= 1);
+	}
+}
+
+
+#[test]
+fn decodes_opaque_rgb() {
+	let rgb = RgbImage::from_fn(16, 16, |x, y| image::Rgb([200, (x * 16) as u8, (y * 16) as u8]));
+	let data = encode(&DynamicImage::ImageRgb8(rgb.clone()));
+
+	let decoder = AvifDecoder::new(Cursor::new(&data)).unwrap();
+	assert!(!decoder.has_alpha());
+
+	let (_, decoded) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	let expected = DynamicImage::ImageRgb8(rgb).to_rgba8();
+	assert!(mean_abs_diff(&decoded.to_rgba8(), &expected) < 2.0);
+}
+
+
+#[test]
+fn nclx_matrix_is_honored() {
+	let img = DynamicImage::ImageRgba8(gradient());
+	let data = encode(&img);
+	let decoder = AvifDecoder::new(Cursor::new(&data)).unwrap();
+	let cicp = decoder.cicp().expect("the encoder writes an nclx colr property");
+	assert_eq!(decoder.ycbcr_matrix(), cicp.ycbcr_matrix());
+	let (_, as_encoded) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+
+	// Relabel the same planes as BT.2020, which must shift the colors
+	let nclx = data.windows(8).position(|w| w == b"colrnclx").unwrap() + 8;
+	let mut bt2020 = data.clone();
+	bt2020[nclx + 4..nclx + 6].copy_from_slice(&9u16.to_be_bytes());
+	let decoder = AvifDecoder::new(Cursor::new(&bt2020)).unwrap();
+	assert_eq!(decoder.cicp().unwrap().matrix_coefficients, 9);
+	assert_eq!(decoder.ycbcr_matrix(), Some(YCbCrMatrix::Bt2020));
+	let (_, relabeled) = imgest::load_image_from_reader(Cursor::new(&bt2020)).unwrap();
+	assert!(mean_abs_diff(&as_encoded.to_rgba8(), &relabeled.to_rgba8()) > 1.0);
+}
+
+
+#[test]
+fn limits_are_respected() {
+	let data = encode(&DynamicImage::ImageRgba8(gradient()));
+	let mut limits = image::Limits::default();
+	limits.max_image_height = Some(23);
+	assert!(matches!(AvifDecoder::with_limits(Cursor::new(&data), limits), Err(imgest::Error::Limits(_))));
+}
+
+
+#[test]
+fn thread_count_doesnt_change_the_output() {
+	let data = encode(&DynamicImage::ImageRgba8(gradient()));
+	let (_, threaded) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	let options = LoadOptions::new().avif(AvifOptions { threads: Some(1) });
+	let (_, single) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &options).unwrap();
+	assert_eq!(single, threaded);
+}
+
+
+#[test]
+fn garbage_is_an_error() {
+	let mut data = encode(&DynamicImage::ImageRgba8(gradient()));
+	data.truncate(data.len() / 2);
+	assert!(imgest::load_image_from_reader(Cursor::new(&data)).is_err());
+}
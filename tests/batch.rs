@@ -0,0 +1,143 @@
+mod common;
+
+use common::RawPng;
+use image::ImageFormat;
+use imgest::{
+	CancelToken, Error, Format, LoadOptions,
+	batch::{BatchDecoder, BatchInput},
+	shutdown::ShutdownHandle,
+};
+
+
+/// A 1x1 gray PNG of value `v`, or garbage for odd `v`.
+fn input(v: u8) -> Vec<u8> {
+	if v.is_multiple_of(2) { RawPng::new(1, 1, 8, 0).encode(&[vec![v]]) } else { b"not an image at all".to_vec() }
+}
+
+
+#[test]
+fn batch_in_order() {
+	let inputs: Vec<Vec<u8>> = (0..50).map(input).collect();
+	let decoder = BatchDecoder::new(LoadOptions::new()).threads(4).max_in_flight(4);
+	let results: Vec<_> = decoder.decode(inputs).collect();
+	assert_eq!(results.len(), 50);
+	for (n, (index, result)) in results.into_iter().enumerate() {
+		assert_eq!(index, n);
+		match result {
+			Ok((_, img)) => assert_eq!(img.as_luma8().unwrap().as_raw(), &[n as u8]),
+			Err(e) => assert!(n % 2 == 1 && matches!(e, Error::UnsupportedFormat), "{n}: {e}"),
+		}
+	}
+}
+
+
+#[test]
+fn batch_as_completed() {
+	let dir = tempfile::tempdir().unwrap();
+	let paths: Vec<_> = (0..20)
+		.map(|v| {
+			let path = dir.path().join(format!("{v}.png"));
+			std::fs::write(&path, input(v * 2)).unwrap();
+			BatchInput::Path(path)
+		})
+		.collect();
+	let mut results: Vec<_> = BatchDecoder::new(LoadOptions::new()).threads(3).ordered(false).decode(paths).collect();
+	results.sort_by_key(|(index, _)| *index);
+	assert_eq!(results.len(), 20);
+	for (n, (index, result)) in results.into_iter().enumerate() {
+		assert_eq!(index, n);
+		assert_eq!(result.unwrap().1.as_luma8().unwrap().as_raw(), &[n as u8 * 2]);
+	}
+}
+
+
+#[test]
+fn batch_stops_when_dropped() {
+	// An endless input, of which only as much is decoded as is consumed plus what's in flight
+	let mut results = BatchDecoder::new(LoadOptions::new()).threads(2).max_in_flight(2).decode((0..).map(|v: u64| input((v % 100) as u8 * 2)));
+	for n in 0..10 {
+		assert_eq!(results.next().unwrap().0, n);
+	}
+	drop(results);
+}
+
+
+#[test]
+fn batch_with_format_weights() {
+	// PNGs take the whole budget (the weight is capped at 3), so they decode one at a time, between the garbage inputs
+	let inputs: Vec<Vec<u8>> = (0..30).map(input).collect();
+	let decoder = BatchDecoder::new(LoadOptions::new()).threads(3).format_weight(Format::Image(ImageFormat::Png), 10);
+	for ordered in [true, false] {
+		let mut results: Vec<_> = decoder.clone().ordered(ordered).decode(inputs.clone()).collect();
+		results.sort_by_key(|(index, _)| *index);
+		assert_eq!(results.len(), 30);
+		for (n, (index, result)) in results.into_iter().enumerate() {
+			assert_eq!(index, n);
+			assert_eq!(result.ok().map(|(_, img)| img.into_luma8().into_raw()), (n % 2 == 0).then(|| vec![n as u8]));
+		}
+	}
+}
+
+
+#[test]
+fn batch_drains_on_shutdown() {
+	// An endless input, which ends once the inputs already taken have been returned
+	for ordered in [true, false] {
+		let handle = ShutdownHandle::new();
+		let decoder = BatchDecoder::new(LoadOptions::new()).threads(3).max_in_flight(4).ordered(ordered).shutdown(handle.clone());
+		let mut results = decoder.decode((0..).map(|v: u64| input((v % 100) as u8 * 2)));
+		for _ in 0..10 {
+			assert!(results.next().unwrap().1.is_ok());
+		}
+		handle.drain();
+		let rest: Vec<_> = results.collect();
+		assert!(rest.len() <= 4, "{}", rest.len());
+		assert!(rest.into_iter().all(|(_, result)| result.is_ok()));
+	}
+}
+
+
+#[test]
+fn batch_cancels_on_shutdown() {
+	let handle = ShutdownHandle::new();
+	let mut results = BatchDecoder::new(LoadOptions::new()).threads(2).max_in_flight(2).shutdown(handle.clone()).decode((0..).map(|_| input(0)));
+	assert!(results.next().unwrap().1.is_ok());
+	handle.cancel();
+	let rest: Vec<_> = results.collect();
+	assert!(rest.len() <= 2, "{}", rest.len());
+	assert!(rest.into_iter().all(|(_, result)| matches!(result, Ok(_) | Err(Error::Cancelled))));
+
+	// The handle's token is checked alongside the options' own, rather than replacing it
+	let token = CancelToken::new();
+	token.cancel();
+	let decoder = BatchDecoder::new(LoadOptions::new().cancel_token(token)).shutdown(ShutdownHandle::new());
+	let results: Vec<_> = decoder.decode((0..10).map(|_| input(0))).collect();
+	assert_eq!(results.len(), 10);
+	assert!(results.into_iter().all(|(_, result)| matches!(result, Err(Error::Cancelled))));
+}
+
+
+#[test]
+fn priority_lane_skips_the_queue() {
+	// Nothing is consumed, so the batch's own inputs stall once two are in flight, yet the lane is still served
+	let results = BatchDecoder::new(LoadOptions::new()).threads(2).max_in_flight(2).decode((0..).map(|_| input(0)));
+	let lane = results.priority_lane();
+	let threads: Vec<_> = (0..4u8)
+		.map(|v| {
+			let lane = lane.clone();
+			std::thread::spawn(move || lane.decode(input(v * 2)))
+		})
+		.collect();
+	for (v, thread) in threads.into_iter().enumerate() {
+		assert_eq!(thread.join().unwrap().unwrap().1.as_luma8().unwrap().as_raw(), &[v as u8 * 2]);
+	}
+	assert!(matches!(lane.decode(input(1)), Err(Error::UnsupportedFormat)));
+
+	// Once the batch is over, the lane decodes on the calling thread
+	drop(results);
+	assert_eq!(lane.decode(input(4)).unwrap().1.as_luma8().unwrap().as_raw(), &[4]);
+	let mut results = BatchDecoder::new(LoadOptions::new()).threads(2).decode((0..3).map(input));
+	let lane = results.priority_lane();
+	assert_eq!(results.by_ref().count(), 3);
+	assert_eq!(lane.decode(input(6)).unwrap().1.as_luma8().unwrap().as_raw(), &[6]);
+}
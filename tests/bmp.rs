@@ -0,0 +1,141 @@
+//! BMP storage variants and ICO entry selection.
+
+use std::io::Cursor;
+
+use image::{
+	DynamicImage, ExtendedColorType, Rgba, RgbaImage,
+	codecs::ico::{IcoEncoder, IcoFrame},
+};
+use imgest::{IcoDecoder, IcoOptions, LoadOptions};
+
+
+/// A BMP with a BITMAPINFOHEADER. Negative `height` means top-down rows.
+fn bmp(width: i32, height: i32, bits: u16, compression: u32, palette: &[[u8; 3]], pixels: &[u8]) -> Vec<u8> {
+	let offset = 14 + 40 + palette.len() as u32 * 4;
+	let mut out = b"BM".to_vec();
+	out.extend_from_slice(&(offset + pixels.len() as u32).to_le_bytes());
+	out.extend_from_slice(&[0; 4]);
+	out.extend_from_slice(&offset.to_le_bytes());
+	out.extend_from_slice(&40u32.to_le_bytes());
+	out.extend_from_slice(&width.to_le_bytes());
+	out.extend_from_slice(&height.to_le_bytes());
+	out.extend_from_slice(&1u16.to_le_bytes());
+	out.extend_from_slice(&bits.to_le_bytes());
+	out.extend_from_slice(&compression.to_le_bytes());
+	out.extend_from_slice(&(pixels.len() as u32).to_le_bytes());
+	out.extend_from_slice(&[0; 8]);
+	out.extend_from_slice(&(palette.len() as u32).to_le_bytes());
+	out.extend_from_slice(&[0; 4]);
+	for [r, g, b] in palette {
+		out.extend_from_slice(&[*b, *g, *r, 0]);
+	}
+	out.extend_from_slice(pixels);
+	out
+}
+
+
+const PALETTE: [[u8; 3]; 4] = [[0, 0, 0], [255, 0, 0], [0, 255, 0], [0, 0, 255]];
+
+
+fn rgb_rows(img: &DynamicImage) -> Vec<[u8; 3]> {
+	img.to_rgb8().pixels().map(|p| p.0).collect()
+}
+
+
+#[test]
+fn bottom_up_and_top_down() {
+	// 2x2, 24-bit BGR rows padded to 4 bytes
+	let first = [0, 0, 255, 0, 255, 0, 0, 0];
+	let second = [255, 0, 0, 255, 255, 255, 0, 0];
+	let bottom_up = bmp(2, 2, 24, 0, &[], &[second, first].concat());
+	let top_down = bmp(2, -2, 24, 0, &[], &[first, second].concat());
+	let expected = vec![[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]];
+
+	for data in [bottom_up, top_down] {
+		let (format, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+		assert_eq!(format, image::ImageFormat::Bmp);
+		assert_eq!(rgb_rows(&img), expected);
+	}
+}
+
+
+#[test]
+fn rle8() {
+	// Bottom row: a run of three 1s and a literal 2, 3, 2 (padded to an even length), then end of line; the top row is
+	// cut short by end of bitmap and left at index 0
+	let pixels = [3, 1, 0, 3, 2, 3, 2, 0, 0, 0, 0, 1];
+	let data = bmp(6, 2, 8, 1, &PALETTE, &pixels);
+	let (_, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	let [black, red, green, blue] = PALETTE;
+	assert_eq!(rgb_rows(&img), [vec![black; 6], vec![red, red, red, green, blue, green]].concat());
+}
+
+
+#[test]
+fn rle4() {
+	// A run of five alternating 1/2 nibbles, then a literal 3, 0, 3
+	let pixels = [5, 0x12, 0, 3, 0x30, 0x30, 0, 1];
+	let data = bmp(8, 1, 4, 2, &PALETTE, &pixels);
+	let (_, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	let [black, red, green, blue] = PALETTE;
+	assert_eq!(rgb_rows(&img), [red, green, red, green, red, blue, black, blue]);
+}
+
+
+fn ico(sizes: &[u32]) -> Vec<u8> {
+	let frames: Vec<IcoFrame> = sizes
+		.iter()
+		.map(|&size| {
+			let img = RgbaImage::from_pixel(size, size, Rgba([size as u8, 0, 0, 255]));
+			IcoFrame::as_png(img.as_raw(), size, size, ExtendedColorType::Rgba8).unwrap()
+		})
+		.collect();
+	let mut out = Vec::new();
+	IcoEncoder::new(&mut out).encode_images(&frames).unwrap();
+	out
+}
+
+
+#[test]
+fn ico_picks_largest_by_default() {
+	let data = ico(&[16, 48, 32]);
+	let decoder = IcoDecoder::new(Cursor::new(&data)).unwrap();
+	assert_eq!(decoder.entries().len(), 3);
+	let chosen = decoder.chosen();
+	assert_eq!((chosen.index, chosen.width, chosen.height, chosen.png), (1, 48, 48, true));
+
+	let (format, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!(format, image::ImageFormat::Ico);
+	assert_eq!((img.width(), img.height()), (48, 48));
+	assert_eq!(img.to_rgba8().get_pixel(0, 0).0, [48, 0, 0, 255]);
+
+	let images = imgest::probe_images_from_reader(Cursor::new(&data)).unwrap();
+	let sizes: Vec<_> = images.iter().map(|image| (image.index, image.width, image.primary)).collect();
+	assert_eq!(sizes, [(0, 16, false), (1, 48, true), (2, 32, false)]);
+}
+
+
+#[test]
+fn ico_requested_size() {
+	let data = ico(&[16, 48, 32]);
+	for (requested, expected) in [(32, 32), (16, 16), (20, 32), (33, 48), (64, 48)] {
+		let decoder = IcoDecoder::with_size(Cursor::new(&data), Some(requested), image::Limits::no_limits()).unwrap();
+		assert_eq!(decoder.chosen().width, expected, "requested {requested}");
+
+		let options = LoadOptions {
+			ico: IcoOptions { size: Some(requested) },
+			..Default::default()
+		};
+		let (_, img) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &options).unwrap();
+		assert_eq!(img.width(), expected, "requested {requested}");
+	}
+}
+
+
+#[test]
+fn ico_bad_directory_is_an_error() {
+	let mut data = ico(&[16]);
+	// Point the only entry past the end of the file
+	data[18..22].copy_from_slice(&u32::MAX.to_le_bytes());
+	assert!(matches!(IcoDecoder::new(Cursor::new(&data)), Err(imgest::Error::Decoding(_))));
+}
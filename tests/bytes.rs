@@ -0,0 +1,49 @@
+mod common;
+
+use std::io::Cursor;
+
+use common::encode_png;
+use image::{DynamicImage, RgbImage};
+use imgest::{LoadOptions, OutputColor};
+
+
+#[test]
+fn loads_from_memory() {
+	let img = DynamicImage::ImageRgb8(RgbImage::from_fn(7, 5, |x, y| image::Rgb([x as u8 * 30, y as u8 * 40, 200])));
+	let data = encode_png(&img);
+
+	let (format, decoded) = imgest::load_image_from_bytes(&data).unwrap();
+	assert_eq!(format, image::ImageFormat::Png);
+	assert_eq!(decoded, img);
+	assert_eq!(imgest::load_image_from_vec(data.clone()).unwrap().1, img);
+
+	let options = LoadOptions::new().output(OutputColor::Rgba8);
+	let (_, rgba) = imgest::load_image_from_bytes_with_options(&data, &options).unwrap();
+	assert_eq!(rgba, DynamicImage::ImageRgba8(img.to_rgba8()));
+	assert_eq!(imgest::load_image_from_vec_with_options(data, &options).unwrap().1, rgba);
+
+	assert!(matches!(imgest::load_image_from_bytes(b"not an image at all"), Err(imgest::Error::UnsupportedFormat)));
+}
+
+
+#[test]
+fn loads_byte_ranges() {
+	let img = DynamicImage::ImageRgb8(RgbImage::from_fn(7, 5, |x, y| image::Rgb([x as u8 * 30, y as u8 * 40, 200])));
+	let png = encode_png(&img);
+	// Embedded between other data, with trailing bytes that would corrupt the PNG if decoding read past its end
+	let mut container = b"%PDF-1.7 some preceding objects".to_vec();
+	let offset = container.len() as u64;
+	container.extend_from_slice(&png);
+	container.extend_from_slice(b"endstream trailing garbage");
+
+	let (format, decoded) = imgest::load_image_from_reader_at(Cursor::new(&container), offset, png.len() as u64).unwrap();
+	assert_eq!(format, image::ImageFormat::Png);
+	assert_eq!(decoded, img);
+
+	let options = LoadOptions::new().output(OutputColor::Rgba8);
+	let (_, rgba) = imgest::load_image_from_reader_at_with_options(Cursor::new(&container), offset, png.len() as u64, &options).unwrap();
+	assert_eq!(rgba, DynamicImage::ImageRgba8(img.to_rgba8()));
+
+	assert!(matches!(imgest::load_image_from_reader_at(Cursor::new(&container), 0, 16), Err(imgest::Error::UnsupportedFormat)));
+	assert!(matches!(imgest::load_image_from_reader_at(Cursor::new(&container), offset, container.len() as u64), Err(imgest::Error::Io(_))));
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use std::{
+	io::{BufRead, Cursor, Read, Seek, SeekFrom},
+	time::Duration,
+};
+
+use common::RawPng;
+use image::{ExtendedColorType, ImageEncoder, codecs::jpeg::JpegEncoder};
+use imgest::{CancelToken, Error, LoadOptions};
+
+
+/// Cancels `token` once more than `after` bytes have been read.
+struct CancellingReader<'a> {
+	inner: Cursor<&'a [u8]>,
+	token: CancelToken,
+	after: u64,
+}
+
+impl CancellingReader<'_> {
+	fn check(&self) {
+		if self.inner.position() > self.after {
+			self.token.cancel();
+		}
+	}
+}
+
+impl Read for CancellingReader<'_> {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		self.check();
+		self.inner.read(buf)
+	}
+}
+
+impl BufRead for CancellingReader<'_> {
+	fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+		self.check();
+		// Small reads, like a file would give
+		let buf = self.inner.fill_buf()?;
+		Ok(&buf[..buf.len().min(4096)])
+	}
+
+	fn consume(&mut self, amt: usize) {
+		self.inner.consume(amt);
+	}
+}
+
+impl Seek for CancellingReader<'_> {
+	fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+		self.inner.seek(pos)
+	}
+}
+
+
+fn test_images() -> [Vec<u8>; 2] {
+	let png = RawPng::new(256, 256, 8, 2).encode(&vec![vec![0x80; 256 * 3]; 256]);
+	let mut jpeg = Vec::new();
+	JpegEncoder::new(&mut jpeg).write_image(&[0x80; 64 * 64 * 3], 64, 64, ExtendedColorType::Rgb8).unwrap();
+	[png, jpeg]
+}
+
+
+#[test]
+fn cancelled_before_decoding() {
+	for data in test_images() {
+		let token = CancelToken::new();
+		let options = LoadOptions::new().cancel_token(token.clone());
+		imgest::load_image_from_bytes_with_options(&data, &options).unwrap();
+
+		token.cancel();
+		assert!(matches!(imgest::load_image_from_bytes_with_options(&data, &options), Err(Error::Cancelled)));
+
+		let options = LoadOptions::new().cancel_token(CancelToken::with_timeout(Duration::ZERO));
+		assert!(matches!(imgest::load_image_from_bytes_with_options(&data, &options), Err(Error::Cancelled)));
+	}
+}
+
+
+#[test]
+fn png_cancelled_partway() {
+	let [png, _] = test_images();
+	let token = CancelToken::new();
+	let reader = CancellingReader {
+		inner: Cursor::new(png.as_slice()),
+		token: token.clone(),
+		after: png.len() as u64 / 2,
+	};
+	let result = imgest::load_image_from_reader_with_options(reader, &LoadOptions::new().cancel_token(token));
+	assert!(matches!(result, Err(Error::Cancelled)));
+}
@@ -0,0 +1,29 @@
+#![cfg(feature = "candle")]
+
+mod common;
+
+use std::io::Cursor;
+
+use candle_core::{DType, Device};
+use common::encode_png;
+use image::{DynamicImage, RgbImage};
+use imgest::{
+	LoadOptions,
+	convert::{Normalization, TensorDType, TensorLayout},
+};
+
+
+#[test]
+fn candle_tensors() {
+	let img = DynamicImage::ImageRgb8(RgbImage::from_fn(4, 3, |x, y| image::Rgb([x as u8 * 60, y as u8 * 100, 255])));
+	let decoded = imgest::load_image_from_reader_with_metadata(Cursor::new(encode_png(&img)), &LoadOptions::new()).unwrap();
+
+	let hwc = decoded.to_candle_tensor(&Device::Cpu, TensorLayout::Hwc, &TensorDType::U8).unwrap();
+	assert_eq!((hwc.dims(), hwc.dtype()), (&[3, 4, 3][..], DType::U8));
+	assert_eq!(hwc.flatten_all().unwrap().to_vec1::<u8>().unwrap(), img.as_bytes());
+
+	let chw = decoded.to_candle_tensor(&Device::Cpu, TensorLayout::Chw, &TensorDType::F32(Normalization::Unit)).unwrap();
+	assert_eq!((chw.dims(), chw.dtype()), (&[3, 3, 4][..], DType::F32));
+	let planes = chw.to_vec3::<f32>().unwrap();
+	assert_eq!((planes[0][2][1], planes[1][2][1], planes[2][0][0]), (60.0 / 255.0, 200.0 / 255.0, 1.0));
+}
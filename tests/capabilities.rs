@@ -0,0 +1,27 @@
+use image::ImageFormat;
+use imgest::Format;
+
+
+#[test]
+fn capabilities_describe_the_build() {
+	let capabilities = imgest::capabilities();
+	assert_eq!(capabilities.version, env!("CARGO_PKG_VERSION"));
+	assert!(capabilities.formats.contains(&Format::Image(ImageFormat::Png)));
+	assert!(capabilities.formats.contains(&Format::Jxl));
+	assert_eq!(capabilities.formats.contains(&Format::Svg), cfg!(feature = "svg"));
+	assert!(capabilities.backends.contains(&("png", "0.18.0")));
+	assert_eq!(capabilities.backends.iter().any(|&(name, _)| name == "resvg"), cfg!(feature = "svg"));
+	assert_eq!(capabilities.features.contains(&"raw"), cfg!(feature = "raw"));
+	assert_eq!(capabilities, imgest::capabilities());
+}
+
+
+#[test]
+fn capabilities_json() {
+	let capabilities = imgest::capabilities();
+	let json = capabilities.to_json();
+	assert!(json.starts_with(&format!("{{\"version\":\"{}\",\"formats\":[\"png\",\"jpeg\",\"mpo\",", env!("CARGO_PKG_VERSION"))), "{json}");
+	assert!(json.contains("\"png\":\"0.18.0\""), "{json}");
+	assert!(json.ends_with("]}"), "{json}");
+	assert!(!json.contains('\n'));
+}
@@ -0,0 +1,66 @@
+//! CMYK and YCCK JPEGs, which jpeg-encoder writes the way Photoshop does: inverted, with an Adobe APP14 marker.
+
+use std::io::Cursor;
+
+use image::ImageDecoder;
+use imgest::{DctScale, JpegDecoder, JpegOptions, LoadOptions};
+
+
+/// (C, M, Y, K) in ink amounts, and the RGB we expect for it: (1 - C)(1 - K) and so on.
+const SWATCHES: [([u8; 4], [u8; 3]); 4] = [
+	([255, 0, 0, 0], [0, 255, 255]),
+	([0, 255, 0, 0], [255, 0, 255]),
+	([0, 0, 0, 255], [0, 0, 0]),
+	([0, 102, 204, 51], [204, 122, 41]),
+];
+
+
+fn encode(cmyk: [u8; 4], color_type: jpeg_encoder::ColorType) -> Vec<u8> {
+	let mut out = Vec::new();
+	let encoder = jpeg_encoder::Encoder::new(&mut out, 100);
+	encoder.encode(&cmyk.repeat(16 * 16), 16, 16, color_type).unwrap();
+	out
+}
+
+
+/// Checks every pixel at each DCT scale, since reduced scales take a different decoder.
+fn assert_close(data: &[u8], expected: [u8; 3]) {
+	for dct_scale in [DctScale::Full, DctScale::Half, DctScale::Eighth] {
+		let options = LoadOptions::new().jpeg(JpegOptions { dct_scale, ..Default::default() });
+		let (_, img) = imgest::load_image_from_reader_with_options(Cursor::new(data), &options).unwrap();
+		let img = img.as_rgb8().expect("CMYK converts to RGB");
+		assert_eq!(img.dimensions(), dct_scale.scale_dimensions(16, 16));
+		for pixel in img.pixels() {
+			assert!(pixel.0.iter().zip(expected).all(|(&a, b)| a.abs_diff(b) <= 3), "{dct_scale:?}: {:?} != {expected:?}", pixel.0);
+		}
+	}
+}
+
+
+#[test]
+fn adobe_cmyk() {
+	for (cmyk, rgb) in SWATCHES {
+		let data = encode(cmyk, jpeg_encoder::ColorType::Cmyk);
+		assert_eq!(JpegDecoder::new(Cursor::new(&data)).unwrap().color_type(), image::ColorType::Rgb8);
+		assert_close(&data, rgb);
+	}
+}
+
+
+#[test]
+fn adobe_ycck() {
+	for (cmyk, rgb) in SWATCHES {
+		assert_close(&encode(cmyk, jpeg_encoder::ColorType::CmykAsYcck), rgb);
+	}
+}
+
+
+#[test]
+fn cmyk_without_adobe_marker_is_not_inverted() {
+	let mut data = encode([0, 102, 204, 51], jpeg_encoder::ColorType::Cmyk);
+	// Drop the APP14 segment, leaving the stored (inverted) samples to be read as plain ink amounts
+	let at = data.windows(7).position(|w| w[0] == 0xFF && w[1] == 0xEE && &w[4..] == b"Ado").unwrap();
+	let len = usize::from(u16::from_be_bytes([data[at + 2], data[at + 3]]));
+	data.drain(at..at + 2 + len);
+	assert_close(&data, [0, 20, 41]);
+}
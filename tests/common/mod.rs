@@ -0,0 +1,138 @@
+//! Fixture builders shared by the integration tests.
+#![allow(dead_code)]
+
+use image::{DynamicImage, ExtendedColorType, ImageEncoder, codecs::png::PngEncoder};
+
+
+pub const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+
+pub fn encode_png(img: &DynamicImage) -> Vec<u8> {
+	let mut out = Vec::new();
+	PngEncoder::new(&mut out)
+		.write_image(img.as_bytes(), img.width(), img.height(), ExtendedColorType::from(img.color()))
+		.expect("PNG encoding should succeed");
+	out
+}
+
+
+/// Writes a PNG by hand, for layouts the `png` encoder can't produce (Adam7 interlacing, malformed palettes, bogus headers).
+///
+/// `scanlines` must already be in PNG layout (big endian, packed sub-byte samples, and for interlaced images the
+/// reduced images of all seven passes back to back); each scanline gets filter type 0.
+pub struct RawPng {
+	pub width: u32,
+	pub height: u32,
+	pub bit_depth: u8,
+	pub color_type: u8,
+	pub interlaced: bool,
+	pub chunks_before_idat: Vec<([u8; 4], Vec<u8>)>,
+}
+
+impl RawPng {
+	pub fn new(width: u32, height: u32, bit_depth: u8, color_type: u8) -> Self {
+		Self {
+			width,
+			height,
+			bit_depth,
+			color_type,
+			interlaced: false,
+			chunks_before_idat: Vec::new(),
+		}
+	}
+
+	pub fn chunk(mut self, kind: &[u8; 4], data: &[u8]) -> Self {
+		self.chunks_before_idat.push((*kind, data.to_vec()));
+		self
+	}
+
+	pub fn encode(&self, scanlines: &[Vec<u8>]) -> Vec<u8> {
+		let mut ihdr = Vec::new();
+		ihdr.extend_from_slice(&self.width.to_be_bytes());
+		ihdr.extend_from_slice(&self.height.to_be_bytes());
+		ihdr.extend_from_slice(&[self.bit_depth, self.color_type, 0, 0, self.interlaced as u8]);
+
+		let mut raw = Vec::new();
+		for line in scanlines {
+			raw.push(0);
+			raw.extend_from_slice(line);
+		}
+
+		let mut out = PNG_SIGNATURE.to_vec();
+		write_chunk(&mut out, b"IHDR", &ihdr);
+		for (kind, data) in &self.chunks_before_idat {
+			write_chunk(&mut out, kind, data);
+		}
+		write_chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+		write_chunk(&mut out, b"IEND", &[]);
+		out
+	}
+}
+
+
+/// Splits 8-bit-per-sample pixel data (`bpp` bytes per pixel) into the scanlines of the seven Adam7 passes.
+pub fn adam7_scanlines(width: u32, height: u32, bpp: usize, data: &[u8]) -> Vec<Vec<u8>> {
+	const PASSES: [(u32, u32, u32, u32); 7] = [(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)];
+
+	let mut lines = Vec::new();
+	for (x0, y0, dx, dy) in PASSES {
+		for y in (y0..height).step_by(dy as usize) {
+			let mut line = Vec::new();
+			for x in (x0..width).step_by(dx as usize) {
+				let offset = (y as usize * width as usize + x as usize) * bpp;
+				line.extend_from_slice(&data[offset..offset + bpp]);
+			}
+			if !line.is_empty() {
+				lines.push(line);
+			}
+		}
+	}
+	lines
+}
+
+
+pub fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+	out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+	out.extend_from_slice(kind);
+	out.extend_from_slice(data);
+	let mut crc_input = kind.to_vec();
+	crc_input.extend_from_slice(data);
+	out.extend_from_slice(&crc32(&crc_input).to_be_bytes());
+}
+
+
+/// A zlib stream made of uncompressed deflate blocks.
+pub fn zlib_stored(data: &[u8]) -> Vec<u8> {
+	let mut out = vec![0x78, 0x01];
+	let mut blocks = data.chunks(0xFFFF).peekable();
+	if blocks.peek().is_none() {
+		out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+	}
+	while let Some(block) = blocks.next() {
+		let len = block.len() as u16;
+		out.push(blocks.peek().is_none() as u8);
+		out.extend_from_slice(&len.to_le_bytes());
+		out.extend_from_slice(&(!len).to_le_bytes());
+		out.extend_from_slice(block);
+	}
+
+	let (mut a, mut b) = (1u32, 0u32);
+	for &byte in data {
+		a = (a + byte as u32) % 65521;
+		b = (b + a) % 65521;
+	}
+	out.extend_from_slice(&((b << 16) | a).to_be_bytes());
+	out
+}
+
+
+pub fn crc32(data: &[u8]) -> u32 {
+	let mut crc = !0u32;
+	for &byte in data {
+		crc ^= byte as u32;
+		for _ in 0..8 {
+			crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+		}
+	}
+	!crc
+}
@@ -0,0 +1,158 @@
+#![cfg(feature = "conformance")]
+
+use imgest::conformance::{self, DiffStats, PillowEnv, Profiles, Tolerances, diff_rgba8, diff_samples};
+
+
+/// Whether Pillow can be imported, which `compare` needs. Without it, the tests comparing against it are skipped.
+fn have_pillow() -> bool {
+	let have = PillowEnv::probe().is_ok();
+	if !have {
+		eprintln!("Pillow isn't installed in the active Python environment; skipping the comparison");
+	}
+	have
+}
+
+
+#[test]
+fn builtin_profiles() {
+	let profiles = Profiles::builtin();
+	for name in ["default", "ijg", "pillow-10", "strict", "lenient"] {
+		assert!(profiles.get(name).is_some(), "{name}");
+	}
+	assert_eq!(&Tolerances::default(), profiles.get("default").unwrap());
+	let strict = profiles.get("strict").unwrap();
+	assert_eq!(strict.border, 1);
+	assert_eq!(strict.edge_max_diff, 0);
+	assert!(strict.limits.is_empty());
+}
+
+
+#[test]
+fn profiles_from_toml() {
+	let profiles = Profiles::from_toml(
+		r#"
+		[custom]
+		edge_max_diff = 5
+		limits = [{ format = "webp", avg_diff = 0.1, max_diff = 3 }]
+		"#,
+	)
+	.unwrap();
+	let custom = profiles.get("custom").unwrap();
+	assert_eq!(custom.edge_max_diff, 5);
+	assert_eq!(custom.limits[0].format, "webp");
+	assert_eq!(custom.limits[0].bit_depth, None);
+
+	// Typos are errors rather than silently loosening or tightening a profile
+	assert!(Profiles::from_toml("[custom]\nedge_max_dif = 5\n").is_err());
+	assert!(Profiles::from_toml("[custom]\nedge_max_diff = 5\nlimits = [{ format = \"png\", avg = 0.1, max_diff = 1 }]\n").is_err());
+}
+
+
+#[test]
+fn edge_differences_are_separate() {
+	let theirs = vec![100; 4 * 4 * 4];
+	let mut ours = theirs.clone();
+	// Top left pixel, on the edge
+	ours[0] = 150;
+	// (1, 1), inside
+	ours[(4 + 1) * 4 + 2] = 103;
+	let stats = diff_rgba8(&ours, &theirs, 4, 4, 1);
+	assert_eq!(stats.max_edge, 50);
+	assert_eq!(stats.max_inner, 3);
+	assert_eq!(stats.mae, 53.0 / 64.0);
+	// 12 of the 16 pixels are on the edge
+	assert_eq!(stats.edge_mae, 50.0 / 48.0);
+
+	// A border of two swallows the whole image, and none holds every pixel to the inner limits
+	let stats = diff_rgba8(&ours, &theirs, 4, 4, 2);
+	assert_eq!((stats.max_edge, stats.max_inner), (50, 0));
+	let stats = diff_rgba8(&ours, &theirs, 4, 4, 0);
+	assert_eq!((stats.max_edge, stats.max_inner, stats.edge_mae), (0, 50, 0.0));
+}
+
+
+#[test]
+fn edge_limits() {
+	let tolerances = Profiles::from_toml("[p]\nborder = 2\nedge_max_diff = 40\nedge_avg_diff = 1.0\n").unwrap().get("p").unwrap().clone();
+	assert_eq!(tolerances.border, 2);
+	let format = image::ImageFormat::Png.into();
+	let stats = DiffStats {
+		max_edge: 40,
+		edge_mae: 0.5,
+		..Default::default()
+	};
+	assert!(tolerances.accepts(format, 8, &stats));
+	assert!(!tolerances.accepts(format, 8, &DiffStats { edge_mae: 1.5, ..stats.clone() }));
+	assert!(!tolerances.accepts(format, 8, &DiffStats { max_edge: 41, ..stats.clone() }));
+	// No limit matches, so away from the border it must be exact
+	assert!(!tolerances.accepts(format, 8, &DiffStats { max_inner: 1, ..stats.clone() }));
+}
+
+
+#[test]
+fn compare_png() {
+	if !have_pillow() {
+		return;
+	}
+	let dir = tempfile::tempdir().unwrap();
+	let path = dir.path().join("gradient.png");
+	image::RgbImage::from_fn(16, 8, |x, y| image::Rgb([x as u8 * 16, y as u8 * 32, 7])).save(&path).unwrap();
+
+	let comparison = conformance::compare(&path, &Tolerances::default()).unwrap();
+	assert_eq!(comparison.format, imgest::Format::Image(image::ImageFormat::Png));
+	assert!(!comparison.sixteen_bit);
+	assert_eq!(comparison.stats, DiffStats::default());
+}
+
+
+#[test]
+fn sixteen_bit_differences() {
+	// A difference of one 16-bit step, which disappears when both sides are reduced to 8 bits
+	let theirs: Vec<u16> = (0..9).map(|i| i * 7000).collect();
+	let mut ours = theirs.clone();
+	ours[4] += 1;
+	let stats = diff_samples(&ours, &theirs, 3, 3, 1, 1);
+	assert_eq!((stats.max_inner, stats.max_edge), (1, 0));
+	let reduced = |v: &[u16]| v.iter().map(|&v| (v >> 8) as u8).collect::<Vec<_>>();
+	assert_eq!(diff_samples(&reduced(&ours), &reduced(&theirs), 3, 3, 1, 1), DiffStats::default());
+
+	let format = image::ImageFormat::Png.into();
+	let profiles = Profiles::from_toml("[p]\nedge_max_diff = 80\nlimits_16bit = [{ format = \"tif\", avg_diff = 1.0, max_diff = 2 }]\n").unwrap();
+	let tolerances = profiles.get("p").unwrap();
+	// The built-in profiles and this one hold 16-bit PNGs to an exact match, border included
+	assert!(!Tolerances::default().accepts_16bit(format, &stats));
+	assert!(!tolerances.accepts_16bit(format, &DiffStats { max_edge: 1, ..Default::default() }));
+	assert!(tolerances.accepts_16bit(image::ImageFormat::Tiff.into(), &stats));
+}
+
+
+#[test]
+fn compare_16bit_grayscale() {
+	if !have_pillow() {
+		return;
+	}
+	let dir = tempfile::tempdir().unwrap();
+	let path = dir.path().join("gray16.png");
+	// Neighbouring values that only differ below the top 8 bits
+	image::ImageBuffer::<image::Luma<u16>, _>::from_fn(8, 8, |x, y| image::Luma([30000 + (x + 8 * y) as u16])).save(&path).unwrap();
+
+	let comparison = conformance::compare(&path, &Tolerances::default()).unwrap();
+	assert!(comparison.sixteen_bit);
+	assert_eq!(comparison.stats, DiffStats::default());
+}
+
+
+#[test]
+fn difference_histogram() {
+	let theirs = vec![100; 3 * 3 * 4];
+	// A systematic +1 on every red sample, and one sample of one pixel off by 40
+	let mut ours: Vec<u8> = theirs.chunks(4).flat_map(|p| [p[0] + 1, p[1], p[2], p[3]]).collect();
+	ours[4 * 4 + 1] = 140;
+	let stats = diff_rgba8(&ours, &theirs, 3, 3, 1);
+	assert_eq!(stats.differing_pixels, 9);
+	assert_eq!(stats.histogram.len(), 41);
+	assert_eq!((stats.histogram[0], stats.histogram[1], stats.histogram[40]), (26, 9, 1));
+	assert_eq!(stats.bias, 49.0 / 36.0);
+	assert_eq!(stats.histogram_summary(1), "1:9 ...");
+	assert_eq!(stats.histogram_summary(8), "1:9 40:1");
+}
@@ -0,0 +1,127 @@
+#![cfg(feature = "office")]
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use common::{crc32, encode_png, zlib_stored};
+use image::{DynamicImage, RgbImage};
+use imgest::container::{self, Container, ContainerKind};
+
+
+/// A zip archive of (name, contents, deflate) entries. Deflated entries use stored deflate blocks.
+fn zip(entries: &[(&str, &[u8], bool)]) -> Vec<u8> {
+	let mut out = Vec::new();
+	let mut directory = Vec::new();
+	for &(name, data, deflate) in entries {
+		let (method, compressed) = if deflate {
+			let zlib = zlib_stored(data);
+			(8u16, zlib[2..zlib.len() - 4].to_vec())
+		} else {
+			(0, data.to_vec())
+		};
+		// Version, flags, method, time, date, CRC, sizes, name length; the local header has no extra field
+		let mut fields = vec![20, 0, 0, 0];
+		fields.extend_from_slice(&method.to_le_bytes());
+		fields.extend_from_slice(&[0; 4]);
+		fields.extend_from_slice(&crc32(data).to_le_bytes());
+		fields.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+		fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+		fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+		fields.extend_from_slice(&[0, 0]);
+
+		directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+		directory.extend_from_slice(&[20, 0]);
+		directory.extend_from_slice(&fields);
+		// Comment length, disk, internal and external attributes, then the local header offset
+		directory.extend_from_slice(&[0; 10]);
+		directory.extend_from_slice(&(out.len() as u32).to_le_bytes());
+		directory.extend_from_slice(name.as_bytes());
+
+		out.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+		out.extend_from_slice(&fields);
+		out.extend_from_slice(name.as_bytes());
+		out.extend_from_slice(&compressed);
+	}
+	let offset = out.len() as u32;
+	out.extend_from_slice(&directory);
+	out.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+	out.extend_from_slice(&[0; 4]);
+	out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+	out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+	out.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+	out.extend_from_slice(&offset.to_le_bytes());
+	out.extend_from_slice(&[0, 0]);
+	out
+}
+
+
+fn image(seed: u8) -> DynamicImage {
+	DynamicImage::ImageRgb8(RgbImage::from_fn(5, 3, |x, y| image::Rgb([x as u8 * 40, y as u8 * 60, seed])))
+}
+
+
+fn docx() -> Vec<u8> {
+	zip(&[
+		("[Content_Types].xml", b"<Types/>", true),
+		("word/document.xml", b"<w:document/>", true),
+		("word/media/image1.png", &encode_png(&image(1)), false),
+		("word/media/image2.emf", b"not an image we decode", false),
+		("word/media/image3.PNG", &encode_png(&image(3)), true),
+		("customXml/thumbnail.png", &encode_png(&image(9)), false),
+	])
+}
+
+
+#[test]
+fn lists_and_decodes_office_images() {
+	let container = Container::from_vec(docx()).unwrap();
+	assert_eq!(container.kind(), ContainerKind::Docx);
+	assert_eq!(container.images(), ["word/media/image1.png", "word/media/image3.PNG"]);
+	assert_eq!(container.read("word/document.xml").unwrap(), b"<w:document/>");
+	assert!(container.read("word/missing.xml").is_err());
+
+	let decoded: Vec<_> = container.load_images(&imgest::LoadOptions::new()).map(|(name, result)| (name, result.unwrap().1)).collect();
+	assert_eq!(decoded, [("word/media/image1.png", image(1)), ("word/media/image3.PNG", image(3))]);
+
+	let pptx = zip(&[("ppt/presentation.xml", b"", false), ("ppt/media/image1.png", &encode_png(&image(1)), false)]);
+	assert_eq!(Container::from_vec(pptx).unwrap().kind(), ContainerKind::Pptx);
+	let epub = zip(&[
+		("mimetype", b"application/epub+zip", false),
+		("OEBPS/images/cover.jpg", b"", false),
+		("OEBPS/chapter1.xhtml", b"", true),
+	]);
+	let epub = Container::from_vec(epub).unwrap();
+	assert_eq!((epub.kind(), epub.images()), (ContainerKind::Epub, vec!["OEBPS/images/cover.jpg"]));
+
+	assert!(Container::from_vec(b"PK\x03\x04 but not really a zip".to_vec()).is_err());
+	// A deflated entry larger than its declared size
+	let mut bomb = zip(&[("a.png", &[0; 1000], true)]);
+	let at = bomb.len() - 22 - 46 - 5 + 24;
+	bomb[at..at + 4].copy_from_slice(&10u32.to_le_bytes());
+	assert!(Container::from_vec(bomb).unwrap().read("a.png").is_err());
+}
+
+
+#[test]
+fn expands_and_reads_entry_paths() {
+	let dir = tempfile::tempdir().unwrap();
+	let plain = dir.path().join("plain.png");
+	std::fs::write(&plain, encode_png(&image(0))).unwrap();
+	let docx_path = dir.path().join("report.docx");
+	std::fs::write(&docx_path, docx()).unwrap();
+	let broken = dir.path().join("broken.zip");
+	std::fs::write(&broken, b"not a zip").unwrap();
+
+	let expanded = container::expand(vec![plain.clone(), docx_path.clone(), broken.clone()]);
+	let entry = container::entry_path(&docx_path, "word/media/image3.PNG");
+	assert_eq!(expanded, [plain.clone(), container::entry_path(&docx_path, "word/media/image1.png"), entry.clone(), broken]);
+	assert_eq!(container::split_entry_path(&entry), Some((docx_path.as_path(), "word/media/image3.PNG")));
+	assert_eq!(container::split_entry_path(Path::new("dir!/file.png")), None);
+	assert_eq!(container::split_entry_path(&PathBuf::from("a.zip!/b.zip!/c.png")), Some((Path::new("a.zip"), "b.zip!/c.png")));
+
+	let (_, img) = imgest::load_image_from_vec(container::read(&entry).unwrap()).unwrap();
+	assert_eq!(img, image(3));
+	assert_eq!(container::read(&plain).unwrap(), std::fs::read(&plain).unwrap());
+	assert!(container::read(container::entry_path(&docx_path, "word/media/missing.png")).is_err());
+}
@@ -0,0 +1,192 @@
+use image::DynamicImage;
+use imgest::convert::{self, Cicp, Normalization, TensorLayout, YCbCrMatrix};
+use proptest::prelude::*;
+
+
+#[test]
+fn bit_depth_roundtrips() {
+	for v in 0..=255u8 {
+		assert_eq!(convert::u16_to_u8(convert::u8_to_u16(v)), v);
+		assert_eq!(convert::u16_to_u8_truncate(convert::u8_to_u16(v)), v);
+	}
+	assert_eq!(convert::u8_to_u16(255), 65535);
+}
+
+
+#[test]
+fn u16_to_u8_rounds() {
+	for v in 0..=u16::MAX {
+		let expected = (f64::from(v) * 255.0 / 65535.0).round() as u8;
+		assert_eq!(convert::u16_to_u8(v), expected, "{v}");
+	}
+	// Truncation and rounding disagree on values just below a multiple of 257
+	assert_eq!(convert::u16_to_u8(0x00ff), 1);
+	assert_eq!(convert::u16_to_u8_truncate(0x00ff), 0);
+}
+
+
+#[test]
+fn be16_to_native() {
+	let mut buf = [0x12, 0x34, 0xAB, 0xCD];
+	convert::be16_to_native_in_place(&mut buf);
+	assert_eq!(buf, [0x1234u16.to_ne_bytes(), 0xABCDu16.to_ne_bytes()].concat()[..]);
+}
+
+
+#[test]
+fn gray_and_rgb() {
+	assert_eq!(convert::gray_to_rgb(&[1u16, 2]), vec![1, 1, 1, 2, 2, 2]);
+	assert_eq!(convert::gray_alpha_to_rgba(&[10u8, 200, 20, 100]), vec![10, 10, 10, 200, 20, 20, 20, 100]);
+	assert_eq!(convert::rgb_to_gray(&[255, 255, 255, 0, 0, 0, 255, 0, 0], YCbCrMatrix::Bt601), vec![255, 0, 76]);
+	assert_eq!(convert::rgb_to_gray(&[255, 0, 0], YCbCrMatrix::Bt709), vec![54]);
+}
+
+
+#[test]
+fn luma8() {
+	// 16-bit samples are native endian and rounded; alpha is dropped
+	let la16: Vec<u8> = [0x00ffu16, 7].iter().flat_map(|v| v.to_ne_bytes()).collect();
+	let mut out = [0];
+	convert::row_to_luma8(&la16, image::ColorType::La16, &mut out);
+	assert_eq!(out, [1]);
+
+	let rgba = DynamicImage::ImageRgba8(image::RgbaImage::from_raw(2, 1, vec![255, 0, 0, 0, 255, 255, 255, 255]).unwrap());
+	assert_eq!(convert::into_luma8(rgba).as_raw(), &[76, 255]);
+	let rgb32f = DynamicImage::ImageRgb32F(image::Rgb32FImage::from_raw(1, 1, vec![1.0, 0.0, 0.0]).unwrap());
+	assert_eq!(convert::into_luma8(rgb32f).as_raw(), &[76]);
+}
+
+
+#[test]
+fn rgba8_rows() {
+	let la16: Vec<u8> = [0x00ffu16, 7].iter().flat_map(|v| v.to_ne_bytes()).collect();
+	let mut out = [0; 4];
+	convert::row_to_rgba8(&la16, image::ColorType::La16, &mut out);
+	assert_eq!(out, [1, 1, 1, 0]);
+
+	let mut out = [0; 8];
+	convert::row_to_rgba8(&[10, 20, 30, 40, 50, 60], image::ColorType::Rgb8, &mut out);
+	assert_eq!(out, [10, 20, 30, 255, 40, 50, 60, 255]);
+	convert::row_to_rgba8(&[9, 200], image::ColorType::L8, &mut out);
+	assert_eq!(out, [9, 9, 9, 255, 200, 200, 200, 255]);
+}
+
+
+#[test]
+fn f32_tensors() {
+	// Two RGB pixels
+	let rgb8 = DynamicImage::ImageRgb8(image::RgbImage::from_raw(2, 1, vec![0, 51, 255, 255, 102, 0]).unwrap());
+	assert_eq!(convert::to_f32(&rgb8, TensorLayout::Hwc, &Normalization::Unit), [0.0, 0.2, 1.0, 1.0, 0.4, 0.0]);
+	assert_eq!(convert::to_f32(&rgb8, TensorLayout::Chw, &Normalization::Unit), [0.0, 1.0, 0.2, 0.4, 1.0, 0.0]);
+	let centered = Normalization::MeanStd { mean: vec![0.5], std: vec![0.5] };
+	let tensor = convert::to_f32(&rgb8, TensorLayout::Hwc, &centered);
+	assert!(tensor.iter().zip([-1.0, -0.6, 1.0, 1.0, -0.2, -1.0]).all(|(a, b)| (a - b).abs() < 1e-6), "{tensor:?}");
+	let imagenet = convert::to_f32(&rgb8, TensorLayout::Chw, &Normalization::imagenet());
+	assert!((imagenet[0] - -0.485 / 0.229).abs() < 1e-6 && (imagenet[5] - -0.406 / 0.225).abs() < 1e-6);
+
+	// 16-bit samples keep the precision 8 bits would lose
+	let luma16 = DynamicImage::ImageLuma16(image::ImageBuffer::from_raw(2, 1, vec![1u16, 65535]).unwrap());
+	assert_eq!(convert::to_f32(&luma16, TensorLayout::Chw, &Normalization::Unit), [1.0 / 65535.0, 1.0]);
+}
+
+
+#[test]
+fn u8_tensors() {
+	let rgb8 = DynamicImage::ImageRgb8(image::RgbImage::from_raw(2, 1, vec![0, 51, 255, 255, 102, 0]).unwrap());
+	assert_eq!(convert::to_u8(&rgb8, TensorLayout::Hwc), [0, 51, 255, 255, 102, 0]);
+	assert_eq!(convert::to_u8(&rgb8, TensorLayout::Chw), [0, 255, 51, 102, 255, 0]);
+	let luma16 = DynamicImage::ImageLuma16(image::ImageBuffer::from_raw(2, 1, vec![0x1280u16, 65535]).unwrap());
+	assert_eq!(convert::to_u8(&luma16, TensorLayout::Chw), [0x12, 255]);
+	let rgb32f = DynamicImage::ImageRgb32F(image::ImageBuffer::from_raw(1, 1, vec![-1.0, 0.2, 2.0]).unwrap());
+	assert_eq!(convert::to_u8(&rgb32f, TensorLayout::Hwc), [0, 51, 255]);
+}
+
+
+#[test]
+#[should_panic]
+fn f32_tensor_normalization_must_match_channels() {
+	let rgba8 = DynamicImage::ImageRgba8(image::RgbaImage::new(1, 1));
+	convert::to_f32(&rgba8, convert::TensorLayout::Hwc, &convert::Normalization::imagenet());
+}
+
+
+#[test]
+fn ycbcr_reference_values() {
+	// Primaries under full range BT.601 (JFIF)
+	assert_eq!(convert::rgb_to_ycbcr(255, 0, 0, YCbCrMatrix::Bt601), [76, 85, 255]);
+	assert_eq!(convert::rgb_to_ycbcr(0, 0, 255, YCbCrMatrix::Bt601), [29, 255, 107]);
+	assert_eq!(convert::ycbcr_to_rgb(128, 128, 128, YCbCrMatrix::Bt601), [128, 128, 128]);
+	assert_eq!(convert::ycbcr_to_rgb(255, 128, 128, YCbCrMatrix::Bt709), [255, 255, 255]);
+
+	let mut buf = [76, 85, 255, 0, 128, 128];
+	convert::ycbcr_to_rgb_in_place(&mut buf, YCbCrMatrix::Bt601);
+	assert_eq!(buf, [254, 0, 0, 0, 0, 0]);
+}
+
+
+#[test]
+fn cicp_matrices() {
+	let cicp = |matrix_coefficients| Cicp {
+		color_primaries: 2,
+		transfer_characteristics: 2,
+		matrix_coefficients,
+		full_range: true,
+	};
+	assert_eq!(cicp(0).ycbcr_matrix(), None);
+	assert_eq!(cicp(1).ycbcr_matrix(), Some(YCbCrMatrix::Bt709));
+	assert_eq!(cicp(6).ycbcr_matrix(), Some(YCbCrMatrix::Bt601));
+	assert_eq!(cicp(9).ycbcr_matrix(), Some(YCbCrMatrix::Bt2020));
+	assert_eq!(cicp(2).ycbcr_matrix(), Some(YCbCrMatrix::Bt601));
+
+	// Saturated red lands on a different luma under each matrix
+	assert_eq!(convert::rgb_to_gray(&[255, 0, 0], YCbCrMatrix::Bt2020), vec![67]);
+	let [r, g, b] = convert::ycbcr_to_rgb_f32(0.5, 0.0, 0.0, YCbCrMatrix::Bt2020);
+	assert!((r - 0.5).abs() < 1e-6 && (g - 0.5).abs() < 1e-6 && (b - 0.5).abs() < 1e-6);
+}
+
+
+#[test]
+fn srgb_transfer() {
+	assert_eq!(convert::srgb8_to_linear(0), 0.0);
+	assert_eq!(convert::srgb8_to_linear(255), 1.0);
+	assert!((convert::srgb8_to_linear(128) - 0.2158605).abs() < 1e-6);
+	for v in 0..=255u8 {
+		assert_eq!(convert::linear_to_srgb8(convert::srgb8_to_linear(v)), v);
+	}
+	assert_eq!(convert::linear_to_srgb8(-1.0), 0);
+	assert_eq!(convert::linear_to_srgb8(2.0), 255);
+}
+
+
+proptest! {
+	#[test]
+	fn gray_alpha_fast_paths_match_image(samples in prop::collection::vec(any::<u16>(), 0..64)) {
+		let pixels = samples.len() / 2;
+		let samples = &samples[..pixels * 2];
+		let la16 = DynamicImage::ImageLumaA16(image::ImageBuffer::from_raw(pixels as u32, 1, samples.to_vec()).unwrap());
+		let la8 = DynamicImage::ImageLumaA8(la16.to_luma_alpha8());
+		for img in [la16, la8] {
+			prop_assert_eq!(convert::into_rgba8(img.clone()), img.to_rgba8());
+		}
+	}
+
+	#[test]
+	fn ycbcr_roundtrip_is_close(r in any::<u8>(), g in any::<u8>(), b in any::<u8>(), matrix in prop_oneof![Just(YCbCrMatrix::Bt601), Just(YCbCrMatrix::Bt709), Just(YCbCrMatrix::Bt2020)]) {
+		let [y, cb, cr] = convert::rgb_to_ycbcr(r, g, b, matrix);
+		let back = convert::ycbcr_to_rgb(y, cb, cr, matrix);
+		// 8-bit YCbCr can't represent every RGB triple, so allow the quantization error
+		for (a, b) in back.iter().zip([r, g, b]) {
+			prop_assert!(a.abs_diff(b) <= 2, "{:?} -> {:?} -> {:?}", [r, g, b], [y, cb, cr], back);
+		}
+	}
+}
+
+
+proptest! {
+	#[test]
+	fn reinhard_is_monotonic_and_bounded(a in 0.0f32..1e6, b in 0.0f32..1e6) {
+		let (lo, hi) = if a <= b { (a, b) } else { (b, a) };
+		prop_assert!(convert::reinhard(lo) <= convert::reinhard(hi));
+		prop_assert!((0.0..=1.0).contains(&convert::reinhard(hi)));
+	}
+}
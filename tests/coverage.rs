@@ -0,0 +1,150 @@
+//! Asserts that the synthetic fixture corpus exercises every decoder branch reported by `imgest::coverage`.
+#![cfg(feature = "testing")]
+
+mod common;
+
+use std::{collections::HashSet, io::Cursor};
+
+use common::{RawPng, adam7_scanlines, encode_png, zlib_stored};
+use image::{DynamicImage, ExtendedColorType, ImageEncoder, RgbImage, codecs::jpeg::JpegEncoder};
+use imgest::coverage::{self, Branch};
+
+
+const WIDTH: u32 = 19;
+const HEIGHT: u32 = 13;
+
+
+fn gradient() -> RgbImage {
+	RgbImage::from_fn(WIDTH, HEIGHT, |x, y| image::Rgb([(x * 13) as u8, (y * 19) as u8, ((x + y) * 7) as u8]))
+}
+
+
+fn png_fixtures() -> Vec<(&'static str, Vec<u8>)> {
+	let rgb = DynamicImage::ImageRgb8(gradient());
+
+	let interlaced = {
+		let mut png = RawPng::new(WIDTH, HEIGHT, 8, 2);
+		png.interlaced = true;
+		png.encode(&adam7_scanlines(WIDTH, HEIGHT, 3, rgb.as_bytes()))
+	};
+
+	let palette = {
+		let mut out = Vec::new();
+		let mut encoder = png::Encoder::new(&mut out, 4, 2);
+		encoder.set_color(png::ColorType::Indexed);
+		encoder.set_depth(png::BitDepth::Two);
+		encoder.set_palette(vec![0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0, 255]);
+		let mut writer = encoder.write_header().unwrap();
+		writer.write_image_data(&[0b00_01_10_11, 0b11_10_01_00]).unwrap();
+		writer.finish().unwrap();
+		out
+	};
+
+	let icc = {
+		let mut iccp = b"profile\0\0".to_vec();
+		iccp.extend_from_slice(&zlib_stored(&[0; 128]));
+		RawPng::new(WIDTH, HEIGHT, 8, 2)
+			.chunk(b"iCCP", &iccp)
+			.encode(&rgb.as_bytes().chunks(WIDTH as usize * 3).map(<[u8]>::to_vec).collect::<Vec<_>>())
+	};
+
+	let animated = {
+		let mut out = Vec::new();
+		let mut encoder = png::Encoder::new(&mut out, 2, 2);
+		encoder.set_color(png::ColorType::Grayscale);
+		encoder.set_animated(2, 0).unwrap();
+		let mut writer = encoder.write_header().unwrap();
+		writer.write_image_data(&[0; 4]).unwrap();
+		writer.write_image_data(&[255; 4]).unwrap();
+		writer.finish().unwrap();
+		out
+	};
+
+	vec![
+		("png rgb8", encode_png(&rgb)),
+		("png rgb16", encode_png(&DynamicImage::ImageRgb16(rgb.to_rgb16()))),
+		("png interlaced", interlaced),
+		("png palette", palette),
+		("png icc", icc),
+		("png animated", animated),
+	]
+}
+
+
+fn jpeg_fixtures() -> Vec<(&'static str, Vec<u8>)> {
+	let rgb = gradient();
+
+	let baseline = {
+		let mut out = Vec::new();
+		JpegEncoder::new(&mut out).write_image(rgb.as_raw(), WIDTH, HEIGHT, ExtendedColorType::Rgb8).unwrap();
+		out
+	};
+
+	let grayscale_icc = {
+		let gray = DynamicImage::ImageRgb8(rgb.clone()).to_luma8();
+		let mut out = Vec::new();
+		let mut encoder = JpegEncoder::new(&mut out);
+		encoder.set_icc_profile(vec![0; 128]).unwrap();
+		encoder.write_image(gray.as_raw(), WIDTH, HEIGHT, ExtendedColorType::L8).unwrap();
+		out
+	};
+
+	let progressive = {
+		let mut out = Vec::new();
+		let mut encoder = jpeg_encoder::Encoder::new(&mut out, 90);
+		encoder.set_progressive(true);
+		encoder.encode(rgb.as_raw(), WIDTH as u16, HEIGHT as u16, jpeg_encoder::ColorType::Rgb).unwrap();
+		out
+	};
+
+	vec![("jpeg baseline", baseline), ("jpeg grayscale icc", grayscale_icc), ("jpeg progressive", progressive)]
+}
+
+
+fn other_fixtures() -> Vec<(&'static str, Vec<u8>)> {
+	let rgb = DynamicImage::ImageRgb8(gradient());
+
+	let mut webp = Vec::new();
+	rgb.write_to(&mut Cursor::new(&mut webp), image::ImageFormat::WebP).unwrap();
+	let mut bmp = Vec::new();
+	rgb.write_to(&mut Cursor::new(&mut bmp), image::ImageFormat::Bmp).unwrap();
+
+	vec![("webp", webp), ("bmp", bmp)]
+}
+
+
+#[test]
+fn fixture_corpus_covers_every_branch() {
+	let mut covered = HashSet::new();
+
+	for (name, data) in png_fixtures().into_iter().chain(jpeg_fixtures()).chain(other_fixtures()) {
+		coverage::take();
+		let result = imgest::load_image_from_reader(Cursor::new(&data));
+		let hits = coverage::take();
+
+		if name == "png animated" {
+			assert!(matches!(result, Err(imgest::Error::Animated)), "{name}: expected animated rejection");
+		} else {
+			result.unwrap_or_else(|e| panic!("{name}: failed to decode: {e}"));
+		}
+		assert!(!hits.is_empty(), "{name}: no branches recorded");
+		covered.extend(hits);
+	}
+
+	let missing: Vec<_> = Branch::ALL.iter().filter(|branch| !covered.contains(branch)).collect();
+	assert!(missing.is_empty(), "fixture corpus never exercised: {missing:?}");
+}
+
+
+#[test]
+fn interlaced_png_matches_source() {
+	let rgb = gradient();
+	let mut png = RawPng::new(WIDTH, HEIGHT, 8, 2);
+	png.interlaced = true;
+	let data = png.encode(&adam7_scanlines(WIDTH, HEIGHT, 3, rgb.as_raw()));
+
+	coverage::take();
+	let (_, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	assert!(coverage::take().contains(&Branch::PngInterlaced));
+	assert_eq!(img.to_rgb8(), rgb);
+}
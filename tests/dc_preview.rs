@@ -0,0 +1,76 @@
+use std::io::Cursor;
+
+use image::{DynamicImage, RgbImage};
+use imgest::{DctScale, JpegDecoder, JpegOptions};
+
+
+fn gradient() -> RgbImage {
+	RgbImage::from_fn(83, 61, |x, y| image::Rgb([(x * 3) as u8, (y * 4) as u8, ((x + y) * 2) as u8]))
+}
+
+
+fn encode(img: &DynamicImage, configure: impl FnOnce(&mut jpeg_encoder::Encoder<&mut Vec<u8>>)) -> Vec<u8> {
+	let mut out = Vec::new();
+	let mut encoder = jpeg_encoder::Encoder::new(&mut out, 95);
+	configure(&mut encoder);
+	let color_type = if img.color().has_color() { jpeg_encoder::ColorType::Rgb } else { jpeg_encoder::ColorType::Luma };
+	encoder.encode(img.as_bytes(), img.width() as u16, img.height() as u16, color_type).unwrap();
+	out
+}
+
+
+/// Mean absolute difference between the DC preview and a full decode box filtered to 1/8 scale.
+fn preview_error(data: &[u8]) -> f64 {
+	let preview = JpegDecoder::new(Cursor::new(data)).unwrap().dc_preview().unwrap();
+	let options = JpegOptions {
+		dct_scale: DctScale::Eighth,
+		..Default::default()
+	};
+	let scaled = DynamicImage::from_decoder(JpegDecoder::with_options(Cursor::new(data), &options).unwrap()).unwrap();
+	assert_eq!((preview.width(), preview.height(), preview.color()), (scaled.width(), scaled.height(), scaled.color()));
+	let (a, b) = (preview.as_bytes(), scaled.as_bytes());
+	a.iter().zip(b).map(|(&a, &b)| f64::from(a.abs_diff(b))).sum::<f64>() / a.len() as f64
+}
+
+
+/// Mean absolute difference between the DC preview and 8x8 block means of jpeg-decoder's full decode.
+///
+/// zune-jpeg drops the chroma of progressive files with restart intervals, so those are checked against jpeg-decoder instead.
+fn reference_error(data: &[u8]) -> f64 {
+	let preview = JpegDecoder::new(Cursor::new(data)).unwrap().dc_preview().unwrap().to_rgb8();
+	let full = RgbImage::from_raw(83, 61, jpeg_decoder::Decoder::new(Cursor::new(data)).decode().unwrap()).unwrap();
+	let mut total = 0.0;
+	for (x, y, pixel) in preview.enumerate_pixels() {
+		let block: Vec<_> = (y * 8..(y * 8 + 8).min(full.height())).flat_map(|y| (x * 8..(x * 8 + 8).min(full.width())).map(move |x| (x, y))).collect();
+		for c in 0..3 {
+			let mean = block.iter().map(|&(x, y)| f64::from(full.get_pixel(x, y)[c])).sum::<f64>() / block.len() as f64;
+			total += (f64::from(pixel[c]) - mean).abs();
+		}
+	}
+	total / (preview.len() as f64)
+}
+
+
+#[test]
+fn baseline_matches_scaled_decode() {
+	let img = DynamicImage::ImageRgb8(gradient());
+	// Chroma subsampled, then not
+	assert!(preview_error(&encode(&img, |_| ())) < 2.0);
+	assert!(preview_error(&encode(&img, |e| e.set_sampling_factor(jpeg_encoder::SamplingFactor::R_4_4_4))) < 2.0);
+	assert!(preview_error(&encode(&img.grayscale(), |_| ())) < 2.0);
+	// The last scan runs to the end of a file without an EOI
+	let data = encode(&img, |_| ());
+	assert!(preview_error(&data[..data.len() - 2]) < 2.0);
+}
+
+
+#[test]
+fn progressive_and_restart_intervals() {
+	let img = DynamicImage::ImageRgb8(gradient());
+	assert!(preview_error(&encode(&img, |e| e.set_progressive(true))) < 2.0);
+	assert!(preview_error(&encode(&img, |e| e.set_restart_interval(3))) < 2.0);
+	assert!(reference_error(&encode(&img, |e| {
+		e.set_progressive(true);
+		e.set_restart_interval(2);
+	})) < 2.0);
+}
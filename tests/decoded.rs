@@ -0,0 +1,136 @@
+mod common;
+
+use std::io::Cursor;
+
+use common::RawPng;
+use image::metadata::Orientation;
+use imgest::{DecodedPixels, LoadOptions, OutputColor, OutputDepth};
+
+
+/// Big endian EXIF with just an Orientation of 6 (rotate 90° clockwise to display).
+const EXIF_ROTATE_90: &[u8] = b"MM\0*\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0\x06\0\0\0\0\0\0";
+
+
+#[test]
+fn png_metadata_is_returned() {
+	let xmp = b"XML:com.adobe.xmp\0\0\0\0\0<x:xmpmeta/>";
+	let data = RawPng::new(3, 2, 16, 0)
+		.chunk(b"gAMA", &45455u32.to_be_bytes())
+		.chunk(b"eXIf", EXIF_ROTATE_90)
+		.chunk(b"iTXt", xmp)
+		.encode(&[vec![0x12, 0x34, 0, 0, 0xFF, 0xFF], vec![0; 6]]);
+
+	let decoded = imgest::load_image_from_reader_with_metadata(Cursor::new(&data), &LoadOptions::new().output(OutputColor::Rgba8)).unwrap();
+	assert_eq!(decoded.format, image::ImageFormat::Png);
+	assert_eq!(decoded.image.color(), image::ColorType::Rgba8);
+	// Reported for the decoded samples, before the output conversion
+	assert!(decoded.is_16bit);
+	assert!((decoded.gamma.unwrap() - 0.45455).abs() < 1e-6);
+	assert_eq!(decoded.exif.as_deref(), Some(EXIF_ROTATE_90));
+	assert_eq!(decoded.xmp.as_deref(), Some(&b"<x:xmpmeta/>"[..]));
+	assert_eq!(decoded.orientation, Orientation::Rotate90);
+	assert_eq!(decoded.icc_profile, None);
+	assert_eq!(decoded.iptc, None);
+
+	let (_, img) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &LoadOptions::new().output(OutputColor::Rgba8)).unwrap();
+	assert_eq!(decoded.image, img);
+}
+
+
+#[test]
+fn formats_without_metadata() {
+	let img = image::DynamicImage::ImageRgb8(image::RgbImage::new(4, 4));
+	let mut data = Vec::new();
+	img.write_to(&mut Cursor::new(&mut data), image::ImageFormat::Bmp).unwrap();
+
+	let decoded = imgest::load_image_from_reader_with_metadata(Cursor::new(&data), &LoadOptions::new()).unwrap();
+	assert_eq!((decoded.format, decoded.image), (image::ImageFormat::Bmp.into(), img));
+	assert!(decoded.exif.is_none() && decoded.gamma.is_none() && !decoded.is_16bit);
+	assert_eq!(decoded.orientation, Orientation::NoTransforms);
+}
+
+
+#[test]
+fn output_depth_and_u16_pixels() {
+	// 16-bit gray, with samples whose low byte matters
+	let data = RawPng::new(2, 1, 16, 0).encode(&[vec![0x12, 0x34, 0xFF, 0x01]]);
+	let load = |depth| {
+		let options = LoadOptions::new().output_depth(depth);
+		imgest::load_image_from_reader_with_metadata(Cursor::new(&data), &options).unwrap().into_pixels()
+	};
+	assert_eq!(load(OutputDepth::Preserve).as_u16(), Some(&[0x1234, 0xFF01][..]));
+	let decoded = imgest::load_image_from_reader_with_metadata(Cursor::new(&data), &LoadOptions::new()).unwrap();
+	let tensor = decoded.to_f32(imgest::convert::TensorLayout::Chw, &imgest::convert::Normalization::Unit);
+	assert_eq!(tensor, [f32::from(0x1234u16) / 65535.0, f32::from(0xFF01u16) / 65535.0]);
+	assert_eq!(load(OutputDepth::Force16).into_u16(), Ok(vec![0x1234, 0xFF01]));
+	let eight = load(OutputDepth::Force8);
+	assert_eq!((eight.bit_depth(), eight.as_u8()), (8, Some(&[0x12, 0xFE][..])));
+	assert!(eight.into_u16().is_err());
+
+	// 8-bit images are widened, keeping their channels, and after `output`
+	let rgb = image::DynamicImage::ImageRgb8(image::RgbImage::from_raw(1, 1, vec![0, 128, 255]).unwrap());
+	let mut data = Vec::new();
+	rgb.write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png).unwrap();
+	let options = LoadOptions::new().output_depth(OutputDepth::Force16);
+	let (_, img) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &options).unwrap();
+	assert_eq!(DecodedPixels::from(img), DecodedPixels::U16(vec![0, 0x8080, 0xFFFF]));
+	let (_, img) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &options.output(OutputColor::Luma8)).unwrap();
+	assert_eq!(img.color(), image::ColorType::L16);
+}
+
+
+#[test]
+fn raw_keeps_the_decoder_color_type() {
+	let data = RawPng::new(2, 1, 16, 0).encode(&[vec![0x12, 0x34, 0xFF, 0x01]]);
+	// Options working on the decoded image are left out
+	let options = LoadOptions::new().output(OutputColor::Rgba8).output_depth(OutputDepth::Force8);
+	let (format, raw) = imgest::load_raw_from_reader_with_options(Cursor::new(&data), &options).unwrap();
+	assert_eq!((format, raw.width, raw.height, raw.color_type), (image::ImageFormat::Png.into(), 2, 1, image::ColorType::L16));
+	let native: Vec<u8> = [0x1234u16, 0xFF01].iter().flat_map(|v| v.to_ne_bytes()).collect();
+	assert_eq!(raw.data, native);
+
+	let (_, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!(raw.data, img.as_bytes());
+
+	// Formats going through the `image` crate
+	let rgb = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(3, 2, |x, y| image::Rgb([x as u8, y as u8, 9])));
+	let mut bmp = Vec::new();
+	rgb.write_to(&mut Cursor::new(&mut bmp), image::ImageFormat::Bmp).unwrap();
+	let (_, raw) = imgest::load_raw_from_reader(Cursor::new(&bmp)).unwrap();
+	assert_eq!((raw.color_type, raw.data.as_slice()), (image::ColorType::Rgb8, rgb.as_bytes()));
+
+	let options = LoadOptions::new().max_pixels(5);
+	assert!(matches!(imgest::load_raw_from_reader_with_options(Cursor::new(&bmp), &options), Err(imgest::Error::TooBig)));
+}
+
+
+#[test]
+fn raw_into_caller_allocated_buffers() {
+	use std::cell::Cell;
+
+	use imgest::allocator::OutputAllocator;
+
+	/// Hands out boxed slices, counting them
+	struct Counting(Cell<usize>);
+
+	impl OutputAllocator for Counting {
+		type Buffer = Box<[u8]>;
+
+		fn allocate(&self, len: usize) -> Result<Box<[u8]>, imgest::Error> {
+			self.0.set(self.0.get() + 1);
+			Ok(vec![0xAA; len].into_boxed_slice())
+		}
+	}
+
+	let data = RawPng::new(2, 1, 16, 0).encode(&[vec![0x12, 0x34, 0xFF, 0x01]]);
+	let allocator = Counting(Cell::new(0));
+	let (_, raw) = imgest::load_raw_from_reader_with_allocator(Cursor::new(&data), &LoadOptions::new(), &allocator).unwrap();
+	let (_, expected) = imgest::load_raw_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!((raw.color_type, &raw.data[..]), (expected.color_type, &expected.data[..]));
+	assert_eq!(allocator.0.get(), 1);
+
+	// Nothing is allocated for images over the limits
+	let options = LoadOptions::new().max_pixels(1);
+	assert!(imgest::load_raw_from_reader_with_allocator(Cursor::new(&data), &options, &allocator).is_err());
+	assert_eq!(allocator.0.get(), 1);
+}
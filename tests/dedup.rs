@@ -0,0 +1,69 @@
+use imgest::{
+	dedup::{Cluster, DedupEntry, DedupIndex},
+	phash::PHash,
+};
+
+
+fn entry(name: &str, hash: u64, width: u32, height: u32, file_size: u64) -> DedupEntry {
+	DedupEntry {
+		path: name.into(),
+		hash: PHash(hash),
+		width,
+		height,
+		file_size,
+	}
+}
+
+
+#[test]
+fn chains_form_one_cluster() {
+	let mut index = DedupIndex::new();
+	// a-b and b-c are within 3 bits, a-c isn't; d is unrelated
+	index.insert(entry("a", 0, 100, 100, 5_000));
+	index.insert(entry("b", 0b111, 200, 200, 9_000));
+	index.insert(entry("c", 0b111_111, 100, 100, 5_000));
+	index.insert(entry("d", u64::MAX, 100, 100, 5_000));
+
+	assert_eq!(index.near_duplicates(3), vec![(0, 1), (1, 2)]);
+	let clusters = index.clusters(3);
+	assert_eq!(
+		clusters,
+		vec![Cluster {
+			representative: 1,
+			members: vec![0, 1, 2],
+		}]
+	);
+	assert_eq!(clusters[0].redundant().collect::<Vec<_>>(), vec![0, 2]);
+	assert!(index.clusters(2).is_empty());
+}
+
+
+#[test]
+fn equal_resolution_prefers_least_compressed() {
+	let mut index = DedupIndex::new();
+	index.insert(entry("small.jpg", 0xABCD, 640, 480, 40_000));
+	index.insert(entry("large.png", 0xABCD, 640, 480, 400_000));
+	index.insert(entry("same.jpg", 0xABCD, 640, 480, 40_000));
+	assert_eq!(index.clusters(0)[0].representative, 1);
+}
+
+
+#[test]
+fn candidates_match_brute_force() {
+	let mut index = DedupIndex::new();
+	let mut state = 0x1234_5678_9abc_def0u64;
+	for i in 0..200 {
+		state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+		// Every fourth hash is a near copy of the previous one
+		let hash = if i % 4 == 3 { index.entries()[i - 1].hash.0 ^ (state & 0x0101_0001) } else { state };
+		index.insert(entry("", hash, 1, 1, 1));
+	}
+	for max_distance in [0, 2, 5, 12] {
+		let entries = index.entries();
+		let expected: Vec<_> = (0..entries.len())
+			.flat_map(|a| (a + 1..entries.len()).map(move |b| (a, b)))
+			.filter(|&(a, b)| entries[a].hash.distance(entries[b].hash) <= max_distance)
+			.collect();
+		assert_eq!(index.near_duplicates(max_distance), expected, "{max_distance}");
+	}
+}
@@ -0,0 +1,36 @@
+use image::{DynamicImage, GrayImage, ImageBuffer, Luma};
+use imgest::digest::{ManifestEntry, PixelDigest};
+
+
+#[test]
+fn digests_cover_pixels_and_layout() {
+	let img = DynamicImage::ImageLuma8(GrayImage::from_raw(2, 2, vec![1, 2, 3, 4]).unwrap());
+	let digest = PixelDigest::of(&img);
+	assert_eq!(digest, PixelDigest::of(&img.clone()));
+	assert_ne!(digest, PixelDigest::of(&DynamicImage::ImageLuma8(GrayImage::from_raw(2, 2, vec![1, 2, 3, 5]).unwrap())));
+	assert_ne!(digest, PixelDigest::of(&DynamicImage::ImageLuma8(GrayImage::from_raw(4, 1, vec![1, 2, 3, 4]).unwrap())));
+	let wide: ImageBuffer<Luma<u16>, Vec<u16>> = ImageBuffer::from_raw(1, 2, vec![0x0201, 0x0403]).unwrap();
+	assert_ne!(digest, PixelDigest::of(&DynamicImage::ImageLuma16(wide)));
+	assert_eq!(digest.to_string().parse::<PixelDigest>(), Ok(digest));
+}
+
+
+#[test]
+fn manifest_entries_roundtrip() {
+	let digest = PixelDigest([0xab; 32]);
+	let entry = ManifestEntry {
+		digest: Some(digest),
+		path: "a/b c.png".into(),
+	};
+	assert_eq!(entry.to_string(), format!("{}  a/b c.png", "ab".repeat(32)));
+	assert_eq!(entry.to_string().parse(), Ok(entry));
+
+	let failed = ManifestEntry {
+		digest: None,
+		path: "a/c.png".into(),
+	};
+	assert_eq!(failed.to_string(), "failed  a/c.png");
+	assert_eq!("failed  a/c.png".parse(), Ok(failed));
+	assert!("abc  a/d.png".parse::<ManifestEntry>().is_err());
+	assert!("a/d.png".parse::<ManifestEntry>().is_err());
+}
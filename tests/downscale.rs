@@ -0,0 +1,99 @@
+mod common;
+
+use std::io::Cursor;
+
+use common::{RawPng, adam7_scanlines};
+use image::{ExtendedColorType, GenericImageView, ImageEncoder, RgbImage, codecs::jpeg::JpegEncoder};
+use imgest::{DctScale, JpegDecoder, LoadOptions, OutputColor, PngDecoder};
+
+
+const WIDTH: u32 = 100;
+const HEIGHT: u32 = 61;
+
+
+fn gradient() -> RgbImage {
+	RgbImage::from_fn(WIDTH, HEIGHT, |x, y| image::Rgb([(x * 2) as u8, (y * 4) as u8, ((x * y) % 256) as u8]))
+}
+
+
+/// Box filters 8-bit RGB by `factor`, clipping blocks at the edges.
+fn box_filter(img: &RgbImage, factor: u32) -> RgbImage {
+	RgbImage::from_fn(img.width().div_ceil(factor), img.height().div_ceil(factor), |ox, oy| {
+		let xs = ox * factor..((ox + 1) * factor).min(img.width());
+		let ys = oy * factor..((oy + 1) * factor).min(img.height());
+		let count = xs.len() as u32 * ys.len() as u32;
+		image::Rgb(std::array::from_fn(|c| {
+			let sum: u32 = ys.clone().flat_map(|y| xs.clone().map(move |x| (x, y))).map(|(x, y)| u32::from(img.get_pixel(x, y)[c])).sum();
+			((sum + count / 2) / count) as u8
+		}))
+	})
+}
+
+
+#[test]
+fn png_is_reduced_while_streaming() {
+	let img = gradient();
+	let rows: Vec<Vec<u8>> = img.as_raw().chunks_exact(WIDTH as usize * 3).map(<[u8]>::to_vec).collect();
+	let plain = RawPng::new(WIDTH, HEIGHT, 8, 2).encode(&rows);
+	let mut interlaced = RawPng::new(WIDTH, HEIGHT, 8, 2);
+	interlaced.interlaced = true;
+	let interlaced = interlaced.encode(&adam7_scanlines(WIDTH, HEIGHT, 3, img.as_raw()));
+
+	for data in [&plain, &interlaced] {
+		// The largest factor keeping the long side at least 30 is 3
+		let (_, reduced) = imgest::load_image_from_bytes_with_options(data, &LoadOptions::new().target_max_dimension(30)).unwrap();
+		assert_eq!(reduced.dimensions(), (34, 21));
+		assert_eq!(reduced.as_rgb8().unwrap(), &box_filter(&img, 3));
+
+		let direct = PngDecoder::new(Cursor::new(data)).unwrap().decode_downscaled(3).unwrap();
+		assert_eq!(direct, reduced);
+
+		// Targets at or above the size leave it alone
+		let (_, full) = imgest::load_image_from_bytes_with_options(data, &LoadOptions::new().target_max_dimension(WIDTH)).unwrap();
+		assert_eq!(full.as_rgb8().unwrap(), &img);
+	}
+}
+
+
+#[test]
+fn png_16bit() {
+	let samples: Vec<u16> = (0..WIDTH * HEIGHT).map(|i| (i * 10) as u16).collect();
+	let rows: Vec<Vec<u8>> = samples.chunks_exact(WIDTH as usize).map(|row| row.iter().flat_map(|s| s.to_be_bytes()).collect()).collect();
+	let data = RawPng::new(WIDTH, HEIGHT, 16, 0).encode(&rows);
+
+	let reduced = PngDecoder::new(Cursor::new(&data)).unwrap().decode_downscaled(2).unwrap();
+	let reduced = reduced.as_luma16().unwrap();
+	assert_eq!(reduced.dimensions(), (50, 31));
+	let mean = |x: u32, y: u32| (samples[(y * WIDTH + x) as usize] as u32 + samples[(y * WIDTH + x + 1) as usize] as u32) as f64 / 2.0;
+	assert_eq!(f64::from(reduced.get_pixel(3, 2)[0]), ((mean(6, 4) + mean(6, 5)) / 2.0).round());
+	// The last row only has one source row
+	assert_eq!(f64::from(reduced.get_pixel(3, 30)[0]), mean(6, 60).round());
+
+	// A 300x300 block of white sums past u32::MAX
+	let white = RawPng::new(300, 300, 16, 0).encode(&vec![vec![0xFF; 600]; 300]);
+	let reduced = PngDecoder::new(Cursor::new(&white)).unwrap().decode_downscaled(300).unwrap();
+	assert_eq!(reduced.as_luma16().unwrap().as_raw(), &[u16::MAX]);
+}
+
+
+#[test]
+fn jpeg_is_reduced() {
+	let img = RgbImage::from_fn(320, 200, |x, y| image::Rgb([x as u8, y as u8, 128]));
+	let mut data = Vec::new();
+	JpegEncoder::new_with_quality(&mut data, 90).write_image(img.as_raw(), 320, 200, ExtendedColorType::Rgb8).unwrap();
+
+	// Half is the largest reduction keeping 100 pixels
+	let (_, half) = imgest::load_image_from_bytes_with_options(&data, &LoadOptions::new().target_max_dimension(100)).unwrap();
+	assert_eq!(half.dimensions(), (160, 100));
+	let (_, luma) = imgest::load_image_from_bytes_with_options(&data, &LoadOptions::new().target_max_dimension(100).output(OutputColor::Luma8)).unwrap();
+	assert_eq!(luma.dimensions(), (160, 100));
+
+	// An eighth takes the DC-only path
+	let (_, eighth) = imgest::load_image_from_bytes_with_options(&data, &LoadOptions::new().target_max_dimension(40)).unwrap();
+	assert_eq!(eighth, JpegDecoder::new(Cursor::new(&data)).unwrap().dc_preview().unwrap());
+	assert_eq!(eighth.dimensions(), (40, 25));
+
+	assert_eq!(DctScale::for_target(320, 200, 321), DctScale::Full);
+	assert_eq!(DctScale::for_target(320, 200, 80), DctScale::Quarter);
+	assert_eq!(DctScale::for_target(320, 200, 1), DctScale::Eighth);
+}
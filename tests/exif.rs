@@ -0,0 +1,296 @@
+use std::path::Path;
+
+use image::metadata::Orientation;
+use imgest::exif::{ByteOrder, DateTime, Exif, GpsPosition, Ifd, Rational, Value, tags};
+use proptest::prelude::*;
+
+
+/// (tag, type, count, value bytes)
+type RawEntry = (u16, u16, u32, Vec<u8>);
+
+
+/// Minimal TIFF writer for building EXIF fixtures.
+struct TiffBuilder {
+	big_endian: bool,
+	ifds: Vec<Vec<RawEntry>>,
+}
+
+
+impl TiffBuilder {
+	fn u16(&self, v: u16) -> [u8; 2] {
+		if self.big_endian { v.to_be_bytes() } else { v.to_le_bytes() }
+	}
+
+	fn u32(&self, v: u32) -> [u8; 4] {
+		if self.big_endian { v.to_be_bytes() } else { v.to_le_bytes() }
+	}
+
+	/// Lays out the IFDs back to back after the header. Entries of type 4 with the value `u32::MAX` are replaced by
+	/// the offset of the IFD whose index is stored in the entry's count, so sub-IFD pointers can be expressed.
+	fn build(&self) -> Vec<u8> {
+		let ifd_size = |ifd: &Vec<RawEntry>| 2 + ifd.len() * 12 + 4 + ifd.iter().map(|e| if e.3.len() > 4 { e.3.len() } else { 0 }).sum::<usize>();
+		let mut offsets = Vec::new();
+		let mut offset = 8;
+		for ifd in &self.ifds {
+			offsets.push(offset as u32);
+			offset += ifd_size(ifd);
+		}
+
+		let mut out = if self.big_endian { b"MM\0*".to_vec() } else { b"II*\0".to_vec() };
+		out.extend_from_slice(&self.u32(8));
+		for (index, ifd) in self.ifds.iter().enumerate() {
+			let mut data_offset = offsets[index] as usize + 2 + ifd.len() * 12 + 4;
+			let mut data = Vec::new();
+			out.extend_from_slice(&self.u16(ifd.len() as u16));
+			for (tag, kind, count, value) in ifd {
+				out.extend_from_slice(&self.u16(*tag));
+				out.extend_from_slice(&self.u16(*kind));
+				if *kind == 4 && value == &u32::MAX.to_le_bytes() {
+					out.extend_from_slice(&self.u32(1));
+					out.extend_from_slice(&self.u32(offsets[*count as usize]));
+					continue;
+				}
+				out.extend_from_slice(&self.u32(*count));
+				if value.len() > 4 {
+					out.extend_from_slice(&self.u32(data_offset as u32));
+					data_offset += value.len();
+					data.extend_from_slice(value);
+				} else {
+					let mut inline = value.clone();
+					inline.resize(4, 0);
+					out.extend_from_slice(&inline);
+				}
+			}
+			out.extend_from_slice(&self.u32(0));
+			out.extend_from_slice(&data);
+		}
+		out
+	}
+
+	fn rational(&self, numerator: u32, denominator: u32) -> Vec<u8> {
+		[self.u32(numerator), self.u32(denominator)].concat()
+	}
+}
+
+
+fn ascii(s: &str) -> (u32, Vec<u8>) {
+	let mut bytes = s.as_bytes().to_vec();
+	bytes.push(0);
+	(bytes.len() as u32, bytes)
+}
+
+
+fn camera_exif(big_endian: bool) -> Vec<u8> {
+	let mut builder = TiffBuilder { big_endian, ifds: vec![] };
+	let (make_len, make) = ascii("Canon");
+	let (date_len, date) = ascii("2019:07:14 16:20:05");
+	let orientation = builder.u16(6).to_vec();
+	let resolution = builder.rational(72, 1);
+	let bogus_resolution = builder.rational(300, 0);
+	let latitude = [builder.rational(37, 1), builder.rational(46, 1), builder.rational(3000, 100)].concat();
+	builder.ifds = vec![
+		vec![
+			(tags::MAKE, 2, make_len, make),
+			(tags::ORIENTATION, 3, 1, orientation),
+			(tags::X_RESOLUTION, 5, 1, resolution),
+			(tags::Y_RESOLUTION, 5, 1, bogus_resolution),
+			(tags::EXIF_IFD_POINTER, 4, 1, u32::MAX.to_le_bytes().to_vec()),
+			(tags::GPS_IFD_POINTER, 4, 2, u32::MAX.to_le_bytes().to_vec()),
+		],
+		vec![(tags::DATE_TIME_ORIGINAL, 2, date_len, date)],
+		vec![(tags::GPS_LATITUDE_REF, 2, 2, b"N\0".to_vec()), (tags::GPS_LATITUDE, 5, 3, latitude)],
+	];
+	builder.build()
+}
+
+
+#[test]
+fn parses_ifds_in_both_byte_orders() {
+	for big_endian in [false, true] {
+		let exif = Exif::parse(&camera_exif(big_endian)).unwrap();
+		assert_eq!(exif.byte_order, if big_endian { ByteOrder::BigEndian } else { ByteOrder::LittleEndian });
+
+		assert_eq!(exif.get(Ifd::Primary, tags::MAKE).and_then(Value::as_ascii), Some("Canon"));
+		assert_eq!(exif.get(Ifd::Primary, tags::ORIENTATION).and_then(Value::as_u32), Some(6));
+		assert_eq!(exif.get(Ifd::Primary, tags::X_RESOLUTION).and_then(Value::as_f64), Some(72.0));
+		assert_eq!(exif.get(Ifd::Primary, tags::Y_RESOLUTION).and_then(Value::as_f64), None);
+		assert_eq!(
+			exif.get(Ifd::Exif, tags::DATE_TIME_ORIGINAL).and_then(Value::as_datetime),
+			Some(DateTime {
+				year: 2019,
+				month: 7,
+				day: 14,
+				hour: 16,
+				minute: 20,
+				second: 5
+			})
+		);
+		assert_eq!(exif.get(Ifd::Gps, tags::GPS_LATITUDE_REF).and_then(Value::as_ascii), Some("N"));
+		let latitude = exif.get(Ifd::Gps, tags::GPS_LATITUDE).and_then(Value::as_rationals).unwrap();
+		assert_eq!(
+			latitude[2],
+			Rational {
+				numerator: 3000,
+				denominator: 100
+			}
+		);
+	}
+}
+
+
+#[test]
+fn accepts_jpeg_app1_prefix() {
+	let mut data = b"Exif\0\0".to_vec();
+	data.extend_from_slice(&camera_exif(false));
+	assert_eq!(Exif::parse(&data), Exif::parse(&camera_exif(false)));
+}
+
+
+#[test]
+fn exiftool_json() {
+	for big_endian in [false, true] {
+		let exif = Exif::parse(&camera_exif(big_endian)).unwrap();
+		// The zero denominator YResolution is left out
+		assert_eq!(
+			exif.to_exiftool_json(Path::new("a/b.jpg")),
+			concat!(
+				r#"{"SourceFile":"a/b.jpg","Make":"Canon","Orientation":6,"XResolution":72,"#,
+				r#""DateTimeOriginal":"2019:07:14 16:20:05","GPSLatitudeRef":"N","GPSLatitude":37.775}"#
+			)
+		);
+	}
+}
+
+
+#[test]
+fn common_fields() {
+	for big_endian in [false, true] {
+		// The fixture has a latitude but no longitude, so no position
+		let fields = Exif::parse(&camera_exif(big_endian)).unwrap().fields();
+		assert_eq!(fields.make.as_deref(), Some("Canon"));
+		assert_eq!(fields.orientation, Some(Orientation::Rotate90));
+		assert_eq!(fields.date_time_original.map(|date| date.to_string()).as_deref(), Some("2019:07:14 16:20:05"));
+		assert_eq!((fields.model, fields.lens_model, fields.gps), (None, None, None));
+
+		let mut builder = TiffBuilder { big_endian, ifds: vec![] };
+		let (lens_len, lens) = ascii("EF50mm f/1.8");
+		let longitude = [builder.rational(122, 1), builder.rational(25, 1), builder.rational(0, 1)].concat();
+		let latitude = [builder.rational(37, 1), builder.rational(45, 1), builder.rational(0, 1)].concat();
+		let (altitude, f_number, iso) = (builder.rational(10, 1), builder.rational(18, 10), builder.u16(400).to_vec());
+		builder.ifds = vec![
+			vec![
+				(tags::EXIF_IFD_POINTER, 4, 1, u32::MAX.to_le_bytes().to_vec()),
+				(tags::GPS_IFD_POINTER, 4, 2, u32::MAX.to_le_bytes().to_vec()),
+			],
+			vec![
+				(tags::LENS_MODEL, 2, lens_len, lens),
+				(tags::F_NUMBER, 5, 1, f_number),
+				(tags::ISO_SPEED, 3, 1, iso),
+			],
+			vec![
+				(tags::GPS_LATITUDE_REF, 2, 2, b"N\0".to_vec()),
+				(tags::GPS_LATITUDE, 5, 3, latitude),
+				(tags::GPS_LONGITUDE_REF, 2, 2, b"W\0".to_vec()),
+				(tags::GPS_LONGITUDE, 5, 3, longitude),
+				(tags::GPS_ALTITUDE_REF, 1, 1, vec![1]),
+				(tags::GPS_ALTITUDE, 5, 1, altitude),
+			],
+		];
+		let fields = Exif::parse(&builder.build()).unwrap().fields();
+		assert_eq!(fields.lens_model.as_deref(), Some("EF50mm f/1.8"));
+		assert_eq!((fields.f_number, fields.iso, fields.orientation), (Some(1.8), Some(400), None));
+		assert_eq!(
+			fields.gps,
+			Some(GpsPosition {
+				latitude: 37.75,
+				longitude: -122.41666666666667,
+				altitude: Some(-10.0),
+			})
+		);
+	}
+}
+
+
+#[test]
+fn survives_ifd_loops() {
+	// IFD0 whose next pointer and Exif pointer both point back at itself
+	let builder = TiffBuilder {
+		big_endian: false,
+		ifds: vec![vec![(tags::EXIF_IFD_POINTER, 4, 0, u32::MAX.to_le_bytes().to_vec())]],
+	};
+	let mut data = builder.build();
+	let next_offset = 8 + 2 + 12;
+	data[next_offset..next_offset + 4].copy_from_slice(&8u32.to_le_bytes());
+
+	let exif = Exif::parse(&data).unwrap();
+	assert!(exif.entries.is_empty());
+}
+
+
+#[test]
+fn rejects_bad_headers() {
+	assert!(Exif::parse(b"").is_none());
+	assert!(Exif::parse(b"II*\0").is_none());
+	assert!(Exif::parse(b"XX*\0\x08\0\0\0").is_none());
+}
+
+
+#[test]
+fn datetime_parsing() {
+	let parse = |s: &str| DateTime::parse(s.as_bytes());
+	let expected = DateTime {
+		year: 2021,
+		month: 3,
+		day: 4,
+		hour: 5,
+		minute: 6,
+		second: 7,
+	};
+
+	assert_eq!(parse("2021:03:04 05:06:07"), Some(expected));
+	assert_eq!(parse("2021:03:04 05:06:07\0"), Some(expected));
+	assert_eq!(parse("2021-03-04T05:06:07"), Some(expected));
+	assert_eq!(parse("2021/03/04 05:06:07.123+02:00"), Some(expected));
+	assert_eq!(parse("2021:03:04 05:06"), Some(DateTime { second: 0, ..expected }));
+
+	assert_eq!(parse("    :  :     :  :  "), None);
+	assert_eq!(parse("0000:00:00 00:00:00"), None);
+	assert_eq!(parse("2021:13:04 05:06:07"), None);
+	assert_eq!(parse("2021:03:04 25:06:07"), None);
+	assert_eq!(parse("2021:03:04"), None);
+	assert_eq!(parse("２０２１:03:04 05:06:07"), None);
+	assert_eq!(DateTime::parse(b"2021:03:04 05:06:07\xff"), None);
+}
+
+
+proptest! {
+	#[test]
+	fn parse_never_panics(data in prop::collection::vec(any::<u8>(), 0..512)) {
+		let _ = Exif::parse(&data);
+	}
+
+	#[test]
+	fn parse_never_panics_past_the_header(big_endian in any::<bool>(), body in prop::collection::vec(any::<u8>(), 0..512)) {
+		let mut data = if big_endian { b"MM\0*\0\0\0\x08".to_vec() } else { b"II*\0\x08\0\0\0".to_vec() };
+		data.extend_from_slice(&body);
+		if let Some(exif) = Exif::parse(&data) {
+			for entry in &exif.entries {
+				let _ = (entry.value.as_u32(), entry.value.as_f64(), entry.value.as_ascii(), entry.value.as_datetime());
+				if let Some(f) = entry.value.as_f64() {
+					prop_assert!(f.is_finite());
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn datetime_parse_never_panics(data in prop::collection::vec(any::<u8>(), 0..32)) {
+		let _ = DateTime::parse(&data);
+	}
+
+	#[test]
+	fn datetime_display_roundtrips(year in 1u16..=9999, month in 1u8..=12, day in 1u8..=31, hour in 0u8..24, minute in 0u8..60, second in 0u8..=60) {
+		let dt = DateTime { year, month, day, hour, minute, second };
+		prop_assert_eq!(DateTime::parse(dt.to_string().as_bytes()), Some(dt));
+	}
+}
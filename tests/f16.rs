@@ -0,0 +1,28 @@
+#![cfg(feature = "f16")]
+
+use half::f16;
+use image::DynamicImage;
+use imgest::convert::{self, Normalization, TensorLayout};
+
+
+#[test]
+fn f16_tensors() {
+	let rgba8 = DynamicImage::ImageRgba8(image::RgbaImage::from_raw(2, 1, vec![0, 51, 255, 128, 255, 188, 10, 255]).unwrap());
+	let tensor = convert::to_f16(&rgba8, TensorLayout::Chw, &Normalization::Unit, false);
+	let f32_tensor = convert::to_f32(&rgba8, TensorLayout::Chw, &Normalization::Unit);
+	assert_eq!(tensor, f32_tensor.iter().map(|&v| f16::from_f32(v)).collect::<Vec<_>>());
+
+	// Color samples are linearized before the precision is dropped, alpha isn't
+	let linear = convert::to_f16(&rgba8, TensorLayout::Hwc, &Normalization::Unit, true);
+	let expected = [convert::srgb8_to_linear(0), convert::srgb8_to_linear(51), 1.0, 128.0 / 255.0, 1.0, convert::srgb8_to_linear(188)];
+	assert_eq!(linear[..6], expected.map(f16::from_f32));
+	assert_eq!(linear[7], f16::ONE);
+
+	// 16-bit samples, and float images, which are linear already
+	let luma16 = DynamicImage::ImageLuma16(image::ImageBuffer::from_raw(1, 1, vec![32768u16]).unwrap());
+	let v = convert::to_f16(&luma16, TensorLayout::Hwc, &Normalization::Unit, true)[0].to_f32();
+	assert!((v - convert::srgb_to_linear(32768.0 / 65535.0)).abs() < 1e-3, "{v}");
+	let rgb32f = DynamicImage::ImageRgb32F(image::ImageBuffer::from_raw(1, 1, vec![0.25, 2.0, -1.0]).unwrap());
+	let centered = Normalization::MeanStd { mean: vec![0.5], std: vec![0.5] };
+	assert_eq!(convert::to_f16(&rgb32f, TensorLayout::Hwc, &centered, true), [-0.5, 3.0, -3.0].map(f16::from_f32));
+}
@@ -0,0 +1,78 @@
+use std::io::Cursor;
+
+use image::ImageDecoder;
+use imgest::GifDecoder;
+
+
+const PALETTE: [u8; 12] = [255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255];
+
+
+/// Encodes frames of palette indices, each covering the whole `width`x`height` screen, with index 3 transparent.
+fn encode(width: u16, height: u16, frames: &[Vec<u8>]) -> Vec<u8> {
+	let mut out = Vec::new();
+	{
+		let mut encoder = gif::Encoder::new(&mut out, width, height, &PALETTE).unwrap();
+		for indices in frames {
+			let mut frame = gif::Frame::from_indexed_pixels(width, height, indices.clone(), Some(3));
+			frame.delay = 10;
+			encoder.write_frame(&frame).unwrap();
+		}
+	}
+	out
+}
+
+
+fn indices(width: u16, height: u16) -> Vec<u8> {
+	(0..u32::from(width) * u32::from(height)).map(|i| (i % 4) as u8).collect()
+}
+
+
+#[test]
+fn static_gif_with_transparency() {
+	let data = encode(7, 3, &[indices(7, 3)]);
+
+	let decoder = GifDecoder::new(Cursor::new(&data)).unwrap();
+	assert!(!decoder.is_animated());
+	assert_eq!(decoder.dimensions(), (7, 3));
+	assert_eq!(decoder.color_type(), image::ColorType::Rgba8);
+
+	let (format, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!(format, image::ImageFormat::Gif);
+	let img = img.into_rgba8();
+	for (&index, pixel) in indices(7, 3).iter().zip(img.pixels()) {
+		let expected = match index {
+			3 => 0,
+			_ => 255,
+		};
+		assert_eq!(pixel.0[3], expected);
+		if index != 3 {
+			let i = usize::from(index) * 3;
+			assert_eq!(pixel.0[..3], PALETTE[i..i + 3]);
+		}
+	}
+
+	// Matches the image crate's decoder
+	let reference = image::load_from_memory_with_format(&data, image::ImageFormat::Gif).unwrap().into_rgba8();
+	assert_eq!(img, reference);
+}
+
+
+#[test]
+fn animated_gif_is_rejected() {
+	let first = indices(4, 4);
+	let second: Vec<u8> = first.iter().map(|i| (i + 1) % 4).collect();
+	let data = encode(4, 4, &[first, second]);
+
+	assert!(GifDecoder::new(Cursor::new(&data)).unwrap().is_animated());
+	let result = imgest::load_image_from_reader(Cursor::new(&data));
+	assert!(matches!(result, Err(imgest::Error::Animated)), "{result:?}");
+}
+
+
+#[test]
+fn limits_are_respected() {
+	let data = encode(7, 3, &[indices(7, 3)]);
+	let mut limits = image::Limits::default();
+	limits.max_image_width = Some(6);
+	assert!(matches!(GifDecoder::with_limits(Cursor::new(&data), limits), Err(imgest::Error::Limits(_))));
+}
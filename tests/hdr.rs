@@ -0,0 +1,123 @@
+use std::io::Cursor;
+
+use image::{DynamicImage, Rgb, Rgb32FImage, Rgba, Rgba32FImage};
+use imgest::{HdrOptions, LoadOptions, NonFinite, ToneMap, convert};
+
+
+fn linear() -> Rgb32FImage {
+	// Spans shadows through highlights well above 1.0
+	Rgb32FImage::from_fn(8, 4, |x, y| Rgb([x as f32 * 0.5, y as f32 * 0.25, 0.1]))
+}
+
+
+fn encode(img: &DynamicImage, format: image::ImageFormat) -> Vec<u8> {
+	let mut data = Vec::new();
+	img.write_to(&mut Cursor::new(&mut data), format).unwrap();
+	data
+}
+
+
+fn tone_mapped(tone_map: ToneMap) -> LoadOptions {
+	LoadOptions {
+		hdr: HdrOptions {
+			tone_map: Some(tone_map),
+			..Default::default()
+		},
+		..Default::default()
+	}
+}
+
+
+#[test]
+fn exr_decodes_to_float() {
+	let img = DynamicImage::ImageRgb32F(linear());
+	let data = encode(&img, image::ImageFormat::OpenExr);
+	let (format, decoded) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!(format, image::ImageFormat::OpenExr);
+	assert_eq!(decoded, img);
+
+	let rgba = DynamicImage::ImageRgba32F(Rgba32FImage::from_fn(3, 3, |x, _| Rgba([2.0, 0.5, 0.0, x as f32 / 2.0])));
+	let (_, decoded) = imgest::load_image_from_reader(Cursor::new(encode(&rgba, image::ImageFormat::OpenExr))).unwrap();
+	assert_eq!(decoded, rgba);
+}
+
+
+#[test]
+fn radiance_decodes_to_float() {
+	let img = DynamicImage::ImageRgb32F(linear());
+	let data = encode(&img, image::ImageFormat::Hdr);
+	let (format, decoded) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!(format, image::ImageFormat::Hdr);
+	let decoded = decoded.as_rgb32f().expect("Radiance HDR decodes to Rgb32F").clone();
+	// RGBE keeps 8 bits of mantissa under an exponent shared by the pixel, so the error scales with its largest channel
+	for (a, b) in decoded.pixels().zip(linear().pixels()) {
+		let tolerance = b.0.iter().fold(0f32, |m, &c| m.max(c.abs())) / 64.0 + 1e-3;
+		for (a, b) in a.0.iter().zip(b.0) {
+			assert!((a - b).abs() <= tolerance, "{a} vs {b}");
+		}
+	}
+}
+
+
+#[test]
+fn tone_mapping() {
+	let data = encode(&DynamicImage::ImageRgb32F(linear()), image::ImageFormat::OpenExr);
+
+	let (_, clamped) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &tone_mapped(ToneMap::Clamp)).unwrap();
+	let clamped = clamped.as_rgba8().expect("tone mapping produces Rgba8").clone();
+	for (pixel, source) in clamped.pixels().zip(linear().pixels()) {
+		let expected = source.0.map(convert::linear_to_srgb8);
+		assert_eq!(pixel.0, [expected[0], expected[1], expected[2], 255]);
+	}
+
+	let (_, reinhard) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &tone_mapped(ToneMap::Reinhard)).unwrap();
+	let reinhard = reinhard.into_rgba8();
+	// Highlights that clamping flattens to white stay distinct
+	assert_eq!(clamped.get_pixel(3, 0).0[0], 255);
+	assert_eq!(clamped.get_pixel(7, 0).0[0], 255);
+	assert!(reinhard.get_pixel(3, 0).0[0] < reinhard.get_pixel(7, 0).0[0]);
+	assert!(reinhard.get_pixel(7, 0).0[0] < 255);
+}
+
+
+#[test]
+fn non_finite_policies() {
+	let mut img = linear();
+	img.put_pixel(1, 0, Rgb([f32::NAN, 0.5, 0.5]));
+	img.put_pixel(2, 1, Rgb([0.5, f32::INFINITY, f32::NEG_INFINITY]));
+	img.put_pixel(3, 2, Rgb([f32::INFINITY, f32::NAN, 0.0]));
+	let data = encode(&DynamicImage::ImageRgb32F(img), image::ImageFormat::OpenExr);
+	let load = |non_finite| {
+		let options = LoadOptions {
+			hdr: HdrOptions { non_finite, ..Default::default() },
+			..Default::default()
+		};
+		imgest::load_image_from_reader_with_report(Cursor::new(&data), &options).map(|(_, img, report)| (img.into_rgb32f(), report.non_finite_pixels))
+	};
+
+	let (kept, count) = load(NonFinite::Keep).unwrap();
+	assert_eq!(count, 3);
+	assert!(kept.get_pixel(1, 0).0[0].is_nan());
+
+	let (zeroed, count) = load(NonFinite::Zero).unwrap();
+	assert_eq!(count, 3);
+	assert_eq!(zeroed.get_pixel(1, 0).0, [0.0, 0.5, 0.5]);
+	assert_eq!(zeroed.get_pixel(2, 1).0, [0.5, 0.0, 0.0]);
+	assert!(zeroed.as_raw().iter().all(|v| v.is_finite()));
+
+	// The largest finite sample in `linear` is 3.5, the smallest 0
+	let (clamped, _) = load(NonFinite::Clamp).unwrap();
+	assert_eq!(clamped.get_pixel(2, 1).0, [0.5, 3.5, 0.0]);
+	assert_eq!(clamped.get_pixel(3, 2).0, [3.5, 0.0, 0.0]);
+
+	assert!(matches!(load(NonFinite::Reject), Err(imgest::Error::NonFinite(3))));
+	let clean = encode(&DynamicImage::ImageRgb32F(linear()), image::ImageFormat::OpenExr);
+	let options = LoadOptions {
+		hdr: HdrOptions {
+			non_finite: NonFinite::Reject,
+			..Default::default()
+		},
+		..Default::default()
+	};
+	imgest::load_image_from_reader_with_options(Cursor::new(&clean), &options).unwrap();
+}
@@ -0,0 +1,41 @@
+use std::io::Cursor;
+
+use imgest::Format;
+
+
+/// Just enough of an ISOBMFF file for format detection: an `ftyp` box with the given major brand.
+fn ftyp(brand: &[u8; 4]) -> Vec<u8> {
+	let mut out = 28u32.to_be_bytes().to_vec();
+	out.extend_from_slice(b"ftyp");
+	out.extend_from_slice(brand);
+	out.extend_from_slice(&[0, 0, 0, 0]);
+	out.extend_from_slice(b"mif1heicmiaf");
+	out
+}
+
+
+#[test]
+fn heif_brands_are_detected() {
+	for brand in [b"heic", b"heix", b"mif1", b"msf1", b"hevc"] {
+		assert_eq!(Format::guess(&ftyp(brand)), Some(Format::Heif), "{}", String::from_utf8_lossy(brand));
+	}
+	assert_eq!(Format::guess(&ftyp(b"avif")), Some(Format::Image(image::ImageFormat::Avif)));
+	assert_eq!(Format::guess(&ftyp(b"isom")), None);
+	assert_eq!(Format::Heif.image_format(), None);
+}
+
+
+#[cfg(not(feature = "heif"))]
+#[test]
+fn heif_needs_the_feature() {
+	let result = imgest::load_image_from_reader(Cursor::new(ftyp(b"heic")));
+	assert!(matches!(result, Err(imgest::Error::UnsupportedFormat)));
+}
+
+
+#[cfg(feature = "heif")]
+#[test]
+fn truncated_heif_is_an_error() {
+	let result = imgest::load_image_from_reader(Cursor::new(ftyp(b"heic")));
+	assert!(matches!(result, Err(imgest::Error::Decoding(_))));
+}
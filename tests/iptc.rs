@@ -0,0 +1,136 @@
+use std::io::Cursor;
+
+use image::{ExtendedColorType, ImageEncoder, RgbImage, codecs::jpeg::JpegEncoder};
+use imgest::{
+	LoadOptions,
+	iptc::{Iptc, IptcFields, Record, datasets},
+};
+
+
+/// An IIM record, with the extended length form for values of 32 KiB and more.
+fn record((record, dataset): (u8, u8), value: &[u8]) -> Vec<u8> {
+	let mut out = vec![0x1C, record, dataset];
+	if value.len() < 0x8000 {
+		out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+	} else {
+		out.extend_from_slice(&[0x80, 4]);
+		out.extend_from_slice(&(value.len() as u32).to_be_bytes());
+	}
+	out.extend_from_slice(value);
+	out
+}
+
+
+/// A Photoshop image resource, with an empty name.
+fn resource(id: u16, data: &[u8]) -> Vec<u8> {
+	let mut out = b"8BIM".to_vec();
+	out.extend_from_slice(&id.to_be_bytes());
+	out.extend_from_slice(&[0, 0]);
+	out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+	out.extend_from_slice(data);
+	if data.len() % 2 == 1 {
+		out.push(0);
+	}
+	out
+}
+
+
+fn records() -> Vec<u8> {
+	[
+		record((1, 90), b"\x1b%G"),
+		record(datasets::OBJECT_NAME, b"Harbour"),
+		record(datasets::KEYWORDS, b"boats"),
+		record(datasets::KEYWORDS, "caf\u{e9}".as_bytes()),
+		record(datasets::BY_LINE, b"A. Photographer"),
+		record(datasets::CREDIT, b"Agency\0"),
+		record(datasets::CAPTION, b"Boats at dawn "),
+		record(datasets::DATE_CREATED, b"20240501"),
+		record(datasets::COUNTRY_NAME, b""),
+	]
+	.concat()
+}
+
+
+#[test]
+fn parses_records_in_every_wrapping() {
+	let iptc = Iptc::parse(&records()).unwrap();
+	assert_eq!(iptc.records.len(), 9);
+	let harbour = Record {
+		record: 2,
+		dataset: 5,
+		value: b"Harbour".to_vec(),
+	};
+	assert_eq!(iptc.records[1], harbour);
+	assert_eq!(iptc.get(datasets::KEYWORDS).collect::<Vec<_>>(), [b"boats".as_slice(), "caf\u{e9}".as_bytes()]);
+	let fields = IptcFields {
+		object_name: Some("Harbour".to_string()),
+		caption: Some("Boats at dawn".to_string()),
+		keywords: vec!["boats".to_string(), "caf\u{e9}".to_string()],
+		by_line: vec!["A. Photographer".to_string()],
+		credit: Some("Agency".to_string()),
+		date_created: Some("20240501".to_string()),
+		..IptcFields::default()
+	};
+	assert_eq!(iptc.fields(), fields);
+
+	// Photoshop resources, with the IPTC resource after another one
+	let resources = [resource(0x03ED, b"resolution"), resource(0x0404, &records())].concat();
+	assert_eq!(Iptc::parse(&resources), Some(iptc.clone()));
+	assert_eq!(Iptc::parse(&[b"Photoshop 3.0\0".as_slice(), &resources].concat()), Some(iptc.clone()));
+
+	// A PNG raw profile text chunk, of either type
+	let hex = |data: &[u8]| data.iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>().chunks(36).map(|line| line.concat()).collect::<Vec<_>>().join("\n");
+	let profile = format!("\niptc\n      {}\n{}\n", records().len(), hex(&records()));
+	assert_eq!(Iptc::parse(profile.as_bytes()), Some(iptc.clone()));
+	let profile = format!("\n8bim\n      {}\n{}\n", resources.len(), hex(&resources));
+	assert_eq!(Iptc::parse(profile.as_bytes()), Some(iptc));
+
+	// Latin-1, and the extended length form
+	let long = vec![b'x'; 0x9000];
+	let iptc = Iptc::parse(&[record(datasets::CITY, b"Z\xfcrich"), record(datasets::CAPTION, &long)].concat()).unwrap();
+	assert_eq!(iptc.fields().city.as_deref(), Some("Z\u{fc}rich"));
+	assert_eq!(iptc.fields().caption.map(|caption| caption.len()), Some(0x9000));
+}
+
+
+#[test]
+fn malformed_data() {
+	assert_eq!(Iptc::parse(b""), None);
+	assert_eq!(Iptc::parse(b"not iptc"), None);
+	assert_eq!(Iptc::parse(&resource(0x03ED, b"no iptc here")), None);
+	assert_eq!(Iptc::parse(b"\n8bim\n  100\n0102\n"), None);
+	// Records after a truncated one are dropped
+	let mut data = [record(datasets::HEADLINE, b"kept"), record(datasets::CAPTION, b"cut")].concat();
+	data.truncate(data.len() - 1);
+	assert_eq!(Iptc::parse(&data).unwrap().records.len(), 1);
+	// A resource size running past the end
+	let mut data = resource(0x0404, &records());
+	data[10] = 0xFF;
+	assert_eq!(Iptc::parse(&data), None);
+}
+
+
+#[test]
+fn jpeg_app13_segments() {
+	let rgb = RgbImage::new(8, 8);
+	let mut jpeg = Vec::new();
+	JpegEncoder::new(&mut jpeg).write_image(rgb.as_raw(), 8, 8, ExtendedColorType::Rgb8).unwrap();
+	// The resources split across two APP13 segments
+	let payload = [resource(0x03ED, b"resolution"), resource(0x0404, &records())].concat();
+	let (first, second) = payload.split_at(20);
+	let mut data = jpeg[..2].to_vec();
+	for part in [first, second] {
+		let segment = [b"Photoshop 3.0\0".as_slice(), part].concat();
+		data.extend_from_slice(&[0xFF, 0xED]);
+		data.extend_from_slice(&(segment.len() as u16 + 2).to_be_bytes());
+		data.extend_from_slice(&segment);
+	}
+	data.extend_from_slice(&jpeg[2..]);
+
+	let decoded = imgest::load_image_from_reader_with_metadata(Cursor::new(&data), &LoadOptions::new()).unwrap();
+	assert_eq!(decoded.iptc, Some(records()));
+	assert_eq!(decoded.parsed_iptc().unwrap().fields().keywords, ["boats", "caf\u{e9}"]);
+
+	let decoded = imgest::load_image_from_reader_with_metadata(Cursor::new(&jpeg), &LoadOptions::new()).unwrap();
+	assert_eq!((decoded.parsed_iptc(), decoded.iptc), (None, None));
+}
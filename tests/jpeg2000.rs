@@ -0,0 +1,36 @@
+use std::io::Cursor;
+
+use imgest::Format;
+
+
+const JP2_START: &[u8] = b"\0\0\0\x0CjP  \r\n\x87\n\0\0\0\x14ftypjp2 ";
+
+/// SOC, then a SIZ segment for a 16x8 image of one unsigned 12-bit component, cut off before any tile data.
+const J2K_TRUNCATED: &[u8] = b"\xFF\x4F\xFF\x51\x00\x29\x00\x00\
+	\x00\x00\x00\x10\x00\x00\x00\x08\x00\x00\x00\x00\x00\x00\x00\x00\
+	\x00\x00\x00\x10\x00\x00\x00\x08\x00\x00\x00\x00\x00\x00\x00\x00\
+	\x00\x01\x0B\x01\x01";
+
+
+#[test]
+fn jpeg2000_is_detected() {
+	assert_eq!(Format::guess(JP2_START), Some(Format::Jpeg2000));
+	assert_eq!(Format::guess(J2K_TRUNCATED), Some(Format::Jpeg2000));
+	assert_eq!(Format::Jpeg2000.image_format(), None);
+}
+
+
+#[cfg(not(feature = "jp2"))]
+#[test]
+fn jpeg2000_needs_feature() {
+	let result = imgest::load_image_from_reader(Cursor::new(J2K_TRUNCATED));
+	assert!(matches!(result, Err(imgest::Error::UnsupportedFormat)), "{result:?}");
+}
+
+
+#[cfg(feature = "jp2")]
+#[test]
+fn truncated_is_an_error() {
+	assert!(imgest::load_image_from_reader(Cursor::new(J2K_TRUNCATED)).is_err());
+	assert!(imgest::load_image_from_reader(Cursor::new(JP2_START)).is_err());
+}
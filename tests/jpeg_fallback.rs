@@ -0,0 +1,86 @@
+//! JPEG processes zune-jpeg doesn't implement: lossless (SOF3) and arithmetic coding.
+
+use std::io::Cursor;
+
+use image::ImageDecoder;
+use imgest::{JpegDecoder, LoadOptions};
+
+
+/// A 4x2 lossless JPEG of one component at `precision` bits, with predictor 1 (left, or above at the start of a
+/// row). The samples are `base + [2, 2, 3, 3]` on both rows, `base` being the initial prediction 2^(precision - 1),
+/// coded with a three entry Huffman table: difference categories 0 ("0"), 1 ("10") and 2 ("11").
+fn lossless(precision: u8) -> Vec<u8> {
+	let mut out = vec![0xFF, 0xD8];
+	out.extend_from_slice(&[0xFF, 0xC3, 0x00, 0x0B, precision, 0x00, 0x02, 0x00, 0x04, 0x01, 0x01, 0x11, 0x00]);
+	out.extend_from_slice(&[0xFF, 0xC4, 0x00, 0x16, 0x00, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2]);
+	out.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x08, 0x01, 0x01, 0x00, 0x01, 0x00, 0x00]);
+	// Row 0: +2 ("11" "10"), 0 ("0"), +1 ("10" "1"), 0 ("0"); row 1: 0, 0, +1, 0; padded with ones
+	out.extend_from_slice(&[0b1110_0101, 0b0001_0101]);
+	out.extend_from_slice(&[0xFF, 0xD9]);
+	out
+}
+
+
+#[test]
+fn lossless_8bit() {
+	let data = lossless(8);
+	let decoder = JpegDecoder::new(Cursor::new(&data)).unwrap();
+	assert_eq!(decoder.dimensions(), (4, 2));
+	assert_eq!(decoder.color_type(), image::ColorType::L8);
+
+	let (format, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!(format, image::ImageFormat::Jpeg);
+	assert_eq!(img.as_luma8().unwrap().as_raw(), &[130, 130, 131, 131, 130, 130, 131, 131]);
+}
+
+
+#[test]
+fn lossless_12bit_is_scaled_to_16() {
+	let data = lossless(12);
+	let (_, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	let scale = |v: u32| ((v * 65535 + 4095 / 2) / 4095) as u16;
+	let (a, b) = (scale(2050), scale(2051));
+	assert_eq!(img.as_luma16().unwrap().as_raw(), &[a, a, b, b, a, a, b, b]);
+}
+
+
+#[test]
+fn lossless_metadata_is_read() {
+	let mut data = lossless(8);
+	let mut exif = b"Exif\0\0MM\0\x2A\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0\x06\0\0\0\0\0\0".to_vec();
+	let mut app1 = vec![0xFF, 0xE1];
+	app1.extend_from_slice(&(exif.len() as u16 + 2).to_be_bytes());
+	app1.append(&mut exif);
+	data.splice(2..2, app1);
+
+	let mut decoder = JpegDecoder::new(Cursor::new(&data)).unwrap();
+	assert_eq!(decoder.orientation().unwrap(), image::metadata::Orientation::Rotate90);
+}
+
+
+#[test]
+fn lossless_size_is_checked_before_decoding() {
+	// A frame header claiming 4096x4096, and no Huffman table, so decoding fails instead of padding out the image
+	let mut data = lossless(8);
+	data[7..11].copy_from_slice(&[0x10, 0x00, 0x10, 0x00]);
+	data.drain(15..39);
+	let decoder = JpegDecoder::new(Cursor::new(&data)).unwrap();
+	assert_eq!(decoder.dimensions(), (4096, 4096));
+
+	let options = LoadOptions::new().max_pixels(1 << 20);
+	let result = imgest::load_image_from_reader_with_options(Cursor::new(&data), &options);
+	assert!(matches!(result, Err(imgest::Error::TooBig)), "{result:?}");
+	let result = imgest::load_image_from_reader(Cursor::new(&data));
+	assert!(matches!(result, Err(imgest::Error::Decoding(_))), "{result:?}");
+}
+
+
+#[cfg(not(feature = "jpeg-arithmetic"))]
+#[test]
+fn arithmetic_needs_feature() {
+	// Same frame as the lossless image, marked as arithmetic coded sequential
+	let mut data = lossless(8);
+	data[3] = 0xC9;
+	let result = imgest::load_image_from_reader(Cursor::new(&data));
+	assert!(matches!(result, Err(imgest::Error::Unsupported(_))), "{result:?}");
+}
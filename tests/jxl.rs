@@ -0,0 +1,25 @@
+use std::io::Cursor;
+
+use imgest::Format;
+
+
+#[test]
+fn jxl_is_detected() {
+	let mut container = b"\0\0\0\x0CJXL \r\n\x87\n".to_vec();
+	container.extend_from_slice(&[0; 8]);
+	assert_eq!(Format::guess(&container), Some(Format::Jxl));
+
+	let mut codestream = b"\xFF\x0A".to_vec();
+	codestream.extend_from_slice(&[0; 14]);
+	assert_eq!(Format::guess(&codestream), Some(Format::Jxl));
+	assert_eq!(Format::Jxl.image_format(), None);
+}
+
+
+#[test]
+fn garbage_jxl_is_an_error() {
+	let mut codestream = b"\xFF\x0A".to_vec();
+	codestream.extend_from_slice(&[0xAA; 30]);
+	let result = imgest::load_image_from_reader(Cursor::new(codestream));
+	assert!(matches!(result, Err(imgest::Error::Decoding(_) | imgest::Error::Io(_))), "{result:?}");
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use std::io::Cursor;
+
+use common::encode_png;
+use image::{DynamicImage, ExtendedColorType, ImageEncoder, RgbImage, codecs::jpeg::JpegEncoder, metadata::Orientation};
+use imgest::{Format, metadata::extract_metadata};
+
+
+fn jpeg() -> Vec<u8> {
+	let rgb = RgbImage::from_fn(16, 8, |x, y| image::Rgb([x as u8 * 16, y as u8 * 32, 128]));
+	let mut out = Vec::new();
+	JpegEncoder::new(&mut out).write_image(rgb.as_raw(), 16, 8, ExtendedColorType::Rgb8).unwrap();
+	out
+}
+
+
+/// `jpeg` with APP segments of (marker, payload) inserted after SOI.
+fn with_segments(jpeg: &[u8], segments: &[(u8, Vec<u8>)]) -> Vec<u8> {
+	let mut out = jpeg[..2].to_vec();
+	for (marker, payload) in segments {
+		out.extend_from_slice(&[0xFF, *marker]);
+		out.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+		out.extend_from_slice(payload);
+	}
+	out.extend_from_slice(&jpeg[2..]);
+	out
+}
+
+
+/// A little endian EXIF blob with just an orientation.
+fn exif(orientation: u16) -> Vec<u8> {
+	let mut out = b"II*\0\x08\0\0\0\x01\0\x12\x01\x03\0\x01\0\0\0".to_vec();
+	out.extend_from_slice(&orientation.to_le_bytes());
+	out.extend_from_slice(&[0; 6]);
+	out
+}
+
+
+#[test]
+fn jpeg_app_segments() {
+	let xmp = b"<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"/>".to_vec();
+	let iptc = b"\x1c\x02\x05\0\x02hi".to_vec();
+	let segments = [
+		(0xE1, [b"Exif\0\0".as_slice(), &exif(6)].concat()),
+		(0xE1, [b"http://ns.adobe.com/xap/1.0/\0".as_slice(), &xmp].concat()),
+		// An ICC profile split over two APP2 segments, out of order
+		(0xE2, [b"ICC_PROFILE\0\x02\x02".as_slice(), b"second"].concat()),
+		(0xE2, [b"ICC_PROFILE\0\x01\x02".as_slice(), b"first "].concat()),
+		(0xED, [b"Photoshop 3.0\08BIM\x04\x04\0\0\0\0\0\x07".as_slice(), &iptc, b"\0"].concat()),
+	];
+	let metadata = extract_metadata(Cursor::new(with_segments(&jpeg(), &segments))).unwrap();
+	assert_eq!(metadata.info.format, Format::Image(image::ImageFormat::Jpeg));
+	assert_eq!((metadata.info.width, metadata.info.height), (16, 8));
+	assert_eq!(metadata.exif, Some(exif(6)));
+	assert_eq!(metadata.orientation, Orientation::Rotate90);
+	assert_eq!(metadata.parsed_exif().unwrap().fields().orientation, Some(Orientation::Rotate90));
+	assert_eq!(metadata.xmp, Some(xmp));
+	assert_eq!(metadata.icc_profile.as_deref(), Some(b"first second".as_slice()));
+	assert_eq!(metadata.iptc, Some(iptc));
+	assert_eq!(metadata.parsed_iptc().unwrap().fields().object_name.as_deref(), Some("hi"));
+
+	let plain = extract_metadata(Cursor::new(jpeg())).unwrap();
+	assert_eq!((plain.exif, plain.xmp, plain.icc_profile, plain.iptc), (None, None, None, None));
+	assert_eq!(plain.orientation, Orientation::NoTransforms);
+}
+
+
+#[test]
+fn other_formats() {
+	let img = DynamicImage::ImageRgb8(RgbImage::new(3, 2));
+	let metadata = extract_metadata(Cursor::new(encode_png(&img))).unwrap();
+	assert_eq!((metadata.info.width, metadata.info.height), (3, 2));
+	assert_eq!((metadata.exif, metadata.xmp, metadata.iptc), (None, None, None));
+
+	let mut tiff = Vec::new();
+	img.write_to(&mut Cursor::new(&mut tiff), image::ImageFormat::Tiff).unwrap();
+	let metadata = extract_metadata(Cursor::new(tiff)).unwrap();
+	assert_eq!(metadata.info.format, Format::Image(image::ImageFormat::Tiff));
+	assert_eq!((metadata.exif, metadata.orientation), (None, Orientation::NoTransforms));
+
+	assert!(extract_metadata(Cursor::new(b"not an image at all".to_vec())).is_err());
+}
+
+
+#[test]
+fn extended_xmp() {
+	let guid = b"0123456789ABCDEF0123456789ABCDEF";
+	let xmp = [b"<rdf:Description xmpNote:HasExtendedXMP=\"".as_slice(), guid, b"\"/>"].concat();
+	let extended = b"<x:xmpmeta>a large thumbnail</x:xmpmeta>".to_vec();
+	let chunk = |offset: usize, end: usize| {
+		let header = [&(extended.len() as u32).to_be_bytes()[..], &(offset as u32).to_be_bytes()].concat();
+		[b"http://ns.adobe.com/xmp/extension/\0".as_slice(), guid, &header, &extended[offset..end]].concat()
+	};
+	let segments = [
+		(0xE1, [b"http://ns.adobe.com/xap/1.0/\0".as_slice(), &xmp].concat()),
+		(0xE1, chunk(20, extended.len())),
+		(0xE1, chunk(0, 20)),
+	];
+	let data = with_segments(&jpeg(), &segments);
+	let mut decoder = imgest::JpegDecoder::new(Cursor::new(&data)).unwrap();
+	assert_eq!(decoder.extended_xmp(), Some(extended.clone()));
+	assert_eq!(image::ImageDecoder::xmp_metadata(&mut decoder).unwrap(), Some(xmp.clone()));
+	assert_eq!(extract_metadata(Cursor::new(&data)).unwrap().extended_xmp, Some(extended));
+
+	// A missing chunk
+	let data = with_segments(&jpeg(), &segments[..2]);
+	assert_eq!(imgest::JpegDecoder::new(Cursor::new(&data)).unwrap().extended_xmp(), None);
+}
@@ -0,0 +1,89 @@
+//! Multi-picture (MPO) files: two JPEGs back to back, the first carrying an MP index in APP2.
+
+use std::io::Cursor;
+
+use image::{DynamicImage, RgbImage};
+use imgest::{Format, JpegDecoder};
+
+
+fn encode(img: &RgbImage) -> Vec<u8> {
+	let mut out = Vec::new();
+	let encoder = jpeg_encoder::Encoder::new(&mut out, 90);
+	encoder.encode(img.as_raw(), img.width() as u16, img.height() as u16, jpeg_encoder::ColorType::Rgb).unwrap();
+	out
+}
+
+
+/// Inserts a little endian MP index after the SOI of `primary`, followed by `second`.
+fn mpo(primary: &[u8], second: &[u8]) -> Vec<u8> {
+	// "MPF\0", TIFF header, an IFD with NumberOfImages and MPEntry, then the two 16 byte entries
+	let mut index = b"MPF\0II*\0".to_vec();
+	index.extend(8u32.to_le_bytes());
+	index.extend(2u16.to_le_bytes());
+	index.extend([0x01, 0xB0, 4, 0, 1, 0, 0, 0, 2, 0, 0, 0]);
+	index.extend([0x02, 0xB0, 7, 0, 32, 0, 0, 0, 38, 0, 0, 0]);
+	index.extend(0u32.to_le_bytes());
+	let entries_at = index.len();
+	index.resize(entries_at + 32, 0);
+
+	let primary_len = primary.len() + 4 + index.len();
+	// The TIFF header sits after SOI, the APP2 marker and length, and "MPF\0"
+	let header_start = 2 + 4 + 4;
+	let entry = |attribute: u32, size: usize, offset: usize| [attribute.to_le_bytes(), (size as u32).to_le_bytes(), (offset as u32).to_le_bytes(), [0; 4]].concat();
+	index[entries_at..entries_at + 16].copy_from_slice(&entry(0x2003_0000, primary_len, 0));
+	index[entries_at + 16..].copy_from_slice(&entry(0x0002_0002, second.len(), primary_len - header_start));
+
+	let mut out = primary[..2].to_vec();
+	out.extend([0xFF, 0xE2]);
+	out.extend(((index.len() + 2) as u16).to_be_bytes());
+	out.extend(index);
+	out.extend(&primary[2..]);
+	assert_eq!(out.len(), primary_len);
+	out.extend(second);
+	out
+}
+
+
+#[test]
+fn decodes_primary_image() {
+	let left = RgbImage::from_fn(40, 24, |x, y| image::Rgb([(x * 6) as u8, (y * 10) as u8, 64]));
+	let right = RgbImage::from_fn(40, 24, |x, y| image::Rgb([200, (x * 6) as u8, (y * 10) as u8]));
+	let primary = encode(&left);
+	let data = mpo(&primary, &encode(&right));
+
+	let (format, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!(format, Format::Mpo);
+	let (_, expected) = imgest::load_image_from_reader(Cursor::new(&primary)).unwrap();
+	assert_eq!(img, expected);
+	assert!(matches!(img, DynamicImage::ImageRgb8(_)));
+
+	assert_eq!(JpegDecoder::new(Cursor::new(&data)).unwrap().mpo_image_count(), Some(2));
+	assert_eq!(JpegDecoder::new(Cursor::new(&primary)).unwrap().mpo_image_count(), None);
+}
+
+
+#[test]
+fn probes_every_picture() {
+	let left = encode(&RgbImage::new(40, 24));
+	let data = mpo(&left, &encode(&RgbImage::new(20, 12)));
+	let images = imgest::probe_images_from_reader(Cursor::new(&data)).unwrap();
+	let sizes: Vec<_> = images.iter().map(|image| (image.index, image.width, image.height, image.primary)).collect();
+	assert_eq!(sizes, [(0, 40, 24, true), (1, 20, 12, false)]);
+	assert_eq!(imgest::probe_images_from_reader(Cursor::new(&left)).unwrap().len(), 1);
+}
+
+
+#[test]
+fn entries_past_the_end_are_harmless() {
+	let primary = encode(&RgbImage::new(40, 24));
+	let mut data = mpo(&primary, &encode(&RgbImage::new(20, 12)));
+	// Point the second entry at 4 GiB into the file, with a 4 GiB size
+	let entry = data.windows(4).position(|w| w == 0x0002_0002u32.to_le_bytes()).unwrap();
+	data[entry + 4..entry + 12].fill(0xFF);
+
+	let (format, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!(format, Format::Mpo);
+	assert_eq!((img.width(), img.height()), (40, 24));
+	assert!(JpegDecoder::new(Cursor::new(&data)).unwrap().mpo_image_count().is_some());
+	assert_eq!(imgest::probe_images_from_reader(Cursor::new(&data)).unwrap().len(), 1);
+}
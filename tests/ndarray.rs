@@ -0,0 +1,33 @@
+#![cfg(feature = "ndarray")]
+
+mod common;
+
+use std::io::Cursor;
+
+use common::{RawPng, encode_png};
+use image::{DynamicImage, RgbImage};
+use imgest::{DecodedArray, LoadOptions, convert::Normalization};
+
+
+#[test]
+fn hwc_arrays() {
+	let img = DynamicImage::ImageRgb8(RgbImage::from_fn(4, 3, |x, y| image::Rgb([x as u8 * 60, y as u8 * 100, 7])));
+	let data = encode_png(&img);
+	let decoded = imgest::load_image_from_reader_with_metadata(Cursor::new(&data), &LoadOptions::new()).unwrap();
+
+	let floats = decoded.to_array3_f32(&Normalization::Unit);
+	assert_eq!(floats.dim(), (3, 4, 3));
+	assert_eq!(floats[[2, 1, 1]], 200.0 / 255.0);
+
+	let array = decoded.into_array3();
+	let view = array.as_u8().unwrap();
+	assert_eq!(view.dim(), (3, 4, 3));
+	assert_eq!((view[[2, 1, 0]], view[[2, 1, 1]], view[[2, 1, 2]]), (60, 200, 7));
+	assert_eq!(array.into_u8().unwrap().into_raw_vec_and_offset().0, img.as_bytes());
+
+	// 16-bit gray keeps its depth and gets a channel axis of one
+	let data = RawPng::new(2, 1, 16, 0).encode(&[vec![0x12, 0x34, 0xFF, 0x01]]);
+	let array = imgest::load_image_from_reader_with_metadata(Cursor::new(&data), &LoadOptions::new()).unwrap().into_array3();
+	assert_eq!(array.as_u16().unwrap().dim(), (1, 2, 1));
+	assert!(matches!(array.into_f32(), Err(DecodedArray::U16(a)) if a.iter().eq(&[0x1234, 0xFF01])));
+}
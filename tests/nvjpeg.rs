@@ -0,0 +1,48 @@
+#![cfg(feature = "nvjpeg")]
+
+mod common;
+
+use common::encode_png;
+use image::{DynamicImage, ExtendedColorType, ImageEncoder, RgbImage, codecs::jpeg::JpegEncoder};
+use imgest::{
+	LoadOptions,
+	nvjpeg::{NvJpeg, NvJpegImage, NvJpegOutput},
+};
+
+
+fn gradient() -> RgbImage {
+	RgbImage::from_fn(37, 21, |x, y| image::Rgb([(x * 5) as u8, (y * 7) as u8, ((x + y) * 3) as u8]))
+}
+
+
+#[test]
+fn gpu_decodes_match_the_cpu() {
+	// Machines without a CUDA device only check that's reported
+	let mut gpu = match NvJpeg::new() {
+		Ok(gpu) => gpu,
+		Err(e) => {
+			assert!(matches!(e, imgest::Error::Unsupported(_)), "{e}");
+			return;
+		},
+	};
+	let rgb = gradient();
+	let mut jpeg = Vec::new();
+	JpegEncoder::new_with_quality(&mut jpeg, 95).write_image(rgb.as_raw(), rgb.width(), rgb.height(), ExtendedColorType::Rgb8).unwrap();
+	let (_, cpu) = imgest::load_image_from_bytes(&jpeg).unwrap();
+
+	let NvJpegImage::Host(host) = gpu.decode(&jpeg, NvJpegOutput::Host).unwrap() else {
+		panic!("expected a host image")
+	};
+	assert_eq!(host.color(), image::ColorType::Rgb8);
+	assert!(host.as_bytes().iter().zip(cpu.as_bytes()).all(|(a, b)| a.abs_diff(*b) <= 4));
+	let NvJpegImage::Device(device) = gpu.decode(&jpeg, NvJpegOutput::Device).unwrap() else {
+		panic!("expected a device image")
+	};
+	assert_eq!((device.width, device.height, device.channels), (37, 21, 3));
+	assert_eq!(device.to_host().unwrap(), host);
+
+	// Other formats fall back to the CPU
+	let png = encode_png(&DynamicImage::ImageRgb8(rgb.clone()));
+	assert_eq!(gpu.load_image(&png, &LoadOptions::new()).unwrap().1, DynamicImage::ImageRgb8(rgb));
+	assert!(gpu.decode(&png, NvJpegOutput::Host).is_err());
+}
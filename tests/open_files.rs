@@ -0,0 +1,52 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use imgest::open_files::OpenFileLimit;
+
+
+#[test]
+fn permits_are_bounded() {
+	let limit = OpenFileLimit::new(2);
+	let held = AtomicUsize::new(0);
+	let peak = AtomicUsize::new(0);
+	std::thread::scope(|scope| {
+		for _ in 0..8 {
+			scope.spawn(|| {
+				for _ in 0..20 {
+					let _permit = limit.acquire();
+					let now = held.fetch_add(1, Ordering::SeqCst) + 1;
+					peak.fetch_max(now, Ordering::SeqCst);
+					std::thread::yield_now();
+					held.fetch_sub(1, Ordering::SeqCst);
+				}
+			});
+		}
+	});
+	assert!(peak.into_inner() <= 2);
+	assert_eq!(held.into_inner(), 0);
+}
+
+
+#[test]
+fn read_releases_its_permit() {
+	let dir = tempfile::tempdir().unwrap();
+	let path = dir.path().join("data");
+	std::fs::write(&path, b"abc").unwrap();
+
+	let limit = OpenFileLimit::new(1);
+	for _ in 0..3 {
+		assert_eq!(limit.read(&path).unwrap(), b"abc");
+	}
+	assert!(limit.read(dir.path().join("missing")).is_err());
+	assert_eq!(limit.read(&path).unwrap(), b"abc");
+}
+
+
+#[test]
+fn default_from_rlimit() {
+	assert_eq!(OpenFileLimit::new(0).max(), 1);
+	let limit = OpenFileLimit::from_rlimit();
+	assert!(limit.max() >= 1);
+	if let Some(descriptors) = imgest::open_files::descriptor_limit() {
+		assert!(limit.max() as u64 <= descriptors.max(1));
+	}
+}
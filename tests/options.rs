@@ -0,0 +1,552 @@
+mod common;
+
+use std::io::Cursor;
+
+use common::{RawPng, adam7_scanlines, zlib_stored};
+use image::{DynamicImage, ExtendedColorType, GenericImageView, ImageDecoder, ImageEncoder, Limits, RgbImage, codecs::jpeg::JpegEncoder};
+use imgest::{
+	AnimatedPolicy, AvifOptions, DctScale, JpegDecoder, JpegOptions, LoadOptions, OutputColor, Placeholder, PlaceholderFill, PngDecoder, PngGamma,
+	PngOptions, SignificantBits, SixteenBit,
+};
+
+
+const PALETTE: [u8; 12] = [0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0, 255];
+
+
+fn palette_png(depth: png::BitDepth, width: u32, height: u32, data: &[u8]) -> Vec<u8> {
+	let mut out = Vec::new();
+	let mut encoder = png::Encoder::new(&mut out, width, height);
+	encoder.set_color(png::ColorType::Indexed);
+	encoder.set_depth(depth);
+	encoder.set_palette(PALETTE.to_vec());
+	let mut writer = encoder.write_header().unwrap();
+	writer.write_image_data(data).unwrap();
+	writer.finish().unwrap();
+	out
+}
+
+
+fn keep_indexed() -> LoadOptions {
+	LoadOptions {
+		png: PngOptions {
+			keep_indexed: true,
+			..Default::default()
+		},
+		..Default::default()
+	}
+}
+
+
+#[test]
+fn png_keep_indexed_returns_indices() {
+	// 5 pixels wide so the last byte of each 2-bit row is only partially used
+	let data = palette_png(png::BitDepth::Two, 5, 2, &[0b00_01_10_11, 0b00_000000, 0b11_10_01_00, 0b11_000000]);
+
+	let (_, img) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &keep_indexed()).unwrap();
+	let DynamicImage::ImageLuma8(indices) = img else { panic!("expected L8, got {:?}", img.color()) };
+	assert_eq!(indices.as_raw(), &[0, 1, 2, 3, 0, 3, 2, 1, 0, 3]);
+
+	let decoder = PngDecoder::with_options(Cursor::new(&data), Limits::no_limits(), &keep_indexed().png).unwrap();
+	assert_eq!(decoder.palette(), Some(&PALETTE[..]));
+	assert_eq!(decoder.transparency(), None);
+
+	// The default still expands through the palette
+	let (_, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!(img.to_rgb8().get_pixel(1, 0).0, [255, 0, 0]);
+}
+
+
+#[test]
+fn png_keep_indexed_8bit_and_non_palette() {
+	let data = palette_png(png::BitDepth::Eight, 2, 2, &[3, 2, 1, 0]);
+	let (_, img) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &keep_indexed()).unwrap();
+	assert_eq!(img.as_luma8().unwrap().as_raw(), &[3, 2, 1, 0]);
+
+	// Has no effect on images without a palette
+	let rgb = DynamicImage::ImageRgb8(RgbImage::from_pixel(3, 3, image::Rgb([10, 20, 30])));
+	let mut data = Vec::new();
+	rgb.write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png).unwrap();
+	let (_, img) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &keep_indexed()).unwrap();
+	assert_eq!(img, rgb);
+}
+
+
+#[test]
+fn png_sixteen_bit_reduction() {
+	// 0x00FF and 0x10FF are where truncation and rounding disagree
+	let samples: [u16; 4] = [0x0000, 0x00FF, 0x10FF, 0xFFFF];
+	let img = DynamicImage::ImageLuma16(image::ImageBuffer::from_raw(2, 2, samples.to_vec()).unwrap());
+	let mut data = Vec::new();
+	img.write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png).unwrap();
+
+	let load = |sixteen_bit| {
+		let options = LoadOptions {
+			png: PngOptions {
+				sixteen_bit,
+				..Default::default()
+			},
+			..Default::default()
+		};
+		imgest::load_image_from_reader_with_options(Cursor::new(&data), &options).unwrap().1
+	};
+	assert_eq!(load(SixteenBit::Keep), img);
+	assert_eq!(load(SixteenBit::Truncate).as_luma8().unwrap().as_raw(), &[0x00, 0x00, 0x10, 0xFF]);
+	assert_eq!(load(SixteenBit::Round).as_luma8().unwrap().as_raw(), &[0x00, 0x01, 0x11, 0xFF]);
+
+	// Rows come out reduced too, and the decoder still reports the stored depth
+	let options = PngOptions {
+		sixteen_bit: SixteenBit::Truncate,
+		..Default::default()
+	};
+	let decoder = PngDecoder::with_options(Cursor::new(&data), Limits::no_limits(), &options).unwrap();
+	assert!(decoder.is_16bit());
+	assert_eq!(decoder.color_type(), image::ColorType::L8);
+	let mut rows = decoder.into_rows();
+	assert_eq!(rows.next_row().unwrap().unwrap().data, &[0x00, 0x00]);
+	assert_eq!(rows.next_row().unwrap().unwrap().data, &[0x10, 0xFF]);
+}
+
+
+/// A 2x1 grayscale PNG with an ICC profile, EXIF, XMP and a tEXt chunk, plus the payload sizes of the ICC profile and EXIF.
+fn metadata_png() -> (Vec<u8>, u64, u64) {
+	let mut iccp = b"profile\0\0".to_vec();
+	iccp.extend_from_slice(&zlib_stored(&[0; 128]));
+	let exif = b"MM\0\x2A\0\0\0\x08\0\0";
+	let mut xmp = b"XML:com.adobe.xmp\0\0\0\0\0".to_vec();
+	xmp.extend_from_slice(b"<x:xmpmeta/>");
+	let data = RawPng::new(2, 1, 8, 0)
+		.chunk(b"iCCP", &iccp)
+		.chunk(b"eXIf", exif)
+		.chunk(b"iTXt", &xmp)
+		.chunk(b"tEXt", b"parameters\0a very long prompt")
+		.encode(&[vec![7, 9]]);
+	(data, iccp.len() as u64, exif.len() as u64)
+}
+
+
+#[test]
+fn png_skip_metadata() {
+	let (data, _, _) = metadata_png();
+
+	let mut decoder = PngDecoder::new(Cursor::new(&data)).unwrap();
+	assert!(decoder.icc_profile().unwrap().is_some());
+	assert!(decoder.exif_metadata().unwrap().is_some());
+	assert!(decoder.xmp_metadata().unwrap().is_some());
+
+	let options = PngOptions {
+		skip_metadata: true,
+		..Default::default()
+	};
+	let mut decoder = PngDecoder::with_options(Cursor::new(&data), Limits::no_limits(), &options).unwrap();
+	assert_eq!(decoder.icc_profile().unwrap(), None);
+	assert_eq!(decoder.exif_metadata().unwrap(), None);
+	assert_eq!(decoder.xmp_metadata().unwrap(), None);
+	assert_eq!(DynamicImage::from_decoder(decoder).unwrap().as_luma8().unwrap().as_raw(), &[7, 9]);
+}
+
+
+#[test]
+fn png_max_metadata_bytes() {
+	let (data, icc, exif) = metadata_png();
+	let load = |max_metadata_bytes| {
+		let options = PngOptions {
+			max_metadata_bytes,
+			..Default::default()
+		};
+		let mut decoder = PngDecoder::with_options(Cursor::new(&data), Limits::no_limits(), &options).unwrap();
+		let kept = (
+			decoder.xmp_metadata().unwrap().is_some(),
+			decoder.exif_metadata().unwrap().is_some(),
+			decoder.icc_profile().unwrap().is_some(),
+		);
+		let truncated = decoder.metadata_truncated();
+		// Dropping metadata never touches the pixels
+		assert_eq!(DynamicImage::from_decoder(decoder).unwrap().as_luma8().unwrap().as_raw(), &[7, 9]);
+		(kept, truncated)
+	};
+
+	assert_eq!(load(None), ((true, true, true), false));
+	assert_eq!(load(Some(10_000)), ((true, true, true), false));
+	assert_eq!(load(Some(icc + exif)), ((false, true, true), true));
+	assert_eq!(load(Some(icc)), ((false, false, true), true));
+	assert_eq!(load(Some(0)), ((false, false, false), true));
+}
+
+
+#[test]
+fn output_color() {
+	let la = DynamicImage::ImageLumaA16(image::ImageBuffer::from_fn(3, 2, |x, y| image::LumaA([x as u16 * 20000, y as u16 * 60000])));
+	let rgb = DynamicImage::ImageRgb8(RgbImage::from_pixel(2, 2, image::Rgb([1, 2, 3])));
+
+	for (img, output, expected) in [
+		(&la, OutputColor::Native, la.clone()),
+		(&la, OutputColor::Rgba8, DynamicImage::ImageRgba8(la.to_rgba8())),
+		(&la, OutputColor::Rgba8KeepGrayAlpha, DynamicImage::ImageLumaA8(la.to_luma_alpha8())),
+		(&rgb, OutputColor::Rgba8KeepGrayAlpha, DynamicImage::ImageRgba8(rgb.to_rgba8())),
+	] {
+		let mut data = Vec::new();
+		img.write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png).unwrap();
+		let options = LoadOptions { output, ..Default::default() };
+		let (_, decoded) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &options).unwrap();
+		assert_eq!(decoded, expected, "{output:?} {:?}", img.color());
+	}
+}
+
+
+#[test]
+fn animated_first_frame() {
+	let apng = {
+		let mut out = Vec::new();
+		let mut encoder = png::Encoder::new(&mut out, 2, 2);
+		encoder.set_color(png::ColorType::Grayscale);
+		encoder.set_animated(2, 0).unwrap();
+		let mut writer = encoder.write_header().unwrap();
+		writer.write_image_data(&[10; 4]).unwrap();
+		writer.write_image_data(&[200; 4]).unwrap();
+		writer.finish().unwrap();
+		out
+	};
+	let gif = {
+		let mut out = Vec::new();
+		let mut encoder = gif::Encoder::new(&mut out, 2, 2, &[10, 10, 10, 200, 200, 200]).unwrap();
+		encoder.write_frame(&gif::Frame::from_indexed_pixels(2, 2, vec![0; 4], None)).unwrap();
+		encoder.write_frame(&gif::Frame::from_indexed_pixels(2, 2, vec![1; 4], None)).unwrap();
+		drop(encoder);
+		out
+	};
+
+	let first_frame = LoadOptions {
+		animated_policy: AnimatedPolicy::FirstFrame,
+		..Default::default()
+	};
+	for (name, data) in [("apng", apng), ("gif", gif)] {
+		let result = imgest::load_image_from_reader(Cursor::new(&data));
+		assert!(matches!(result, Err(imgest::Error::Animated)), "{name}: {result:?}");
+
+		let (_, img) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &first_frame).unwrap();
+		assert_eq!(img.dimensions(), (2, 2), "{name}");
+		assert!(img.to_luma8().pixels().all(|p| p.0 == [10]), "{name}");
+	}
+}
+
+
+fn gradient_jpeg(width: u32, height: u32) -> Vec<u8> {
+	let rgb = RgbImage::from_fn(width, height, |x, y| image::Rgb([(x * 5) as u8, (y * 7) as u8, ((x + y) * 3) as u8]));
+	let mut out = Vec::new();
+	JpegEncoder::new_with_quality(&mut out, 95).write_image(rgb.as_raw(), width, height, ExtendedColorType::Rgb8).unwrap();
+	out
+}
+
+
+#[test]
+fn jpeg_dct_scale() {
+	let data = gradient_jpeg(37, 21);
+	let (_, full) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	let full = full.to_rgb8();
+
+	for (scale, expected) in [(DctScale::Full, (37, 21)), (DctScale::Half, (19, 11)), (DctScale::Quarter, (10, 6)), (DctScale::Eighth, (5, 3))] {
+		let options = JpegOptions {
+			dct_scale: scale,
+			..Default::default()
+		};
+		assert_eq!(JpegDecoder::with_options(Cursor::new(&data), &options).unwrap().dimensions(), expected, "{scale:?}");
+
+		let options = LoadOptions { jpeg: options, ..Default::default() };
+		let (_, scaled) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &options).unwrap();
+		let scaled = scaled.to_rgb8();
+		assert_eq!(scaled.dimensions(), expected, "{scale:?}");
+
+		// The scaled IDCT comes close to the mean of each block, including the clipped one at the bottom right
+		let d = scale.denominator();
+		let (x, y) = (expected.0 - 1, expected.1 - 1);
+		let block: Vec<_> = (y * d..full.height()).flat_map(|y| (x * d..full.width()).map(move |x| (x, y))).collect();
+		let mean = block.iter().map(|&(x, y)| u32::from(full.get_pixel(x, y).0[0])).sum::<u32>() as f64 / block.len() as f64;
+		assert!((f64::from(scaled.get_pixel(x, y).0[0]) - mean).abs() <= 4.0, "{scale:?}");
+	}
+}
+
+
+#[test]
+fn jpeg_dct_scale_output_size() {
+	// Long thin images, where a smaller scale reaches the requested size along the short side
+	for (width, height) in [(3, 70), (70, 3), (1, 1), (9, 17)] {
+		let data = gradient_jpeg(width, height);
+		for scale in [DctScale::Half, DctScale::Quarter, DctScale::Eighth] {
+			let options = JpegOptions {
+				dct_scale: scale,
+				..Default::default()
+			};
+			let expected = scale.scale_dimensions(width, height);
+			let decoder = JpegDecoder::with_options(Cursor::new(&data), &options).unwrap();
+			assert_eq!(decoder.dimensions(), expected, "{width}x{height} {scale:?}");
+			let mut buf = vec![0; decoder.total_bytes() as usize];
+			decoder.read_image(&mut buf).unwrap();
+
+			let options = LoadOptions::new().jpeg(options);
+			for output in [OutputColor::Native, OutputColor::Luma8, OutputColor::Rgba8] {
+				let (_, img) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &options.clone().output(output)).unwrap();
+				assert_eq!(img.dimensions(), expected, "{width}x{height} {scale:?} {output:?}");
+			}
+		}
+	}
+}
+
+
+#[test]
+fn luma8_output() {
+	let luma = LoadOptions {
+		output: OutputColor::Luma8,
+		..Default::default()
+	};
+
+	// JPEG decodes only the Y plane, which matches converting the color decode up to rounding
+	let data = gradient_jpeg(37, 21);
+	let (_, color) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	let (_, gray) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &luma).unwrap();
+	let gray = gray.as_luma8().unwrap();
+	let expected = imgest::convert::into_luma8(color);
+	assert!(gray.as_raw().iter().zip(expected.as_raw()).all(|(&a, &b)| a.abs_diff(b) <= 2));
+
+	// PNG converts row by row, including Adam7 passes, to exactly what a full decode converts to
+	let rgba = image::RgbaImage::from_fn(9, 7, |x, y| image::Rgba([(x * 25) as u8, (y * 30) as u8, 90, 128]));
+	for interlaced in [false, true] {
+		let mut png = RawPng::new(9, 7, 8, 6);
+		png.interlaced = interlaced;
+		let scanlines = if interlaced { adam7_scanlines(9, 7, 4, rgba.as_raw()) } else { rgba.as_raw().chunks(9 * 4).map(<[u8]>::to_vec).collect() };
+		let data = png.encode(&scanlines);
+		let (_, gray) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &luma).unwrap();
+		assert_eq!(gray.as_luma8().unwrap(), &imgest::convert::into_luma8(DynamicImage::ImageRgba8(rgba.clone())), "{interlaced}");
+	}
+}
+
+
+#[test]
+fn rgba8_output() {
+	let rgba8 = LoadOptions::new().output(OutputColor::Rgba8);
+
+	// JPEG color converts straight to RGBA, at full size and DCT scaled
+	let data = gradient_jpeg(37, 21);
+	for scale in [DctScale::Full, DctScale::Half] {
+		let jpeg = JpegOptions {
+			dct_scale: scale,
+			..Default::default()
+		};
+		let (_, color) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &LoadOptions::new().jpeg(jpeg.clone())).unwrap();
+		let (_, rgba) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &rgba8.clone().jpeg(jpeg)).unwrap();
+		let rgba = rgba.as_rgba8().unwrap();
+		let expected = imgest::convert::into_rgba8(color);
+		assert_eq!(rgba.dimensions(), expected.dimensions(), "{scale:?}");
+		assert!(rgba.as_raw().iter().zip(expected.as_raw()).all(|(&a, &b)| a.abs_diff(b) <= 1), "{scale:?}");
+	}
+
+	// PNG converts row by row, including Adam7 passes and 16-bit gray with alpha, to exactly what a full decode converts to
+	let la16 = image::ImageBuffer::<image::LumaA<u16>, _>::from_fn(9, 7, |x, y| image::LumaA([(x * 7000) as u16, (y * 9000 + 5) as u16]));
+	let be: Vec<u8> = la16.as_raw().iter().flat_map(|v| v.to_be_bytes()).collect();
+	for interlaced in [false, true] {
+		let mut png = RawPng::new(9, 7, 16, 4);
+		png.interlaced = interlaced;
+		let scanlines = if interlaced { adam7_scanlines(9, 7, 4, &be) } else { be.chunks(9 * 4).map(<[u8]>::to_vec).collect() };
+		let data = png.encode(&scanlines);
+		let (_, rgba) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &rgba8).unwrap();
+		assert_eq!(rgba.as_rgba8().unwrap(), &imgest::convert::into_rgba8(DynamicImage::ImageLumaA16(la16.clone())), "{interlaced}");
+	}
+
+	// Palette entries are expanded, opaque without tRNS
+	let data = palette_png(png::BitDepth::Eight, 3, 1, &[0, 1, 2]);
+	let (_, rgba) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &rgba8).unwrap();
+	assert_eq!(rgba.as_rgba8().unwrap().as_raw(), &[0, 0, 0, 255, 255, 0, 0, 255, 0, 255, 0, 255]);
+}
+
+
+#[test]
+fn png_significant_bits() {
+	// 10 significant bits, stored in the high bits as the spec says, then in the low bits as some scanners do
+	let decode = |samples: [u16; 2], significant_bits| {
+		let row = samples.iter().flat_map(|s| s.to_be_bytes()).collect();
+		let data = RawPng::new(2, 1, 16, 0).chunk(b"sBIT", &[10]).encode(&[row]);
+		let options = PngOptions {
+			significant_bits,
+			..Default::default()
+		};
+		let decoder = PngDecoder::with_options(Cursor::new(&data), Limits::no_limits(), &options).unwrap();
+		assert_eq!(decoder.significant_bits(), Some(&[10][..]));
+		DynamicImage::from_decoder(decoder).unwrap().into_luma16().into_raw()
+	};
+
+	assert_eq!(decode([0xFFC0, 0x8000], SignificantBits::Ignore), [0xFFC0, 0x8000]);
+	assert_eq!(decode([0xFFC0, 0x8015], SignificantBits::HighBits), [0xFFFF, 32800]);
+	assert_eq!(decode([0x03FF, 0x0200], SignificantBits::LowBits), [0xFFFF, 32800]);
+	assert_eq!(decode([0x0400, 0x0000], SignificantBits::LowBits), [0xFFFF, 0]);
+
+	// Reported either way
+	let data = RawPng::new(1, 1, 16, 0).chunk(b"sBIT", &[12]).encode(&[vec![0, 0]]);
+	let decoded = imgest::load_image_from_reader_with_metadata(Cursor::new(&data), &LoadOptions::default()).unwrap();
+	assert_eq!(decoded.significant_bits, Some(vec![12]));
+}
+
+
+#[test]
+fn png_gamma_correction() {
+	// Gray with alpha, encoded with a gamma of 0.5
+	let gamma = RawPng::new(3, 1, 8, 4).chunk(b"gAMA", &50000u32.to_be_bytes()).encode(&[vec![0, 10, 128, 20, 255, 30]]);
+	let decode = |data: &[u8], gamma| {
+		let options = LoadOptions::new().png(PngOptions {
+			gamma,
+			..Default::default()
+		});
+		imgest::load_image_from_bytes_with_options(data, &options).unwrap().1.into_luma_alpha8().into_raw()
+	};
+
+	assert_eq!(decode(&gamma, PngGamma::Ignore), [0, 10, 128, 20, 255, 30]);
+	assert_eq!(decode(&gamma, PngGamma::Linear), [0, 10, 64, 20, 255, 30]);
+	let srgb = decode(&gamma, PngGamma::Srgb);
+	assert_eq!((srgb[0], srgb[4]), (0, 255));
+	assert!(srgb[2].abs_diff(137) <= 1);
+
+	// sRGB takes precedence over gAMA
+	let srgb_chunk = RawPng::new(3, 1, 8, 4)
+		.chunk(b"sRGB", &[0])
+		.chunk(b"gAMA", &50000u32.to_be_bytes())
+		.encode(&[vec![0, 10, 128, 20, 255, 30]]);
+	assert_eq!(decode(&srgb_chunk, PngGamma::Linear), [0, 10, 128, 20, 255, 30]);
+	assert_eq!(PngDecoder::new(Cursor::new(&srgb_chunk)).unwrap().gamma_only(), None);
+	assert_eq!(PngDecoder::new(Cursor::new(&gamma)).unwrap().gamma_only(), Some(0.5));
+}
+
+
+#[test]
+fn builder_matches_struct_literal() {
+	let built = LoadOptions::new().output(OutputColor::Rgba8).animated_policy(AnimatedPolicy::FirstFrame).png(keep_indexed().png);
+	let literal = LoadOptions {
+		output: OutputColor::Rgba8,
+		animated_policy: AnimatedPolicy::FirstFrame,
+		png: keep_indexed().png,
+		..Default::default()
+	};
+	assert_eq!(built, literal);
+	assert_eq!(LoadOptions::new().limits, Limits::no_limits());
+}
+
+
+#[test]
+fn size_caps_are_too_big() {
+	let jpeg = gradient_jpeg(300, 200);
+	let png = palette_png(png::BitDepth::Eight, 300, 200, &[1; 300 * 200]);
+
+	for data in [&jpeg, &png] {
+		for output in [OutputColor::Native, OutputColor::Luma8] {
+			let base = LoadOptions::new().output(output);
+			for options in [
+				base.clone().max_pixels(300 * 200 - 1),
+				base.clone().max_file_bytes(data.len() as u64 - 1),
+				// Less than the RGB8 output buffer
+				base.clone().max_alloc_bytes(300 * 200 * 3 - 1),
+			] {
+				let result = imgest::load_image_from_reader_with_options(Cursor::new(data), &options);
+				assert!(matches!(result, Err(imgest::Error::TooBig)), "{options:?}");
+			}
+
+			let options = base.max_pixels(300 * 200).max_file_bytes(data.len() as u64).max_alloc_bytes(1 << 20);
+			imgest::load_image_from_reader_with_options(Cursor::new(data), &options).unwrap();
+		}
+	}
+}
+
+
+#[test]
+fn decompression_bombs_are_too_big() {
+	// Declares 50000x50000 RGBA (10 GB decoded) in about a hundred bytes
+	let bomb = RawPng::new(50_000, 50_000, 8, 6).encode(&[vec![0; 4]]);
+	let options = LoadOptions::new().max_expansion_ratio(1000);
+	for output in [OutputColor::Native, OutputColor::Luma8] {
+		let result = imgest::load_image_from_reader_with_options(Cursor::new(&bomb), &options.clone().output(output));
+		assert!(matches!(result, Err(imgest::Error::TooBig)), "{output:?}");
+	}
+
+	let jpeg = gradient_jpeg(300, 200);
+	let ratio = (300 * 200 * 3) / jpeg.len() as u64;
+	let result = imgest::load_image_from_reader_with_options(Cursor::new(&jpeg), &LoadOptions::new().max_expansion_ratio(ratio - 1));
+	assert!(matches!(result, Err(imgest::Error::TooBig)));
+	imgest::load_image_from_reader_with_options(Cursor::new(&jpeg), &LoadOptions::new().max_expansion_ratio(ratio + 1)).unwrap();
+}
+
+
+#[test]
+fn enormous_headers_are_limit_errors() {
+	// 2^31-1 pixels square at 8 bytes a pixel, whose size doesn't fit in a u64, let alone a usize on 32-bit targets
+	let huge = RawPng::new(0x7FFF_FFFF, 0x7FFF_FFFF, 16, 6).encode(&[vec![0; 8]]);
+	for output in [OutputColor::Native, OutputColor::Luma8, OutputColor::Rgba8] {
+		let result = imgest::load_image_from_reader_with_options(Cursor::new(&huge), &LoadOptions::new().output(output));
+		assert!(matches!(result, Err(imgest::Error::Limits(_))), "{output:?}");
+	}
+
+	// A buffer of the wrong length is an error rather than a panic
+	let png = RawPng::new(3, 2, 8, 2).encode(&[vec![0; 9], vec![0; 9]]);
+	let decoder = PngDecoder::new(Cursor::new(&png)).unwrap();
+	assert_eq!(decoder.total_bytes(), 18);
+	assert!(matches!(decoder.read_image(&mut [0; 17]), Err(image::ImageError::Parameter(_))));
+}
+
+
+#[test]
+fn config_fingerprint() {
+	let fingerprint = LoadOptions::new().config_fingerprint();
+	assert_eq!(fingerprint.len(), 64);
+	assert!(fingerprint.bytes().all(|b| b.is_ascii_hexdigit()));
+	assert_eq!(LoadOptions::default().config_fingerprint(), fingerprint);
+	// Cancellation doesn't change what a decode produces
+	assert_eq!(LoadOptions::new().cancel_token(imgest::CancelToken::new()).config_fingerprint(), fingerprint);
+
+	assert_ne!(LoadOptions::new().output(OutputColor::Rgba8).config_fingerprint(), fingerprint);
+	assert_ne!(LoadOptions::new().png(keep_indexed().png).config_fingerprint(), fingerprint);
+	assert_ne!(LoadOptions::new().max_pixels(1 << 20).config_fingerprint(), fingerprint);
+}
+
+
+#[test]
+fn options_fingerprint_is_pinned() {
+	// The SHA-256 of the default options' field list, "imgest-options v1\npng.keep_indexed=false\n...". A change here
+	// means stored fingerprints no longer match, so it must come with a bump of the field list's version tag.
+	let fingerprint = LoadOptions::new().options_fingerprint();
+	assert_eq!(fingerprint, "478ad44f4baa5112cd611aa2a81f5df54df0d0a68e7d055dfff8a7815b7fbd8d");
+	assert_eq!(LoadOptions::new().cancel_token(imgest::CancelToken::new()).options_fingerprint(), fingerprint);
+	assert_eq!(LoadOptions::new().avif(AvifOptions { threads: Some(1) }).options_fingerprint(), fingerprint);
+
+	assert_ne!(LoadOptions::new().output(OutputColor::Luma8).options_fingerprint(), fingerprint);
+	assert_ne!(LoadOptions::new().limits(Limits::default()).options_fingerprint(), fingerprint);
+	let solid = Placeholder {
+		fill: PlaceholderFill::Solid([0xCC, 0xCC, 0xCC, 0xFF]),
+		..Default::default()
+	};
+	assert_ne!(LoadOptions::new().placeholder(solid).options_fingerprint(), fingerprint);
+}
+
+
+#[test]
+fn limits_apply_to_every_path() {
+	let jpeg = gradient_jpeg(300, 200);
+	let png = palette_png(png::BitDepth::Eight, 300, 200, &[1; 300 * 200]);
+
+	let mut narrow = Limits::no_limits();
+	narrow.max_image_width = Some(299);
+	let mut small_alloc = Limits::no_limits();
+	// Less than the RGB8 output buffer
+	small_alloc.max_alloc = Some(300 * 200 * 3 - 1);
+
+	for limits in [narrow, small_alloc] {
+		for output in [OutputColor::Native, OutputColor::Luma8] {
+			let options = LoadOptions::new().limits(limits.clone()).output(output);
+			for data in [&jpeg, &png] {
+				let result = imgest::load_image_from_reader_with_options(Cursor::new(data), &options);
+				assert!(matches!(result, Err(imgest::Error::Limits(_))), "{limits:?} {output:?}");
+			}
+		}
+	}
+
+	let mut roomy = Limits::no_limits();
+	roomy.max_image_width = Some(300);
+	roomy.max_alloc = Some(1 << 20);
+	for data in [&jpeg, &png] {
+		imgest::load_image_from_reader_with_options(Cursor::new(data), &LoadOptions::new().limits(roomy.clone())).unwrap();
+	}
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use std::io::Cursor;
+
+use common::RawPng;
+use image::{DynamicImage, GenericImageView, ImageEncoder, RgbImage, codecs::jpeg::JpegEncoder, metadata::Orientation};
+use imgest::{
+	LoadOptions,
+	exif::{ByteOrder, Entry, Exif, Ifd, Value, tags},
+	orientation::{RotationSignal, SuspectedRotation, rotation_hint},
+};
+
+
+/// Bright blue sky over dark green ground, with a few horizontal stripes in the ground.
+fn landscape() -> RgbImage {
+	RgbImage::from_fn(120, 80, |_, y| match y {
+		0..40 => image::Rgb([120, 170, 240]),
+		_ if y % 8 < 4 => image::Rgb([30, 70, 25]),
+		_ => image::Rgb([50, 90, 40]),
+	})
+}
+
+
+fn exif(entries: Vec<(Ifd, u16, Value)>) -> Exif {
+	Exif {
+		byte_order: ByteOrder::LittleEndian,
+		entries: entries.into_iter().map(|(ifd, tag, value)| Entry { ifd, tag, value }).collect(),
+	}
+}
+
+
+#[test]
+fn upright_scene_is_not_flagged() {
+	let hint = rotation_hint(&DynamicImage::ImageRgb8(landscape()), None);
+	assert!(hint.signals.is_empty(), "{hint:?}");
+	assert_eq!(hint.confidence, 0.0);
+	assert_eq!(hint.suspected, None);
+}
+
+
+#[test]
+fn sideways_scene_is_flagged_with_direction() {
+	// Rotating counter clockwise puts the sky on the left, so the fix is a clockwise rotation
+	let img = DynamicImage::ImageRgb8(landscape()).rotate270();
+	let hint = rotation_hint(&img, None);
+	assert!(hint.signals.iter().any(|s| matches!(s, RotationSignal::SkyOnSide(_))), "{hint:?}");
+	assert!(hint.signals.contains(&RotationSignal::VerticalEdgeDominance), "{hint:?}");
+	assert_eq!(hint.suspected, Some(SuspectedRotation::Clockwise90));
+	assert!(hint.confidence > 0.5, "{hint:?}");
+
+	let hint = rotation_hint(&DynamicImage::ImageRgb8(landscape()).rotate90(), None);
+	assert_eq!(hint.suspected, Some(SuspectedRotation::CounterClockwise90));
+}
+
+
+#[test]
+fn exif_inconsistencies() {
+	let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(30, 50, image::Rgb([128, 128, 128])));
+
+	let swapped = exif(vec![
+		(Ifd::Primary, tags::MAKE, Value::Ascii(b"Canon\0".to_vec())),
+		(Ifd::Exif, tags::PIXEL_X_DIMENSION, Value::Long(vec![50])),
+		(Ifd::Exif, tags::PIXEL_Y_DIMENSION, Value::Short(vec![30])),
+	]);
+	let hint = rotation_hint(&img, Some(&swapped));
+	assert_eq!(hint.signals, vec![RotationSignal::ExifDimensionsSwapped]);
+	assert_eq!(hint.suspected, None);
+	assert!((hint.confidence - 0.5).abs() < 1e-6);
+
+	let consistent = exif(vec![
+		(Ifd::Exif, tags::PIXEL_X_DIMENSION, Value::Long(vec![30])),
+		(Ifd::Exif, tags::PIXEL_Y_DIMENSION, Value::Long(vec![50])),
+		(Ifd::Thumbnail, tags::ORIENTATION, Value::Short(vec![1])),
+	]);
+	assert!(rotation_hint(&img, Some(&consistent)).signals.is_empty());
+
+	let mismatch = exif(vec![(Ifd::Primary, tags::ORIENTATION, Value::Short(vec![1])), (Ifd::Thumbnail, tags::ORIENTATION, Value::Short(vec![6]))]);
+	assert_eq!(rotation_hint(&img, Some(&mismatch)).signals, vec![RotationSignal::ThumbnailOrientationMismatch]);
+}
+
+
+#[test]
+fn landscape_paper_scans() {
+	let scan = DynamicImage::ImageRgb8(RgbImage::from_pixel(297, 210, image::Rgb([250, 250, 250])));
+	assert_eq!(rotation_hint(&scan, None).signals, vec![RotationSignal::PaperAspectLandscape]);
+
+	// Portrait pages and camera photos with paper-like ratios are left alone
+	assert!(rotation_hint(&scan.rotate90(), None).signals.is_empty());
+	let camera = exif(vec![(Ifd::Primary, tags::MAKE, Value::Ascii(b"NIKON\0".to_vec()))]);
+	assert!(rotation_hint(&scan, Some(&camera)).signals.is_empty());
+}
+
+
+/// Big endian EXIF with just an Orientation of 6 (rotate 90° clockwise to display).
+const EXIF_ROTATE_90: &[u8] = b"MM\0*\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0\x06\0\0\0\0\0\0";
+
+
+#[test]
+fn png_is_rotated() {
+	// 3x2 gray, with a single white pixel at the top left
+	let data = RawPng::new(3, 2, 8, 0).chunk(b"eXIf", EXIF_ROTATE_90).encode(&[vec![255, 0, 0], vec![0; 3]]);
+
+	let (_, img) = imgest::load_image_from_bytes(&data).unwrap();
+	assert_eq!(img.dimensions(), (3, 2));
+
+	let options = LoadOptions::new().apply_orientation(true);
+	let (_, img) = imgest::load_image_from_bytes_with_options(&data, &options).unwrap();
+	assert_eq!(img.dimensions(), (2, 3));
+	assert_eq!(img.as_luma8().unwrap().as_raw(), &[0, 255, 0, 0, 0, 0]);
+
+	let decoded = imgest::load_image_from_reader_with_metadata(Cursor::new(&data), &options).unwrap();
+	assert_eq!(decoded.image, img);
+	assert_eq!(decoded.orientation, Orientation::NoTransforms);
+	assert_eq!(decoded.exif.as_deref(), Some(EXIF_ROTATE_90));
+}
+
+
+#[test]
+fn jpeg_is_rotated() {
+	let mut plain = Vec::new();
+	JpegEncoder::new(&mut plain).write_image(&[128; 16 * 8 * 3], 16, 8, image::ExtendedColorType::Rgb8).unwrap();
+	// APP1 right after SOI
+	let mut data = plain[..2].to_vec();
+	data.extend_from_slice(&[0xFF, 0xE1]);
+	data.extend_from_slice(&(2 + 6 + EXIF_ROTATE_90.len() as u16).to_be_bytes());
+	data.extend_from_slice(b"Exif\0\0");
+	data.extend_from_slice(EXIF_ROTATE_90);
+	data.extend_from_slice(&plain[2..]);
+
+	let options = LoadOptions::new().apply_orientation(true);
+	let (_, img) = imgest::load_image_from_bytes_with_options(&data, &options).unwrap();
+	assert_eq!(img.dimensions(), (8, 16));
+	// Also for the luma-only path
+	let (_, img) = imgest::load_image_from_bytes_with_options(&data, &options.clone().output(imgest::OutputColor::Luma8)).unwrap();
+	assert_eq!(img.dimensions(), (8, 16));
+
+	let (_, img) = imgest::load_image_from_bytes_with_options(&plain, &options).unwrap();
+	assert_eq!(img.dimensions(), (16, 8));
+}
@@ -0,0 +1,127 @@
+//! Palette PNGs across bit depths, palette lengths and tRNS lengths, including the malformed combinations seen in the
+//! wild: short palettes, out of range indices and tRNS chunks shorter than the palette.
+
+mod common;
+
+use std::io::Cursor;
+
+use common::{RawPng, adam7_scanlines};
+use image::{DynamicImage, ImageDecoder, Limits};
+use imgest::{LoadOptions, PngDecoder, PngOptions};
+
+
+const WIDTH: u32 = 11;
+const HEIGHT: u32 = 5;
+
+
+/// Every possible index for the bit depth, cycled, so out of range indices show up whenever the palette is short.
+fn indices(bits: u8) -> Vec<u8> {
+	let count = 1u32 << bits;
+	(0..WIDTH * HEIGHT).map(|i| ((i * 7) % count) as u8).collect()
+}
+
+
+fn pack(indices: &[u8], bits: u8) -> Vec<Vec<u8>> {
+	indices
+		.chunks(WIDTH as usize)
+		.map(|row| {
+			let mut line = vec![0; (row.len() * usize::from(bits)).div_ceil(8)];
+			for (x, &index) in row.iter().enumerate() {
+				let bit = x * usize::from(bits);
+				line[bit / 8] |= index << (8 - usize::from(bits) - bit % 8);
+			}
+			line
+		})
+		.collect()
+}
+
+
+fn palette(entries: usize) -> Vec<u8> {
+	(0..entries).flat_map(|i| [i as u8, 100 + i as u8 / 2, 255 - i as u8]).collect()
+}
+
+
+/// What libpng (and so Pillow and Firefox) produce for `index`.
+fn expected_pixel(index: u8, palette: &[u8], trns: Option<&[u8]>) -> [u8; 4] {
+	let i = usize::from(index);
+	match palette.get(i * 3..i * 3 + 3) {
+		Some(rgb) => [rgb[0], rgb[1], rgb[2], trns.and_then(|t| t.get(i).copied()).unwrap_or(255)],
+		None => [0, 0, 0, 255],
+	}
+}
+
+
+fn check(bits: u8, palette: &[u8], trns: Option<&[u8]>, interlaced: bool) {
+	let context = format!("bits={bits} palette={} trns={:?} interlaced={interlaced}", palette.len() / 3, trns.map(<[u8]>::len));
+	let indices = indices(bits);
+	let mut png = RawPng::new(WIDTH, HEIGHT, bits, 3).chunk(b"PLTE", palette);
+	if let Some(trns) = trns {
+		png = png.chunk(b"tRNS", trns);
+	}
+	png.interlaced = interlaced;
+	let scanlines = if interlaced { adam7_scanlines(WIDTH, HEIGHT, 1, &indices) } else { pack(&indices, bits) };
+	let data = png.encode(&scanlines);
+
+	let (_, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap_or_else(|e| panic!("{context}: {e}"));
+	let expected_color = if trns.is_some() { image::ColorType::Rgba8 } else { image::ColorType::Rgb8 };
+	assert_eq!(img.color(), expected_color, "{context}");
+	let rgba = img.to_rgba8();
+	for (i, (&index, pixel)) in indices.iter().zip(rgba.pixels()).enumerate() {
+		assert_eq!(pixel.0, expected_pixel(index, palette, trns), "{context}: pixel {i} (index {index})");
+	}
+
+	// Streaming rows agree with the full decode
+	let mut rows = PngDecoder::new(Cursor::new(&data)).unwrap().into_rows();
+	let bytes_per_pixel = usize::from(rows.color_type().bytes_per_pixel());
+	let mut streamed = vec![0; img.as_bytes().len()];
+	while let Some(row) = rows.next_row().unwrap() {
+		row.position.scatter(row.data, &mut streamed, WIDTH, bytes_per_pixel);
+	}
+	assert_eq!(streamed, img.as_bytes(), "{context}: rows");
+
+	// Keeping indices returns them untouched, out of range or not
+	let options = LoadOptions {
+		png: PngOptions {
+			keep_indexed: true,
+			..Default::default()
+		},
+		..Default::default()
+	};
+	let (_, kept) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &options).unwrap();
+	assert_eq!(kept.as_luma8().unwrap().as_raw(), &indices, "{context}: keep_indexed");
+}
+
+
+#[test]
+fn palette_matrix() {
+	for bits in [1, 2, 4, 8] {
+		let full = 1usize << bits;
+		for entries in [1, full / 2, full].into_iter().filter(|&n| n > 0) {
+			let palette = palette(entries);
+			let alphas: Vec<u8> = (0..entries as u32).map(|i| (i * 37 % 256) as u8).collect();
+			let trns_lengths = [None, Some(1), Some(entries / 2), Some(entries)];
+			for trns in trns_lengths.into_iter().filter(|&len| len != Some(0)).map(|len| len.map(|len| &alphas[..len])) {
+				check(bits, &palette, trns, false);
+			}
+		}
+	}
+}
+
+
+#[test]
+fn interlaced_short_palette() {
+	let palette = palette(100);
+	check(8, &palette, None, true);
+	check(8, &palette, Some(&[0, 128, 255]), true);
+}
+
+
+#[test]
+fn decoder_reports_expanded_color() {
+	let data = RawPng::new(WIDTH, HEIGHT, 2, 3).chunk(b"PLTE", &palette(2)).encode(&pack(&indices(2), 2));
+	let decoder = PngDecoder::with_limits(Cursor::new(&data), Limits::no_limits()).unwrap();
+	assert_eq!(decoder.color_type(), image::ColorType::Rgb8);
+	assert_eq!(decoder.palette(), Some(&palette(2)[..]));
+	let img = DynamicImage::from_decoder(decoder).unwrap();
+	assert_eq!(img.to_rgb8().get_pixel(1, 0).0, [0, 0, 0]);
+}
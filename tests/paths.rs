@@ -0,0 +1,93 @@
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use common::encode_png;
+use image::{DynamicImage, RgbImage};
+
+
+fn write_fixture(path: &Path) -> DynamicImage {
+	let img = DynamicImage::ImageRgb8(RgbImage::from_fn(7, 5, |x, y| image::Rgb([x as u8 * 30, y as u8 * 40, 200])));
+	std::fs::write(path, encode_png(&img)).unwrap();
+	img
+}
+
+
+#[cfg(unix)]
+#[test]
+fn loads_non_utf8_paths() {
+	use std::{ffi::OsStr, os::unix::ffi::OsStrExt as _};
+
+	let dir = tempfile::tempdir().unwrap();
+	let subdir = dir.path().join(OsStr::from_bytes(b"dir-\xc3\x28"));
+	std::fs::create_dir(&subdir).unwrap();
+	let path = subdir.join(OsStr::from_bytes(b"image-\xff\xfe.png"));
+	assert!(path.to_str().is_none(), "fixture path should not be valid UTF-8");
+
+	let expected = write_fixture(&path);
+	let (_, img) = imgest::load_image(&path).unwrap();
+	assert_eq!(img, expected);
+}
+
+
+#[test]
+fn loads_paths_longer_than_max_path() {
+	let dir = tempfile::tempdir().unwrap();
+	let mut path = PathBuf::from(dir.path());
+	// Well past Windows' 260 character MAX_PATH while keeping each component under the usual 255 byte limit
+	for i in 0..6 {
+		path.push(format!("{i}-{}", "a".repeat(200)));
+	}
+	std::fs::create_dir_all(&path).unwrap();
+	path.push("image.png");
+	assert!(path.as_os_str().len() > 1200);
+
+	let expected = write_fixture(&path);
+	let (_, img) = imgest::load_image(&path).unwrap();
+	assert_eq!(img, expected);
+}
+
+
+#[test]
+fn loads_memory_mapped() {
+	let dir = tempfile::tempdir().unwrap();
+	let path = dir.path().join("image.png");
+	let expected = write_fixture(&path);
+	assert_eq!(imgest::load_image_mmap(&path).unwrap(), (image::ImageFormat::Png.into(), expected.clone()));
+	let options = imgest::LoadOptions::new().output(imgest::OutputColor::Rgba8);
+	let (_, rgba) = imgest::load_image_mmap_with_options(&path, &options).unwrap();
+	assert_eq!(rgba, DynamicImage::ImageRgba8(expected.to_rgba8()));
+
+	assert!(matches!(imgest::load_image_mmap(dir.path().join("missing.png")), Err(imgest::Error::Io(_))));
+	assert_eq!(imgest::load_image_rgba8(&path).unwrap(), (image::ImageFormat::Png.into(), expected.to_rgba8()));
+	let empty = dir.path().join("empty.png");
+	std::fs::write(&empty, b"").unwrap();
+	assert!(imgest::load_image_mmap(&empty).is_err());
+}
+
+
+#[test]
+fn loads_large_and_tiny_files() {
+	let dir = tempfile::tempdir().unwrap();
+	// Large enough for a read buffer past the default 8 KiB, with the image data crossing several refills of it
+	let img = DynamicImage::ImageRgb8(RgbImage::from_fn(400, 300, |x, y| {
+		// Noise, so the PNG doesn't compress
+		let hash = (x.wrapping_mul(0x9E37_79B9) ^ y.wrapping_mul(0x85EB_CA6B)).wrapping_mul(0xC2B2_AE35);
+		image::Rgb([hash as u8, (hash >> 8) as u8, (hash >> 16) as u8])
+	}));
+	let png = dir.path().join("large.png");
+	std::fs::write(&png, encode_png(&img)).unwrap();
+	assert!(std::fs::metadata(&png).unwrap().len() > 300_000);
+	assert_eq!(imgest::load_image(&png).unwrap(), (image::ImageFormat::Png.into(), img.clone()));
+	assert_eq!(imgest::load_image_with_metadata(&png, &imgest::LoadOptions::new()).unwrap().image, img);
+	assert_eq!(imgest::load_raw(&png).unwrap().1.data, img.as_bytes());
+
+	let jpeg = dir.path().join("large.jpg");
+	img.save_with_format(&jpeg, image::ImageFormat::Jpeg).unwrap();
+	assert_eq!(imgest::load_image(&jpeg).unwrap(), imgest::load_image_from_vec(std::fs::read(&jpeg).unwrap()).unwrap());
+
+	// Shorter than the 16 bytes the format is guessed from
+	let tiny = dir.path().join("tiny.png");
+	std::fs::write(&tiny, &encode_png(&img)[..10]).unwrap();
+	assert!(matches!(imgest::load_image(&tiny), Err(imgest::Error::Io(_))));
+}
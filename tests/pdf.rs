@@ -0,0 +1,108 @@
+mod common;
+
+use imgest::Format;
+
+
+/// A PDF of the given objects (dictionary, stream data), numbered from 1 in order. Streams get their `/Length` from
+/// object 100, written at the end, when the dictionary says `/Length 100 0 R`.
+fn pdf(objects: &[(&str, Option<&[u8]>)]) -> Vec<u8> {
+	let mut out = b"%PDF-1.7\n%\xE2\xE3\xCF\xD3\n".to_vec();
+	let mut indirect_length = None;
+	for (n, (dict, stream)) in objects.iter().enumerate() {
+		out.extend_from_slice(format!("{} 0 obj\n{}", n + 1, dict).as_bytes());
+		if let Some(stream) = stream {
+			if dict.contains("100 0 R") {
+				indirect_length = Some(stream.len());
+			}
+			out.extend_from_slice(b"\nstream\r\n");
+			out.extend_from_slice(stream);
+			out.extend_from_slice(b"\nendstream");
+		}
+		out.extend_from_slice(b"\nendobj\n");
+	}
+	if let Some(length) = indirect_length {
+		out.extend_from_slice(format!("100 0 obj\n{length}\nendobj\n").as_bytes());
+	}
+	out.extend_from_slice(b"trailer\n<< /Root 1 0 R >>\n%%EOF\n");
+	out
+}
+
+
+#[test]
+fn pdf_is_detected() {
+	assert_eq!(Format::guess(b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n1 0 obj"), Some(Format::Pdf));
+	assert_eq!(Format::Pdf.image_format(), None);
+}
+
+
+#[cfg(not(feature = "pdf"))]
+#[test]
+fn pdf_needs_feature() {
+	let data = pdf(&[("<< /Type /Catalog >>", None)]);
+	assert!(matches!(imgest::load_image_from_bytes(&data), Err(imgest::Error::UnsupportedFormat)));
+}
+
+
+#[cfg(feature = "pdf")]
+#[test]
+fn pdf_largest_image() {
+	use std::io::Cursor;
+
+	use common::zlib_stored;
+	use image::{ExtendedColorType, ImageEncoder, RgbImage, codecs::jpeg::JpegEncoder};
+
+	let rgb = RgbImage::from_fn(6, 4, |x, y| image::Rgb([x as u8 * 40, y as u8 * 60, 7]));
+	// PNG predicted rows: Sub on the first, Up on the rest
+	let mut predicted = Vec::new();
+	for y in 0..4 {
+		let row = &rgb.as_raw()[y * 18..(y + 1) * 18];
+		if y == 0 {
+			predicted.push(1);
+			predicted.extend((0..18).map(|i| row[i].wrapping_sub(if i >= 3 { row[i - 3] } else { 0 })));
+		} else {
+			predicted.push(2);
+			predicted.extend((0..18).map(|i| row[i].wrapping_sub(rgb.as_raw()[(y - 1) * 18 + i])));
+		}
+	}
+	let flate = zlib_stored(&predicted);
+
+	let mut jpeg = Vec::new();
+	JpegEncoder::new_with_quality(&mut jpeg, 90).write_image(&[128; 2 * 2 * 3], 2, 2, ExtendedColorType::Rgb8).unwrap();
+
+	let data = pdf(&[
+		("<< /Type /Catalog /Pages 2 0 R >>", None),
+		("<< /Type /Pages /Kids [3 0 R] /Count 1 >>", None),
+		("<< /Type /Page /MediaBox [0 0 612 792] /Resources << /XObject << /Im1 4 0 R /Im2 5 0 R >> >> >>", None),
+		(
+			"<< /Type /XObject /Subtype /Image /Width 6 /Height 4 /ColorSpace [/ICCBased 6 0 R] /BitsPerComponent 8 /Filter \
+			 /FlateDecode /DecodeParms << /Predictor 15 /Colors 3 /Columns 6 >> /Length 100 0 R >>",
+			Some(&flate),
+		),
+		(
+			&format!(
+				"<< /Type /XObject /Subtype /Image /Width 2 /Height 2 /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /DCTDecode /Length {} >>",
+				jpeg.len()
+			),
+			Some(&jpeg),
+		),
+		("<< /N 3 /Length 0 >>", Some(b"")),
+	]);
+
+	let (format, img) = imgest::load_image_from_bytes(&data).unwrap();
+	assert_eq!(format, Format::Pdf);
+	assert_eq!(img.as_rgb8().unwrap(), &rgb);
+
+	let info = imgest::probe_image_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!((info.format, info.width, info.height, info.color_type), (Format::Pdf, 6, 4, image::ColorType::Rgb8));
+
+	// Without the deflated image, the JPEG is the largest
+	let data = pdf(&[(
+		&format!("<< /Subtype /Image /Width 2 /Height 2 /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter [/DCTDecode] /Length {} >>", jpeg.len()),
+		Some(&jpeg),
+	)]);
+	let (_, img) = imgest::load_image_from_bytes(&data).unwrap();
+	assert_eq!((img.width(), img.height()), (2, 2));
+
+	let data = pdf(&[("<< /Type /Catalog >>", None)]);
+	assert!(matches!(imgest::load_image_from_bytes(&data), Err(imgest::Error::Unsupported(_))));
+}
@@ -0,0 +1,53 @@
+use std::io::Cursor;
+
+use image::{DynamicImage, GrayImage, ImageEncoder, codecs::jpeg::JpegEncoder, imageops::FilterType};
+use imgest::phash::{self, PHash};
+
+
+fn pattern(seed: u32) -> GrayImage {
+	GrayImage::from_fn(320, 240, |x, y| {
+		let v = (x * seed / 7) ^ (y * (seed + 3) / 5);
+		image::Luma([(v % 256) as u8])
+	})
+}
+
+
+#[test]
+fn similar_images_hash_close() {
+	let img = pattern(3);
+	let hash = phash::phash(&img);
+	assert_eq!(phash::phash(&img), hash);
+
+	// Rescaled, and JPEG recompressed
+	let resized = image::imageops::resize(&img, 160, 120, FilterType::Triangle);
+	assert!(phash::phash(&resized).distance(hash) <= 4);
+	let mut jpeg = Vec::new();
+	JpegEncoder::new_with_quality(&mut jpeg, 70).write_image(img.as_raw(), 320, 240, image::ExtendedColorType::L8).unwrap();
+	let (_, decoded) = imgest::load_image_from_reader(Cursor::new(&jpeg)).unwrap();
+	assert!(phash::phash(&decoded.into_luma8()).distance(hash) <= 4);
+
+	assert!(phash::phash(&pattern(11)).distance(hash) > 10);
+}
+
+
+#[test]
+fn phash_file_uses_the_dc_preview_for_jpegs() {
+	let img = DynamicImage::ImageLuma8(pattern(5));
+	let dir = tempfile::tempdir().unwrap();
+	let path = dir.path().join("pattern.jpg");
+	img.save(&path).unwrap();
+	assert!(phash::phash_file(&path).unwrap().distance(phash::phash(img.as_luma8().unwrap())) <= 4);
+
+	let path = dir.path().join("pattern.png");
+	img.save(&path).unwrap();
+	assert_eq!(phash::phash_file(&path).unwrap(), phash::phash(img.as_luma8().unwrap()));
+}
+
+
+#[test]
+fn hex_roundtrip() {
+	let hash = PHash(0x0123_4567_89ab_cdef);
+	assert_eq!(hash.to_string(), "0123456789abcdef");
+	assert_eq!("0123456789abcdef".parse::<PHash>().unwrap(), hash);
+	assert_eq!(hash.distance(PHash(!hash.0)), 64);
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use std::io::Cursor;
+
+use common::RawPng;
+use image::{DynamicImage, ImageFormat};
+use imgest::{Format, LoadOptions, OutputColor, Placeholder, PlaceholderFill};
+
+
+#[test]
+fn failures_get_a_placeholder() {
+	// A PNG cut off partway through its pixels probes as 40x30 but can't be decoded
+	let png = RawPng::new(40, 30, 8, 2).encode(&vec![vec![0; 40 * 3]; 30]);
+	let truncated = &png[..png.len() - 100];
+	let (format, img, warning) = imgest::load_image_from_reader_or_placeholder(Cursor::new(truncated), &LoadOptions::new());
+	assert_eq!(format, Some(Format::Image(ImageFormat::Png)));
+	assert!(warning.is_some());
+	let img = img.as_rgba8().unwrap();
+	assert_eq!(img.dimensions(), (40, 30));
+	assert_eq!(img.get_pixel(0, 0).0, [0xCC, 0xCC, 0xCC, 0xFF]);
+	assert_eq!(img.get_pixel(16, 0).0, [0xFF; 4]);
+	assert_eq!(img.get_pixel(16, 16).0, [0xCC, 0xCC, 0xCC, 0xFF]);
+
+	// Unrecognized input falls back to the placeholder's own size
+	let options = LoadOptions::new().output(OutputColor::Luma8).placeholder(Placeholder {
+		fill: PlaceholderFill::Solid([0, 0, 0, 255]),
+		width: 8,
+		height: 4,
+	});
+	let (format, img, warning) = imgest::load_image_from_reader_or_placeholder(Cursor::new(b"not an image at all"), &options);
+	assert_eq!(format, None);
+	assert!(matches!(warning, Some(imgest::Error::UnsupportedFormat)));
+	assert_eq!(img, DynamicImage::ImageLuma8(image::GrayImage::new(8, 4)));
+
+	// So does a probed size over the limits
+	let options = options.max_pixels(100);
+	let (_, img, _) = imgest::load_image_from_reader_or_placeholder(Cursor::new(truncated), &options);
+	assert_eq!((img.width(), img.height()), (8, 4));
+
+	let (_, _, warning) = imgest::load_image_or_placeholder("does/not/exist.png", &options);
+	assert!(matches!(warning, Some(imgest::Error::Io(_))));
+}
+
+
+#[test]
+fn successes_are_not_placeholders() {
+	let png = RawPng::new(2, 1, 8, 0).encode(&[vec![7, 9]]);
+	let (format, img, warning) = imgest::load_image_from_reader_or_placeholder(Cursor::new(&png), &LoadOptions::new());
+	assert_eq!(format, Some(Format::Image(ImageFormat::Png)));
+	assert!(warning.is_none());
+	assert_eq!(img.as_luma8().unwrap().as_raw(), &[7, 9]);
+}
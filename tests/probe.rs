@@ -0,0 +1,78 @@
+mod common;
+
+use std::io::Cursor;
+
+use common::encode_png;
+use image::{ColorType, DynamicImage, ImageBuffer, Rgba, RgbImage};
+use imgest::{Format, ImageInfo};
+
+
+fn encode(img: &DynamicImage, format: image::ImageFormat) -> Vec<u8> {
+	let mut data = Vec::new();
+	img.write_to(&mut Cursor::new(&mut data), format).unwrap();
+	data
+}
+
+
+#[test]
+fn matches_full_decode() {
+	let rgb = DynamicImage::ImageRgb8(RgbImage::from_fn(23, 11, |x, y| image::Rgb([x as u8 * 10, y as u8 * 20, 5])));
+	let rgba16 = DynamicImage::ImageRgba16(ImageBuffer::from_fn(5, 9, |x, y| Rgba([x as u16 * 999, y as u16 * 555, 7, 65535])));
+	let fixtures = [
+		encode_png(&rgb),
+		encode_png(&rgba16),
+		encode(&rgb, image::ImageFormat::Jpeg),
+		encode(&rgb.grayscale(), image::ImageFormat::Jpeg),
+		encode(&rgb, image::ImageFormat::Gif),
+		encode(&rgba16, image::ImageFormat::Tiff),
+		encode(&rgb, image::ImageFormat::Bmp),
+		encode(&DynamicImage::ImageRgb32F(rgb.to_rgb32f()), image::ImageFormat::OpenExr),
+	];
+
+	for data in fixtures {
+		let info = imgest::probe_image_from_reader(Cursor::new(&data)).unwrap();
+		let (format, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+		let expected = ImageInfo {
+			format,
+			width: img.width(),
+			height: img.height(),
+			bit_depth: match img.color() {
+				ColorType::Rgb32F | ColorType::Rgba32F => 32,
+				ColorType::Rgba16 => 16,
+				_ => 8,
+			},
+			color_type: img.color(),
+			animated: false,
+		};
+		assert_eq!(info, expected);
+	}
+}
+
+
+#[test]
+fn reports_animation_without_rejecting() {
+	let mut data = Vec::new();
+	{
+		let mut encoder = image::codecs::gif::GifEncoder::new(&mut data);
+		for shade in [0, 255] {
+			let frame = image::RgbaImage::from_pixel(4, 4, Rgba([shade, 0, 0, 255]));
+			encoder.encode_frame(image::Frame::new(frame)).unwrap();
+		}
+	}
+
+	let info = imgest::probe_image_from_reader(Cursor::new(&data)).unwrap();
+	assert!(info.animated);
+	assert_eq!((info.format, info.width, info.height), (Format::Image(image::ImageFormat::Gif), 4, 4));
+	assert!(matches!(imgest::load_image_from_reader(Cursor::new(&data)), Err(imgest::Error::Animated)));
+}
+
+
+#[test]
+fn probes_files() {
+	let dir = tempfile::tempdir().unwrap();
+	let path = dir.path().join("image.png");
+	std::fs::write(&path, encode_png(&DynamicImage::ImageLuma8(image::GrayImage::new(640, 480)))).unwrap();
+	let info = imgest::probe_image(&path).unwrap();
+	assert_eq!((info.width, info.height, info.color_type, info.bit_depth), (640, 480, ColorType::L8, 8));
+	assert!(imgest::probe_image(dir.path().join("missing.png")).is_err());
+}
@@ -0,0 +1,119 @@
+use std::io::Cursor;
+
+use image::{ExtendedColorType, ImageEncoder, RgbImage, codecs::jpeg::JpegEncoder};
+use imgest::JpegDecoder;
+
+
+fn gradient() -> RgbImage {
+	RgbImage::from_fn(64, 48, |x, y| image::Rgb([(x * 4) as u8, (y * 5) as u8, ((x ^ y) * 4) as u8]))
+}
+
+
+fn progressive_jpeg() -> Vec<u8> {
+	let rgb = gradient();
+	let mut out = Vec::new();
+	let mut encoder = jpeg_encoder::Encoder::new(&mut out, 90);
+	encoder.set_progressive(true);
+	encoder.encode(rgb.as_raw(), 64, 48, jpeg_encoder::ColorType::Rgb).unwrap();
+	out
+}
+
+
+fn mean_abs_diff(a: &RgbImage, b: &RgbImage) -> f64 {
+	a.as_raw().iter().zip(b.as_raw()).map(|(&a, &b)| f64::from(a.abs_diff(b))).sum::<f64>() / a.as_raw().len() as f64
+}
+
+
+#[test]
+fn progressive_refinements_converge() {
+	let data = progressive_jpeg();
+	let decoder = JpegDecoder::new(Cursor::new(&data)).unwrap();
+	assert!(decoder.is_progressive());
+	let scans = decoder.scan_count();
+	assert!(scans > 2, "expected several scans, got {scans}");
+
+	let (_, full) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	let full = full.to_rgb8();
+
+	let refinements: Vec<_> = decoder.refinements().collect::<Result<_, _>>().unwrap();
+	assert_eq!(refinements.len(), scans);
+	assert!(refinements.iter().enumerate().all(|(i, r)| r.scans == i + 1 && r.is_final == (i + 1 == scans)));
+	assert!(refinements.iter().all(|r| r.image.width() == 64 && r.image.height() == 48));
+
+	let errors: Vec<_> = refinements.iter().map(|r| mean_abs_diff(&r.image.to_rgb8(), &full)).collect();
+	assert_eq!(*errors.last().unwrap(), 0.0);
+	assert!(errors[0] > 0.0);
+	// Once every component has its DC scan the preview should already resemble the final image
+	assert!(errors[2] < 40.0, "{errors:?}");
+
+	// Asking for more scans than exist is the full decode
+	assert_eq!(decoder.preview(scans + 10).unwrap().to_rgb8(), full);
+}
+
+
+#[test]
+fn baseline_has_a_single_scan() {
+	let rgb = gradient();
+	let mut data = Vec::new();
+	JpegEncoder::new(&mut data).write_image(rgb.as_raw(), 64, 48, ExtendedColorType::Rgb8).unwrap();
+
+	let decoder = JpegDecoder::new(Cursor::new(&data)).unwrap();
+	assert!(!decoder.is_progressive());
+	assert_eq!(decoder.scan_count(), 1);
+	let refinements: Vec<_> = decoder.refinements().collect::<Result<_, _>>().unwrap();
+	assert_eq!(refinements.len(), 1);
+	assert!(refinements[0].is_final);
+}
+
+
+#[test]
+fn read_header_stops_at_the_frame() {
+	let data = progressive_jpeg();
+	let mut reader = Cursor::new(&data);
+	let header = JpegDecoder::read_header(&mut reader).unwrap();
+	assert_eq!((header.width, header.height, header.components, header.precision), (64, 48, 3, 8));
+	assert!(header.progressive);
+	assert_eq!(header.color_type, image::ColorType::Rgb8);
+	assert!(reader.position() < 1024);
+
+	// Nothing past the frame header is needed
+	let sof = data.windows(2).position(|w| w == [0xFF, 0xC2]).unwrap();
+	assert_eq!(JpegDecoder::read_header(Cursor::new(&data[..sof + 12])).unwrap(), header);
+	assert!(JpegDecoder::read_header(Cursor::new(&data[..sof + 4])).is_err());
+}
+
+
+#[test]
+fn truncated_progressive_stops_at_last_complete_scan() {
+	let data = progressive_jpeg();
+	let decoder = JpegDecoder::new(Cursor::new(&data)).unwrap();
+	assert!(!decoder.is_truncated());
+	let scans = decoder.scan_count();
+	let expected = decoder.preview(scans - 1).unwrap();
+
+	// Cut partway into the last scan's entropy coded data
+	let last_sos = data.windows(2).rposition(|w| w == [0xFF, 0xDA]).unwrap();
+	let truncated = &data[..last_sos + 20];
+	let decoder = JpegDecoder::new(Cursor::new(truncated)).unwrap();
+	assert!(decoder.is_truncated());
+	assert_eq!(decoder.scan_count(), scans - 1);
+	let (_, img) = imgest::load_image_from_reader(Cursor::new(truncated)).unwrap();
+	assert_eq!(img, expected);
+}
+
+
+#[test]
+fn complete_progressive_without_eoi_keeps_every_scan() {
+	let data = progressive_jpeg();
+	let decoder = JpegDecoder::new(Cursor::new(&data)).unwrap();
+	let scans = decoder.scan_count();
+	let (_, full) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+
+	// All of the last scan's data is there, only the EOI marker is missing
+	let without_eoi = &data[..data.len() - 2];
+	let decoder = JpegDecoder::new(Cursor::new(without_eoi)).unwrap();
+	assert!(!decoder.is_truncated());
+	assert_eq!(decoder.scan_count(), scans);
+	let (_, img) = imgest::load_image_from_reader(Cursor::new(without_eoi)).unwrap();
+	assert_eq!(img, full);
+}
@@ -0,0 +1,69 @@
+use std::io::Cursor;
+
+use image::{ImageDecoder, ImageEncoder, RgbImage, RgbaImage, codecs::qoi::QoiEncoder};
+use imgest::QoiDecoder;
+
+
+fn encode(data: &[u8], width: u32, height: u32, color: image::ExtendedColorType) -> Vec<u8> {
+	let mut out = Vec::new();
+	QoiEncoder::new(&mut out).write_image(data, width, height, color).unwrap();
+	out
+}
+
+
+/// Runs, repeats, small and large deltas and alpha changes, so every op gets used.
+fn rgba() -> RgbaImage {
+	RgbaImage::from_fn(37, 19, |x, y| match (x / 8, y % 3) {
+		(0, _) => image::Rgba([10, 20, 30, 255]),
+		(1, _) => image::Rgba([10 + x as u8, 20 + x as u8, 30 + x as u8, 255]),
+		(2, 0) => image::Rgba([(x * 40) as u8, (y * 70) as u8, 5, 255]),
+		(2, _) => image::Rgba([(x * 40) as u8, (y * 70) as u8, 5, (x * 9) as u8]),
+		_ => image::Rgba([(x % 2 * 200) as u8, 50, (y % 2 * 200) as u8, 128]),
+	})
+}
+
+
+#[test]
+fn decodes_rgba() {
+	let img = rgba();
+	let data = encode(img.as_raw(), img.width(), img.height(), image::ExtendedColorType::Rgba8);
+
+	let decoder = QoiDecoder::new(Cursor::new(&data)).unwrap();
+	assert_eq!(decoder.dimensions(), (37, 19));
+	assert_eq!(decoder.color_type(), image::ColorType::Rgba8);
+	assert!(!decoder.is_linear());
+
+	let (format, decoded) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!(format, image::ImageFormat::Qoi);
+	assert_eq!(decoded.as_rgba8().unwrap(), &img);
+}
+
+
+#[test]
+fn decodes_rgb() {
+	let img = RgbImage::from_fn(16, 9, |x, y| image::Rgb([(x * 16) as u8, (y * 28) as u8, 77]));
+	let data = encode(img.as_raw(), img.width(), img.height(), image::ExtendedColorType::Rgb8);
+
+	let (_, decoded) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!(decoded.as_rgb8().unwrap(), &img);
+}
+
+
+#[test]
+fn truncated_is_an_error() {
+	let img = rgba();
+	let data = encode(img.as_raw(), img.width(), img.height(), image::ExtendedColorType::Rgba8);
+	// Dropping only the end marker still leaves every pixel
+	assert!(imgest::load_image_from_reader(Cursor::new(&data[..data.len() - 8])).is_ok());
+	assert!(imgest::load_image_from_reader(Cursor::new(&data[..data.len() / 2])).is_err());
+}
+
+
+#[test]
+fn limits_are_respected() {
+	let img = rgba();
+	let data = encode(img.as_raw(), img.width(), img.height(), image::ExtendedColorType::Rgba8);
+	let mut limits = image::Limits::default();
+	limits.max_image_width = Some(36);
+	assert!(matches!(QoiDecoder::with_limits(Cursor::new(&data), limits), Err(imgest::Error::Limits(_))));
+}
@@ -0,0 +1,152 @@
+#![cfg(feature = "raw")]
+
+use std::io::Cursor;
+
+use image::{ExtendedColorType, ImageEncoder, RgbImage, codecs::jpeg::JpegEncoder};
+use imgest::Format;
+
+
+enum V {
+	Long(u32),
+	Bytes([u8; 4]),
+	/// Offset of the IFD with this index
+	Ifd(usize),
+	/// Offset and length of the blob with this index
+	Blob(usize),
+	BlobLen(usize),
+}
+
+
+/// An IFD's entries, and the index of the next IFD in its chain
+type TestIfd = (Vec<(u16, V)>, Option<usize>);
+
+
+/// Little endian TIFF: the blobs right after the header, then the IFDs, each with the index of the next IFD in its
+/// chain. Every entry is a single LONG, except `V::Bytes` which is four BYTEs.
+fn tiff(signature: &[u8; 4], ifds: &[TestIfd], blobs: &[&[u8]]) -> Vec<u8> {
+	let mut blob_offsets = Vec::new();
+	let mut offset = 16;
+	for blob in blobs {
+		blob_offsets.push(offset);
+		offset += blob.len() as u32;
+	}
+	let mut ifd_offsets = Vec::new();
+	for (ifd, _) in ifds {
+		ifd_offsets.push(offset);
+		offset += 2 + ifd.len() as u32 * 12 + 4;
+	}
+
+	let mut out = b"II\x2A\0".to_vec();
+	out.extend_from_slice(&ifd_offsets[0].to_le_bytes());
+	out.extend_from_slice(signature);
+	out.extend_from_slice(&[0; 4]);
+	for blob in blobs {
+		out.extend_from_slice(blob);
+	}
+	for (ifd, next) in ifds {
+		out.extend_from_slice(&(ifd.len() as u16).to_le_bytes());
+		for (tag, value) in ifd {
+			out.extend_from_slice(&tag.to_le_bytes());
+			let (kind, value) = match value {
+				V::Long(v) => (4u16, *v),
+				V::Bytes(b) => (1, u32::from_le_bytes(*b)),
+				V::Ifd(i) => (4, ifd_offsets[*i]),
+				V::Blob(i) => (4, blob_offsets[*i]),
+				V::BlobLen(i) => (4, blobs[*i].len() as u32),
+			};
+			let count: u32 = if kind == 1 { 4 } else { 1 };
+			out.extend_from_slice(&kind.to_le_bytes());
+			out.extend_from_slice(&count.to_le_bytes());
+			out.extend_from_slice(&value.to_le_bytes());
+		}
+		out.extend_from_slice(&next.map_or(0, |i| ifd_offsets[i]).to_le_bytes());
+	}
+	out
+}
+
+
+fn jpeg(width: u32, height: u32, shade: u8) -> Vec<u8> {
+	let rgb = RgbImage::from_pixel(width, height, image::Rgb([shade, shade, shade]));
+	let mut out = Vec::new();
+	JpegEncoder::new_with_quality(&mut out, 95).write_image(rgb.as_raw(), width, height, ExtendedColorType::Rgb8).unwrap();
+	out
+}
+
+
+#[test]
+fn dng_uses_largest_preview() {
+	let thumbnail = jpeg(16, 12, 40);
+	let preview = jpeg(64, 48, 200);
+	let sensor = vec![0u8; 64];
+	let data = tiff(
+		b"\0\0\0\0",
+		&[
+			// IFD0: the thumbnail as a JPEG strip, with the sensor data in a SubIFD
+			(
+				vec![
+					(0x0103, V::Long(7)),
+					(0x0111, V::Blob(0)),
+					(0x0117, V::BlobLen(0)),
+					(0x014A, V::Ifd(2)),
+					(0xC612, V::Bytes([1, 4, 0, 0])),
+				],
+				Some(1),
+			),
+			// IFD1: the full-size preview through JPEGInterchangeFormat
+			(vec![(0x0201, V::Blob(1)), (0x0202, V::BlobLen(1))], None),
+			// SubIFD: CFA sensor data
+			(vec![(0x0103, V::Long(1)), (0x0106, V::Long(32803)), (0x0111, V::Blob(2)), (0x0117, V::BlobLen(2))], None),
+		],
+		&[&thumbnail, &preview, &sensor],
+	);
+
+	let (format, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!(format, Format::RawPreview);
+	assert_eq!(format.image_format(), None);
+	assert_eq!((img.width(), img.height()), (64, 48));
+	assert!(img.to_rgb8().get_pixel(10, 10).0[0].abs_diff(200) <= 2);
+}
+
+
+#[test]
+fn cr2_skips_lossless_sensor_data() {
+	let preview = jpeg(32, 24, 90);
+	// SOF3 (lossless) header claiming a much larger image; it must never be chosen
+	let lossless = [0xFF, 0xD8, 0xFF, 0xC3, 0, 11, 14, 0x10, 0, 0x20, 0, 2, 0x01, 0x11, 0, 0xFF, 0xD9];
+	let data = tiff(
+		b"CR\x02\0",
+		&[
+			(vec![(0x0103, V::Long(6)), (0x0111, V::Blob(0)), (0x0117, V::BlobLen(0))], Some(1)),
+			(vec![(0x0103, V::Long(6)), (0x0111, V::Blob(1)), (0x0117, V::BlobLen(1))], None),
+		],
+		&[&preview, &lossless],
+	);
+
+	let (format, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!(format, Format::RawPreview);
+	assert_eq!((img.width(), img.height()), (32, 24));
+}
+
+
+#[test]
+fn raw_without_preview_is_unsupported() {
+	let sensor = vec![0u8; 64];
+	let data = tiff(
+		b"\0\0\0\0",
+		&[(vec![(0x0103, V::Long(34713)), (0x0111, V::Blob(0)), (0x0117, V::BlobLen(0))], None)],
+		&[&sensor],
+	);
+	let result = imgest::load_image_from_reader(Cursor::new(&data));
+	assert!(matches!(result, Err(imgest::Error::Unsupported(_))), "{result:?}");
+}
+
+
+#[test]
+fn plain_tiff_is_not_raw() {
+	let rgb = RgbImage::from_pixel(4, 4, image::Rgb([1, 2, 3]));
+	let mut data = Vec::new();
+	image::DynamicImage::ImageRgb8(rgb.clone()).write_to(&mut Cursor::new(&mut data), image::ImageFormat::Tiff).unwrap();
+	let (format, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!(format, image::ImageFormat::Tiff);
+	assert_eq!(img.into_rgb8(), rgb);
+}
@@ -0,0 +1,64 @@
+use std::io::Cursor;
+
+use image::{DynamicImage, GrayImage};
+use imgest::{JpegDecoder, JpegOptions, LoadOptions};
+
+
+/// A 64x16 grayscale JPEG with a restart marker after every block, so each 8x8 block is its own restart interval.
+fn restart_jpeg() -> Vec<u8> {
+	let img = GrayImage::from_fn(64, 16, |x, y| image::Luma([(x * 3 + y * 5) as u8]));
+	let mut out = Vec::new();
+	let mut encoder = jpeg_encoder::Encoder::new(&mut out, 90);
+	encoder.set_restart_interval(1);
+	encoder.encode(img.as_raw(), 64, 16, jpeg_encoder::ColorType::Luma).unwrap();
+	out
+}
+
+
+fn block(img: &GrayImage, bx: u32, by: u32) -> Vec<u8> {
+	(0..8).flat_map(|y| (0..8).map(move |x| img.get_pixel(bx * 8 + x, by * 8 + y).0[0])).collect()
+}
+
+
+fn resync() -> JpegOptions {
+	JpegOptions {
+		resync: true,
+		..Default::default()
+	}
+}
+
+
+#[test]
+fn damaged_interval_is_replaced_by_its_neighbor() {
+	let mut data = restart_jpeg();
+	let sos = data.windows(2).position(|w| w == [0xFF, 0xDA]).unwrap();
+	let restarts: Vec<usize> = (sos..data.len() - 1).filter(|&i| data[i] == 0xFF && (0xD0..=0xD7).contains(&data[i + 1])).collect();
+	// Drop the entropy coded data of the sixth block, between the fifth and sixth restart markers
+	data.drain(restarts[4] + 2..restarts[5]);
+
+	let decoder = JpegDecoder::with_options(Cursor::new(&data), &resync()).unwrap();
+	assert_eq!(decoder.repaired_intervals(), 1);
+	let img = DynamicImage::from_decoder(decoder).unwrap().into_luma8();
+	assert_eq!(block(&img, 5, 0), block(&img, 4, 0));
+
+	// Everything else decodes as in the intact file
+	let (_, intact) = imgest::load_image_from_reader(Cursor::new(restart_jpeg())).unwrap();
+	let intact = intact.into_luma8();
+	assert_eq!(block(&img, 6, 0), block(&intact, 6, 0));
+	assert_eq!(block(&img, 5, 1), block(&intact, 5, 1));
+}
+
+
+#[test]
+fn intact_files_are_untouched() {
+	let data = restart_jpeg();
+	let decoder = JpegDecoder::with_options(Cursor::new(&data), &resync()).unwrap();
+	assert_eq!(decoder.repaired_intervals(), 0);
+	// The last interval runs to the end of a file without an EOI
+	let decoder = JpegDecoder::with_options(Cursor::new(&data[..data.len() - 2]), &resync()).unwrap();
+	assert_eq!(decoder.repaired_intervals(), 0);
+
+	let options = LoadOptions { jpeg: resync(), ..Default::default() };
+	let (_, img) = imgest::load_image_from_reader_with_options(Cursor::new(&data), &options).unwrap();
+	assert_eq!(img, imgest::load_image_from_reader(Cursor::new(&data)).unwrap().1);
+}
@@ -0,0 +1,161 @@
+use std::io::Cursor;
+
+use image::{
+	ColorType, DynamicImage, ExtendedColorType, ImageDecoder, ImageEncoder, ImageFormat, Limits,
+	codecs::{jpeg::JpegEncoder, png::PngEncoder},
+};
+use imgest::{JpegDecoder, PngDecoder};
+use proptest::prelude::*;
+
+
+const MAX_DIM: u32 = 48;
+
+const PNG_COLOR_TYPES: &[ColorType] = &[
+	ColorType::L8,
+	ColorType::La8,
+	ColorType::Rgb8,
+	ColorType::Rgba8,
+	ColorType::L16,
+	ColorType::La16,
+	ColorType::Rgb16,
+	ColorType::Rgba16,
+];
+
+
+/// A randomly generated image in a color type our PNG path supports, stored as native-endian bytes.
+#[derive(Debug, Clone)]
+struct RandomImage {
+	width: u32,
+	height: u32,
+	color_type: ColorType,
+	data: Vec<u8>,
+}
+
+impl RandomImage {
+	fn to_dynamic(&self) -> DynamicImage {
+		let (w, h) = (self.width, self.height);
+		let as_u16 = || self.data.chunks_exact(2).map(|c| u16::from_ne_bytes([c[0], c[1]])).collect::<Vec<u16>>();
+		match self.color_type {
+			ColorType::L8 => DynamicImage::ImageLuma8(image::ImageBuffer::from_raw(w, h, self.data.clone()).unwrap()),
+			ColorType::La8 => DynamicImage::ImageLumaA8(image::ImageBuffer::from_raw(w, h, self.data.clone()).unwrap()),
+			ColorType::Rgb8 => DynamicImage::ImageRgb8(image::ImageBuffer::from_raw(w, h, self.data.clone()).unwrap()),
+			ColorType::Rgba8 => DynamicImage::ImageRgba8(image::ImageBuffer::from_raw(w, h, self.data.clone()).unwrap()),
+			ColorType::L16 => DynamicImage::ImageLuma16(image::ImageBuffer::from_raw(w, h, as_u16()).unwrap()),
+			ColorType::La16 => DynamicImage::ImageLumaA16(image::ImageBuffer::from_raw(w, h, as_u16()).unwrap()),
+			ColorType::Rgb16 => DynamicImage::ImageRgb16(image::ImageBuffer::from_raw(w, h, as_u16()).unwrap()),
+			ColorType::Rgba16 => DynamicImage::ImageRgba16(image::ImageBuffer::from_raw(w, h, as_u16()).unwrap()),
+			_ => unreachable!(),
+		}
+	}
+}
+
+
+fn random_image() -> impl Strategy<Value = RandomImage> {
+	(1..=MAX_DIM, 1..=MAX_DIM, prop::sample::select(PNG_COLOR_TYPES)).prop_flat_map(|(width, height, color_type)| {
+		let len = width as usize * height as usize * color_type.bytes_per_pixel() as usize;
+		prop::collection::vec(any::<u8>(), len).prop_map(move |data| RandomImage {
+			width,
+			height,
+			color_type,
+			data,
+		})
+	})
+}
+
+
+fn encode_png(img: &DynamicImage) -> Vec<u8> {
+	let mut out = Vec::new();
+	PngEncoder::new(&mut out)
+		.write_image(img.as_bytes(), img.width(), img.height(), ExtendedColorType::from(img.color()))
+		.expect("PNG encoding should succeed");
+	out
+}
+
+
+fn encode_jpeg(width: u32, height: u32, data: &[u8], quality: u8) -> Vec<u8> {
+	let mut out = Vec::new();
+	JpegEncoder::new_with_quality(&mut out, quality)
+		.write_image(data, width, height, ExtendedColorType::Rgb8)
+		.expect("JPEG encoding should succeed");
+	out
+}
+
+
+proptest! {
+	#[test]
+	fn png_roundtrip_is_lossless(img in random_image()) {
+		let original = img.to_dynamic();
+		let encoded = encode_png(&original);
+
+		let (format, decoded) = imgest::load_image_from_reader(Cursor::new(&encoded)).unwrap();
+		prop_assert_eq!(format, ImageFormat::Png);
+		prop_assert_eq!(decoded.color(), original.color());
+		prop_assert_eq!(decoded.as_bytes(), original.as_bytes());
+
+		// decode -> encode -> decode must be stable
+		let reencoded = encode_png(&decoded);
+		let (_, redecoded) = imgest::load_image_from_reader(Cursor::new(&reencoded)).unwrap();
+		prop_assert_eq!(redecoded, decoded);
+	}
+
+	#[test]
+	fn png_probed_dimensions_match_decoded(img in random_image()) {
+		let encoded = encode_png(&img.to_dynamic());
+
+		let decoder = PngDecoder::new(Cursor::new(&encoded)).unwrap();
+		let probed = decoder.dimensions();
+		let color_type = decoder.color_type();
+		let decoded = DynamicImage::from_decoder(decoder).unwrap();
+		prop_assert_eq!(probed, (img.width, img.height));
+		prop_assert_eq!(probed, (decoded.width(), decoded.height()));
+		prop_assert_eq!(color_type, decoded.color());
+	}
+
+	#[test]
+	fn jpeg_probed_dimensions_match_decoded(
+		(width, height, data) in (1..=MAX_DIM, 1..=MAX_DIM).prop_flat_map(|(w, h)| {
+			(Just(w), Just(h), prop::collection::vec(any::<u8>(), (w * h * 3) as usize))
+		}),
+		quality in 1u8..=100,
+	) {
+		let encoded = encode_jpeg(width, height, &data, quality);
+
+		let decoder = JpegDecoder::new(Cursor::new(&encoded)).unwrap();
+		let probed = decoder.dimensions();
+		let decoded = DynamicImage::from_decoder(decoder).unwrap();
+		prop_assert_eq!(probed, (width, height));
+		prop_assert_eq!(probed, (decoded.width(), decoded.height()));
+
+		let (format, loaded) = imgest::load_image_from_reader(Cursor::new(&encoded)).unwrap();
+		prop_assert_eq!(format, ImageFormat::Jpeg);
+		prop_assert_eq!(loaded, decoded);
+	}
+
+	#[test]
+	fn png_limits_are_respected(img in random_image(), max_width in 1..=MAX_DIM, max_height in 1..=MAX_DIM) {
+		let encoded = encode_png(&img.to_dynamic());
+		let mut limits = Limits::no_limits();
+		limits.max_image_width = Some(max_width);
+		limits.max_image_height = Some(max_height);
+
+		let within_limits = img.width <= max_width && img.height <= max_height;
+		let result = PngDecoder::with_limits(Cursor::new(&encoded), limits);
+		prop_assert_eq!(result.is_ok(), within_limits);
+	}
+
+	#[test]
+	fn jpeg_limits_are_respected(
+		(width, height) in (1..=MAX_DIM, 1..=MAX_DIM),
+		max_width in 1..=MAX_DIM,
+		max_height in 1..=MAX_DIM,
+	) {
+		let encoded = encode_jpeg(width, height, &vec![128; (width * height * 3) as usize], 90);
+		let mut limits = Limits::no_limits();
+		limits.max_image_width = Some(max_width);
+		limits.max_image_height = Some(max_height);
+
+		let within_limits = width <= max_width && height <= max_height;
+		let mut decoder = JpegDecoder::new(Cursor::new(&encoded)).unwrap();
+		prop_assert_eq!(decoder.set_limits(limits).is_ok(), within_limits);
+	}
+}
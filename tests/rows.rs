@@ -0,0 +1,95 @@
+mod common;
+
+use std::io::Cursor;
+
+use common::{RawPng, adam7_scanlines, encode_png};
+use image::{DynamicImage, Limits, RgbImage};
+use imgest::{PngDecoder, PngOptions, RowPosition};
+
+
+fn gradient(width: u32, height: u32) -> RgbImage {
+	RgbImage::from_fn(width, height, |x, y| image::Rgb([(x * 13) as u8, (y * 19) as u8, ((x + y) * 7) as u8]))
+}
+
+
+fn interlaced_png(width: u32, height: u32) -> Vec<u8> {
+	let mut png = RawPng::new(width, height, 8, 2);
+	png.interlaced = true;
+	png.encode(&adam7_scanlines(width, height, 3, gradient(width, height).as_raw()))
+}
+
+
+/// Streams `data` and reassembles the full image from the rows, returning it along with every row position seen.
+fn stream(data: &[u8], options: &PngOptions) -> (Vec<u8>, Vec<RowPosition>) {
+	let decoder = PngDecoder::with_options(Cursor::new(data), Limits::no_limits(), options).unwrap();
+	let mut rows = decoder.into_rows();
+	let (width, height) = rows.dimensions();
+	let bpp = usize::from(rows.color_type().bytes_per_pixel());
+
+	let mut image = vec![0; width as usize * height as usize * bpp];
+	let mut positions = Vec::new();
+	while let Some(row) = rows.next_row().unwrap() {
+		assert_eq!(row.data.len(), row.position.width as usize * bpp, "{:?}", row.position);
+		row.position.scatter(row.data, &mut image, width, bpp);
+		positions.push(row.position);
+	}
+	(image, positions)
+}
+
+
+#[test]
+fn non_interlaced_rows_match_full_decode() {
+	for img in [DynamicImage::ImageRgb8(gradient(23, 9)), DynamicImage::ImageRgb16(DynamicImage::ImageRgb8(gradient(23, 9)).to_rgb16())] {
+		let data = encode_png(&img);
+		let (image, positions) = stream(&data, &PngOptions::default());
+		assert_eq!(image, img.as_bytes());
+		assert_eq!(positions.len(), 9);
+		assert!(positions.iter().enumerate().all(|(y, p)| p.pass.is_none() && p.y == y as u32 && p.x_step == 1 && p.width == 23));
+	}
+}
+
+
+#[test]
+fn interlaced_passes_reassemble() {
+	let data = interlaced_png(19, 13);
+	let (image, positions) = stream(&data, &PngOptions::default());
+	assert_eq!(image, gradient(19, 13).into_raw());
+
+	let passes: Vec<u8> = positions.iter().filter_map(|p| p.pass).collect();
+	assert!(passes.is_sorted());
+	assert_eq!(passes.first(), Some(&1));
+	assert_eq!(passes.last(), Some(&7));
+	// Pass 7 holds every odd row in full
+	let pass7: Vec<_> = positions.iter().filter(|p| p.pass == Some(7)).collect();
+	assert_eq!(pass7.iter().map(|p| p.y).collect::<Vec<_>>(), vec![1, 3, 5, 7, 9, 11]);
+	assert!(pass7.iter().all(|p| p.width == 19));
+}
+
+
+#[test]
+fn interlaced_tiny_images_skip_empty_passes() {
+	for (width, height) in [(1, 1), (1, 5), (3, 2), (5, 1), (8, 8)] {
+		let (image, _) = stream(&interlaced_png(width, height), &PngOptions::default());
+		assert_eq!(image, gradient(width, height).into_raw(), "{width}x{height}");
+	}
+}
+
+
+#[test]
+fn indexed_rows_are_unpacked() {
+	let mut out = Vec::new();
+	let mut encoder = png::Encoder::new(&mut out, 5, 2);
+	encoder.set_color(png::ColorType::Indexed);
+	encoder.set_depth(png::BitDepth::Two);
+	encoder.set_palette(vec![0; 12]);
+	let mut writer = encoder.write_header().unwrap();
+	writer.write_image_data(&[0b00_01_10_11, 0b00_000000, 0b11_10_01_00, 0b11_000000]).unwrap();
+	writer.finish().unwrap();
+
+	let options = PngOptions {
+		keep_indexed: true,
+		..Default::default()
+	};
+	let (image, _) = stream(&out, &options);
+	assert_eq!(image, [0, 1, 2, 3, 0, 3, 2, 1, 0, 3]);
+}
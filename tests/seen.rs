@@ -0,0 +1,62 @@
+use imgest::seen::{ContentHash, MemorySeenSet, SeenSet, SortedFileSeenSet};
+
+
+#[test]
+fn content_hash_round_trips() {
+	let hash = ContentHash::of(b"abc");
+	assert_eq!(hash.to_string(), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+	assert_eq!(hash.to_string().parse::<ContentHash>().unwrap(), hash);
+	assert!("ba78".parse::<ContentHash>().is_err());
+}
+
+
+#[test]
+fn memory_set_marks_repeats() {
+	let mut seen = MemorySeenSet::new();
+	assert!(seen.insert(ContentHash::of(b"a")).unwrap());
+	assert!(seen.insert(ContentHash::of(b"b")).unwrap());
+	assert!(!seen.insert(ContentHash::of(b"a")).unwrap());
+	assert_eq!(seen.len(), 2);
+}
+
+
+#[test]
+fn file_set_persists_across_runs() {
+	let dir = tempfile::tempdir().unwrap();
+	let path = dir.path().join("seen.bin");
+	let hashes: Vec<ContentHash> = (0..200u32).map(|i| ContentHash::of(&i.to_le_bytes())).collect();
+
+	let mut seen = SortedFileSeenSet::open(&path).unwrap();
+	assert!(seen.is_empty());
+	for hash in &hashes[..100] {
+		assert!(seen.insert(*hash).unwrap());
+	}
+	assert!(!seen.insert(hashes[5]).unwrap());
+	seen.flush().unwrap();
+	// A second batch merged into an existing file
+	for hash in &hashes[100..150] {
+		assert!(seen.insert(*hash).unwrap());
+	}
+	seen.flush().unwrap();
+	drop(seen);
+	assert_eq!(std::fs::metadata(&path).unwrap().len(), 150 * 32);
+
+	let mut seen = SortedFileSeenSet::open(&path).unwrap();
+	assert_eq!(seen.len(), 150);
+	for (i, hash) in hashes.iter().enumerate() {
+		assert_eq!(seen.insert(*hash).unwrap(), i >= 150, "hash {i}");
+	}
+	seen.flush().unwrap();
+
+	let stored = std::fs::read(&path).unwrap();
+	assert!(stored.chunks_exact(32).zip(stored.chunks_exact(32).skip(1)).all(|(a, b)| a < b));
+}
+
+
+#[test]
+fn rejects_truncated_file() {
+	let dir = tempfile::tempdir().unwrap();
+	let path = dir.path().join("seen.bin");
+	std::fs::write(&path, [0; 40]).unwrap();
+	assert!(SortedFileSeenSet::open(&path).is_err());
+}
@@ -0,0 +1,32 @@
+use imgest::shutdown::{self, ShutdownHandle};
+
+
+#[test]
+#[cfg(unix)]
+fn signal_requests_shutdown() {
+	shutdown::install_handlers().unwrap();
+	assert!(!shutdown::requested());
+	assert!(!ShutdownHandle::on_signals().is_requested());
+	// SAFETY: the handler installed above only sets a flag on the first signal
+	assert_eq!(unsafe { libc::raise(libc::SIGTERM) }, 0);
+	assert!(shutdown::requested());
+	assert!(ShutdownHandle::on_signals().is_requested());
+	// Handles not tied to signals are only stopped directly
+	assert!(!ShutdownHandle::new().is_requested());
+}
+
+
+#[test]
+fn handle_requests() {
+	let handle = ShutdownHandle::new();
+	let clone = handle.clone();
+	assert!(!clone.is_requested());
+	handle.drain();
+	assert!(clone.is_requested());
+	assert!(!clone.cancel_token().is_cancelled());
+
+	let handle = ShutdownHandle::new();
+	handle.clone().cancel();
+	assert!(handle.is_requested());
+	assert!(handle.cancel_token().is_cancelled());
+}
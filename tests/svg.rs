@@ -0,0 +1,81 @@
+use imgest::Format;
+
+
+const SQUARE: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" width="40" height="20">
+	<rect x="0" y="0" width="20" height="20" fill="#ff0000"/>
+	<rect x="20" y="0" width="20" height="20" fill="#0000ff" fill-opacity="0.5"/>
+</svg>"##;
+
+
+#[test]
+fn svg_is_detected() {
+	for start in [&b"<svg xmlns=\"http"[..], b"\xEF\xBB\xBF  <?xml version=", b"\n<!DOCTYPE svg PUBLIC"] {
+		assert_eq!(Format::guess(start), Some(Format::Svg), "{:?}", String::from_utf8_lossy(start));
+	}
+	assert_eq!(Format::Svg.image_format(), None);
+}
+
+
+#[cfg(not(feature = "svg"))]
+#[test]
+fn svg_needs_feature() {
+	let result = imgest::load_image_from_reader(std::io::Cursor::new(SQUARE));
+	assert!(matches!(result, Err(imgest::Error::UnsupportedFormat)), "{result:?}");
+}
+
+
+#[cfg(feature = "svg")]
+mod rasterize {
+	use std::io::Cursor;
+
+	use imgest::{Format, LoadOptions, SvgOptions};
+
+	use super::SQUARE;
+
+
+	fn load(svg: SvgOptions) -> image::RgbaImage {
+		let options = LoadOptions { svg, ..Default::default() };
+		let (format, img) = imgest::load_image_from_reader_with_options(Cursor::new(SQUARE), &options).unwrap();
+		assert_eq!(format, Format::Svg);
+		img.as_rgba8().expect("SVGs decode to Rgba8").clone()
+	}
+
+
+	#[test]
+	fn intrinsic_size() {
+		let img = load(SvgOptions::default());
+		assert_eq!(img.dimensions(), (40, 20));
+		assert_eq!(img.get_pixel(5, 10).0, [255, 0, 0, 255]);
+		// Not premultiplied
+		let blue = img.get_pixel(30, 10).0;
+		assert_eq!([blue[0], blue[1], blue[2]], [0, 0, 255]);
+		assert!(blue[3].abs_diff(128) <= 1);
+	}
+
+
+	#[test]
+	fn dpi_and_target_size() {
+		assert_eq!(load(SvgOptions { dpi: 192, size: None }).dimensions(), (80, 40));
+		assert_eq!(load(SvgOptions { dpi: 48, size: None }).dimensions(), (20, 10));
+		// Fits inside the box, keeping the aspect ratio
+		assert_eq!(load(SvgOptions { dpi: 96, size: Some((100, 100)) }).dimensions(), (100, 50));
+		assert_eq!(load(SvgOptions { dpi: 96, size: Some((100, 10)) }).dimensions(), (20, 10));
+	}
+
+
+	#[test]
+	fn limits_are_respected() {
+		let mut limits = image::Limits::default();
+		limits.max_image_width = Some(39);
+		let result = imgest::SvgDecoder::with_options(Cursor::new(SQUARE), &SvgOptions::default(), limits);
+		assert!(matches!(result, Err(imgest::Error::Limits(_))));
+	}
+
+
+	#[test]
+	fn malformed_svg_is_an_error() {
+		let result = imgest::load_image_from_reader(Cursor::new("<svg xmlns=\"http://www.w3.org/2000/svg\" <<<"));
+		assert!(matches!(result, Err(imgest::Error::Decoding(_))), "{result:?}");
+	}
+}
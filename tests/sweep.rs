@@ -1,33 +1,25 @@
+#![cfg(feature = "conformance")]
+
 use std::path::{Path, PathBuf};
 
 use anyhow::{Context as _, Result};
 use futures_util::StreamExt as _;
-use image::ImageFormat;
+use imgest::conformance::{self, ConformanceError, DiffStats, PillowEnv, Profiles, Tolerances};
 use indicatif::{ProgressBar, ProgressStyle};
 use log::info;
-use pyo3::{
-	Py, PyErr, PyResult, Python,
-	sync::PyOnceLock,
-	types::{PyAnyMethods as _, PyBytes, PyBytesMethods as _, PyModule},
-};
 use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
 use tokio::io::AsyncWriteExt as _;
 
 
-static PIL_IMAGE_MODULE: PyOnceLock<Py<PyModule>> = PyOnceLock::new();
-// Our test set includes images around 129M pixels, so lift the cap to a higher but still sane value.
-const PIL_MAX_IMAGE_PIXELS: usize = 200_000_000;
-
-// Generally speaking PNGs should always be exact matches since the format is lossless and well defined.
-// But we compare using 8-bit RGBA, so if the image is 16-bit per channel originally there can be subtle differences in the 16->8 bit conversion.
-// Only in those cases do we allow a small tolerance.
-const PNG_AVG_DIFF_LIMIT: f64 = 0.32;
-const PNG_MAX_DIFF_LIMIT: u64 = 1;
+// Set to a results CSV from a previous sweep to only re-test the images that failed in it or weren't in it at all.
+// Passes from the previous run are carried over, so the new results file is again complete.
+const RERUN_ENV: &str = "SWEEP_RERUN_FROM";
 
-const JPG_AVG_DIFF_LIMIT: f64 = 0.30;
-const JPG_MAX_DIFF_LIMIT: u64 = 20;
 
-const EDGE_MAX_DIFF_LIMIT: u64 = 80;
+// Set to a profile name to compare with its tolerances instead of the built-in profile picked for the Pillow build,
+// and optionally point the other at a TOML file to take profiles from it instead of the built-in `tolerances.toml`.
+const PROFILE_ENV: &str = "SWEEP_PROFILE";
+const PROFILES_FILE_ENV: &str = "SWEEP_PROFILES_FILE";
 
 
 // Filenames of images to ignore in the test
@@ -47,7 +39,28 @@ async fn sweep_test() -> Result<()> {
 		.try_init()?;
 
 	// Disable python's handler so that Ctrl-C works properly
-	disable_python_sigint_handler();
+	conformance::disable_python_sigint_handler();
+
+	// Fail fast on a Pillow that can't decode what we compare against, and pick tolerances for the one we have.
+	// Other versions still run, with a warning, since the versions recorded in the results make any shift traceable.
+	let pillow = PillowEnv::probe().context("failed to probe Pillow")?;
+	pillow.check_required().map_err(anyhow::Error::msg)?;
+	info!("Reference decoder: {}", pillow.summary());
+	if !pillow.is_tested_version() {
+		log::warn!("Pillow {} differs from the tested {}; tolerances may be off", pillow.pillow, conformance::TESTED_PILLOW_VERSION);
+	}
+	let tolerances = match std::env::var(PROFILE_ENV) {
+		Ok(name) => {
+			let profiles = match std::env::var_os(PROFILES_FILE_ENV) {
+				Some(file) => Profiles::load(Path::new(&file))?,
+				None => Profiles::builtin().clone(),
+			};
+			let tolerances = profiles.get(&name).with_context(|| format!("no tolerance profile named {name:?}"))?.clone();
+			info!("Using tolerance profile {name:?}");
+			tolerances
+		},
+		Err(_) => Tolerances::for_pillow(&pillow),
+	};
 
 	// Fetch all image paths from bigasp database
 	let mut paths = fetch_paths().await?;
@@ -66,15 +79,27 @@ async fn sweep_test() -> Result<()> {
 	// Filter out ignored images
 	let ignore_set: std::collections::HashSet<&str> = IGNORE_LIST.iter().cloned().collect();
 	paths.retain(|path| {
-		if let Some(filename) = path.file_name().and_then(|n| n.to_str()) {
-			if ignore_set.contains(filename) {
-				info!("Skipping image at path {:?} due to ignore list", path);
-				return false;
-			}
+		if let Some(filename) = path.file_name().and_then(|n| n.to_str())
+			&& ignore_set.contains(filename)
+		{
+			info!("Skipping image at path {:?} due to ignore list", path);
+			return false;
 		}
 		true
 	});
 
+	// Differential re-run: drop everything that passed last time
+	let previous_passes = match std::env::var_os(RERUN_ENV) {
+		Some(previous) => {
+			let passes = read_previous_passes(Path::new(&previous))?;
+			let before = paths.len();
+			paths.retain(|path| !passes.contains_key(path));
+			info!("Re-run mode: skipping {} images that passed in {:?}", before - paths.len(), previous);
+			passes
+		},
+		None => std::collections::HashMap::new(),
+	};
+
 	let pb = add_progress_bar(paths.len() as u64, "images", "Testing image loading...");
 	let pb_for_tasks = pb.clone();
 	let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
@@ -82,8 +107,9 @@ async fn sweep_test() -> Result<()> {
 		.for_each_concurrent(16, move |path| {
 			let pb = pb_for_tasks.clone();
 			let tx = tx.clone();
+			let tolerances = tolerances.clone();
 			async move {
-				let diff = test_loading_image(path.clone()).await;
+				let diff = test_loading_image(path.clone(), tolerances).await;
 				let _ = tx.send((path, diff));
 				pb.inc(1);
 			}
@@ -93,19 +119,30 @@ async fn sweep_test() -> Result<()> {
 	pb.finish_and_clear();
 	info!("Completed image loading sweep test in {} seconds", pb.elapsed().as_secs_f32());
 
-	// Log MAEs as CSV
+	// Log MAEs and pass/fail as CSV
 	let mut mae_csv = tokio::io::BufWriter::new(tokio::fs::File::create("mae_log.csv").await?);
-	mae_csv.write_all(b"image_path,mae\n").await?;
-	while let Some((path, diff)) = rx.recv().await {
-		let path_str = path.to_string_lossy();
-		let path_csv = csv_escape_field(&path_str);
-		match diff {
-			Some(diff) => {
-				mae_csv.write_all(format!("{},{}\n", path_csv, diff).as_bytes()).await?;
-			},
-			None => {
-				mae_csv.write_all(format!("{},\n", path_csv).as_bytes()).await?;
+	// Record what the results were compared against; `read_previous_passes` skips this line
+	mae_csv.write_all(format!("# {}\n", pillow.summary()).as_bytes()).await?;
+	mae_csv.write_all(b"image_path,mae,ok,differing_pixels,bias,histogram\n").await?;
+	// Only the MAE of a carried over pass is known
+	let carried_over = previous_passes.into_iter().map(|(path, mae)| (path, Ok(DiffStats { mae, ..Default::default() })));
+	let mut results = Vec::new();
+	while let Some(result) = rx.recv().await {
+		results.push(result);
+	}
+	for (path, result) in carried_over.chain(results) {
+		// Write the raw path bytes so non-UTF8 paths survive the round trip through the log
+		mae_csv.write_all(&csv_escape_field(path.as_os_str().as_encoded_bytes())).await?;
+		let (stats, ok) = match &result {
+			Ok(stats) => (Some(stats), 1),
+			Err(stats) => (stats.as_ref(), 0),
+		};
+		match stats {
+			Some(stats) => {
+				let row = format!(",{},{ok},{},{},{}\n", stats.mae, stats.differing_pixels, stats.bias, stats.histogram_summary(16));
+				mae_csv.write_all(row.as_bytes()).await?
 			},
+			None => mae_csv.write_all(format!(",,{ok},,,\n").as_bytes()).await?,
 		}
 	}
 	mae_csv.flush().await?;
@@ -134,137 +171,27 @@ async fn fetch_paths() -> Result<Vec<PathBuf>> {
 }
 
 
-async fn test_loading_image(path: PathBuf) -> Option<f64> {
-	// Load with Rust decoder
+/// Returns the differences if the image passed, and otherwise the differences if it got as far as comparing pixels.
+async fn test_loading_image(path: PathBuf, tolerances: Tolerances) -> Result<DiffStats, Option<DiffStats>> {
 	let path_clone = path.clone();
-	let Ok(res) = tokio::task::spawn_blocking(move || imgest::load_image(&path_clone)).await else {
-		log::error!("spawn_blocking task panicked");
-		return None;
-	};
-
-	let (format, img) = match res {
-		Ok(img) => img,
-		Err(e) => {
-			log::error!("IMG_FAIL: Failed to load image at path {:?}: {:?}", path, e);
-			return None;
-		},
-	};
-
-	// Load using Pillow
-	let (python_w, python_h, python_data) = match python_decode_image(path.clone()).await {
-		Ok(data) => data,
-		Err(e) => {
-			log::error!("IMG_FAIL: Python decoding failed for image at path {:?}: {:?}", path, e);
-			return None;
-		},
-	};
-
-	if img.width() != python_w || img.height() != python_h {
-		log::error!(
-			"IMG_FAIL: Image dimension mismatch at path {:?}: Rust decoder gave {}x{}, Python decoder gave {}x{}",
-			path,
-			img.width(),
-			img.height(),
-			python_w,
-			python_h
-		);
-		return None;
-	}
-
-	// Compare pixel data
-	let Ok(res) = tokio::task::spawn_blocking(move || {
-		// For PNGs the limits are strict except in the case of 16-bit per channel images where we allow a small tolerance for 16->8 bit conversion differences
-		let is_16bit = img.color().bits_per_pixel() == 16 * (img.color().channel_count() as u16);
-		let img = img.into_rgba8();
-		let (max_diff, avg_diff_limit) = match format {
-			ImageFormat::Png if is_16bit => (PNG_MAX_DIFF_LIMIT, PNG_AVG_DIFF_LIMIT),
-			ImageFormat::Png => (0, 0.0),
-			ImageFormat::Jpeg => (JPG_MAX_DIFF_LIMIT, JPG_AVG_DIFF_LIMIT),
-			_ => (0, 0.0),
-		};
-
-		let w = img.width() as usize;
-		let h = img.height() as usize;
-		let rust_data = img.into_raw();
-		if rust_data.len() != python_data.len() {
-			return Err((
-				0.0,
-				format!(
-					"({:?}) data length mismatch: Rust decoder gave {}, Python decoder gave {}",
-					format,
-					rust_data.len(),
-					python_data.len()
-				),
-			));
-		}
-
-		if rust_data != python_data {
-			let mut diff_sum = 0;
-			let mut diff_max_inner = 0;
-			let mut diff_max_edge = 0;
-			//let mut edge_outliers = 0;
-
-			let bpp = 4usize; // RGBA8
-
-			for (i, (b1, b2)) in rust_data.iter().zip(python_data.iter()).enumerate() {
-				let diff = (*b1 as i64 - *b2 as i64).abs() as u64;
-				if diff == 0 {
-					continue;
-				}
-
-				diff_sum += diff;
-
-				let pix = i / bpp;
-				let x = pix % w;
-				let y = pix / w;
-				let is_edge = x == 0 || y == 0 || x == w - 1 || y == h - 1;
-
-				if is_edge {
-					diff_max_edge = diff_max_edge.max(diff);
-					/*if diff > max_diff {
-						edge_outliers += 1;
-					}*/
-				} else {
-					diff_max_inner = diff_max_inner.max(diff);
-				}
-			}
-
-			let avg_diff = diff_sum as f64 / rust_data.len() as f64;
-
-			//let edge_rgba_bytes = (2 * w + 2 * h - 4) * 4;
-			//let allowed_edge_outliers = (edge_rgba_bytes / 20_000).clamp(4, 128); // ~0.005%
-
-			// Fail if the differences exceed the limits
-			if avg_diff > avg_diff_limit || diff_max_inner > max_diff || diff_max_edge > EDGE_MAX_DIFF_LIMIT
-			/*|| edge_outliers > allowed_edge_outliers*/
-			{
-				return Err((
-					avg_diff,
-					format!(
-						"({format:?}) mismatch, avg_diff={avg_diff}, max_inner={diff_max_inner}, max_edge={diff_max_edge}", //, edge_outliers={edge_outliers}/{allowed_edge_outliers}",
-					),
-				));
-			}
-
-			Ok(avg_diff)
-		} else {
-			Ok(0.0)
-		}
-	})
-	.await
-	else {
+	let Ok(res) = tokio::task::spawn_blocking(move || conformance::compare(&path_clone, &tolerances)).await else {
 		log::error!("spawn_blocking task panicked");
-		return None;
+		return Err(None);
 	};
 
 	match res {
-		Err((mae, msg)) => {
-			log::error!("IMG_FAIL: Image data mismatch at path {:?}: {}", path, msg);
-			Some(mae)
-		},
-		Ok(avg_diff) => {
+		Ok(comparison) => {
 			log::trace!("IMG_OK: Successfully loaded and verified image at path {:?}", path);
-			Some(avg_diff)
+			Ok(comparison.stats)
+		},
+		Err(ConformanceError::Mismatch(comparison)) => {
+			let stats = comparison.stats.clone();
+			log::error!("IMG_FAIL: Image data mismatch at path {:?}: {}", path, ConformanceError::Mismatch(comparison));
+			Err(Some(stats))
+		},
+		Err(e) => {
+			log::error!("IMG_FAIL: {} at path {:?}", e, path);
+			Err(None)
 		},
 	}
 }
@@ -347,85 +274,89 @@ impl std::io::Write for SplitWriter {
 }
 
 
-fn decode_with_pillow(py: Python<'_>, path: &Path) -> PyResult<(u32, u32, Vec<u8>)> {
-	let pil = PIL_IMAGE_MODULE
-		.get_or_try_init(py, || {
-			let module = py.import("PIL.Image")?;
-			module.setattr("MAX_IMAGE_PIXELS", PIL_MAX_IMAGE_PIXELS)?;
-			let file_module = py.import("PIL.ImageFile")?;
-			file_module.setattr("LOAD_TRUNCATED_IMAGES", true)?;
-			let module = module.unbind();
-			Ok::<Py<PyModule>, PyErr>(module)
-		})?
-		.bind(py);
-
-	// Open image
-	let image = pil.call_method1("open", (path.to_string_lossy().as_ref(),))?;
-
-	// Normalize 16-bit grayscale to 8-bit before RGBA conversion (avoids oddness in Pillow's direct I;16 -> RGBA conversion which saturates to white).
-	let mode: String = image.getattr("mode")?.extract()?;
-	let image = if mode.starts_with("I;16") {
-		// 65536-entry LUT: v -> v >> 8
-		let lut: Vec<u16> = (0..=65535).map(|v| (v >> 8) as u16).collect();
-
-		image.call_method1("convert", ("I",))?.call_method1("point", (lut, "L"))?
-	} else {
-		image
-	};
-
-	// Convert to RGBA8
-	let image = image.call_method1("convert", ("RGBA",))?;
-
-	// (width, height)
-	let (width, height): (u32, u32) = image.getattr("size")?.extract()?;
-
-	// Get raw bytes as Bound<PyBytes>
-	let bytes = image.call_method0("tobytes")?;
-	let bytes = bytes.cast::<PyBytes>()?;
+/// Reads a results CSV written by `sweep_test`, returning the images that passed along with their MAE. Results from
+/// before the `ok` column existed count as failures, so they are all re-tested; later columns are ignored.
+fn read_previous_passes(path: &Path) -> Result<std::collections::HashMap<PathBuf, f64>> {
+	use std::os::unix::ffi::OsStrExt as _;
 
-	let data = bytes.as_bytes().to_vec();
-
-	Ok((width, height, data))
+	let data = std::fs::read(path).with_context(|| format!("failed to read previous results {path:?}"))?;
+	let mut passes = std::collections::HashMap::new();
+	for row in csv_parse(&data).into_iter().skip(1) {
+		if let [path, mae, ok, ..] = &row[..]
+			&& ok == b"1"
+		{
+			let mae = std::str::from_utf8(mae).ok().and_then(|mae| mae.parse().ok()).unwrap_or(0.0);
+			passes.insert(PathBuf::from(std::ffi::OsStr::from_bytes(path)), mae);
+		}
+	}
+	Ok(passes)
 }
 
-fn csv_escape_field(s: &str) -> String {
-	let needs_quote = s.contains(',') || s.contains('"') || s.contains('\n') || s.contains('\r');
-	if !needs_quote {
-		return s.to_string();
-	}
 
-	let mut out = String::with_capacity(s.len() + 2);
-	out.push('"');
-	for ch in s.chars() {
-		if ch == '"' {
-			out.push('"');
+/// Splits CSV into rows of raw fields, undoing `csv_escape_field`.
+fn csv_parse(data: &[u8]) -> Vec<Vec<Vec<u8>>> {
+	let mut rows = Vec::new();
+	let mut row = Vec::new();
+	let mut field = Vec::new();
+	let mut quoted = false;
+	let mut bytes = data.iter().copied().peekable();
+	while let Some(b) = bytes.next() {
+		match (quoted, b) {
+			(true, b'"') if bytes.peek() == Some(&b'"') => {
+				bytes.next();
+				field.push(b'"');
+			},
+			(true, b'"') => quoted = false,
+			(true, b) => field.push(b),
+			(false, b'"') => quoted = true,
+			(false, b',') => row.push(std::mem::take(&mut field)),
+			(false, b'\n') => {
+				row.push(std::mem::take(&mut field));
+				rows.push(std::mem::take(&mut row));
+			},
+			(false, b) => field.push(b),
 		}
-		out.push(ch);
 	}
-	out.push('"');
-	out
+	if !field.is_empty() || !row.is_empty() {
+		row.push(field);
+		rows.push(row);
+	}
+	rows
 }
 
 
-fn disable_python_sigint_handler() {
-	let _ = Python::attach(|py| -> PyResult<()> {
-		let signal = py.import("signal")?;
-		let sigint = signal.getattr("SIGINT")?;
-		let sig_dfl = signal.getattr("SIG_DFL")?;
-		signal.getattr("signal")?.call1((sigint, sig_dfl))?;
-		Ok(())
-	});
+#[test]
+fn csv_roundtrip() {
+	let fields: [&[u8]; 4] = [b"plain", b"with,comma", b"with \"quote\"\nand newline", b""];
+	let mut data = Vec::new();
+	for _ in 0..2 {
+		data.extend(fields.iter().map(|&f| csv_escape_field(f)).collect::<Vec<_>>().join(&b','));
+		data.push(b'\n');
+	}
+	let rows = csv_parse(&data);
+	assert_eq!(rows.len(), 2);
+	for row in rows {
+		assert_eq!(row, fields.map(<[u8]>::to_vec));
+	}
 }
 
 
-pub async fn python_decode_image(path: PathBuf) -> Result<(u32, u32, Vec<u8>), PyErr> {
-	let join_handle = tokio::task::spawn_blocking(move || {
-		// Attach this blocking thread to the Python interpreter and run Pillow
-		Python::attach(|py| decode_with_pillow(py, &path))
-	});
+fn csv_escape_field(s: &[u8]) -> Vec<u8> {
+	let needs_quote = s.iter().any(|b| matches!(b, b',' | b'"' | b'\n' | b'\r'));
+	if !needs_quote {
+		return s.to_vec();
+	}
 
-	let py_result: PyResult<_> = join_handle.await.expect("blocking task panicked");
-	Ok(py_result?)
+	let mut out = Vec::with_capacity(s.len() + 2);
+	out.push(b'"');
+	for &b in s {
+		if b == b'"' {
+			out.push(b'"');
+		}
+		out.push(b);
+	}
+	out.push(b'"');
+	out
 }
 
 
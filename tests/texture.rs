@@ -0,0 +1,137 @@
+#![cfg(feature = "texture")]
+
+use std::io::Cursor;
+
+use imgest::{Format, TextureDecoder, TextureEncoding};
+
+
+const RED: [u8; 4] = [255, 0, 0, 255];
+const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+
+/// A BC1 block with pure red and pure blue endpoints (in four color mode, since red > blue as RGB565), where the
+/// texels on even rows use red and those on odd rows blue.
+fn bc1_block() -> [u8; 8] {
+	let mut block = [0; 8];
+	block[0..2].copy_from_slice(&0xF800u16.to_le_bytes());
+	block[2..4].copy_from_slice(&0x001Fu16.to_le_bytes());
+	// 2 bits per texel, one byte per row: index 0 (red) or 1 (blue)
+	block[4..8].copy_from_slice(&[0x00, 0x55, 0x00, 0x55]);
+	block
+}
+
+
+fn expected_bc1(width: u32, height: u32) -> Vec<u8> {
+	(0..height).flat_map(|y| (0..width).flat_map(move |_| if y % 2 == 0 { RED } else { BLUE })).collect()
+}
+
+
+/// A DDS file with a legacy pixel format: either a FourCC, or an RGB bit count and channel masks.
+fn dds(width: u32, height: u32, fourcc: Option<&[u8; 4]>, masks: Option<(u32, [u32; 4])>, data: &[u8]) -> Vec<u8> {
+	let mut out = vec![0; 128];
+	out[0..4].copy_from_slice(b"DDS ");
+	out[4..8].copy_from_slice(&124u32.to_le_bytes());
+	out[12..16].copy_from_slice(&height.to_le_bytes());
+	out[16..20].copy_from_slice(&width.to_le_bytes());
+	out[76..80].copy_from_slice(&32u32.to_le_bytes());
+	if let Some(fourcc) = fourcc {
+		out[80..84].copy_from_slice(&0x4u32.to_le_bytes());
+		out[84..88].copy_from_slice(fourcc);
+	}
+	if let Some((bit_count, [r, g, b, a])) = masks {
+		let flags = 0x40 | if a != 0 { 0x1 } else { 0 };
+		out[80..84].copy_from_slice(&u32::to_le_bytes(flags));
+		for (offset, value) in [(88, bit_count), (92, r), (96, g), (100, b), (104, a)] {
+			out[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+		}
+	}
+	out.extend_from_slice(data);
+	out
+}
+
+
+fn ktx2(width: u32, height: u32, vk_format: u32, supercompression: u32, data: &[u8]) -> Vec<u8> {
+	let mut out = Vec::new();
+	out.extend_from_slice(b"\xABKTX 20\xBB\r\n\x1A\n");
+	// vkFormat, typeSize, pixelWidth, pixelHeight, pixelDepth, layerCount, faceCount, levelCount, supercompressionScheme
+	for value in [vk_format, 1, width, height, 0, 0, 1, 1, supercompression] {
+		out.extend_from_slice(&value.to_le_bytes());
+	}
+	// No data format descriptor, key/value data or supercompression global data
+	out.extend_from_slice(&[0; 32]);
+	// The one level, right after its index entry
+	let offset = out.len() as u64 + 24;
+	for value in [offset, data.len() as u64, data.len() as u64] {
+		out.extend_from_slice(&value.to_le_bytes());
+	}
+	out.extend_from_slice(data);
+	out
+}
+
+
+#[test]
+fn dds_bc1() {
+	// 6x6 needs 2x2 blocks, with the partial blocks cropped
+	let data = dds(6, 6, Some(b"DXT1"), None, &bc1_block().repeat(4));
+	let decoder = TextureDecoder::new(Cursor::new(&data)).unwrap();
+	assert_eq!(decoder.encoding(), TextureEncoding::Bc1);
+	assert_eq!(decoder.container(), image::ImageFormat::Dds);
+
+	let (format, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!(format, image::ImageFormat::Dds);
+	assert_eq!(img.as_rgba8().unwrap().as_raw(), &expected_bc1(6, 6));
+}
+
+
+#[test]
+fn dds_uncompressed() {
+	let texels: Vec<u8> = (0..4 * 2 * 4).map(|i| i as u8).collect();
+
+	// BGRA, the usual layout for A8R8G8B8
+	let data = dds(4, 2, None, Some((32, [0xFF_0000, 0xFF00, 0xFF, 0xFF00_0000])), &texels);
+	let (_, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	let expected: Vec<u8> = texels.chunks(4).flat_map(|t| [t[2], t[1], t[0], t[3]]).collect();
+	assert_eq!(img.as_rgba8().unwrap().as_raw(), &expected);
+
+	// 24-bit RGB without alpha comes out opaque
+	let data = dds(4, 2, None, Some((24, [0xFF, 0xFF00, 0xFF_0000, 0])), &texels[..4 * 2 * 3]);
+	let (_, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	let expected: Vec<u8> = texels[..4 * 2 * 3].chunks(3).flat_map(|t| [t[0], t[1], t[2], 255]).collect();
+	assert_eq!(img.as_rgba8().unwrap().as_raw(), &expected);
+
+	// Packed 16-bit formats aren't covered
+	let data = dds(4, 2, None, Some((16, [0xF800, 0x07E0, 0x001F, 0])), &texels[..16]);
+	assert!(matches!(imgest::load_image_from_reader(Cursor::new(&data)), Err(imgest::Error::Unsupported(_))));
+}
+
+
+#[test]
+fn ktx2_bc1() {
+	// VK_FORMAT_BC1_RGB_UNORM_BLOCK
+	let data = ktx2(8, 4, 131, 0, &bc1_block().repeat(2));
+	let (format, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!(format, Format::Ktx2);
+	assert_eq!(img.as_rgba8().unwrap().as_raw(), &expected_bc1(8, 4));
+}
+
+
+#[test]
+fn ktx2_unsupported() {
+	// Zstandard supercompression
+	let data = ktx2(8, 4, 131, 2, &bc1_block().repeat(2));
+	assert!(matches!(imgest::load_image_from_reader(Cursor::new(&data)), Err(imgest::Error::Unsupported(_))));
+	// ASTC 4x4
+	let data = ktx2(8, 4, 157, 0, &[0; 32]);
+	assert!(matches!(imgest::load_image_from_reader(Cursor::new(&data)), Err(imgest::Error::Unsupported(_))));
+}
+
+
+#[test]
+fn truncated_and_limits() {
+	let data = dds(8, 8, Some(b"DXT1"), None, &bc1_block().repeat(4));
+	assert!(imgest::load_image_from_reader(Cursor::new(&data[..data.len() - 1])).is_err());
+
+	let mut limits = image::Limits::default();
+	limits.max_image_width = Some(7);
+	assert!(matches!(TextureDecoder::with_limits(Cursor::new(&data), limits), Err(imgest::Error::Limits(_))));
+}
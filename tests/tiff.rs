@@ -0,0 +1,238 @@
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageDecoder, ImageEncoder, Rgb, RgbImage, codecs::tiff::TiffEncoder};
+use imgest::{LoadOptions, MultiPage, TiffDecoder, TiffOptions, TiffSamples};
+use tiff::encoder::{Compression, DeflateLevel, colortype};
+
+
+fn gradient16() -> image::ImageBuffer<Rgb<u16>, Vec<u16>> {
+	image::ImageBuffer::from_fn(9, 5, |x, y| Rgb([x as u16 * 7000 + 3, y as u16 * 13000 + 1, 0xFFFF - x as u16 * 257]))
+}
+
+
+fn encode_pages(pages: &[&[u16]], compression: Compression) -> Vec<u8> {
+	let mut out = Cursor::new(Vec::new());
+	let mut encoder = tiff::encoder::TiffEncoder::new(&mut out).unwrap().with_compression(compression);
+	for page in pages {
+		encoder.write_image::<colortype::RGB16>(9, 5, page).unwrap();
+	}
+	out.into_inner()
+}
+
+
+#[test]
+fn compressions_preserve_16_bit() {
+	let img = gradient16();
+	let compressions = [
+		("uncompressed", Compression::Uncompressed),
+		("LZW", Compression::Lzw),
+		("deflate", Compression::Deflate(DeflateLevel::Balanced)),
+		("PackBits", Compression::Packbits),
+	];
+	for (name, compression) in compressions {
+		let data = encode_pages(&[img.as_raw()], compression);
+		let (format, decoded) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+		assert_eq!(format, image::ImageFormat::Tiff);
+		assert_eq!(decoded, DynamicImage::ImageRgb16(img.clone()), "{name}");
+	}
+}
+
+
+#[test]
+fn multi_page() {
+	let first = gradient16();
+	let second = image::ImageBuffer::from_pixel(9, 5, Rgb([1u16, 2, 3]));
+	let data = encode_pages(&[first.as_raw(), second.as_raw()], Compression::Lzw);
+
+	assert!(TiffDecoder::new(Cursor::new(&data)).unwrap().is_multi_page());
+	let (_, decoded) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!(decoded, DynamicImage::ImageRgb16(first));
+
+	let mut out = Cursor::new(Vec::new());
+	let mut encoder = tiff::encoder::TiffEncoder::new(&mut out).unwrap();
+	encoder.write_image::<colortype::RGB16>(9, 5, second.as_raw()).unwrap();
+	encoder.write_image::<colortype::Gray8>(3, 2, &[0; 6]).unwrap();
+	let images = imgest::probe_images_from_reader(Cursor::new(out.into_inner())).unwrap();
+	let images: Vec<_> = images.iter().map(|image| (image.index, image.width, image.height, image.primary)).collect();
+	assert_eq!(images, [(0, 9, 5, true), (1, 3, 2, false)]);
+
+	let options = LoadOptions {
+		tiff: TiffOptions { multi_page: MultiPage::Reject },
+		..Default::default()
+	};
+	let result = imgest::load_image_from_reader_with_options(Cursor::new(&data), &options);
+	assert!(matches!(result, Err(imgest::Error::MultiPage)), "{result:?}");
+
+	// A single page is fine either way
+	let single = encode_pages(&[second.as_raw()], Compression::Lzw);
+	assert!(!TiffDecoder::new(Cursor::new(&single)).unwrap().is_multi_page());
+	imgest::load_image_from_reader_with_options(Cursor::new(&single), &options).unwrap();
+}
+
+
+/// A little endian, uncompressed, BlackIsZero bilevel TIFF, as written by document scanners.
+fn bilevel(width: u16, rows: &[&[u8]]) -> Vec<u8> {
+	let strip: Vec<u8> = rows.concat();
+	let entries: [(u16, u16, u32); 8] = [
+		(256, 3, u32::from(width)),    // ImageWidth
+		(257, 3, rows.len() as u32),   // ImageLength
+		(258, 3, 1),                   // BitsPerSample
+		(259, 3, 1),                   // Compression: none
+		(262, 3, 1),                   // PhotometricInterpretation: BlackIsZero
+		(273, 4, 8),                   // StripOffsets
+		(278, 3, rows.len() as u32),   // RowsPerStrip
+		(279, 4, strip.len() as u32),  // StripByteCounts
+	];
+	let mut out = b"II\x2A\0".to_vec();
+	out.extend_from_slice(&(8 + strip.len() as u32).to_le_bytes());
+	out.extend_from_slice(&strip);
+	out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+	for (tag, kind, value) in entries {
+		out.extend_from_slice(&tag.to_le_bytes());
+		out.extend_from_slice(&kind.to_le_bytes());
+		out.extend_from_slice(&1u32.to_le_bytes());
+		match kind {
+			3 => out.extend_from_slice(&[(value as u16).to_le_bytes(), [0, 0]].concat()),
+			_ => out.extend_from_slice(&value.to_le_bytes()),
+		}
+	}
+	out.extend_from_slice(&0u32.to_le_bytes());
+	out
+}
+
+
+#[test]
+fn bilevel_unpacks_to_l8() {
+	// 10 pixels per row, so each row is padded out to two bytes
+	let data = bilevel(10, &[&[0b1010_0000, 0b1100_0000], &[0b0000_1111, 0b0100_0000]]);
+	let decoder = TiffDecoder::new(Cursor::new(&data)).unwrap();
+	assert_eq!(decoder.color_type(), image::ColorType::L8);
+	let (_, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	let on = |bits: &str| bits.bytes().map(|b| if b == b'1' { 255 } else { 0 }).collect::<Vec<u8>>();
+	assert_eq!(img.as_luma8().unwrap().as_raw(), &[on("1010000011"), on("0000111101")].concat());
+}
+
+
+#[test]
+fn matches_image_crate_for_8_bit() {
+	let rgb = RgbImage::from_fn(7, 6, |x, y| Rgb([(x * 30) as u8, (y * 40) as u8, 77]));
+	let mut data = Vec::new();
+	TiffEncoder::new(Cursor::new(&mut data)).write_image(rgb.as_raw(), 7, 6, image::ExtendedColorType::Rgb8).unwrap();
+	let (_, decoded) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	assert_eq!(decoded, image::load_from_memory(&data).unwrap());
+}
+
+
+#[test]
+fn limits_are_respected() {
+	let data = encode_pages(&[gradient16().as_raw()], Compression::Uncompressed);
+	let mut limits = image::Limits::default();
+	limits.max_image_height = Some(4);
+	assert!(matches!(TiffDecoder::with_limits(Cursor::new(&data), limits), Err(imgest::Error::Limits(_))));
+}
+
+
+/// A little endian, uncompressed, chunky TIFF of `bands` 32-bit float samples per pixel, as GDAL writes satellite
+/// imagery.
+fn multiband_f32(width: u16, height: u16, bands: u16, samples: &[f32]) -> Vec<u8> {
+	let strip: Vec<u8> = samples.iter().flat_map(|v| v.to_le_bytes()).collect();
+	let bits_offset = 8 + strip.len() as u32;
+	let format_offset = bits_offset + u32::from(bands) * 2;
+	let entries: [(u16, u16, u32, u32); 11] = [
+		(256, 3, 1, u32::from(width)),        // ImageWidth
+		(257, 3, 1, u32::from(height)),       // ImageLength
+		(258, 3, u32::from(bands), bits_offset), // BitsPerSample
+		(259, 3, 1, 1),                       // Compression: none
+		(262, 3, 1, 1),                       // PhotometricInterpretation: BlackIsZero
+		(273, 4, 1, 8),                       // StripOffsets
+		(277, 3, 1, u32::from(bands)),        // SamplesPerPixel
+		(278, 3, 1, u32::from(height)),       // RowsPerStrip
+		(279, 4, 1, strip.len() as u32),      // StripByteCounts
+		(284, 3, 1, 1),                       // PlanarConfiguration: chunky
+		(339, 3, u32::from(bands), format_offset), // SampleFormat
+	];
+	let mut out = b"II\x2A\0".to_vec();
+	out.extend_from_slice(&(format_offset + u32::from(bands) * 2).to_le_bytes());
+	out.extend_from_slice(&strip);
+	out.extend(std::iter::repeat_n(32u16.to_le_bytes(), usize::from(bands)).flatten());
+	out.extend(std::iter::repeat_n(3u16.to_le_bytes(), usize::from(bands)).flatten());
+	out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+	for (tag, kind, count, value) in entries {
+		out.extend_from_slice(&tag.to_le_bytes());
+		out.extend_from_slice(&kind.to_le_bytes());
+		out.extend_from_slice(&count.to_le_bytes());
+		match (kind, count) {
+			(3, 1) => out.extend_from_slice(&[(value as u16).to_le_bytes(), [0, 0]].concat()),
+			_ => out.extend_from_slice(&value.to_le_bytes()),
+		}
+	}
+	out.extend_from_slice(&0u32.to_le_bytes());
+	out
+}
+
+
+#[test]
+fn multiband_float_decodes_first_three_bands() {
+	let (width, height, bands) = (4, 3, 5);
+	let samples: Vec<f32> = (0..width * height * bands).map(|i| i as f32 * 0.25 - 1.0).collect();
+	let data = multiband_f32(width as u16, height as u16, bands as u16, &samples);
+
+	let decoder = TiffDecoder::new(Cursor::new(&data)).unwrap();
+	assert_eq!(decoder.samples(), TiffSamples { bands: 5, bits_per_sample: 32, float: true });
+	assert_eq!(decoder.color_type(), image::ColorType::Rgb32F);
+	assert_eq!(decoder.dropped_bands(), 2);
+
+	let (_, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	let expected: Vec<f32> = samples.chunks_exact(5).flat_map(|pixel| pixel[..3].to_vec()).collect();
+	assert_eq!(img.as_rgb32f().unwrap().as_raw(), &expected);
+
+	let all = TiffDecoder::new(Cursor::new(&data)).unwrap().read_bands().unwrap();
+	assert_eq!((all.width, all.height, all.bands), (4, 3, 5));
+	assert_eq!(all.data, samples);
+}
+
+
+#[test]
+fn gray_float_expands_to_rgb() {
+	let gray: Vec<f32> = (0..6 * 4).map(|i| i as f32 / 7.0).collect();
+	let mut data = Cursor::new(Vec::new());
+	tiff::encoder::TiffEncoder::new(&mut data).unwrap().write_image::<colortype::Gray32Float>(6, 4, &gray).unwrap();
+	let data = data.into_inner();
+
+	let decoder = TiffDecoder::new(Cursor::new(&data)).unwrap();
+	assert_eq!(decoder.dropped_bands(), 0);
+	let (_, img) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+	let expected: Vec<f32> = gray.iter().flat_map(|&v| [v; 3]).collect();
+	assert_eq!(img.as_rgb32f().unwrap().as_raw(), &expected);
+}
+
+
+#[test]
+fn geotiff_tags_pass_through() {
+	use tiff::tags::Tag;
+
+	let key_directory: &[u16] = &[1, 1, 0, 2, 1024, 0, 1, 2, 2049, 34737, 7, 0];
+	let mut data = Cursor::new(Vec::new());
+	{
+		let mut encoder = tiff::encoder::TiffEncoder::new(&mut data).unwrap();
+		let mut image = encoder.new_image::<colortype::Gray8>(3, 2).unwrap();
+		let directory = image.encoder();
+		directory.write_tag(Tag::from_u16_exhaustive(33550), &[0.5f64, 0.25, 0.0][..]).unwrap();
+		directory.write_tag(Tag::from_u16_exhaustive(33922), &[0.0f64, 0.0, 0.0, 500_000.0, 4_000_000.0, 0.0][..]).unwrap();
+		directory.write_tag(Tag::from_u16_exhaustive(34735), key_directory).unwrap();
+		directory.write_tag(Tag::from_u16_exhaustive(34737), "WGS 84|").unwrap();
+		image.write_data(&[0, 1, 2, 3, 4, 5]).unwrap();
+	}
+	let data = data.into_inner();
+
+	let decoder = TiffDecoder::new(Cursor::new(&data)).unwrap();
+	let geo = decoder.geotiff().expect("GeoTIFF tags");
+	assert_eq!(geo.model_pixel_scale.as_deref(), Some(&[0.5, 0.25, 0.0][..]));
+	assert_eq!(geo.model_tiepoint.as_deref(), Some(&[0.0, 0.0, 0.0, 500_000.0, 4_000_000.0, 0.0][..]));
+	assert_eq!(geo.model_transformation, None);
+	assert_eq!(geo.geo_key_directory.as_deref(), Some(key_directory));
+	assert_eq!(geo.geo_ascii_params.as_deref(), Some("WGS 84|"));
+
+	let plain = encode_pages(&[gradient16().as_raw()], Compression::Uncompressed);
+	assert!(TiffDecoder::new(Cursor::new(&plain)).unwrap().geotiff().is_none());
+}
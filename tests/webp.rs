@@ -0,0 +1,125 @@
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageDecoder, RgbImage, RgbaImage};
+use imgest::WebPDecoder;
+
+
+fn encode(img: &DynamicImage, metadata: bool) -> Vec<u8> {
+	let mut out = Vec::new();
+	let mut encoder = image_webp::WebPEncoder::new(&mut out);
+	if metadata {
+		encoder.set_icc_profile(vec![7; 64]);
+		encoder.set_exif_metadata(b"II*\0\x08\0\0\0\0\0\0\0\0\0".to_vec());
+		encoder.set_xmp_metadata(b"<x:xmpmeta/>".to_vec());
+	}
+	let color = match img {
+		DynamicImage::ImageRgb8(_) => image_webp::ColorType::Rgb8,
+		DynamicImage::ImageRgba8(_) => image_webp::ColorType::Rgba8,
+		_ => unreachable!(),
+	};
+	encoder.encode(img.as_bytes(), img.width(), img.height(), color).unwrap();
+	out
+}
+
+
+fn riff_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+	out.extend_from_slice(kind);
+	out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+	out.extend_from_slice(data);
+	if data.len() % 2 == 1 {
+		out.push(0);
+	}
+}
+
+
+fn u24(v: u32) -> [u8; 3] {
+	let b = v.to_le_bytes();
+	[b[0], b[1], b[2]]
+}
+
+
+/// Wraps the VP8L bitstream of a still image into a two frame animation.
+fn animated(still: &[u8], width: u32, height: u32) -> Vec<u8> {
+	let vp8l_len = u32::from_le_bytes(still[16..20].try_into().unwrap()) as usize;
+	let vp8l = &still[20..20 + vp8l_len];
+
+	let mut body = b"WEBP".to_vec();
+	let mut vp8x = vec![0x02, 0, 0, 0];
+	vp8x.extend_from_slice(&u24(width - 1));
+	vp8x.extend_from_slice(&u24(height - 1));
+	riff_chunk(&mut body, b"VP8X", &vp8x);
+	riff_chunk(&mut body, b"ANIM", &[0, 0, 0, 0, 0, 0]);
+	for _ in 0..2 {
+		let mut anmf = Vec::new();
+		anmf.extend_from_slice(&u24(0));
+		anmf.extend_from_slice(&u24(0));
+		anmf.extend_from_slice(&u24(width - 1));
+		anmf.extend_from_slice(&u24(height - 1));
+		anmf.extend_from_slice(&u24(100));
+		anmf.push(0);
+		riff_chunk(&mut anmf, b"VP8L", vp8l);
+		riff_chunk(&mut body, b"ANMF", &anmf);
+	}
+
+	let mut out = b"RIFF".to_vec();
+	out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+	out.extend_from_slice(&body);
+	out
+}
+
+
+#[test]
+fn lossless_rgb_and_alpha() {
+	let rgb = DynamicImage::ImageRgb8(RgbImage::from_fn(17, 9, |x, y| image::Rgb([(x * 15) as u8, (y * 28) as u8, 99])));
+	let rgba = DynamicImage::ImageRgba8(RgbaImage::from_fn(17, 9, |x, y| image::Rgba([(x * 15) as u8, 3, 99, (y * 28) as u8])));
+
+	for img in [rgb, rgba] {
+		let data = encode(&img, false);
+		let mut decoder = WebPDecoder::new(Cursor::new(&data)).unwrap();
+		assert!(!decoder.is_lossy());
+		assert!(!decoder.is_animated());
+		assert_eq!(decoder.has_alpha(), img.color().has_alpha());
+
+		let (format, decoded) = imgest::load_image_from_reader(Cursor::new(&data)).unwrap();
+		assert_eq!(format, image::ImageFormat::WebP);
+		assert_eq!(decoded, img);
+	}
+}
+
+
+#[test]
+fn metadata_is_exposed() {
+	let img = DynamicImage::ImageRgb8(RgbImage::new(4, 4));
+	let mut decoder = WebPDecoder::new(Cursor::new(encode(&img, true))).unwrap();
+	assert_eq!(decoder.icc_profile().unwrap(), Some(vec![7; 64]));
+	assert_eq!(decoder.xmp_metadata().unwrap().as_deref(), Some(&b"<x:xmpmeta/>"[..]));
+	assert!(decoder.exif_metadata().unwrap().is_some());
+	assert_eq!(decoder.orientation().unwrap(), image::metadata::Orientation::NoTransforms);
+}
+
+
+#[test]
+fn animations_are_rejected() {
+	let still = encode(&DynamicImage::ImageRgb8(RgbImage::new(6, 5)), false);
+	let data = animated(&still, 6, 5);
+	assert!(WebPDecoder::new(Cursor::new(&data)).unwrap().is_animated());
+	assert!(matches!(imgest::load_image_from_reader(Cursor::new(&data)), Err(imgest::Error::Animated)));
+}
+
+
+#[test]
+fn limits_are_respected() {
+	let data = encode(&DynamicImage::ImageRgb8(RgbImage::new(40, 30)), false);
+	let mut limits = image::Limits::default();
+	limits.max_image_width = Some(39);
+	assert!(matches!(WebPDecoder::with_limits(Cursor::new(&data), limits), Err(imgest::Error::Limits(_))));
+}
+
+
+#[test]
+fn wrong_buffer_length_is_an_error() {
+	let data = encode(&DynamicImage::ImageRgb8(RgbImage::new(4, 3)), false);
+	let decoder = WebPDecoder::new(Cursor::new(&data)).unwrap();
+	assert_eq!(decoder.total_bytes(), 36);
+	assert!(matches!(decoder.read_image(&mut [0; 35]), Err(image::ImageError::Parameter(_))));
+}